@@ -8,7 +8,12 @@ import Link from 'next/link';
 import BN from 'bn.js';
 import { 
   initPoseidon, 
+  initWasm,
   poseidonHash, 
+  extDataHash,
+  fetchRelayerQuote,
+  withdrawalFee,
+  waitForRelayJob,
   generateBlinding, 
   generateKeypair,
   MerkleTree,
@@ -119,7 +124,9 @@ export default function TransferPage() {
     setProgress({ step: 'init', message: 'Initializing...' });
     
     try {
-      await initPoseidon();
+      await Promise.all([initPoseidon(), initWasm()]);
+      // Before depositing, so an unreachable relayer doesn't strand the SOL
+      const quote = await fetchRelayerQuote();
       
       const solMintAddress = 1n;
       const recipientPubkey = new PublicKey(recipient);
@@ -152,8 +159,7 @@ export default function TransferPage() {
       const outputCommitment2 = poseidonHash([0n, utxoKeypair.pubkey, outBlinding2, solMintAddress]);
       
       const depositPublicAmountField = new BN(amountLamports).add(FIELD_SIZE).mod(FIELD_SIZE);
-      const senderNum = BigInt('0x' + publicKey.toBuffer().toString('hex').slice(0, 16));
-      const depositExtDataHash = poseidonHash([senderNum, BigInt(amountLamports)]);
+      const depositExtDataHash = extDataHash(publicKey, BigInt(amountLamports), publicKey, 0n);
       
       const zeroPath = new Array(MERKLE_TREE_HEIGHT).fill('0');
       const depositInput = {
@@ -259,8 +265,12 @@ export default function TransferPage() {
       const withdrawOutputCommitment2 = poseidonHash([0n, utxoKeypair.pubkey, withdrawOutBlinding2, solMintAddress]);
       
       const withdrawPublicAmountField = new BN(amountLamports).neg().add(FIELD_SIZE).mod(FIELD_SIZE);
-      const recipientNum = BigInt('0x' + recipientPubkey.toBuffer().slice(0, 8).toString('hex'));
-      const withdrawExtDataHash = poseidonHash([recipientNum, BigInt(amountLamports)]);
+      const withdrawExtDataHash = extDataHash(
+        recipientPubkey,
+        BigInt(amountLamports),
+        quote.relayer,
+        withdrawalFee(quote, BigInt(amountLamports)),
+      );
       
       const withdrawInput = {
         root: withdrawRoot.toString(),
@@ -318,8 +328,9 @@ export default function TransferPage() {
         throw new Error(relayResult.error || 'Relayer failed');
       }
       
-      const withdrawSig = relayResult.signature;
-      await connection.confirmTransaction(withdrawSig, 'confirmed');
+      const withdrawSig = await waitForRelayJob(relayResult.job_id, message =>
+        setProgress({ step: 'withdraw-tx', message })
+      );
       
       setProgress({ step: 'done', message: 'Transfer complete!' });
       setResult({ depositSig, withdrawSig });
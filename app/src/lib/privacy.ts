@@ -64,6 +64,96 @@ export function poseidonHash(inputs: bigint[]): bigint {
   return poseidonInstance.F.toObject(hash);
 }
 
+// ============================================================================
+// WASM bindings (built by `wasm-pack build wasm --target web`)
+// ============================================================================
+
+let wasmInstance: typeof import('privacy-zig-wasm') | null = null;
+
+export async function initWasm(): Promise<void> {
+  if (wasmInstance) return;
+  const wasm = await import('privacy-zig-wasm');
+  await wasm.default();
+  wasmInstance = wasm;
+}
+
+/**
+ * extDataHash of a transaction paying `amount` to `recipient`, sent by
+ * `relayer` for `fee`: the same hash the CLI proves and the relayer checks
+ */
+export function extDataHash(recipient: PublicKey, amount: bigint, relayer: PublicKey, fee: bigint): bigint {
+  if (!wasmInstance) throw new Error('WASM bindings not initialized');
+  return BigInt(
+    wasmInstance.extDataHash(recipient.toBase58(), amount.toString(), relayer.toBase58(), fee.toString())
+  );
+}
+
+// ============================================================================
+// Relayer
+// ============================================================================
+
+/** Who signs a relayed withdrawal, and the fee rate it is paid at */
+export interface RelayerQuote {
+  /** The relayer's signing address, which the proof commits to */
+  relayer: PublicKey;
+  /** On-chain withdrawal fee rate, in basis points */
+  feeRateBps: number;
+}
+
+async function relayerGet(path: string): Promise<any> {
+  const response = await fetch(`${RELAYER_URL}${path}`);
+  if (!response.ok) {
+    throw new Error(`Relayer ${path} failed: ${await response.text()}`);
+  }
+  return response.json();
+}
+
+export async function fetchRelayerQuote(): Promise<RelayerQuote> {
+  const [info, fee] = await Promise.all([relayerGet('/info'), relayerGet('/fee')]);
+  return {
+    relayer: new PublicKey(info.relayer_address),
+    feeRateBps: fee.withdrawal_fee_rate_bps,
+  };
+}
+
+/** The fee the program pays the relayer: `feeRateBps` of `amount`, rounded down */
+export function withdrawalFee(quote: RelayerQuote, amount: bigint): bigint {
+  return (amount * BigInt(quote.feeRateBps)) / 10_000n;
+}
+
+const STATUS_POLL_INTERVAL_MS = 2_000;
+
+/** How long to wait for a queued withdrawal before giving up on polling */
+const STATUS_TIMEOUT_MS = 300_000;
+
+/**
+ * Poll `GET /status/:job_id` until the relayer's job for a withdrawal is
+ * confirmed, returning its signature, or throw once it has failed
+ */
+export async function waitForRelayJob(jobId: string, onProgress?: (msg: string) => void): Promise<string> {
+  const started = Date.now();
+  for (;;) {
+    const status = await relayerGet(`/status/${jobId}`);
+    if (status.status === 'confirmed') {
+      if (!status.signature) throw new Error('No signature in confirmed job');
+      return status.signature;
+    }
+    if (status.status === 'failed') {
+      throw new Error(`Relayer error: ${status.error || 'Unknown error'}`);
+    }
+
+    onProgress?.(
+      status.signature
+        ? `Relayer sent ${status.signature}, confirming...`
+        : `Queued at relayer (job ${jobId})...`
+    );
+    if (Date.now() - started > STATUS_TIMEOUT_MS) {
+      throw new Error(`Relay job ${jobId} is still ${status.status}; check ${RELAYER_URL}/status/${jobId} later`);
+    }
+    await new Promise(r => setTimeout(r, STATUS_POLL_INTERVAL_MS));
+  }
+}
+
 // ============================================================================
 // Utils
 // ============================================================================
@@ -315,7 +405,7 @@ export async function deposit(
 ): Promise<DepositResult> {
   try {
     onProgress?.('Initializing...');
-    await initPoseidon();
+    await Promise.all([initPoseidon(), initWasm()]);
     
     const solMintAddress = 1n;
     const utxoKeypair = generateKeypair();
@@ -349,8 +439,8 @@ export async function deposit(
     const outputCommitment2 = poseidonHash([0n, utxoKeypair.pubkey, outBlinding2, solMintAddress]);
     
     const depositPublicAmount = new BN(amountLamports).add(FIELD_SIZE).mod(FIELD_SIZE);
-    const senderNum = BigInt('0x' + senderPubkey.toBuffer().toString('hex').slice(0, 16));
-    const depositExtDataHash = poseidonHash([senderNum, BigInt(amountLamports)]);
+    // Sent by the wallet itself, paying no relayer fee
+    const depositExtDataHash = extDataHash(senderPubkey, BigInt(amountLamports), senderPubkey, 0n);
     
     const zeroPath = new Array(MERKLE_TREE_HEIGHT).fill('0');
     const depositInput = {
@@ -458,7 +548,8 @@ export async function withdraw(
 ): Promise<WithdrawResult> {
   try {
     onProgress?.('Initializing...');
-    await initPoseidon();
+    await Promise.all([initPoseidon(), initWasm()]);
+    const quote = await fetchRelayerQuote();
     
     const solMintAddress = 1n;
     const recipientPubkey = new PublicKey(recipientAddress);
@@ -511,13 +602,18 @@ export async function withdraw(
     const outputCommitment2 = poseidonHash([0n, pubkey, outBlinding2, solMintAddress]);
     
     const withdrawPublicAmount = new BN(amountLamports).neg().add(FIELD_SIZE).mod(FIELD_SIZE);
-    const recipientNum = BigInt('0x' + recipientPubkey.toBuffer().slice(0, 8).toString('hex'));
-    const extDataHash = poseidonHash([recipientNum, BigInt(amountLamports)]);
+    // The relayer refuses proofs that don't commit to it and its fee
+    const withdrawExtDataHash = extDataHash(
+      recipientPubkey,
+      BigInt(amountLamports),
+      quote.relayer,
+      withdrawalFee(quote, BigInt(amountLamports)),
+    );
     
     const withdrawInput = {
       root: root.toString(),
       publicAmount: withdrawPublicAmount.toString(),
-      extDataHash: extDataHash.toString(),
+      extDataHash: withdrawExtDataHash.toString(),
       mintAddress: solMintAddress.toString(),
       inputNullifier: [nullifier.toString(), dummyNullifier.toString()],
       inAmount: [amountLamports.toString(), '0'],
@@ -595,9 +691,8 @@ export async function withdraw(
     
     onProgress?.('Confirming...');
     
-    // Wait for confirmation
-    const signature = relayResult.signature;
-    await connection.confirmTransaction(signature, 'confirmed');
+    // The relayer queues the withdrawal and sends it in the background
+    const signature = await waitForRelayJob(relayResult.job_id, onProgress);
     
     // Update note status
     updateNote(note.id, { status: 'withdrawn', withdrawTxSig: signature });
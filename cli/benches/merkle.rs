@@ -0,0 +1,56 @@
+//! Commitment tree and note benchmarks: `cargo bench --bench merkle`
+//!
+//! To compare the `parallel` rebuild with the sequential one:
+//!
+//! ```sh
+//! cargo bench --bench merkle -- --save-baseline sequential rebuild
+//! cargo bench --bench merkle --features parallel -- --baseline sequential rebuild
+//! ```
+
+use ark_bn254::Fr;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use privacy_cli::crypto::{sol_mint, MERKLE_TREE_HEIGHT};
+use privacy_cli::{MerkleTree, Utxo};
+
+fn tree(leaves: u64) -> MerkleTree {
+    let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+    tree.insert_many(&(0..leaves).map(Fr::from).collect::<Vec<_>>()).unwrap();
+    tree
+}
+
+/// Finding a note's leaf costs the same in a pool of any size
+fn index_of(c: &mut Criterion) {
+    let mut group = c.benchmark_group("index_of");
+    for leaves in [1_000, 10_000, 100_000] {
+        let tree = tree(leaves);
+        // The newest leaf, which a scan from the start reaches last
+        let newest = Fr::from(leaves - 1);
+        group.bench_with_input(BenchmarkId::from_parameter(leaves), &tree, |b, tree| {
+            b.iter(|| tree.index_of(black_box(&newest)))
+        });
+    }
+    group.finish();
+}
+
+/// Building the tree from a pool's commitments, as every withdrawal does
+fn rebuild(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rebuild");
+    group.sample_size(10);
+    for leaves in [1_000, 10_000, 100_000] {
+        group.bench_with_input(BenchmarkId::from_parameter(leaves), &leaves, |b, &leaves| {
+            b.iter(|| tree(leaves).root())
+        });
+    }
+    group.finish();
+}
+
+/// A new note and its nullifier: four Poseidon hashes of one, three and
+/// four inputs
+fn utxo(c: &mut Criterion) {
+    c.bench_function("utxo", |b| {
+        b.iter(|| Utxo::new(black_box(1_000_000), sol_mint()).unwrap().compute_nullifier(0).unwrap())
+    });
+}
+
+criterion_group!(benches, index_of, rebuild, utxo);
+criterion_main!(benches);
@@ -0,0 +1,348 @@
+//! Circuit artifacts: finding them, downloading them and checking them
+//!
+//! The prover needs `transaction2.wasm` and `transaction2.zkey`. Their
+//! directory resolves as `--artifacts` > `PRIVACY_ARTIFACTS` >
+//! `artifacts_path` in the config file > the first of [`search_paths`] that
+//! holds them. `privacy artifacts download` fetches them into
+//! `~/.privacy-zig/artifacts`, the first place searched.
+//!
+//! Every file is pinned to a sha256 checksum. A download that doesn't match
+//! is deleted before it can be used, and the prover refuses to load files
+//! that don't match, since a tampered proving key or witness generator
+//! undermines the privacy of every proof made with it. The pins are those of
+//! [`ARTIFACTS`] unless `--artifacts-sha256`, `PRIVACY_ARTIFACTS_SHA256` or
+//! `artifacts_sha256` in the config file replaces them, for a pool deployed
+//! from another trusted setup.
+
+use anyhow::{anyhow, Context, Result};
+use console::style;
+use indicatif::{ProgressBar, ProgressStyle};
+use solana_sdk::hash::Hasher;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::notes::NoteStore;
+use crate::output::say;
+
+pub use privacy_zig_core::artifacts::{
+    missing, pinned, pins, set_pins, sha256_bytes, Artifact, Pin, ARTIFACTS,
+};
+
+/// The pins of [`ARTIFACTS`], with those named in `overrides` (each
+/// `NAME=HEX`) replaced
+pub fn parse_pins<S: AsRef<str>>(overrides: &[S]) -> Result<Vec<Pin>> {
+    let mut pins: Vec<Pin> = ARTIFACTS.iter().map(Pin::from).collect();
+    for pin in overrides {
+        let pin = pin.as_ref().trim();
+        let (name, sha256) = pin
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid artifact pin {:?}: expected NAME=SHA256", pin))?;
+        let sha256 = sha256.trim().to_ascii_lowercase();
+        if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(anyhow!(
+                "Invalid sha256 {:?} for {}: expected 64 hex digits",
+                sha256,
+                name.trim()
+            ));
+        }
+        let entry = pins
+            .iter_mut()
+            .find(|entry| entry.name == name.trim())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown artifact {:?}; expected one of {}",
+                    name.trim(),
+                    ARTIFACTS.map(|artifact| artifact.name).join(", ")
+                )
+            })?;
+        entry.sha256 = sha256;
+    }
+    Ok(pins)
+}
+
+/// Where `download` fetches the files from, tried in order; override with
+/// `--url`, `PRIVACY_ARTIFACTS_URL` or `artifacts_url` in the config file
+pub const DEFAULT_URLS: [&str; 1] =
+    ["https://raw.githubusercontent.com/DaviRain-Su/privacy-zig/main/artifacts"];
+
+/// Directories relative to the working directory that are searched after
+/// the managed one, for running from a checkout of the repository
+const RELATIVE_PATHS: [&str; 3] = ["../artifacts", "../../privacy-zig/artifacts", "./artifacts"];
+
+/// `~/.privacy-zig/artifacts`, where `download` puts the files
+pub fn managed_dir() -> Result<PathBuf> {
+    Ok(NoteStore::data_dir()?.join("artifacts"))
+}
+
+/// Directories searched when no artifacts path is configured, in order
+pub fn search_paths() -> Vec<PathBuf> {
+    managed_dir()
+        .into_iter()
+        .chain(RELATIVE_PATHS.iter().map(PathBuf::from))
+        .collect()
+}
+
+/// The first of [`search_paths`] holding the wasm file, or the managed
+/// directory if none does, so errors point at where `download` would put it
+pub fn default_path() -> String {
+    let paths = search_paths();
+    paths
+        .iter()
+        .find(|dir| dir.join(ARTIFACTS[0].name).exists())
+        .or(paths.first())
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_else(|| RELATIVE_PATHS[0].to_string())
+}
+
+/// How a file compares with its pinned checksum
+#[derive(Debug, PartialEq, Eq)]
+pub enum Check {
+    Ok,
+    Missing,
+    Mismatch { actual: String },
+}
+
+/// Hex sha256 of a file, read in chunks
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Hasher::default();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if n == 0 {
+            break;
+        }
+        hasher.hash(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.result().to_bytes()))
+}
+
+/// Compare every file in `dir` with its pinned checksum
+pub fn verify(dir: &Path) -> Result<Vec<(&'static str, Check)>> {
+    pins()
+        .iter()
+        .map(|pin| {
+            let path = dir.join(pin.name);
+            let check = if !path.exists() {
+                Check::Missing
+            } else {
+                let actual = sha256_file(&path)?;
+                if actual == pin.sha256 {
+                    Check::Ok
+                } else {
+                    Check::Mismatch { actual }
+                }
+            };
+            Ok((pin.name, check))
+        })
+        .collect()
+}
+
+/// Fetch every file missing from `dir` or not matching its checksum (all of
+/// them with `force`), trying `urls` in order until one serves it
+pub async fn download(urls: &[String], dir: &Path, force: bool) -> Result<()> {
+    if urls.is_empty() {
+        return Err(anyhow!("No artifact URLs configured"));
+    }
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    for pin in pins() {
+        let path = dir.join(pin.name);
+        if !force && path.exists() && sha256_file(&path)? == pin.sha256 {
+            say!("  {} {} (already verified)", style("✓").green(), pin.name);
+            continue;
+        }
+
+        let mut last_error = None;
+        for base in urls {
+            let url = format!("{}/{}", base.trim_end_matches('/'), pin.name);
+            match fetch(&url, &path, &pin.sha256).await {
+                Ok(()) => {
+                    last_error = None;
+                    break;
+                }
+                // A source serving the wrong bytes isn't worth falling back
+                // from quietly
+                Err(e) if e.is::<ChecksumMismatch>() => return Err(e),
+                Err(e) => {
+                    say!("  {} {}: {:#}", style("✗").red(), url, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        if let Some(e) = last_error {
+            return Err(e.context(format!("Failed to download {}", pin.name)));
+        }
+        say!("  {} {} (sha256 {})", style("✓").green(), pin.name, &pin.sha256[..16]);
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "Checksum mismatch for {url}: expected sha256 {expected}, got {actual}. The download was \
+     deleted; don't use artifacts from this source."
+)]
+struct ChecksumMismatch {
+    url: String,
+    expected: String,
+    actual: String,
+}
+
+/// Download `url` to `dest` through a `.part` file that is only renamed
+/// into place once its sha256 is `expected`, and deleted otherwise
+async fn fetch(url: &str, dest: &Path, expected: &str) -> Result<()> {
+    let mut part = dest.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+
+    let result = fetch_to(url, &part, expected).await;
+    match result {
+        Ok(()) => fs::rename(&part, dest)
+            .with_context(|| format!("Failed to move {} into place", part.display())),
+        Err(e) => {
+            let _ = fs::remove_file(&part);
+            Err(e)
+        }
+    }
+}
+
+async fn fetch_to(url: &str, part: &Path, expected: &str) -> Result<()> {
+    let mut response = reqwest::get(url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Failed to fetch {}", url))?;
+
+    let pb = match response.content_length() {
+        Some(len) => ProgressBar::new(len).with_style(
+            ProgressStyle::default_bar()
+                .template("  {msg} [{bar:30}] {bytes}/{total_bytes}")
+                .unwrap(),
+        ),
+        None => ProgressBar::new_spinner(),
+    };
+    pb.set_message(url.rsplit('/').next().unwrap_or(url).to_string());
+
+    let mut file =
+        File::create(part).with_context(|| format!("Failed to create {}", part.display()))?;
+    let mut hasher = Hasher::default();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .with_context(|| format!("Download of {} was interrupted", url))?
+    {
+        hasher.hash(&chunk);
+        file.write_all(&chunk)
+            .with_context(|| format!("Failed to write {}", part.display()))?;
+        pb.inc(chunk.len() as u64);
+    }
+    file.sync_all()?;
+    pb.finish_and_clear();
+
+    let actual = hex::encode(hasher.result().to_bytes());
+    if actual != expected {
+        return Err(ChecksumMismatch {
+            url: url.to_string(),
+            expected: expected.to_string(),
+            actual,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("artifacts-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Serve `body` to every request on a local port and return its URL
+    async fn serve(body: &'static [u8]) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).await;
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(head.as_bytes()).await.unwrap();
+                stream.write_all(body).await.unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[test]
+    fn test_repository_artifacts_match_pinned_checksums() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../artifacts");
+        let checks = verify(&dir).unwrap();
+        assert!(checks.iter().all(|(_, check)| *check == Check::Ok), "{:?}", checks);
+    }
+
+    #[test]
+    fn test_verify_reports_missing_and_tampered_files() {
+        let dir = scratch_dir("verify");
+        fs::write(dir.join("transaction2.zkey"), b"not a zkey").unwrap();
+        let checks = verify(&dir).unwrap();
+        assert_eq!(checks[0], ("transaction2.wasm", Check::Missing));
+        let Check::Mismatch { actual } = &checks[1].1 else {
+            panic!("expected a mismatch, got {:?}", checks[1]);
+        };
+        assert_eq!(actual, &sha256_file(&dir.join("transaction2.zkey")).unwrap());
+        assert_eq!(missing(&dir), ["transaction2.wasm"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pins_can_be_replaced_by_name() {
+        assert_eq!(parse_pins::<&str>(&[]).unwrap(), pins());
+        assert_eq!(pinned(Path::new("/any/dir/transaction2.zkey")), Some(ARTIFACTS[1].sha256));
+        assert_eq!(pinned(Path::new("/any/dir/truncated.zkey")), None);
+
+        let sha256 = "AB".repeat(32);
+        let pins = parse_pins(&[format!(" transaction2.zkey = {} ", sha256)]).unwrap();
+        assert_eq!(pins[0].sha256, ARTIFACTS[0].sha256);
+        assert_eq!(pins[1].sha256, "ab".repeat(32));
+
+        let error = |pin: &str| parse_pins(&[pin]).unwrap_err().to_string();
+        assert!(error("transaction2.zkey").contains("expected NAME=SHA256"));
+        assert!(error("transaction2.zkey=abc").contains("expected 64 hex digits"));
+        assert!(error(&format!("verifier.zkey={}", sha256)).contains("Unknown artifact"));
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_download_is_deleted() {
+        let url = serve(b"tampered").await;
+        let dir = scratch_dir("download");
+        let dest = dir.join("transaction2.zkey");
+
+        let err = fetch(&format!("{}/transaction2.zkey", url), &dest, ARTIFACTS[1].sha256)
+            .await
+            .unwrap_err();
+        assert!(err.is::<ChecksumMismatch>(), "{:#}", err);
+        assert!(err.to_string().contains(ARTIFACTS[1].sha256));
+        assert!(!dest.exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0, "partial download left behind");
+
+        // The right bytes are moved into place
+        let expected = hex::encode(solana_sdk::hash::hash(b"tampered").to_bytes());
+        fetch(&format!("{}/transaction2.zkey", url), &dest, &expected).await.unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"tampered");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
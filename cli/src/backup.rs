@@ -0,0 +1,202 @@
+//! Compact single-note backup strings
+//!
+//! A backup is `privacy-sol-v1:<base58 payload>`, short enough to write on
+//! paper or paste into a chat. The payload is
+//!
+//! | Size | Field |
+//! |------|-------|
+//! | 8 | amount (u64 LE, lamports) |
+//! | 32 | privkey (big-endian field element) |
+//! | 32 | blinding (big-endian field element) |
+//! | 32 | pool tree account |
+//! | 1 + n | network name, length-prefixed |
+//! | 4 | checksum: first 4 bytes of sha256(prefix + everything above) |
+//!
+//! pubkey and commitment are recomputed on restore; the leaf index is looked
+//! up on chain.
+
+use anyhow::{anyhow, Result};
+use ark_bn254::Fr;
+use solana_sdk::{hash::hashv, pubkey::Pubkey};
+use std::fmt;
+use std::str::FromStr;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::crypto::{be_bytes_to_fr_strict, fr_to_be_bytes, fr_to_str, parse_field, SecretString};
+use crate::notes::Note;
+
+const PREFIX: &str = "privacy-sol-";
+const VERSION: &str = "v1";
+const CHECKSUM_LEN: usize = 4;
+
+#[derive(Clone, PartialEq)]
+pub struct NoteBackup {
+    pub amount: u64,
+    pub privkey: Fr,
+    pub blinding: Fr,
+    pub network: String,
+    pub pool: Pubkey,
+}
+
+impl fmt::Debug for NoteBackup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NoteBackup")
+            .field("amount", &self.amount)
+            .field("network", &self.network)
+            .field("pool", &self.pool)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for NoteBackup {
+    fn drop(&mut self) {
+        self.privkey.zeroize();
+        self.blinding.zeroize();
+    }
+}
+
+impl NoteBackup {
+    pub fn from_note(note: &Note) -> Result<Self> {
+        if let Some(mint) = &note.mint {
+            return Err(anyhow!(
+                "Note {} holds tokens of mint {}, which backup strings can't record; \
+                 keep it with 'privacy notes export' instead",
+                note.id,
+                mint
+            ));
+        }
+        Ok(Self {
+            amount: note.amount,
+            privkey: parse_field("privkey", note.spending_key()?)?,
+            blinding: parse_field("blinding", &note.blinding)?,
+            network: note.network.clone(),
+            pool: Pubkey::from_str(&note.pool).map_err(|e| anyhow!("Invalid pool: {}", e))?,
+        })
+    }
+
+    pub fn privkey_str(&self) -> SecretString {
+        fr_to_str(&self.privkey).into()
+    }
+
+    pub fn blinding_str(&self) -> SecretString {
+        fr_to_str(&self.blinding).into()
+    }
+
+    pub fn encode(&self) -> String {
+        let mut payload = Zeroizing::new(Vec::with_capacity(8 + 32 * 3 + 1 + self.network.len() + CHECKSUM_LEN));
+        payload.extend_from_slice(&self.amount.to_le_bytes());
+        payload.extend_from_slice(&fr_to_be_bytes(&self.privkey));
+        payload.extend_from_slice(&fr_to_be_bytes(&self.blinding));
+        payload.extend_from_slice(self.pool.as_ref());
+        payload.push(self.network.len() as u8);
+        payload.extend_from_slice(self.network.as_bytes());
+        let checksum = checksum(VERSION, &payload);
+        payload.extend_from_slice(&checksum);
+
+        format!("{}{}:{}", PREFIX, VERSION, bs58::encode(payload.as_slice()).into_string())
+    }
+
+    pub fn decode(backup: &str) -> Result<Self> {
+        let rest = backup
+            .trim()
+            .strip_prefix(PREFIX)
+            .ok_or_else(|| anyhow!("Not a privacy-zig note backup (expected {}{}:...)", PREFIX, VERSION))?;
+        let (version, encoded) = rest
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Malformed note backup: missing ':'"))?;
+        if version != VERSION {
+            return Err(anyhow!(
+                "Unsupported note backup version {} (this build reads {})",
+                version,
+                VERSION
+            ));
+        }
+
+        let data = Zeroizing::new(
+            bs58::decode(encoded)
+                .into_vec()
+                .map_err(|e| anyhow!("Malformed note backup: {}", e))?,
+        );
+        if data.len() < 8 + 32 * 3 + 1 + CHECKSUM_LEN {
+            return Err(anyhow!("Malformed note backup: too short"));
+        }
+        let (payload, expected) = data.split_at(data.len() - CHECKSUM_LEN);
+        if checksum(version, payload) != expected {
+            return Err(anyhow!("Note backup checksum mismatch; check for typos"));
+        }
+
+        let network_len = payload[104] as usize;
+        let network = payload
+            .get(105..)
+            .filter(|rest| rest.len() == network_len)
+            .ok_or_else(|| anyhow!("Malformed note backup: bad network length"))?;
+
+        Ok(Self {
+            amount: u64::from_le_bytes(payload[0..8].try_into().unwrap()),
+            privkey: field_element(&payload[8..40], "privkey")?,
+            blinding: field_element(&payload[40..72], "blinding")?,
+            pool: Pubkey::try_from(&payload[72..104]).unwrap(),
+            network: String::from_utf8(network.to_vec())
+                .map_err(|_| anyhow!("Malformed note backup: network is not UTF-8"))?,
+        })
+    }
+}
+
+fn checksum(version: &str, payload: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let hash = hashv(&[PREFIX.as_bytes(), version.as_bytes(), payload]);
+    let mut out = [0u8; CHECKSUM_LEN];
+    out.copy_from_slice(&hash.to_bytes()[..CHECKSUM_LEN]);
+    out
+}
+
+/// Big-endian bytes to a field element, rejecting values >= the modulus
+fn field_element(bytes: &[u8], name: &str) -> Result<Fr> {
+    be_bytes_to_fr_strict(bytes).map_err(|_| anyhow!("Malformed note backup: {} is not a field element", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup(privkey: Fr, blinding: Fr) -> NoteBackup {
+        NoteBackup {
+            amount: u64::MAX,
+            privkey,
+            blinding,
+            network: "mainnet-beta".to_string(),
+            pool: Pubkey::new_unique(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_max_field_elements() {
+        let max = Fr::from(0u64) - Fr::from(1u64);
+        for (privkey, blinding) in [(max, max), (Fr::from(0u64), max), (Fr::from(7u64), Fr::from(0u64))] {
+            let original = backup(privkey, blinding);
+            let encoded = original.encode();
+            assert!(encoded.starts_with("privacy-sol-v1:"));
+            assert_eq!(NoteBackup::decode(&encoded).unwrap(), original);
+        }
+    }
+
+    #[test]
+    fn test_rejects_bad_checksum() {
+        let encoded = backup(Fr::from(1u64), Fr::from(2u64)).encode();
+        let (prefix, body) = encoded.split_once(':').unwrap();
+        let mut data = bs58::decode(body).into_vec().unwrap();
+        data[10] ^= 1;
+        let tampered = format!("{}:{}", prefix, bs58::encode(data).into_string());
+
+        let err = NoteBackup::decode(&tampered).unwrap_err();
+        assert!(err.to_string().contains("checksum"));
+    }
+
+    #[test]
+    fn test_rejects_unknown_version() {
+        let encoded = backup(Fr::from(1u64), Fr::from(2u64)).encode();
+        let future = encoded.replacen("-v1:", "-v2:", 1);
+
+        let err = NoteBackup::decode(&future).unwrap_err();
+        assert!(err.to_string().contains("Unsupported note backup version v2"));
+    }
+}
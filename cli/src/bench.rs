@@ -0,0 +1,148 @@
+//! `privacy bench`: where a withdrawal's time goes
+//!
+//! Times the stages of a withdrawal on this machine: loading the circuit
+//! artifacts, rebuilding the commitment tree, proving against it and
+//! fetching the pool's commitments. The tree is synthetic, so its size is
+//! set independently of the pool's; only the fetch reads the chain. Run it
+//! before and after a change to see what the change bought.
+
+use anyhow::Result;
+use ark_bn254::Fr;
+use console::style;
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use std::time::{Duration, Instant};
+
+use crate::chain::fetch_commitments_from_chain;
+use crate::crypto::{sol_mint, str_to_fr, ExtData, MerkleTree, Utxo, MERKLE_TREE_HEIGHT};
+use crate::interrupt::{self, CancelToken};
+use crate::output::say;
+use crate::pool;
+use crate::prover::PrivacyProver;
+use crate::rpc::PoolRpc;
+
+/// Milliseconds one stage took across the iterations
+#[derive(Debug, PartialEq, Serialize)]
+pub struct StageTiming {
+    pub stage: &'static str,
+    pub min_ms: f64,
+    pub mean_ms: f64,
+    pub max_ms: f64,
+}
+
+impl StageTiming {
+    fn new(stage: &'static str, samples: &[Duration]) -> Self {
+        let ms: Vec<f64> = samples.iter().map(|d| d.as_nanos() as f64 / 1e6).collect();
+        Self {
+            stage,
+            min_ms: ms.iter().copied().fold(f64::INFINITY, f64::min),
+            mean_ms: ms.iter().sum::<f64>() / ms.len() as f64,
+            max_ms: ms.iter().copied().fold(0.0, f64::max),
+        }
+    }
+}
+
+/// Result of `bench`
+#[derive(Serialize)]
+pub struct BenchResult {
+    /// Leaves in the synthetic tree
+    pub leaves: usize,
+    pub iterations: u32,
+    /// Leaves fetched from the pool
+    pub pool_leaves: usize,
+    pub stages: Vec<StageTiming>,
+}
+
+/// Run `stage` `iterations` times, at least once, and keep its last result
+fn time<T>(iterations: u32, mut stage: impl FnMut() -> Result<T>) -> Result<(Vec<Duration>, T)> {
+    let start = Instant::now();
+    let mut last = stage()?;
+    let mut samples = vec![start.elapsed()];
+    for _ in 1..iterations {
+        let start = Instant::now();
+        last = stage()?;
+        samples.push(start.elapsed());
+    }
+    Ok((samples, last))
+}
+
+pub fn run(rpc: &dyn PoolRpc, artifacts: &str, leaves: usize, iterations: u32) -> Result<BenchResult> {
+    let pb = interrupt::spinner();
+
+    pb.set_message("Loading circuit...");
+    let (load, prover) = time(iterations, || Ok(PrivacyProver::from_dir(artifacts, |_, _| {})?))?;
+
+    // The withdrawn note is the newest leaf, as a fresh deposit's is
+    pb.set_message(format!("Rebuilding a {}-leaf tree...", leaves));
+    let utxo = Utxo::new(1_000_000_000, sol_mint())?;
+    let mut commitments: Vec<Fr> = (1..leaves as u64).map(Fr::from).collect();
+    commitments.push(str_to_fr(&utxo.commitment)?);
+    let (rebuild, tree) = time(iterations, || {
+        let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT)?;
+        tree.insert_many(&commitments)?;
+        Ok(tree)
+    })?;
+
+    pb.set_message("Generating ZK proof...");
+    let ext_data = ExtData::direct(Pubkey::new_unique(), Pubkey::new_unique());
+    let (prove, _) = time(iterations, || {
+        Ok(prover.prove_withdraw(&utxo, leaves - 1, &tree, &ext_data, &CancelToken::default())?)
+    })?;
+
+    pb.set_message("Fetching commitments from chain...");
+    let config = pool::config();
+    let (fetch, fetched) = time(iterations, || fetch_commitments_from_chain(rpc, &config))?;
+    pb.finish_and_clear();
+
+    let stages = vec![
+        StageTiming::new("load_prover", &load),
+        StageTiming::new("rebuild_tree", &rebuild),
+        StageTiming::new("prove_withdraw", &prove),
+        StageTiming::new("fetch_commitments", &fetch),
+    ];
+    say!("{}", style(format!("⏱️  Benchmark ({} leaves, {} iterations)", leaves, iterations)).bold());
+    say!("{}", style("─".repeat(50)).dim());
+    say!("  {:<18} {:>10} {:>10} {:>10}", "Stage (ms)", "mean", "min", "max");
+    for stage in &stages {
+        say!(
+            "  {:<18} {:>10.1} {:>10.1} {:>10.1}",
+            stage.stage,
+            stage.mean_ms,
+            stage.min_ms,
+            stage.max_ms
+        );
+    }
+    say!("{}", style(format!("  The pool's tree has {} leaves", fetched.len())).dim());
+    say!();
+
+    Ok(BenchResult { leaves, iterations, pool_leaves: fetched.len(), stages })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_timing() {
+        let samples = [30, 10, 20].map(Duration::from_millis);
+        assert_eq!(
+            StageTiming::new("rebuild_tree", &samples),
+            StageTiming { stage: "rebuild_tree", min_ms: 10.0, mean_ms: 20.0, max_ms: 30.0 }
+        );
+    }
+
+    #[test]
+    fn test_time_runs_every_iteration() {
+        let mut runs = 0;
+        let (samples, last) = time(3, || {
+            runs += 1;
+            Ok(runs)
+        })
+        .unwrap();
+        assert_eq!((samples.len(), last), (3, 3));
+
+        // Zero iterations still runs the stage once, for its result
+        let (samples, _) = time(0, || Ok(())).unwrap();
+        assert_eq!(samples.len(), 1);
+    }
+}
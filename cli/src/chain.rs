@@ -0,0 +1,1219 @@
+//! Reading pool state from the chain
+//!
+//! Everything here goes through [`PoolRpc`] so it can be tested against
+//! recorded cassettes (see `tests/fixtures/cassettes`).
+
+use anyhow::{anyhow, Context, Result};
+use ark_bn254::Fr;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use privacy_zig_core::instruction::{
+    COMMITMENT1_OFFSET, COMMITMENT2_OFFSET, EXT_DATA_HASH_OFFSET, NULLIFIER1_OFFSET,
+    NULLIFIER2_OFFSET, PUBLIC_AMOUNT_OFFSET,
+};
+use privacy_zig_core::spl::{self, TokenAccount};
+use privacy_zig_core::tree::ROOT_HISTORY_SIZE;
+use privacy_zig_core::{
+    CommitmentData, GlobalConfig, PriorityFee, MEMO_PROGRAM_ID, TRANSACT_SPL_DISCRIMINATOR,
+};
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::crypto::{be_bytes_to_fr_strict, fr_to_be_bytes, MerkleTree, Utxo, MERKLE_TREE_HEIGHT};
+use crate::notes::Note;
+use crate::pool::{PoolConfig, TRANSACT_DISCRIMINATOR};
+use crate::rpc::PoolRpc;
+use crate::shielded::PoolMemo;
+use crate::token::Token;
+
+/// Headroom for transaction and rent fees required on top of a deposit
+pub const DEPOSIT_FEE_BUFFER: u64 = 10_000_000;
+
+/// A leaf of the tree and the transaction that inserted it
+#[derive(Debug)]
+pub struct ChainLeaf {
+    pub commitment: Fr,
+    /// `public_amount` of the inserting transaction (positive = deposit)
+    pub public_amount: i64,
+    pub signature: String,
+}
+
+#[derive(Debug)]
+pub struct PoolStats {
+    pub vault_lamports: u64,
+    /// Number of leaves in the tree (two per transaction)
+    pub next_index: u64,
+    /// Largest deposit the program accepts
+    pub max_deposit_lamports: u64,
+}
+
+impl PoolStats {
+    /// Share of the tree's 2^26 leaves in use, in percent
+    pub fn fill_percent(&self) -> f64 {
+        self.next_index as f64 * 100.0 / (1u64 << MERKLE_TREE_HEIGHT) as f64
+    }
+}
+
+pub fn pool_stats(rpc: &dyn PoolRpc, config: &PoolConfig) -> Result<PoolStats> {
+    let vault_lamports = rpc.get_balance(&config.pool_vault)?;
+    let tree = rpc
+        .get_account(&config.tree_account)?
+        .ok_or_else(|| anyhow!("Tree account {} not found", config.tree_account))?;
+    let too_short = || anyhow!("Tree account data is too short ({} bytes)", tree.data.len());
+    let next_index = privacy_zig_core::tree::next_index(&tree.data).ok_or_else(too_short)?;
+    let max_deposit_lamports =
+        privacy_zig_core::tree::max_deposit_amount(&tree.data).ok_or_else(too_short)?;
+
+    Ok(PoolStats { vault_lamports, next_index, max_deposit_lamports })
+}
+
+/// The pool's fee settings, from its global config account
+pub fn global_config(rpc: &dyn PoolRpc, config: &PoolConfig) -> Result<GlobalConfig> {
+    let account = rpc
+        .get_account(&config.global_config)?
+        .ok_or_else(|| anyhow!("Global config account {} not found", config.global_config))?;
+    GlobalConfig::parse(&account.data)
+        .with_context(|| format!("Failed to read global config account {}", config.global_config))
+}
+
+/// Fail if the program would reject a deposit of `amount` of `token`
+pub fn check_deposit_amount(stats: &PoolStats, amount: u64, token: &Token) -> Result<()> {
+    if amount > stats.max_deposit_lamports {
+        return Err(anyhow!(
+            "Deposit of {} exceeds the pool's maximum of {}",
+            token.format(amount),
+            token.format(stats.max_deposit_lamports)
+        ));
+    }
+    Ok(())
+}
+
+/// Fail unless `owner` can afford a deposit of `lamports` plus fees
+pub fn check_deposit_balance(rpc: &dyn PoolRpc, owner: &Pubkey, lamports: u64) -> Result<()> {
+    let balance = rpc.get_balance(owner)?;
+    if balance < lamports.saturating_add(DEPOSIT_FEE_BUFFER) {
+        return Err(anyhow!(
+            "Insufficient balance. Have {} SOL, need {} SOL + fees",
+            balance as f64 / 1e9,
+            lamports as f64 / 1e9
+        ));
+    }
+    Ok(())
+}
+
+/// Decimals of the token `mint`
+pub fn mint_decimals(rpc: &dyn PoolRpc, mint: &Pubkey) -> Result<u8> {
+    let account = rpc
+        .get_account(mint)?
+        .ok_or_else(|| anyhow!("Token mint {} not found", mint))?;
+    spl::mint_decimals(&account.data).with_context(|| format!("Failed to read mint {}", mint))
+}
+
+/// `owner`'s associated token account for `mint`, failing if it doesn't
+/// exist: the program moves tokens between existing accounts only
+pub fn token_account(rpc: &dyn PoolRpc, owner: &Pubkey, mint: &Pubkey) -> Result<TokenAccount> {
+    let address = spl::associated_token_address(owner, mint);
+    let account = rpc.get_account(&address)?.ok_or_else(|| {
+        anyhow!(
+            "{} has no token account for mint {}; create it first, e.g. with \
+             `spl-token create-account {} --owner {}`",
+            owner,
+            mint,
+            mint,
+            owner
+        )
+    })?;
+    let parsed = TokenAccount::parse(&account.data)
+        .with_context(|| format!("Failed to read token account {}", address))?;
+    if parsed.mint != *mint {
+        return Err(anyhow!("Token account {} holds mint {}, not {}", address, parsed.mint, mint));
+    }
+    Ok(parsed)
+}
+
+/// Fail unless the pool vault can pay out a SOL note of `amount`, and is
+/// the system-owned vault of `config`'s program at all
+pub fn check_vault(rpc: &dyn PoolRpc, config: &PoolConfig, amount: u64) -> Result<()> {
+    let account = rpc
+        .get_account(&config.pool_vault)?
+        .map(|a| Ok::<_, anyhow::Error>((a.owner.parse::<Pubkey>()?, a.lamports)))
+        .transpose()
+        .with_context(|| format!("Failed to read pool vault {}", config.pool_vault))?;
+    Ok(privacy_zig_core::vault::check_withdrawal(config, account, amount)?)
+}
+
+/// Fail unless `owner` holds `amount` of `token` and enough SOL for the
+/// network fee and the nullifier rent, which the depositor pays
+pub fn check_token_deposit_balance(
+    rpc: &dyn PoolRpc,
+    owner: &Pubkey,
+    token: &Token,
+    amount: u64,
+    network_fee: u64,
+) -> Result<()> {
+    let mint = token.mint.ok_or_else(|| anyhow!("SOL deposits have no token account"))?;
+    let held = token_account(rpc, owner, &mint)?.amount;
+    if held < amount {
+        return Err(anyhow!(
+            "Insufficient balance. Have {}, need {}",
+            token.format(held),
+            token.format(amount)
+        ));
+    }
+    check_deposit_balance(rpc, owner, network_fee)
+}
+
+/// The compute unit price for a transaction on the pool: `fee` itself, or
+/// for `auto`, a percentile of what recent transactions on the pool paid
+pub fn priority_fee(rpc: &dyn PoolRpc, config: &PoolConfig, fee: PriorityFee) -> Result<u64> {
+    fee.resolve(|| {
+        rpc.get_recent_prioritization_fees(&config.contended_accounts())
+            .context("Failed to fetch recent priority fees for --priority-fee auto")
+    })
+}
+
+/// How long `transfer` waits for its deposit to appear in the tree
+pub const COMMITMENT_WAIT_TIMEOUT: Duration = Duration::from_secs(120);
+
+const COMMITMENT_POLL_BASE_DELAY: Duration = Duration::from_secs(1);
+const COMMITMENT_POLL_MAX_DELAY: Duration = Duration::from_secs(8);
+
+/// Fetch commitments from on-chain transaction history
+pub fn fetch_commitments_from_chain(rpc: &dyn PoolRpc, config: &PoolConfig) -> Result<Vec<Fr>> {
+    Ok(fetch_leaves_from_chain(rpc, config)?
+        .into_iter()
+        .map(|leaf| leaf.commitment)
+        .collect())
+}
+
+/// A successful transact and the memos its transaction carried
+struct Transact {
+    signature: String,
+    /// Unix time of the block, if the cluster reported it
+    block_time: Option<i64>,
+    data: Vec<u8>,
+    memos: Vec<String>,
+    /// Commitment events the program logged while executing it
+    events: Vec<CommitmentData>,
+}
+
+impl Transact {
+    /// The two commitments it inserted, from its logged events, which name
+    /// their leaves, or from its instruction data if it logged no pair
+    fn commitments(&self) -> Result<[(Fr, Option<u64>); 2]> {
+        if let [first, second] = self.events[..] {
+            let commitment = |event: CommitmentData| {
+                be_bytes_to_fr_strict(&event.commitment)
+                    .context("Pool transaction logged an invalid commitment")
+            };
+            return Ok([
+                (commitment(first)?, Some(first.index)),
+                (commitment(second)?, Some(second.index)),
+            ]);
+        }
+        let [first, second] = commitments(&self.data)?;
+        Ok([(first, None), (second, None)])
+    }
+}
+
+/// The `CommitmentData` events `program_id` logged, one list per top-level
+/// instruction, whether it is the pool or another program that called it.
+/// Log lines aren't tagged with their program, so the stack of invocations
+/// is followed to leave out other programs' `Program data`.
+fn commitment_events(logs: &[String], program_id: &str) -> Vec<Vec<CommitmentData>> {
+    let mut events: Vec<Vec<CommitmentData>> = Vec::new();
+    let mut invoked: Vec<&str> = Vec::new();
+    for line in logs {
+        let Some(line) = line.strip_prefix("Program ") else {
+            continue;
+        };
+        if let Some(data) = line.strip_prefix("data: ") {
+            let event = BASE64.decode(data).ok().and_then(|data| CommitmentData::parse(&data));
+            if let (Some(event), Some(&program), Some(current)) =
+                (event, invoked.last(), events.last_mut())
+            {
+                if program == program_id {
+                    current.push(event);
+                }
+            }
+        } else if let Some((program, depth)) = line.split_once(" invoke [") {
+            if depth == "1]" {
+                events.push(Vec::new());
+                invoked.clear();
+            }
+            invoked.push(program);
+        } else if let Some((program, outcome)) = line.split_once(' ') {
+            // `log:`, `return:` and the like are a program's output
+            let returned = outcome == "success" || outcome.starts_with("failed");
+            if returned && !program.ends_with(':') {
+                invoked.pop();
+            }
+        }
+    }
+    events
+}
+
+/// Every successful transact, oldest first, including those other programs
+/// made by CPI
+fn transact_history(rpc: &dyn PoolRpc, config: &PoolConfig) -> Result<Vec<Transact>> {
+    let signatures = rpc.get_signatures_for_address(&config.tree_account)?;
+    let program_id = config.program_id.to_string();
+    let memo_program_id = MEMO_PROGRAM_ID.to_string();
+
+    let mut transacts = Vec::new();
+    for signature in signatures.iter().rev() {
+        let Some(tx) = rpc.get_transaction(signature)? else {
+            continue;
+        };
+        if tx.failed {
+            continue;
+        }
+
+        // The memo program only accepts UTF-8, so anything else isn't a memo
+        let memos: Vec<String> = tx
+            .instructions
+            .iter()
+            .filter(|ix| ix.program_id == memo_program_id)
+            .filter_map(|ix| String::from_utf8(ix.data.clone()).ok())
+            .collect();
+        let mut events = commitment_events(&tx.log_messages, &program_id);
+        let mut inner = tx.inner_instructions;
+        for (position, ix) in tx.instructions.into_iter().enumerate() {
+            // The pool called directly, or by another program
+            let calls = if ix.program_id == program_id {
+                vec![ix]
+            } else {
+                inner.get_mut(position).map(std::mem::take).unwrap_or_default()
+            };
+            let mut events = events.get_mut(position).map(std::mem::take).unwrap_or_default();
+            for call in calls.into_iter().filter(|call| call.program_id == program_id) {
+                // A token pool's tree only sees `transact_spl`, which carries
+                // the same data
+                let transact = [TRANSACT_DISCRIMINATOR, TRANSACT_SPL_DISCRIMINATOR];
+                if call.data.len() < EXT_DATA_HASH_OFFSET || !transact.iter().any(|d| call.data[0..8] == *d) {
+                    continue;
+                }
+                // Each transact logs its two commitments, in call order
+                let logged = events.drain(..events.len().min(2)).collect();
+                transacts.push(Transact {
+                    signature: signature.clone(),
+                    block_time: tx.block_time,
+                    data: call.data,
+                    memos: memos.clone(),
+                    events: logged,
+                });
+            }
+        }
+    }
+
+    Ok(transacts)
+}
+
+/// `public_amount` of transact instruction data
+fn public_amount(data: &[u8]) -> i64 {
+    i64::from_le_bytes(data[PUBLIC_AMOUNT_OFFSET..PUBLIC_AMOUNT_OFFSET + 8].try_into().unwrap())
+}
+
+/// Fetch the tree leaves, in insertion order, from on-chain transaction history
+pub fn fetch_leaves_from_chain(rpc: &dyn PoolRpc, config: &PoolConfig) -> Result<Vec<ChainLeaf>> {
+    let mut leaves = Vec::new();
+    for transact in transact_history(rpc, config)? {
+        for (commitment, index) in transact.commitments()? {
+            let leaf = ChainLeaf {
+                commitment,
+                public_amount: public_amount(&transact.data),
+                signature: transact.signature.clone(),
+            };
+            leaves.push((index, leaf));
+        }
+    }
+
+    in_leaf_order(leaves)
+}
+
+/// `leaves` without their logged leaf indices, in tree order. When every
+/// leaf's index is known they are sorted by it, rather than trusting the
+/// order history lists transactions of the same block in; an index that
+/// doesn't match its leaf's position means history is missing transactions.
+fn in_leaf_order<T>(mut leaves: Vec<(Option<u64>, T)>) -> Result<Vec<T>> {
+    if leaves.iter().all(|(index, _)| index.is_some()) {
+        leaves.sort_by_key(|(index, _)| *index);
+    }
+    for (position, (index, _)) in leaves.iter().enumerate() {
+        if let Some(index) = *index {
+            if index != position as u64 {
+                return Err(anyhow!(
+                    "The pool's history has leaf {} where leaf {} should be: the transactions \
+                     inserting the leaves in between weren't returned or couldn't be read; try \
+                     another RPC with --rpc-url",
+                    index,
+                    position
+                ));
+            }
+        }
+    }
+    Ok(leaves.into_iter().map(|(_, leaf)| leaf).collect())
+}
+
+/// `output_commitment1` and `output_commitment2` of transact instruction data
+fn commitments(data: &[u8]) -> Result<[Fr; 2]> {
+    let commitment = |offset: usize| {
+        be_bytes_to_fr_strict(&data[offset..offset + 32])
+            .context("Pool transaction has an invalid output commitment")
+    };
+    Ok([commitment(COMMITMENT1_OFFSET)?, commitment(COMMITMENT2_OFFSET)?])
+}
+
+/// The memos of pool transactions, oldest first, each with the commitments
+/// its transaction inserted
+pub fn fetch_memos(rpc: &dyn PoolRpc, config: &PoolConfig) -> Result<Vec<PoolMemo>> {
+    let mut memos = Vec::new();
+    for (index, transact) in transact_history(rpc, config)?.into_iter().enumerate() {
+        let [(first, first_leaf), (second, _)] = transact.commitments()?;
+        for memo in transact.memos {
+            memos.push(PoolMemo {
+                memo,
+                commitments: [first, second],
+                // Each transact inserts two leaves
+                first_leaf: first_leaf.map_or(index * 2, |leaf| leaf as usize),
+                signature: transact.signature.clone(),
+            });
+        }
+    }
+    Ok(memos)
+}
+
+/// What the pool's transaction history shows about its use
+///
+/// The tree account has no deposit counter: `next_index` counts leaves, and
+/// every transact inserts two whether it deposits, withdraws or transfers.
+/// Transactions are told apart by the sign of their `public_amount` instead.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PoolActivity {
+    pub deposits: u64,
+    pub withdrawals: u64,
+    /// Transactions that only moved value inside the pool
+    pub transfers: u64,
+    /// Distinct nullifiers published, two per transaction, zero-value
+    /// padding inputs included
+    pub nullifiers: u64,
+    /// Unix time of the newest deposit's block
+    pub last_deposit: Option<i64>,
+}
+
+impl PoolActivity {
+    /// Estimated number of unspent notes a withdrawal hides among
+    ///
+    /// Without their keys, nullifiers can't be matched to the commitments
+    /// they spend, and each transaction publishes two whether or not both
+    /// inputs are real. Notes created by the CLI and the app are withdrawn
+    /// whole, one per withdrawal, so every withdrawal is counted as spending
+    /// one deposit; transfers spend one note and create another.
+    pub fn anonymity_set(&self) -> u64 {
+        self.deposits.saturating_sub(self.withdrawals)
+    }
+}
+
+/// Count deposits, withdrawals and transfers in the transaction history
+pub fn pool_activity(rpc: &dyn PoolRpc, config: &PoolConfig) -> Result<PoolActivity> {
+    let mut activity = PoolActivity::default();
+    let mut nullifiers = HashSet::new();
+    for Transact { data, block_time, .. } in transact_history(rpc, config)? {
+        match public_amount(&data) {
+            amount if amount > 0 => {
+                activity.deposits += 1;
+                activity.last_deposit = block_time.or(activity.last_deposit);
+            }
+            amount if amount < 0 => activity.withdrawals += 1,
+            _ => activity.transfers += 1,
+        }
+        nullifiers.insert(data[NULLIFIER1_OFFSET..NULLIFIER1_OFFSET + 32].to_vec());
+        nullifiers.insert(data[NULLIFIER2_OFFSET..NULLIFIER2_OFFSET + 32].to_vec());
+    }
+    activity.nullifiers = nullifiers.len() as u64;
+    Ok(activity)
+}
+
+/// A note is spent once the PDA of its nullifier exists
+/// Poll until `commitment` is in the tree and return its leaf index
+///
+/// A confirmed deposit can take a while to show up in transaction history.
+/// Polls back off exponentially; `on_wait` is called before each one with the
+/// time waited so far.
+pub async fn wait_for_commitment(
+    rpc: &dyn PoolRpc,
+    config: &PoolConfig,
+    commitment: &Fr,
+    timeout: Duration,
+    mut on_wait: impl FnMut(Duration),
+) -> Result<usize> {
+    let started = Instant::now();
+    let mut delay = COMMITMENT_POLL_BASE_DELAY;
+    loop {
+        let commitments = fetch_commitments_from_chain(rpc, config)?;
+        if let Some(index) = commitments.iter().position(|c| c == commitment) {
+            return Ok(index);
+        }
+        if started.elapsed() >= timeout {
+            return Err(anyhow!(
+                "Commitment did not appear in the tree within {}s",
+                timeout.as_secs()
+            ));
+        }
+        on_wait(started.elapsed());
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(COMMITMENT_POLL_MAX_DELAY);
+    }
+}
+
+/// The roots the program accepts proofs against: the tree account's root
+/// history, which keeps the last `ROOT_HISTORY_SIZE`, one per transaction
+pub fn root_history(rpc: &dyn PoolRpc, config: &PoolConfig) -> Result<Vec<[u8; 32]>> {
+    let account = rpc
+        .get_account(&config.tree_account)?
+        .ok_or_else(|| anyhow!("Tree account {} not found", config.tree_account))?;
+    privacy_zig_core::tree::root_history(&account.data)
+        .ok_or_else(|| anyhow!("Tree account data is too short ({} bytes)", account.data.len()))
+}
+
+/// Leaf counts [`accepted_tree`] looks back over: the last
+/// `ROOT_HISTORY_SIZE` transactions inserted two leaves each
+const ACCEPTED_ROOT_LOOKBACK: usize = 2 * ROOT_HISTORY_SIZE;
+
+/// The newest prefix of `tree` whose root is still in the program's root
+/// history, to prove against. That is `tree` itself unless the RPC served
+/// transactions the tree account doesn't reflect yet, as a node ahead of
+/// the one the account was read from does; the program would reject a
+/// proof against their root. Fails like [`check_tree_root`] when no
+/// prefix matches.
+pub fn accepted_tree(rpc: &dyn PoolRpc, config: &PoolConfig, tree: MerkleTree) -> Result<MerkleTree> {
+    let account = rpc
+        .get_account(&config.tree_account)?
+        .ok_or_else(|| anyhow!("Tree account {} not found", config.tree_account))?;
+    let data = &account.data;
+    let history = privacy_zig_core::tree::root_history(data)
+        .ok_or_else(|| anyhow!("Tree account data is too short ({} bytes)", data.len()))?;
+
+    if history.contains(&fr_to_be_bytes(&tree.root())) {
+        return Ok(tree);
+    }
+    let recent = tree.recent_roots(ACCEPTED_ROOT_LOOKBACK)?;
+    match recent.iter().position(|root| history.contains(&fr_to_be_bytes(root))) {
+        Some(behind) => {
+            let mut accepted = MerkleTree::new(MERKLE_TREE_HEIGHT)?;
+            accepted.insert_many(&tree.leaves()[..tree.leaf_count() - behind])?;
+            Ok(accepted)
+        }
+        None => Err(root_mismatch(data, &tree)?),
+    }
+}
+
+/// Check that a tree rebuilt from fetched history has a root the program
+/// accepts. A missed commitment would otherwise only show up as a proof the
+/// program rejects, after proving and sending it.
+pub fn check_tree_root(rpc: &dyn PoolRpc, config: &PoolConfig, tree: &MerkleTree) -> Result<()> {
+    let account = rpc
+        .get_account(&config.tree_account)?
+        .ok_or_else(|| anyhow!("Tree account {} not found", config.tree_account))?;
+    let data = &account.data;
+    let too_short = || anyhow!("Tree account data is too short ({} bytes)", data.len());
+    let history = privacy_zig_core::tree::root_history(data).ok_or_else(too_short)?;
+
+    // Deposits landing after the history was fetched move the current root
+    // on, but the rebuilt one stays in the history for a while
+    let root = fr_to_be_bytes(&tree.root());
+    if history.contains(&root) {
+        return Ok(());
+    }
+
+    Err(root_mismatch(data, tree)?)
+}
+
+/// The error for a rebuilt tree none of whose roots the tree account
+/// `data` holds
+fn root_mismatch(data: &[u8], tree: &MerkleTree) -> Result<anyhow::Error> {
+    let too_short = || anyhow!("Tree account data is too short ({} bytes)", data.len());
+    let current = privacy_zig_core::tree::current_root(data).ok_or_else(too_short)?;
+    let next_index = privacy_zig_core::tree::next_index(data).ok_or_else(too_short)?;
+    Ok(anyhow!(
+        "The Merkle tree rebuilt from chain history ({} leaves, root {}) doesn't match the pool \
+         ({} leaves, root {}); commitments were missed while fetching it. Retry to resync the \
+         full history, or point --rpc-url at an endpoint that serves all of it",
+        tree.leaf_count(),
+        hex::encode(fr_to_be_bytes(&tree.root())),
+        next_index,
+        hex::encode(current)
+    ))
+}
+
+pub fn is_spent(rpc: &dyn PoolRpc, config: &PoolConfig, utxo: &Utxo, leaf_index: usize) -> Result<bool> {
+    let nullifier = utxo.compute_nullifier(leaf_index)?;
+    let nullifier_pda = config.nullifier_pda(&fr_to_be_bytes(&nullifier));
+    Ok(rpc.get_account(&nullifier_pda)?.is_some())
+}
+
+/// Whether `note` was spent, or `None` for a watch-only note: its nullifier
+/// takes the spending key
+pub fn is_note_spent(rpc: &dyn PoolRpc, config: &PoolConfig, note: &Note, leaf_index: usize) -> Result<Option<bool>> {
+    if note.is_watch_only() {
+        return Ok(None);
+    }
+    is_spent(rpc, config, &note.utxo()?, leaf_index).map(Some)
+}
+
+/// What became of a note's deposit transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepositOutcome {
+    Succeeded,
+    Failed,
+    /// No signature recorded, or the cluster has never seen it
+    Unknown,
+}
+
+/// Look up the outcome of each deposit signature
+pub fn deposit_outcomes(rpc: &dyn PoolRpc, signatures: &[String]) -> Result<HashMap<String, DepositOutcome>> {
+    if signatures.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let statuses = rpc.get_signature_statuses(signatures)?;
+    Ok(signatures
+        .iter()
+        .zip(statuses)
+        .map(|(signature, status)| {
+            let outcome = match status {
+                Some(status) if status.err.is_none() => DepositOutcome::Succeeded,
+                Some(_) => DepositOutcome::Failed,
+                None => DepositOutcome::Unknown,
+            };
+            (signature.clone(), outcome)
+        })
+        .collect())
+}
+
+/// The status a note should have given what the chain says about it, or
+/// `None` if the chain doesn't settle it
+pub fn reconcile_status(in_tree: bool, spent: bool, deposit: DepositOutcome) -> Option<&'static str> {
+    match (in_tree, spent, deposit) {
+        (true, true, _) => Some("withdrawn"),
+        (true, false, _) => Some("deposited"),
+        // The deposit never landed, so there is nothing to spend
+        (false, _, DepositOutcome::Failed | DepositOutcome::Unknown) => Some("invalid"),
+        // The deposit landed but its commitment is missing from the history
+        // the RPC returned (signature lists are truncated on busy pools)
+        (false, _, DepositOutcome::Succeeded) => None,
+    }
+}
+
+/// Where a note stands on chain, as reported by `scan`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteState {
+    Spendable,
+    Spent,
+    /// The deposit failed or never landed, so there is nothing to spend
+    DepositFailed,
+    /// The deposit landed but its commitment is missing from the history
+    /// the RPC returned
+    NotFound,
+    /// In the tree, but watch-only: whether it was spent takes the
+    /// spending key
+    WatchOnly,
+}
+
+impl NoteState {
+    pub fn new(in_tree: bool, spent: bool, deposit: DepositOutcome) -> Self {
+        match (in_tree, spent, deposit) {
+            (true, true, _) => Self::Spent,
+            (true, false, _) => Self::Spendable,
+            (false, _, DepositOutcome::Failed | DepositOutcome::Unknown) => Self::DepositFailed,
+            (false, _, DepositOutcome::Succeeded) => Self::NotFound,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Spendable => "spendable",
+            Self::Spent => "spent",
+            Self::DepositFailed => "deposit failed",
+            Self::NotFound => "not found",
+            Self::WatchOnly => "watch-only",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::{CachedHistory, Cassette};
+    use privacy_zig_core::{with_compute_budget, COMPUTE_UNIT_LIMIT};
+    use solana_sdk::{compute_budget::ComputeBudgetInstruction, instruction::Instruction};
+
+    fn cassette(name: &str) -> Cassette {
+        Cassette::replay(format!("{}/tests/fixtures/cassettes/{}.json", env!("CARGO_MANIFEST_DIR"), name))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_pool_stats() {
+        let stats = pool_stats(&cassette("pool_stats"), &PoolConfig::testnet()).unwrap();
+        assert_eq!(stats.vault_lamports, 510_000_000);
+        assert_eq!(stats.next_index, 26);
+        assert_eq!(stats.max_deposit_lamports, 1_000_000_000_000);
+
+        assert!(check_deposit_amount(&stats, 1_000_000_000_000, &Token::SOL).is_ok());
+        let err = check_deposit_amount(&stats, 1_000_000_000_001, &Token::SOL).unwrap_err();
+        assert!(err.to_string().contains("maximum of 1000 SOL"), "{}", err);
+    }
+
+    #[test]
+    fn test_global_config() {
+        let fees = global_config(&cassette("global_config"), &PoolConfig::testnet()).unwrap();
+        assert_eq!(fees.deposit_fee_rate, 0);
+        assert_eq!(fees.withdrawal_fee_rate, 25);
+        assert_eq!(fees.fee_recipient, PoolConfig::testnet().fee_recipient);
+
+        let err = global_config(&cassette("global_config_malformed"), &PoolConfig::testnet()).unwrap_err();
+        assert!(format!("{:#}", err).contains("too short (40 bytes"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_pool_stats_missing_tree_account() {
+        let err = pool_stats(&cassette("tree_missing"), &PoolConfig::testnet()).unwrap_err();
+        assert!(err.to_string().contains("not found"));
+    }
+
+    #[test]
+    fn test_pool_stats_malformed_tree_account() {
+        let err = pool_stats(&cassette("tree_malformed"), &PoolConfig::testnet()).unwrap_err();
+        assert!(err.to_string().contains("too short"));
+    }
+
+    #[test]
+    fn test_rate_limited_rpc_surfaces_error() {
+        let rpc = cassette("rate_limited");
+        let err = pool_stats(&rpc, &PoolConfig::testnet()).unwrap_err();
+        assert!(err.to_string().contains("429"));
+
+        // The retry in the cassette succeeds
+        let stats = pool_stats(&rpc, &PoolConfig::testnet()).unwrap();
+        assert_eq!(stats.next_index, 26);
+        assert_eq!(stats.fill_percent(), 26.0 * 100.0 / 67_108_864.0);
+    }
+
+    #[test]
+    fn test_deposit_balance_check() {
+        let rpc = cassette("wallet_balance");
+        let owner: Pubkey = "FM7WTd5Hr7ppp6vu3M4uAspF4DoRjrYPPFvAmqB7H95D".parse().unwrap();
+        assert!(check_deposit_balance(&rpc, &owner, 100_000_000).is_ok());
+        let err = check_deposit_balance(&rpc, &owner, 1_000_000_000).unwrap_err();
+        assert!(err.to_string().contains("Insufficient balance"));
+    }
+
+    #[test]
+    fn test_vault_check() {
+        let config = PoolConfig::testnet();
+        let vault = |owner: &str, lamports: u64| {
+            let cassette = serde_json::json!({
+                "url": "https://api.testnet.solana.com",
+                "interactions": [{
+                    "method": "get_account",
+                    "params": [config.pool_vault.to_string()],
+                    "result": { "lamports": lamports, "owner": owner, "data": "" },
+                }],
+            });
+            Cassette::from_json(&cassette.to_string()).unwrap()
+        };
+        let system = "11111111111111111111111111111111";
+        let needed = privacy_zig_core::vault::withdrawal_lamports(500_000_000);
+
+        assert!(check_vault(&vault(system, needed), &config, 500_000_000).is_ok());
+        let err = check_vault(&vault(system, 510_000_000), &config, 510_000_000).unwrap_err();
+        assert!(err.to_string().contains("holds 0.510000000 SOL"), "{}", err);
+        assert!(err.to_string().contains("needs 0.512895360 SOL"), "{}", err);
+
+        let program = config.program_id.to_string();
+        let err = check_vault(&vault(&program, needed), &config, 500_000_000).unwrap_err();
+        assert!(err.to_string().contains("not the system program"), "{}", err);
+    }
+
+    #[test]
+    fn test_priority_fee() {
+        let config = PoolConfig::testnet();
+        let rpc = cassette("prioritization_fees");
+        let transact =
+            Instruction::new_with_bytes(config.program_id, &TRANSACT_DISCRIMINATOR, vec![]);
+        for (fee, price) in [(PriorityFee::Fixed(25_000), 25_000), (PriorityFee::Auto, 5_000)] {
+            let price_ix = ComputeBudgetInstruction::set_compute_unit_price(price);
+            let unit_price = priority_fee(&rpc, &config, fee).unwrap();
+            let instructions =
+                with_compute_budget(transact.clone(), COMPUTE_UNIT_LIMIT, unit_price);
+            assert_eq!(instructions.len(), 3);
+            assert_eq!(instructions[1], price_ix);
+            assert_eq!(instructions[2], transact);
+        }
+
+        // The cassette holds a single fee lookup, used by `auto` above
+        let err = priority_fee(&rpc, &config, PriorityFee::Auto).unwrap_err();
+        assert!(format!("{:#}", err).contains("--priority-fee auto"));
+    }
+
+    #[test]
+    fn test_commitments_outside_the_field_are_rejected() {
+        let mut data = vec![0u8; 432];
+        data[391] = 1;
+        data[392..424].fill(0xff);
+        let err = commitments(&data).unwrap_err();
+        assert!(format!("{:#}", err).contains("not below the field modulus"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_fetch_leaves_skips_failed_and_foreign_transactions() {
+        let leaves = fetch_leaves_from_chain(&cassette("commitment_sync"), &PoolConfig::testnet()).unwrap();
+
+        // Oldest first; the failed transaction and the foreign program are skipped
+        assert_eq!(leaves.len(), 4);
+        assert_eq!(leaves[0].commitment, Fr::from(1u64));
+        assert_eq!(leaves[1].commitment, Fr::from(2u64));
+        assert_eq!(leaves[0].public_amount, 100_000_000);
+        assert_eq!(leaves[2].commitment, Fr::from(5u64));
+        assert_eq!(leaves[2].public_amount, -100_000_000);
+        assert_eq!(leaves[3].signature, leaves[2].signature);
+    }
+
+    /// A pool transact instruction inserting commitments `commitment` and
+    /// `commitment + 1`
+    fn transact_ix(commitment: u8, public_amount: i64) -> serde_json::Value {
+        use privacy_zig_core::TransactProofData;
+
+        let data = TransactProofData {
+            proof_a: vec![0; 64],
+            proof_b: vec![0; 128],
+            proof_c: vec![0; 64],
+            root: vec![0; 32],
+            nullifier1: vec![commitment; 32],
+            nullifier2: vec![commitment + 1; 32],
+            commitment1: [vec![0; 31], vec![commitment]].concat(),
+            commitment2: [vec![0; 31], vec![commitment + 1]].concat(),
+            public_amount,
+            ext_data_hash: vec![0; 32],
+        };
+        serde_json::json!({
+            "program_id": PoolConfig::testnet().program_id.to_string(),
+            "data": BASE64.encode(data.to_instruction_data()),
+        })
+    }
+
+    /// The pool's transaction history, given newest first as the RPC
+    /// returns it
+    fn history(transactions: &[(&str, serde_json::Value)]) -> Cassette {
+        let mut interactions = vec![serde_json::json!({
+            "method": "get_signatures_for_address",
+            "params": [PoolConfig::testnet().tree_account.to_string()],
+            "result": transactions.iter().map(|(sig, _)| sig).collect::<Vec<_>>(),
+        })];
+        for (signature, result) in transactions {
+            interactions.push(serde_json::json!({
+                "method": "get_transaction",
+                "params": [signature],
+                "result": result,
+            }));
+        }
+        let cassette = serde_json::json!({
+            "url": "https://api.testnet.solana.com",
+            "interactions": interactions,
+        });
+        Cassette::from_json(&cassette.to_string()).unwrap()
+    }
+
+    /// The log line of a `CommitmentData` event for `commitment` at `index`
+    fn event_log(index: u64, commitment: u8) -> String {
+        let mut bytes = [0; 32];
+        bytes[31] = commitment;
+        let event = CommitmentData { index, commitment: bytes };
+        format!("Program data: {}", BASE64.encode(event.to_bytes()))
+    }
+
+    #[test]
+    fn test_fetch_memos() {
+        let config = PoolConfig::testnet();
+        let transact = |commitment: u8| transact_ix(commitment, 0);
+        let memo = |data: &[u8]| {
+            serde_json::json!({
+                "program_id": MEMO_PROGRAM_ID.to_string(),
+                "data": BASE64.encode(data),
+            })
+        };
+        let tx = |failed: bool, instructions: Vec<serde_json::Value>| {
+            serde_json::json!({ "failed": failed, "block_time": null, "instructions": instructions })
+        };
+        let rpc = history(&[
+            ("failed", tx(true, vec![transact(7), memo(b"lost")])),
+            ("second", tx(false, vec![memo(b"first memo"), transact(3), memo(&[0xff, 0xfe])])),
+            ("first", tx(false, vec![transact(1)])),
+        ]);
+
+        // Only the successful transaction's UTF-8 memo, next to the leaves
+        // its transact inserted after the first transaction's two
+        let memos = fetch_memos(&rpc, &config).unwrap();
+        assert_eq!(memos.len(), 1);
+        assert_eq!(memos[0].memo, "first memo");
+        assert_eq!(memos[0].commitments, [Fr::from(3u64), Fr::from(4u64)]);
+        assert_eq!(memos[0].first_leaf, 2);
+        assert_eq!(memos[0].signature, "second");
+    }
+
+    #[test]
+    fn test_commitment_events_follow_the_invocation_stack() {
+        let pool = PoolConfig::testnet().program_id.to_string();
+        let router = "Router1111111111111111111111111111111111111";
+        let logs = [
+            "Program ComputeBudget111111111111111111111111111111 invoke [1]".to_string(),
+            "Program ComputeBudget111111111111111111111111111111 success".to_string(),
+            // Another program invokes the pool, then logs an event of the
+            // same shape itself
+            format!("Program {} invoke [1]", router),
+            format!("Program {} invoke [2]", pool),
+            "Program log: Inserting commitments...".to_string(),
+            event_log(4, 4),
+            event_log(5, 5),
+            format!("Program {} success", pool),
+            event_log(99, 99),
+            format!("Program {} success", router),
+            // A log line that looks like a return isn't one
+            format!("Program {} invoke [1]", pool),
+            "Program log: success".to_string(),
+            event_log(6, 6),
+            "Program data: not base64".to_string(),
+            event_log(7, 7),
+            format!("Program {} consumed 1000 of 200000 compute units", pool),
+            format!("Program {} success", pool),
+        ];
+        let indices: Vec<Vec<u64>> = commitment_events(&logs, &pool)
+            .iter()
+            .map(|events| events.iter().map(|event| event.index).collect())
+            .collect();
+        assert_eq!(indices, [vec![], vec![4, 5], vec![6, 7]]);
+    }
+
+    #[test]
+    fn test_fetch_leaves_prefers_logged_events() {
+        let config = PoolConfig::testnet();
+        let pool = config.program_id.to_string();
+        let tx = |commitment: u8, public_amount: i64, events: &[(u64, u8)]| {
+            let mut logs = vec![format!("Program {} invoke [1]", pool)];
+            logs.extend(events.iter().map(|&(index, commitment)| event_log(index, commitment)));
+            logs.push(format!("Program {} success", pool));
+            serde_json::json!({
+                "failed": false,
+                "block_time": null,
+                "instructions": [transact_ix(commitment, public_amount)],
+                "log_messages": logs,
+            })
+        };
+
+        // The instruction data says 1 to 4, as if its layout had changed;
+        // the events say otherwise, and that the newest-listed transaction
+        // landed first
+        let rpc = history(&[
+            ("second", tx(3, -5, &[(0, 10), (1, 11)])),
+            ("first", tx(1, 5, &[(2, 12), (3, 13)])),
+        ]);
+        let leaves = fetch_leaves_from_chain(&rpc, &config).unwrap();
+        let commitments: Vec<Fr> = leaves.iter().map(|leaf| leaf.commitment).collect();
+        assert_eq!(commitments, [10u64, 11, 12, 13].map(Fr::from));
+        assert_eq!(leaves[0].signature, "second");
+        assert_eq!(leaves[0].public_amount, -5);
+        assert_eq!(leaves[3].public_amount, 5);
+
+        // Without a pair of events, the instruction data is used
+        let rpc = history(&[("second", tx(3, 0, &[])), ("first", tx(1, 0, &[(0, 12), (1, 13)]))]);
+        let commitments: Vec<Fr> =
+            fetch_leaves_from_chain(&rpc, &config).unwrap().iter().map(|leaf| leaf.commitment).collect();
+        assert_eq!(commitments, [12u64, 13, 3, 4].map(Fr::from));
+
+        // A transaction missing from history leaves a gap
+        let rpc = history(&[("second", tx(3, 0, &[(2, 12), (3, 13)]))]);
+        let err = fetch_leaves_from_chain(&rpc, &config).unwrap_err().to_string();
+        assert!(err.contains("has leaf 2 where leaf 0 should be"), "{}", err);
+    }
+
+    #[test]
+    fn test_fetch_leaves_inserted_by_cpi() {
+        let config = PoolConfig::testnet();
+        let pool = config.program_id.to_string();
+        let router = "Router1111111111111111111111111111111111111";
+        // A router calls the pool, which logs the events under the
+        // router's top-level instruction
+        let routed = serde_json::json!({
+            "failed": false,
+            "block_time": null,
+            "instructions": [{ "program_id": router, "data": "" }],
+            "inner_instructions": [[transact_ix(1, 5)]],
+            "log_messages": [
+                format!("Program {} invoke [1]", router),
+                format!("Program {} invoke [2]", pool),
+                event_log(0, 1),
+                event_log(1, 2),
+                format!("Program {} success", pool),
+                format!("Program {} success", router),
+            ],
+        });
+        let direct = serde_json::json!({
+            "failed": false,
+            "block_time": null,
+            "instructions": [transact_ix(3, -5)],
+            "log_messages": [
+                format!("Program {} invoke [1]", pool),
+                event_log(2, 3),
+                event_log(3, 4),
+                format!("Program {} success", pool),
+            ],
+        });
+        let rpc = history(&[("direct", direct.clone()), ("routed", routed.clone())]);
+        let leaves = fetch_leaves_from_chain(&rpc, &config).unwrap();
+        let commitments: Vec<Fr> = leaves.iter().map(|leaf| leaf.commitment).collect();
+        assert_eq!(commitments, [1u64, 2, 3, 4].map(Fr::from));
+        assert_eq!(leaves[0].signature, "routed");
+        assert_eq!(leaves[0].public_amount, 5);
+
+        // The routed deposit counts as one
+        let rpc = history(&[("direct", direct), ("routed", routed)]);
+        let activity = pool_activity(&rpc, &config).unwrap();
+        assert_eq!((activity.deposits, activity.withdrawals), (1, 1));
+    }
+
+    #[test]
+    fn test_pool_activity() {
+        // One deposit and one withdrawal; the failed deposit and the foreign
+        // program don't count
+        let activity = pool_activity(&cassette("commitment_sync"), &PoolConfig::testnet()).unwrap();
+        assert_eq!(
+            activity,
+            PoolActivity {
+                deposits: 1,
+                withdrawals: 1,
+                transfers: 0,
+                nullifiers: 4,
+                last_deposit: Some(1_736_000_000),
+            }
+        );
+        assert_eq!(activity.anonymity_set(), 0);
+
+        let busy = PoolActivity {
+            deposits: 10,
+            withdrawals: 3,
+            transfers: 2,
+            nullifiers: 30,
+            ..PoolActivity::default()
+        };
+        assert_eq!(busy.anonymity_set(), 7);
+        let drained = PoolActivity { withdrawals: 2, ..PoolActivity::default() };
+        assert_eq!(drained.anonymity_set(), 0);
+    }
+
+    #[test]
+    fn test_pool_activity_from_cached_history() {
+        let config = PoolConfig::testnet();
+        let path = std::env::temp_dir().join(format!("history-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let rpc = cassette("commitment_sync");
+        let history = CachedHistory::open(&rpc, &path);
+        let activity = pool_activity(&history, &config).unwrap();
+        history.save().unwrap();
+
+        // Found transactions are read from the file; the one the cluster
+        // couldn't find is asked for again
+        let mut recorded: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(format!(
+                "{}/tests/fixtures/cassettes/commitment_sync.json",
+                env!("CARGO_MANIFEST_DIR")
+            ))
+            .unwrap(),
+        )
+        .unwrap();
+        recorded["interactions"]
+            .as_array_mut()
+            .unwrap()
+            .retain(|it| it["method"] != "get_transaction" || it["result"].is_null());
+        let rpc = Cassette::from_json(&recorded.to_string()).unwrap();
+        let history = CachedHistory::open(&rpc, &path);
+        assert_eq!(pool_activity(&history, &config).unwrap(), activity);
+
+        // A cache that can't be read only costs the requests it would save
+        std::fs::write(&path, "not json").unwrap();
+        let rpc = cassette("commitment_sync");
+        assert_eq!(pool_activity(&CachedHistory::open(&rpc, &path), &config).unwrap(), activity);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_commitment() {
+        let config = PoolConfig::testnet();
+        let rpc = cassette("commitment_sync");
+        let found = wait_for_commitment(&rpc, &config, &Fr::from(5u64), Duration::ZERO, |_| {}).await;
+        assert_eq!(found.unwrap(), 2);
+
+        // Not there, and no time left to wait for it
+        let rpc = cassette("commitment_sync");
+        let missing = wait_for_commitment(&rpc, &config, &Fr::from(99u64), Duration::ZERO, |_| {}).await;
+        let err = missing.unwrap_err();
+        assert!(err.to_string().contains("did not appear"));
+    }
+
+    #[test]
+    fn test_deposit_outcomes() {
+        let signatures: Vec<String> = ["ok", "failed", "unknown"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let outcomes = deposit_outcomes(&cassette("signature_statuses"), &signatures).unwrap();
+        assert_eq!(outcomes["ok"], DepositOutcome::Succeeded);
+        assert_eq!(outcomes["failed"], DepositOutcome::Failed);
+        assert_eq!(outcomes["unknown"], DepositOutcome::Unknown);
+    }
+
+    #[test]
+    fn test_reconcile_status() {
+        use DepositOutcome::*;
+        assert_eq!(reconcile_status(true, true, Succeeded), Some("withdrawn"));
+        assert_eq!(reconcile_status(true, false, Unknown), Some("deposited"));
+        // A failed deposit leaves a phantom note
+        assert_eq!(reconcile_status(false, false, Failed), Some("invalid"));
+        assert_eq!(reconcile_status(false, false, Unknown), Some("invalid"));
+        // Never invalidate a note whose deposit landed
+        assert_eq!(reconcile_status(false, false, Succeeded), None);
+    }
+
+    #[test]
+    fn test_note_state() {
+        use DepositOutcome::*;
+        assert_eq!(NoteState::new(true, false, Succeeded), NoteState::Spendable);
+        assert_eq!(NoteState::new(true, true, Unknown), NoteState::Spent);
+        assert_eq!(NoteState::new(false, false, Failed), NoteState::DepositFailed);
+        // Only flagged as missing when the deposit is known to have landed
+        assert_eq!(NoteState::new(false, false, Succeeded), NoteState::NotFound);
+    }
+
+    /// A tree account whose root history holds the roots of `trees`, the
+    /// last one current
+    fn tree_account(trees: &[&MerkleTree]) -> Cassette {
+        use privacy_zig_core::tree::{
+            NEXT_INDEX_OFFSET, ROOT_HISTORY_OFFSET, ROOT_HISTORY_SIZE, ROOT_INDEX_OFFSET,
+        };
+
+        let mut data = vec![0u8; ROOT_HISTORY_OFFSET + ROOT_HISTORY_SIZE * 32];
+        let last = trees.len() - 1;
+        let next_index = trees[last].leaf_count() as u64;
+        data[NEXT_INDEX_OFFSET..NEXT_INDEX_OFFSET + 8].copy_from_slice(&next_index.to_le_bytes());
+        data[ROOT_INDEX_OFFSET..ROOT_INDEX_OFFSET + 8].copy_from_slice(&(last as u64).to_le_bytes());
+        for (i, tree) in trees.iter().enumerate() {
+            let offset = ROOT_HISTORY_OFFSET + i * 32;
+            data[offset..offset + 32].copy_from_slice(&fr_to_be_bytes(&tree.root()));
+        }
+        let account = serde_json::json!({
+            "url": "https://api.testnet.solana.com",
+            "interactions": [{
+                "method": "get_account",
+                "params": [PoolConfig::testnet().tree_account.to_string()],
+                "result": {
+                    "lamports": 1000000,
+                    "owner": PoolConfig::testnet().program_id.to_string(),
+                    "data": BASE64.encode(&data),
+                },
+            }],
+        });
+        Cassette::from_json(&account.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_check_tree_root() {
+        let config = PoolConfig::testnet();
+        let tree = |leaves: &[u64]| {
+            let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+            tree.insert_many(&leaves.iter().map(|&l| Fr::from(l)).collect::<Vec<_>>()).unwrap();
+            tree
+        };
+        let (older, current) = (tree(&[1, 2]), tree(&[1, 2, 3, 4]));
+
+        assert!(check_tree_root(&tree_account(&[&older, &current]), &config, &current).is_ok());
+        // Deposits landed after the history was fetched
+        assert!(check_tree_root(&tree_account(&[&older, &current]), &config, &older).is_ok());
+
+        // Commitment 3 was missed while fetching
+        let err = check_tree_root(&tree_account(&[&older, &current]), &config, &tree(&[1, 2, 4]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("(3 leaves"), "{}", err);
+        let current_root = hex::encode(fr_to_be_bytes(&current.root()));
+        assert!(err.contains(&format!("(4 leaves, root {})", current_root)));
+    }
+
+    #[test]
+    fn test_root_history_ring_buffer() {
+        // 130 transactions of two leaves each have wrapped the buffer
+        let history = root_history(&cassette("root_history"), &PoolConfig::testnet()).unwrap();
+        assert_eq!(history.len(), ROOT_HISTORY_SIZE);
+        let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+        tree.insert_many(&(1..=260u64).map(Fr::from).collect::<Vec<_>>()).unwrap();
+        let accepted = |leaves| history.contains(&fr_to_be_bytes(&tree.root_after(leaves).unwrap()));
+
+        assert!(accepted(260));
+        assert!(accepted(62));
+        // Overwritten by the 100 transactions since
+        assert!(!accepted(60));
+        // Between the two inserts of one transaction, never recorded
+        assert!(!accepted(259));
+
+        let err = root_history(&cassette("tree_missing"), &PoolConfig::testnet()).unwrap_err();
+        assert!(err.to_string().contains("not found"));
+    }
+
+    #[test]
+    fn test_accepted_tree_rotates_back_to_a_recorded_root() {
+        let config = PoolConfig::testnet();
+        let tree = |leaves: u64| {
+            let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+            tree.insert_many(&(1..=leaves).map(Fr::from).collect::<Vec<_>>()).unwrap();
+            tree
+        };
+        let recorded = tree(260).root();
+
+        // In step with the account
+        let accepted = accepted_tree(&cassette("root_history"), &config, tree(260)).unwrap();
+        assert_eq!(accepted.root(), recorded);
+
+        // The RPC served a transaction the account doesn't count yet
+        let accepted = accepted_tree(&cassette("root_history"), &config, tree(262)).unwrap();
+        assert_eq!(accepted.leaf_count(), 260);
+        assert_eq!(accepted.root(), recorded);
+
+        // With commitment 1 missed, no recent prefix has a recorded root
+        let mut missed = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+        missed.insert_many(&(2..=260u64).map(Fr::from).collect::<Vec<_>>()).unwrap();
+        let Err(err) = accepted_tree(&cassette("root_history"), &config, missed) else {
+            panic!("a tree without any recorded root was accepted");
+        };
+        assert!(err.to_string().contains("(259 leaves"), "{}", err);
+    }
+
+    #[test]
+    fn test_nullifier_reconciliation() {
+        use crate::crypto::sol_mint;
+
+        let config = PoolConfig::testnet();
+        let utxo = Utxo::from_values(100_000_000, "123", "456", "789", sol_mint()).unwrap();
+        let nullifier = utxo.compute_nullifier(0).unwrap();
+        let pda = config.nullifier_pda(&fr_to_be_bytes(&nullifier));
+        let fixture = include_str!("../tests/fixtures/cassettes/nullifiers.json")
+            .replace("{SPENT_PDA}", &pda.to_string());
+        let rpc = Cassette::from_json(&fixture).unwrap();
+
+        // Unspent, then spent from another device, then the RPC fails
+        assert!(!is_spent(&rpc, &config, &utxo, 0).unwrap());
+        assert!(is_spent(&rpc, &config, &utxo, 0).unwrap());
+        // An RPC failure is an error, not "unspent"
+        assert!(is_spent(&rpc, &config, &utxo, 0).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+//! `~/.privacy-zig/config.toml` and `privacy init`
+//!
+//! Each setting resolves as flag > environment variable > config file >
+//! built-in default. Pool addresses in the file sit between the
+//! `PRIVACY_POOL_*` variables and the network's preset.
+//!
+//! ```toml
+//! network = "testnet"              # used without --network
+//! rpc_url = "https://api.testnet.solana.com,https://rpc.example.com"
+//! artifacts_path = "/opt/privacy-zig/artifacts"
+//! artifacts_url = "https://mirror.example.com/privacy-zig/artifacts"
+//! artifacts_sha256 = "transaction2.zkey=4aa7aa5c...e693"   # replaces a pin
+//! backup_dir = "/mnt/usb/privacy-zig"   # every save copies the note store here
+//! tree_account = "4EGnTF2XfKDTBAszzoqQLe4zbmiURkWtkYQGnj99GiJf"
+//! ```
+
+use anyhow::{anyhow, Context, Result};
+use console::style;
+use dialoguer::{Confirm, Input};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::artifacts;
+use crate::notes::NoteStore;
+use crate::output::say;
+use crate::pool::{Network, PoolAddresses, PoolConfig};
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CliConfig {
+    /// Cluster to use without `--network`: devnet, testnet or mainnet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifacts_path: Option<String>,
+    /// Where `privacy artifacts download` fetches from, comma-separated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifacts_url: Option<String>,
+    /// Checksums replacing the pinned ones, as comma-separated `NAME=SHA256`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifacts_sha256: Option<String>,
+    /// Directory every save of the note store copies it into
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup_dir: Option<String>,
+    #[serde(flatten)]
+    pub pool: PoolAddresses,
+}
+
+impl CliConfig {
+    /// Read `--config`, which must exist, or else the default file if there
+    /// is one
+    pub fn load(explicit: Option<&str>) -> Result<Self> {
+        let path = match explicit {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let path = default_path()?;
+                if !path.exists() {
+                    return Ok(Self::default());
+                }
+                path
+            }
+        };
+        let data = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config from {}", path.display()))?;
+        Self::parse(&data).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    fn parse(data: &str) -> Result<Self> {
+        Ok(toml::from_str(data)?)
+    }
+
+    /// Write the config to `path`, creating its directory
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write config to {}", path.display()))
+    }
+
+    /// The cluster to use, if one is chosen; `flag` is `--network`
+    pub fn network(&self, flag: Option<Network>) -> Result<Option<Network>> {
+        if flag.is_some() {
+            return Ok(flag);
+        }
+        self.network
+            .as_deref()
+            .map(Network::from_str)
+            .transpose()
+            .context("Invalid network in the config file")
+    }
+
+    /// RPC endpoints to fail over between; `flag` is `--rpc-url` or
+    /// `PRIVACY_RPC_URL`, already split at commas
+    pub fn rpc_urls(&self, flag: &[String], network: Network) -> Vec<String> {
+        if !flag.is_empty() {
+            return flag.to_vec();
+        }
+        self.rpc_url
+            .as_deref()
+            .map(split_urls)
+            .filter(|urls| !urls.is_empty())
+            .unwrap_or_else(|| vec![network.rpc_url().to_string()])
+    }
+
+    /// `flag` is `--artifacts` or `PRIVACY_ARTIFACTS`
+    pub fn artifacts_path(&self, flag: Option<&str>, default: impl FnOnce() -> String) -> String {
+        flag.or(self.artifacts_path.as_deref())
+            .map(str::to_string)
+            .unwrap_or_else(default)
+    }
+
+    /// Base URLs to download artifacts from, tried in order; `flag` is
+    /// `--url` or `PRIVACY_ARTIFACTS_URL`, already split at commas
+    pub fn artifact_urls(&self, flag: &[String]) -> Vec<String> {
+        if !flag.is_empty() {
+            return flag.to_vec();
+        }
+        self.artifacts_url
+            .as_deref()
+            .map(split_urls)
+            .filter(|urls| !urls.is_empty())
+            .unwrap_or_else(|| artifacts::DEFAULT_URLS.map(str::to_string).to_vec())
+    }
+
+    /// Checksums the artifacts are held to; `flag` is `--artifacts-sha256`
+    /// or `PRIVACY_ARTIFACTS_SHA256`, already split at commas, and wins over
+    /// the file for the files it names
+    pub fn artifact_pins(&self, flag: &[String]) -> Result<Vec<artifacts::Pin>> {
+        let mut overrides = self
+            .artifacts_sha256
+            .as_deref()
+            .map(split_urls)
+            .unwrap_or_default();
+        overrides.extend_from_slice(flag);
+        artifacts::parse_pins(&overrides).context("Invalid artifact checksum")
+    }
+}
+
+fn split_urls(urls: &str) -> Vec<String> {
+    urls.split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+pub fn default_path() -> Result<PathBuf> {
+    Ok(NoteStore::data_dir()?.join("config.toml"))
+}
+
+/// Prompt for a starter config and write it to `path`
+pub fn run_init(path: &Path, network: Network, artifacts_path: &str, force: bool) -> Result<()> {
+    say!("{}", style("📝 Init").bold());
+    say!("{}", style("─".repeat(40)).dim());
+
+    if path.exists() && !force {
+        return Err(anyhow!(
+            "{} already exists; pass --force to overwrite it",
+            path.display()
+        ));
+    }
+
+    let rpc_url = Input::<String>::new()
+        .with_prompt("RPC URL")
+        .default(network.rpc_url().to_string())
+        .interact_text()?;
+    let artifacts_path = Input::<String>::new()
+        .with_prompt("Circuit artifacts directory")
+        .default(artifacts_path.to_string())
+        .interact_text()?;
+
+    let preset = PoolConfig::preset(network);
+    let mut pool = PoolAddresses::default();
+    let custom = preset.is_none()
+        || Confirm::new()
+            .with_prompt(format!("Use pool addresses other than the {} deployment?", network.name()))
+            .default(false)
+            .interact()?;
+    if custom {
+        let preset = preset.as_ref();
+        pool.program_id = prompt_address("program_id", preset.map(|p| p.program_id))?;
+        pool.tree_account = prompt_address("tree_account", preset.map(|p| p.tree_account))?;
+        pool.global_config = prompt_address("global_config", preset.map(|p| p.global_config))?;
+        pool.pool_vault = prompt_address("pool_vault", preset.map(|p| p.pool_vault))?;
+        pool.fee_recipient = prompt_address("fee_recipient", preset.map(|p| p.fee_recipient))?;
+    }
+
+    let config = CliConfig {
+        network: None,
+        rpc_url: Some(rpc_url),
+        artifacts_path: Some(artifacts_path),
+        artifacts_url: None,
+        artifacts_sha256: None,
+        backup_dir: None,
+        pool,
+    };
+    config.save(path)?;
+
+    say!();
+    say!("{}", style(format!("✅ Wrote {}", path.display())).green().bold());
+    Ok(())
+}
+
+/// Ask for an address, keeping it only if it differs from the preset
+fn prompt_address(field: &str, preset: Option<Pubkey>) -> Result<Option<String>> {
+    let mut input = Input::<String>::new().with_prompt(field).validate_with(|value: &String| {
+        Pubkey::from_str(value.trim())
+            .map(|_| ())
+            .map_err(|_| format!("{} must be a base58 pubkey", field))
+    });
+    if let Some(preset) = preset {
+        input = input.default(preset.to_string());
+    }
+    let value = input.interact_text()?.trim().to_string();
+    Ok((Some(value.as_str()) != preset.map(|p| p.to_string()).as_deref()).then_some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use privacy_zig_core::ConfigError;
+
+    #[test]
+    fn test_parses_config_file() {
+        let config = CliConfig::parse(
+            r#"
+            rpc_url = "http://localhost:8899"
+            artifacts_path = "/opt/artifacts"
+            tree_account = "4EGnTF2XfKDTBAszzoqQLe4zbmiURkWtkYQGnj99GiJf"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.rpc_url.as_deref(), Some("http://localhost:8899"));
+        assert_eq!(
+            config.pool.tree_account.as_deref(),
+            Some("4EGnTF2XfKDTBAszzoqQLe4zbmiURkWtkYQGnj99GiJf")
+        );
+        assert!(config.pool.program_id.is_none());
+
+        let written = toml::to_string_pretty(&config).unwrap();
+        assert_eq!(CliConfig::parse(&written).unwrap(), config);
+    }
+
+    #[test]
+    fn test_flag_overrides_file_overrides_default() {
+        let config = CliConfig {
+            rpc_url: Some("http://localhost:8899, http://backup:8899".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.rpc_urls(&["http://flag:8899".to_string()], Network::Testnet),
+            ["http://flag:8899"]
+        );
+        assert_eq!(
+            config.rpc_urls(&[], Network::Testnet),
+            ["http://localhost:8899", "http://backup:8899"]
+        );
+        assert_eq!(
+            CliConfig::default().rpc_urls(&[], Network::Devnet),
+            [Network::Devnet.rpc_url()]
+        );
+        assert_eq!(
+            config.artifacts_path(None, || "../artifacts".to_string()),
+            "../artifacts"
+        );
+    }
+
+    #[test]
+    fn test_network_flag_overrides_file() {
+        let config = CliConfig::parse(r#"network = "devnet""#).unwrap();
+        assert_eq!(config.network(None).unwrap(), Some(Network::Devnet));
+        assert_eq!(config.network(Some(Network::Mainnet)).unwrap(), Some(Network::Mainnet));
+        assert_eq!(CliConfig::default().network(None).unwrap(), None);
+
+        let err = CliConfig::parse(r#"network = "localnet""#).unwrap().network(None).unwrap_err();
+        assert!(format!("{:#}", err).contains("localnet"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_artifact_urls() {
+        let config = CliConfig::parse(
+            r#"artifacts_url = "https://mirror.example.com/a, https://backup.example.com/a""#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.artifact_urls(&[]),
+            ["https://mirror.example.com/a", "https://backup.example.com/a"]
+        );
+        assert_eq!(
+            config.artifact_urls(&["http://localhost:8000".to_string()]),
+            ["http://localhost:8000"]
+        );
+        assert_eq!(CliConfig::default().artifact_urls(&[]), artifacts::DEFAULT_URLS);
+    }
+
+    #[test]
+    fn test_artifact_pins() {
+        let (zkey, other) = ("a".repeat(64), "b".repeat(64));
+        let config =
+            CliConfig::parse(&format!(r#"artifacts_sha256 = "transaction2.zkey={}""#, zkey))
+                .unwrap();
+        let pins = config.artifact_pins(&[]).unwrap();
+        assert_eq!(pins[0].sha256, artifacts::ARTIFACTS[0].sha256);
+        assert_eq!(pins[1].sha256, zkey);
+
+        let flag = [format!("transaction2.zkey={}", other)];
+        assert_eq!(config.artifact_pins(&flag).unwrap()[1].sha256, other);
+        assert!(config.artifact_pins(&["transaction2.zkey=".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_address_names_field() {
+        let config = CliConfig::parse(r#"pool_vault = "not-a-pubkey""#).unwrap();
+        let err = PoolConfig::resolve(Network::Testnet, &config.pool).unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidAddress { field: "pool_vault", .. }));
+        assert!(err.to_string().contains("pool_vault"));
+    }
+
+    #[test]
+    fn test_explicit_config_must_exist() {
+        let err = CliConfig::load(Some("/nonexistent/config.toml")).unwrap_err();
+        assert!(err.to_string().contains("/nonexistent/config.toml"));
+    }
+}
@@ -0,0 +1,134 @@
+//! Waiting between the deposit and the withdrawal of a transfer
+//!
+//! The proof hides which deposit a withdrawal spends, but not when it
+//! happens. A deposit followed seconds later by a withdrawal of the same
+//! amount is linked by timing alone, so by default `transfer` gives away
+//! exactly what the pool is meant to hide. `--delay` waits a fixed time
+//! after the deposit is in the tree and before the withdrawal is proved;
+//! `--random-delay` picks the wait at random, so the gap doesn't have a
+//! known size either. Other deposits made in the meantime are what the
+//! withdrawal hides among: the longer the wait, the larger that set.
+
+use anyhow::{anyhow, Result};
+use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::interrupt;
+
+/// Seconds to wait, picked uniformly from `min..=max`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DelayRange {
+    pub min: u64,
+    pub max: u64,
+}
+
+impl DelayRange {
+    pub fn fixed(secs: u64) -> Self {
+        Self { min: secs, max: secs }
+    }
+
+    pub fn pick(&self, rng: &mut impl Rng) -> Duration {
+        Duration::from_secs(rng.gen_range(self.min..=self.max))
+    }
+}
+
+/// `MIN..MAX` in seconds, both included
+impl FromStr for DelayRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (min, max) = s
+            .split_once("..")
+            .ok_or_else(|| anyhow!("expected MIN..MAX in seconds, e.g. 600..3600"))?;
+        let secs = |value: &str| {
+            value
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| anyhow!("{:?} is not a number of seconds", value))
+        };
+        let (min, max) = (secs(min)?, secs(max)?);
+        if min > max {
+            return Err(anyhow!("the minimum {} is above the maximum {}", min, max));
+        }
+        Ok(Self { min, max })
+    }
+}
+
+impl fmt::Display for DelayRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", format_duration(Duration::from_secs(self.min)))
+        } else {
+            write!(
+                f,
+                "{} to {}",
+                format_duration(Duration::from_secs(self.min)),
+                format_duration(Duration::from_secs(self.max))
+            )
+        }
+    }
+}
+
+/// Wait `delay` with a countdown. Ctrl-C exits, leaving the deposited note
+/// to withdraw later.
+pub async fn wait(delay: Duration) {
+    let pb = interrupt::spinner();
+    let until = Instant::now() + delay;
+    loop {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        pb.set_message(format!(
+            "Waiting {} before withdrawing, so the two aren't linked by timing...",
+            format_duration(left)
+        ));
+        tokio::time::sleep(left.min(Duration::from_secs(1))).await;
+    }
+    pb.finish_with_message(format!("Waited {}", format_duration(delay)));
+}
+
+/// `1h 2m 3s`, leaving out leading zero units
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_parse_delay_range() {
+        assert_eq!(
+            "600..3600".parse::<DelayRange>().unwrap(),
+            DelayRange { min: 600, max: 3600 }
+        );
+        assert_eq!(" 5 .. 5 ".parse::<DelayRange>().unwrap(), DelayRange::fixed(5));
+        assert!("3600..600".parse::<DelayRange>().is_err());
+        assert!("600".parse::<DelayRange>().is_err());
+        assert!("10m..1h".parse::<DelayRange>().is_err());
+        assert_eq!(DelayRange { min: 90, max: 3600 }.to_string(), "1m 30s to 1h 0m 0s");
+    }
+
+    #[test]
+    fn test_random_delay_stays_in_range() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let range = DelayRange { min: 10, max: 20 };
+        let picks: Vec<u64> = (0..200).map(|_| range.pick(&mut rng).as_secs()).collect();
+        assert!(picks.iter().all(|secs| (10..=20).contains(secs)));
+        assert!(picks.contains(&10) && picks.contains(&20));
+        assert_eq!(DelayRange::fixed(7).pick(&mut rng), Duration::from_secs(7));
+    }
+}
@@ -0,0 +1,192 @@
+//! Ctrl-C handling
+//!
+//! The handler runs as its own task. During a cancellable step, such as
+//! proving on a blocking thread with [`run_cancellable`], Ctrl-C cancels the
+//! step and the command unwinds with [`Cancelled`] before anything is sent.
+//! Otherwise, or on a second Ctrl-C, it waits for any note store write in
+//! progress to finish, finishes the active spinners, says what state was
+//! left behind and exits with status 130. Exiting between two writes is
+//! safe: every write to `notes.json` is a complete, atomic replacement.
+
+use anyhow::{anyhow, Result};
+use console::style;
+use indicatif::{ProgressBar, ProgressStyle, WeakProgressBar};
+use std::future::Future;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::output::{self, say};
+
+pub use privacy_zig_core::cancel::{CancelToken, Cancelled};
+
+/// Held while `notes.json` is written, and by the handler until exit
+static WRITING: Mutex<()> = Mutex::new(());
+
+/// Spinners to finish on Ctrl-C
+static SPINNERS: Mutex<Vec<WeakProgressBar>> = Mutex::new(Vec::new());
+
+/// What an interruption now would leave behind, e.g. a deposited note whose
+/// withdrawal hasn't been sent
+static PENDING: Mutex<Option<String>> = Mutex::new(None);
+
+/// The step Ctrl-C cancels rather than exiting, if one is running
+static RUNNING: Mutex<Option<CancelToken>> = Mutex::new(None);
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Install the handler; call once from within the runtime
+pub fn install() {
+    tokio::spawn(async {
+        loop {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            if !cancel_running() {
+                exit("Cancelled");
+            }
+        }
+    });
+}
+
+/// Cancel the running step, unless there is none or it was already
+/// cancelled
+fn cancel_running() -> bool {
+    match lock(&RUNNING).as_ref() {
+        Some(token) if !token.is_cancelled() => {
+            token.cancel();
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Finish the spinners, say `message` and what was left behind, and exit
+/// with status 130
+pub fn exit(message: &str) -> ! {
+    // Never exit halfway through a write, and never start another
+    let _writing = lock(&WRITING);
+    for pb in lock(&SPINNERS).drain(..).filter_map(|pb| pb.upgrade()) {
+        pb.abandon_with_message(style("Interrupted").red().to_string());
+    }
+    say!();
+    say!("{}", style(message).red());
+    let pending = lock(&PENDING).take();
+    if let Some(pending) = &pending {
+        say!("{}", style(pending).yellow());
+    }
+    let error = match pending {
+        Some(pending) => format!("Interrupted: {}", pending),
+        None => "Interrupted".to_string(),
+    };
+    let _ = output::emit(&serde_json::json!({ "error": error }));
+    std::process::exit(130);
+}
+
+/// Run `step` on a blocking thread, so the runtime stays responsive. Ctrl-C
+/// cancels the token `step` is given and returns [`Cancelled`] at once; the
+/// thread finishes at its next check and its result is dropped.
+pub async fn run_cancellable<T: Send + 'static>(
+    step: impl FnOnce(&CancelToken) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let token = CancelToken::default();
+    *lock(&RUNNING) = Some(token.clone());
+    let worker = token.clone();
+    let task = tokio::task::spawn_blocking(move || step(&worker));
+    let result = tokio::select! {
+        result = task => result.map_err(|e| anyhow!("Blocking task failed: {}", e)).and_then(|r| r),
+        () = token.cancelled() => Err(Cancelled.into()),
+    };
+    lock(&RUNNING).take();
+    result
+}
+
+/// Guard to hold while writing the note store, so Ctrl-C waits for it
+pub fn writing() -> MutexGuard<'static, ()> {
+    lock(&WRITING)
+}
+
+/// Record what an interruption from now on would leave behind
+pub fn set_pending(message: String) {
+    *lock(&PENDING) = Some(message);
+}
+
+pub fn clear_pending() {
+    lock(&PENDING).take();
+}
+
+fn spinner_style(template: &str) -> ProgressStyle {
+    ProgressStyle::default_spinner().template(template).unwrap()
+}
+
+/// A ticking spinner that Ctrl-C finishes rather than leaves mid-frame
+pub fn spinner() -> ProgressBar {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(spinner_style("{spinner:.green} {msg}"));
+    pb.enable_steady_tick(Duration::from_millis(100));
+    let mut spinners = lock(&SPINNERS);
+    spinners.retain(|pb| pb.upgrade().is_some());
+    spinners.push(pb.downgrade());
+    pb
+}
+
+/// Await `work` with `pb` showing `message` and how long it has taken
+pub async fn timed<T>(pb: &ProgressBar, message: &str, work: impl Future<Output = T>) -> T {
+    pb.set_style(spinner_style("{spinner:.green} {msg} {elapsed}"));
+    pb.reset_elapsed();
+    pb.set_message(message.to_string());
+    let result = work.await;
+    pb.set_style(spinner_style("{spinner:.green} {msg}"));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_cancelled_proof_returns_promptly() {
+        // A stub prover: a short witness phase, then a long proving phase
+        let proving_started = Arc::new(AtomicBool::new(false));
+        let started = proving_started.clone();
+        let step = run_cancellable(move |cancel| {
+            std::thread::sleep(Duration::from_millis(200));
+            cancel.check()?;
+            started.store(true, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_secs(10));
+            Ok(())
+        });
+
+        let began = Instant::now();
+        let ctrl_c = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert!(cancel_running());
+            // A second Ctrl-C exits instead
+            assert!(!cancel_running());
+        };
+        let (result, ()) = tokio::join!(step, ctrl_c);
+        assert!(result.unwrap_err().is::<Cancelled>());
+        assert!(
+            began.elapsed() < Duration::from_millis(150),
+            "{:?}",
+            began.elapsed()
+        );
+        assert!(lock(&RUNNING).is_none());
+
+        // The stub stops at its next check rather than starting to prove
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!proving_started.load(Ordering::SeqCst));
+
+        // Left alone, a step returns its result and nothing is cancellable
+        let result = run_cancellable(|cancel| {
+            cancel.check()?;
+            Ok(42)
+        });
+        assert_eq!(result.await.unwrap(), 42);
+        assert!(!cancel_running());
+    }
+}
@@ -0,0 +1,58 @@
+//! privacy-zig as a library: notes, the Merkle tree, proof generation and
+//! the transact instructions, for wallets and bots that deposit and withdraw
+//! without shelling out to the `privacy` CLI, which is built on it.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use privacy_cli::{build_deposit, CancelToken, MerkleTree, PrivacyProver, Utxo};
+//! use privacy_cli::crypto::sol_mint;
+//! use privacy_zig_core::{PoolConfig, MERKLE_TREE_HEIGHT};
+//! # let depositor = solana_sdk::pubkey::Pubkey::new_unique();
+//!
+//! let prover = PrivacyProver::from_dir("artifacts", |_, _| {})?;
+//! let utxo = Utxo::new(100_000_000, sol_mint())?;
+//! // The pool's current root, from its tree account
+//! let root = MerkleTree::new(MERKLE_TREE_HEIGHT)?.root();
+//! let ix = build_deposit(&prover, &PoolConfig::testnet(), None, &depositor, &utxo, root, &CancelToken::default())?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`PrivacyProver`] is `Send` and `Sync`: load it once and share it in an
+//! `Arc` between threads; proofs on one prover run one at a time. [`Utxo`],
+//! [`MerkleTree`], [`ExtData`] and [`TransactProofData`] are plain data and
+//! `Send` and `Sync` too. Keep a note's `Utxo` as secret as a private key:
+//! it is what spends it.
+
+pub mod artifacts;
+pub mod interrupt;
+pub mod notes;
+pub mod output;
+pub mod prover;
+pub mod shielded;
+pub mod store_key;
+pub mod transact;
+
+pub use privacy_zig_core::{circuit, crypto};
+
+pub use crypto::{CryptoError, ExtData, MerkleTree, Utxo};
+pub use interrupt::CancelToken;
+pub use prover::{PrivacyProver, Progress, ProverError, ProverStage, TransactProofData, TransferOutput};
+pub use transact::{build_deposit, build_withdraw, transact_instruction, BuildError, Withdrawal};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_public_types_are_send_and_sync() {
+        assert_send_sync::<PrivacyProver>();
+        assert_send_sync::<Utxo>();
+        assert_send_sync::<MerkleTree>();
+        assert_send_sync::<ExtData>();
+        assert_send_sync::<TransactProofData>();
+        assert_send_sync::<CancelToken>();
+    }
+}
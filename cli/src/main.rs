@@ -1,30 +1,72 @@
 use anyhow::{anyhow, Result};
-use clap::{Parser, Subcommand};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use clap::{Args, Parser, Subcommand};
 use console::style;
 use dialoguer::{Confirm, Select};
-use indicatif::{ProgressBar, ProgressStyle};
-use solana_client::rpc_client::RpcClient;
+use indicatif::ProgressBar;
+use serde::Serialize;
 use solana_sdk::{
-    commitment_config::CommitmentConfig,
-    compute_budget::ComputeBudgetInstruction,
-    instruction::{AccountMeta, Instruction},
+    instruction::Instruction,
+    message::Message,
     pubkey::Pubkey,
-    signature::{read_keypair_file, Keypair, Signer},
-    system_program,
+    signature::{Keypair, Signer},
     transaction::Transaction,
 };
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::Duration;
 
-mod crypto;
-mod notes;
+use privacy_cli::{
+    artifacts, crypto, interrupt, notes, output, prover, shielded, store_key, transact_instruction,
+};
+
+mod backup;
+mod bench;
+mod chain;
+mod config;
+mod delay;
 mod pool;
-mod prover;
+mod privacy_analysis;
+mod proof_file;
+mod relayer;
+mod retry;
+mod rpc;
+mod setup;
+mod token;
+
+use backup::NoteBackup;
+use chain::{fetch_commitments_from_chain, fetch_leaves_from_chain, is_spent};
+use config::CliConfig;
+use crypto::{
+    sol_mint, CommitmentSet, CryptoError, ExtData, MerkleTree, NoteSeed, Utxo, MERKLE_TREE_HEIGHT,
+};
+use delay::DelayRange;
+use notes::{
+    format_timestamp, ExportFormat, ImportMerge, Note, NoteFilter, NoteSort, NoteStore,
+    NOTE_STATUSES,
+};
+use output::say;
+use pool::{Network, PoolConfig};
+use privacy_analysis::{AnonymitySet, Tier};
+use privacy_zig_core::global_config::format_rate;
+use privacy_zig_core::{
+    load_keypair, memo_instruction, priority_fee_lamports, sized_compute_unit_limit,
+    with_compute_budget, PriorityFee, COMPUTE_UNIT_LIMIT, TRANSACT_SPL_DISCRIMINATOR,
+};
+use proof_file::{ProofFile, RootFreshness};
+use prover::{PrivacyProver, ProverError, ProverStage, TransferOutput};
+use retry::RetryPolicy;
+use rpc::{CachedHistory, ClientPool, PoolRpc, RpcOptions};
+use setup::{SetupAnswers, SetupContext, SetupStep};
+use shielded::{IncomingNote, MemoNote, ShieldedAddress, ViewingKey};
+use token::Token;
+
+/// Minimum age of an existing note before `transfer` withdraws from it
+/// instead of depositing fresh funds
+const REUSE_MIN_NOTE_AGE_SECS: u64 = 6 * 3600;
 
-use crypto::{MerkleTree, Utxo, MERKLE_TREE_HEIGHT};
-use notes::{Note, NoteStore};
-use pool::{PoolConfig, PROGRAM_ID};
-use prover::PrivacyProver;
+/// Fresh proofs `withdraw -y` generates when the root history moves on while proving
+const MAX_REPROOFS: u32 = 3;
 
 #[derive(Parser)]
 #[command(name = "privacy")]
@@ -32,21 +74,84 @@ use prover::PrivacyProver;
 #[command(version = "0.1.0")]
 #[command(about = "Anonymous SOL transfers on Solana", long_about = None)]
 struct Cli {
-    /// Solana RPC URL
-    #[arg(short, long, default_value = "https://api.testnet.solana.com")]
-    rpc_url: String,
-
-    /// Path to keypair file
-    #[arg(short, long, default_value_t = default_keypair_path())]
+    /// Cluster to use: devnet, testnet or mainnet [default: the config
+    /// file's `network`, else testnet]. Selects the RPC URL and pool
+    /// addresses; the config file and `PRIVACY_POOL_*` variables override
+    /// single addresses
+    #[arg(long)]
+    network: Option<Network>,
+
+    /// Solana RPC URL; pass several, comma-separated, to fail over between
+    /// them [default: the network's public endpoint]
+    #[arg(short, long, env = "PRIVACY_RPC_URL", value_delimiter = ',')]
+    rpc_url: Vec<String>,
+
+    /// Times to retry an RPC request that was rate limited or timed out
+    #[arg(long, env = "PRIVACY_RPC_RETRIES", default_value_t = retry::DEFAULT_RETRIES)]
+    rpc_retries: u32,
+
+    /// Seconds to wait for each RPC request
+    #[arg(long, env = "PRIVACY_RPC_TIMEOUT", default_value_t = rpc::DEFAULT_TIMEOUT_SECS)]
+    rpc_timeout: u64,
+
+    /// Keypair file, or the key itself: a JSON byte array, a base58 secret
+    /// key or a seed phrase
+    #[arg(short, long, env = "PRIVACY_ZIG_KEYPAIR", hide_env_values = true, default_value_t = default_keypair_path())]
     keypair: String,
 
-    /// Path to circuit artifacts directory
-    #[arg(short, long, default_value_t = default_artifacts_path())]
-    artifacts: String,
-
-    /// Relayer URL for anonymous withdrawals
-    #[arg(long, default_value = "http://localhost:3001")]
-    relayer_url: String,
+    /// Path to circuit artifacts directory [default: ~/.privacy-zig/artifacts,
+    /// or ../artifacts in a checkout; see `artifacts path`]
+    #[arg(short, long, env = "PRIVACY_ARTIFACTS")]
+    artifacts: Option<String>,
+
+    /// Expected sha256 of an artifact as NAME=SHA256, in place of the pinned
+    /// one, for a pool deployed from another trusted setup; repeat or
+    /// comma-separate for both files
+    #[arg(long, global = true, alias = "sha256", env = "PRIVACY_ARTIFACTS_SHA256", value_delimiter = ',')]
+    artifacts_sha256: Vec<String>,
+
+    /// Config file [default: ~/.privacy-zig/config.toml]
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Note store to use, e.g. to keep separate profiles [default:
+    /// ~/.privacy-zig/notes.json]
+    #[arg(long, global = true, env = "PRIVACY_ZIG_NOTES")]
+    notes_file: Option<PathBuf>,
+
+    /// Relayer URL for anonymous withdrawals; without one, withdrawals are
+    /// signed and paid for by your own wallet
+    #[arg(long, alias = "relayer-url")]
+    relayer: Option<String>,
+
+    /// API key for relayers that require one
+    #[arg(long, global = true, env = "PRIVACY_RELAYER_API_KEY", hide_env_values = true)]
+    relayer_api_key: Option<String>,
+
+    /// Compute unit price in micro-lamports, or `auto` to match recent fees
+    /// on the pool, to get transactions you sign through during congestion;
+    /// relayed withdrawals use the relayer's
+    #[arg(long, global = true, env = "PRIVACY_PRIORITY_FEE", default_value = "0")]
+    priority_fee: PriorityFee,
+
+    /// Compute units to request for transactions you sign; by default they
+    /// are simulated first and request what they used plus 10%
+    #[arg(
+        long,
+        global = true,
+        env = "PRIVACY_COMPUTE_UNIT_LIMIT",
+        value_parser = clap::value_parser!(u32).range(1..=COMPUTE_UNIT_LIMIT as i64)
+    )]
+    compute_unit_limit: Option<u32>,
+
+    /// Print the result as JSON on stdout; progress goes to stderr
+    #[arg(long, global = true, default_value_t = false)]
+    json: bool,
+
+    /// Print private keys, blindings and backup strings, which spend
+    /// notes; every command hides them otherwise
+    #[arg(long, global = true, default_value_t = false)]
+    reveal: bool,
 
     #[command(subcommand)]
     command: Commands,
@@ -58,46 +163,125 @@ enum Commands {
     Stats,
 
     /// Deposit SOL to privacy pool
-    Deposit {
-        /// Amount in SOL
-        #[arg(short, long)]
-        amount: f64,
-
-        /// Skip confirmation prompt
-        #[arg(short, long, default_value_t = false)]
-        yes: bool,
-    },
+    Deposit(DepositArgs),
 
     /// Withdraw SOL from privacy pool
-    Withdraw {
+    Withdraw(WithdrawArgs),
+
+    /// One-click anonymous transfer (deposit + withdraw)
+    Transfer(TransferArgs),
+
+    /// Generate a withdrawal proof and save it for `submit`
+    Prove {
+        /// Note ID to withdraw
+        #[arg(short, long)]
+        note_id: String,
+
         /// Recipient address
         #[arg(short, long)]
         recipient: String,
 
-        /// Note ID to use (optional, will prompt if not provided)
-        #[arg(short, long)]
-        note_id: Option<String>,
+        /// Where to write the proof
+        #[arg(short, long, default_value = "proof.json")]
+        output: String,
+
+        /// Prove for sending with your own wallet instead of the relayer
+        #[arg(long, default_value_t = false)]
+        direct: bool,
+    },
+
+    /// Send a withdrawal proof generated by `prove`
+    Submit {
+        /// Proof file written by `prove`
+        file: String,
+
+        /// Sign and send with your own wallet instead of the relayer
+        #[arg(long, default_value_t = false)]
+        direct: bool,
 
         /// Skip confirmation prompt
         #[arg(short, long, default_value_t = false)]
         yes: bool,
     },
 
-    /// One-click anonymous transfer (deposit + withdraw)
-    Transfer {
-        /// Amount in SOL
+    /// Check a proof against the circuit's verifying key, without
+    /// touching the chain
+    Verify {
+        /// Proof file written by `prove`, or a bare proof as JSON
+        file: String,
+
+        /// snarkjs verification_key.json to check against instead of the
+        /// zkey in the artifacts directory
+        #[arg(long)]
+        vkey: Option<String>,
+    },
+
+    /// Print this note store's shielded address, which others pay with
+    /// `shield-send`
+    Address,
+
+    /// Export the keys of this note store's shielded address
+    Keys {
+        #[command(subcommand)]
+        action: KeysAction,
+    },
+
+    /// Pay part of a note to a shielded address; nothing leaves the pool,
+    /// and the recipient imports the note file this writes with `notes
+    /// restore`
+    ShieldSend {
+        /// Shielded address (`pzig1...`) from the recipient's `privacy address`
+        to: String,
+
+        /// Amount in SOL, or in tokens with --mint
         #[arg(short, long)]
         amount: f64,
 
-        /// Recipient address
+        /// Note to pay from [default: the smallest one that covers the amount]
         #[arg(short, long)]
-        recipient: String,
+        note_id: Option<String>,
+
+        /// Pay from a note of this SPL token instead of SOL
+        #[arg(long)]
+        mint: Option<Pubkey>,
+
+        /// Where to write the sealed note for the recipient
+        #[arg(short, long, default_value = "incoming_note.txt")]
+        output: PathBuf,
 
         /// Skip confirmation prompt
         #[arg(short, long, default_value_t = false)]
         yes: bool,
     },
 
+    /// Check every note against the chain and report the spendable balance,
+    /// recover lost notes with --from-seed, or import notes paid to the
+    /// shielded address with --incoming
+    #[command(alias = "balance")]
+    Scan {
+        /// Rebuild deterministic notes from the wallet keypair
+        #[arg(long, default_value_t = false, conflicts_with = "incoming")]
+        from_seed: bool,
+
+        /// Number of note indices to try
+        #[arg(long, default_value_t = 100)]
+        count: u64,
+
+        /// Find notes sent to this store's shielded address in pool
+        /// transaction memos, and import them
+        #[arg(long, default_value_t = false)]
+        incoming: bool,
+
+        /// With --incoming, scan this SPL token's pool instead of SOL's
+        #[arg(long, requires = "incoming")]
+        mint: Option<Pubkey>,
+
+        /// With --incoming, find the notes of this viewing key's address
+        /// instead, and import them watch-only: listed, but not spendable
+        #[arg(long, value_name = "VIEWING_KEY", requires = "incoming")]
+        watch_only: Option<ViewingKey>,
+    },
+
     /// List all notes
     Notes {
         #[command(subcommand)]
@@ -106,27 +290,307 @@ enum Commands {
 
     /// Show program info
     Info,
+
+    /// Guided setup: network, wallet, note store, its passphrase and backup
+    /// target, and circuit artifacts
+    Setup {
+        /// Only run these steps (repeatable)
+        #[arg(long, value_enum)]
+        step: Vec<SetupStep>,
+
+        /// Don't prompt; read answers from --answers instead
+        #[arg(long, default_value_t = false, requires = "answers")]
+        no_input: bool,
+
+        /// Setup answers file (JSON); implies --no-input
+        #[arg(long)]
+        answers: Option<String>,
+    },
+
+    /// Check wallet, RPC, pool accounts, artifacts, note store and its backup
+    Doctor,
+
+    /// Download, verify or locate the circuit artifacts
+    Artifacts {
+        #[command(subcommand)]
+        action: ArtifactsAction,
+    },
+
+    /// Write a starter config file (--config, or ~/.privacy-zig/config.toml)
+    Init {
+        /// Overwrite an existing config file
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+
+    /// Time loading the circuit, rebuilding a tree, proving a withdrawal
+    /// against it and fetching the pool's commitments
+    #[command(hide = true)]
+    Bench {
+        /// Leaves in the tree the withdrawal is proved against
+        #[arg(
+            long,
+            default_value_t = 10_000,
+            value_parser = clap::value_parser!(u64).range(1..=1 << MERKLE_TREE_HEIGHT)
+        )]
+        leaves: u64,
+
+        /// Times to run each stage
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+        iterations: u32,
+    },
+}
+
+#[derive(Subcommand)]
+enum ArtifactsAction {
+    /// Download the artifacts into ~/.privacy-zig/artifacts, checking each
+    /// against its pinned sha256
+    Download {
+        /// Base URL to fetch the files from; repeat or comma-separate to try
+        /// several in order
+        #[arg(long, env = "PRIVACY_ARTIFACTS_URL", value_delimiter = ',')]
+        url: Vec<String>,
+
+        /// Download files again even if they already verify
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+    /// Check the artifacts in use against their pinned sha256
+    Verify,
+    /// Show where artifacts are looked for, in order
+    Path,
+}
+
+#[derive(Subcommand)]
+enum KeysAction {
+    /// Print the viewing key, which finds the notes paid to the shielded
+    /// address (`scan --incoming --watch-only`) but can't spend them
+    ExportViewing,
 }
 
 #[derive(Subcommand)]
 enum NotesAction {
-    /// List all notes
-    List,
+    /// List all notes, or those matching the filters
+    List(ListArgs),
+    /// Show everything about one note, with explorer links; its private
+    /// key and blinding only with --reveal
+    Show {
+        #[arg(short, long)]
+        id: String,
+    },
     /// Export notes to file
     Export {
-        #[arg(short, long, default_value = "notes_backup.json")]
-        file: String,
+        /// Defaults to notes_backup.json, or notes.csv with --format csv
+        #[arg(short, long)]
+        file: Option<String>,
+
+        /// JSON is a backup that `notes import` reads; CSV is for
+        /// spreadsheets and accounting tools
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+
+        /// Add each note's private key and blinding to a CSV export
+        #[arg(long, default_value_t = false)]
+        include_secrets: bool,
     },
     /// Import notes from file
     Import {
         #[arg(short, long)]
         file: String,
+
+        /// What to do with a note that is already in the store; a withdrawn
+        /// note stays withdrawn either way
+        #[arg(long, value_enum, default_value_t = ImportMerge::Skip)]
+        merge: ImportMerge,
     },
     /// Delete a note
     Delete {
         #[arg(short, long)]
         id: String,
     },
+    /// Print a compact backup string for one note; needs --reveal, as
+    /// the string spends the note
+    Backup {
+        #[arg(short, long)]
+        id: String,
+    },
+    /// Restore a note from a backup string, or import one sent to your
+    /// shielded address
+    Restore {
+        /// `privacy-sol-v1:...` string from `notes backup`, a
+        /// `privacy-note-v1:...` note from `shield-send`, or a file holding
+        /// either
+        backup: String,
+    },
+    /// Reclassify notes from on-chain state (never deletes notes)
+    Sync {
+        /// Show what would change without saving
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// Options of `deposit`
+#[derive(Args, Debug)]
+struct DepositArgs {
+    /// Amount in SOL, or in tokens with --mint
+    #[arg(short, long)]
+    amount: f64,
+
+    /// Deposit this SPL token instead of SOL, into its pool from the
+    /// config file
+    #[arg(long)]
+    mint: Option<Pubkey>,
+
+    /// Skip confirmation prompt
+    #[arg(short, long, default_value_t = false)]
+    yes: bool,
+
+    /// Deposit even if the output commitment is already in the tree
+    #[arg(long, default_value_t = false)]
+    allow_duplicate_commitment: bool,
+
+    /// Derive the note keys from the wallet so `scan --from-seed` can
+    /// recover the note if notes.json is lost
+    #[arg(long, default_value_t = false)]
+    deterministic: bool,
+
+    /// Prove and build the transaction, print it and stop: nothing is
+    /// sent and no note is saved
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+/// Options of `withdraw`
+#[derive(Args, Debug)]
+struct WithdrawArgs {
+    /// Recipient address
+    #[arg(short, long)]
+    recipient: String,
+
+    /// Note ID to use (optional, will prompt if not provided)
+    #[arg(short, long)]
+    note_id: Option<String>,
+
+    /// Withdraw a note of this SPL token instead of SOL, to the
+    /// recipient's token account
+    #[arg(long)]
+    mint: Option<Pubkey>,
+
+    /// Leaf the note was deposited at; only needed if its commitment
+    /// appears in the tree more than once
+    #[arg(long)]
+    leaf_index: Option<usize>,
+
+    /// Refuse to withdraw unless at least N deposits of a comparable
+    /// amount were made after the note's
+    #[arg(long, value_name = "N")]
+    min_anonymity_set: Option<u64>,
+
+    /// Skip confirmation prompt
+    #[arg(short, long, default_value_t = false)]
+    yes: bool,
+
+    /// Prove and build the transaction, print it and stop: nothing is
+    /// sent and the note stays unspent
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+/// Options of `transfer`
+#[derive(Args, Debug)]
+struct TransferArgs {
+    /// Amount in SOL
+    #[arg(short, long)]
+    amount: f64,
+
+    /// Recipient address
+    #[arg(short, long)]
+    recipient: String,
+
+    /// Skip confirmation prompt
+    #[arg(short, long, default_value_t = false)]
+    yes: bool,
+
+    /// Derive the deposit note keys from the wallet (see `deposit`)
+    #[arg(long, default_value_t = false)]
+    deterministic: bool,
+
+    /// Always deposit fresh funds, even if an aged note of this amount exists
+    #[arg(long, default_value_t = false)]
+    always_deposit: bool,
+
+    /// Wait this long after the deposit before withdrawing, so the two
+    /// aren't linked by timing
+    #[arg(long, value_name = "SECONDS", conflicts_with = "random_delay")]
+    delay: Option<u64>,
+
+    /// Like --delay, with the wait picked at random, e.g. 600..3600
+    #[arg(long, value_name = "MIN..MAX")]
+    random_delay: Option<DelayRange>,
+
+    /// Refuse to withdraw unless at least N deposits of a comparable
+    /// amount were made after the note's (see `withdraw`)
+    #[arg(long, value_name = "N")]
+    min_anonymity_set: Option<u64>,
+
+    /// Build the first transaction of the transfer, print it and stop
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+/// Filters and order of `notes list`
+#[derive(Args, Debug, Default)]
+struct ListArgs {
+    /// Only notes with this status
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(NOTE_STATUSES))]
+    status: Option<String>,
+
+    /// Only SOL notes of at least this many SOL
+    #[arg(long)]
+    min_amount: Option<f64>,
+
+    /// Only SOL notes of at most this many SOL
+    #[arg(long)]
+    max_amount: Option<f64>,
+
+    #[arg(long, value_enum, default_value_t = NoteSort::Date)]
+    sort: NoteSort,
+
+    /// Largest, newest or last status first
+    #[arg(long, default_value_t = false)]
+    reverse: bool,
+
+    /// Show at most this many notes, after sorting
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Only watch-only notes, found with a viewing key
+    #[arg(long, default_value_t = false)]
+    watch_only: bool,
+}
+
+impl ListArgs {
+    fn filter(self) -> Result<NoteFilter> {
+        let lamports = |sol: Option<f64>| -> Result<Option<u64>> {
+            sol.map(|sol| {
+                if sol.is_nan() || sol < 0.0 {
+                    return Err(anyhow!("Amount filters can't be negative, not {}", sol));
+                }
+                Token::SOL.amount(sol)
+            })
+            .transpose()
+        };
+        Ok(NoteFilter {
+            status: self.status,
+            min_amount: lamports(self.min_amount)?,
+            max_amount: lamports(self.max_amount)?,
+            sort: self.sort,
+            reverse: self.reverse,
+            limit: self.limit,
+            watch_only: self.watch_only,
+        })
+    }
 }
 
 fn default_keypair_path() -> String {
@@ -136,220 +600,611 @@ fn default_keypair_path() -> String {
 }
 
 fn default_artifacts_path() -> String {
-    // Try to find artifacts relative to crate or in common locations
-    let locations = [
-        "../artifacts",
-        "../../privacy-zig/artifacts",
-        "./artifacts",
-    ];
-    
-    for loc in locations {
-        let path = std::path::Path::new(loc);
-        if path.exists() && path.join("transaction2.wasm").exists() {
-            return loc.to_string();
+    artifacts::default_path()
+}
+
+/// Result of `stats`
+#[derive(Serialize)]
+struct StatsResult {
+    network: String,
+    program_id: String,
+    tree_account: String,
+    vault_lamports: u64,
+    /// Leaves in the tree, two per transaction
+    leaves: u64,
+    /// `leaves` as a percentage of the tree's 2^26
+    tree_fill_percent: f64,
+    deposits: u64,
+    withdrawals: u64,
+    transfers: u64,
+    /// Estimated unspent notes (see [`chain::PoolActivity::anonymity_set`])
+    anonymity_set: u64,
+    /// Unix time of the newest deposit, `null` without one
+    last_deposit: Option<i64>,
+    /// Basis points of each deposit taken as a fee
+    deposit_fee_rate: u16,
+    /// Basis points of each withdrawal taken as a fee
+    withdrawal_fee_rate: u16,
+    max_deposit_lamports: u64,
+}
+
+/// Result of `deposit`, `withdraw` and `transfer` with `--dry-run`
+#[derive(Serialize)]
+struct DryRunResult {
+    /// Serialized transaction, base64
+    transaction: String,
+    size_bytes: usize,
+    /// Signed by the wallet; relayed withdrawals are left for the relayer
+    signed: bool,
+    nullifier_pdas: [String; 2],
+    /// Accounts of the transact instruction, in order
+    accounts: Vec<String>,
+}
+
+/// Result of `deposit`
+#[derive(Serialize)]
+struct DepositResult {
+    note_id: String,
+    /// In base units of `mint` for a token deposit
+    amount_lamports: u64,
+    leaf_index: i64,
+    signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mint: Option<String>,
+}
+
+impl From<&Note> for DepositResult {
+    fn from(note: &Note) -> Self {
+        Self {
+            note_id: note.id.clone(),
+            amount_lamports: note.amount,
+            leaf_index: note.leaf_index,
+            signature: note.deposit_tx_sig.clone(),
+            mint: note.mint.clone(),
         }
     }
-    
-    "../artifacts".to_string()
+}
+
+/// Result of `withdraw` and `submit`
+#[derive(Serialize)]
+struct WithdrawResult {
+    note_id: String,
+    /// In base units of `mint` for a token withdrawal
+    amount_lamports: u64,
+    recipient: String,
+    signature: String,
+    relayed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mint: Option<String>,
+}
+
+/// Result of `verify`
+#[derive(Serialize)]
+struct VerifyResult {
+    valid: bool,
+    /// Why the proof doesn't verify
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The zkey or verification key file checked against
+    key: String,
+    root: String,
+    public_amount: i64,
+    nullifiers: [String; 2],
+}
+
+/// Result of `shield-send`
+#[derive(Serialize)]
+struct ShieldSendResult {
+    /// The note spent
+    note_id: String,
+    /// Paid to the shielded address, in base units of `mint` for a token
+    amount_lamports: u64,
+    /// What was left of the note, if anything
+    change_note_id: Option<String>,
+    /// Sealed note for the recipient, if they don't `scan --incoming`
+    note_file: String,
+    signature: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mint: Option<String>,
+}
+
+/// Result of `transfer`; `deposit` is `None` when an existing note was used
+#[derive(Serialize)]
+struct TransferResult {
+    deposit: Option<DepositResult>,
+    withdrawal: WithdrawResult,
+}
+
+/// A note as `scan` found it on chain
+#[derive(Serialize)]
+struct ScannedNote {
+    note_id: String,
+    amount_lamports: u64,
+    leaf_index: Option<usize>,
+    state: chain::NoteState,
+    /// Status in the note store, which may be stale
+    status: String,
+}
+
+/// Result of `scan --incoming`
+#[derive(Serialize)]
+struct IncomingScanResult {
+    /// Notes imported by this scan
+    imported: Vec<ScannedNote>,
+    /// Memo notes for this store that were already in it
+    known: usize,
+    /// Memo notes that couldn't be read, or whose amount doesn't make a
+    /// commitment of their transaction: replayed or forged
+    rejected: usize,
+}
+
+/// Result of `scan`
+#[derive(Serialize)]
+struct ScanResult {
+    notes: Vec<ScannedNote>,
+    deposited_lamports: u64,
+    withdrawn_lamports: u64,
+    spendable_lamports: u64,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    output::set_json(cli.json);
+    output::set_reveal(cli.reveal);
+    relayer::set_api_key(cli.relayer_api_key.clone());
+    if let Some(path) = &cli.notes_file {
+        NoteStore::set_default_path(path.clone());
+    }
+    interrupt::install();
 
-    print_banner();
+    let result = run(cli).await.map_err(explain);
+    if let Err(e) = &result {
+        if e.is::<interrupt::Cancelled>() {
+            interrupt::exit("Cancelled; the transaction wasn't sent");
+        }
+        if output::json() {
+            output::emit(&serde_json::json!({ "error": format!("{:#}", e) }))?;
+            std::process::exit(1);
+        }
+    }
+    result
+}
 
-    let client = RpcClient::new_with_commitment(
-        cli.rpc_url.clone(),
-        CommitmentConfig::confirmed(),
-    );
+/// Lead with what to do about failures whose cause is known, such as a note
+/// whose keys are no longer field elements
+fn explain(e: anyhow::Error) -> anyhow::Error {
+    let hint = e.chain().find_map(|cause| {
+        let prover = cause.downcast_ref::<ProverError>();
+        let crypto = match prover {
+            Some(ProverError::Crypto(e)) => Some(e),
+            _ => cause.downcast_ref::<CryptoError>(),
+        };
+        if let Some(CryptoError::InvalidFieldElement { .. }) = crypto {
+            return Some(
+                "Your note data is corrupted; restore the note with 'privacy notes restore' \
+                 or 'privacy notes import'",
+            );
+        }
+        match prover? {
+            ProverError::ConstraintUnsatisfied(_) => Some(
+                "The circuit rejected this note: it may be corrupted, or the Merkle tree \
+                 fetched from chain incomplete",
+            ),
+            ProverError::ZkeyParse { .. } | ProverError::WasmLoad { .. } => {
+                Some("The circuit artifacts are damaged; check them with 'privacy artifacts verify'")
+            }
+            _ => None,
+        }
+    });
+    match hint {
+        Some(hint) => e.context(hint),
+        None => e,
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    if !output::json() {
+        print_banner();
+    }
+
+    let config_path = match &cli.config {
+        Some(path) => path.into(),
+        None => config::default_path()?,
+    };
+    // Init runs before the config is read, so it can replace a broken one
+    if let Commands::Init { force } = cli.command {
+        let network = cli.network.unwrap_or(Network::Testnet);
+        let artifacts = cli.artifacts.clone().unwrap_or_else(default_artifacts_path);
+        return config::run_init(&config_path, network, &artifacts, force);
+    }
+
+    let file = CliConfig::load(cli.config.as_deref())?;
+    let selected = file.network(cli.network)?;
+    let network = selected.unwrap_or(Network::Testnet);
+    pool::select(selected, PoolConfig::resolve(network, &file.pool)?);
+    let rpc_options = RpcOptions {
+        urls: file.rpc_urls(&cli.rpc_url, network),
+        timeout: Duration::from_secs(cli.rpc_timeout),
+        retry: RetryPolicy::new(cli.rpc_retries),
+    };
+    let artifacts = file.artifacts_path(cli.artifacts.as_deref(), default_artifacts_path);
+    artifacts::set_pins(file.artifact_pins(&cli.artifacts_sha256)?);
+    NoteStore::set_backup_dir(file.backup_dir.as_deref().map(PathBuf::from));
+    if let Ok(dir) = NoteStore::data_dir() {
+        prover::set_debug_dir(dir.join("debug"));
+    }
+
+    let client = rpc::connect(&rpc_options);
+    // Read-only commands go through PoolRpc so they can be recorded
+    let rpc = rpc::from_env(&rpc_options);
+
+    // Setup downloads from where `artifacts download` would without --url
+    let env_urls: Vec<String> = std::env::var("PRIVACY_ARTIFACTS_URL")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect();
+    let artifact_urls = file.artifact_urls(&env_urls);
+    let setup_ctx = SetupContext {
+        rpc: &rpc_options,
+        network,
+        config_path: &config_path,
+        keypair_path: &cli.keypair,
+        artifacts_path: &artifacts,
+        artifact_urls: &artifact_urls,
+    };
 
-    let keypair = read_keypair_file(&cli.keypair)
-        .map_err(|e| anyhow!("Failed to read keypair from {}: {}", cli.keypair, e))?;
+    // Setup, doctor, verify and bench must work before a wallet exists
+    match &cli.command {
+        Commands::Setup { step, answers, .. } => {
+            let answers = answers.as_deref().map(SetupAnswers::load).transpose()?;
+            return setup::run_setup(&setup_ctx, step, answers).await;
+        }
+        Commands::Doctor => {
+            if !setup::run_doctor(&setup_ctx) {
+                return Err(anyhow!("Some checks failed"));
+            }
+            return Ok(());
+        }
+        Commands::Artifacts { action } => {
+            return cmd_artifacts(action, &file, cli.artifacts.as_deref(), &artifacts).await;
+        }
+        Commands::Verify { file, vkey } => {
+            let result = cmd_verify(Path::new(file), vkey.as_deref().map(Path::new), &artifacts)?;
+            output::emit(&result)?;
+            return match result.error {
+                Some(error) => Err(anyhow!("The proof does not verify: {}", error)),
+                None => Ok(()),
+            };
+        }
+        Commands::Bench { leaves, iterations } => {
+            return output::emit(&bench::run(rpc.as_ref(), &artifacts, *leaves as usize, *iterations)?);
+        }
+        _ => {}
+    }
 
+    if setup::is_first_run()
+        && !output::json()
+        && console::user_attended()
+        && Confirm::new()
+            .with_prompt("Looks like this is your first run. Start the guided setup?")
+            .default(true)
+            .interact()?
+    {
+        setup::run_setup(&setup_ctx, &[], None).await?;
+        say!();
+    }
+
+    let keypair = load_keypair(&cli.keypair)
+        .map_err(|e| anyhow!("Failed to load the wallet keypair: {}", e))?;
+
+    let sender = Sender {
+        client: &client,
+        keypair: &keypair,
+        artifacts_path: &artifacts,
+        relayer_url: cli.relayer.as_deref(),
+        priority_fee: cli.priority_fee,
+        compute_unit_limit: cli.compute_unit_limit,
+    };
     match cli.command {
-        Commands::Stats => cmd_stats(&client).await?,
-        Commands::Deposit { amount, yes } => {
-            cmd_deposit(&client, &keypair, amount, &cli.artifacts, yes).await?
+        Commands::Stats => output::emit(&cmd_stats(rpc.as_ref()).await?)?,
+        Commands::Deposit(args) => {
+            let note = cmd_deposit(&sender, args).await?;
+            output::emit(&note.as_ref().map(DepositResult::from))?
+        }
+        Commands::Withdraw(args) => {
+            let withdrawal = cmd_withdraw(&sender, args).await?;
+            output::emit(&withdrawal)?
+        }
+        Commands::Transfer(args) => {
+            let transfer = cmd_transfer(&sender, args).await?;
+            output::emit(&transfer)?
         }
-        Commands::Withdraw { recipient, note_id, yes } => {
-            cmd_withdraw(&client, &keypair, &recipient, note_id, &cli.artifacts, &cli.relayer_url, yes).await?
+        Commands::Prove { note_id, recipient, output, direct } => {
+            let relayer_url = cli.relayer.as_deref().filter(|_| !direct);
+            cmd_prove(&client, &keypair, &note_id, &recipient, &artifacts, relayer_url, &output)
+                .await?
         }
-        Commands::Transfer { amount, recipient, yes } => {
-            cmd_transfer(&client, &keypair, amount, &recipient, &cli.artifacts, &cli.relayer_url, yes).await?
+        Commands::Submit { file, direct, yes } => {
+            let withdrawal = cmd_submit(&sender, &file, direct, yes).await?;
+            output::emit(&withdrawal)?
+        }
+        Commands::Address => cmd_address()?,
+        Commands::Keys { action: KeysAction::ExportViewing } => cmd_export_viewing_key()?,
+        Commands::ShieldSend { to, amount, note_id, mint, output, yes } => {
+            let sent =
+                cmd_shield_send(&sender, &to, amount, note_id, mint, &output, yes).await?;
+            output::emit(&sent)?
+        }
+        Commands::Scan { from_seed, count, incoming, mint, watch_only } => {
+            if from_seed {
+                cmd_scan_from_seed(rpc.as_ref(), &keypair, count).await?
+            } else if incoming {
+                output::emit(&cmd_scan_incoming(rpc.as_ref(), mint, watch_only)?)?
+            } else {
+                output::emit(&cmd_scan(rpc.as_ref())?)?
+            }
+        }
+        Commands::Notes { action } => cmd_notes(rpc.as_ref(), action).await?,
+        Commands::Info => cmd_info(rpc.as_ref(), &keypair).await?,
+        Commands::Setup { .. }
+        | Commands::Doctor
+        | Commands::Artifacts { .. }
+        | Commands::Bench { .. }
+        | Commands::Verify { .. }
+        | Commands::Init { .. } => {
+            unreachable!("handled above")
         }
-        Commands::Notes { action } => cmd_notes(action).await?,
-        Commands::Info => cmd_info(&client, &keypair).await?,
     }
 
     Ok(())
 }
 
 fn print_banner() {
-    println!();
-    println!("{}", style("  🔒 privacy-zig CLI").bold().cyan());
-    println!("{}", style("  Anonymous SOL transfers on Solana").dim());
-    println!();
+    say!();
+    say!("{}", style("  🔒 privacy-zig CLI").bold().cyan());
+    say!("{}", style("  Anonymous SOL transfers on Solana").dim());
+    say!();
 }
 
-async fn cmd_stats(client: &RpcClient) -> Result<()> {
-    println!("{}", style("📊 Pool Statistics").bold());
-    println!("{}", style("─".repeat(40)).dim());
-
-    let config = PoolConfig::default();
-
-    let vault_balance = client.get_balance(&config.pool_vault)?;
-    let vault_sol = vault_balance as f64 / 1_000_000_000.0;
-
-    let tree_data = client.get_account_data(&config.tree_account)?;
-    let leaf_index = if tree_data.len() >= 48 {
-        u64::from_le_bytes(tree_data[40..48].try_into().unwrap())
-    } else {
-        0
-    };
-
-    println!("  Pool Vault:      {} SOL", style(format!("{:.4}", vault_sol)).green());
-    println!("  Total Deposits:  {}", style(leaf_index / 2).yellow());
-    println!("  Network:         {}", style("Testnet").cyan());
-    println!();
-
-    Ok(())
+async fn cmd_stats(rpc: &dyn PoolRpc) -> Result<StatsResult> {
+    say!("{}", style("📊 Pool Statistics").bold());
+    say!("{}", style("─".repeat(40)).dim());
+
+    let config = pool::config();
+    let stats = chain::pool_stats(rpc, &config)?;
+    let fees = chain::global_config(rpc, &config)?;
+    // The history only grows, so transactions read by earlier runs are kept
+    let cache = NoteStore::data_dir()?.join("cache").join("history.json");
+    let history = CachedHistory::open(rpc, cache);
+    let activity = chain::pool_activity(&history, &config)?;
+    // A cache that can't be written only makes the next run slower
+    let _ = history.save();
+    let vault_sol = stats.vault_lamports as f64 / 1_000_000_000.0;
+
+    say!("  Pool Vault:      {} SOL", style(format!("{:.4}", vault_sol)).green());
+    say!("  Deposits:        {}", style(activity.deposits).yellow());
+    say!("  Withdrawals:     {}", style(activity.withdrawals).yellow());
+    say!("  Transfers:       {}", style(activity.transfers).yellow());
+    say!("  Anonymity Set:   ~{} unspent notes", style(activity.anonymity_set()).yellow());
+    if let Some(time) = activity.last_deposit {
+        say!("  Last Deposit:    {}", format_timestamp(time.max(0) as u64, &chrono::Local));
+    }
+    say!("  Tree Leaves:     {} ({:.6}% full)", stats.next_index, stats.fill_percent());
+    say!("  Deposit Fee:     {}", style(format_rate(fees.deposit_fee_rate)).yellow());
+    say!("  Withdrawal Fee:  {}", style(format_rate(fees.withdrawal_fee_rate)).yellow());
+    say!("  Max Deposit:     {} SOL", stats.max_deposit_lamports as f64 / 1e9);
+    say!("  Network:         {}", style(pool::network_name(&rpc.url())).cyan());
+    say!("  Program ID:      {}", style(config.program_id).dim());
+    say!("  Tree Account:    {}", style(config.tree_account).dim());
+    say!();
+
+    Ok(StatsResult {
+        network: pool::network_name(&rpc.url()).to_string(),
+        program_id: config.program_id.to_string(),
+        tree_account: config.tree_account.to_string(),
+        vault_lamports: stats.vault_lamports,
+        leaves: stats.next_index,
+        tree_fill_percent: stats.fill_percent(),
+        deposits: activity.deposits,
+        withdrawals: activity.withdrawals,
+        transfers: activity.transfers,
+        anonymity_set: activity.anonymity_set(),
+        last_deposit: activity.last_deposit,
+        deposit_fee_rate: fees.deposit_fee_rate,
+        withdrawal_fee_rate: fees.withdrawal_fee_rate,
+        max_deposit_lamports: stats.max_deposit_lamports,
+    })
 }
 
-async fn cmd_deposit(
-    client: &RpcClient,
-    keypair: &Keypair,
-    amount: f64,
-    artifacts_path: &str,
-    skip_confirm: bool,
-) -> Result<()> {
-    let lamports = (amount * 1_000_000_000.0) as u64;
+/// The wallet that signs and pays for a command's transactions, and the
+/// global flags for proving and sending them
+#[derive(Clone, Copy)]
+struct Sender<'a> {
+    client: &'a ClientPool,
+    keypair: &'a Keypair,
+    artifacts_path: &'a str,
+    /// `--relayer`; `None` sends withdrawals with the wallet
+    relayer_url: Option<&'a str>,
+    priority_fee: PriorityFee,
+    compute_unit_limit: Option<u32>,
+}
 
-    println!("{}", style("📥 Deposit").bold());
-    println!("{}", style("─".repeat(40)).dim());
-    println!("  Amount:  {} SOL", style(format!("{:.4}", amount)).green());
-    println!("  From:    {}", style(keypair.pubkey().to_string()).dim());
-    println!();
+async fn cmd_deposit(sender: &Sender<'_>, args: DepositArgs) -> Result<Option<Note>> {
+    let Sender { client, keypair, artifacts_path, priority_fee, compute_unit_limit, .. } = *sender;
+    let DepositArgs {
+        amount,
+        mint,
+        yes: skip_confirm,
+        allow_duplicate_commitment,
+        deterministic,
+        dry_run,
+    } = args;
+    let base_config = pool::config();
+    let spl = mint.map(|mint| base_config.spl_pool(&mint).copied()).transpose()?;
+    // A token pool has its own tree and vault
+    let config = spl.map_or_else(|| base_config.clone(), |spl| base_config.for_spl(&spl));
+    let token = Token::load(client, mint)?;
+    let lamports = token.amount(amount)?;
+
+    say!("{}", style("📥 Deposit").bold());
+    say!("{}", style("─".repeat(40)).dim());
+    say!("  Amount:  {}", style(token.format(lamports)).green());
+    say!("  From:    {}", style(keypair.pubkey().to_string()).dim());
+    // Rejected by the program after proving otherwise
+    chain::check_deposit_amount(&chain::pool_stats(client, &config)?, lamports, &token)?;
+    let fees = chain::global_config(client, &config)?;
+    if fees.deposit_fee_rate > 0 {
+        say!(
+            "  Pool fee: {} ({})",
+            style(token.format(fees.deposit_fee(lamports))).yellow(),
+            format_rate(fees.deposit_fee_rate)
+        );
+    }
+    let unit_price = chain::priority_fee(client, &config, priority_fee)?;
+    if let Some(summary) = priority_fee_summary(priority_fee, unit_price, compute_unit_limit) {
+        say!("  Priority: {}", summary);
+    }
+    say!();
 
     // Check balance
-    let balance = client.get_balance(&keypair.pubkey())?;
-    if balance < lamports + 10_000_000 {
-        return Err(anyhow!(
-            "Insufficient balance. Have {} SOL, need {} SOL + fees",
-            balance as f64 / 1e9,
-            amount
-        ));
+    let unit_limit = compute_unit_limit.unwrap_or(COMPUTE_UNIT_LIMIT);
+    let network_fee = priority_fee_lamports(unit_limit, unit_price);
+    match token.mint {
+        Some(_) => chain::check_token_deposit_balance(
+            client,
+            &keypair.pubkey(),
+            &token,
+            lamports,
+            network_fee,
+        )?,
+        None => chain::check_deposit_balance(
+            client,
+            &keypair.pubkey(),
+            lamports.saturating_add(network_fee),
+        )?,
     }
 
-    if !skip_confirm {
-        if !Confirm::new()
+    if !skip_confirm
+        && !dry_run
+        && !Confirm::new()
             .with_prompt("Proceed with deposit?")
             .default(true)
             .interact()?
-        {
-            println!("{}", style("Cancelled").red());
-            return Ok(());
-        }
+    {
+        say!("{}", style("Cancelled").red());
+        return Ok(None);
     }
 
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} {msg}")
-            .unwrap(),
-    );
-    pb.enable_steady_tick(Duration::from_millis(100));
+    let pb = interrupt::spinner();
 
     // Load prover
     pb.set_message("Loading circuit...");
-    let wasm_path = format!("{}/transaction2.wasm", artifacts_path);
-    let zkey_path = format!("{}/transaction2.zkey", artifacts_path);
-    
-    let prover = PrivacyProver::new(&wasm_path, &zkey_path)?;
+    let prover = PrivacyProver::shared(artifacts_path, |done, total| {
+        pb.set_message(format!("Loading circuit... {}%", done * 100 / total))
+    })?;
 
     // Get current tree state from chain
     pb.set_message("Fetching Merkle tree from chain...");
-    let config = PoolConfig::default();
     let commitments = fetch_commitments_from_chain(client, &config)?;
     
-    let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT);
+    let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT)?;
     for c in &commitments {
-        tree.insert(*c);
+        tree.insert(*c)?;
     }
+    chain::check_tree_root(client, &config, &tree)?;
     let root = tree.root();
-    let current_leaf_index = tree.leaves.len();
+    let current_leaf_index = tree.leaf_count();
+
+    // Keep the store (and its lock) until the note is saved, so a concurrent
+    // deposit can't pick the same derivation index before this one is stored
+    let mut store = NoteStore::load()?;
 
     // Generate UTXO
     pb.set_message("Generating UTXO...");
-    let utxo = Utxo::new(lamports)?;
-
-    // Generate proof with current on-chain root
-    pb.set_message("Generating ZK proof (this takes ~30s)...");
-    let payer_bytes: [u8; 32] = keypair.pubkey().to_bytes();
-    let proof_data = prover.prove_deposit(lamports, &utxo, &payer_bytes, root)?;
-
-    // Build transaction
-    pb.set_message("Building transaction...");
-    let instruction_data = proof_data.to_instruction_data();
-
-    // Derive nullifier PDAs
-    let (nullifier1_pda, _) = Pubkey::find_program_address(
-        &[b"nullifier", &proof_data.nullifier1],
-        &config.program_id,
-    );
-    let (nullifier2_pda, _) = Pubkey::find_program_address(
-        &[b"nullifier", &proof_data.nullifier2],
-        &config.program_id,
-    );
-
-    // Account order for v2 program:
-    // For deposit: signer = depositor, recipient = depositor (doesn't matter for deposit)
-    let transact_ix = Instruction {
-        program_id: config.program_id,
-        accounts: vec![
-            AccountMeta::new(config.tree_account, false),
-            AccountMeta::new(nullifier1_pda, false),
-            AccountMeta::new(nullifier2_pda, false),
-            AccountMeta::new_readonly(config.global_config, false),
-            AccountMeta::new(config.pool_vault, false),
-            AccountMeta::new(keypair.pubkey(), true),       // signer (depositor)
-            AccountMeta::new(keypair.pubkey(), false),      // recipient (not used for deposit)
-            AccountMeta::new(config.fee_recipient, false),  // fee_recipient
-            AccountMeta::new_readonly(system_program::id(), false),
-        ],
-        data: instruction_data,
+    let (utxo, derivation_index) = if deterministic {
+        let seed = note_seed(keypair)?;
+        let mut index = store.next_derivation_index();
+        // Indices already on chain belong to notes missing from this store
+        // (e.g. a lost store that was never rescanned); don't reuse them
+        let existing = CommitmentSet::from_leaves(&commitments);
+        loop {
+            let utxo = Utxo::derive(lamports, token.mint_fr(), &seed, index)?;
+            if existing.leaf_index(&crypto::str_to_fr(&utxo.commitment)?).is_none() {
+                break (utxo, Some(index));
+            }
+            index += 1;
+        }
+    } else {
+        (Utxo::new(lamports, token.mint_fr())?, None)
     };
 
-    let compute_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_400_000);
+    // Re-depositing a commitment that is already in the tree creates a leaf
+    // that can never be spent (its nullifier only covers one of them)
+    if !allow_duplicate_commitment {
+        let existing = CommitmentSet::from_leaves(&commitments);
+        let commitment_fr = crypto::str_to_fr(&utxo.commitment)?;
+        if let Some(leaf) = existing.leaf_index(&commitment_fr) {
+            pb.finish_and_clear();
+            let owner = store
+                .notes
+                .iter()
+                .find(|n| n.commitment == utxo.commitment)
+                .map(|n| format!(" (note {})", n.id))
+                .unwrap_or_default();
+            return Err(anyhow!(
+                "Commitment is already in the pool at leaf {}{}. Depositing it again would \
+                 create an unspendable duplicate; spend the existing note instead, or pass \
+                 --allow-duplicate-commitment to override.",
+                leaf,
+                owner
+            ));
+        }
+    }
 
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let tx = Transaction::new_signed_with_payer(
-        &[compute_ix, transact_ix],
-        Some(&keypair.pubkey()),
-        &[keypair],
-        recent_blockhash,
+    // Generate proof with current on-chain root
+    let ext_data = ExtData::direct(keypair.pubkey(), keypair.pubkey());
+    let proving = prover::prove_deposit(
+        &prover,
+        lamports,
+        &utxo,
+        ext_data,
+        root,
+        show_proof_stage(&pb),
     );
+    let proof_data = interrupt::timed(&pb, "Computing witness...", proving).await?;
 
-    // Send transaction
-    pb.set_message("Sending transaction...");
-    let signature = client.send_and_confirm_transaction(&tx)?;
-
-    pb.finish_with_message("Done!");
+    // Build transaction
+    pb.set_message("Building transaction...");
+    // For deposit: signer = depositor, recipient = depositor (doesn't matter
+    // for SOL; a token deposit comes out of the recipient's token account)
+    let transact_ix = transact_instruction(
+        &base_config,
+        spl.as_ref(),
+        &proof_data,
+        &keypair.pubkey(),
+        &keypair.pubkey(),
+    )?;
 
-    println!();
-    println!("{}", style("✅ Deposit successful!").green().bold());
-    println!("Signature: {}", signature);
-    println!(
-        "Explorer: https://explorer.solana.com/tx/{}?cluster=testnet",
-        signature
-    );
+    let budget = ComputeBudget { unit_limit: compute_unit_limit, unit_price };
+    let tx = sign_with_budget(client, keypair, transact_ix, None, budget, &pb)?;
+    if dry_run {
+        pb.finish_and_clear();
+        print_dry_run(&tx, true)?;
+        return Ok(None);
+    }
 
-    // Save note
-    let mut store = NoteStore::load()?;
+    // Save the note before sending: if the transaction lands but this
+    // process doesn't see it confirm, the funds are still recoverable
+    let signature = tx.signatures[0];
+    let first_note = store.notes.is_empty();
     let note = Note {
         id: notes::generate_note_id(),
         amount: lamports,
-        privkey: utxo.privkey,
+        privkey: Some(utxo.privkey),
         pubkey: utxo.pubkey,
         blinding: utxo.blinding,
         commitment: utxo.commitment,
@@ -358,50 +1213,198 @@ async fn cmd_deposit(
         created_at: chrono::Utc::now().timestamp() as u64,
         deposit_tx_sig: Some(signature.to_string()),
         withdraw_tx_sig: None,
+        network: pool::network_name(&client.url()).to_string(),
+        pool: config.tree_account.to_string(),
+        derivation_index,
+        mint: mint.map(|mint| mint.to_string()),
     };
-    store.add(note)?;
+    store.add(note.clone())?;
 
-    println!();
-    println!("{}", style("⚠️  Note saved to ~/.privacy-zig/notes.json").yellow());
-    println!("{}", style("   Make sure to backup your notes!").yellow());
-    println!();
+    // Send transaction
+    pb.set_message("Sending transaction...");
+    if let Err(e) = client.send_and_confirm(&tx) {
+        pb.finish_and_clear();
+        store.update_status(&note.id, "deposit_failed", Some(&signature.to_string()))?;
+        return Err(e.context(format!(
+            "Deposit failed; note {} is marked deposit_failed. If {} confirms after all, \
+             `privacy notes sync` restores it",
+            note.id, signature
+        )));
+    }
+    pb.finish_with_message("Done!");
 
-    Ok(())
-}
+    say!();
+    say!("{}", style("✅ Deposit successful!").green().bold());
+    say!("Signature: {}", signature);
+    say!("Explorer: {}", pool::explorer_tx_url(&client.url(), &signature.to_string()));
 
-async fn cmd_withdraw(
-    client: &RpcClient,
-    _keypair: &Keypair,  // Not used anymore - relayer signs!
-    recipient: &str,
-    note_id: Option<String>,
-    artifacts_path: &str,
-    relayer_url: &str,
-    skip_confirm: bool,
-) -> Result<()> {
-    let recipient_pubkey = Pubkey::from_str(recipient)
-        .map_err(|_| anyhow!("Invalid recipient address"))?;
+    say!();
+    let saved_to = format!("⚠️  Note saved to {}", NoteStore::path()?.display());
+    say!("{}", style(saved_to).yellow());
+    if first_note {
+        print_first_note_banner();
+    } else {
+        say!("{}", style("   Make sure to backup your notes!").yellow());
+    }
+    say!();
 
-    let store = NoteStore::load()?;
-    let available_notes: Vec<_> = store.notes.iter().filter(|n| n.status == "deposited").collect();
+    Ok(Some(note))
+}
 
-    if available_notes.is_empty() {
-        println!("{}", style("❌ No withdrawable notes found.").red());
-        println!("   Use 'privacy deposit' first.");
-        return Ok(());
+/// Shown once, when a deposit writes the store's first note
+fn print_first_note_banner() {
+    for line in [
+        "   This file now holds your note's private key and blinding. They are",
+        "   the only way to withdraw it: anyone who reads the file can spend",
+        "   the note, and if the file is lost, so are the funds.",
+        "   Back it up now with `privacy notes export`, and keep the copy private.",
+        "   No command prints a key or blinding unless you pass --reveal.",
+    ] {
+        say!("{}", style(line).yellow());
     }
+}
 
-    let note = if let Some(id) = note_id {
+/// Spinner message for each stage of a proof, telling a stuck witness
+/// calculation (the circuit's WASM) from the ~30s of proving
+fn show_proof_stage(pb: &ProgressBar) -> impl Fn(ProverStage) + Send + 'static {
+    let pb = pb.clone();
+    move |stage| {
+        pb.set_message(match stage {
+            ProverStage::WitnessStarted => "Computing witness...",
+            ProverStage::WitnessDone => "Witness computed",
+            ProverStage::ProofStarted => "Generating ZK proof (this takes ~30s)...",
+            ProverStage::ProofDone => "Checking proof...",
+        })
+    }
+}
+
+/// Whether to prove again after the pool's root moved past the one a proof
+/// was generated against. With `-y` this retries up to `MAX_REPROOFS` times.
+fn prove_again(pb: &ProgressBar, skip_confirm: bool, reproofs: u32) -> Result<bool> {
+    if skip_confirm {
+        return Ok(reproofs <= MAX_REPROOFS);
+    }
+    Ok(pb.suspend(|| {
+        Confirm::new()
+            .with_prompt(
+                "The pool's root history has moved past the root this proof was generated \
+                 against, so the program would reject it. Generate a new proof against the current root?",
+            )
+            .default(true)
+            .interact()
+    })?)
+}
+
+/// Leaf of `note`'s commitment in `tree`. A commitment deposited more than
+/// once is at several leaves, each spendable once; `claimed` picks one.
+fn note_leaf_index(tree: &MerkleTree, note: &Note, claimed: Option<usize>) -> Result<usize> {
+    let indices = tree.indices_of(&crypto::str_to_fr(&note.commitment)?);
+    let list = || indices.iter().map(usize::to_string).collect::<Vec<_>>().join(", ");
+    match (indices, claimed) {
+        ([], _) => Err(anyhow!("Commitment not found in tree")),
+        (_, Some(claimed)) if indices.contains(&claimed) => Ok(claimed),
+        (_, Some(claimed)) => Err(anyhow!(
+            "Leaf {} doesn't hold note {}'s commitment, which is at leaf {}",
+            claimed,
+            note.id,
+            list()
+        )),
+        ([index], None) => Ok(*index),
+        (_, None) => Err(anyhow!(
+            "Note {}'s commitment appears at leaves {}; pass --leaf-index with the one it \
+             was deposited at (its deposit transaction shows it)",
+            note.id,
+            list()
+        )),
+    }
+}
+
+/// How many deposits a withdrawal hides among, colored by how well
+fn print_anonymity_set(set: &AnonymitySet) {
+    let summary = format!(
+        "{} comparable deposits since this note's (~{} unspent notes in the pool)",
+        set.later_deposits, set.unspent_notes
+    );
+    match set.tier() {
+        Tier::Low => {
+            say!("{}", style(format!("  ⚠️  Anonymity: {}", summary)).red().bold());
+            say!("{}", style("  Too few to hide among: this withdrawal is easy to link to its deposit.").red());
+        }
+        Tier::Fair => {
+            say!("{}", style(format!("  Anonymity:  {}", summary)).yellow());
+            say!("{}", style("  Waiting for more deposits before withdrawing hides it better.").yellow());
+        }
+        Tier::Good => say!("{}", style(format!("  Anonymity:  {}", summary)).green()),
+    }
+    say!();
+}
+
+async fn cmd_withdraw(sender: &Sender<'_>, args: WithdrawArgs) -> Result<Option<WithdrawResult>> {
+    let Sender { client, keypair, artifacts_path, relayer_url, priority_fee, compute_unit_limit } =
+        *sender;
+    let WithdrawArgs {
+        recipient,
+        note_id,
+        mint,
+        leaf_index,
+        min_anonymity_set,
+        yes: skip_confirm,
+        dry_run,
+    } = args;
+    let recipient = recipient.as_str();
+    let recipient_pubkey = Pubkey::from_str(recipient)
+        .map_err(|_| anyhow!("Invalid recipient address"))?;
+
+    // Keep the store (and its lock) for the whole withdrawal so no other
+    // process can spend or rewrite this note in the meantime
+    let mut store = NoteStore::load()?;
+    let network = pool::network_name(&client.url());
+    let base_config = pool::config();
+    let spl = mint.map(|mint| base_config.spl_pool(&mint).copied()).transpose()?;
+    // A token pool has its own tree and vault
+    let config = spl.map_or_else(|| base_config.clone(), |spl| base_config.for_spl(&spl));
+    let tree_account = config.tree_account.to_string();
+
+    // An explicitly requested note of another token or network is an error
+    // worth explaining, rather than "not found"
+    if let Some(id) = &note_id {
+        if let Some(note) = store.notes.iter().find(|n| &n.id == id) {
+            note.check_mint(mint.as_ref())?;
+            note.check_network(network, &tree_account)?;
+            note.spending_key()?;
+        }
+    }
+
+    let available_notes = store.withdrawable(network, &tree_account);
+
+    if available_notes.is_empty() {
+        say!("{}", style(format!("❌ No withdrawable notes found on {}.", network)).red());
+        let elsewhere = store.notes.iter().filter(|n| n.status == "deposited").count();
+        if elsewhere > 0 {
+            say!("   {} unspent notes belong to other networks (see 'privacy notes list').", elsewhere);
+        } else {
+            say!("   Use 'privacy deposit' first.");
+        }
+        return Ok(None);
+    }
+
+    let token = Token::load(client, mint)?;
+    let note: Note = if let Some(id) = note_id {
         available_notes
             .iter()
             .find(|n| n.id == id)
+            .map(|n| (*n).clone())
             .ok_or_else(|| anyhow!("Note {} not found", id))?
     } else if skip_confirm {
         // When skipping confirm, use latest note
-        available_notes.last().ok_or_else(|| anyhow!("No note found"))?
+        available_notes
+            .last()
+            .map(|n| (*n).clone())
+            .ok_or_else(|| anyhow!("No note found"))?
     } else {
         let items: Vec<String> = available_notes
             .iter()
-            .map(|n| format!("{} - {} SOL", n.id, n.amount as f64 / 1e9))
+            .map(|n| format!("{} - {}", n.id, token.format(n.amount)))
             .collect();
 
         let selection = Select::new()
@@ -409,384 +1412,2348 @@ async fn cmd_withdraw(
             .items(&items)
             .interact()?;
 
-        available_notes[selection]
+        available_notes[selection].clone()
     };
+    note.check_mint(mint.as_ref())?;
+    note.verify_commitment()?;
 
-    let amount_sol = note.amount as f64 / 1_000_000_000.0;
+    match relayer_url {
+        Some(_) => say!("{}", style("📤 Withdraw (via Relayer)").bold()),
+        None => say!("{}", style("📤 Withdraw").bold()),
+    }
+    say!("{}", style("─".repeat(40)).dim());
+    say!("  Amount:     {}", style(token.format(note.amount)).green());
+    say!("  Recipient:  {}", style(recipient).cyan());
+    say!("  Note ID:    {}", style(&note.id).dim());
+    let unit_price =
+        direct_priority_fee(client, &config, relayer_url, priority_fee, compute_unit_limit)?;
+    print_submitter(keypair, relayer_url);
+    say!();
+
+    // Before the ~30s proof, not after
+    match &mint {
+        Some(mint) => {
+            chain::token_account(client, &recipient_pubkey, mint)?;
+        }
+        None => chain::check_vault(client, &config, note.amount)?,
+    }
+    let relayer_info = match relayer_url {
+        Some(url) => {
+            let info = relayer::info(url).await?;
+            info.check(&pool::config().program_id.to_string())?;
+            Some(info)
+        }
+        None => None,
+    };
 
-    println!("{}", style("📤 Withdraw (via Relayer)").bold());
-    println!("{}", style("─".repeat(40)).dim());
-    println!("  Amount:     {} SOL", style(format!("{:.4}", amount_sol)).green());
-    println!("  Recipient:  {}", style(recipient).cyan());
-    println!("  Note ID:    {}", style(&note.id).dim());
-    println!("  Relayer:    {}", style(relayer_url).dim());
-    println!();
+    // Read once: it shows how well the withdrawal hides, and the first
+    // proof is made against it
+    let pb = interrupt::spinner();
+    pb.set_message("Reading the pool's history...");
+    let leaves = fetch_leaves_from_chain(client, &config)?;
+    let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT)?;
+    tree.insert_many(&leaves.iter().map(|leaf| leaf.commitment).collect::<Vec<_>>())?;
+    pb.finish_and_clear();
+    let indices = tree.indices_of(&crypto::str_to_fr(&note.commitment)?);
+    // A commitment deposited more than once is at the leaf recorded with
+    // the note, if that leaf holds it
+    let recorded = usize::try_from(note.leaf_index).ok().filter(|leaf| indices.contains(leaf));
+    let leaf_index = leaf_index.or(recorded);
+    // A note not in the tree yet fails below, when it's looked up to prove
+    if !indices.is_empty() {
+        let note_leaf = note_leaf_index(&tree, &note, leaf_index)?;
+        // Each transaction inserts two leaves
+        let public_amounts: Vec<i64> = leaves.iter().step_by(2).map(|l| l.public_amount).collect();
+        let set = AnonymitySet::of(&public_amounts, note_leaf / 2, note.amount);
+        print_anonymity_set(&set);
+        if let Some(min) = min_anonymity_set {
+            set.check(min)?;
+        }
+    }
+    let mut fetched = Some(tree);
 
-    if !skip_confirm {
-        if !Confirm::new()
+    if !skip_confirm
+        && !dry_run
+        && !Confirm::new()
             .with_prompt("Proceed with withdrawal?")
             .default(true)
             .interact()?
-        {
-            println!("{}", style("Cancelled").red());
-            return Ok(());
-        }
+    {
+        say!("{}", style("Cancelled").red());
+        return Ok(None);
     }
 
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} {msg}")
-            .unwrap(),
-    );
-    pb.enable_steady_tick(Duration::from_millis(100));
+    let pb = interrupt::spinner();
 
     // Load prover
     pb.set_message("Loading circuit...");
-    let wasm_path = format!("{}/transaction2.wasm", artifacts_path);
-    let zkey_path = format!("{}/transaction2.zkey", artifacts_path);
-    let prover = PrivacyProver::new(&wasm_path, &zkey_path)?;
+    let prover = PrivacyProver::shared(artifacts_path, |done, total| {
+        pb.set_message(format!("Loading circuit... {}%", done * 100 / total))
+    })?;
 
     // Reconstruct UTXO from note
-    let utxo = Utxo::from_values(
+    let utxo = note.utxo()?;
+
+    let ext_data = withdrawal_ext_data(
+        client,
+        &base_config,
+        keypair,
+        relayer_info.as_ref(),
+        recipient_pubkey,
         note.amount,
-        &note.privkey,
-        &note.pubkey,
-        &note.blinding,
     )?;
 
-    // Fetch commitments and rebuild tree
-    pb.set_message("Fetching Merkle tree from chain...");
-    let config = PoolConfig::default();
-    let commitments = fetch_commitments_from_chain(client, &config)?;
+    let mut reproofs = 0;
+    let proof_data = loop {
+        // Fetch commitments and rebuild tree
+        let tree = match fetched.take() {
+            Some(tree) => tree,
+            None => {
+                pb.set_message("Fetching Merkle tree from chain...");
+                let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT)?;
+                tree.insert_many(&fetch_commitments_from_chain(client, &config)?)?;
+                tree
+            }
+        };
+
+        // Prove against the newest root the program has recorded
+        let tree = chain::accepted_tree(client, &config, tree)?;
+
+        // Find our commitment in tree
+        let leaf_index = note_leaf_index(&tree, &note, leaf_index)?;
+
+        // Generate proof
+        let proving = prover::prove_withdraw(
+            &prover,
+            &utxo,
+            leaf_index,
+            tree,
+            ext_data,
+            show_proof_stage(&pb),
+        );
+        let proof_data = interrupt::timed(&pb, "Computing witness...", proving).await?;
 
-    let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT);
-    for c in &commitments {
-        tree.insert(*c);
+        // Deposits landing while proving push older roots out of the
+        // program's history; a proof against one of those can't land
+        let history = chain::root_history(client, &config)?;
+        if history.iter().any(|root| root[..] == proof_data.root[..]) {
+            break proof_data;
+        }
+        reproofs += 1;
+        if !prove_again(&pb, skip_confirm, reproofs)? {
+            return Err(anyhow!(
+                "The proof's root is no longer in the pool's root history, so the program would reject it; nothing was sent"
+            ));
+        }
+    };
+
+    if dry_run {
+        let tx = match &relayer_info {
+            // The relayer signs as the fee payer and sets its own compute
+            // budget; show the transaction it would be asked to sign
+            Some(info) => {
+                let relayer = info.address()?;
+                let transact_ix = transact_instruction(
+                    &base_config,
+                    spl.as_ref(),
+                    &proof_data,
+                    &relayer,
+                    &recipient_pubkey,
+                )?;
+                let recent_blockhash = client.call(|c| c.get_latest_blockhash())?;
+                Transaction::new_unsigned(Message::new_with_blockhash(
+                    &with_compute_budget(transact_ix, COMPUTE_UNIT_LIMIT, 0),
+                    Some(&relayer),
+                    &recent_blockhash,
+                ))
+            }
+            None => {
+                let transact_ix = transact_instruction(
+                    &base_config,
+                    spl.as_ref(),
+                    &proof_data,
+                    &keypair.pubkey(),
+                    &recipient_pubkey,
+                )?;
+                let budget = ComputeBudget { unit_limit: compute_unit_limit, unit_price };
+                sign_with_budget(client, keypair, transact_ix, None, budget, &pb)?
+            }
+        };
+        pb.finish_and_clear();
+        print_dry_run(&tx, relayer_info.is_none())?;
+        return Ok(None);
     }
 
-    // Find our commitment in tree
-    let commitment_fr = crypto::str_to_fr(&note.commitment)?;
-    let leaf_index = tree
-        .leaves
-        .iter()
-        .position(|&l| l == commitment_fr)
-        .ok_or_else(|| anyhow!("Commitment not found in tree"))?;
+    let signature = match relayer_url {
+        Some(url) => {
+            pb.set_message("Sending to relayer...");
+            relayer::relay_withdrawal(url, &proof_data, recipient, mint.as_ref(), &pb).await?
+        }
+        None => {
+            pb.set_message("Sending transaction...");
+            let budget = ComputeBudget { unit_limit: compute_unit_limit, unit_price };
+            let transact_ix = transact_instruction(
+                &base_config,
+                spl.as_ref(),
+                &proof_data,
+                &keypair.pubkey(),
+                &recipient_pubkey,
+            )?;
+            submit_direct(client, keypair, transact_ix, budget, &pb)?
+        }
+    };
 
-    // Generate proof
-    pb.set_message("Generating ZK proof (this takes ~30s)...");
-    let recipient_bytes: [u8; 32] = recipient_pubkey.to_bytes();
-    let proof_data = prover.prove_withdraw(&utxo, leaf_index, &tree, &recipient_bytes)?;
+    pb.finish_with_message("Done!");
+
+    // Update note status
+    store.update_status(&note.id, "withdrawn", Some(&signature))?;
+
+    say!();
+    say!("{}", style("✅ Withdrawal successful!").green().bold());
+    say!("Amount: {}", token.format(note.amount));
+    say!("Recipient: {}", recipient);
+    say!("Signature: {}", signature);
+    say!();
+    if relayer_url.is_some() {
+        say!("{}", style("🔐 Your address is NOT visible in this transaction!").cyan());
+        say!("{}", style("   Only the relayer address appears on-chain.").dim());
+        say!();
+    }
 
-    // Build instruction data for relayer
-    pb.set_message("Preparing relay request...");
-    let instruction_data = proof_data.to_instruction_data();
+    Ok(Some(WithdrawResult {
+        note_id: note.id,
+        amount_lamports: note.amount,
+        recipient: recipient.to_string(),
+        signature,
+        relayed: relayer_url.is_some(),
+        mint: note.mint,
+    }))
+}
 
-    // Send to relayer instead of submitting directly
-    pb.set_message("Sending to relayer...");
-    
-    let relay_request = serde_json::json!({
-        "instruction_data": base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &instruction_data),
-        "nullifier1": hex::encode(&proof_data.nullifier1),
-        "nullifier2": hex::encode(&proof_data.nullifier2),
-        "recipient": recipient,
-    });
+/// Print the note store's shielded address, creating its keys on first use
+fn cmd_address() -> Result<()> {
+    let mut store = NoteStore::load()?;
+    let address = store.shielded_keys_or_create()?.address()?.to_string();
+    output::emit(&serde_json::json!({ "address": address }))?;
+    say!("{}", style("🛡️  Shielded address").bold());
+    say!("{}", style("─".repeat(40)).dim());
+    say!("{}", address);
+    say!();
+    say!("{}", style("Notes paid to it with `privacy shield-send` are imported with").dim());
+    say!("{}", style("`privacy scan --incoming`, into this note store only.").dim());
+    Ok(())
+}
 
-    let http_client = reqwest::Client::new();
-    let response = http_client
-        .post(format!("{}/relay", relayer_url))
-        .json(&relay_request)
-        .send()
-        .await
-        .map_err(|e| anyhow!("Failed to connect to relayer: {}", e))?;
+/// Print the viewing key of the note store's shielded address
+fn cmd_export_viewing_key() -> Result<()> {
+    let store = NoteStore::load()?;
+    let keys = store.shielded_keys.as_ref().ok_or_else(|| {
+        anyhow!("This note store has no shielded address yet; `privacy address` creates one")
+    })?;
+    let viewing_key = keys.viewing_key()?.to_string();
+    output::emit(&serde_json::json!({ "viewing_key": viewing_key }))?;
+    say!("{}", style("👁️  Viewing key").bold());
+    say!("{}", style("─".repeat(40)).dim());
+    say!("{}", viewing_key);
+    say!();
+    say!(
+        "{} Whoever holds it sees every note paid to your shielded address, and its amount.",
+        style("⚠️").yellow()
+    );
+    say!("{}", style("It can't spend them: `privacy scan --incoming --watch-only <KEY>` imports").dim());
+    say!("{}", style("them watch-only, and withdrawing one fails.").dim());
+    Ok(())
+}
 
-    let relay_result: serde_json::Value = response
-        .json()
-        .await
-        .map_err(|e| anyhow!("Invalid relayer response: {}", e))?;
+/// Pay `amount` of a note to a shielded address with a transfer proof:
+/// one output is committed to the recipient's pubkey, the other returns
+/// the change to a new note of ours, and no funds enter or leave the pool.
+/// The recipient's note is sealed into the transaction's memo, which
+/// `scan --incoming` finds, and into `output` for them to import.
+async fn cmd_shield_send(
+    sender: &Sender<'_>,
+    to: &str,
+    amount: f64,
+    note_id: Option<String>,
+    mint: Option<Pubkey>,
+    output: &Path,
+    skip_confirm: bool,
+) -> Result<Option<ShieldSendResult>> {
+    let Sender { client, keypair, artifacts_path, priority_fee, compute_unit_limit, .. } = *sender;
+    let address = ShieldedAddress::from_str(to)?;
 
-    if !relay_result["success"].as_bool().unwrap_or(false) {
-        let error = relay_result["error"].as_str().unwrap_or("Unknown error");
-        return Err(anyhow!("Relayer error: {}", error));
+    let mut store = NoteStore::load()?;
+    let network = pool::network_name(&client.url());
+    let base_config = pool::config();
+    let spl = mint.map(|mint| base_config.spl_pool(&mint).copied()).transpose()?;
+    let config = spl.map_or_else(|| base_config.clone(), |spl| base_config.for_spl(&spl));
+    let tree_account = config.tree_account.to_string();
+    let token = Token::load(client, mint)?;
+    let value = token.amount(amount)?;
+    if value == 0 {
+        return Err(anyhow!("Amount must be greater than zero"));
     }
 
-    let signature = relay_result["signature"]
-        .as_str()
-        .ok_or_else(|| anyhow!("No signature in relayer response"))?;
+    let note: Note = match &note_id {
+        Some(id) => {
+            let note = store
+                .notes
+                .iter()
+                .find(|n| &n.id == id)
+                .ok_or_else(|| anyhow!("Note {} not found", id))?;
+            note.check_mint(mint.as_ref())?;
+            note.check_network(network, &tree_account)?;
+            note.spending_key()?;
+            if note.status != "deposited" {
+                return Err(anyhow!("Note {} is {}, not spendable", id, note.status));
+            }
+            note.clone()
+        }
+        None => store
+            .withdrawable(network, &tree_account)
+            .into_iter()
+            .filter(|n| n.check_mint(mint.as_ref()).is_ok() && n.amount >= value)
+            .min_by_key(|n| n.amount)
+            .cloned()
+            .ok_or_else(|| {
+                anyhow!("No unspent note on {} holds {}", network, token.format(value))
+            })?,
+    };
+    if note.amount < value {
+        return Err(anyhow!(
+            "Note {} holds {}, less than {}",
+            note.id,
+            token.format(note.amount),
+            token.format(value)
+        ));
+    }
+    note.verify_commitment()?;
+    let change = note.amount - value;
+
+    say!("{}", style("🛡️  Shielded send").bold());
+    say!("{}", style("─".repeat(40)).dim());
+    say!("  Amount:     {}", style(token.format(value)).green());
+    say!("  To:         {}", style(to).cyan());
+    say!("  Note ID:    {}", style(&note.id).dim());
+    if change > 0 {
+        say!("  Change:     {}", token.format(change));
+    }
+    let unit_price = direct_priority_fee(client, &config, None, priority_fee, compute_unit_limit)?;
+    say!("  Signer:     {}", style(keypair.pubkey()).yellow());
+    say!();
+    say!(
+        "{}",
+        style("⚠️  Your wallet signs and pays for this transaction; the amount and").yellow()
+    );
+    say!("{}", style("   recipient stay hidden, but its timing is linked to you.").yellow());
+    say!();
+
+    if !skip_confirm
+        && !Confirm::new()
+            .with_prompt("Proceed with shielded send?")
+            .default(true)
+            .interact()?
+    {
+        say!("{}", style("Cancelled").red());
+        return Ok(None);
+    }
+
+    let pb = interrupt::spinner();
+    pb.set_message("Loading circuit...");
+    let prover = PrivacyProver::shared(artifacts_path, |done, total| {
+        pb.set_message(format!("Loading circuit... {}%", done * 100 / total))
+    })?;
+
+    let mint_fr = note.mint_fr()?;
+    let utxo = note.utxo()?;
 
+    pb.set_message("Fetching Merkle tree from chain...");
+    let commitments = fetch_commitments_from_chain(client, &config)?;
+    let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT)?;
+    tree.insert_many(&commitments)?;
+    chain::check_tree_root(client, &config, &tree)?;
+    let leaf_index = tree
+        .index_of(&crypto::str_to_fr(&note.commitment)?)
+        .ok_or_else(|| anyhow!("Commitment not found in tree"))?;
+    // The two outputs become the next two leaves
+    let first_leaf = tree.leaf_count();
+
+    let payment = TransferOutput::to(value, address.pubkey);
+    let change_utxo = Utxo::new(change, mint_fr)?;
+    let outputs = [payment, TransferOutput::of(&change_utxo)?];
+    let ext_data = ExtData::direct(keypair.pubkey(), keypair.pubkey());
+    let proving = prover::prove_transfer(
+        &prover,
+        &utxo,
+        leaf_index,
+        tree,
+        outputs,
+        ext_data,
+        show_proof_stage(&pb),
+    );
+    let proof_data = interrupt::timed(&pb, "Computing witness...", proving).await?;
+
+    let transact_ix = transact_instruction(
+        &base_config,
+        spl.as_ref(),
+        &proof_data,
+        &keypair.pubkey(),
+        &keypair.pubkey(),
+    )?;
+    let memo = MemoNote { amount: value, blinding: payment.blinding }.seal(&address);
+    let budget = ComputeBudget { unit_limit: compute_unit_limit, unit_price };
+    let tx = sign_with_budget(client, keypair, transact_ix, Some(&memo), budget, &pb)?;
+    let signature = tx.signatures[0].to_string();
+
+    // Write the recipient's note and our change before sending, as for a
+    // deposit: if the transaction lands unseen, neither is lost
+    let incoming = IncomingNote {
+        amount: value,
+        blinding: payment.blinding,
+        commitment: payment.commitment(&mint_fr)?,
+        leaf_index: first_leaf as u64,
+        mint,
+        network: network.to_string(),
+        pool: config.tree_account,
+    };
+    std::fs::write(output, incoming.seal(&address))
+        .map_err(|e| anyhow!("Failed to write {}: {}", output.display(), e))?;
+    let change_note = (change > 0).then(|| Note {
+        id: notes::generate_note_id(),
+        amount: change,
+        privkey: Some(change_utxo.privkey),
+        pubkey: change_utxo.pubkey,
+        blinding: change_utxo.blinding,
+        commitment: change_utxo.commitment,
+        leaf_index: first_leaf as i64 + 1,
+        status: "deposited".to_string(),
+        created_at: chrono::Utc::now().timestamp() as u64,
+        deposit_tx_sig: Some(signature.clone()),
+        withdraw_tx_sig: None,
+        network: network.to_string(),
+        pool: tree_account.clone(),
+        derivation_index: None,
+        mint: note.mint.clone(),
+    });
+    if let Some(change_note) = &change_note {
+        store.add(change_note.clone())?;
+    }
+
+    pb.set_message("Sending transaction...");
+    if let Err(e) = client.send_and_confirm(&tx) {
+        pb.finish_and_clear();
+        if let Some(change_note) = &change_note {
+            store.update_status(&change_note.id, "deposit_failed", Some(&signature))?;
+        }
+        return Err(e.context(format!(
+            "Shielded send failed; note {} is still unspent. If {} confirms after all, \
+             `privacy notes sync` records it",
+            note.id, signature
+        )));
+    }
     pb.finish_with_message("Done!");
+    store.update_status(&note.id, "withdrawn", Some(&signature))?;
+
+    say!();
+    say!("{}", style("✅ Shielded send successful!").green().bold());
+    say!("Signature: {}", signature);
+    say!("Explorer: {}", pool::explorer_tx_url(&client.url(), &signature));
+    say!();
+    say!("The recipient finds the note in the transaction's memo with");
+    say!("  privacy scan --incoming{}", mint.map_or(String::new(), |m| format!(" --mint {}", m)));
+    say!("or imports {} with `privacy notes restore`.", style(output.display()).cyan());
+    say!("{}", style("   Only their note store can open either.").dim());
+    say!();
+
+    Ok(Some(ShieldSendResult {
+        note_id: note.id,
+        amount_lamports: value,
+        change_note_id: change_note.map(|n| n.id),
+        note_file: output.display().to_string(),
+        signature,
+        mint: note.mint,
+    }))
+}
 
-    // Update note status
-    let mut store = NoteStore::load()?;
-    store.update_status(&note.id, "withdrawn", Some(signature))?;
-
-    println!();
-    println!("{}", style("✅ Withdrawal successful!").green().bold());
-    println!("Amount: {} SOL", amount_sol);
-    println!("Recipient: {}", recipient);
-    println!("Signature: {}", signature);
-    println!();
-    println!("{}", style("🔐 Your address is NOT visible in this transaction!").cyan());
-    println!("{}", style("   Only the relayer address appears on-chain.").dim());
-    println!();
+/// Show who signs the withdrawal, warning when it's the user's own wallet
+fn print_submitter(keypair: &Keypair, relayer_url: Option<&str>) {
+    match relayer_url {
+        Some(url) => say!("  Relayer:    {}", style(url).dim()),
+        None => {
+            say!("  Signer:     {}", style(keypair.pubkey()).yellow());
+            say!();
+            say!(
+                "{}",
+                style("⚠️  No relayer configured (--relayer): your wallet signs and pays for the").yellow()
+            );
+            say!("{}", style("   withdrawal, linking it to this withdrawal on-chain.").yellow());
+        }
+    }
+}
 
-    Ok(())
+/// The compute unit price of a withdrawal the user signs, shown in its
+/// summary; relayed withdrawals pay the relayer's price
+fn direct_priority_fee(
+    client: &ClientPool,
+    config: &PoolConfig,
+    relayer_url: Option<&str>,
+    priority_fee: PriorityFee,
+    compute_unit_limit: Option<u32>,
+) -> Result<u64> {
+    if relayer_url.is_some() {
+        return Ok(0);
+    }
+    let unit_price = chain::priority_fee(client, config, priority_fee)?;
+    if let Some(summary) = priority_fee_summary(priority_fee, unit_price, compute_unit_limit) {
+        say!("  Priority:   {}", summary);
+    }
+    Ok(unit_price)
+}
+
+/// The compute unit price and the most it adds to the transaction fee, or
+/// `None` without a priority fee
+fn priority_fee_summary(
+    priority_fee: PriorityFee,
+    unit_price: u64,
+    compute_unit_limit: Option<u32>,
+) -> Option<String> {
+    if priority_fee == PriorityFee::Fixed(0) {
+        return None;
+    }
+    // Without --compute-unit-limit the limit is sized by simulation later,
+    // usually well under the maximum
+    let unit_limit = compute_unit_limit.unwrap_or(COMPUTE_UNIT_LIMIT);
+    let lamports = priority_fee_lamports(unit_limit, unit_price);
+    Some(format!(
+        "{} µlamports/CU{} (up to +{:.6} SOL)",
+        style(unit_price).yellow(),
+        if priority_fee == PriorityFee::Auto { ", auto" } else { "" },
+        lamports as f64 / 1e9
+    ))
+}
+
+/// Compute budget of a transaction the user signs
+#[derive(Clone, Copy)]
+struct ComputeBudget {
+    /// `--compute-unit-limit`, or `None` to size it by simulation
+    unit_limit: Option<u32>,
+    /// Resolved `--priority-fee`, in micro-lamports per compute unit
+    unit_price: u64,
 }
 
-async fn cmd_transfer(
-    client: &RpcClient,
+/// Sign `transact` behind its compute budget. Without a set limit, the
+/// transaction is simulated and requests the units it used plus a margin,
+/// or the maximum if it can't be simulated.
+fn sign_with_budget(
+    client: &dyn PoolRpc,
     keypair: &Keypair,
-    amount: f64,
+    transact: Instruction,
+    memo: Option<&str>,
+    budget: ComputeBudget,
+    pb: &ProgressBar,
+) -> Result<Transaction> {
+    let recent_blockhash = client.get_latest_blockhash()?;
+    let sign = |unit_limit| {
+        let mut instructions = with_compute_budget(transact.clone(), unit_limit, budget.unit_price);
+        instructions.extend(memo.map(memo_instruction));
+        Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&keypair.pubkey()),
+            &[keypair],
+            recent_blockhash,
+        )
+    };
+
+    let (unit_limit, source) = match budget.unit_limit {
+        Some(unit_limit) => (unit_limit, "--compute-unit-limit".to_string()),
+        None => {
+            pb.set_message("Simulating transaction...");
+            let tx = sign(COMPUTE_UNIT_LIMIT);
+            // A transaction that fails simulation is sent anyway, so the
+            // error comes from the cluster rather than a guessed limit
+            let units = client.simulate_units(&tx).ok().flatten();
+            let source = match units {
+                Some(units) => format!("simulation used {}", units),
+                None => "simulation unavailable".to_string(),
+            };
+            (sized_compute_unit_limit(units), source)
+        }
+    };
+    let line = format!("  Compute unit limit: {} ({})", unit_limit, source);
+    pb.suspend(|| say!("{}", style(line).dim()));
+    pb.set_message("Sending transaction...");
+    Ok(sign(unit_limit))
+}
+
+/// Sign and send a withdrawal's `transact` with the user's own wallet
+fn submit_direct(
+    client: &dyn PoolRpc,
+    keypair: &Keypair,
+    transact_ix: Instruction,
+    budget: ComputeBudget,
+    pb: &ProgressBar,
+) -> Result<String> {
+    let tx = sign_with_budget(client, keypair, transact_ix, None, budget, pb)?;
+    Ok(client.send_and_confirm_transaction(&tx)?.to_string())
+}
+
+/// Accounts of the transact instruction, in order
+const TRANSACT_ACCOUNT_NAMES: [&str; 9] = [
+    "tree",
+    "nullifier1",
+    "nullifier2",
+    "global_config",
+    "pool_vault",
+    "signer",
+    "recipient",
+    "fee_recipient",
+    "system_program",
+];
+
+/// Accounts of the `transact_spl` instruction, in order
+const TRANSACT_SPL_ACCOUNT_NAMES: [&str; 12] = [
+    "tree",
+    "token_pool",
+    "nullifier1",
+    "nullifier2",
+    "global_config",
+    "token_account",
+    "vault",
+    "signer",
+    "vault_authority",
+    "fee_token_acct",
+    "system_program",
+    "token_program",
+];
+
+/// Print the transaction `--dry-run` stopped before sending; with `--json`
+/// it is the command's result
+fn print_dry_run(tx: &Transaction, signed: bool) -> Result<()> {
+    let bytes = bincode::serialize(tx)?;
+    let message = &tx.message;
+    let transact = message
+        .instructions
+        .last()
+        .ok_or_else(|| anyhow!("Transaction has no instructions"))?;
+    let accounts: Vec<String> = transact
+        .accounts
+        .iter()
+        .map(|&i| message.account_keys[i as usize].to_string())
+        .collect();
+    let (names, nullifier1): (&[&str], usize) =
+        if transact.data.starts_with(&TRANSACT_SPL_DISCRIMINATOR) {
+            (&TRANSACT_SPL_ACCOUNT_NAMES, 2)
+        } else {
+            (&TRANSACT_ACCOUNT_NAMES, 1)
+        };
+
+    say!();
+    say!("{}", style("🧪 Dry run: nothing was sent").bold());
+    say!("{}", style("─".repeat(40)).dim());
+    say!(
+        "  Size:     {} bytes ({})",
+        bytes.len(),
+        if signed { "signed by your wallet" } else { "unsigned; the relayer signs it" }
+    );
+    say!("  Instruction data: {} bytes", transact.data.len());
+    say!("  Accounts:");
+    for (name, account) in names.iter().zip(&accounts) {
+        say!("    {:<15} {}", name, style(account).dim());
+    }
+    say!("  Transaction (base64):");
+    say!("{}", BASE64.encode(&bytes));
+    say!();
+
+    output::emit(&DryRunResult {
+        transaction: BASE64.encode(&bytes),
+        size_bytes: bytes.len(),
+        signed,
+        nullifier_pdas: [accounts[nullifier1].clone(), accounts[nullifier1 + 1].clone()],
+        accounts,
+    })
+}
+
+/// What a withdrawal's proof commits to: the relayer that sends it and the
+/// fee the program pays it, or the wallet itself and no fee
+fn withdrawal_ext_data(
+    client: &ClientPool,
+    config: &PoolConfig,
+    keypair: &Keypair,
+    relayer: Option<&relayer::RelayerInfo>,
+    recipient: Pubkey,
+    amount: u64,
+) -> Result<ExtData> {
+    Ok(match relayer {
+        Some(info) => ExtData {
+            recipient,
+            relayer: info.address()?,
+            fee: chain::global_config(client, config)?.withdrawal_fee(amount),
+        },
+        None => ExtData::direct(recipient, keypair.pubkey()),
+    })
+}
+
+/// Generate a withdrawal proof and write it to `output` for `submit`
+async fn cmd_prove(
+    client: &ClientPool,
+    keypair: &Keypair,
+    note_id: &str,
     recipient: &str,
     artifacts_path: &str,
-    relayer_url: &str,
-    skip_confirm: bool,
+    relayer_url: Option<&str>,
+    output: &str,
 ) -> Result<()> {
+    let recipient_pubkey = Pubkey::from_str(recipient)
+        .map_err(|_| anyhow!("Invalid recipient address"))?;
+
+    let store = NoteStore::load()?;
+    let network = pool::network_name(&client.url());
+    let config = pool::config();
+    let note = store
+        .notes
+        .iter()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| anyhow!("Note {} not found", note_id))?;
+    // Proof files and `submit` are SOL only
+    note.check_mint(None)?;
+    note.check_network(network, &config.tree_account.to_string())?;
+    if note.status != "deposited" {
+        return Err(anyhow!("Note {} is {}, not deposited", note_id, note.status));
+    }
+    let utxo = note.utxo()?;
+
+    say!("{}", style("🧮 Generate withdrawal proof").bold());
+    say!("{}", style("─".repeat(40)).dim());
+    say!("  Amount:     {} SOL", style(format!("{:.4}", note.amount as f64 / 1e9)).green());
+    say!("  Recipient:  {}", style(recipient).cyan());
+    say!("  Note ID:    {}", style(&note.id).dim());
+    print_submitter(keypair, relayer_url);
+    say!();
+
+    // The proof commits to whoever will send it
+    let relayer_info = match relayer_url {
+        Some(url) => {
+            let info = relayer::info(url).await?;
+            info.check(&config.program_id.to_string())?;
+            Some(info)
+        }
+        None => None,
+    };
+    let ext_data = withdrawal_ext_data(
+        client,
+        &config,
+        keypair,
+        relayer_info.as_ref(),
+        recipient_pubkey,
+        note.amount,
+    )?;
+
+    let pb = interrupt::spinner();
+
+    pb.set_message("Loading circuit...");
+    let prover = PrivacyProver::shared(artifacts_path, |done, total| {
+        pb.set_message(format!("Loading circuit... {}%", done * 100 / total))
+    })?;
+
+    pb.set_message("Fetching Merkle tree from chain...");
+    let commitments = fetch_commitments_from_chain(client, &config)?;
+    let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT)?;
+    tree.insert_many(&commitments)?;
+    chain::check_tree_root(client, &config, &tree)?;
+
+    let leaf_index = CommitmentSet::from_leaves(&commitments)
+        .leaf_index(&crypto::str_to_fr(&note.commitment)?)
+        .ok_or_else(|| anyhow!("Commitment not found in tree"))?;
+
+    let leaf_count = tree.leaf_count() as u64;
+    let proving = prover::prove_withdraw(
+        &prover,
+        &utxo,
+        leaf_index,
+        tree,
+        ext_data,
+        show_proof_stage(&pb),
+    );
+    let proof = interrupt::timed(&pb, "Computing witness...", proving).await?;
+    pb.finish_with_message("Done!");
+
+    let file = ProofFile {
+        version: proof_file::PROOF_FILE_VERSION,
+        network: network.to_string(),
+        pool: config.tree_account.to_string(),
+        note_id: note.id.clone(),
+        recipient: recipient.to_string(),
+        relayer: ext_data.relayer.to_string(),
+        fee: ext_data.fee,
+        leaf_count,
+        created_at: chrono::Utc::now().timestamp() as u64,
+        proof,
+    };
+    file.save(std::path::Path::new(output))?;
+
+    say!();
+    say!("{} Proof written to {}", style("✅").green(), style(output).cyan());
+    say!("   Send it with: privacy submit {}", output);
+    Ok(())
+}
+
+/// Send a proof generated by `prove`, through the relayer unless `direct`
+/// or none is configured
+async fn cmd_submit(
+    sender: &Sender<'_>,
+    file: &str,
+    direct: bool,
+    skip_confirm: bool,
+) -> Result<Option<WithdrawResult>> {
+    let Sender { client, keypair, relayer_url, priority_fee, compute_unit_limit, .. } = *sender;
+    let relayer_url = relayer_url.filter(|_| !direct);
+    let proof = ProofFile::load(std::path::Path::new(file))?;
+    let config = pool::config();
+    let network = pool::network_name(&client.url());
+    if proof.network != network || proof.pool != config.tree_account.to_string() {
+        return Err(anyhow!(
+            "The proof was generated for {} (pool {}), but the CLI is pointed at {} (pool {})",
+            proof.network,
+            proof.pool,
+            network,
+            config.tree_account
+        ));
+    }
+    let recipient = Pubkey::from_str(&proof.recipient)
+        .map_err(|_| anyhow!("Invalid recipient address in proof file"))?;
+
+    say!("{}", style("📤 Submit withdrawal proof").bold());
+    say!("{}", style("─".repeat(40)).dim());
+    say!(
+        "  Amount:     {} SOL",
+        style(format!("{:.4}", proof.proof.public_amount.unsigned_abs() as f64 / 1e9)).green()
+    );
+    say!("  Recipient:  {}", style(&proof.recipient).cyan());
+    say!("  Note ID:    {}", style(&proof.note_id).dim());
+    let unit_price =
+        direct_priority_fee(client, &config, relayer_url, priority_fee, compute_unit_limit)?;
+    print_submitter(keypair, relayer_url);
+    say!();
+
+    // The proof commits to a root, which the program only accepts while
+    // it is in the tree account's root history
+    let commitments = fetch_commitments_from_chain(client, &config)?;
+    let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT)?;
+    tree.insert_many(&commitments)?;
+    chain::check_tree_root(client, &config, &tree)?;
+    match proof.freshness(&tree, &chain::root_history(client, &config)?) {
+        RootFreshness::Current => {}
+        RootFreshness::Advanced { leaves_added } => {
+            say!(
+                "{}",
+                style(format!(
+                    "⚠️  The on-chain root has advanced by {} leaves since this proof was generated.",
+                    leaves_added
+                ))
+                .yellow()
+            );
+            say!(
+                "{}",
+                style("   It only verifies while its root is in the pool's recent root history;").dim()
+            );
+            say!("{}", style("   if it is rejected, run 'privacy prove' again.").dim());
+            say!();
+        }
+        RootFreshness::Expired { leaves_added } => {
+            return Err(anyhow!(
+                "{} leaves have landed since this proof was generated, and its root has left the \
+                 pool's last {} roots, so the program would reject it; run 'privacy prove' again",
+                leaves_added,
+                privacy_zig_core::tree::ROOT_HISTORY_SIZE
+            ));
+        }
+        RootFreshness::Unknown => {
+            return Err(anyhow!(
+                "The proof's root was never this pool's root at {} leaves, so the program would \
+                 reject it; run 'privacy prove' again",
+                proof.leaf_count
+            ));
+        }
+    }
+    if let Some(url) = relayer_url {
+        let info = relayer::info(url).await?;
+        info.check(&config.program_id.to_string())?;
+        // The relayer refuses proofs that don't commit to it
+        if info.relayer_address != proof.relayer {
+            return Err(anyhow!(
+                "The proof was generated for relayer {}, but {} is {}; \
+                 run 'privacy prove' again with this relayer, or submit with --direct",
+                proof.relayer,
+                url,
+                info.relayer_address
+            ));
+        }
+    }
+
+    if !skip_confirm
+        && !Confirm::new()
+            .with_prompt("Submit this withdrawal?")
+            .default(true)
+            .interact()?
+    {
+        say!("{}", style("Cancelled").red());
+        return Ok(None);
+    }
+
+    let pb = interrupt::spinner();
+    let signature = match relayer_url {
+        Some(url) => {
+            pb.set_message("Sending to relayer...");
+            relayer::relay_withdrawal(url, &proof.proof, &proof.recipient, None, &pb).await?
+        }
+        None => {
+            pb.set_message("Sending transaction...");
+            let budget = ComputeBudget { unit_limit: compute_unit_limit, unit_price };
+            let transact_ix =
+                transact_instruction(&config, None, &proof.proof, &keypair.pubkey(), &recipient)?;
+            submit_direct(client, keypair, transact_ix, budget, &pb)?
+        }
+    };
+    pb.finish_and_clear();
+
+    let mut store = NoteStore::load()?;
+    store.update_status(&proof.note_id, "withdrawn", Some(&signature))?;
+
+    say!();
+    say!("{}", style("✅ Withdrawal successful!").green().bold());
+    say!("Signature: {}", signature);
+    Ok(Some(WithdrawResult {
+        note_id: proof.note_id,
+        amount_lamports: proof.proof.public_amount.unsigned_abs(),
+        recipient: proof.recipient,
+        signature,
+        relayed: relayer_url.is_some(),
+        mint: None,
+    }))
+}
+
+/// Check the proof in `file` against `vkey`, a snarkjs verifying key, or
+/// else the key in the artifacts' zkey
+fn cmd_verify(file: &Path, vkey: Option<&Path>, artifacts_path: &str) -> Result<VerifyResult> {
+    let proof = proof_file::read_proof(file)?;
+    let (vk, key) = match vkey {
+        Some(path) => {
+            let data = std::fs::read_to_string(path)
+                .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+            let vk = privacy_zig_core::verifying_key_from_snarkjs(&data)
+                .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+            (vk, path.display().to_string())
+        }
+        None => {
+            let zkey = format!("{}/transaction2.zkey", artifacts_path);
+            let pb = interrupt::spinner();
+            pb.set_message("Reading the verifying key...");
+            let vk = prover::verifying_key(&zkey, &NoteStore::data_dir()?.join("cache"))?;
+            pb.finish_and_clear();
+            (vk, zkey)
+        }
+    };
+    let error = proof.verify(&vk).err().map(|e| e.to_string());
+
+    say!("{}", style("🔍 Proof Verification").bold());
+    say!("{}", style("─".repeat(50)).dim());
+    say!("  Proof:          {}", file.display());
+    say!("  Key:            {}", style(&key).dim());
+    say!("  Root:           {}", hex::encode(&proof.root));
+    say!("  Public amount:  {}", proof.public_amount);
+    match &error {
+        None => say!("  Result:         {}", style("✓ valid").green()),
+        Some(error) => say!("  Result:         {} ({})", style("✗ invalid").red(), error),
+    }
+    say!();
+
+    Ok(VerifyResult {
+        valid: error.is_none(),
+        error,
+        key,
+        root: hex::encode(&proof.root),
+        public_amount: proof.public_amount,
+        nullifiers: [hex::encode(&proof.nullifier1), hex::encode(&proof.nullifier2)],
+    })
+}
+
+async fn cmd_transfer(sender: &Sender<'_>, args: TransferArgs) -> Result<Option<TransferResult>> {
+    let Sender { client, keypair, relayer_url, priority_fee, compute_unit_limit, .. } = *sender;
+    let TransferArgs {
+        amount,
+        recipient,
+        yes: skip_confirm,
+        deterministic,
+        always_deposit,
+        delay,
+        random_delay,
+        min_anonymity_set,
+        dry_run,
+    } = args;
+    let recipient = recipient.as_str();
+    let delay = random_delay.or(delay.map(DelayRange::fixed));
     let _recipient_pubkey = Pubkey::from_str(recipient)
         .map_err(|_| anyhow!("Invalid recipient address"))?;
 
-    println!("{}", style("⚡ Anonymous Transfer (via Relayer)").bold());
-    println!("{}", style("─".repeat(40)).dim());
-    println!("  Amount:     {} SOL", style(format!("{:.4}", amount)).green());
-    println!("  Recipient:  {}", style(recipient).cyan());
-    println!("  From:       {}", style(keypair.pubkey().to_string()).dim());
-    println!("  Relayer:    {}", style(relayer_url).dim());
-    println!();
-    println!("{}", style("  This will deposit and immediately withdraw to recipient.").dim());
-    println!("{}", style("  Withdrawal uses relayer - your address stays hidden!").dim());
-    println!();
-
-    if !skip_confirm {
-        if !Confirm::new()
+    // An aged note of the right size makes the deposit leg unnecessary: no
+    // deposit fee, and no fresh deposit right before the withdrawal
+    let reusable = if always_deposit {
+        None
+    } else {
+        let lamports = (amount * 1_000_000_000.0) as u64;
+        let network = pool::network_name(&client.url());
+        let tree_account = pool::config().tree_account.to_string();
+        let now = chrono::Utc::now().timestamp() as u64;
+        let store = NoteStore::load()?;
+        store
+            .find_reusable(lamports, network, &tree_account, REUSE_MIN_NOTE_AGE_SECS, now)
+            .map(|n| (n.id.clone(), now.saturating_sub(n.created_at)))
+    };
+
+    say!("{}", style("⚡ Anonymous Transfer (via Relayer)").bold());
+    say!("{}", style("─".repeat(40)).dim());
+    say!("  Amount:     {} SOL", style(format!("{:.4}", amount)).green());
+    say!("  Recipient:  {}", style(recipient).cyan());
+    say!("  From:       {}", style(keypair.pubkey().to_string()).dim());
+    // Resolved once, so both legs and the summary agree
+    let unit_price = chain::priority_fee(client, &pool::config(), priority_fee)?;
+    if let Some(summary) = priority_fee_summary(priority_fee, unit_price, compute_unit_limit) {
+        say!("  Priority:   {}", summary);
+    }
+    let sender = &Sender { priority_fee: PriorityFee::Fixed(unit_price), ..*sender };
+    print_submitter(keypair, relayer_url);
+    say!();
+    if let Some((note_id, age)) = &reusable {
+        say!(
+            "{}",
+            style(format!(
+                "  Using existing note {} (deposited {} ago); skipping the deposit.",
+                note_id,
+                format_age(*age)
+            ))
+            .dim()
+        );
+        say!("{}", style("  An aged note hides this withdrawal among every deposit since then,").dim());
+        say!("{}", style("  unlike a fresh deposit right before it. Use --always-deposit to opt out.").dim());
+        if delay.is_some() {
+            say!("{}", style("  The note is already aged, so there is no delay.").dim());
+        }
+    } else if let Some(delay) = delay {
+        say!("{}", style(format!("  This will deposit, wait {} and withdraw to recipient.", delay)).dim());
+        if relayer_url.is_some() {
+            say!("{}", style("  Withdrawal uses relayer - your address stays hidden!").dim());
+        }
+    } else {
+        say!("{}", style("  This will deposit and immediately withdraw to recipient.").dim());
+        say!(
+            "{}",
+            style("  ⚠️  The withdrawal follows its deposit within seconds, so timing alone")
+                .red()
+                .bold()
+        );
+        say!(
+            "{}",
+            style("  links the two, however many other notes are in the pool. Use --delay or").red()
+        );
+        say!(
+            "{}",
+            style("  --random-delay (see 'privacy transfer --help') to hide among later deposits.")
+                .red()
+        );
+        if relayer_url.is_some() {
+            say!("{}", style("  Withdrawal uses relayer - your address stays hidden!").dim());
+        }
+    }
+    say!();
+
+    // A fresh note withdrawn straight away has no later deposits at all;
+    // refuse before depositing rather than after
+    if let (None, None, Some(min @ 1..)) = (&reusable, delay, min_anonymity_set) {
+        return Err(anyhow!(
+            "A note withdrawn right after its deposit has no later deposits to hide among, \
+             below --min-anonymity-set {}; pass --delay or --random-delay",
+            min
+        ));
+    }
+
+    if !skip_confirm
+        && !dry_run
+        && !Confirm::new()
             .with_prompt("Proceed with anonymous transfer?")
             .default(true)
             .interact()?
-        {
-            println!("{}", style("Cancelled").red());
-            return Ok(());
-        }
+    {
+        say!("{}", style("Cancelled").red());
+        return Ok(None);
     }
 
-    // Step 1: Deposit (user signs this - deposit is public anyway)
-    println!();
-    println!("{}", style("Step 1/2: Depositing...").bold());
-    cmd_deposit(client, keypair, amount, artifacts_path, true).await?;
-
-    // Wait for transaction confirmation before querying tree
-    println!("{}", style("Waiting for confirmation...").dim());
-    // Wait longer for transaction to be indexed and commitment to appear in tree
-    // 30 seconds is needed for testnet to index the commitment
-    tokio::time::sleep(Duration::from_secs(30)).await;
-
-    // Step 2: Withdraw to recipient via relayer
-    println!();
-    println!("{}", style("Step 2/2: Withdrawing to recipient via relayer...").bold());
-    
-    // Get latest note
-    let store = NoteStore::load()?;
-    let latest_note = store
-        .notes
-        .iter()
-        .filter(|n| n.status == "deposited")
-        .last()
-        .ok_or_else(|| anyhow!("No deposited note found"))?;
+    if let Some((note_id, _)) = reusable {
+        let withdraw = WithdrawArgs {
+            recipient: recipient.to_string(),
+            note_id: Some(note_id),
+            mint: None,
+            leaf_index: None,
+            min_anonymity_set,
+            yes: true,
+            dry_run,
+        };
+        let withdrawal = cmd_withdraw(sender, withdraw).await?;
+        let Some(withdrawal) = withdrawal else {
+            if dry_run {
+                return Ok(None);
+            }
+            return Err(anyhow!("No withdrawable note for the transfer"));
+        };
 
-    cmd_withdraw(
+        say!();
+        say!("{}", style("✅ Anonymous transfer complete!").green().bold());
+        say!();
+        return Ok(Some(TransferResult { deposit: None, withdrawal }));
+    }
+
+    let deposit = DepositArgs {
+        amount,
+        mint: None,
+        yes: true,
+        allow_duplicate_commitment: false,
+        deterministic,
+        dry_run,
+    };
+
+    // The withdrawal can't be proved before the deposit is in the tree
+    if dry_run {
+        say!();
+        say!("{}", style("Step 1/2: Depositing (dry run)...").bold());
+        cmd_deposit(sender, deposit).await?;
+        say!("{}", style("  Step 2/2 needs the deposit in the tree, so it wasn't built.").dim());
+        return Ok(None);
+    }
+
+    // Step 1: Deposit (user signs this - deposit is public anyway)
+    say!();
+    say!("{}", style("Step 1/2: Depositing...").bold());
+    let note = cmd_deposit(sender, deposit)
+        .await?
+        .ok_or_else(|| anyhow!("Deposit was cancelled"))?;
+    // The note is saved as deposited, so stopping here loses nothing
+    interrupt::set_pending(format!(
+        "Note {} is deposited and still spendable; withdraw it with \
+         'privacy withdraw --note-id {} --recipient {}'",
+        note.id, note.id, recipient
+    ));
+
+    // The withdrawal proof needs the new leaf, which can take a while to be
+    // indexed; wait for this deposit's commitment rather than a fixed time
+    let pb = interrupt::spinner();
+    pb.set_message("Waiting for the deposit to appear in the tree...");
+    let leaf_index = chain::wait_for_commitment(
         client,
-        keypair,
-        recipient,
-        Some(latest_note.id.clone()),
-        artifacts_path,
-        relayer_url,
-        true,
+        &pool::config(),
+        &crypto::str_to_fr(&note.commitment)?,
+        chain::COMMITMENT_WAIT_TIMEOUT,
+        |waited| {
+            pb.set_message(format!(
+                "Waiting for the deposit to appear in the tree ({}s)...",
+                waited.as_secs()
+            ))
+        },
     )
     .await?;
+    pb.finish_with_message(format!("Deposit is leaf {}", leaf_index));
 
-    println!();
-    println!("{}", style("✅ Anonymous transfer complete!").green().bold());
-    println!();
-    println!("{}", style("🔐 Privacy achieved:").cyan().bold());
-    println!("   • Deposit: your address visible (unavoidable)");
-    println!("   • Withdraw: only relayer address visible!");
-    println!("   • No on-chain link between you and recipient");
-    println!();
+    // Waiting starts once the deposit is in the tree, so the gap seen on
+    // chain is at least the delay
+    if let Some(delay) = delay {
+        delay::wait(delay.pick(&mut rand::thread_rng())).await;
+    }
 
-    Ok(())
+    // Step 2: Withdraw to recipient
+    say!();
+    say!("{}", style("Step 2/2: Withdrawing to recipient...").bold());
+
+    let withdraw = WithdrawArgs {
+        recipient: recipient.to_string(),
+        note_id: Some(note.id.clone()),
+        mint: None,
+        leaf_index: None,
+        min_anonymity_set,
+        yes: true,
+        dry_run: false,
+    };
+    let withdrawal = cmd_withdraw(sender, withdraw)
+        .await?
+        .ok_or_else(|| anyhow!("No withdrawable note for the transfer"))?;
+    interrupt::clear_pending();
+
+    say!();
+    say!("{}", style("✅ Anonymous transfer complete!").green().bold());
+    say!();
+    if relayer_url.is_some() {
+        say!("{}", style("🔐 Privacy achieved:").cyan().bold());
+        say!("   • Deposit: your address visible (unavoidable)");
+        say!("   • Withdraw: only relayer address visible!");
+        say!("   • No address links you to the recipient; timing and amounts still can");
+        say!();
+    }
+
+    Ok(Some(TransferResult {
+        deposit: Some(DepositResult::from(&note)),
+        withdrawal,
+    }))
 }
 
-async fn cmd_notes(action: Option<NotesAction>) -> Result<()> {
-    let action = action.unwrap_or(NotesAction::List);
+async fn cmd_notes(client: &dyn PoolRpc, action: Option<NotesAction>) -> Result<()> {
+    let action = action.unwrap_or_else(|| NotesAction::List(ListArgs::default()));
 
     match action {
-        NotesAction::List => {
+        NotesAction::List(args) => {
             let store = NoteStore::load()?;
+            let notes = store.select(&args.filter()?);
+            output::emit(&notes.iter().map(|n| n.listing()).collect::<Vec<_>>())?;
 
-            println!("{}", style("📝 My Notes").bold());
-            println!("{}", style("─".repeat(50)).dim());
+            say!("{}", style("📝 My Notes").bold());
+            say!("{}", style("─".repeat(50)).dim());
 
             if store.notes.is_empty() {
-                println!("  No notes found. Use 'privacy deposit' first.");
+                say!("  No notes found. Use 'privacy deposit' first.");
+                return Ok(());
+            }
+            if notes.is_empty() {
+                say!("  None of the {} notes match.", store.notes.len());
                 return Ok(());
             }
 
-            for note in &store.notes {
+            // Decimals of each token held, read once; without the chain,
+            // token amounts are shown in base units
+            let mut tokens = std::collections::HashMap::new();
+            for note in &notes {
                 let status_style = match note.status.as_str() {
                     "deposited" => style(&note.status).green(),
                     "withdrawn" => style(&note.status).dim(),
+                    "invalid" | "deposit_failed" => style(&note.status).red(),
                     _ => style(&note.status).yellow(),
                 };
+                let amount = match note.mint()? {
+                    Some(mint) => tokens
+                        .entry(mint)
+                        .or_insert_with(|| {
+                            Token::load(client, Some(mint))
+                                .unwrap_or(Token { mint: Some(mint), decimals: 0 })
+                        })
+                        .format(note.amount),
+                    None => format!("{:.4} SOL", note.amount as f64 / 1e9),
+                };
 
-                println!(
-                    "  {} │ {} SOL │ {}",
+                say!(
+                    "  {} │ {} │ {} │ {}{}",
                     style(&note.id).cyan(),
-                    style(format!("{:.4}", note.amount as f64 / 1e9)).white(),
-                    status_style
+                    style(amount).white(),
+                    status_style,
+                    style(&note.network).dim(),
+                    if note.is_watch_only() { style(" │ watch-only").yellow() } else { style("") }
                 );
             }
 
-            println!();
+            say!();
 
-            let available: u64 = store
-                .notes
-                .iter()
-                .filter(|n| n.status == "deposited")
-                .map(|n| n.amount)
-                .sum();
+            let deposited = |watch_only: bool| -> u64 {
+                notes
+                    .iter()
+                    .filter(|n| n.status == "deposited" && n.mint.is_none() && n.is_watch_only() == watch_only)
+                    .map(|n| n.amount)
+                    .sum()
+            };
+            let available = deposited(false);
 
-            println!(
+            if notes.len() < store.notes.len() {
+                say!("  Showing {} of {} notes", notes.len(), store.notes.len());
+            }
+            say!(
                 "  Available: {} SOL",
                 style(format!("{:.4}", available as f64 / 1e9)).green()
             );
-            println!();
+            let watched = deposited(true);
+            if watched > 0 {
+                say!("  Watch-only: {:.4} SOL, not spendable here", watched as f64 / 1e9);
+            }
+            say!();
         }
 
-        NotesAction::Export { file } => {
+        NotesAction::Show { id } => cmd_show_note(client, &id)?,
+
+        NotesAction::Export { file, format, include_secrets } => {
+            let file = file.unwrap_or_else(|| match format {
+                ExportFormat::Json => "notes_backup.json".to_string(),
+                ExportFormat::Csv => "notes.csv".to_string(),
+            });
             let store = NoteStore::load()?;
-            store.export(&file)?;
-            println!(
+            store.export(&file, format, include_secrets)?;
+            say!(
                 "{} Notes exported to {}",
                 style("✅").green(),
                 style(&file).cyan()
             );
+            if format == ExportFormat::Csv && include_secrets {
+                say!(
+                    "{} It holds the keys that spend your notes; keep it as safe as notes.json",
+                    style("⚠️").yellow()
+                );
+            }
         }
 
-        NotesAction::Import { file } => {
+        NotesAction::Import { file, merge } => {
             let mut store = NoteStore::load()?;
-            let count = store.import(&file)?;
-            println!(
-                "{} Imported {} notes from {}",
+            let summary = store.import(&file, merge)?;
+            output::emit(&summary)?;
+            say!(
+                "{} Imported {} notes from {} ({} updated, {} already present)",
                 style("✅").green(),
-                style(count).yellow(),
-                style(&file).cyan()
+                style(summary.imported).yellow(),
+                style(&file).cyan(),
+                summary.updated,
+                summary.skipped
             );
+            for invalid in &summary.invalid {
+                say!(
+                    "{} Skipped entry {}{}: {}",
+                    style("⚠️").yellow(),
+                    invalid.index,
+                    invalid.id.as_deref().map(|id| format!(" ({})", id)).unwrap_or_default(),
+                    invalid.reason
+                );
+            }
         }
 
         NotesAction::Delete { id } => {
             let mut store = NoteStore::load()?;
             if store.delete(&id) {
-                println!("{} Note {} deleted", style("✅").green(), style(&id).cyan());
+                say!("{} Note {} deleted", style("✅").green(), style(&id).cyan());
             } else {
-                println!("{} Note {} not found", style("❌").red(), style(&id).cyan());
+                say!("{} Note {} not found", style("❌").red(), style(&id).cyan());
             }
         }
+
+        NotesAction::Backup { id } => {
+            let store = NoteStore::load()?;
+            let note = store
+                .notes
+                .iter()
+                .find(|n| n.id == id)
+                .ok_or_else(|| anyhow!("Note {} not found", id))?;
+            let encoded = NoteBackup::from_note(note)?.encode();
+            let backup = output::secret(encoded.as_str()).ok_or_else(|| {
+                anyhow!("A backup string spends note {}; pass --reveal to print it", note.id)
+            })?;
+            output::emit(&serde_json::json!({ "note_id": note.id, "backup": backup }))?;
+            say!("{}", backup);
+            say!();
+            say!("{}", style("⚠️  Anyone with this string can spend the note. Keep it secret.").yellow());
+        }
+
+        NotesAction::Restore { backup } => cmd_restore_note(client, &backup)?,
+
+        NotesAction::Sync { dry_run } => cmd_sync_notes(client, dry_run)?,
     }
 
     Ok(())
 }
 
-async fn cmd_info(client: &RpcClient, keypair: &Keypair) -> Result<()> {
-    let config = PoolConfig::default();
+/// Print every field of a note, its dates in local time and its
+/// transactions as explorer links; its secrets only with `--reveal`
+fn cmd_show_note(client: &dyn PoolRpc, id: &str) -> Result<()> {
+    let store = NoteStore::load()?;
+    let note = store
+        .notes
+        .iter()
+        .find(|n| n.id == id)
+        .ok_or_else(|| anyhow!("Note {} not found", id))?;
+    // Links go to the cluster the note was made on, or to the one the CLI
+    // is pointed at if that isn't a known network
+    let rpc_url = client.url();
+    let explorer = |signature: &str| match note.network.parse::<Network>() {
+        Ok(network) => network.explorer_tx_url(signature),
+        Err(_) => pool::explorer_tx_url(&rpc_url, signature),
+    };
+    let detail = note.detail(output::reveal(), explorer);
+    output::emit(&detail)?;
+
+    // Without the chain, token amounts are shown in base units
+    let mint = note.mint()?;
+    let amount = Token::load(client, mint).unwrap_or(Token { mint, decimals: 0 }).format(note.amount);
+    let hidden = style(output::MASKED).dim().to_string();
+
+    say!("{}", style(format!("📝 Note {}", note.id)).bold());
+    say!("{}", style("─".repeat(50)).dim());
+    say!("  Amount:      {}", style(amount).green());
+    say!("  Status:      {}", note.status);
+    say!("  Created:     {}", detail.created);
+    say!("  Network:     {}", note.network);
+    say!("  Pool:        {}", note.pool);
+    if let Some(mint) = &note.mint {
+        say!("  Mint:        {}", mint);
+    }
+    say!("  Leaf index:  {}", note.leaf_index);
+    say!("  Commitment:  {}", note.commitment);
+    say!("  Pubkey:      {}", note.pubkey);
+    let privkey = match detail.privkey {
+        Some(privkey) => privkey.to_string(),
+        None if note.is_watch_only() => style("none; watch-only, found with a viewing key").yellow().to_string(),
+        None => hidden.clone(),
+    };
+    say!("  Private key: {}", privkey);
+    say!("  Blinding:    {}", detail.blinding.map_or(hidden, str::to_string));
+    if let Some(index) = note.derivation_index {
+        say!("  Derived at:  index {} of the wallet's seed", index);
+    }
+    for (label, signature, url) in [
+        ("Deposit tx: ", &note.deposit_tx_sig, &detail.deposit_tx_url),
+        ("Withdraw tx:", &note.withdraw_tx_sig, &detail.withdraw_tx_url),
+    ] {
+        if let (Some(signature), Some(url)) = (signature, url) {
+            say!("  {} {}", label, signature);
+            say!("               {}", style(url).cyan());
+        }
+    }
+    if output::reveal() {
+        say!();
+        say!("{}", style("⚠️  Anyone with the private key and blinding can spend the note.").yellow());
+    }
+    say!();
+    Ok(())
+}
 
-    println!("{}", style("ℹ️  Program Info").bold());
-    println!("{}", style("─".repeat(50)).dim());
-    println!("  Program ID:     {}", style(PROGRAM_ID).cyan());
-    println!("  Tree Account:   {}", style(config.tree_account.to_string()).dim());
-    println!("  Global Config:  {}", style(config.global_config.to_string()).dim());
-    println!("  Pool Vault:     {}", style(config.pool_vault.to_string()).dim());
-    println!();
-    println!("{}", style("👛 Wallet").bold());
-    println!("{}", style("─".repeat(50)).dim());
-    println!("  Address:  {}", style(keypair.pubkey().to_string()).cyan());
+/// Rebuild a note from a `notes backup` string, or open one sent to the
+/// store's shielded address, and find its leaf on chain
+fn cmd_restore_note(client: &dyn PoolRpc, backup: &str) -> Result<()> {
+    let backup = note_argument(backup)?;
+    if IncomingNote::is_sealed(&backup) {
+        return cmd_receive_note(client, &backup);
+    }
 
-    let balance = client.get_balance(&keypair.pubkey())?;
-    println!(
-        "  Balance:  {} SOL",
-        style(format!("{:.4}", balance as f64 / 1e9)).green()
-    );
-    println!();
+    let backup = NoteBackup::decode(&backup)?;
+    let config = pool::config();
+    check_restore_pool(client, &config, &backup.network, &backup.pool)?;
+
+    let privkey = backup.privkey_str();
+    let pubkey = crypto::fr_to_str(&crypto::PoseidonHash::new()?.hash1(&backup.privkey)?);
+    let utxo = Utxo::from_values(backup.amount, &privkey, &pubkey, &backup.blinding_str(), sol_mint())?;
+    restore_utxo(client, NoteStore::load()?, &config, utxo, backup.network.clone(), None)
+}
+
+/// Import a note `shield-send` sealed to this store's shielded address
+fn cmd_receive_note(client: &dyn PoolRpc, sealed: &str) -> Result<()> {
+    let store = NoteStore::load()?;
+    let keys = store.shielded_keys.clone().ok_or_else(|| {
+        anyhow!(
+            "This note store has no shielded address, so the note wasn't sent to it; \
+             restore it into the store that printed the recipient's address"
+        )
+    })?;
+    let incoming = IncomingNote::open(sealed, &keys)?;
+    let base_config = pool::config();
+    let config = match &incoming.mint {
+        Some(mint) => base_config.for_spl(base_config.spl_pool(mint)?),
+        None => base_config,
+    };
+    check_restore_pool(client, &config, &incoming.network, &incoming.pool)?;
+
+    let utxo = incoming.to_utxo(&keys)?;
+    restore_utxo(client, store, &config, utxo, incoming.network, incoming.mint)
+}
+
+/// A note string given on the command line, or the contents of the file
+/// it names
+fn note_argument(arg: &str) -> Result<String> {
+    let path = Path::new(arg);
+    if !path.is_file() {
+        return Ok(arg.trim().to_string());
+    }
+    let data = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+    Ok(data.trim().to_string())
+}
 
+/// A note can only be found in the tree of the pool it was made on
+fn check_restore_pool(
+    client: &dyn PoolRpc,
+    config: &PoolConfig,
+    note_network: &str,
+    note_pool: &Pubkey,
+) -> Result<()> {
+    let network = pool::network_name(&client.url());
+    if note_network != network || *note_pool != config.tree_account {
+        return Err(anyhow!(
+            "This note belongs to {} (pool {}), but the CLI is pointed at {} (pool {}); \
+             switch --rpc-url to restore it",
+            note_network,
+            note_pool,
+            network,
+            config.tree_account
+        ));
+    }
     Ok(())
 }
 
-/// Fetch commitments from on-chain transaction history
-fn fetch_commitments_from_chain(
-    client: &RpcClient,
+/// Find `utxo`'s leaf in the tree of `config` and add it to `store`,
+/// withdrawn if its nullifier is already on chain
+fn restore_utxo(
+    client: &dyn PoolRpc,
+    mut store: NoteStore,
     config: &PoolConfig,
-) -> Result<Vec<ark_bn254::Fr>> {
-    use solana_client::rpc_config::RpcTransactionConfig;
-    use solana_sdk::commitment_config::CommitmentConfig;
-    use solana_transaction_status::UiTransactionEncoding;
+    utxo: Utxo,
+    network: String,
+    mint: Option<Pubkey>,
+) -> Result<()> {
+    if let Some(existing) = store.notes.iter().find(|n| n.commitment == utxo.commitment) {
+        say!("{} Note already in the store as {}", style("ℹ️").cyan(), style(&existing.id).cyan());
+        return Ok(());
+    }
 
-    let signatures = client.get_signatures_for_address(&config.tree_account)?;
+    let leaves = fetch_leaves_from_chain(client, config)?;
+    let commitments: Vec<_> = leaves.iter().map(|leaf| leaf.commitment).collect();
+    let leaf_index = CommitmentSet::from_leaves(&commitments)
+        .leaf_index(&crypto::str_to_fr(&utxo.commitment)?)
+        .ok_or_else(|| anyhow!("The note's commitment is not in the tree; was its transaction confirmed?"))?;
+    let spent = is_spent(client, config, &utxo, leaf_index)?;
+    let amount = Token::load(client, mint)?.format(utxo.amount);
 
-    let mut commitments = Vec::new();
-    let discriminator = [217u8, 149, 130, 143, 221, 52, 252, 119];
+    let note = Note {
+        id: notes::generate_note_id(),
+        amount: utxo.amount,
+        privkey: Some(utxo.privkey),
+        pubkey: utxo.pubkey,
+        blinding: utxo.blinding,
+        commitment: utxo.commitment,
+        leaf_index: leaf_index as i64,
+        status: if spent { "withdrawn" } else { "deposited" }.to_string(),
+        created_at: chrono::Utc::now().timestamp() as u64,
+        deposit_tx_sig: Some(leaves[leaf_index].signature.clone()),
+        withdraw_tx_sig: None,
+        network,
+        pool: config.tree_account.to_string(),
+        derivation_index: None,
+        mint: mint.map(|mint| mint.to_string()),
+    };
+    let id = note.id.clone();
+    store.add(note)?;
 
-    for sig_info in signatures.iter().rev() {
-        let sig = sig_info.signature.parse().ok();
-        if sig.is_none() {
+    say!(
+        "{} Restored note {} ({}, leaf {}{})",
+        style("✅").green(),
+        style(&id).cyan(),
+        amount,
+        leaf_index,
+        if spent { ", already withdrawn" } else { "" }
+    );
+    Ok(())
+}
+
+/// Reconcile note statuses with the chain: a note whose nullifier PDA
+/// exists is `withdrawn`, one whose commitment is in the tree is
+/// `deposited`, and one whose deposit never landed is `invalid`
+fn cmd_sync_notes(client: &dyn PoolRpc, dry_run: bool) -> Result<()> {
+    let config = pool::config();
+    let network = pool::network_name(&client.url());
+    let pool_id = config.tree_account.to_string();
+    let mut store = NoteStore::load()?;
+
+    say!("{}", style("🔄 Syncing notes with the chain").bold());
+    say!("{}", style("─".repeat(50)).dim());
+
+    let leaves = fetch_leaves_from_chain(client, &config)?;
+    let commitments: Vec<_> = leaves.iter().map(|leaf| leaf.commitment).collect();
+    let tree = CommitmentSet::from_leaves(&commitments);
+
+    let signatures: Vec<String> = store
+        .notes
+        .iter()
+        .filter(|n| n.is_on(network, &pool_id))
+        .filter_map(|n| n.deposit_tx_sig.clone())
+        .collect();
+    let outcomes = chain::deposit_outcomes(client, &signatures)?;
+
+    // (id, amount, old status, new status)
+    let mut changes = Vec::new();
+    let mut skipped = 0;
+    for note in store.notes.iter_mut() {
+        if !note.is_on(network, &pool_id) {
+            skipped += 1;
             continue;
         }
 
-        let tx_result = client.get_transaction_with_config(
-            &sig.unwrap(),
-            RpcTransactionConfig {
-                encoding: Some(UiTransactionEncoding::Base64),
-                commitment: Some(CommitmentConfig::confirmed()),
-                max_supported_transaction_version: Some(0),
+        let leaf_index = tree.leaf_index(&crypto::str_to_fr(&note.commitment)?);
+        let spent = match leaf_index {
+            Some(index) => chain::is_note_spent(client, &config, note, index)?,
+            None => Some(false),
+        };
+        let deposit = note
+            .deposit_tx_sig
+            .as_ref()
+            .and_then(|sig| outcomes.get(sig).copied())
+            .unwrap_or(chain::DepositOutcome::Unknown);
+
+        // A watch-only note keeps its status: it can't be told spent
+        let Some(status) = spent.and_then(|spent| chain::reconcile_status(leaf_index.is_some(), spent, deposit))
+        else {
+            continue;
+        };
+        if let Some(index) = leaf_index {
+            note.leaf_index = index as i64;
+        }
+        if note.status != status {
+            changes.push((note.id.clone(), note.amount, note.status.clone(), status));
+            note.status = status.to_string();
+        }
+    }
+
+    if changes.is_empty() {
+        say!("  All notes match the chain.");
+    } else {
+        for (id, amount, old, new) in &changes {
+            say!(
+                "  {} │ {} SOL │ {} → {}",
+                style(id).cyan(),
+                style(format!("{:.4}", *amount as f64 / 1e9)).white(),
+                style(old).dim(),
+                style(new).yellow()
+            );
+        }
+    }
+    if skipped > 0 {
+        say!("  {} note(s) on other networks or pools were not checked", skipped);
+    }
+    say!();
+
+    if dry_run {
+        say!("{} Dry run: {} change(s) not saved", style("ℹ️").cyan(), changes.len());
+    } else {
+        // Leaf indices may have been filled in even without status changes
+        store.save()?;
+        say!("{} {} note(s) updated", style("✅").green(), changes.len());
+    }
+    Ok(())
+}
+
+/// Report where each note stands on chain without touching the store;
+/// `notes sync` writes the same findings back
+fn cmd_scan(client: &dyn PoolRpc) -> Result<ScanResult> {
+    let config = pool::config();
+    let network = pool::network_name(&client.url());
+    let pool_id = config.tree_account.to_string();
+    let store = NoteStore::load()?;
+    let notes: Vec<&Note> = store.notes.iter().filter(|n| n.is_on(network, &pool_id)).collect();
+
+    say!("{}", style("🔍 Scan").bold());
+    say!("{}", style("─".repeat(50)).dim());
+
+    let leaves = fetch_leaves_from_chain(client, &config)?;
+    let commitments: Vec<_> = leaves.iter().map(|leaf| leaf.commitment).collect();
+    let tree = CommitmentSet::from_leaves(&commitments);
+
+    let signatures: Vec<String> = notes.iter().filter_map(|n| n.deposit_tx_sig.clone()).collect();
+    let outcomes = chain::deposit_outcomes(client, &signatures)?;
+
+    let mut scanned = Vec::new();
+    for note in &notes {
+        let leaf_index = tree.leaf_index(&crypto::str_to_fr(&note.commitment)?);
+        let spent = match leaf_index {
+            Some(index) => chain::is_note_spent(client, &config, note, index)?,
+            None => Some(false),
+        };
+        let deposit = note
+            .deposit_tx_sig
+            .as_ref()
+            .and_then(|sig| outcomes.get(sig).copied())
+            .unwrap_or(chain::DepositOutcome::Unknown);
+        scanned.push(ScannedNote {
+            note_id: note.id.clone(),
+            amount_lamports: note.amount,
+            leaf_index,
+            state: spent.map_or(chain::NoteState::WatchOnly, |spent| {
+                chain::NoteState::new(leaf_index.is_some(), spent, deposit)
+            }),
+            status: note.status.clone(),
+        });
+    }
+
+    let total = |states: &[chain::NoteState]| -> u64 {
+        scanned
+            .iter()
+            .filter(|n| states.contains(&n.state))
+            .map(|n| n.amount_lamports)
+            .sum()
+    };
+    let result = ScanResult {
+        deposited_lamports: total(&[
+            chain::NoteState::Spendable,
+            chain::NoteState::Spent,
+            chain::NoteState::WatchOnly,
+        ]),
+        withdrawn_lamports: total(&[chain::NoteState::Spent]),
+        spendable_lamports: total(&[chain::NoteState::Spendable]),
+        notes: scanned,
+    };
+
+    if result.notes.is_empty() {
+        say!("  No notes on this network and pool.");
+    }
+    for note in &result.notes {
+        let leaf = note.leaf_index.map_or("-".to_string(), |i| i.to_string());
+        let state = match note.state {
+            chain::NoteState::Spendable => style(note.state.as_str()).green(),
+            chain::NoteState::Spent => style(note.state.as_str()).dim(),
+            chain::NoteState::WatchOnly => style(note.state.as_str()).yellow(),
+            _ => style(note.state.as_str()).red(),
+        };
+        say!(
+            "  {} │ {} SOL │ leaf {:>5} │ {}",
+            style(&note.note_id).cyan(),
+            style(format!("{:.4}", note.amount_lamports as f64 / 1e9)).white(),
+            leaf,
+            state
+        );
+    }
+    let skipped = store.notes.len() - notes.len();
+    if skipped > 0 {
+        say!("  {} note(s) on other networks or pools were not checked", skipped);
+    }
+    say!("{}", style("─".repeat(50)).dim());
+    say!("  Deposited:  {:.4} SOL", result.deposited_lamports as f64 / 1e9);
+    say!("  Withdrawn:  {:.4} SOL", result.withdrawn_lamports as f64 / 1e9);
+    say!(
+        "  Spendable:  {}",
+        style(format!("{:.4} SOL", result.spendable_lamports as f64 / 1e9)).green().bold()
+    );
+
+    let failed = result
+        .notes
+        .iter()
+        .filter(|n| matches!(n.state, chain::NoteState::DepositFailed))
+        .count();
+    if failed > 0 {
+        say!();
+        say!(
+            "{} {} note(s) were never deposited; `privacy notes sync` marks them invalid",
+            style("⚠️").yellow(),
+            failed
+        );
+    }
+    let stale = result
+        .notes
+        .iter()
+        .filter(|n| match n.state {
+            chain::NoteState::Spendable => n.status != "deposited",
+            chain::NoteState::Spent => n.status != "withdrawn",
+            _ => false,
+        })
+        .count();
+    if stale > 0 {
+        say!(
+            "{} {} note status(es) in the store are out of date; run `privacy notes sync`",
+            style("ℹ️").cyan(),
+            stale
+        );
+    }
+    say!();
+
+    Ok(result)
+}
+
+/// Trial-decrypt the memos of the pool's transactions with the store's
+/// shielded keys, and import every note they announce that isn't in the
+/// store yet
+///
+/// Anyone can put a memo in a pool transaction, so a memo only counts if
+/// the amount and blinding in it make one of the commitments its own
+/// transaction inserted: a memo copied into another transaction, or
+/// claiming another amount, is rejected rather than imported.
+fn cmd_scan_incoming(
+    client: &dyn PoolRpc,
+    mint: Option<Pubkey>,
+    watch_only: Option<ViewingKey>,
+) -> Result<IncomingScanResult> {
+    let mut store = NoteStore::load()?;
+    // With a viewing key, notes are found but can't be spent, nor told spent
+    let (view, keys) = match watch_only {
+        Some(view) => (view, None),
+        None => {
+            let keys = store.shielded_keys.clone().ok_or_else(|| {
+                anyhow!("This note store has no shielded address yet; `privacy address` creates one")
+            })?;
+            (keys.viewing_key()?, Some(keys))
+        }
+    };
+    let base_config = pool::config();
+    let config = match &mint {
+        Some(mint) => base_config.for_spl(base_config.spl_pool(mint)?),
+        None => base_config,
+    };
+    let mint_fr = mint.as_ref().map_or_else(sol_mint, crypto::spl_mint);
+    let network = pool::network_name(&client.url());
+    let token = Token::load(client, mint)?;
+
+    say!("{}", style("🔍 Scan (incoming)").bold());
+    say!("{}", style("─".repeat(50)).dim());
+
+    let pb = interrupt::spinner();
+    pb.set_message("Fetching pool transactions...");
+    let memos = chain::fetch_memos(client, &config)?;
+
+    let mut result = IncomingScanResult { imported: Vec::new(), known: 0, rejected: 0 };
+    for memo in memos.iter().filter(|memo| MemoNote::is_memo(&memo.memo)) {
+        pb.set_message(format!("Checking memo of {}...", memo.signature));
+        let found = match MemoNote::open(&memo.memo, &view) {
+            Ok(Some(note)) => note.find(&view, mint_fr, memo)?,
+            // Sealed to someone else
+            Ok(None) => continue,
+            Err(_) => None,
+        };
+        let Some(found) = found else {
+            result.rejected += 1;
+            continue;
+        };
+        let commitment = crypto::fr_to_str(&found.commitment);
+        // Also covers the same memo seen twice
+        if store.notes.iter().any(|n| n.commitment == commitment) {
+            result.known += 1;
+            continue;
+        }
+
+        let (privkey, spent) = match &keys {
+            Some(keys) => {
+                let utxo = found.to_utxo(keys, mint_fr)?;
+                let spent = is_spent(client, &config, &utxo, found.leaf_index)?;
+                (Some(utxo.privkey), Some(spent))
+            }
+            None => (None, None),
+        };
+        let leaf_index = found.leaf_index;
+        let note = Note {
+            id: notes::generate_note_id(),
+            amount: found.amount,
+            privkey,
+            pubkey: crypto::fr_to_str(&view.pubkey),
+            blinding: crypto::fr_to_str(&found.blinding).into(),
+            commitment,
+            leaf_index: leaf_index as i64,
+            status: if spent == Some(true) { "withdrawn" } else { "deposited" }.to_string(),
+            created_at: chrono::Utc::now().timestamp() as u64,
+            deposit_tx_sig: Some(memo.signature.clone()),
+            withdraw_tx_sig: None,
+            network: network.to_string(),
+            pool: config.tree_account.to_string(),
+            derivation_index: None,
+            mint: mint.map(|mint| mint.to_string()),
+        };
+        result.imported.push(ScannedNote {
+            note_id: note.id.clone(),
+            amount_lamports: note.amount,
+            leaf_index: Some(leaf_index),
+            state: match spent {
+                Some(true) => chain::NoteState::Spent,
+                Some(false) => chain::NoteState::Spendable,
+                None => chain::NoteState::WatchOnly,
             },
+            status: note.status.clone(),
+        });
+        store.add(note)?;
+    }
+    pb.finish_and_clear();
+
+    for note in &result.imported {
+        say!(
+            "  {} Imported {} ({}, leaf {}{})",
+            style("✅").green(),
+            style(&note.note_id).cyan(),
+            token.format(note.amount_lamports),
+            note.leaf_index.unwrap_or_default(),
+            match note.state {
+                chain::NoteState::Spent => ", already withdrawn",
+                chain::NoteState::WatchOnly => ", watch-only",
+                _ => "",
+            }
         );
+    }
+    say!("{}", style("─".repeat(50)).dim());
+    say!(
+        "  {} new note(s), {} already in the store, from {} memo(s) in the pool's transactions",
+        result.imported.len(),
+        result.known,
+        memos.len()
+    );
+    if result.rejected > 0 {
+        say!(
+            "{} {} memo(s) were malformed or didn't match their transaction, and were skipped",
+            style("⚠️").yellow(),
+            result.rejected
+        );
+    }
+    say!();
 
-        if let Ok(tx) = tx_result {
-            if let Some(meta) = tx.transaction.meta {
-                if meta.err.is_some() {
-                    continue;
-                }
+    Ok(result)
+}
+
+/// Human-readable duration, e.g. `3d 4h`
+fn format_age(secs: u64) -> String {
+    let (days, hours, minutes) = (secs / 86_400, secs % 86_400 / 3600, secs % 3600 / 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// Seed for deterministic note keys, from the wallet's ed25519 secret key
+fn note_seed(keypair: &Keypair) -> Result<NoteSeed> {
+    let mut secret = [0u8; 32];
+    secret.copy_from_slice(&keypair.to_bytes()[..32]);
+    Ok(NoteSeed::from_secret_key(&secret)?)
+}
+
+/// Rebuild deterministic notes by matching derived commitments against the
+/// tree. Amounts aren't stored anywhere, so every deposit amount seen on
+/// chain is tried for each index.
+async fn cmd_scan_from_seed(client: &dyn PoolRpc, keypair: &Keypair, count: u64) -> Result<()> {
+    say!("{}", style("🔍 Scan (from seed)").bold());
+    say!("{}", style("─".repeat(40)).dim());
+    say!("  Wallet:   {}", style(keypair.pubkey().to_string()).dim());
+    say!("  Indices:  0..{}", count);
+    say!();
+
+    let pb = interrupt::spinner();
+    let seed = note_seed(keypair)?;
+    let mut store = NoteStore::load()?;
+    let mut recovered = 0;
+    let mut known = 0;
+
+    // The SOL pool's tree, then each token pool's: a deterministic token
+    // deposit is derived with its mint into its own pool's tree
+    let base_config = pool::config();
+    let trees = std::iter::once((base_config.clone(), None)).chain(
+        base_config.spl_pools.iter().map(|spl| (base_config.for_spl(spl), Some(spl.mint))),
+    );
+    for (config, mint) in trees {
+        let (found, already) =
+            scan_tree_from_seed(client, &config, mint, &seed, count, &mut store, &pb)?;
+        recovered += found;
+        known += already;
+    }
+    store.save()?;
+
+    pb.finish_and_clear();
+    say!(
+        "{} Recovered {} notes ({} already in the store)",
+        style("✅").green(),
+        style(recovered).yellow(),
+        known
+    );
+    say!();
+
+    Ok(())
+}
+
+/// Recover the notes of indices `0..count` that `seed` deposited into
+/// `config`'s tree, of `mint`'s tokens or SOL, into `store`. Returns how many
+/// were recovered and how many the store already had.
+fn scan_tree_from_seed(
+    client: &dyn PoolRpc,
+    config: &PoolConfig,
+    mint: Option<Pubkey>,
+    seed: &NoteSeed,
+    count: u64,
+    store: &mut NoteStore,
+    pb: &ProgressBar,
+) -> Result<(usize, usize)> {
+    let network = pool::network_name(&client.url());
+    pb.set_message("Fetching tree leaves from chain...");
+    let leaves = fetch_leaves_from_chain(client, config)?;
+    let commitments: Vec<_> = leaves.iter().map(|leaf| leaf.commitment).collect();
+    let tree_leaves = CommitmentSet::from_leaves(&commitments);
+
+    let mut amounts: Vec<u64> = leaves
+        .iter()
+        .filter(|leaf| leaf.public_amount > 0)
+        .map(|leaf| leaf.public_amount as u64)
+        .collect();
+    amounts.sort_unstable();
+    amounts.dedup();
+
+    let mint_fr = mint.as_ref().map_or_else(sol_mint, crypto::spl_mint);
+    let mut recovered = 0;
+    let mut known = 0;
+
+    for index in 0..count {
+        pb.set_message(format!("Checking note index {}...", index));
+        for &amount in &amounts {
+            let utxo = Utxo::derive(amount, mint_fr, seed, index)?;
+            let Some(leaf_index) = tree_leaves.leaf_index(&crypto::str_to_fr(&utxo.commitment)?)
+            else {
+                continue;
+            };
+
+            if store.notes.iter().any(|n| n.commitment == utxo.commitment) {
+                known += 1;
+                break;
             }
 
-            // Parse transaction to extract commitments
-            // This is simplified - in production you'd parse the full tx
-            if let Some(tx_data) = tx.transaction.transaction.decode() {
-                for ix in tx_data.message.instructions() {
-                    let data = ix.data.as_slice();
-                    if data.len() >= 424 && data[0..8] == discriminator {
-                        // commitment1 at offset 360, commitment2 at offset 392
-                        let c1_bytes = &data[360..392];
-                        let c2_bytes = &data[392..424];
-
-                        if let (Ok(c1), Ok(c2)) = (
-                            bytes_to_fr(c1_bytes),
-                            bytes_to_fr(c2_bytes),
-                        ) {
-                            commitments.push(c1);
-                            commitments.push(c2);
-                        }
+            let spent = is_spent(client, config, &utxo, leaf_index)?;
+
+            store.notes.push(Note {
+                id: notes::generate_note_id(),
+                amount,
+                privkey: Some(utxo.privkey),
+                pubkey: utxo.pubkey,
+                blinding: utxo.blinding,
+                commitment: utxo.commitment,
+                leaf_index: leaf_index as i64,
+                status: if spent { "withdrawn" } else { "deposited" }.to_string(),
+                created_at: chrono::Utc::now().timestamp() as u64,
+                deposit_tx_sig: Some(leaves[leaf_index].signature.clone()),
+                withdraw_tx_sig: None,
+                network: network.to_string(),
+                pool: config.tree_account.to_string(),
+                derivation_index: Some(index),
+                mint: mint.map(|m| m.to_string()),
+            });
+            recovered += 1;
+            break;
+        }
+    }
+
+    Ok((recovered, known))
+}
+
+async fn cmd_artifacts(
+    action: &ArtifactsAction,
+    file: &CliConfig,
+    flag: Option<&str>,
+    artifacts_path: &str,
+) -> Result<()> {
+    match action {
+        ArtifactsAction::Download { url, force } => {
+            let urls = file.artifact_urls(url);
+            let dir = artifacts::managed_dir()?;
+            say!("{}", style("📦 Download artifacts").bold());
+            say!("{}", style("─".repeat(40)).dim());
+            say!("  Into:  {}", style(dir.display()).cyan());
+            say!("  From:  {}", style(urls.join(", ")).dim());
+            say!();
+            artifacts::download(&urls, &dir, *force).await?;
+            if dir.as_path() != Path::new(artifacts_path) {
+                say!();
+                say!(
+                    "{}",
+                    style(format!(
+                        "⚠️  --artifacts or the config file points at {}; unset it to use these",
+                        artifacts_path
+                    ))
+                    .yellow()
+                );
+            }
+        }
+        ArtifactsAction::Verify => {
+            say!("{}", style("🔍 Verify artifacts").bold());
+            say!("{}", style("─".repeat(40)).dim());
+            say!("  In:  {}", style(artifacts_path).cyan());
+            let mut ok = true;
+            for (name, check) in artifacts::verify(Path::new(artifacts_path))? {
+                match check {
+                    artifacts::Check::Ok => say!("  {} {}", style("✓").green(), name),
+                    artifacts::Check::Missing => {
+                        ok = false;
+                        say!("  {} {} missing", style("✗").red(), name);
+                    }
+                    artifacts::Check::Mismatch { actual } => {
+                        ok = false;
+                        say!(
+                            "  {} {} has sha256 {}, not the pinned one",
+                            style("✗").red(),
+                            name,
+                            actual
+                        );
                     }
                 }
             }
+            if !ok {
+                return Err(anyhow!(
+                    "Artifacts in {} failed verification; run 'privacy artifacts download --force'",
+                    artifacts_path
+                ));
+            }
+        }
+        ArtifactsAction::Path => {
+            say!("{}", style("📂 Artifact search order").bold());
+            say!("{}", style("─".repeat(40)).dim());
+            let configured = [
+                ("--artifacts / PRIVACY_ARTIFACTS", flag.map(str::to_string)),
+                ("config file artifacts_path", file.artifacts_path.clone()),
+            ];
+            let searched = artifacts::search_paths()
+                .into_iter()
+                .map(|dir| ("search", Some(dir.display().to_string())));
+            for (i, (source, dir)) in configured.into_iter().chain(searched).enumerate() {
+                let dir = dir.unwrap_or_else(|| "(not set)".to_string());
+                let found = artifacts::missing(Path::new(&dir)).is_empty();
+                say!(
+                    "  {}. {:<32} {} {}",
+                    i + 1,
+                    source,
+                    if dir == artifacts_path { style(&dir).cyan() } else { style(&dir).dim() },
+                    if found { "✓" } else { "" }
+                );
+            }
+            say!();
+            say!("  Using: {}", style(artifacts_path).cyan());
+            output::emit(&serde_json::json!({ "artifacts_path": artifacts_path }))?;
         }
     }
+    Ok(())
+}
+
+async fn cmd_info(rpc: &dyn PoolRpc, keypair: &Keypair) -> Result<()> {
+    let config = pool::config();
+
+    say!("{}", style("ℹ️  Program Info").bold());
+    say!("{}", style("─".repeat(50)).dim());
+    say!("  Program ID:     {}", style(config.program_id).cyan());
+    say!("  Tree Account:   {}", style(config.tree_account.to_string()).dim());
+    say!("  Global Config:  {}", style(config.global_config.to_string()).dim());
+    say!("  Pool Vault:     {}", style(config.pool_vault.to_string()).dim());
+    say!();
+    say!("{}", style("⚙️  Pool Parameters").bold());
+    say!("{}", style("─".repeat(50)).dim());
+    let stats = chain::pool_stats(rpc, &config)?;
+    let fees = chain::global_config(rpc, &config)?;
+    say!("  Deposit Fee:    {}", style(format_rate(fees.deposit_fee_rate)).yellow());
+    say!("  Withdrawal Fee: {}", style(format_rate(fees.withdrawal_fee_rate)).yellow());
+    say!("  Max Deposit:    {} SOL", stats.max_deposit_lamports as f64 / 1e9);
+    say!("  Fee Recipient:  {}", style(fees.fee_recipient.to_string()).dim());
+    say!("  Authority:      {}", style(fees.authority.to_string()).dim());
+    say!();
+    say!("{}", style("👛 Wallet").bold());
+    say!("{}", style("─".repeat(50)).dim());
+    say!("  Address:  {}", style(keypair.pubkey().to_string()).cyan());
+
+    let balance = rpc.get_balance(&keypair.pubkey())?;
+    say!(
+        "  Balance:  {} SOL",
+        style(format!("{:.4}", balance as f64 / 1e9)).green()
+    );
+    say!();
 
-    Ok(commitments)
+    Ok(())
 }
 
-fn bytes_to_fr(bytes: &[u8]) -> Result<ark_bn254::Fr> {
-    use ark_ff::PrimeField;
-    if bytes.len() != 32 {
-        return Err(anyhow!("Invalid length"));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use privacy_zig_core::spl::associated_token_address;
+    use privacy_zig_core::{SplPool, TransactProofData};
+    use rpc::{Cassette, MockPoolRpc};
+    use solana_sdk::compute_budget::ComputeBudgetInstruction;
+
+    fn proof(root: [u8; 32]) -> TransactProofData {
+        TransactProofData {
+            proof_a: vec![1; 64],
+            proof_b: vec![2; 128],
+            proof_c: vec![3; 64],
+            root: root.to_vec(),
+            nullifier1: vec![5; 32],
+            nullifier2: vec![6; 32],
+            commitment1: vec![7; 32],
+            commitment2: vec![8; 32],
+            public_amount: -100_000_000,
+            ext_data_hash: vec![9; 32],
+        }
+    }
+
+    #[test]
+    fn test_direct_withdrawal_builds_transact_transaction() {
+        let cassette = Cassette::replay(format!(
+            "{}/tests/fixtures/cassettes/commitment_sync.json",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let rpc = MockPoolRpc::new(cassette, Some(200_000));
+        let config = PoolConfig::testnet();
+        let keypair = Keypair::new();
+        let recipient = Pubkey::new_unique();
+
+        // The tree is rebuilt from canned chain history, as in `cmd_withdraw`
+        let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+        tree.insert_many(&fetch_commitments_from_chain(&rpc, &config).unwrap()).unwrap();
+        let proof = proof(crypto::fr_to_be_bytes(&tree.root()));
+
+        let budget = ComputeBudget { unit_limit: None, unit_price: 5_000 };
+        let pb = ProgressBar::hidden();
+        let transact_ix =
+            transact_instruction(&config, None, &proof, &keypair.pubkey(), &recipient).unwrap();
+        let signature = submit_direct(&rpc, &keypair, transact_ix, budget, &pb).unwrap();
+
+        let sent = rpc.sent();
+        assert_eq!(sent.len(), 1);
+        let tx = &sent[0];
+        assert_eq!(signature, tx.signatures[0].to_string());
+        assert_eq!(tx.message.recent_blockhash, rpc.blockhash());
+        tx.verify().unwrap();
+
+        // Simulated once at the maximum limit, then sized from what it used
+        let simulated = rpc.simulated();
+        assert_eq!(simulated.len(), 1);
+        assert_eq!(
+            simulated[0].message.instructions[0].data,
+            ComputeBudgetInstruction::set_compute_unit_limit(COMPUTE_UNIT_LIMIT).data
+        );
+        let keys = &tx.message.account_keys;
+        let instructions = &tx.message.instructions;
+        assert_eq!(instructions.len(), 3);
+        let unit_limit = sized_compute_unit_limit(Some(200_000));
+        for (ix, expected) in instructions.iter().zip([
+            ComputeBudgetInstruction::set_compute_unit_limit(unit_limit),
+            ComputeBudgetInstruction::set_compute_unit_price(5_000),
+        ]) {
+            assert_eq!(keys[ix.program_id_index as usize], expected.program_id);
+            assert_eq!(ix.data, expected.data);
+        }
+
+        let transact = &instructions[2];
+        assert_eq!(keys[transact.program_id_index as usize], config.program_id);
+        assert_eq!(transact.data, proof.to_instruction_data());
+        assert_eq!(transact.data.len(), 464);
+        assert_eq!(transact.data[264..296], proof.root);
+        assert_eq!(transact.data[296..328], [5; 32]);
+        assert_eq!(transact.data[328..360], [6; 32]);
+
+        let accounts: Vec<Pubkey> = transact.accounts.iter().map(|&i| keys[i as usize]).collect();
+        assert_eq!(accounts.len(), TRANSACT_ACCOUNT_NAMES.len());
+        assert_eq!(accounts[0], config.tree_account);
+        assert_eq!(accounts[1], config.nullifier_pda(&[5; 32]));
+        assert_eq!(accounts[2], config.nullifier_pda(&[6; 32]));
+        assert_eq!(accounts[3], config.global_config);
+        assert_eq!(accounts[4], config.pool_vault);
+        assert_eq!(accounts[5], keypair.pubkey());
+        assert_eq!(accounts[6], recipient);
+        assert_eq!(accounts[7], config.fee_recipient);
+        assert_eq!(tx.message.account_keys[0], keypair.pubkey());
+    }
+
+    #[test]
+    fn test_token_withdrawal_pays_token_accounts() {
+        let config = PoolConfig::testnet();
+        let pool = SplPool {
+            mint: Pubkey::new_unique(),
+            tree_account: Pubkey::new_unique(),
+            token_pool: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+        };
+        let (signer, recipient) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let proof = proof([4; 32]);
+
+        let ix = transact_instruction(&config, Some(&pool), &proof, &signer, &recipient).unwrap();
+        assert_eq!(ix.data[..8], TRANSACT_SPL_DISCRIMINATOR);
+        assert_eq!(ix.accounts.len(), TRANSACT_SPL_ACCOUNT_NAMES.len());
+        let account = |name| {
+            let index = TRANSACT_SPL_ACCOUNT_NAMES.iter().position(|n| *n == name).unwrap();
+            ix.accounts[index].pubkey
+        };
+        assert_eq!(account("tree"), pool.tree_account);
+        assert_eq!(account("nullifier1"), config.nullifier_pda(&[5; 32]));
+        assert_eq!(account("token_account"), associated_token_address(&recipient, &pool.mint));
+        assert_eq!(account("vault"), pool.vault);
+        assert_eq!(account("signer"), signer);
+        assert_eq!(
+            account("fee_token_acct"),
+            associated_token_address(&config.fee_recipient, &pool.mint)
+        );
+
+        // Without a pool it's the SOL transact
+        let ix = transact_instruction(&config, None, &proof, &signer, &recipient).unwrap();
+        assert_eq!(ix.data, proof.to_instruction_data());
+        assert_eq!(ix.accounts.len(), TRANSACT_ACCOUNT_NAMES.len());
+    }
+
+    #[test]
+    fn test_scan_from_seed_recovers_token_notes() {
+        let config = PoolConfig::testnet();
+        let spl = SplPool {
+            mint: Pubkey::new_unique(),
+            tree_account: Pubkey::new_unique(),
+            token_pool: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+        };
+        let spl_config = config.for_spl(&spl);
+        let seed = note_seed(&Keypair::new()).unwrap();
+
+        // Note 2 of the seed, deposited into the token pool's tree as
+        // `deposit --mint --deterministic` does
+        let utxo = Utxo::derive(5_000_000, crypto::spl_mint(&spl.mint), &seed, 2).unwrap();
+        let mut deposit = proof([0; 32]);
+        let commitment = crypto::str_to_fr(&utxo.commitment).unwrap();
+        deposit.commitment1 = crypto::fr_to_be_bytes(&commitment).to_vec();
+        deposit.public_amount = 5_000_000;
+        let mut data = deposit.to_instruction_data();
+        data[..8].copy_from_slice(&TRANSACT_SPL_DISCRIMINATOR);
+        let nullifier = utxo.compute_nullifier(0).unwrap();
+        let cassette = serde_json::json!({
+            "url": "https://api.testnet.solana.com",
+            "interactions": [
+                {
+                    "method": "get_signatures_for_address",
+                    "params": [spl.tree_account.to_string()],
+                    "result": ["deposit"],
+                },
+                {
+                    "method": "get_transaction",
+                    "params": ["deposit"],
+                    "result": {
+                        "failed": false,
+                        "block_time": null,
+                        "instructions": [
+                            { "program_id": config.program_id.to_string(), "data": BASE64.encode(&data) },
+                        ],
+                    },
+                },
+                {
+                    "method": "get_account",
+                    "params": [spl_config.nullifier_pda(&crypto::fr_to_be_bytes(&nullifier)).to_string()],
+                    "result": null,
+                },
+            ],
+        });
+        let rpc = Cassette::from_json(&cassette.to_string()).unwrap();
+
+        let mut store = NoteStore::default();
+        let pb = ProgressBar::hidden();
+        let found =
+            scan_tree_from_seed(&rpc, &spl_config, Some(spl.mint), &seed, 4, &mut store, &pb).unwrap();
+        assert_eq!(found, (1, 0));
+        let note = &store.notes[0];
+        assert_eq!(note.commitment, utxo.commitment);
+        assert_eq!(note.amount, 5_000_000);
+        assert_eq!(note.leaf_index, 0);
+        assert_eq!(note.status, "deposited");
+        assert_eq!(note.derivation_index, Some(2));
+        assert_eq!(note.pool, spl.tree_account.to_string());
+        assert_eq!(note.mint, Some(spl.mint.to_string()));
+        assert_eq!(note.mint_fr().unwrap(), crypto::spl_mint(&spl.mint));
+        assert_eq!(note.deposit_tx_sig.as_deref(), Some("deposit"));
+    }
+
+    #[test]
+    fn test_corrupted_notes_are_explained() {
+        let invalid = || crypto::parse_field("blinding", "12abc").unwrap_err();
+        let from_store = anyhow::Error::from(invalid()).context("Note 1a2b has an invalid blinding");
+        let from_prover = anyhow::Error::from(ProverError::from(invalid()));
+        for e in [from_store, from_prover] {
+            let message = format!("{:#}", explain(e));
+            assert!(message.starts_with("Your note data is corrupted"), "{}", message);
+            assert!(message.contains("Invalid blinding: not a decimal integer"), "{}", message);
+        }
+
+        let other = explain(anyhow!("Insufficient balance"));
+        assert_eq!(format!("{:#}", other), "Insufficient balance");
+        assert!(explain(interrupt::Cancelled.into()).is::<interrupt::Cancelled>());
     }
-    // Convert from big-endian
-    let mut le_bytes = bytes.to_vec();
-    le_bytes.reverse();
-    Ok(ark_bn254::Fr::from_le_bytes_mod_order(&le_bytes))
 }
@@ -1,59 +1,756 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use ark_bn254::Fr;
+use fs2::FileExt;
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::PathBuf;
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::crypto::{self, SecretString, Utxo};
+use crate::shielded::ShieldedKeys;
+use crate::store_key::{self, Envelope, PassphraseError, StoreKey};
+
+/// How long to wait for another `privacy` process to release the note store
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `--notes-file`, replacing `~/.privacy-zig/notes.json` for the whole run
+static NOTES_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// The config file's `backup_dir`, which every save copies the store into
+static BACKUP_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Schema version written by this build. Files without a `version` field
+/// predate versioning and are treated as v1.
+pub const CURRENT_VERSION: u32 = 6;
+
+/// Upgrade steps: `MIGRATIONS[i]` turns a v`i + 1` store into v`i + 2`
+const MIGRATIONS: [fn(&mut Value) -> Result<()>; (CURRENT_VERSION - 1) as usize] =
+    [migrate_v1_to_v2, migrate_v2_to_v3, migrate_v3_to_v4, migrate_v4_to_v5, migrate_v5_to_v6];
+
+/// Network and pool of notes written before notes were tagged: every
+/// earlier release only talked to the testnet deployment
+const LEGACY_NETWORK: &str = "testnet";
+const LEGACY_POOL: &str = privacy_zig_core::TREE_ACCOUNT;
+
+/// v1 -> v2: the layout is unchanged, v2 only adds the `version` field
+fn migrate_v1_to_v2(_store: &mut Value) -> Result<()> {
+    Ok(())
+}
+
+/// v2 -> v3: tag every note with the network and pool it belongs to
+fn migrate_v2_to_v3(store: &mut Value) -> Result<()> {
+    let notes = store
+        .get_mut("notes")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| anyhow!("missing notes array"))?;
+    notes.iter_mut().for_each(tag_legacy_note);
+    Ok(())
+}
+
+/// v3 -> v4: notes gain `derivation_index`; existing ones have random keys
+fn migrate_v3_to_v4(store: &mut Value) -> Result<()> {
+    let notes = store
+        .get_mut("notes")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| anyhow!("missing notes array"))?;
+    for note in notes.iter_mut().filter_map(Value::as_object_mut) {
+        note.entry("derivation_index").or_insert(Value::Null);
+    }
+    Ok(())
+}
+
+/// v4 -> v5: the store may hold `shielded_keys`; bumped so that a build
+/// which doesn't know them refuses the file rather than dropping them
+fn migrate_v4_to_v5(_store: &mut Value) -> Result<()> {
+    Ok(())
+}
+
+/// v5 -> v6: notes found with a viewing key have a null `privkey`; bumped
+/// so that a build which requires one refuses the file
+fn migrate_v5_to_v6(_store: &mut Value) -> Result<()> {
+    Ok(())
+}
+
+/// Give an untagged note (old store or old export) the legacy network
+fn tag_legacy_note(note: &mut Value) {
+    if let Some(note) = note.as_object_mut() {
+        note.entry("network").or_insert_with(|| LEGACY_NETWORK.into());
+        note.entry("pool").or_insert_with(|| LEGACY_POOL.into());
+    }
+}
+
+/// notes.json was written by a newer privacy-zig than this one
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "this notes.json was written by a newer privacy-zig (schema v{found}, \
+     this build reads up to v{}); upgrade privacy-zig to use it",
+    CURRENT_VERSION
+)]
+pub struct NewerSchemaError {
+    pub found: u32,
+}
+
+/// A note found with a viewing key was asked to be spent
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "note {0} is watch-only: it was found with a viewing key, which can't spend it. \
+     Spend it from the wallet holding the address's spending key"
+)]
+pub struct WatchOnlyNote(pub String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Note {
     pub id: String,
     pub amount: u64,
-    pub privkey: String,
+    /// UTXO private key; `None` for a watch-only note, found with a viewing
+    /// key (see `shielded::ViewingKey`)
+    #[serde(default)]
+    pub privkey: Option<SecretString>,
     pub pubkey: String,
-    pub blinding: String,
+    pub blinding: SecretString,
     pub commitment: String,
     pub leaf_index: i64,
     pub status: String,
     pub created_at: u64,
     pub deposit_tx_sig: Option<String>,
     pub withdraw_tx_sig: Option<String>,
+    /// Cluster the note was deposited on (`testnet`, `devnet`, ...)
+    pub network: String,
+    /// Tree account of the pool holding the commitment
+    pub pool: String,
+    /// Index of a note whose keys are derived from the wallet (see
+    /// `crypto::NoteSeed`); `None` for notes with random keys
+    pub derivation_index: Option<u64>,
+    /// SPL token mint of a token note, whose `amount` is in the mint's base
+    /// units; `None` for SOL, as for every note written before tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mint: Option<String>,
+}
+
+/// How `notes import` treats a note whose commitment is already in the store
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ImportMerge {
+    /// Keep the note already in the store
+    #[default]
+    Skip,
+    /// Replace it with the imported note
+    Overwrite,
+    /// Replace it if the imported note was created later
+    Newer,
+}
+
+/// File format of `notes export`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// Every field of every note; `notes import` reads it back
+    #[default]
+    Json,
+    /// One row per note for spreadsheets and accounting tools; can't be
+    /// imported
+    Csv,
+}
+
+/// Columns of a CSV export, then those `--include-secrets` adds
+const CSV_COLUMNS: [&str; 7] =
+    ["id", "amount", "mint", "status", "created_at", "deposit_tx_sig", "withdraw_tx_sig"];
+const CSV_SECRET_COLUMNS: [&str; 2] = ["privkey", "blinding"];
+
+/// Statuses a note can have, from deposit to withdrawal
+pub const NOTE_STATUSES: [&str; 4] = ["deposited", "withdrawn", "deposit_failed", "invalid"];
+
+/// Order of `notes list`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum NoteSort {
+    /// Oldest first
+    #[default]
+    Date,
+    /// Smallest first
+    Amount,
+    /// Alphabetical by status
+    Status,
+}
+
+/// Which notes `notes list` shows, and in what order
+#[derive(Debug, Clone, Default)]
+pub struct NoteFilter {
+    pub status: Option<String>,
+    /// Bounds in lamports; with either, only SOL notes are shown, as token
+    /// amounts aren't in SOL
+    pub min_amount: Option<u64>,
+    pub max_amount: Option<u64>,
+    pub sort: NoteSort,
+    pub reverse: bool,
+    /// Show at most this many, after sorting
+    pub limit: Option<usize>,
+    /// Only watch-only notes
+    pub watch_only: bool,
+}
+
+/// What `notes import` did with the entries of a file
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ImportSummary {
+    /// Notes that weren't in the store
+    pub imported: usize,
+    /// Notes in the store that were replaced
+    pub updated: usize,
+    /// Notes in the store that were kept as they were
+    pub skipped: usize,
+    pub invalid: Vec<InvalidNote>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// An entry of an export that was left out, and why
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct InvalidNote {
+    /// Position in the file, from 0
+    pub index: usize,
+    pub id: Option<String>,
+    pub reason: String,
+}
+
+/// What `notes list --json` shows of a note: everything but its keys
+#[derive(Debug, Serialize)]
+pub struct NoteListing<'a> {
+    pub id: &'a str,
+    pub amount: u64,
+    pub commitment: &'a str,
+    pub leaf_index: i64,
+    pub status: &'a str,
+    pub created_at: u64,
+    pub deposit_tx_sig: Option<&'a str>,
+    pub withdraw_tx_sig: Option<&'a str>,
+    pub network: &'a str,
+    pub pool: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mint: Option<&'a str>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub watch_only: bool,
+}
+
+/// What `notes show` shows of a note: its listing, its public key, and its
+/// private key and blinding only when asked
+#[derive(Debug, Serialize)]
+pub struct NoteDetail<'a> {
+    #[serde(flatten)]
+    pub listing: NoteListing<'a>,
+    /// `created_at` in local time
+    pub created: String,
+    pub pubkey: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub privkey: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blinding: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub derivation_index: Option<u64>,
+    pub deposit_tx_url: Option<String>,
+    pub withdraw_tx_url: Option<String>,
+}
+
+/// A Unix timestamp as a date and time in `tz`
+pub fn format_timestamp<Tz: chrono::TimeZone>(secs: u64, tz: &Tz) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    match i64::try_from(secs).ok().and_then(|secs| chrono::DateTime::from_timestamp(secs, 0)) {
+        Some(time) => time.with_timezone(tz).format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+        None => format!("{} (invalid timestamp)", secs),
+    }
+}
+
+impl Note {
+    /// The note's detail, with the explorer link `explorer` makes for each
+    /// of its transactions; the private key and blinding only if `reveal`
+    pub fn detail(&self, reveal: bool, explorer: impl Fn(&str) -> String) -> NoteDetail<'_> {
+        NoteDetail {
+            listing: self.listing(),
+            created: format_timestamp(self.created_at, &chrono::Local),
+            pubkey: &self.pubkey,
+            privkey: self.privkey.as_deref().filter(|_| reveal),
+            blinding: reveal.then_some(self.blinding.as_str()),
+            derivation_index: self.derivation_index,
+            deposit_tx_url: self.deposit_tx_sig.as_deref().map(&explorer),
+            withdraw_tx_url: self.withdraw_tx_sig.as_deref().map(&explorer),
+        }
+    }
+
+    pub fn listing(&self) -> NoteListing<'_> {
+        NoteListing {
+            id: &self.id,
+            amount: self.amount,
+            commitment: &self.commitment,
+            leaf_index: self.leaf_index,
+            status: &self.status,
+            created_at: self.created_at,
+            deposit_tx_sig: self.deposit_tx_sig.as_deref(),
+            withdraw_tx_sig: self.withdraw_tx_sig.as_deref(),
+            network: &self.network,
+            pool: &self.pool,
+            mint: self.mint.as_deref(),
+            watch_only: self.is_watch_only(),
+        }
+    }
+
+    /// Whether the note was found with a viewing key and can't be spent
+    pub fn is_watch_only(&self) -> bool {
+        self.privkey.is_none()
+    }
+
+    /// The UTXO private key, which a watch-only note doesn't have
+    pub fn spending_key(&self) -> Result<&str, WatchOnlyNote> {
+        self.privkey.as_deref().ok_or_else(|| WatchOnlyNote(self.id.clone()))
+    }
+
+    /// The note as a UTXO to spend
+    pub fn utxo(&self) -> Result<Utxo> {
+        Ok(Utxo::from_values(
+            self.amount,
+            self.spending_key()?,
+            &self.pubkey,
+            &self.blinding,
+            self.mint_fr()?,
+        )?)
+    }
+
+    /// The SPL token mint the note holds, or `None` for SOL
+    pub fn mint(&self) -> Result<Option<Pubkey>> {
+        self.mint
+            .as_deref()
+            .map(|mint| {
+                Pubkey::from_str(mint)
+                    .map_err(|_| anyhow!("Note {} has an invalid mint {:?}", self.id, mint))
+            })
+            .transpose()
+    }
+
+    /// The mint as committed to: [`crypto::sol_mint`] or
+    /// [`crypto::spl_mint`]
+    pub fn mint_fr(&self) -> Result<Fr> {
+        Ok(match self.mint()? {
+            Some(mint) => crypto::spl_mint(&mint),
+            None => crypto::sol_mint(),
+        })
+    }
+
+    /// Reject spending the note as another token than the one it holds:
+    /// its commitment only opens with its own mint, and its pool is that
+    /// mint's
+    pub fn check_mint(&self, mint: Option<&Pubkey>) -> Result<()> {
+        let held = self.mint()?;
+        if held.as_ref() == mint {
+            return Ok(());
+        }
+        let name = |mint: Option<&Pubkey>| match mint {
+            Some(mint) => format!("tokens of mint {}", mint),
+            None => "SOL".to_string(),
+        };
+        let hint = match held {
+            Some(mint) => format!("pass --mint {}", mint),
+            None => "leave out --mint".to_string(),
+        };
+        Err(anyhow!(
+            "Note {} holds {}, not {}; {} to spend it",
+            self.id,
+            name(held.as_ref()),
+            name(mint),
+            hint
+        ))
+    }
+
+    /// Whether the note lives in the pool the CLI is pointed at
+    pub fn is_on(&self, network: &str, pool: &str) -> bool {
+        self.network == network && self.pool == pool
+    }
+
+    /// Check the keys and commitment are field elements, so a corrupt note
+    /// is rejected instead of being reduced into a different one
+    pub fn check_fields(&self) -> Result<()> {
+        let fields = [
+            ("privkey", self.privkey.as_deref()),
+            ("pubkey", Some(self.pubkey.as_str())),
+            ("blinding", Some(self.blinding.as_str())),
+            ("commitment", Some(self.commitment.as_str())),
+        ];
+        for (name, value) in fields.into_iter().filter_map(|(name, value)| Some((name, value?))) {
+            crypto::str_to_fr_strict(value)
+                .with_context(|| format!("Note {} has an invalid {}", self.id, name))?;
+        }
+        Ok(())
+    }
+
+    /// Check the commitment matches the amount and keys, so a tampered or
+    /// mismatched note is caught before it is imported or spent. A
+    /// watch-only note has no private key to check the pubkey against.
+    pub fn verify_commitment(&self) -> Result<()> {
+        self.check_fields()?;
+        let verified = match &self.privkey {
+            Some(privkey) => Utxo {
+                amount: self.amount,
+                pubkey: self.pubkey.clone(),
+                privkey: privkey.clone(),
+                blinding: self.blinding.clone(),
+                mint: crypto::fr_to_str(&self.mint_fr()?),
+                commitment: self.commitment.clone(),
+            }
+            .verify_commitment(),
+            None => {
+                let field = crypto::str_to_fr_strict;
+                let preimage = [Fr::from(self.amount), field(&self.pubkey)?, field(&self.blinding)?, self.mint_fr()?];
+                crypto::poseidon(&preimage)? == field(&self.commitment)?
+            }
+        };
+        if !verified {
+            return Err(anyhow!(
+                "Note {} is corrupt: its commitment doesn't match its amount and keys. \
+                 It may have been edited; restore it from a backup",
+                self.id
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reject spending the note anywhere but the pool it was deposited in
+    pub fn check_network(&self, network: &str, pool: &str) -> Result<()> {
+        if self.is_on(network, pool) {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "Note {} was deposited on {} (pool {}), but the CLI is pointed at {} (pool {}). \
+             Its commitment doesn't exist there; switch --rpc-url (and PRIVACY_POOL_* \
+             overrides) back to {} to spend it",
+            self.id,
+            self.network,
+            self.pool,
+            network,
+            pool,
+            self.network
+        ))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct NoteStore {
+    pub version: u32,
     pub notes: Vec<Note>,
+    /// Keys of the store's shielded address, created by the first
+    /// `privacy address`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shielded_keys: Option<ShieldedKeys>,
+    /// Held for as long as the store is alive so that concurrent processes
+    /// can't interleave load/modify/save and drop each other's notes
+    #[serde(skip)]
+    lock: Option<StoreLock>,
+    /// File the store was loaded from, which `save` writes back to
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// Key the store is encrypted with on disk, if it is
+    #[serde(skip)]
+    key: Option<StoreKey>,
+    /// Directory `save` copies the store into
+    #[serde(skip)]
+    backup_dir: Option<PathBuf>,
+}
+
+impl Default for NoteStore {
+    fn default() -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            notes: Vec::new(),
+            shielded_keys: None,
+            lock: None,
+            path: None,
+            key: None,
+            backup_dir: None,
+        }
+    }
+}
+
+/// Advisory lock on `notes.json.lock`, released on drop
+#[derive(Debug)]
+struct StoreLock {
+    file: File,
+}
+
+impl StoreLock {
+    /// Acquire the lock, waiting up to `LOCK_TIMEOUT` for another holder
+    fn acquire(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Failed to open lock file {}", path.display()))?;
+
+        let started = Instant::now();
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(Self { file }),
+                Err(e) if e.kind() == fs2::lock_contended_error().kind() => {
+                    if started.elapsed() >= LOCK_TIMEOUT {
+                        return Err(anyhow!(
+                            "note store is locked by another privacy process ({})",
+                            path.display()
+                        ));
+                    }
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => return Err(anyhow!("Failed to lock note store: {}", e)),
+            }
+        }
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// `notes.json` -> `notes.json<suffix>`
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
 }
 
 impl NoteStore {
-    /// Get the default notes file path
-    fn notes_path() -> Result<PathBuf> {
+    /// Directory holding the note store (`~/.privacy-zig`)
+    pub fn data_dir() -> Result<PathBuf> {
         let home = dirs::home_dir().ok_or_else(|| anyhow!("Could not find home directory"))?;
-        let dir = home.join(".privacy-zig");
-        
-        if !dir.exists() {
-            fs::create_dir_all(&dir)?;
+        Ok(home.join(".privacy-zig"))
+    }
+
+    /// Use `path` instead of `~/.privacy-zig/notes.json` for the rest of
+    /// the run
+    pub fn set_default_path(path: PathBuf) {
+        *NOTES_FILE.lock().unwrap_or_else(|e| e.into_inner()) = Some(path);
+    }
+
+    /// Copy the store into `dir` on every save, for the rest of the run
+    pub fn set_backup_dir(dir: Option<PathBuf>) {
+        *BACKUP_DIR.lock().unwrap_or_else(|e| e.into_inner()) = dir;
+    }
+
+    /// Directory every save copies the store into, if there is one
+    pub fn backup_dir() -> Option<PathBuf> {
+        BACKUP_DIR.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The note store [`load`](Self::load) reads: `--notes-file` if given,
+    /// otherwise `~/.privacy-zig/notes.json`
+    pub fn path() -> Result<PathBuf> {
+        match NOTES_FILE.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+            Some(path) => Ok(path.clone()),
+            None => Ok(Self::data_dir()?.join("notes.json")),
         }
-        
-        Ok(dir.join("notes.json"))
     }
 
     /// Load notes from disk
+    ///
+    /// The returned store holds the advisory lock until it is dropped. If
+    /// `notes.json` is unreadable, the previous version in `notes.json.bak`
+    /// is used instead. Stores in an older schema are migrated and written
+    /// back in the current one. An encrypted store is opened with
+    /// [`store_key::passphrase`].
     pub fn load() -> Result<Self> {
-        let path = Self::notes_path()?;
-        
-        if !path.exists() {
-            return Ok(Self::default());
+        Self::load_from(&Self::path()?)
+    }
+
+    /// [`load`](Self::load) the store at `path`, which [`save`](Self::save)
+    /// then writes back to; a missing file is an empty store
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let lock = StoreLock::acquire(&sibling(path, ".lock"))?;
+
+        let (mut store, migrated) = if !path.exists() {
+            (Self::default(), false)
+        } else {
+            match Self::read_file(path, None) {
+                Ok(loaded) => loaded,
+                // Falling back to an older backup would silently hide notes,
+                // and the backup has the same passphrase
+                Err(e)
+                    if e.downcast_ref::<NewerSchemaError>().is_some()
+                        || e.downcast_ref::<PassphraseError>().is_some() =>
+                {
+                    return Err(e)
+                }
+                Err(e) => {
+                    let backup = sibling(path, ".bak");
+                    if !backup.exists() {
+                        return Err(e);
+                    }
+                    eprintln!(
+                        "Warning: {} is corrupted ({:#}); loading backup {}",
+                        path.display(),
+                        e,
+                        backup.display()
+                    );
+                    Self::read_file(&backup, None)?
+                }
+            }
+        };
+
+        store.lock = Some(lock);
+        store.path = Some(path.to_path_buf());
+        store.backup_dir = Self::backup_dir();
+        if migrated {
+            store.save()?;
         }
-        
-        let data = fs::read_to_string(&path)?;
-        let store: NoteStore = serde_json::from_str(&data)?;
         Ok(store)
     }
 
+    /// Read and parse a store, returning whether it needed migrating. An
+    /// encrypted one is opened with `key`, or else the key of the store
+    /// passphrase.
+    fn read_file(path: &Path, key: Option<&StoreKey>) -> Result<(Self, bool)> {
+        // The file holds every note's keys
+        let data = Zeroizing::new(
+            fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?,
+        );
+        let Some(envelope) = Envelope::parse(&data) else {
+            return Self::parse(&data).with_context(|| format!("Failed to load {}", path.display()));
+        };
+        let opened = (|| -> Result<(Self, bool)> {
+            let key = match key {
+                Some(key) => key.clone(),
+                None => StoreKey::for_envelope(&envelope, &store_key::passphrase()?)?,
+            };
+            let plaintext = key.open(&envelope)?;
+            let (mut store, migrated) = Self::parse(std::str::from_utf8(&plaintext)?)?;
+            store.key = Some(key);
+            Ok((store, migrated))
+        })();
+        opened.with_context(|| format!("Failed to load {}", path.display()))
+    }
+
+    /// Parse a store in any known schema version, upgrading it step by step
+    fn parse(data: &str) -> Result<(Self, bool)> {
+        let mut value: Value = serde_json::from_str(data)?;
+
+        let version = match value.get("version") {
+            None => 1,
+            Some(v) => v
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .filter(|v| *v >= 1)
+                .ok_or_else(|| anyhow!("Invalid schema version: {}", v))?,
+        };
+        if version > CURRENT_VERSION {
+            return Err(NewerSchemaError { found: version }.into());
+        }
+
+        for from in version..CURRENT_VERSION {
+            MIGRATIONS[(from - 1) as usize](&mut value)
+                .with_context(|| format!("Failed to migrate notes from v{} to v{}", from, from + 1))?;
+            value["version"] = Value::from(from + 1);
+        }
+
+        let store: NoteStore = serde_json::from_value(value)?;
+        Ok((store, version < CURRENT_VERSION))
+    }
+
     /// Save notes to disk
+    ///
+    /// Writes `notes.json.tmp`, fsyncs it and renames it over `notes.json`,
+    /// so a crash mid-write never leaves a truncated store behind. The
+    /// previous good version is kept as `notes.json.bak`. An encrypted store
+    /// is written encrypted, and with a backup directory configured the new
+    /// file is copied there too.
     pub fn save(&self) -> Result<()> {
-        let path = Self::notes_path()?;
-        let data = serde_json::to_string_pretty(self)?;
-        fs::write(&path, data)?;
+        match &self.path {
+            Some(path) => self.save_to(path),
+            None => self.save_to(&Self::path()?),
+        }
+    }
+
+    /// [`save`](Self::save) to `path`, without taking its lock; Ctrl-C
+    /// waits for it to finish
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        let _writing = crate::interrupt::writing();
+        let mut data = Zeroizing::new(serde_json::to_string_pretty(self)?);
+        if let Some(key) = &self.key {
+            data = Zeroizing::new(key.seal(data.as_bytes())?);
+        }
+
+        let tmp = sibling(path, ".tmp");
+        {
+            let mut file = File::create(&tmp)
+                .with_context(|| format!("Failed to create {}", tmp.display()))?;
+            file.write_all(data.as_bytes())?;
+            file.sync_all()?;
+        }
+
+        // Only rotate a primary that still parses, otherwise a corrupted
+        // file would overwrite the last good backup. One in plaintext or
+        // under an old passphrase would leave the notes readable without
+        // this store's passphrase, so it isn't kept at all.
+        if path.exists() {
+            let backup = sibling(path, ".bak");
+            match Self::read_file(path, self.key.as_ref()) {
+                Ok((previous, _)) if previous.key.is_none() && self.key.is_some() => {
+                    let _ = fs::remove_file(&backup);
+                }
+                Ok(_) => {
+                    fs::copy(path, &backup)?;
+                }
+                Err(e) if e.downcast_ref::<PassphraseError>().is_some() => {
+                    let _ = fs::remove_file(&backup);
+                }
+                Err(_) => {}
+            }
+        }
+
+        fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+
+        // Persist the rename itself
+        if let Some(dir) = path.parent() {
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+
+        // The notes are saved; an unreachable backup target shouldn't fail
+        // the command that changed them
+        if let Some(dir) = &self.backup_dir {
+            if let Err(e) = Self::copy_to(path, dir) {
+                eprintln!("Warning: failed to back up the note store to {}: {:#}", dir.display(), e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copy the store file at `path` into `dir`, under the same name,
+    /// returning the copy's path
+    pub fn copy_to(path: &Path, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let name = path.file_name().ok_or_else(|| anyhow!("{} is not a file", path.display()))?;
+        let copy = dir.join(name);
+        let tmp = sibling(&copy, ".tmp");
+        fs::copy(path, &tmp).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &copy).with_context(|| format!("Failed to replace {}", copy.display()))?;
+        Ok(copy)
+    }
+
+    /// Whether the store is encrypted on disk
+    pub fn is_encrypted(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Encrypt the store with `passphrase` from the next save on, replacing
+    /// the passphrase it had
+    pub fn encrypt(&mut self, passphrase: &str) -> Result<()> {
+        self.key = Some(StoreKey::new(passphrase)?);
         Ok(())
     }
 
@@ -64,20 +761,33 @@ impl NoteStore {
     }
 
     /// Update note status
+    ///
+    /// `tx_sig` is the transaction that led to `status`: the deposit's for
+    /// `deposited` and `deposit_failed`, the withdrawal's for `withdrawn`.
     pub fn update_status(&mut self, id: &str, status: &str, tx_sig: Option<&str>) -> Result<bool> {
-        if let Some(note) = self.notes.iter_mut().find(|n| n.id == id) {
-            note.status = status.to_string();
-            if let Some(sig) = tx_sig {
-                if status == "withdrawn" {
-                    note.withdraw_tx_sig = Some(sig.to_string());
-                }
-            }
+        if self.set_status(id, status, tx_sig) {
             self.save()?;
             return Ok(true);
         }
         Ok(false)
     }
 
+    /// [`update_status`](Self::update_status) without saving
+    fn set_status(&mut self, id: &str, status: &str, tx_sig: Option<&str>) -> bool {
+        let Some(note) = self.notes.iter_mut().find(|n| n.id == id) else {
+            return false;
+        };
+        note.status = status.to_string();
+        if let Some(sig) = tx_sig {
+            match status {
+                "deposited" | "deposit_failed" => note.deposit_tx_sig = Some(sig.to_string()),
+                "withdrawn" => note.withdraw_tx_sig = Some(sig.to_string()),
+                _ => {}
+            }
+        }
+        true
+    }
+
     /// Delete a note
     pub fn delete(&mut self, id: &str) -> bool {
         let len_before = self.notes.len();
@@ -90,37 +800,223 @@ impl NoteStore {
         false
     }
 
-    /// Export notes to file
-    pub fn export(&self, path: &str) -> Result<()> {
-        let data = serde_json::to_string_pretty(&self.notes)?;
-        fs::write(path, data)?;
+    /// Export notes to file. A CSV export only holds the notes' keys with
+    /// `include_secrets`; a JSON one always does, as it is a backup.
+    pub fn export(&self, path: &str, format: ExportFormat, include_secrets: bool) -> Result<()> {
+        let data = Zeroizing::new(match format {
+            ExportFormat::Json => serde_json::to_string_pretty(&self.notes)?,
+            ExportFormat::Csv => self.to_csv(include_secrets),
+        });
+        fs::write(path, data).with_context(|| format!("Failed to write {}", path))?;
         Ok(())
     }
 
+    /// A header row and one row per note. SOL amounts are in SOL; token
+    /// amounts are in their mint's base units, with the mint alongside.
+    pub fn to_csv(&self, include_secrets: bool) -> String {
+        let mut header = CSV_COLUMNS.to_vec();
+        if include_secrets {
+            header.extend(CSV_SECRET_COLUMNS);
+        }
+        let mut csv = csv_row(header);
+        for note in &self.notes {
+            let amount = match note.mint {
+                Some(_) => note.amount.to_string(),
+                None => format!("{}.{:09}", note.amount / 1_000_000_000, note.amount % 1_000_000_000),
+            };
+            let created_at = i64::try_from(note.created_at)
+                .ok()
+                .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+                .map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+                .unwrap_or_default();
+            let mut row = vec![
+                note.id.as_str(),
+                &amount,
+                note.mint.as_deref().unwrap_or_default(),
+                &note.status,
+                &created_at,
+                note.deposit_tx_sig.as_deref().unwrap_or_default(),
+                note.withdraw_tx_sig.as_deref().unwrap_or_default(),
+            ];
+            if include_secrets {
+                row.extend([note.privkey.as_deref().unwrap_or_default(), note.blinding.as_str()]);
+            }
+            csv.push_str(&csv_row(row));
+        }
+        csv
+    }
+
     /// Import notes from file
-    pub fn import(&mut self, path: &str) -> Result<usize> {
-        let data = fs::read_to_string(path)?;
-        let imported: Vec<Note> = serde_json::from_str(&data)?;
-        
-        let existing_ids: std::collections::HashSet<_> = 
-            self.notes.iter().map(|n| n.commitment.clone()).collect();
-        
-        let mut count = 0;
-        for note in imported {
-            if !existing_ids.contains(&note.commitment) {
+    ///
+    /// Notes keep the network they were exported with; exports from before
+    /// notes were tagged get the legacy testnet tag. The store is only
+    /// written if something changed, so importing a file twice is harmless.
+    pub fn import(&mut self, path: &str, merge: ImportMerge) -> Result<ImportSummary> {
+        let data = Zeroizing::new(
+            fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?,
+        );
+        let summary = self
+            .merge_export(&data, merge)
+            .with_context(|| format!("Failed to import {}", path))?;
+        if summary.imported + summary.updated > 0 {
+            self.save()?;
+        }
+        Ok(summary)
+    }
+
+    /// Merge an export into the store without saving it. Each entry is
+    /// checked on its own: one that isn't a note, or whose commitment doesn't
+    /// match its amount and keys, is reported in `invalid` and left out.
+    fn merge_export(&mut self, data: &str, merge: ImportMerge) -> Result<ImportSummary> {
+        if data.starts_with(&format!("{},", CSV_COLUMNS[0])) {
+            return Err(anyhow!(
+                "this is a CSV export, which is for accounting tools and can't be imported; \
+                 import a JSON export (`notes export --format json`) instead"
+            ));
+        }
+        let entries: Vec<Value> = serde_json::from_str(data)?;
+        let mut summary = ImportSummary::default();
+        for (index, mut entry) in entries.into_iter().enumerate() {
+            tag_legacy_note(&mut entry);
+            let id = entry.get("id").and_then(Value::as_str).map(str::to_string);
+            let note = serde_json::from_value::<Note>(entry)
+                .map_err(anyhow::Error::from)
+                .and_then(|note| note.verify_commitment().map(|()| note));
+            let mut note = match note {
+                Ok(note) => note,
+                Err(e) => {
+                    summary.invalid.push(InvalidNote { index, id, reason: format!("{:#}", e) });
+                    continue;
+                }
+            };
+
+            let Some(existing) = self.notes.iter_mut().find(|n| n.commitment == note.commitment)
+            else {
                 self.notes.push(note);
-                count += 1;
+                summary.imported += 1;
+                continue;
+            };
+            let replace = match merge {
+                ImportMerge::Skip => false,
+                ImportMerge::Overwrite => true,
+                ImportMerge::Newer => note.created_at > existing.created_at,
+            };
+            // A spent note stays spent, whatever an older export says
+            if existing.status == "withdrawn" {
+                note.status = existing.status.clone();
+                note.withdraw_tx_sig = existing.withdraw_tx_sig.clone();
+            }
+            if replace && note != *existing {
+                *existing = note;
+                summary.updated += 1;
+            } else {
+                summary.skipped += 1;
             }
         }
-        
+        Ok(summary)
+    }
+
+    /// Next unused deterministic note index
+    pub fn next_derivation_index(&self) -> u64 {
+        self.notes
+            .iter()
+            .filter_map(|n| n.derivation_index)
+            .max()
+            .map_or(0, |i| i + 1)
+    }
+
+    /// Keys of the store's shielded address, generated and saved on first
+    /// use
+    pub fn shielded_keys_or_create(&mut self) -> Result<ShieldedKeys> {
+        if let Some(keys) = &self.shielded_keys {
+            return Ok(keys.clone());
+        }
+        let keys = ShieldedKeys::generate();
+        self.shielded_keys = Some(keys.clone());
         self.save()?;
-        Ok(count)
+        Ok(keys)
+    }
+
+    /// Oldest unspent note of exactly `amount` on the given pool that has
+    /// been in the tree for at least `min_age_secs`
+    pub fn find_reusable(
+        &self,
+        amount: u64,
+        network: &str,
+        pool: &str,
+        min_age_secs: u64,
+        now: u64,
+    ) -> Option<&Note> {
+        self.withdrawable(network, pool)
+            .into_iter()
+            .filter(|n| n.amount == amount && now.saturating_sub(n.created_at) >= min_age_secs)
+            .min_by_key(|n| n.created_at)
+    }
+
+    /// Notes on the given pool that can be withdrawn; not ones already
+    /// withdrawn, nor ones whose deposit failed or was found invalid, nor
+    /// watch-only ones
+    pub fn withdrawable(&self, network: &str, pool: &str) -> Vec<&Note> {
+        self.notes
+            .iter()
+            .filter(|n| n.status == "deposited" && n.is_on(network, pool) && !n.is_watch_only())
+            .collect()
     }
 
     /// Get notes by status
     pub fn get_by_status(&self, status: &str) -> Vec<&Note> {
         self.notes.iter().filter(|n| n.status == status).collect()
     }
+
+    /// The notes `filter` selects, in its order; notes that sort equal
+    /// keep their order in the store, reversed or not
+    pub fn select(&self, filter: &NoteFilter) -> Vec<&Note> {
+        let mut notes = match &filter.status {
+            Some(status) => self.get_by_status(status),
+            None => self.notes.iter().collect(),
+        };
+        if filter.watch_only {
+            notes.retain(|n| n.is_watch_only());
+        }
+        if filter.min_amount.is_some() || filter.max_amount.is_some() {
+            notes.retain(|n| {
+                n.mint.is_none()
+                    && filter.min_amount.is_none_or(|min| n.amount >= min)
+                    && filter.max_amount.is_none_or(|max| n.amount <= max)
+            });
+        }
+        notes.sort_by(|a, b| {
+            let order = match filter.sort {
+                NoteSort::Date => a.created_at.cmp(&b.created_at),
+                NoteSort::Amount => a.amount.cmp(&b.amount),
+                NoteSort::Status => a.status.cmp(&b.status),
+            };
+            if filter.reverse {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+        if let Some(limit) = filter.limit {
+            notes.truncate(limit);
+        }
+        notes
+    }
+}
+
+/// One CSV line, quoting fields that hold a comma, quote or line break
+fn csv_row<'a>(fields: impl IntoIterator<Item = &'a str>) -> String {
+    let fields: Vec<String> = fields
+        .into_iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    fields.join(",") + "\r\n"
 }
 
 /// Generate a unique note ID
@@ -138,6 +1034,521 @@ pub fn generate_note_id() -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_load_every_schema_version() {
+        let fixtures = [
+            (1, include_str!("../tests/fixtures/notes_v1.json")),
+            (2, include_str!("../tests/fixtures/notes_v2.json")),
+            (3, include_str!("../tests/fixtures/notes_v3.json")),
+            (4, include_str!("../tests/fixtures/notes_v4.json")),
+            (5, include_str!("../tests/fixtures/notes_v5.json")),
+            (6, include_str!("../tests/fixtures/notes_v6.json")),
+        ];
+        assert_eq!(fixtures.len(), CURRENT_VERSION as usize);
+
+        for (version, data) in fixtures {
+            let (store, migrated) = NoteStore::parse(data).unwrap();
+            assert_eq!(store.version, CURRENT_VERSION);
+            assert_eq!(migrated, version < CURRENT_VERSION);
+            assert_eq!(store.notes.len(), 2);
+            assert_eq!(store.notes[0].amount, 100_000_000);
+            assert_eq!(store.notes[1].status, "withdrawn");
+            assert!(store.notes[0].is_on(LEGACY_NETWORK, LEGACY_POOL));
+            assert_eq!(store.notes[0].derivation_index, None);
+            assert_eq!(store.shielded_keys.is_some(), version >= 5);
+            assert_eq!(store.notes[0].is_watch_only(), version == 6);
+        }
+    }
+
+    #[test]
+    fn test_note_detail_hides_secrets_unless_revealed() {
+        let (store, _) = NoteStore::parse(include_str!("../tests/fixtures/notes_v4.json")).unwrap();
+        let note = &store.notes[1];
+        let explorer = |sig: &str| format!("https://explorer/{}", sig);
+
+        let hidden = serde_json::to_value(note.detail(false, explorer)).unwrap();
+        assert_eq!(hidden["id"], note.id.as_str());
+        assert_eq!(hidden["pubkey"], note.pubkey.as_str());
+        assert!(hidden.get("privkey").is_none() && hidden.get("blinding").is_none());
+        let privkey = note.privkey.as_deref().unwrap();
+        let text = hidden.to_string();
+        assert!(!text.contains(privkey) && !text.contains(note.blinding.as_str()));
+        assert!(!format!("{:?}", note.detail(false, explorer)).contains(privkey));
+
+        let sig = note.withdraw_tx_sig.as_deref().unwrap();
+        assert_eq!(hidden["withdraw_tx_url"], format!("https://explorer/{}", sig));
+
+        let revealed = serde_json::to_value(note.detail(true, explorer)).unwrap();
+        assert_eq!(revealed["privkey"], privkey);
+        assert_eq!(revealed["blinding"], note.blinding.as_str());
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(1_736_000_000, &chrono::Utc), "2025-01-04 14:13:20 +00:00");
+        let tokyo = chrono::FixedOffset::east_opt(9 * 3600).unwrap();
+        assert_eq!(format_timestamp(0, &tokyo), "1970-01-01 09:00:00 +09:00");
+        assert!(format_timestamp(u64::MAX, &chrono::Utc).contains("invalid"));
+        assert!(format_timestamp(i64::MAX as u64, &chrono::Utc).contains("invalid"));
+    }
+
+    #[test]
+    fn test_wrong_network_is_rejected() {
+        let (store, _) = NoteStore::parse(include_str!("../tests/fixtures/notes_v3.json")).unwrap();
+        let note = &store.notes[0];
+        assert!(note.check_network("testnet", LEGACY_POOL).is_ok());
+
+        let err = note.check_network("devnet", LEGACY_POOL).unwrap_err();
+        assert!(err.to_string().contains("deposited on testnet"));
+    }
+
+    #[test]
+    fn test_debug_omits_keys() {
+        let (mut store, _) = NoteStore::parse(include_str!("../tests/fixtures/notes_v6.json")).unwrap();
+        store.notes[1].blinding = "31415926535897932384".into();
+        let debug = format!("{:?}", store);
+        let keys = store.shielded_keys.as_ref().unwrap();
+        let secrets = store
+            .notes
+            .iter()
+            .flat_map(|n| [n.privkey.as_deref(), Some(n.blinding.as_str())])
+            .chain([Some(keys.privkey.as_str()), Some(keys.encryption_key.as_str())])
+            .flatten();
+        for secret in secrets {
+            assert!(!debug.contains(secret), "{} in {}", secret, debug);
+        }
+        assert!(debug.contains(&store.notes[0].commitment));
+    }
+
+    #[test]
+    fn test_listing_omits_keys() {
+        let (store, _) = NoteStore::parse(include_str!("../tests/fixtures/notes_v4.json")).unwrap();
+        let note = &store.notes[0];
+        let listing = serde_json::to_value(note.listing()).unwrap();
+        assert_eq!(listing["id"], note.id.as_str());
+        assert_eq!(listing["amount"], note.amount);
+        assert!(listing.get("privkey").is_none());
+        assert!(listing.get("blinding").is_none());
+    }
+
+    #[test]
+    fn test_out_of_range_fields_are_rejected() {
+        let (store, _) = NoteStore::parse(include_str!("../tests/fixtures/notes_v4.json")).unwrap();
+        let mut note = store.notes[0].clone();
+        assert!(note.check_fields().is_ok());
+
+        note.blinding = crypto::FIELD_SIZE.into();
+        let err = format!("{:#}", note.check_fields().unwrap_err());
+        assert!(err.contains("invalid blinding"), "{}", err);
+        assert!(err.contains("not below the field modulus"), "{}", err);
+
+        let data = serde_json::to_string(&[note]).unwrap();
+        let mut store = NoteStore::default();
+        let summary = store.merge_export(&data, ImportMerge::Skip).unwrap();
+        assert_eq!(summary.imported, 0);
+        assert!(summary.invalid[0].reason.contains("invalid blinding"), "{:?}", summary);
+        assert!(store.notes.is_empty());
+    }
+
+    #[test]
+    fn test_tampered_note_is_rejected() {
+        let utxo = Utxo::new(100_000_000, crypto::sol_mint()).unwrap();
+        let (store, _) = NoteStore::parse(include_str!("../tests/fixtures/notes_v4.json")).unwrap();
+        let mut note = Note {
+            amount: utxo.amount,
+            privkey: Some(utxo.privkey.clone()),
+            pubkey: utxo.pubkey.clone(),
+            blinding: utxo.blinding.clone(),
+            commitment: utxo.commitment.clone(),
+            ..store.notes[0].clone()
+        };
+        assert!(note.verify_commitment().is_ok());
+
+        note.blinding = "12345".into();
+        let err = note.verify_commitment().unwrap_err().to_string();
+        assert!(err.contains(&format!("Note {} is corrupt", note.id)), "{}", err);
+    }
+
+    #[test]
+    fn test_watch_only_note_cant_be_spent() {
+        let (store, _) = NoteStore::parse(include_str!("../tests/fixtures/notes_v4.json")).unwrap();
+        let utxo = Utxo::new(100_000_000, crypto::sol_mint()).unwrap();
+        let spendable = Note {
+            amount: utxo.amount,
+            privkey: Some(utxo.privkey.clone()),
+            pubkey: utxo.pubkey.clone(),
+            blinding: utxo.blinding.clone(),
+            commitment: utxo.commitment.clone(),
+            ..store.notes[0].clone()
+        };
+        let note = Note { privkey: None, ..spendable.clone() };
+        assert!(note.is_watch_only() && !spendable.is_watch_only());
+
+        // The commitment still checks out without the private key...
+        note.verify_commitment().unwrap();
+        let tampered = Note { amount: 1, ..note.clone() };
+        assert!(tampered.verify_commitment().is_err());
+        // ...but nothing will spend it
+        let err = note.utxo().unwrap_err().to_string();
+        assert!(err.contains(&format!("note {} is watch-only", note.id)), "{}", err);
+        assert_eq!(spendable.utxo().unwrap().commitment, utxo.commitment);
+        let store = NoteStore {
+            notes: vec![note.clone()],
+            ..NoteStore::default()
+        };
+        assert!(store.withdrawable(LEGACY_NETWORK, LEGACY_POOL).is_empty());
+        assert!(store.find_reusable(utxo.amount, LEGACY_NETWORK, LEGACY_POOL, 0, u64::MAX).is_none());
+
+        // Stored with a null privkey, listed as watch-only
+        let json = serde_json::to_value(&note).unwrap();
+        assert!(json["privkey"].is_null());
+        assert_eq!(serde_json::from_value::<Note>(json).unwrap(), note);
+        assert_eq!(serde_json::to_value(note.listing()).unwrap()["watch_only"], true);
+        assert!(serde_json::to_value(spendable.listing()).unwrap().get("watch_only").is_none());
+    }
+
+    #[test]
+    fn test_deposit_stays_spendable_without_its_withdrawal() {
+        let dir = std::env::temp_dir().join(format!("notes-interrupted-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.json");
+        let utxo = Utxo::new(200_000_000, crypto::sol_mint()).unwrap();
+        let (mut store, _) =
+            NoteStore::parse(include_str!("../tests/fixtures/notes_v4.json")).unwrap();
+        store.save_to(&path).unwrap();
+
+        // Step 1 of a transfer saves the deposit...
+        let note = Note {
+            id: generate_note_id(),
+            amount: utxo.amount,
+            privkey: Some(utxo.privkey.clone()),
+            pubkey: utxo.pubkey.clone(),
+            blinding: utxo.blinding.clone(),
+            commitment: utxo.commitment.clone(),
+            created_at: 1736000200,
+            ..store.notes[0].clone()
+        };
+        store.notes.push(note.clone());
+        store.save_to(&path).unwrap();
+        // ...then Ctrl-C skips step 2, after a write of the store was cut
+        // short before its rename
+        fs::write(sibling(&path, ".tmp"), "{\"version\": 4, \"notes\": [").unwrap();
+
+        let (reloaded, _) = NoteStore::read_file(&path, None).unwrap();
+        assert_eq!(reloaded.notes.len(), 3);
+        let saved = reloaded.notes.iter().find(|n| n.id == note.id).unwrap();
+        assert_eq!(saved.status, "deposited");
+        assert!(saved.verify_commitment().is_ok());
+        assert_eq!(
+            reloaded
+                .find_reusable(note.amount, LEGACY_NETWORK, LEGACY_POOL, 0, 1736000200)
+                .map(|n| &n.id),
+            Some(&note.id)
+        );
+
+        // The leftover write doesn't get in the way of the next one
+        reloaded.save_to(&path).unwrap();
+        assert_eq!(NoteStore::read_file(&path, None).unwrap().0.notes.len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_store_at_custom_path() {
+        let dir = std::env::temp_dir().join(format!("notes-profile-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("profiles/alice.json");
+        let (exported, data) = export();
+
+        let mut store = NoteStore::load_from(&path).unwrap();
+        assert!(store.notes.is_empty());
+        store.add(exported.notes[0].clone()).unwrap();
+        let id = exported.notes[0].id.clone();
+        assert!(store.update_status(&id, "withdrawn", Some("sig")).unwrap());
+        drop(store);
+
+        let mut store = NoteStore::load_from(&path).unwrap();
+        assert_eq!(store.notes[0].withdraw_tx_sig.as_deref(), Some("sig"));
+        assert!(store.delete(&id));
+        let import = dir.join("import.json");
+        fs::write(&import, data).unwrap();
+        let summary = store.import(import.to_str().unwrap(), ImportMerge::Skip).unwrap();
+        assert_eq!(summary.imported, 2);
+        let exported_to = dir.join("elsewhere/export.json");
+        fs::create_dir_all(exported_to.parent().unwrap()).unwrap();
+        store.export(exported_to.to_str().unwrap(), ExportFormat::Json, false).unwrap();
+        drop(store);
+
+        let store = NoteStore::load_from(&path).unwrap();
+        assert_eq!(store.notes, exported.notes);
+        let reexported: Vec<Note> =
+            serde_json::from_str(&fs::read_to_string(&exported_to).unwrap()).unwrap();
+        assert_eq!(reexported, exported.notes);
+        assert!(sibling(&path, ".bak").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_store() {
+        let dir = std::env::temp_dir().join(format!("notes-encrypted-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("notes.json");
+        let (exported, _) = export();
+
+        let mut store = NoteStore::load_from(&path).unwrap();
+        store.add(exported.notes[0].clone()).unwrap();
+        assert!(!store.is_encrypted());
+        store.encrypt("correct horse").unwrap();
+        store.save().unwrap();
+        let key = store.key.clone().unwrap();
+        drop(store);
+
+        // Nothing readable is left on disk, not even the plaintext backup
+        let data = fs::read_to_string(&path).unwrap();
+        assert!(Envelope::parse(&data).is_some());
+        assert!(!data.contains(&exported.notes[0].commitment));
+        assert!(!sibling(&path, ".bak").exists());
+
+        let (store, _) = NoteStore::read_file(&path, Some(&key)).unwrap();
+        assert!(store.is_encrypted());
+        assert_eq!(store.notes, exported.notes[..1]);
+
+        // Saves stay encrypted and rotate the encrypted backup
+        store.save_to(&path).unwrap();
+        let (backup, _) = NoteStore::read_file(&sibling(&path, ".bak"), Some(&key)).unwrap();
+        assert_eq!(backup.notes, exported.notes[..1]);
+
+        // A new passphrase drops the backup under the old one
+        let mut store = store;
+        store.encrypt("battery staple").unwrap();
+        store.save_to(&path).unwrap();
+        assert!(!sibling(&path, ".bak").exists());
+        let err = NoteStore::read_file(&path, Some(&key)).unwrap_err();
+        assert!(err.downcast_ref::<PassphraseError>().is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_copies_to_backup_dir() {
+        let dir = std::env::temp_dir().join(format!("notes-backup-dir-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("notes.json");
+        let (exported, _) = export();
+
+        let mut store = NoteStore::load_from(&path).unwrap();
+        store.backup_dir = Some(dir.join("usb/privacy"));
+        store.add(exported.notes[0].clone()).unwrap();
+        let copy = dir.join("usb/privacy/notes.json");
+        assert_eq!(fs::read_to_string(&copy).unwrap(), fs::read_to_string(&path).unwrap());
+
+        // An unwritable target warns rather than failing the save
+        fs::write(dir.join("file"), "").unwrap();
+        store.backup_dir = Some(dir.join("file"));
+        store.add(exported.notes[1].clone()).unwrap();
+        assert_eq!(NoteStore::read_file(&path, None).unwrap().0.notes, exported.notes);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// An export of the fixture's two notes with real commitments: one
+    /// deposited, one withdrawn
+    fn export() -> (NoteStore, String) {
+        let (mut store, _) =
+            NoteStore::parse(include_str!("../tests/fixtures/notes_v4.json")).unwrap();
+        for note in &mut store.notes {
+            let utxo = Utxo::new(note.amount, crypto::sol_mint()).unwrap();
+            (note.privkey, note.pubkey) = (Some(utxo.privkey), utxo.pubkey);
+            (note.blinding, note.commitment) = (utxo.blinding, utxo.commitment);
+        }
+        let data = serde_json::to_string(&store.notes).unwrap();
+        (store, data)
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let (mut store, _) = export();
+        store.notes[0].id = "note, \"quoted\"".to_string();
+        store.notes[1].mint = Some("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string());
+
+        let csv = store.to_csv(false);
+        let lines: Vec<&str> = csv.split_terminator("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "id,amount,mint,status,created_at,deposit_tx_sig,withdraw_tx_sig");
+        let deposit = store.notes[0].deposit_tx_sig.as_deref().unwrap();
+        assert_eq!(
+            lines[1],
+            format!("\"note, \"\"quoted\"\"\",0.100000000,,deposited,2025-01-04T14:13:20Z,{},", deposit)
+        );
+        assert!(lines[2].starts_with("note_1736000100000_5e6f7a8b,250000000,EPjF"), "{}", lines[2]);
+        for note in &store.notes {
+            assert!(!csv.contains(note.privkey.as_deref().unwrap()) && !csv.contains(note.blinding.as_str()));
+        }
+
+        let secrets = store.to_csv(true);
+        assert!(secrets.starts_with("id,amount,mint,status,created_at,deposit_tx_sig,withdraw_tx_sig,privkey,blinding\r\n"));
+        let note = &store.notes[1];
+        assert!(secrets.contains(&format!(",{},{}\r\n", note.privkey.as_deref().unwrap(), note.blinding.as_str())));
+
+        // Neither is a backup that import would take
+        for csv in [csv, secrets] {
+            let err = NoteStore::default().merge_export(&csv, ImportMerge::Skip).unwrap_err();
+            assert!(err.to_string().contains("CSV export"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_import_is_idempotent() {
+        let (exported, data) = export();
+        for merge in [ImportMerge::Skip, ImportMerge::Overwrite, ImportMerge::Newer] {
+            let mut store = NoteStore::default();
+            let first = store.merge_export(&data, merge).unwrap();
+            assert_eq!((first.imported, first.skipped), (2, 0));
+            assert_eq!(store.notes, exported.notes);
+
+            let second = store.merge_export(&data, merge).unwrap();
+            assert_eq!(second, ImportSummary { skipped: 2, ..Default::default() });
+            assert_eq!(store.notes, exported.notes);
+        }
+    }
+
+    #[test]
+    fn test_import_never_unspends_a_note() {
+        let (mut store, _) = export();
+        let mut stale = store.notes[1].clone();
+        store.notes[1].created_at -= 1;
+        stale.status = "deposited".to_string();
+        stale.withdraw_tx_sig = None;
+        let data = serde_json::to_string(&[stale]).unwrap();
+
+        for merge in [ImportMerge::Skip, ImportMerge::Overwrite, ImportMerge::Newer] {
+            store.merge_export(&data, merge).unwrap();
+            assert_eq!(store.notes[1].status, "withdrawn", "{:?}", merge);
+            assert!(store.notes[1].withdraw_tx_sig.is_some());
+            assert_eq!(store.withdrawable("testnet", LEGACY_POOL).len(), 1);
+        }
+        // The rest of the imported note was still taken
+        assert_eq!(store.notes[1].created_at, 1736000100);
+    }
+
+    #[test]
+    fn test_import_merge_policies() {
+        let (exported, _) = export();
+        let mut newer = exported.notes[0].clone();
+        newer.created_at += 10;
+        newer.leaf_index = 99;
+        let mut older = exported.notes[0].clone();
+        older.created_at -= 10;
+        older.leaf_index = 98;
+
+        let mut store = NoteStore { notes: exported.notes.clone(), ..Default::default() };
+        let older_data = serde_json::to_string(&[&older]).unwrap();
+        let newer_data = serde_json::to_string(&[&newer]).unwrap();
+        let summary = store.merge_export(&newer_data, ImportMerge::Skip).unwrap();
+        assert_eq!((summary.skipped, store.notes[0].leaf_index), (1, 12));
+        let summary = store.merge_export(&older_data, ImportMerge::Newer).unwrap();
+        assert_eq!((summary.skipped, store.notes[0].leaf_index), (1, 12));
+        let summary = store.merge_export(&newer_data, ImportMerge::Newer).unwrap();
+        assert_eq!((summary.updated, store.notes[0].leaf_index), (1, 99));
+        let summary = store.merge_export(&older_data, ImportMerge::Overwrite).unwrap();
+        assert_eq!((summary.updated, store.notes[0].leaf_index), (1, 98));
+        assert_eq!(store.notes.len(), 2);
+    }
+
+    #[test]
+    fn test_import_reports_invalid_entries() {
+        let (exported, _) = export();
+        let mut tampered = serde_json::to_value(&exported.notes[0]).unwrap();
+        tampered["amount"] = 1.into();
+        let data = serde_json::json!([tampered, {"id": "half", "amount": 5}, exported.notes[1]]);
+
+        let mut store = NoteStore::default();
+        let summary = store.merge_export(&data.to_string(), ImportMerge::Skip).unwrap();
+        assert_eq!(summary.imported, 1);
+        assert_eq!(summary.invalid.len(), 2);
+        assert_eq!(summary.invalid[0].index, 0);
+        assert!(summary.invalid[0].reason.contains("is corrupt"), "{:?}", summary.invalid);
+        assert_eq!(summary.invalid[1].id.as_deref(), Some("half"));
+        assert_eq!(store.notes, exported.notes[1..]);
+        assert!(store.merge_export("{}", ImportMerge::Skip).is_err());
+    }
+
+    #[test]
+    fn test_shielded_keys_are_created_once() {
+        let dir = std::env::temp_dir().join(format!("notes-shielded-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("notes.json");
+
+        let mut store = NoteStore::load_from(&path).unwrap();
+        let keys = store.shielded_keys_or_create().unwrap();
+        assert_eq!(store.shielded_keys_or_create().unwrap(), keys);
+        drop(store);
+
+        let mut store = NoteStore::load_from(&path).unwrap();
+        assert_eq!(store.shielded_keys_or_create().unwrap(), keys);
+        // Not part of an export, which only carries notes
+        let exported = dir.join("export.json");
+        store.export(exported.to_str().unwrap(), ExportFormat::Json, false).unwrap();
+        assert!(!fs::read_to_string(&exported).unwrap().contains(keys.privkey.as_str()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_newer_schema_is_rejected() {
+        let err = NoteStore::parse(r#"{"version": 99, "notes": []}"#).unwrap_err();
+        assert!(err.downcast_ref::<NewerSchemaError>().is_some());
+        assert!(err.to_string().contains("newer privacy-zig"));
+    }
+
+    #[test]
+    fn test_token_notes_are_spent_as_their_mint() {
+        let (store, _) = NoteStore::parse(include_str!("../tests/fixtures/notes_v4.json")).unwrap();
+        // Notes written before tokens are SOL, and stay without a mint field
+        let sol = &store.notes[0];
+        assert_eq!(sol.mint().unwrap(), None);
+        assert!(!serde_json::to_string(sol).unwrap().contains("mint"));
+        sol.check_mint(None).unwrap();
+
+        let usdc: Pubkey = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".parse().unwrap();
+        let err = sol.check_mint(Some(&usdc)).unwrap_err().to_string();
+        assert!(err.contains("holds SOL") && err.contains("leave out --mint"), "{}", err);
+
+        let utxo = Utxo::new(1_500_000, crypto::spl_mint(&usdc)).unwrap();
+        let token = Note {
+            amount: utxo.amount,
+            privkey: Some(utxo.privkey),
+            pubkey: utxo.pubkey,
+            blinding: utxo.blinding,
+            commitment: utxo.commitment,
+            mint: Some(usdc.to_string()),
+            ..sol.clone()
+        };
+        token.verify_commitment().unwrap();
+        token.check_mint(Some(&usdc)).unwrap();
+        let err = token.check_mint(None).unwrap_err().to_string();
+        assert!(err.contains(&format!("pass --mint {}", usdc)), "{}", err);
+
+        // The commitment binds the mint: the same note read as SOL is corrupt
+        let as_sol = Note { mint: None, ..token.clone() };
+        assert!(as_sol.verify_commitment().is_err());
+
+        let json = serde_json::to_value(&token).unwrap();
+        assert_eq!(json["mint"], usdc.to_string());
+        assert_eq!(serde_json::from_value::<Note>(json).unwrap(), token);
+    }
+
+    #[test]
+    fn test_find_reusable_note() {
+        let (store, _) = NoteStore::parse(include_str!("../tests/fixtures/notes_v4.json")).unwrap();
+        let created = store.notes[0].created_at;
+
+        let note = store.find_reusable(100_000_000, "testnet", LEGACY_POOL, 3600, created + 7200);
+        assert_eq!(note.map(|n| n.id.as_str()), Some(store.notes[0].id.as_str()));
+
+        // Too fresh, wrong amount, wrong network, already withdrawn
+        assert!(store.find_reusable(100_000_000, "testnet", LEGACY_POOL, 3600, created + 60).is_none());
+        assert!(store.find_reusable(200_000_000, "testnet", LEGACY_POOL, 0, created).is_none());
+        assert!(store.find_reusable(100_000_000, "devnet", LEGACY_POOL, 0, created).is_none());
+        assert!(store.find_reusable(250_000_000, "testnet", LEGACY_POOL, 0, u64::MAX).is_none());
+    }
+
     #[test]
     fn test_note_store() {
         let mut store = NoteStore::default();
@@ -145,19 +1556,121 @@ mod tests {
         let note = Note {
             id: "test_note".to_string(),
             amount: 1_000_000_000,
-            privkey: "abc".to_string(),
+            privkey: Some("abc".into()),
             pubkey: "def".to_string(),
-            blinding: "123".to_string(),
+            blinding: "123".into(),
             commitment: "456".to_string(),
             leaf_index: 0,
             status: "deposited".to_string(),
             created_at: 0,
             deposit_tx_sig: None,
             withdraw_tx_sig: None,
+            network: "testnet".to_string(),
+            pool: LEGACY_POOL.to_string(),
+            derivation_index: None,
+            mint: None,
         };
         
         store.notes.push(note);
         assert_eq!(store.notes.len(), 1);
         assert_eq!(store.get_by_status("deposited").len(), 1);
     }
+
+    #[test]
+    fn test_next_derivation_index() {
+        let (mut store, _) = NoteStore::parse(include_str!("../tests/fixtures/notes_v4.json")).unwrap();
+        store.notes.truncate(1);
+        store.notes[0].derivation_index = None;
+        assert_eq!(store.next_derivation_index(), 0);
+
+        // Past the highest index in use, not the number of derived notes
+        let mut derived = store.notes[0].clone();
+        derived.derivation_index = Some(4);
+        store.notes.push(derived);
+        assert_eq!(store.next_derivation_index(), 5);
+    }
+
+    #[test]
+    fn test_select_filters_and_sorts() {
+        let (mut store, _) = NoteStore::parse(include_str!("../tests/fixtures/notes_v4.json")).unwrap();
+        let template = store.notes[0].clone();
+        store.notes.clear();
+        for (id, amount, status, created_at) in [
+            ("a", 300_000_000, "deposited", 30),
+            ("b", 100_000_000, "withdrawn", 10),
+            ("c", 500_000_000, "deposited", 20),
+            ("d", 100_000_000, "deposit_failed", 40),
+            ("e", 200_000_000, "invalid", 50),
+        ] {
+            store.notes.push(Note {
+                id: id.to_string(),
+                amount,
+                status: status.to_string(),
+                created_at,
+                ..template.clone()
+            });
+        }
+        let mut token = store.notes[2].clone();
+        token.id = "token".to_string();
+        token.created_at = 60;
+        token.mint = Some(Pubkey::new_unique().to_string());
+        store.notes.push(token);
+        let ids = |filter: NoteFilter| -> Vec<String> {
+            store.select(&filter).iter().map(|n| n.id.clone()).collect()
+        };
+
+        assert_eq!(ids(NoteFilter::default()), ["b", "c", "a", "d", "e", "token"]);
+        let deposited = NoteFilter {
+            status: Some("deposited".to_string()),
+            ..NoteFilter::default()
+        };
+        assert_eq!(ids(deposited.clone()), ["c", "a", "token"]);
+        assert_eq!(ids(NoteFilter { sort: NoteSort::Amount, reverse: true, ..deposited }), ["c", "token", "a"]);
+
+        // Amount bounds are inclusive and leave token notes out
+        let range = NoteFilter {
+            min_amount: Some(100_000_000),
+            max_amount: Some(300_000_000),
+            sort: NoteSort::Amount,
+            ..NoteFilter::default()
+        };
+        assert_eq!(ids(range.clone()), ["b", "d", "e", "a"]);
+        assert_eq!(ids(NoteFilter { limit: Some(2), ..range }), ["b", "d"]);
+
+        // Equal statuses keep store order
+        let by_status = NoteFilter { sort: NoteSort::Status, ..NoteFilter::default() };
+        assert_eq!(ids(by_status), ["d", "a", "c", "token", "e", "b"]);
+        let newest = NoteFilter { reverse: true, limit: Some(1), ..NoteFilter::default() };
+        assert_eq!(ids(newest), ["token"]);
+        assert!(ids(NoteFilter { status: Some("pending".to_string()), ..NoteFilter::default() }).is_empty());
+    }
+
+    #[test]
+    fn test_status_transitions_record_their_signature() {
+        let (mut store, _) =
+            NoteStore::parse(include_str!("../tests/fixtures/notes_v4.json")).unwrap();
+        let mut failed = store.notes[0].clone();
+        failed.id = "note_failed".to_string();
+        store.notes.push(failed);
+        let (spent, failed) = (store.notes[0].id.clone(), "note_failed");
+        assert_eq!(store.withdrawable("testnet", LEGACY_POOL).len(), 2);
+
+        // deposited -> withdrawn
+        assert!(store.set_status(&spent, "withdrawn", Some("withdraw_sig")));
+        let note = &store.notes[0];
+        assert_eq!(note.status, "withdrawn");
+        assert_eq!(note.withdraw_tx_sig.as_deref(), Some("withdraw_sig"));
+        assert!(note.deposit_tx_sig.as_deref().unwrap().starts_with("5VERv8"));
+
+        // deposited -> deposit_failed
+        assert!(store.set_status(failed, "deposit_failed", Some("deposit_sig")));
+        let note = &store.notes[2];
+        assert_eq!(note.status, "deposit_failed");
+        assert_eq!(note.deposit_tx_sig.as_deref(), Some("deposit_sig"));
+        assert_eq!(note.withdraw_tx_sig, None);
+
+        assert!(store.withdrawable("testnet", LEGACY_POOL).is_empty());
+        assert!(store.find_reusable(100_000_000, "testnet", LEGACY_POOL, 0, u64::MAX).is_none());
+        assert!(!store.set_status("note_missing", "withdrawn", None));
+    }
 }
@@ -0,0 +1,84 @@
+//! `--json`: machine-readable output for scripts
+//!
+//! In JSON mode a command prints exactly one JSON document to stdout (its
+//! result, `null` if it was cancelled, or `{"error": ...}`), and the
+//! human-readable progress goes to stderr without colors. Use [`say!`] for
+//! anything meant for people.
+//!
+//! `--reveal`: private keys, blindings and backup strings spend notes, so no
+//! command prints one, in either mode, unless it passes through [`secret`]
+//! or [`masked`] with `--reveal` given.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static EMITTED: AtomicBool = AtomicBool::new(false);
+static REVEAL: AtomicBool = AtomicBool::new(false);
+
+/// What [`masked`] prints in place of a secret
+pub const MASKED: &str = "hidden; --reveal prints it";
+
+/// Switch JSON mode on for the rest of the run
+pub fn set_json(enabled: bool) {
+    JSON.store(enabled, Ordering::Relaxed);
+    if enabled {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+}
+
+pub fn json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Let commands print secrets for the rest of the run
+pub fn set_reveal(enabled: bool) {
+    REVEAL.store(enabled, Ordering::Relaxed);
+}
+
+pub fn reveal() -> bool {
+    REVEAL.load(Ordering::Relaxed)
+}
+
+/// `secret` if `--reveal` was given; for results, which leave the field out
+/// otherwise
+pub fn secret<T: ?Sized>(secret: &T) -> Option<&T> {
+    reveal().then_some(secret)
+}
+
+/// `secret` if `--reveal` was given, [`MASKED`] otherwise; for people
+pub fn masked(value: &str) -> String {
+    match secret(value) {
+        Some(value) => value.to_string(),
+        None => console::style(MASKED).dim().to_string(),
+    }
+}
+
+/// Print a command's result to stdout; does nothing outside JSON mode,
+/// where the command has already printed it for people. Only the first
+/// result is printed, so a command that emits its own (such as a dry run)
+/// isn't followed by the one its caller emits.
+pub fn emit(value: &impl Serialize) -> Result<()> {
+    if json() && !EMITTED.swap(true, Ordering::Relaxed) {
+        println!("{}", serde_json::to_string_pretty(value)?);
+    }
+    Ok(())
+}
+
+/// `println!` for human-readable output: stdout normally, stderr in JSON mode
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __say {
+    ($($arg:tt)*) => {
+        if $crate::output::json() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+pub use crate::__say as say;
+
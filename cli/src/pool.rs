@@ -1,55 +1,54 @@
-use solana_sdk::pubkey::Pubkey;
-use std::str::FromStr;
+//! Pool addresses and protocol constants, shared with the relayer through
+//! `privacy-zig-core`
 
-/// Program ID for privacy-zig on testnet (with separate recipient account)
-pub const PROGRAM_ID: &str = "9A6fck3xNW2C6vwwqM4i1f4GeYpieuB7XKpF1YFduT6h";
+pub use privacy_zig_core::{Network, PoolAddresses, PoolConfig, TRANSACT_DISCRIMINATOR};
 
-/// Pool configuration with all relevant addresses
-pub struct PoolConfig {
-    pub program_id: Pubkey,
-    pub tree_account: Pubkey,
-    pub global_config: Pubkey,
-    pub pool_vault: Pubkey,
-    pub fee_recipient: Pubkey,
-}
+use std::sync::OnceLock;
 
-impl Default for PoolConfig {
-    fn default() -> Self {
-        Self {
-            program_id: load_pubkey("PRIVACY_POOL_PROGRAM_ID", PROGRAM_ID),
-            tree_account: load_pubkey(
-                "PRIVACY_POOL_TREE_ACCOUNT",
-                "4EGnTF2XfKDTBAszzoqQLe4zbmiURkWtkYQGnj99GiJf",
-            ),
-            global_config: load_pubkey(
-                "PRIVACY_POOL_GLOBAL_CONFIG",
-                "7RUeHfhA6L7BUrmt9ZK7SJ9rmTMkD8qjjJgHRrUEGMq9",
-            ),
-            pool_vault: load_pubkey(
-                "PRIVACY_POOL_POOL_VAULT",
-                "7nAKNHQwTeaybrnX6y3c3fLDL3qzQ3A6FGwMwH1LPc8q",
-            ),
-            // Use relayer address as fee_recipient to avoid exposing user address
-            fee_recipient: load_pubkey(
-                "PRIVACY_POOL_FEE_RECIPIENT",
-                "FcuLoWBhZ8bNQRsSgGhH5NCJJbqK5uhHMZR6V21kyTgS",
-            ),
-        }
-    }
-}
+/// `--network` and the pool addresses resolved for it
+static SELECTED: OnceLock<(Option<Network>, PoolConfig)> = OnceLock::new();
 
-fn load_pubkey(env_key: &str, fallback: &str) -> Pubkey {
-    std::env::var(env_key)
-        .ok()
-        .and_then(|value| Pubkey::from_str(&value).ok())
-        .unwrap_or_else(|| Pubkey::from_str(fallback).expect("Invalid fallback pubkey"))
+/// Use `config` for the rest of the run; `network` is the `--network` flag
+pub fn select(network: Option<Network>, config: PoolConfig) {
+    let _ = SELECTED.set((network, config));
 }
 
-/// Merkle tree constants
-pub const MERKLE_TREE_HEIGHT: usize = 26;
+/// Pool addresses for this run: the selected network's preset with config
+/// file and `PRIVACY_POOL_*` overrides, or testnet's if nothing was selected
+pub fn config() -> PoolConfig {
+    SELECTED
+        .get()
+        .map(|(_, config)| config.clone())
+        .unwrap_or_else(PoolConfig::from_env)
+}
 
-/// BN254 field size
-pub const FIELD_SIZE: &str = "21888242871839275222246405745257275088548364400416034343698204186575808495617";
+/// Cluster name used to tag notes with the network they were deposited on:
+/// the `--network` selection, or else guessed from the RPC URL
+pub fn network_name(rpc_url: &str) -> &'static str {
+    if let Some((Some(network), _)) = SELECTED.get() {
+        return network.name();
+    }
+    let url = rpc_url.to_ascii_lowercase();
+    if url.contains("devnet") {
+        "devnet"
+    } else if url.contains("testnet") {
+        "testnet"
+    } else if url.contains("mainnet") {
+        "mainnet-beta"
+    } else if url.contains("localhost") || url.contains("127.0.0.1") {
+        "localnet"
+    } else {
+        "custom"
+    }
+}
 
-/// Transact instruction discriminator
-pub const TRANSACT_DISCRIMINATOR: [u8; 8] = [217, 149, 130, 143, 221, 52, 252, 119];
+/// Solana Explorer link for a transaction sent through `rpc_url`
+pub fn explorer_tx_url(rpc_url: &str, signature: &str) -> String {
+    match network_name(rpc_url).parse::<Network>() {
+        Ok(network) => network.explorer_tx_url(signature),
+        Err(_) => format!(
+            "https://explorer.solana.com/tx/{}?cluster=custom&customUrl={}",
+            signature, rpc_url
+        ),
+    }
+}
@@ -0,0 +1,116 @@
+//! How well a withdrawal hides among the pool's other notes
+//!
+//! The proof hides which deposit a withdrawal spends, but only among the
+//! deposits it could plausibly be: a 5 SOL withdrawal from a note deposited
+//! just before it, with no other deposits of that size since, points to
+//! that deposit as surely as a signature would. These estimates use only
+//! what anyone can read from the pool's history, the sign and size of each
+//! transaction's `public_amount`, so they are what an observer sees too.
+
+use anyhow::{anyhow, Result};
+
+/// Fewer comparable deposits than this since a note's is a warning in red
+pub const LOW_ANONYMITY_SET: u64 = 5;
+
+/// At least this many is shown in green
+pub const GOOD_ANONYMITY_SET: u64 = 20;
+
+/// How loudly to warn about an anonymity set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    Low,
+    Fair,
+    Good,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnonymitySet {
+    /// Deposits of a comparable amount made after the note's
+    pub later_deposits: u64,
+    /// Estimated unspent notes in the pool, as `stats` counts them:
+    /// deposits less withdrawals
+    pub unspent_notes: u64,
+}
+
+impl AnonymitySet {
+    /// The set of a note of `amount` deposited by transaction
+    /// `note_transaction`, given the `public_amount` of every transaction
+    /// in the pool, oldest first
+    pub fn of(public_amounts: &[i64], note_transaction: usize, amount: u64) -> Self {
+        let deposits = public_amounts.iter().filter(|&&a| a > 0).count() as u64;
+        let withdrawals = public_amounts.iter().filter(|&&a| a < 0).count() as u64;
+        let later_deposits = public_amounts
+            .iter()
+            .skip(note_transaction + 1)
+            .filter(|&&a| a > 0 && comparable(a as u64, amount))
+            .count() as u64;
+        Self { later_deposits, unspent_notes: deposits.saturating_sub(withdrawals) }
+    }
+
+    pub fn tier(&self) -> Tier {
+        match self.later_deposits {
+            n if n < LOW_ANONYMITY_SET => Tier::Low,
+            n if n < GOOD_ANONYMITY_SET => Tier::Fair,
+            _ => Tier::Good,
+        }
+    }
+
+    /// An error if fewer than `min` comparable deposits followed the note's
+    pub fn check(&self, min: u64) -> Result<()> {
+        if self.later_deposits >= min {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "Only {} deposits of a comparable amount since this note's, below \
+             --min-anonymity-set {}; nothing was sent. Wait for more deposits",
+            self.later_deposits,
+            min
+        ))
+    }
+}
+
+/// Whether a deposit of `deposit` could pass for a note of `amount`:
+/// between half and twice its size
+fn comparable(deposit: u64, amount: u64) -> bool {
+    deposit >= amount / 2 && deposit <= amount.saturating_mul(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOL: i64 = 1_000_000_000;
+
+    #[test]
+    fn test_counts_comparable_deposits_after_the_note() {
+        // The note is transaction 1; a withdrawal, a transfer, a deposit too
+        // small and one too large don't count
+        let history = [SOL, SOL, 2 * SOL, -SOL, 0, SOL / 10, 3 * SOL, SOL / 2];
+        let set = AnonymitySet::of(&history, 1, SOL as u64);
+        assert_eq!(set, AnonymitySet { later_deposits: 2, unspent_notes: 5 });
+        assert_eq!(set.tier(), Tier::Low);
+
+        // The newest note has nothing after it
+        let set = AnonymitySet::of(&history, history.len() - 1, SOL as u64);
+        assert_eq!(set.later_deposits, 0);
+    }
+
+    #[test]
+    fn test_tiers() {
+        let set = |later_deposits| AnonymitySet { later_deposits, unspent_notes: 100 };
+        assert_eq!(set(0).tier(), Tier::Low);
+        assert_eq!(set(LOW_ANONYMITY_SET - 1).tier(), Tier::Low);
+        assert_eq!(set(LOW_ANONYMITY_SET).tier(), Tier::Fair);
+        assert_eq!(set(GOOD_ANONYMITY_SET - 1).tier(), Tier::Fair);
+        assert_eq!(set(GOOD_ANONYMITY_SET).tier(), Tier::Good);
+    }
+
+    #[test]
+    fn test_minimum_anonymity_set() {
+        let set = AnonymitySet { later_deposits: 3, unspent_notes: 10 };
+        assert!(set.check(3).is_ok());
+        let err = set.check(4).unwrap_err().to_string();
+        assert!(err.contains("Only 3 deposits"), "{}", err);
+        assert!(err.contains("nothing was sent"), "{}", err);
+    }
+}
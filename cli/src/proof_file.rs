@@ -0,0 +1,222 @@
+//! Proofs generated by `privacy prove` and sent by `privacy submit`
+//!
+//! Proving is the expensive step and needs the note's keys; submitting only
+//! needs the proof. Splitting them lets the proof be generated on an
+//! air-gapped machine and carried over as a JSON file.
+
+use anyhow::{anyhow, Context, Result};
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::crypto::{be_bytes_to_fr_strict, fr_to_be_bytes, MerkleTree};
+use crate::prover::TransactProofData;
+
+/// 2 added `relayer` and `fee`, which the proof's `extDataHash` commits to;
+/// 3 commits to whole addresses instead of their first 8 bytes
+pub const PROOF_FILE_VERSION: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProofFile {
+    pub version: u32,
+    /// Network and pool tree account the proof was generated against
+    pub network: String,
+    pub pool: String,
+    /// Note being withdrawn, so `submit` can mark it spent
+    pub note_id: String,
+    pub recipient: String,
+    /// Who the proof is for: the relayer's address, or the wallet's for
+    /// `submit --direct`
+    pub relayer: String,
+    /// Relayer fee the proof commits to, 0 when sent directly
+    pub fee: u64,
+    /// Number of leaves in the tree when the proof was generated
+    pub leaf_count: u64,
+    pub created_at: u64,
+    pub proof: TransactProofData,
+}
+
+/// How the current on-chain root relates to the proof's root
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootFreshness {
+    Current,
+    /// Deposits landed since the proof was generated; it only verifies while
+    /// its root is still in the program's recent root history
+    Advanced { leaves_added: u64 },
+    /// So many transactions landed since that the root has left the
+    /// program's root history; the program rejects the proof
+    Expired { leaves_added: u64 },
+    /// The pool's tree never had this root at the proof's leaf count, as
+    /// for a proof against another deployment or a tree missing commitments
+    Unknown,
+}
+
+impl ProofFile {
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        fs::write(path, data).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&data).with_context(|| format!("Invalid proof file {}", path.display()))
+    }
+
+    fn parse(data: &str) -> Result<Self> {
+        let version = serde_json::from_str::<serde_json::Value>(data)?["version"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("missing version"))?;
+        if version != PROOF_FILE_VERSION as u64 {
+            return Err(anyhow!(
+                "proof file version {} is not supported (this build reads {}); \
+                 run 'privacy prove' again",
+                version,
+                PROOF_FILE_VERSION
+            ));
+        }
+        let file: Self = serde_json::from_str(data)?;
+        be_bytes_to_fr_strict(&file.proof.root).context("root is not a field element")?;
+        Ok(file)
+    }
+
+    /// Merkle root the proof was generated against; a loaded file's root
+    /// has been checked to be in the field, so nothing is reduced
+    pub fn root(&self) -> Fr {
+        Fr::from_be_bytes_mod_order(&self.proof.root)
+    }
+
+    /// Where the proof's root stands against `tree`, rebuilt from the
+    /// pool's history, and `root_history`, the roots the program accepts
+    pub fn freshness(&self, tree: &MerkleTree, root_history: &[[u8; 32]]) -> RootFreshness {
+        let root = self.root();
+        if root == tree.root() {
+            return RootFreshness::Current;
+        }
+        let leaves_added = (tree.leaf_count() as u64).saturating_sub(self.leaf_count);
+        if root_history.contains(&fr_to_be_bytes(&root)) {
+            return RootFreshness::Advanced { leaves_added };
+        }
+        match usize::try_from(self.leaf_count).map(|leaves| tree.root_after(leaves)) {
+            Ok(Ok(earlier)) if earlier == root => RootFreshness::Expired { leaves_added },
+            _ => RootFreshness::Unknown,
+        }
+    }
+}
+
+/// The proof in `path`: a proof file from `prove`, or a bare
+/// [`TransactProofData`] as JSON, such as a fixture pinning a known-good proof
+pub fn read_proof(path: &Path) -> Result<TransactProofData> {
+    let data = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_proof(&data).with_context(|| format!("Invalid proof file {}", path.display()))
+}
+
+fn parse_proof(data: &str) -> Result<TransactProofData> {
+    if serde_json::from_str::<serde_json::Value>(data)?.get("version").is_some() {
+        return Ok(ProofFile::parse(data)?.proof);
+    }
+    Ok(serde_json::from_str(data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::MERKLE_TREE_HEIGHT;
+
+    fn proof_file(root: Fr) -> ProofFile {
+        ProofFile {
+            version: PROOF_FILE_VERSION,
+            network: "testnet".to_string(),
+            pool: "4EGnTF2XfKDTBAszzoqQLe4zbmiURkWtkYQGnj99GiJf".to_string(),
+            note_id: "note_1".to_string(),
+            recipient: "FM7WTd5Hr7ppp6vu3M4uAspF4DoRjrYPPFvAmqB7H95D".to_string(),
+            relayer: "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin".to_string(),
+            fee: 250_000,
+            leaf_count: 26,
+            created_at: 1_736_000_000,
+            proof: TransactProofData {
+                proof_a: vec![1; 64],
+                proof_b: vec![2; 128],
+                proof_c: vec![3; 64],
+                root: fr_to_be_bytes(&root).to_vec(),
+                nullifier1: vec![5; 32],
+                nullifier2: vec![6; 32],
+                commitment1: vec![7; 32],
+                commitment2: vec![8; 32],
+                public_amount: -100_000_000,
+                ext_data_hash: vec![9; 32],
+            },
+        }
+    }
+
+    #[test]
+    fn test_round_trip_keeps_instruction_data() {
+        let original = proof_file(Fr::from(42u64));
+        let parsed = ProofFile::parse(&serde_json::to_string(&original).unwrap()).unwrap();
+        assert_eq!(parsed, original);
+        assert_eq!(parsed.proof.to_instruction_data(), original.proof.to_instruction_data());
+    }
+
+    #[test]
+    fn test_detects_advanced_and_expired_roots() {
+        let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+        tree.insert_many(&(1..=26u64).map(Fr::from).collect::<Vec<_>>()).unwrap();
+        let file = proof_file(tree.root());
+        let history = [fr_to_be_bytes(&tree.root())];
+        assert_eq!(file.freshness(&tree, &history), RootFreshness::Current);
+
+        tree.insert_many(&(27..=30u64).map(Fr::from).collect::<Vec<_>>()).unwrap();
+        let history = [history[0], fr_to_be_bytes(&tree.root())];
+        assert_eq!(file.freshness(&tree, &history), RootFreshness::Advanced { leaves_added: 4 });
+        // Rotated out of the program's history
+        assert_eq!(file.freshness(&tree, &history[1..]), RootFreshness::Expired { leaves_added: 4 });
+
+        let stranger = proof_file(Fr::from(42u64));
+        assert_eq!(stranger.freshness(&tree, &history), RootFreshness::Unknown);
+    }
+
+    #[test]
+    fn test_reads_proof_files_and_bare_proofs() {
+        use privacy_zig_core::{verifying_key_from_snarkjs, ProofError};
+
+        let file = proof_file(Fr::from(42u64));
+        let proof = parse_proof(&serde_json::to_string(&file).unwrap()).unwrap();
+        assert_eq!(proof, file.proof);
+
+        // A test key with a known trapdoor, and a proof simulated with it
+        let vk = verifying_key_from_snarkjs(include_str!("../tests/fixtures/verify/vk.json")).unwrap();
+        let mut proof = parse_proof(include_str!("../tests/fixtures/verify/proof.json")).unwrap();
+        assert_eq!(proof.verify(&vk), Ok(()));
+        proof.public_amount += 1;
+        assert_eq!(proof.verify(&vk), Err(ProofError::Invalid));
+
+        assert!(parse_proof(r#"{"proof_a": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_rejects_root_outside_the_field() {
+        let mut file = proof_file(Fr::from(1u64));
+        file.proof.root = vec![0xff; 32];
+        let err = ProofFile::parse(&serde_json::to_string(&file).unwrap()).unwrap_err();
+        assert!(format!("{:#}", err).contains("root is not a field element"));
+    }
+
+    #[test]
+    fn test_rejects_unknown_version() {
+        let mut file = proof_file(Fr::from(1u64));
+        file.version = 4;
+        let err = ProofFile::parse(&serde_json::to_string(&file).unwrap()).unwrap_err();
+        assert!(err.to_string().contains("version 4"));
+
+        // Version 1 proofs commit to neither relayer nor fee, and version 2
+        // ones to only a prefix of each address
+        for version in [1, 2] {
+            file.version = version;
+            let err = ProofFile::parse(&serde_json::to_string(&file).unwrap()).unwrap_err();
+            assert!(err.to_string().contains("run 'privacy prove' again"));
+        }
+    }
+}
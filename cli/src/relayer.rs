@@ -0,0 +1,232 @@
+//! Client for the withdrawal relayer
+//!
+//! The relayer signs and pays for withdrawals so the user's wallet never
+//! appears in them. It exposes `GET /info` (its address and balance),
+//! `POST /relay`, which queues a withdrawal and returns a job id, and
+//! `GET /status/:job_id`. Relayers that require an API key get it from
+//! `--relayer-api-key` as a bearer token on the last two. A token
+//! withdrawal's request names its `mint`.
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use indicatif::ProgressBar;
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::prover::TransactProofData;
+
+/// Balance the relayer needs to pay for a withdrawal: the transaction fee
+/// plus rent for the two nullifier accounts it creates
+pub const MIN_RELAYER_BALANCE_SOL: f64 = 0.01;
+
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long to wait for a queued withdrawal before giving up on polling
+const STATUS_TIMEOUT: Duration = Duration::from_secs(300);
+
+static API_KEY: OnceLock<Option<String>> = OnceLock::new();
+
+/// Use `key` for the rest of the run
+pub fn set_api_key(key: Option<String>) {
+    let _ = API_KEY.set(key.filter(|k| !k.is_empty()));
+}
+
+/// Attach the API key, if any
+fn authorized(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match API_KEY.get().and_then(Option::as_deref) {
+        Some(key) => request.bearer_auth(key),
+        None => request,
+    }
+}
+
+/// `GET /info`
+#[derive(Debug, Deserialize)]
+pub struct RelayerInfo {
+    pub relayer_address: String,
+    pub program_id: String,
+    /// SOL
+    pub balance: f64,
+}
+
+impl RelayerInfo {
+    /// The relayer's signing address, which its withdrawals' proofs commit to
+    pub fn address(&self) -> Result<Pubkey> {
+        self.relayer_address
+            .parse()
+            .map_err(|_| anyhow!("Relayer reported an invalid address"))
+    }
+
+    /// Fail if the relayer serves another program or can't afford to send
+    pub fn check(&self, program_id: &str) -> Result<()> {
+        if self.program_id != program_id {
+            return Err(anyhow!(
+                "Relayer serves program {}, but the CLI uses {}",
+                self.program_id,
+                program_id
+            ));
+        }
+        if self.balance < MIN_RELAYER_BALANCE_SOL {
+            return Err(anyhow!(
+                "Relayer {} has {} SOL, less than the {} SOL needed to send a withdrawal",
+                self.relayer_address,
+                self.balance,
+                MIN_RELAYER_BALANCE_SOL
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// `GET /status/:job_id`
+#[derive(Debug, Deserialize)]
+pub struct JobStatus {
+    pub status: String,
+    pub signature: Option<String>,
+    pub error: Option<String>,
+}
+
+impl JobStatus {
+    /// The signature once confirmed, an error once failed, `None` while pending
+    fn outcome(&self) -> Option<Result<String>> {
+        match self.status.as_str() {
+            "confirmed" => Some(
+                self.signature
+                    .clone()
+                    .ok_or_else(|| anyhow!("No signature in confirmed job")),
+            ),
+            "failed" => Some(Err(anyhow!(
+                "Relayer error: {}",
+                self.error.as_deref().unwrap_or("Unknown error")
+            ))),
+            _ => None,
+        }
+    }
+}
+
+pub async fn info(relayer_url: &str) -> Result<RelayerInfo> {
+    reqwest::get(format!("{}/info", relayer_url))
+        .await
+        .map_err(|e| anyhow!("Failed to connect to relayer: {}", e))?
+        .json()
+        .await
+        .map_err(|e| anyhow!("Invalid relayer info: {}", e))
+}
+
+/// Have the relayer sign and send a withdrawal, and wait for it to be
+/// confirmed, returning its signature
+pub async fn relay_withdrawal(
+    relayer_url: &str,
+    proof_data: &TransactProofData,
+    recipient: &str,
+    mint: Option<&Pubkey>,
+    pb: &ProgressBar,
+) -> Result<String> {
+    let mut relay_request = serde_json::json!({
+        "instruction_data": BASE64.encode(proof_data.to_instruction_data()),
+        "nullifier1": hex::encode(&proof_data.nullifier1),
+        "nullifier2": hex::encode(&proof_data.nullifier2),
+        "recipient": recipient,
+    });
+    // Token withdrawals name their mint; the relayer pays the recipient's
+    // token account from that mint's pool
+    if let Some(mint) = mint {
+        relay_request["mint"] = mint.to_string().into();
+    }
+
+    let http_client = reqwest::Client::new();
+    let response = authorized(http_client.post(format!("{}/relay", relayer_url)))
+        .json(&relay_request)
+        .send()
+        .await
+        .map_err(|e| anyhow!("Failed to connect to relayer: {}", e))?;
+
+    let relay_result: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| anyhow!("Invalid relayer response: {}", e))?;
+
+    if !relay_result["success"].as_bool().unwrap_or(false) {
+        let error = relay_result["error"].as_str().unwrap_or("Unknown error");
+        return Err(anyhow!("Relayer error: {}", error));
+    }
+
+    let job_id = relay_result["job_id"]
+        .as_str()
+        .ok_or_else(|| anyhow!("No job id in relayer response"))?;
+
+    let started = Instant::now();
+    loop {
+        let status_url = format!("{}/status/{}", relayer_url, job_id);
+        let status: JobStatus = authorized(http_client.get(status_url))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| anyhow!("Failed to get status of relay job {}: {}", job_id, e))?
+            .json()
+            .await
+            .map_err(|e| anyhow!("Invalid relayer status: {}", e))?;
+        if let Some(outcome) = status.outcome() {
+            return outcome;
+        }
+
+        match &status.signature {
+            Some(signature) => pb.set_message(format!("Relayer sent {}, confirming...", signature)),
+            None => pb.set_message(format!("Queued at relayer (job {})...", job_id)),
+        }
+        if started.elapsed() > STATUS_TIMEOUT {
+            return Err(anyhow!(
+                "Relay job {} is still {}; check {}/status/{} later",
+                job_id,
+                status.status,
+                relayer_url,
+                job_id
+            ));
+        }
+        tokio::time::sleep(STATUS_POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "9A6fck3xNW2C6vwwqM4i1f4GeYpieuB7XKpF1YFduT6h";
+
+    fn relayer_info(program_id: &str, balance: f64) -> RelayerInfo {
+        RelayerInfo {
+            relayer_address: "FcuLoWBhZ8bNQRsSgGhH5NCJJbqK5uhHMZR6V21kyTgS".to_string(),
+            program_id: program_id.to_string(),
+            balance,
+        }
+    }
+
+    #[test]
+    fn test_checks_relayer_balance() {
+        assert!(relayer_info(PROGRAM, 1.5).check(PROGRAM).is_ok());
+        let err = relayer_info(PROGRAM, 0.001).check(PROGRAM).unwrap_err();
+        assert!(err.to_string().contains("0.001 SOL"));
+    }
+
+    #[test]
+    fn test_job_outcome() {
+        let status = |json: &str| serde_json::from_str::<JobStatus>(json).unwrap();
+        assert!(status(r#"{"job_id":"j","status":"queued","signature":null,"error":null}"#)
+            .outcome()
+            .is_none());
+        assert!(status(r#"{"job_id":"j","status":"submitted","signature":"abc","error":null}"#)
+            .outcome()
+            .is_none());
+        let confirmed = status(r#"{"job_id":"j","status":"confirmed","signature":"abc","error":null}"#);
+        assert_eq!(confirmed.outcome().unwrap().unwrap(), "abc");
+        let failed = status(r#"{"job_id":"j","status":"failed","signature":null,"error":"spent"}"#);
+        assert!(failed.outcome().unwrap().unwrap_err().to_string().contains("spent"));
+    }
+
+    #[test]
+    fn test_rejects_relayer_for_other_program() {
+        let err = relayer_info("11111111111111111111111111111111", 1.5).check(PROGRAM).unwrap_err();
+        assert!(err.to_string().contains("serves program"));
+    }
+}
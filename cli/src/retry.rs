@@ -0,0 +1,133 @@
+//! Retrying RPC calls on transient errors
+//!
+//! Public endpoints rate-limit and time out often enough that one failed call
+//! shouldn't abort a deposit halfway through. Failed attempts are retried
+//! after an exponential backoff with jitter, up to `--rpc-retries` times, and
+//! the error that finally comes back says how many attempts were made.
+//!
+//! Only requests that are safe to repeat go through here: reads, and sending
+//! an already signed transaction, which can land at most once.
+
+use anyhow::{anyhow, Result};
+use std::fmt::Display;
+use std::time::Duration;
+
+pub const DEFAULT_RETRIES: u32 = 3;
+
+/// Delay before the first retry, doubled for each one after it
+const BASE_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub retries: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(retries: u32) -> Self {
+        Self {
+            retries,
+            base_delay: BASE_DELAY,
+        }
+    }
+
+    /// Exponential backoff for `retry`, scaled into its upper half by
+    /// `jitter` in `[0, 1)`
+    pub fn delay(&self, retry: u32, jitter: f64) -> Duration {
+        let backoff = self.base_delay.saturating_mul(1 << retry.min(16));
+        backoff / 2 + backoff.mul_f64(jitter.clamp(0.0, 1.0) / 2.0)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_RETRIES)
+    }
+}
+
+/// Run `f` until it succeeds, fails with an error `retryable` rejects, or
+/// the retries run out; `f` is given the attempt number, starting at 0
+pub fn retry<T, E>(
+    policy: &RetryPolicy,
+    retryable: impl Fn(&E) -> bool,
+    mut f: impl FnMut(u32) -> Result<T, E>,
+) -> Result<T>
+where
+    E: Display + Into<anyhow::Error>,
+{
+    let mut attempt = 0;
+    loop {
+        let err = match f(attempt) {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+        if attempt < policy.retries && retryable(&err) {
+            std::thread::sleep(policy.delay(attempt, rand::random()));
+            attempt += 1;
+            continue;
+        }
+        return Err(match attempt {
+            0 => err.into(),
+            _ => anyhow!("{} (gave up after {} attempts)", err, attempt + 1),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_DELAY: RetryPolicy = RetryPolicy {
+        retries: 2,
+        base_delay: Duration::ZERO,
+    };
+
+    fn transient(err: &anyhow::Error) -> bool {
+        err.to_string() == "429 Too Many Requests"
+    }
+
+    #[test]
+    fn test_retries_transient_errors() {
+        let result = retry(&NO_DELAY, transient, |attempt| match attempt {
+            0 | 1 => Err(anyhow!("429 Too Many Requests")),
+            _ => Ok(attempt),
+        });
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[test]
+    fn test_reports_attempts_when_giving_up() {
+        let mut calls = 0;
+        let err = retry(&NO_DELAY, transient, |_| -> Result<()> {
+            calls += 1;
+            Err(anyhow!("429 Too Many Requests"))
+        })
+        .unwrap_err();
+        assert_eq!(calls, 3);
+        assert_eq!(
+            err.to_string(),
+            "429 Too Many Requests (gave up after 3 attempts)"
+        );
+    }
+
+    #[test]
+    fn test_other_errors_are_not_retried() {
+        let mut calls = 0;
+        let err = retry(&NO_DELAY, transient, |_| -> Result<()> {
+            calls += 1;
+            Err(anyhow!("Invalid param"))
+        })
+        .unwrap_err();
+        assert_eq!(calls, 1);
+        assert_eq!(err.to_string(), "Invalid param");
+    }
+
+    #[test]
+    fn test_delay_backs_off_with_jitter() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(0, 0.0), Duration::from_millis(250));
+        assert_eq!(policy.delay(2, 0.0), Duration::from_secs(1));
+        assert!(policy.delay(2, 0.99) < Duration::from_secs(2));
+    }
+}
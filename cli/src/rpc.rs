@@ -0,0 +1,753 @@
+//! RPC abstraction for the chain
+//!
+//! Commands read the chain and send transactions through [`PoolRpc`]
+//! instead of `RpcClient` directly, so the logic can be tested against a
+//! [`Cassette`]: a JSON file of real responses recorded once from a cluster
+//! and replayed offline. Tests that build transactions use a `MockPoolRpc`,
+//! which keeps what would have been sent instead of sending it.
+//!
+//! To record a cassette, run any command with `PRIVACY_RECORD_CASSETTE` set:
+//!
+//! ```bash
+//! PRIVACY_RECORD_CASSETTE=tests/fixtures/cassettes/stats.json privacy stats
+//! ```
+//!
+//! New chain-reading code should go through `PoolRpc` and ship with a
+//! cassette-backed test.
+//!
+//! Live calls go through a [`ClientPool`], which fails over between the
+//! comma-separated `--rpc-url` endpoints and retries transient errors.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use privacy_zig_core::{Endpoint, RpcPool, DEFAULT_REPROBE_INTERVAL};
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_client::RpcClient,
+    rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig, hash::Hash, pubkey::Pubkey, signature::Signature,
+    transaction::Transaction,
+};
+use solana_transaction_status::{UiInstruction, UiLoadedAddresses, UiTransactionEncoding};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::retry::{self, RetryPolicy};
+
+/// Default for `--rpc-timeout`, in seconds
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// An `RpcClient` the [`ClientPool`] can fail over from
+pub struct RpcNode(RpcClient);
+
+impl Deref for RpcNode {
+    type Target = RpcClient;
+
+    fn deref(&self) -> &RpcClient {
+        &self.0
+    }
+}
+
+impl Endpoint for RpcNode {
+    type Error = ClientError;
+
+    fn url(&self) -> String {
+        self.0.url()
+    }
+
+    /// Rate limits and server errors surface as HTTP errors, timeouts and
+    /// refused connections as I/O errors
+    fn is_unavailable(err: &ClientError) -> bool {
+        matches!(err.kind(), ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_))
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.0.get_health().is_ok()
+    }
+}
+
+/// `--rpc-url`, `--rpc-timeout` and `--rpc-retries`
+#[derive(Debug, Clone)]
+pub struct RpcOptions {
+    pub urls: Vec<String>,
+    /// Per request
+    pub timeout: Duration,
+    pub retry: RetryPolicy,
+}
+
+/// Every `--rpc-url` endpoint, in order of preference
+pub struct ClientPool {
+    pool: RpcPool<RpcNode>,
+    retry: RetryPolicy,
+}
+
+impl ClientPool {
+    /// The endpoint calls currently go to
+    pub fn current(&self) -> &RpcNode {
+        self.pool.current()
+    }
+
+    pub fn endpoints(&self) -> &[RpcNode] {
+        self.pool.endpoints()
+    }
+
+    /// Run a request that is safe to repeat, failing over between endpoints
+    /// and retrying when all of them are unavailable
+    pub fn call<T>(&self, mut f: impl FnMut(&RpcNode) -> Result<T, ClientError>) -> Result<T> {
+        retry::retry(&self.retry, RpcNode::is_unavailable, |_| self.pool.call(&mut f))
+    }
+
+    /// Run a request that must not be repeated, such as an airdrop
+    pub fn call_once<T>(&self, f: impl FnMut(&RpcNode) -> Result<T, ClientError>) -> Result<T> {
+        Ok(self.pool.call(f)?)
+    }
+
+    /// Send a signed transaction and wait for it to confirm, retrying on
+    /// transient errors
+    ///
+    /// A failed call may still have landed, so before each retry we look
+    /// the signature up and only resend if the cluster hasn't seen it.
+    pub fn send_and_confirm(&self, tx: &Transaction) -> Result<Signature> {
+        let signature = tx.signatures[0];
+        retry::retry(&self.retry, RpcNode::is_unavailable, |attempt| {
+            if attempt > 0 {
+                if let Ok(Some(status)) = self.pool.call(|c| c.get_signature_status(&signature)) {
+                    return status.map(|()| signature).map_err(ClientError::from);
+                }
+            }
+            self.pool.call(|c| c.send_and_confirm_transaction(tx))
+        })
+    }
+}
+
+pub fn connect(options: &RpcOptions) -> ClientPool {
+    let nodes = options
+        .urls
+        .iter()
+        .map(|url| {
+            RpcNode(RpcClient::new_with_timeout_and_commitment(
+                url.clone(),
+                options.timeout,
+                CommitmentConfig::confirmed(),
+            ))
+        })
+        .collect();
+    ClientPool {
+        pool: RpcPool::new(nodes, DEFAULT_REPROBE_INTERVAL),
+        retry: options.retry,
+    }
+}
+
+/// Account contents as far as the CLI cares
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountInfo {
+    pub lamports: u64,
+    pub owner: String,
+    #[serde(with = "base64_bytes")]
+    pub data: Vec<u8>,
+}
+
+/// A confirmed transaction, reduced to its top-level instructions and logs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainTransaction {
+    pub failed: bool,
+    pub block_time: Option<i64>,
+    pub instructions: Vec<ChainInstruction>,
+    /// Program logs, empty if the cluster didn't return them
+    #[serde(default)]
+    pub log_messages: Vec<String>,
+    /// Instructions each top-level instruction invoked by CPI, by its
+    /// position; empty if the cluster didn't return them
+    #[serde(default)]
+    pub inner_instructions: Vec<Vec<ChainInstruction>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainInstruction {
+    pub program_id: String,
+    #[serde(with = "base64_bytes")]
+    pub data: Vec<u8>,
+}
+
+/// Outcome of a processed transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureStatus {
+    /// Transaction error, `None` if it succeeded
+    pub err: Option<String>,
+}
+
+/// The subset of the Solana RPC API the CLI goes through
+pub trait PoolRpc {
+    /// Endpoint URL, used to tell which network notes belong to
+    fn url(&self) -> String;
+
+    fn get_balance(&self, pubkey: &Pubkey) -> Result<u64>;
+
+    /// `None` if the account doesn't exist
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Option<AccountInfo>>;
+
+    /// Signatures involving `address`, newest first
+    fn get_signatures_for_address(&self, address: &Pubkey) -> Result<Vec<String>>;
+
+    /// `None` if the transaction can't be found
+    fn get_transaction(&self, signature: &str) -> Result<Option<ChainTransaction>>;
+
+    /// Status of each signature, searching the full ledger history; `None`
+    /// for signatures the cluster doesn't know
+    fn get_signature_statuses(&self, signatures: &[String]) -> Result<Vec<Option<SignatureStatus>>>;
+
+    /// Prioritization fees paid in recent slots by transactions that locked
+    /// any of `accounts`, in micro-lamports per compute unit
+    fn get_recent_prioritization_fees(&self, accounts: &[Pubkey]) -> Result<Vec<u64>>;
+
+    fn get_latest_blockhash(&self) -> Result<Hash>;
+
+    /// Compute units `tx` used in simulation, `None` if it failed or the
+    /// cluster didn't say
+    fn simulate_units(&self, tx: &Transaction) -> Result<Option<u64>>;
+
+    /// Send `tx` and wait for it to be confirmed
+    fn send_and_confirm_transaction(&self, tx: &Transaction) -> Result<Signature>;
+}
+
+impl PoolRpc for ClientPool {
+    fn url(&self) -> String {
+        self.current().url()
+    }
+
+    fn get_balance(&self, pubkey: &Pubkey) -> Result<u64> {
+        self.call(|c| c.get_balance(pubkey))
+    }
+
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Option<AccountInfo>> {
+        let account = self
+            .call(|c| c.get_account_with_commitment(pubkey, c.commitment()))?
+            .value;
+        Ok(account.map(|a| AccountInfo {
+            lamports: a.lamports,
+            owner: a.owner.to_string(),
+            data: a.data,
+        }))
+    }
+
+    fn get_signatures_for_address(&self, address: &Pubkey) -> Result<Vec<String>> {
+        Ok(self
+            .call(|c| c.get_signatures_for_address(address))?
+            .into_iter()
+            .map(|s| s.signature)
+            .collect())
+    }
+
+    fn get_transaction(&self, signature: &str) -> Result<Option<ChainTransaction>> {
+        let signature = Signature::from_str(signature)?;
+        let tx = match self.call(|c| {
+            c.get_transaction_with_config(
+                &signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+        }) {
+            Ok(tx) => tx,
+            // Pruned or not yet indexed
+            Err(_) => return Ok(None),
+        };
+
+        let failed = tx
+            .transaction
+            .meta
+            .as_ref()
+            .is_some_and(|meta| meta.err.is_some());
+        let Some(decoded) = tx.transaction.transaction.decode() else {
+            return Ok(None);
+        };
+        let mut keys = decoded.message.static_account_keys().to_vec();
+        let meta = tx.transaction.meta;
+        // Programs invoked by CPI may come from address lookup tables
+        let loaded: Option<UiLoadedAddresses> =
+            meta.as_ref().and_then(|m| m.loaded_addresses.clone().into());
+        if let Some(loaded) = loaded {
+            for address in loaded.writable.iter().chain(&loaded.readonly) {
+                keys.push(Pubkey::from_str(address)?);
+            }
+        }
+        let instructions: Vec<ChainInstruction> = decoded
+            .message
+            .instructions()
+            .iter()
+            .filter_map(|ix| {
+                keys.get(ix.program_id_index as usize).map(|program| ChainInstruction {
+                    program_id: program.to_string(),
+                    data: ix.data.clone(),
+                })
+            })
+            .collect();
+
+        let mut inner_instructions = vec![Vec::new(); instructions.len()];
+        let inner: Option<Vec<_>> = meta.as_ref().and_then(|m| m.inner_instructions.clone().into());
+        for invoked in inner.unwrap_or_default() {
+            let Some(calls) = inner_instructions.get_mut(invoked.index as usize) else {
+                continue;
+            };
+            for ix in invoked.instructions {
+                let UiInstruction::Compiled(ix) = ix else {
+                    continue;
+                };
+                if let (Some(program), Ok(data)) =
+                    (keys.get(ix.program_id_index as usize), bs58::decode(&ix.data).into_vec())
+                {
+                    calls.push(ChainInstruction { program_id: program.to_string(), data });
+                }
+            }
+        }
+
+        let log_messages = meta
+            .and_then(|meta| Option::<Vec<String>>::from(meta.log_messages))
+            .unwrap_or_default();
+
+        Ok(Some(ChainTransaction {
+            failed,
+            block_time: tx.block_time,
+            instructions,
+            log_messages,
+            inner_instructions,
+        }))
+    }
+
+    fn get_signature_statuses(&self, signatures: &[String]) -> Result<Vec<Option<SignatureStatus>>> {
+        let signatures = signatures
+            .iter()
+            .map(|s| Signature::from_str(s))
+            .collect::<Result<Vec<_>, _>>()?;
+        // The RPC accepts at most 256 signatures per request
+        let mut statuses = Vec::with_capacity(signatures.len());
+        for chunk in signatures.chunks(256) {
+            statuses.extend(
+                self.call(|c| c.get_signature_statuses_with_history(chunk))?
+                    .value
+                    .into_iter()
+                    .map(|status| {
+                        status.map(|s| SignatureStatus {
+                            err: s.err.map(|e| e.to_string()),
+                        })
+                    }),
+            );
+        }
+        Ok(statuses)
+    }
+
+    fn get_recent_prioritization_fees(&self, accounts: &[Pubkey]) -> Result<Vec<u64>> {
+        Ok(self
+            .call(|c| c.get_recent_prioritization_fees(accounts))?
+            .into_iter()
+            .map(|fee| fee.prioritization_fee)
+            .collect())
+    }
+
+    fn get_latest_blockhash(&self) -> Result<Hash> {
+        self.call(|c| c.get_latest_blockhash())
+    }
+
+    fn simulate_units(&self, tx: &Transaction) -> Result<Option<u64>> {
+        let result = self.call(|c| c.simulate_transaction(tx))?.value;
+        Ok(result.units_consumed.filter(|_| result.err.is_none()))
+    }
+
+    fn send_and_confirm_transaction(&self, tx: &Transaction) -> Result<Signature> {
+        self.send_and_confirm(tx)
+    }
+}
+
+/// One recorded call: the response or the error it produced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    method: String,
+    params: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    url: String,
+    interactions: Vec<Interaction>,
+}
+
+/// Records calls to a live [`PoolRpc`], or replays them from a file
+///
+/// On replay, each call consumes the next unused interaction with the same
+/// method and parameters, so repeated calls can return different results
+/// (e.g. a 429 followed by a success).
+pub struct Cassette {
+    file: RefCell<CassetteFile>,
+    used: RefCell<Vec<bool>>,
+    recorder: Option<(Box<dyn PoolRpc>, PathBuf)>,
+}
+
+impl Cassette {
+    /// Replay a recorded cassette
+    #[cfg(test)]
+    pub fn replay(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read cassette {}", path.display()))?;
+        Self::from_json(&data).with_context(|| format!("Invalid cassette {}", path.display()))
+    }
+
+    #[cfg(test)]
+    pub fn from_json(data: &str) -> Result<Self> {
+        let file: CassetteFile = serde_json::from_str(data)?;
+        let used = vec![false; file.interactions.len()];
+        Ok(Self {
+            file: RefCell::new(file),
+            used: RefCell::new(used),
+            recorder: None,
+        })
+    }
+
+    /// Forward calls to `inner` and write every response to `path`
+    pub fn record(inner: Box<dyn PoolRpc>, path: impl Into<PathBuf>) -> Self {
+        let file = CassetteFile {
+            url: inner.url(),
+            interactions: Vec::new(),
+        };
+        Self {
+            file: RefCell::new(file),
+            used: RefCell::new(Vec::new()),
+            recorder: Some((inner, path.into())),
+        }
+    }
+
+    fn call<T: Serialize + DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Vec<String>,
+        live: impl FnOnce(&dyn PoolRpc) -> Result<T>,
+    ) -> Result<T> {
+        match &self.recorder {
+            Some((inner, path)) => {
+                let result = live(inner.as_ref());
+                let interaction = match &result {
+                    Ok(value) => Interaction {
+                        method: method.to_string(),
+                        params,
+                        result: Some(serde_json::to_value(value)?),
+                        error: None,
+                    },
+                    Err(e) => Interaction {
+                        method: method.to_string(),
+                        params,
+                        result: None,
+                        error: Some(format!("{:#}", e)),
+                    },
+                };
+                let mut file = self.file.borrow_mut();
+                file.interactions.push(interaction);
+                fs::write(path, serde_json::to_string_pretty(&*file)?)
+                    .with_context(|| format!("Failed to write cassette {}", path.display()))?;
+                result
+            }
+            None => {
+                let file = self.file.borrow();
+                let mut used = self.used.borrow_mut();
+                let (i, interaction) = file
+                    .interactions
+                    .iter()
+                    .enumerate()
+                    .find(|(i, it)| !used[*i] && it.method == method && it.params == params)
+                    .ok_or_else(|| {
+                        anyhow!("cassette has no recorded {}({})", method, params.join(", "))
+                    })?;
+                used[i] = true;
+
+                match (&interaction.error, &interaction.result) {
+                    (Some(error), _) => Err(anyhow!("{}", error)),
+                    (None, Some(result)) => Ok(serde_json::from_value(result.clone())?),
+                    (None, None) => Ok(serde_json::from_value(Value::Null)?),
+                }
+            }
+        }
+    }
+}
+
+impl PoolRpc for Cassette {
+    fn url(&self) -> String {
+        self.file.borrow().url.clone()
+    }
+
+    fn get_balance(&self, pubkey: &Pubkey) -> Result<u64> {
+        self.call("get_balance", vec![pubkey.to_string()], |rpc| rpc.get_balance(pubkey))
+    }
+
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Option<AccountInfo>> {
+        self.call("get_account", vec![pubkey.to_string()], |rpc| rpc.get_account(pubkey))
+    }
+
+    fn get_signatures_for_address(&self, address: &Pubkey) -> Result<Vec<String>> {
+        self.call("get_signatures_for_address", vec![address.to_string()], |rpc| {
+            rpc.get_signatures_for_address(address)
+        })
+    }
+
+    fn get_transaction(&self, signature: &str) -> Result<Option<ChainTransaction>> {
+        self.call("get_transaction", vec![signature.to_string()], |rpc| {
+            rpc.get_transaction(signature)
+        })
+    }
+
+    fn get_signature_statuses(&self, signatures: &[String]) -> Result<Vec<Option<SignatureStatus>>> {
+        self.call("get_signature_statuses", signatures.to_vec(), |rpc| {
+            rpc.get_signature_statuses(signatures)
+        })
+    }
+
+    fn get_recent_prioritization_fees(&self, accounts: &[Pubkey]) -> Result<Vec<u64>> {
+        let params = accounts.iter().map(Pubkey::to_string).collect();
+        self.call("get_recent_prioritization_fees", params, |rpc| {
+            rpc.get_recent_prioritization_fees(accounts)
+        })
+    }
+
+    fn get_latest_blockhash(&self) -> Result<Hash> {
+        let hash = self.call("get_latest_blockhash", vec![], |rpc| {
+            rpc.get_latest_blockhash().map(|hash| hash.to_string())
+        })?;
+        Ok(Hash::from_str(&hash)?)
+    }
+
+    // Transactions are keyed by signature, which is stable on replay since
+    // the blockhash they sign over is replayed too
+    fn simulate_units(&self, tx: &Transaction) -> Result<Option<u64>> {
+        self.call("simulate_units", vec![tx.signatures[0].to_string()], |rpc| {
+            rpc.simulate_units(tx)
+        })
+    }
+
+    fn send_and_confirm_transaction(&self, tx: &Transaction) -> Result<Signature> {
+        let signature = self.call(
+            "send_and_confirm_transaction",
+            vec![tx.signatures[0].to_string()],
+            |rpc| rpc.send_and_confirm_transaction(tx).map(|s| s.to_string()),
+        )?;
+        Ok(Signature::from_str(&signature)?)
+    }
+}
+
+/// Reads from a [`Cassette`]; transactions are simulated as using a fixed
+/// number of units and kept in [`sent`](Self::sent) instead of being sent
+#[cfg(test)]
+pub struct MockPoolRpc {
+    reads: Cassette,
+    blockhash: Hash,
+    units: Option<u64>,
+    simulated: RefCell<Vec<Transaction>>,
+    sent: RefCell<Vec<Transaction>>,
+}
+
+#[cfg(test)]
+impl MockPoolRpc {
+    pub fn new(reads: Cassette, units: Option<u64>) -> Self {
+        Self {
+            reads,
+            blockhash: Hash::new_unique(),
+            units,
+            simulated: RefCell::new(Vec::new()),
+            sent: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn blockhash(&self) -> Hash {
+        self.blockhash
+    }
+
+    /// Transactions simulated so far, in order
+    pub fn simulated(&self) -> Vec<Transaction> {
+        self.simulated.borrow().clone()
+    }
+
+    /// Transactions sent so far, in order
+    pub fn sent(&self) -> Vec<Transaction> {
+        self.sent.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+impl PoolRpc for MockPoolRpc {
+    fn url(&self) -> String {
+        self.reads.url()
+    }
+
+    fn get_balance(&self, pubkey: &Pubkey) -> Result<u64> {
+        self.reads.get_balance(pubkey)
+    }
+
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Option<AccountInfo>> {
+        self.reads.get_account(pubkey)
+    }
+
+    fn get_signatures_for_address(&self, address: &Pubkey) -> Result<Vec<String>> {
+        self.reads.get_signatures_for_address(address)
+    }
+
+    fn get_transaction(&self, signature: &str) -> Result<Option<ChainTransaction>> {
+        self.reads.get_transaction(signature)
+    }
+
+    fn get_signature_statuses(&self, signatures: &[String]) -> Result<Vec<Option<SignatureStatus>>> {
+        self.reads.get_signature_statuses(signatures)
+    }
+
+    fn get_recent_prioritization_fees(&self, accounts: &[Pubkey]) -> Result<Vec<u64>> {
+        self.reads.get_recent_prioritization_fees(accounts)
+    }
+
+    fn get_latest_blockhash(&self) -> Result<Hash> {
+        Ok(self.blockhash)
+    }
+
+    fn simulate_units(&self, tx: &Transaction) -> Result<Option<u64>> {
+        self.simulated.borrow_mut().push(tx.clone());
+        Ok(self.units)
+    }
+
+    fn send_and_confirm_transaction(&self, tx: &Transaction) -> Result<Signature> {
+        self.sent.borrow_mut().push(tx.clone());
+        Ok(tx.signatures[0])
+    }
+}
+
+/// Reads the pool's history through a file of the transactions already
+/// read, so a command only fetches those it hasn't seen
+///
+/// Signatures are still listed from the cluster, which is how new
+/// transactions are found. A transaction the cluster can't find isn't
+/// kept, so it's asked for again next time. Every other call goes straight
+/// to the wrapped RPC.
+pub struct CachedHistory<'a> {
+    inner: &'a dyn PoolRpc,
+    path: PathBuf,
+    transactions: RefCell<HashMap<String, ChainTransaction>>,
+    /// Transactions in the file when it was opened
+    loaded: usize,
+}
+
+impl<'a> CachedHistory<'a> {
+    /// Wrap `inner`, starting from the transactions saved at `path`; a
+    /// missing or unreadable file starts empty and is replaced on
+    /// [`save`](Self::save)
+    pub fn open(inner: &'a dyn PoolRpc, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let transactions: HashMap<String, ChainTransaction> = fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        Self {
+            inner,
+            path,
+            loaded: transactions.len(),
+            transactions: RefCell::new(transactions),
+        }
+    }
+
+    /// Write the transactions read so far, if any are new
+    pub fn save(&self) -> Result<()> {
+        let transactions = self.transactions.borrow();
+        if transactions.len() == self.loaded {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Written aside and renamed, so an interrupted write leaves the old file
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(&*transactions)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace {}", self.path.display()))
+    }
+}
+
+impl PoolRpc for CachedHistory<'_> {
+    fn url(&self) -> String {
+        self.inner.url()
+    }
+
+    fn get_balance(&self, pubkey: &Pubkey) -> Result<u64> {
+        self.inner.get_balance(pubkey)
+    }
+
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Option<AccountInfo>> {
+        self.inner.get_account(pubkey)
+    }
+
+    fn get_signatures_for_address(&self, address: &Pubkey) -> Result<Vec<String>> {
+        self.inner.get_signatures_for_address(address)
+    }
+
+    fn get_transaction(&self, signature: &str) -> Result<Option<ChainTransaction>> {
+        if let Some(tx) = self.transactions.borrow().get(signature) {
+            return Ok(Some(tx.clone()));
+        }
+        let tx = self.inner.get_transaction(signature)?;
+        if let Some(tx) = &tx {
+            self.transactions.borrow_mut().insert(signature.to_string(), tx.clone());
+        }
+        Ok(tx)
+    }
+
+    fn get_signature_statuses(&self, signatures: &[String]) -> Result<Vec<Option<SignatureStatus>>> {
+        self.inner.get_signature_statuses(signatures)
+    }
+
+    fn get_recent_prioritization_fees(&self, accounts: &[Pubkey]) -> Result<Vec<u64>> {
+        self.inner.get_recent_prioritization_fees(accounts)
+    }
+
+    fn get_latest_blockhash(&self) -> Result<Hash> {
+        self.inner.get_latest_blockhash()
+    }
+
+    fn simulate_units(&self, tx: &Transaction) -> Result<Option<u64>> {
+        self.inner.simulate_units(tx)
+    }
+
+    fn send_and_confirm_transaction(&self, tx: &Transaction) -> Result<Signature> {
+        self.inner.send_and_confirm_transaction(tx)
+    }
+}
+
+/// RPC for read-only commands: the cluster at `options.urls`, recorded to a
+/// cassette if `PRIVACY_RECORD_CASSETTE` is set
+pub fn from_env(options: &RpcOptions) -> Box<dyn PoolRpc> {
+    let client = connect(options);
+    match std::env::var("PRIVACY_RECORD_CASSETTE") {
+        Ok(path) if !path.is_empty() => Box::new(Cassette::record(Box::new(client), path)),
+        _ => Box::new(client),
+    }
+}
+
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        STANDARD.decode(s).map_err(serde::de::Error::custom)
+    }
+}
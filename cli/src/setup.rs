@@ -0,0 +1,640 @@
+//! Guided first-run setup and `privacy doctor`
+//!
+//! Setup walks a new user through choosing a network, choosing (or
+//! creating) a wallet, creating the note store, encrypting it with a
+//! passphrase, choosing a directory to back it up to and downloading the
+//! circuit artifacts, then runs the doctor checks. Every step can be skipped
+//! and re-run later with `privacy setup --step <name>`. With `--no-input` the
+//! answers come from a JSON file so machines can be provisioned unattended.
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use console::style;
+use dialoguer::{Confirm, Input, Password, Select};
+use privacy_zig_core::keypair::{self, load_keypair};
+use serde::Deserialize;
+use solana_sdk::{
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    signature::{write_keypair_file, Keypair, Signer},
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::artifacts;
+use crate::config::CliConfig;
+use crate::crypto::SecretString;
+use crate::notes::NoteStore;
+use crate::output::say;
+use crate::pool::{self, Network};
+use crate::rpc::{self, RpcOptions};
+use crate::store_key::{self, PASSPHRASE_ENV};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SetupStep {
+    /// Choose the cluster to use, saved to the config file
+    Network,
+    /// Choose or create a wallet, optionally funded from the devnet faucet
+    Wallet,
+    /// Create the local note store
+    Store,
+    /// Encrypt the note store with a passphrase
+    Passphrase,
+    /// Choose a directory every save copies the note store into
+    Backup,
+    /// Download the circuit artifacts if they're missing
+    Artifacts,
+    /// Run `privacy doctor`
+    Check,
+}
+
+impl SetupStep {
+    pub const ALL: [SetupStep; 7] = [
+        SetupStep::Network,
+        SetupStep::Wallet,
+        SetupStep::Store,
+        SetupStep::Passphrase,
+        SetupStep::Backup,
+        SetupStep::Artifacts,
+        SetupStep::Check,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            SetupStep::Network => "Network",
+            SetupStep::Wallet => "Wallet",
+            SetupStep::Store => "Note store",
+            SetupStep::Passphrase => "Store passphrase",
+            SetupStep::Backup => "Backup target",
+            SetupStep::Artifacts => "Circuit artifacts",
+            SetupStep::Check => "Verify setup",
+        }
+    }
+}
+
+/// Answers for `privacy setup --no-input`
+///
+/// ```json
+/// { "network": "devnet", "keypair": "/srv/privacy/id.json", "create_wallet": true, "airdrop_sol": 1.0,
+///   "encrypt_store": true, "backup_dir": "/mnt/backup/privacy", "skip": ["artifacts"] }
+/// ```
+///
+/// The passphrase itself never goes in the file: `encrypt_store` takes it
+/// from `PRIVACY_STORE_PASSPHRASE`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SetupAnswers {
+    /// Cluster to save to the config file (the config is left as is without
+    /// one)
+    pub network: Option<String>,
+    /// Keypair to use (defaults to `--keypair`)
+    pub keypair: Option<String>,
+    /// Generate the keypair if it doesn't exist
+    pub create_wallet: bool,
+    /// Devnet faucet airdrop to request, in SOL
+    pub airdrop_sol: Option<f64>,
+    /// Encrypt the note store with `PRIVACY_STORE_PASSPHRASE`
+    pub encrypt_store: bool,
+    /// Directory to back the note store up to (the config is left as is
+    /// without one)
+    pub backup_dir: Option<String>,
+    /// Steps to leave out
+    pub skip: Vec<SetupStep>,
+}
+
+impl SetupAnswers {
+    pub fn load(path: &str) -> Result<Self> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read setup answers from {}", path))?;
+        serde_json::from_str(&data).with_context(|| format!("Invalid setup answers in {}", path))
+    }
+}
+
+/// Global options the setup steps work against
+pub struct SetupContext<'a> {
+    pub rpc: &'a RpcOptions,
+    /// The cluster selected for this run
+    pub network: Network,
+    /// The config file the network step writes to
+    pub config_path: &'a Path,
+    pub keypair_path: &'a str,
+    pub artifacts_path: &'a str,
+    /// Where the artifacts step downloads from
+    pub artifact_urls: &'a [String],
+}
+
+/// A first run is one where the privacy-zig data directory doesn't exist yet
+pub fn is_first_run() -> bool {
+    NoteStore::data_dir().map(|dir| !dir.exists()).unwrap_or(false)
+}
+
+/// Run the given setup steps (all of them if `steps` is empty)
+///
+/// Prompts before each step unless `answers` is given, in which case the
+/// steps listed in `answers.skip` are left out and nothing is asked.
+pub async fn run_setup(
+    ctx: &SetupContext<'_>,
+    steps: &[SetupStep],
+    answers: Option<SetupAnswers>,
+) -> Result<()> {
+    let interactive = answers.is_none();
+    let answers = answers.unwrap_or_default();
+    let steps = if steps.is_empty() { &SetupStep::ALL[..] } else { steps };
+    let mut keypair_path = ctx.keypair_path.to_string();
+    let mut rpc = ctx.rpc.clone();
+
+    say!("{}", style("🛠  Setup").bold());
+    say!("{}", style("─".repeat(40)).dim());
+
+    for (i, step) in steps.iter().enumerate() {
+        say!();
+        say!(
+            "{} {}",
+            style(format!("[{}/{}]", i + 1, steps.len())).dim(),
+            style(step.title()).bold()
+        );
+
+        let run = if interactive {
+            Confirm::new()
+                .with_prompt(format!("Set up {}? (skip to do it later)", step.title().to_lowercase()))
+                .default(true)
+                .interact()?
+        } else {
+            !answers.skip.contains(step)
+        };
+        if !run {
+            say!("  {} re-run with `privacy setup --step {}`", style("skipped,").dim(), step_name(*step));
+            continue;
+        }
+
+        match step {
+            SetupStep::Network => {
+                let network = setup_network(ctx, &answers, interactive)?;
+                // Later steps talk to the chosen cluster, unless the
+                // endpoints were picked by hand
+                if rpc.urls == [ctx.network.rpc_url()] {
+                    rpc.urls = vec![network.rpc_url().to_string()];
+                }
+            }
+            SetupStep::Wallet => {
+                let ctx = SetupContext { rpc: &rpc, ..*ctx };
+                keypair_path = setup_wallet(&ctx, &answers, interactive)?;
+            }
+            SetupStep::Store => setup_store()?,
+            SetupStep::Passphrase => setup_passphrase(&answers, interactive)?,
+            SetupStep::Backup => setup_backup(ctx, &answers, interactive)?,
+            SetupStep::Artifacts => setup_artifacts(ctx, interactive).await?,
+            SetupStep::Check => {
+                let ctx = SetupContext { rpc: &rpc, keypair_path: &keypair_path, ..*ctx };
+                if !run_doctor(&ctx) {
+                    say!();
+                    say!("{}", style("Some checks failed, see above").yellow());
+                }
+            }
+        }
+    }
+
+    if keypair_path != ctx.keypair_path {
+        say!();
+        say!("Pass {} to use this wallet", style(format!("--keypair {}", keypair_path)).cyan());
+    }
+    say!();
+    say!("{}", style("✅ Setup complete").green().bold());
+    Ok(())
+}
+
+fn step_name(step: SetupStep) -> String {
+    step.to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// Save the chosen cluster as the config file's `network`, which commands
+/// use from then on without `--network`
+fn setup_network(ctx: &SetupContext, answers: &SetupAnswers, interactive: bool) -> Result<Network> {
+    const NETWORKS: [Network; 3] = [Network::Devnet, Network::Testnet, Network::Mainnet];
+
+    let network = if interactive {
+        let names: Vec<_> = NETWORKS.iter().map(|n| n.name()).collect();
+        let current = NETWORKS.iter().position(|n| *n == ctx.network).unwrap_or_default();
+        NETWORKS[Select::new()
+            .with_prompt("Network")
+            .items(&names)
+            .default(current)
+            .interact()?]
+    } else {
+        match answers.network.as_deref() {
+            Some(name) => Network::from_str(name).context("Invalid network in the setup answers")?,
+            None => {
+                say!("  Using {}", style(ctx.network.name()).cyan());
+                return Ok(ctx.network);
+            }
+        }
+    };
+
+    let mut config = load_config(ctx.config_path)?;
+    config.network = Some(network.name().to_string());
+    config.save(ctx.config_path)?;
+    say!(
+        "  Using {}, saved to {}",
+        style(network.name()).cyan(),
+        style(ctx.config_path.display()).dim()
+    );
+    Ok(network)
+}
+
+/// The config file at `path`, or an empty one if there is none yet
+fn load_config(path: &Path) -> Result<CliConfig> {
+    if path.exists() {
+        CliConfig::load(path.to_str())
+    } else {
+        Ok(CliConfig::default())
+    }
+}
+
+fn setup_wallet(ctx: &SetupContext, answers: &SetupAnswers, interactive: bool) -> Result<String> {
+    // A key given inline is used as is; prompting with it would echo it
+    let path = if keypair::inline_format(ctx.keypair_path).is_some() && answers.keypair.is_none() {
+        ctx.keypair_path.to_string()
+    } else if interactive {
+        Input::<String>::new()
+            .with_prompt("Keypair path")
+            .default(ctx.keypair_path.to_string())
+            .interact_text()?
+    } else {
+        answers.keypair.clone().unwrap_or_else(|| ctx.keypair_path.to_string())
+    };
+
+    let pubkey = if keypair::inline_format(&path).is_some() || Path::new(&path).exists() {
+        let keypair =
+            load_keypair(&path).map_err(|e| anyhow!("Failed to load the wallet keypair: {}", e))?;
+        say!("  Using wallet {}", style(keypair.pubkey()).cyan());
+        keypair.pubkey()
+    } else {
+        let create = if interactive {
+            Confirm::new()
+                .with_prompt(format!("No keypair at {}. Generate a new one?", path))
+                .default(true)
+                .interact()?
+        } else {
+            answers.create_wallet
+        };
+        if !create {
+            say!("  {}", style(format!("No keypair at {}, wallet not set up", path)).yellow());
+            return Ok(path);
+        }
+
+        let keypair = Keypair::new();
+        if let Some(parent) = Path::new(&path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_keypair_file(&keypair, &path)
+            .map_err(|e| anyhow!("Failed to write keypair to {}: {}", path, e))?;
+        say!("  Created wallet {}", style(keypair.pubkey()).cyan());
+        keypair.pubkey()
+    };
+
+    // The public faucet only exists on devnet
+    if !ctx.rpc.urls.iter().any(|url| url.contains("devnet")) {
+        return Ok(path);
+    }
+    let airdrop_sol = if interactive {
+        if Confirm::new()
+            .with_prompt("Fund the wallet from the devnet faucet?")
+            .default(true)
+            .interact()?
+        {
+            Some(Input::<f64>::new().with_prompt("Amount (SOL)").default(1.0).interact_text()?)
+        } else {
+            None
+        }
+    } else {
+        answers.airdrop_sol
+    };
+    if let Some(sol) = airdrop_sol {
+        // Faucets are rate limited; a failed airdrop shouldn't abort setup
+        if let Err(e) = airdrop(ctx.rpc, &pubkey, sol) {
+            say!("  {}", style(format!("Airdrop failed: {}", e)).yellow());
+        }
+    }
+
+    Ok(path)
+}
+
+fn airdrop(options: &RpcOptions, pubkey: &Pubkey, sol: f64) -> Result<()> {
+    let client = rpc::connect(options);
+    let signature =
+        client.call_once(|c| c.request_airdrop(pubkey, (sol * LAMPORTS_PER_SOL as f64) as u64))?;
+    client.call(|c| c.poll_for_signature(&signature))?;
+    say!("  Airdropped {} SOL", style(sol).green());
+    Ok(())
+}
+
+fn setup_store() -> Result<()> {
+    let store = NoteStore::load()?;
+    store.save()?;
+    say!(
+        "  Note store at {} ({} notes)",
+        style(NoteStore::path()?.display()).cyan(),
+        store.notes.len()
+    );
+    say!("  {}", style("Back it up: losing it means losing access to deposited funds").dim());
+    Ok(())
+}
+
+fn setup_passphrase(answers: &SetupAnswers, interactive: bool) -> Result<()> {
+    if !interactive && !answers.encrypt_store {
+        say!("  Leaving the note store as it is");
+        return Ok(());
+    }
+    // An encrypted store asks for its current passphrase first
+    let store = NoteStore::load()?;
+    if store.is_encrypted() {
+        say!("  The note store is encrypted; a new passphrase replaces the current one");
+    }
+
+    let passphrase: SecretString = match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) => {
+            say!("  Using the passphrase in {}", style(PASSPHRASE_ENV).cyan());
+            passphrase.into()
+        }
+        Err(_) if interactive => Password::new()
+            .with_prompt("Store passphrase")
+            .with_confirmation("Repeat the passphrase", "The passphrases don't match")
+            .interact()?
+            .into(),
+        Err(_) => {
+            return Err(anyhow!(
+                "encrypt_store takes the passphrase from {}, which isn't set",
+                PASSPHRASE_ENV
+            ))
+        }
+    };
+
+    encrypt_store(store, &passphrase)?;
+    store_key::remember(&passphrase);
+    say!("  Encrypted the note store at {}", style(NoteStore::path()?.display()).cyan());
+    say!(
+        "  {}",
+        style(format!(
+            "Unattended runs need {} set; a forgotten passphrase can't be recovered",
+            PASSPHRASE_ENV
+        ))
+        .dim()
+    );
+    Ok(())
+}
+
+/// Write `store` encrypted with `passphrase`
+fn encrypt_store(mut store: NoteStore, passphrase: &str) -> Result<()> {
+    store.encrypt(passphrase)?;
+    store.save()
+}
+
+fn setup_backup(ctx: &SetupContext, answers: &SetupAnswers, interactive: bool) -> Result<()> {
+    let dir = if interactive {
+        let current = load_config(ctx.config_path)?.backup_dir.unwrap_or_default();
+        Input::<String>::new()
+            .with_prompt("Back the note store up to (e.g. a mounted drive; empty for none)")
+            .default(current)
+            .allow_empty(true)
+            .interact_text()?
+    } else {
+        match &answers.backup_dir {
+            Some(dir) => dir.clone(),
+            None => {
+                say!("  Leaving the backup target as it is");
+                return Ok(());
+            }
+        }
+    };
+    let dir = Some(PathBuf::from(dir.trim())).filter(|dir| !dir.as_os_str().is_empty());
+
+    let copy = save_backup_dir(ctx.config_path, &NoteStore::path()?, dir.as_deref())?;
+    NoteStore::set_backup_dir(dir.clone());
+    match (dir, copy) {
+        (Some(dir), Some(copy)) => say!(
+            "  Backing up to {}, saved to {}; copied the store to {}",
+            style(dir.display()).cyan(),
+            style(ctx.config_path.display()).dim(),
+            style(copy.display()).dim()
+        ),
+        (Some(dir), None) => say!(
+            "  Backing up to {}, saved to {}",
+            style(dir.display()).cyan(),
+            style(ctx.config_path.display()).dim()
+        ),
+        (None, _) => say!("  {}", style("No backup target; back the note store up yourself").yellow()),
+    }
+    Ok(())
+}
+
+/// Save `dir` as the config file's `backup_dir`, or remove it, and copy the
+/// store at `store_path` there now so a target that can't be written to is
+/// found during setup. Returns the copy, if the store exists yet.
+fn save_backup_dir(config_path: &Path, store_path: &Path, dir: Option<&Path>) -> Result<Option<PathBuf>> {
+    let mut config = load_config(config_path)?;
+    config.backup_dir = dir.map(|dir| dir.display().to_string());
+    config.save(config_path)?;
+    match dir {
+        Some(dir) if store_path.exists() => NoteStore::copy_to(store_path, dir).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Whether the copy of the store at `store_path` in `dir` is up to date
+fn check_backup(store_path: &Path, dir: &Path) -> Result<String> {
+    let Some(name) = store_path.file_name() else {
+        return Err(anyhow!("{} is not a file", store_path.display()));
+    };
+    let copy = dir.join(name);
+    if !store_path.exists() {
+        return Ok(dir.display().to_string());
+    }
+    match (fs::read(store_path), fs::read(&copy)) {
+        (Ok(store), Ok(backup)) if store == backup => Ok(copy.display().to_string()),
+        (_, Err(_)) => Err(anyhow!("no copy of the note store in {}", dir.display())),
+        _ => Err(anyhow!("{} is behind the note store; the next save updates it", copy.display())),
+    }
+}
+
+async fn setup_artifacts(ctx: &SetupContext<'_>, interactive: bool) -> Result<()> {
+    let missing = artifacts::missing(Path::new(ctx.artifacts_path));
+    if missing.is_empty() {
+        say!("  Found circuit artifacts in {}", style(ctx.artifacts_path).cyan());
+        return Ok(());
+    }
+    say!(
+        "  {}",
+        style(format!("Missing {} in {}", missing.join(", "), ctx.artifacts_path)).yellow()
+    );
+
+    let dir = artifacts::managed_dir()?;
+    let download = !interactive
+        || Confirm::new()
+            .with_prompt(format!("Download them into {}?", dir.display()))
+            .default(true)
+            .interact()?;
+    if !download {
+        say!("  Run 'privacy artifacts download' or pass --artifacts <DIR>");
+        return Ok(());
+    }
+    artifacts::download(ctx.artifact_urls, &dir, false).await?;
+    if dir.as_path() != Path::new(ctx.artifacts_path) {
+        say!(
+            "  {}",
+            style(format!(
+                "--artifacts or the config file points at {}; unset it to use these",
+                ctx.artifacts_path
+            ))
+            .yellow()
+        );
+    }
+    Ok(())
+}
+
+/// Check the wallet, RPC, pool accounts, artifacts, note store and its
+/// backup
+///
+/// Prints one line per check and returns whether all of them passed.
+pub fn run_doctor(ctx: &SetupContext) -> bool {
+    say!("{}", style("🩺 Doctor").bold());
+    say!("{}", style("─".repeat(40)).dim());
+
+    let client = rpc::connect(ctx.rpc);
+    let config = pool::config();
+    let mut ok = true;
+    let mut report = |name: &str, result: Result<String>| match result {
+        Ok(detail) => say!("  {} {:<12} {}", style("✓").green(), name, style(detail).dim()),
+        Err(e) => {
+            ok = false;
+            say!("  {} {:<12} {}", style("✗").red(), name, e);
+        }
+    };
+
+    let keypair = load_keypair(ctx.keypair_path);
+    report("Wallet", keypair.as_ref().map(|k| k.pubkey().to_string()).map_err(|e| anyhow!("{}", e)));
+
+    // Check every endpoint, not just the one failover would settle on
+    let mut rpc_ok = false;
+    for node in client.endpoints() {
+        let url = node.url();
+        let version = node.get_version().map_err(|e| anyhow!("{} unreachable: {}", url, e));
+        rpc_ok |= version.is_ok();
+        report("RPC", version.map(|v| format!("{} (solana {})", url, v.solana_core)));
+    }
+
+    if rpc_ok {
+        if let Ok(keypair) = &keypair {
+            report(
+                "Balance",
+                client
+                    .call(|c| c.get_balance(&keypair.pubkey()))
+                    .map(|lamports| format!("{:.4} SOL", lamports as f64 / 1e9)),
+            );
+        }
+        report(
+            "Pool",
+            client
+                .call(|c| c.get_account(&config.tree_account))
+                .map(|_| format!("tree {}", config.tree_account))
+                .map_err(|e| anyhow!("tree account {} not found: {}", config.tree_account, e)),
+        );
+    }
+
+    let missing = artifacts::missing(Path::new(ctx.artifacts_path));
+    report(
+        "Artifacts",
+        if missing.is_empty() {
+            Ok(ctx.artifacts_path.to_string())
+        } else {
+            Err(anyhow!("missing {} in {}", missing.join(", "), ctx.artifacts_path))
+        },
+    );
+
+    report(
+        "Note store",
+        NoteStore::load().map(|store| {
+            let encrypted = if store.is_encrypted() { ", encrypted" } else { "" };
+            format!("{} notes{}", store.notes.len(), encrypted)
+        }),
+    );
+    if let Some(dir) = NoteStore::backup_dir() {
+        report("Backup", NoteStore::path().and_then(|path| check_backup(&path, &dir)));
+    }
+
+    ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store_key::{Envelope, StoreKey};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("setup-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_answers_file() {
+        let answers: SetupAnswers = serde_json::from_str(
+            r#"{ "network": "devnet", "encrypt_store": true, "backup_dir": "/mnt/backup", "skip": ["artifacts"] }"#,
+        )
+        .unwrap();
+        assert!(answers.encrypt_store);
+        assert_eq!(answers.backup_dir.as_deref(), Some("/mnt/backup"));
+        assert_eq!(answers.skip, [SetupStep::Artifacts]);
+
+        // A passphrase in the file is refused rather than ignored
+        assert!(serde_json::from_str::<SetupAnswers>(r#"{ "passphrase": "hunter2" }"#).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_store() {
+        let dir = temp_dir("passphrase");
+        let path = dir.join("notes.json");
+        NoteStore::load_from(&path).unwrap().save().unwrap();
+
+        encrypt_store(NoteStore::load_from(&path).unwrap(), "correct horse").unwrap();
+        let envelope = Envelope::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+        let key = StoreKey::for_envelope(&envelope, "correct horse").unwrap();
+        let plaintext = key.open(&envelope).unwrap();
+        let store: serde_json::Value = serde_json::from_slice(&plaintext).unwrap();
+        assert_eq!(store["notes"], serde_json::json!([]));
+        assert!(StoreKey::for_envelope(&envelope, "wrong").unwrap().open(&envelope).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_backup_target() {
+        let dir = temp_dir("backup");
+        let config_path = dir.join("config.toml");
+        let store_path = dir.join("notes.json");
+        let backup = dir.join("usb");
+
+        // Before the store exists only the config is written
+        assert_eq!(save_backup_dir(&config_path, &store_path, Some(&backup)).unwrap(), None);
+        let config = CliConfig::load(config_path.to_str()).unwrap();
+        assert_eq!(config.backup_dir, Some(backup.display().to_string()));
+
+        NoteStore::load_from(&store_path).unwrap().save().unwrap();
+        let copy = save_backup_dir(&config_path, &store_path, Some(&backup)).unwrap().unwrap();
+        assert_eq!(copy, backup.join("notes.json"));
+        assert_eq!(check_backup(&store_path, &backup).unwrap(), copy.display().to_string());
+
+        // The doctor notices a copy that fell behind, or is gone
+        fs::write(&store_path, r#"{"version":6,"notes":[]} "#).unwrap();
+        assert!(check_backup(&store_path, &backup).unwrap_err().to_string().contains("behind"));
+        fs::remove_file(&copy).unwrap();
+        assert!(check_backup(&store_path, &backup).unwrap_err().to_string().contains("no copy"));
+
+        // An empty answer removes the target
+        assert_eq!(save_backup_dir(&config_path, &store_path, None).unwrap(), None);
+        assert_eq!(CliConfig::load(config_path.to_str()).unwrap().backup_dir, None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
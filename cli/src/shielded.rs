@@ -0,0 +1,679 @@
+//! Shielded addresses and encrypted notes
+//!
+//! Paying another pool user without anything leaving the pool means
+//! creating a note they can spend: one committed to their UTXO pubkey. A
+//! shielded address carries that pubkey along with an x25519 key, and is
+//! written as bech32m with the `pzig` prefix (longer than the 90 characters
+//! plain bech32m allows, as 64 bytes need):
+//!
+//! | Size | Field |
+//! |------|-------|
+//! | 32 | UTXO pubkey (big-endian field element) |
+//! | 32 | x25519 public key |
+//!
+//! The keys behind it, [`ShieldedKeys`], live in the note store. The sender
+//! can't hand over a spendable note, only its amount and blinding, so
+//! [`IncomingNote`] carries those and what locates the commitment, sealed
+//! to the x25519 key: `privacy-note-v1:<base58 ephemeral key + ciphertext>`.
+//! The recipient recomputes the commitment with their own pubkey before
+//! trusting any of it.
+//!
+//! `shield-send` also puts a [`MemoNote`] in its transaction's memo, so the
+//! recipient finds the note with `scan --incoming` without being sent
+//! anything. A memo only has room for the amount and blinding; the
+//! commitment, leaf and pool are the transaction's own.
+//!
+//! A [`ViewingKey`] is the address's pubkey and x25519 secret without the
+//! UTXO private key: enough to open memos and recompute commitments, so a
+//! watch-only store sees the notes paid to the address, but not to sign the
+//! nullifiers that spend them. It is written as bech32m with the `pzigview`
+//! prefix.
+
+use anyhow::{anyhow, Result};
+use ark_bn254::Fr;
+use bech32::primitives::checksum::Checksum;
+use bech32::primitives::decode::CheckedHrpstring;
+use bech32::{Bech32m, Hrp};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use privacy_zig_core::MAX_MEMO_LEN;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::str::FromStr;
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::crypto::{
+    self, be_bytes_to_fr_strict, fr_to_be_bytes, fr_to_str, poseidon, random_fr, str_to_fr_strict,
+    SecretString, Utxo,
+};
+
+/// Human-readable prefix of shielded addresses
+pub const ADDRESS_HRP: &str = "pzig";
+
+/// Human-readable prefix of viewing keys
+pub const VIEWING_KEY_HRP: &str = "pzigview";
+
+const NOTE_PREFIX: &str = "privacy-note-";
+const NOTE_VERSION: &str = "v1";
+
+/// Domain separation for the key an incoming note is sealed with
+const SEAL_DOMAIN: &[u8] = b"privacy-zig incoming note v1";
+
+const MEMO_PREFIX: &str = "privacy-memo-v1:";
+const MEMO_DOMAIN: &[u8] = b"privacy-zig memo note v1";
+/// Sealed size of a memo note: ephemeral key, amount and blinding, tag
+const MEMO_SEALED_LEN: usize = 32 + 8 + 32 + 16;
+
+/// Bech32m's checksum without its 90-character limit
+enum Bech32mLong {}
+
+impl Checksum for Bech32mLong {
+    type MidstateRepr = u32;
+    const CODE_LENGTH: usize = 1023;
+    const CHECKSUM_LENGTH: usize = Bech32m::CHECKSUM_LENGTH;
+    const GENERATOR_SH: [u32; 5] = Bech32m::GENERATOR_SH;
+    const TARGET_RESIDUE: u32 = Bech32m::TARGET_RESIDUE;
+}
+
+/// The keys of a note store's shielded address
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShieldedKeys {
+    /// UTXO private key of the notes paid to the address
+    pub privkey: SecretString,
+    /// x25519 secret key, hex, that incoming notes are opened with
+    pub encryption_key: SecretString,
+}
+
+impl fmt::Debug for ShieldedKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ShieldedKeys { .. }")
+    }
+}
+
+impl ShieldedKeys {
+    pub fn generate() -> Self {
+        let secret = StaticSecret::random_from_rng(rand::rngs::OsRng);
+        Self {
+            privkey: fr_to_str(&random_fr()).into(),
+            encryption_key: hex::encode(secret.to_bytes()).into(),
+        }
+    }
+
+    pub fn address(&self) -> Result<ShieldedAddress> {
+        self.viewing_key()?.address()
+    }
+
+    /// The keys without the UTXO private key, which spends notes
+    pub fn viewing_key(&self) -> Result<ViewingKey> {
+        let privkey = crypto::parse_field("shielded privkey", &self.privkey)?;
+        Ok(ViewingKey {
+            pubkey: poseidon(&[privkey])?,
+            secret: self.secret()?.to_bytes(),
+        })
+    }
+
+    fn secret(&self) -> Result<StaticSecret> {
+        let bytes = Zeroizing::new(hex::decode(self.encryption_key.as_str()).unwrap_or_default());
+        let mut bytes: [u8; 32] = bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow!("Invalid shielded encryption key in the note store"))?;
+        let secret = StaticSecret::from(bytes);
+        bytes.zeroize();
+        Ok(secret)
+    }
+}
+
+/// What finds and reads the notes paid to a shielded address, but can't
+/// spend them
+#[derive(Clone, PartialEq, Eq)]
+pub struct ViewingKey {
+    /// UTXO pubkey the address's notes are committed to
+    pub pubkey: Fr,
+    /// x25519 secret key the notes' memos are opened with
+    secret: [u8; 32],
+}
+
+impl Drop for ViewingKey {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+impl fmt::Debug for ViewingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ViewingKey { .. }")
+    }
+}
+
+impl ViewingKey {
+    pub fn address(&self) -> Result<ShieldedAddress> {
+        Ok(ShieldedAddress {
+            pubkey: self.pubkey,
+            encryption_key: PublicKey::from(&StaticSecret::from(self.secret)).to_bytes(),
+        })
+    }
+}
+
+impl fmt::Display for ViewingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut data = Vec::with_capacity(64);
+        data.extend_from_slice(&fr_to_be_bytes(&self.pubkey));
+        data.extend_from_slice(&self.secret);
+        let hrp = Hrp::parse(VIEWING_KEY_HRP).expect("valid prefix");
+        let encoded = bech32::encode::<Bech32mLong>(hrp, &data).map_err(|_| fmt::Error)?;
+        f.write_str(&encoded)
+    }
+}
+
+impl FromStr for ViewingKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (pubkey, secret) = decode_keys(s, VIEWING_KEY_HRP, "viewing key")?;
+        Ok(Self { pubkey, secret })
+    }
+}
+
+/// Where to send a note so that its recipient can spend it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShieldedAddress {
+    /// UTXO pubkey the note is committed to
+    pub pubkey: Fr,
+    /// x25519 key the note's secrets are sealed to
+    pub encryption_key: [u8; 32],
+}
+
+impl fmt::Display for ShieldedAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut data = Vec::with_capacity(64);
+        data.extend_from_slice(&fr_to_be_bytes(&self.pubkey));
+        data.extend_from_slice(&self.encryption_key);
+        let hrp = Hrp::parse(ADDRESS_HRP).expect("valid prefix");
+        let encoded = bech32::encode::<Bech32mLong>(hrp, &data).map_err(|_| fmt::Error)?;
+        f.write_str(&encoded)
+    }
+}
+
+impl FromStr for ShieldedAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (pubkey, encryption_key) = decode_keys(s, ADDRESS_HRP, "shielded address")?;
+        Ok(Self { pubkey, encryption_key })
+    }
+}
+
+/// The pubkey and x25519 key of a bech32m string with prefix `hrp`
+fn decode_keys(s: &str, hrp: &str, what: &str) -> Result<(Fr, [u8; 32])> {
+    let invalid = |reason: &str| anyhow!("Invalid {}: {}", what, reason);
+    let checked = CheckedHrpstring::new::<Bech32mLong>(s.trim())
+        .map_err(|e| invalid(&e.to_string()))?;
+    if checked.hrp().as_str() != hrp {
+        return Err(invalid(&format!("expected {}1...", hrp)));
+    }
+    let data: Vec<u8> = checked.byte_iter().collect();
+    if data.len() != 64 {
+        return Err(invalid(&format!("{} bytes, expected 64", data.len())));
+    }
+    let pubkey = be_bytes_to_fr_strict(&data[..32]).map_err(|_| invalid("pubkey is not a field element"))?;
+    Ok((pubkey, data[32..].try_into().unwrap()))
+}
+
+/// What the recipient of a note needs to spend it, besides their own keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncomingNote {
+    pub amount: u64,
+    pub blinding: Fr,
+    pub commitment: Fr,
+    /// Leaf the sender expects the commitment at; the recipient looks it up
+    pub leaf_index: u64,
+    /// SPL token mint; `None` for SOL
+    pub mint: Option<Pubkey>,
+    pub network: String,
+    pub pool: Pubkey,
+}
+
+impl IncomingNote {
+    /// Whether `s` is a sealed note rather than a `notes backup` string
+    pub fn is_sealed(s: &str) -> bool {
+        s.trim_start().starts_with(NOTE_PREFIX)
+    }
+
+    /// The note, encrypted so only the holder of `to`'s keys can read it
+    pub fn seal(&self, to: &ShieldedAddress) -> String {
+        let sealed = seal(SEAL_DOMAIN, &Zeroizing::new(self.to_bytes()), to);
+        format!("{}{}:{}", NOTE_PREFIX, NOTE_VERSION, bs58::encode(sealed).into_string())
+    }
+
+    /// Open a note sealed to the address of `keys`
+    pub fn open(sealed: &str, keys: &ShieldedKeys) -> Result<Self> {
+        let rest = sealed
+            .trim()
+            .strip_prefix(NOTE_PREFIX)
+            .ok_or_else(|| anyhow!("Not a privacy-zig note (expected {}{}:...)", NOTE_PREFIX, NOTE_VERSION))?;
+        let (version, encoded) = rest
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Malformed note: missing ':'"))?;
+        if version != NOTE_VERSION {
+            return Err(anyhow!(
+                "Unsupported note version {} (this build reads {})",
+                version,
+                NOTE_VERSION
+            ));
+        }
+        let data = bs58::decode(encoded)
+            .into_vec()
+            .map_err(|e| anyhow!("Malformed note: {}", e))?;
+        if data.len() < 32 {
+            return Err(anyhow!("Malformed note: too short"));
+        }
+
+        let plaintext = open(SEAL_DOMAIN, &data, &keys.secret()?)
+            .ok_or_else(|| anyhow!("This note was not sent to this note store's shielded address"))?;
+        Self::from_bytes(&plaintext)
+    }
+
+    /// The spendable note, with the pubkey and privkey of `keys`, if the
+    /// commitment the sender claimed is the one these values make
+    pub fn to_utxo(&self, keys: &ShieldedKeys) -> Result<Utxo> {
+        let address = keys.address()?;
+        let mint = match &self.mint {
+            Some(mint) => crypto::spl_mint(mint),
+            None => crypto::sol_mint(),
+        };
+        let utxo = Utxo::from_values(
+            self.amount,
+            &keys.privkey,
+            &fr_to_str(&address.pubkey),
+            &fr_to_str(&self.blinding),
+            mint,
+        )?;
+        if str_to_fr_strict(&utxo.commitment)? != self.commitment {
+            return Err(anyhow!(
+                "The note's commitment doesn't match its amount and blinding for this \
+                 shielded address; it can't be spent, so it wasn't imported"
+            ));
+        }
+        Ok(utxo)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + 32 * 2 + 8 + 33 + 32 + 1 + self.network.len());
+        out.extend_from_slice(&self.amount.to_le_bytes());
+        out.extend_from_slice(&fr_to_be_bytes(&self.blinding));
+        out.extend_from_slice(&fr_to_be_bytes(&self.commitment));
+        out.extend_from_slice(&self.leaf_index.to_le_bytes());
+        match &self.mint {
+            Some(mint) => {
+                out.push(1);
+                out.extend_from_slice(mint.as_ref());
+            }
+            None => out.push(0),
+        }
+        out.extend_from_slice(self.pool.as_ref());
+        out.push(self.network.len() as u8);
+        out.extend_from_slice(self.network.as_bytes());
+        out
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<Self> {
+        let malformed = |what: &str| anyhow!("Malformed note: {}", what);
+        let mut rest = data;
+        let mut take = |len: usize| -> Result<&[u8]> {
+            if rest.len() < len {
+                return Err(malformed("too short"));
+            }
+            let (head, tail) = rest.split_at(len);
+            rest = tail;
+            Ok(head)
+        };
+
+        let amount = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let blinding = be_bytes_to_fr_strict(take(32)?).map_err(|_| malformed("blinding is not a field element"))?;
+        let commitment =
+            be_bytes_to_fr_strict(take(32)?).map_err(|_| malformed("commitment is not a field element"))?;
+        let leaf_index = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let mint = match take(1)?[0] {
+            0 => None,
+            1 => Some(Pubkey::try_from(take(32)?).unwrap()),
+            _ => return Err(malformed("bad mint flag")),
+        };
+        let pool = Pubkey::try_from(take(32)?).unwrap();
+        let network_len = take(1)?[0] as usize;
+        let network = String::from_utf8(take(network_len)?.to_vec())
+            .map_err(|_| malformed("network is not UTF-8"))?;
+        if !rest.is_empty() {
+            return Err(malformed("trailing bytes"));
+        }
+        Ok(Self {
+            amount,
+            blinding,
+            commitment,
+            leaf_index,
+            mint,
+            network,
+            pool,
+        })
+    }
+}
+
+/// An output's amount and blinding, sealed to its recipient in the memo of
+/// the transaction that creates it: `privacy-memo-v1:` and the base58
+/// ephemeral key and ciphertext, within [`MAX_MEMO_LEN`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoNote {
+    pub amount: u64,
+    pub blinding: Fr,
+}
+
+/// A note of a viewing key's address, found in a pool transaction's memo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundNote {
+    pub amount: u64,
+    pub blinding: Fr,
+    pub commitment: Fr,
+    pub leaf_index: usize,
+}
+
+impl FoundNote {
+    /// The spendable note, with the address's `privkey`
+    pub fn to_utxo(&self, keys: &ShieldedKeys, mint: Fr) -> Result<Utxo> {
+        let utxo = Utxo::from_values(
+            self.amount,
+            &keys.privkey,
+            &fr_to_str(&keys.address()?.pubkey),
+            &fr_to_str(&self.blinding),
+            mint,
+        )?;
+        if str_to_fr_strict(&utxo.commitment)? != self.commitment {
+            return Err(anyhow!("The viewing key and spending key are of different addresses"));
+        }
+        Ok(utxo)
+    }
+}
+
+/// A memo of a pool transaction and the two commitments it inserted
+#[derive(Debug, Clone)]
+pub struct PoolMemo {
+    pub memo: String,
+    pub commitments: [Fr; 2],
+    /// Leaf index of the first commitment
+    pub first_leaf: usize,
+    pub signature: String,
+}
+
+impl MemoNote {
+    /// Whether `memo` claims to be a memo note; anyone can attach other
+    /// memos to a pool transaction
+    pub fn is_memo(memo: &str) -> bool {
+        memo.starts_with(MEMO_PREFIX)
+    }
+
+    pub fn seal(&self, to: &ShieldedAddress) -> String {
+        let mut plaintext = Zeroizing::new(self.amount.to_le_bytes().to_vec());
+        plaintext.extend_from_slice(&fr_to_be_bytes(&self.blinding));
+        let sealed = seal(MEMO_DOMAIN, &plaintext, to);
+        let memo = format!("{}{}", MEMO_PREFIX, bs58::encode(sealed).into_string());
+        debug_assert!(memo.len() <= MAX_MEMO_LEN, "{} bytes", memo.len());
+        memo
+    }
+
+    /// Trial decryption of `memo` with `key`: `None` if it was sealed to
+    /// someone else, an error if it isn't a well-formed memo note at all
+    pub fn open(memo: &str, key: &ViewingKey) -> Result<Option<Self>> {
+        let malformed = |what: &str| anyhow!("Malformed memo note: {}", what);
+        if memo.len() > MAX_MEMO_LEN {
+            return Err(malformed("too long"));
+        }
+        let encoded = memo
+            .strip_prefix(MEMO_PREFIX)
+            .ok_or_else(|| malformed("not a privacy-zig memo"))?;
+        let data = bs58::decode(encoded)
+            .into_vec()
+            .map_err(|e| malformed(&e.to_string()))?;
+        if data.len() != MEMO_SEALED_LEN {
+            return Err(malformed(&format!("{} bytes, expected {}", data.len(), MEMO_SEALED_LEN)));
+        }
+
+        let Some(plaintext) = open(MEMO_DOMAIN, &data, &StaticSecret::from(key.secret)) else {
+            return Ok(None);
+        };
+        let blinding =
+            be_bytes_to_fr_strict(&plaintext[8..]).map_err(|_| malformed("blinding is not a field element"))?;
+        Ok(Some(Self {
+            amount: u64::from_le_bytes(plaintext[..8].try_into().unwrap()),
+            blinding,
+        }))
+    }
+
+    /// The note of `key`'s address that `memo` announces, if its
+    /// commitment is one `memo`'s transaction inserted. A memo copied into
+    /// another transaction, or lying about its amount, makes none.
+    pub fn find(&self, key: &ViewingKey, mint: Fr, memo: &PoolMemo) -> Result<Option<FoundNote>> {
+        let commitment = poseidon(&[Fr::from(self.amount), key.pubkey, self.blinding, mint])?;
+        Ok(memo
+            .commitments
+            .iter()
+            .position(|c| *c == commitment)
+            .map(|position| FoundNote {
+                amount: self.amount,
+                blinding: self.blinding,
+                commitment,
+                leaf_index: memo.first_leaf + position,
+            }))
+    }
+}
+
+/// `plaintext` encrypted to `to`: a fresh ephemeral x25519 key, then the
+/// ciphertext under the key it agrees with `to`'s
+fn seal(domain: &[u8], plaintext: &[u8], to: &ShieldedAddress) -> Vec<u8> {
+    let ephemeral = StaticSecret::random_from_rng(rand::rngs::OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral);
+    let recipient = PublicKey::from(to.encryption_key);
+    let key = seal_key(domain, &ephemeral.diffie_hellman(&recipient), &ephemeral_public, &recipient);
+    // Each note gets a fresh ephemeral key, so the key is never reused and a
+    // fixed nonce is safe
+    let ciphertext = ChaCha20Poly1305::new(&key)
+        .encrypt(&Nonce::default(), plaintext)
+        .expect("encryption of an in-memory buffer");
+
+    let mut sealed = ephemeral_public.to_bytes().to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    sealed
+}
+
+/// The plaintext of `data`, sealed with [`seal`], or `None` if it wasn't
+/// sealed to the key of `secret`; wiped when dropped. `data` holds at least
+/// the ephemeral key.
+fn open(domain: &[u8], data: &[u8], secret: &StaticSecret) -> Option<Zeroizing<Vec<u8>>> {
+    let ephemeral_public = PublicKey::from(<[u8; 32]>::try_from(&data[..32]).unwrap());
+    let shared = secret.diffie_hellman(&ephemeral_public);
+    let key = seal_key(domain, &shared, &ephemeral_public, &PublicKey::from(secret));
+    ChaCha20Poly1305::new(&key)
+        .decrypt(&Nonce::default(), &data[32..])
+        .ok()
+        .map(Zeroizing::new)
+}
+
+/// Key a note is sealed with, from the x25519 `shared` secret of the
+/// ephemeral and recipient keys
+fn seal_key(domain: &[u8], shared: &SharedSecret, ephemeral: &PublicKey, recipient: &PublicKey) -> Key {
+    let digest = Sha256::new()
+        .chain_update(domain)
+        .chain_update(shared.as_bytes())
+        .chain_update(ephemeral.as_bytes())
+        .chain_update(recipient.as_bytes())
+        .finalize();
+    *Key::from_slice(&digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn incoming(keys: &ShieldedKeys) -> IncomingNote {
+        let address = keys.address().unwrap();
+        let blinding = random_fr();
+        let mint = crypto::sol_mint();
+        let commitment = poseidon(&[Fr::from(250_000_000u64), address.pubkey, blinding, mint]).unwrap();
+        IncomingNote {
+            amount: 250_000_000,
+            blinding,
+            commitment,
+            leaf_index: 42,
+            mint: None,
+            network: "devnet".to_string(),
+            pool: Pubkey::new_unique(),
+        }
+    }
+
+    #[test]
+    fn test_address_round_trip() {
+        let keys = ShieldedKeys::generate();
+        let address = keys.address().unwrap();
+        let encoded = address.to_string();
+        assert!(encoded.starts_with("pzig1"), "{}", encoded);
+        assert_eq!(encoded.parse::<ShieldedAddress>().unwrap(), address);
+        assert_eq!(keys.address().unwrap(), address);
+
+        // A typo is caught by the checksum
+        let mut typo = encoded.clone().into_bytes();
+        let last = typo.len() - 1;
+        typo[last] = if typo[last] == b'q' { b'p' } else { b'q' };
+        assert!(String::from_utf8(typo).unwrap().parse::<ShieldedAddress>().is_err());
+        assert!("9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin".parse::<ShieldedAddress>().is_err());
+    }
+
+    #[test]
+    fn test_sealed_note_opens_only_for_its_recipient() {
+        let keys = ShieldedKeys::generate();
+        let note = incoming(&keys);
+        let sealed = note.seal(&keys.address().unwrap());
+        assert!(IncomingNote::is_sealed(&sealed));
+        assert!(sealed.starts_with("privacy-note-v1:"));
+
+        let opened = IncomingNote::open(&sealed, &keys).unwrap();
+        assert_eq!(opened, note);
+        let utxo = opened.to_utxo(&keys).unwrap();
+        assert_eq!(utxo.privkey, keys.privkey);
+        assert_eq!(str_to_fr_strict(&utxo.commitment).unwrap(), note.commitment);
+
+        let err = IncomingNote::open(&sealed, &ShieldedKeys::generate()).unwrap_err();
+        assert!(err.to_string().contains("not sent to this note store"), "{}", err);
+
+        // Any change to the ciphertext fails authentication
+        let (prefix, body) = sealed.split_once(':').unwrap();
+        let mut data = bs58::decode(body).into_vec().unwrap();
+        data[40] ^= 1;
+        let tampered = format!("{}:{}", prefix, bs58::encode(data).into_string());
+        assert!(IncomingNote::open(&tampered, &keys).is_err());
+        assert!(IncomingNote::open("privacy-note-v2:abc", &keys).is_err());
+    }
+
+    #[test]
+    fn test_rejects_commitment_that_does_not_match() {
+        let keys = ShieldedKeys::generate();
+        let mut note = incoming(&keys);
+        note.amount += 1;
+        let opened = IncomingNote::open(&note.seal(&keys.address().unwrap()), &keys).unwrap();
+        let err = opened.to_utxo(&keys).unwrap_err();
+        assert!(err.to_string().contains("doesn't match"), "{}", err);
+    }
+
+    fn pool_memo(memo: String, commitments: [Fr; 2]) -> PoolMemo {
+        PoolMemo {
+            memo,
+            commitments,
+            first_leaf: 10,
+            signature: "sig".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_memo_note_is_found_by_its_recipient_only() {
+        let keys = ShieldedKeys::generate();
+        let address = keys.address().unwrap();
+        let note = MemoNote {
+            amount: u64::MAX,
+            blinding: random_fr(),
+        };
+        let mint = crypto::sol_mint();
+        let commitment = poseidon(&[Fr::from(u64::MAX), address.pubkey, note.blinding, mint]).unwrap();
+
+        let memo = note.seal(&address);
+        assert!(MemoNote::is_memo(&memo));
+        assert!(memo.len() <= MAX_MEMO_LEN, "{} bytes", memo.len());
+        let view = keys.viewing_key().unwrap();
+        assert_eq!(MemoNote::open(&memo, &view).unwrap(), Some(note));
+        let other = ShieldedKeys::generate().viewing_key().unwrap();
+        assert_eq!(MemoNote::open(&memo, &other).unwrap(), None);
+
+        let pool = pool_memo(memo.clone(), [random_fr(), commitment]);
+        let found = note.find(&view, mint, &pool).unwrap().unwrap();
+        assert_eq!(found.leaf_index, 11);
+        assert_eq!(found.commitment, commitment);
+        let utxo = found.to_utxo(&keys, mint).unwrap();
+        assert_eq!(str_to_fr_strict(&utxo.commitment).unwrap(), commitment);
+        assert_eq!(utxo.privkey, keys.privkey);
+        assert!(found.to_utxo(&ShieldedKeys::generate(), mint).is_err());
+
+        // Replayed into a transaction that didn't create the note
+        let replayed = pool_memo(memo, [random_fr(), random_fr()]);
+        assert!(note.find(&view, mint, &replayed).unwrap().is_none());
+        // Sealed for another mint's pool
+        assert!(note.find(&view, Fr::from(7u64), &pool).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_viewing_key_round_trip() {
+        let keys = ShieldedKeys::generate();
+        let view = keys.viewing_key().unwrap();
+        let encoded = view.to_string();
+        assert!(encoded.starts_with("pzigview1"), "{}", encoded);
+        assert_eq!(encoded.parse::<ViewingKey>().unwrap(), view);
+        assert_eq!(view.address().unwrap(), keys.address().unwrap());
+
+        // Neither the spending key nor the x25519 secret leaks through Debug
+        assert_eq!(format!("{:?}", view), "ViewingKey { .. }");
+        // An address isn't a viewing key, nor the other way round
+        let address = keys.address().unwrap().to_string();
+        assert!(address.parse::<ViewingKey>().is_err());
+        assert!(encoded.parse::<ShieldedAddress>().is_err());
+    }
+
+    #[test]
+    fn test_malformed_memos_are_rejected() {
+        let keys = ShieldedKeys::generate().viewing_key().unwrap();
+        let memo = MemoNote {
+            amount: 1,
+            blinding: random_fr(),
+        }
+        .seal(&keys.address().unwrap());
+
+        assert!(!MemoNote::is_memo("gm"));
+        assert!(MemoNote::open("gm", &keys).is_err());
+        assert!(MemoNote::open("privacy-memo-v1:0OIl", &keys).is_err());
+        // Truncated, or padded past what a transaction can carry
+        assert!(MemoNote::open(&memo[..memo.len() - 4], &keys).is_err());
+        let long = format!("{}{}", memo, "1".repeat(MAX_MEMO_LEN));
+        let err = MemoNote::open(&long, &keys).unwrap_err();
+        assert!(err.to_string().contains("too long"), "{}", err);
+
+        // A flipped bit fails authentication, so it reads as someone else's
+        let mut data = bs58::decode(&memo[MEMO_PREFIX.len()..]).into_vec().unwrap();
+        data[40] ^= 1;
+        let tampered = format!("{}{}", MEMO_PREFIX, bs58::encode(data).into_string());
+        assert_eq!(MemoNote::open(&tampered, &keys).unwrap(), None);
+    }
+
+    #[test]
+    fn test_keys_are_not_printed() {
+        let keys = ShieldedKeys::generate();
+        let debug = format!("{:?}", keys);
+        assert!(!debug.contains(keys.privkey.as_str()) && !debug.contains(keys.encryption_key.as_str()));
+    }
+}
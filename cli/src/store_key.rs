@@ -0,0 +1,203 @@
+//! Passphrase encryption of the note store
+//!
+//! An encrypted `notes.json` is a JSON envelope around the plaintext store:
+//!
+//! ```json
+//! { "encryption": "argon2id-chacha20poly1305", "salt": "<hex>", "nonce": "<hex>", "ciphertext": "<base64>" }
+//! ```
+//!
+//! The key is derived from the passphrase and the salt with Argon2id (the
+//! `argon2` crate's default cost). The salt stays the same for the life of
+//! a passphrase so the key is only derived once per run; every save draws a
+//! fresh nonce.
+
+use anyhow::{anyhow, Result};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+use crate::crypto::SecretString;
+
+/// Passphrase of an encrypted note store, for unattended runs
+pub const PASSPHRASE_ENV: &str = "PRIVACY_STORE_PASSPHRASE";
+
+const ENCRYPTION: &str = "argon2id-chacha20poly1305";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// The passphrase typed at the prompt, so it's asked for once per run
+static PROMPTED: Mutex<Option<SecretString>> = Mutex::new(None);
+
+#[derive(Debug, thiserror::Error)]
+pub enum PassphraseError {
+    #[error(
+        "the note store is encrypted; set {} to its passphrase, or run attended to be asked for it",
+        PASSPHRASE_ENV
+    )]
+    Missing,
+    #[error("wrong note store passphrase")]
+    Wrong,
+}
+
+/// An encrypted store as written to disk
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Envelope {
+    encryption: String,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Envelope {
+    /// `data` as an envelope, or `None` if it's a plaintext store
+    pub fn parse(data: &str) -> Option<Self> {
+        serde_json::from_str(data).ok()
+    }
+
+    fn salt(&self) -> Result<[u8; SALT_LEN]> {
+        hex::decode(&self.salt)
+            .ok()
+            .and_then(|salt| salt.try_into().ok())
+            .ok_or_else(|| anyhow!("Invalid salt in the encrypted note store"))
+    }
+}
+
+/// Key a store is encrypted with, and the salt it was derived with
+#[derive(Clone)]
+pub struct StoreKey {
+    salt: [u8; SALT_LEN],
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl fmt::Debug for StoreKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StoreKey").field("salt", &hex::encode(self.salt)).finish_non_exhaustive()
+    }
+}
+
+impl StoreKey {
+    /// A key for `passphrase` under a fresh salt
+    pub fn new(passphrase: &str) -> Result<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        Self::derive(passphrase, salt)
+    }
+
+    fn derive(passphrase: &str, salt: [u8; SALT_LEN]) -> Result<Self> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| anyhow!("Failed to derive the note store key: {}", e))?;
+        Ok(Self { salt, key })
+    }
+
+    /// The key `envelope` was sealed with, if `passphrase` is its passphrase
+    pub fn for_envelope(envelope: &Envelope, passphrase: &str) -> Result<Self> {
+        if envelope.encryption != ENCRYPTION {
+            return Err(anyhow!("Unsupported note store encryption {}", envelope.encryption));
+        }
+        Self::derive(passphrase, envelope.salt()?)
+    }
+
+    /// `plaintext` sealed into an envelope, under a fresh nonce
+    pub fn seal(&self, plaintext: &[u8]) -> Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(self.key.as_ref()))
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .expect("encryption of an in-memory buffer");
+        let envelope = Envelope {
+            encryption: ENCRYPTION.to_string(),
+            salt: hex::encode(self.salt),
+            nonce: hex::encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+        Ok(serde_json::to_string_pretty(&envelope)?)
+    }
+
+    /// The plaintext store in `envelope`; wiped when dropped
+    pub fn open(&self, envelope: &Envelope) -> Result<Zeroizing<Vec<u8>>> {
+        if envelope.salt()? != self.salt {
+            return Err(PassphraseError::Wrong.into());
+        }
+        let nonce: [u8; NONCE_LEN] = hex::decode(&envelope.nonce)
+            .ok()
+            .and_then(|nonce| nonce.try_into().ok())
+            .ok_or_else(|| anyhow!("Invalid nonce in the encrypted note store"))?;
+        let ciphertext = BASE64
+            .decode(&envelope.ciphertext)
+            .map_err(|e| anyhow!("Invalid ciphertext in the encrypted note store: {}", e))?;
+        ChaCha20Poly1305::new(Key::from_slice(self.key.as_ref()))
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map(Zeroizing::new)
+            .map_err(|_| PassphraseError::Wrong.into())
+    }
+}
+
+/// The store passphrase: `PRIVACY_STORE_PASSPHRASE`, or else asked for on
+/// the terminal the first time it's needed
+pub fn passphrase() -> Result<SecretString> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase.into());
+    }
+    let mut prompted = PROMPTED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(passphrase) = prompted.as_ref() {
+        return Ok(passphrase.as_str().into());
+    }
+    if !console::user_attended() {
+        return Err(PassphraseError::Missing.into());
+    }
+    let passphrase: SecretString =
+        dialoguer::Password::new().with_prompt("Note store passphrase").interact()?.into();
+    *prompted = Some(passphrase.as_str().into());
+    Ok(passphrase)
+}
+
+/// Use `passphrase` for the rest of the run, e.g. after setup sets a new one
+pub fn remember(passphrase: &str) {
+    *PROMPTED.lock().unwrap_or_else(|e| e.into_inner()) = Some(passphrase.into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = StoreKey::new("correct horse").unwrap();
+        let sealed = key.seal(br#"{"version":6,"notes":[]}"#).unwrap();
+        assert!(!sealed.contains("notes"));
+
+        let envelope = Envelope::parse(&sealed).unwrap();
+        let reopened = StoreKey::for_envelope(&envelope, "correct horse").unwrap();
+        assert_eq!(reopened.open(&envelope).unwrap().as_slice(), br#"{"version":6,"notes":[]}"#);
+
+        // Every save draws a new nonce under the same salt
+        let resealed = Envelope::parse(&key.seal(b"{}").unwrap()).unwrap();
+        assert_eq!(resealed.salt, envelope.salt);
+        assert_ne!(resealed.nonce, envelope.nonce);
+
+        // A plaintext store isn't an envelope
+        assert!(Envelope::parse(r#"{"version":6,"notes":[]}"#).is_none());
+    }
+
+    #[test]
+    fn test_wrong_passphrase_is_rejected() {
+        let sealed = StoreKey::new("correct horse").unwrap().seal(b"{}").unwrap();
+        let envelope = Envelope::parse(&sealed).unwrap();
+        let wrong = StoreKey::for_envelope(&envelope, "battery staple").unwrap();
+        let err = wrong.open(&envelope).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(PassphraseError::Wrong)));
+
+        // A key under another salt doesn't even try
+        let other = StoreKey::new("correct horse").unwrap();
+        assert!(other.open(&envelope).is_err());
+    }
+}
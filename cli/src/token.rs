@@ -0,0 +1,88 @@
+//! SOL or an SPL token, and amounts of it
+//!
+//! Notes, deposits and withdrawals hold base units: lamports for SOL, the
+//! mint's smallest unit for a token. [`Token`] converts them to and from
+//! what the user types and reads, with the mint's decimals.
+
+use anyhow::{Context, Result};
+use ark_bn254::Fr;
+use privacy_zig_core::spl;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::chain;
+use crate::crypto::{sol_mint, spl_mint};
+use crate::rpc::PoolRpc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    /// `None` for SOL
+    pub mint: Option<Pubkey>,
+    pub decimals: u8,
+}
+
+impl Token {
+    pub const SOL: Token = Token { mint: None, decimals: 9 };
+
+    /// SOL, or the token of `mint` with its decimals read from the chain
+    pub fn load(rpc: &dyn PoolRpc, mint: Option<Pubkey>) -> Result<Self> {
+        match mint {
+            Some(mint) => Ok(Self { mint: Some(mint), decimals: chain::mint_decimals(rpc, &mint)? }),
+            None => Ok(Self::SOL),
+        }
+    }
+
+    /// `SOL`, or the mint shortened to `EPjF…Dt1v`
+    pub fn symbol(&self) -> String {
+        match self.mint {
+            Some(mint) => {
+                let mint = mint.to_string();
+                format!("{}…{}", &mint[..4], &mint[mint.len() - 4..])
+            }
+            None => "SOL".to_string(),
+        }
+    }
+
+    /// `amount` base units with the symbol, e.g. `1.5 SOL`
+    pub fn format(&self, amount: u64) -> String {
+        format!("{} {}", spl::format_amount(amount, self.decimals), self.symbol())
+    }
+
+    /// Base units of `--amount`. SOL amounts are truncated to whole
+    /// lamports as they always were; token amounts must fit the mint's
+    /// decimals.
+    pub fn amount(&self, amount: f64) -> Result<u64> {
+        match self.mint {
+            None => Ok((amount * 1_000_000_000.0) as u64),
+            Some(_) => spl::parse_amount(&amount.to_string(), self.decimals)
+                .with_context(|| format!("Invalid amount of {}", self.symbol())),
+        }
+    }
+
+    /// The mint as committed to in notes of this token
+    pub fn mint_fr(&self) -> Fr {
+        match &self.mint {
+            Some(mint) => spl_mint(mint),
+            None => sol_mint(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_amounts() {
+        assert_eq!(Token::SOL.amount(1.5).unwrap(), 1_500_000_000);
+        assert_eq!(Token::SOL.format(1_500_000_000), "1.5 SOL");
+        assert_eq!(Token::SOL.mint_fr(), sol_mint());
+
+        let usdc: Pubkey = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".parse().unwrap();
+        let token = Token { mint: Some(usdc), decimals: 6 };
+        assert_eq!(token.amount(2.25).unwrap(), 2_250_000);
+        assert_eq!(token.amount(0.1).unwrap(), 100_000);
+        assert!(token.amount(0.0000001).is_err());
+        assert_eq!(token.format(2_250_000), "2.25 EPjF…Dt1v");
+        assert_eq!(token.mint_fr(), spl_mint(&usdc));
+    }
+}
@@ -0,0 +1,93 @@
+//! Deposits and withdrawals as instructions, proof included
+//!
+//! [`build_deposit`] and [`build_withdraw`] prove a transaction and build
+//! the `transact` (or, for a token pool, `transact_spl`) instruction that
+//! submits it, for a wallet that signs and pays for it itself. A withdrawal
+//! sent through a relayer is proved for the relayer instead, with
+//! [`PrivacyProver::prove_withdraw`] and an [`ExtData`] naming it and its
+//! fee, and the relayer builds the instruction.
+
+use ark_bn254::Fr;
+use privacy_zig_core::spl::associated_token_address;
+use privacy_zig_core::{
+    build_transact_instruction, build_transact_spl_instruction, PoolConfig, SplPool,
+    TransactDataError, TransactProofData,
+};
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::crypto::{ExtData, MerkleTree, Utxo};
+use crate::interrupt::CancelToken;
+use crate::prover::{PrivacyProver, ProverError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum BuildError {
+    #[error(transparent)]
+    Prove(#[from] ProverError),
+    #[error(transparent)]
+    Instruction(#[from] TransactDataError),
+}
+
+/// Prove a deposit of `utxo` against the pool's current `root` and build
+/// the instruction making it, signed and paid for by `depositor`. A token
+/// deposit comes out of the depositor's associated token account.
+pub fn build_deposit(
+    prover: &PrivacyProver,
+    config: &PoolConfig,
+    spl: Option<&SplPool>,
+    depositor: &Pubkey,
+    utxo: &Utxo,
+    root: Fr,
+    cancel: &CancelToken,
+) -> Result<Instruction, BuildError> {
+    let ext_data = ExtData::direct(*depositor, *depositor);
+    let proof = prover.prove_deposit(utxo.amount, utxo, &ext_data, root, cancel)?;
+    Ok(transact_instruction(config, spl, &proof, depositor, depositor)?)
+}
+
+/// A note to withdraw: `utxo`, at `leaf_index` of `tree`, paid to
+/// `recipient`
+pub struct Withdrawal<'a> {
+    pub utxo: &'a Utxo,
+    pub leaf_index: usize,
+    pub tree: &'a MerkleTree,
+    pub recipient: Pubkey,
+}
+
+/// Prove `withdrawal` and build the instruction making it, signed and paid
+/// for by `signer`
+pub fn build_withdraw(
+    prover: &PrivacyProver,
+    config: &PoolConfig,
+    spl: Option<&SplPool>,
+    signer: &Pubkey,
+    withdrawal: &Withdrawal,
+    cancel: &CancelToken,
+) -> Result<Instruction, BuildError> {
+    let Withdrawal { utxo, leaf_index, tree, recipient } = *withdrawal;
+    let ext_data = ExtData::direct(recipient, *signer);
+    let proof = prover.prove_withdraw(utxo, leaf_index, tree, &ext_data, cancel)?;
+    Ok(transact_instruction(config, spl, &proof, signer, &recipient)?)
+}
+
+/// The `transact` instruction, or for a token pool `transact_spl`, paying
+/// `recipient` (or its token account) and the pool's fee recipient
+pub fn transact_instruction(
+    config: &PoolConfig,
+    spl: Option<&SplPool>,
+    proof: &TransactProofData,
+    signer: &Pubkey,
+    recipient: &Pubkey,
+) -> Result<Instruction, TransactDataError> {
+    match spl {
+        Some(pool) => build_transact_spl_instruction(
+            config,
+            pool,
+            proof,
+            signer,
+            &associated_token_address(recipient, &pool.mint),
+            &associated_token_address(&config.fee_recipient, &pool.mint),
+        ),
+        None => build_transact_instruction(config, proof, signer, recipient, &config.fee_recipient),
+    }
+}
@@ -0,0 +1,85 @@
+//! The circuit artifacts the prover loads, and the checksums they're held to
+//!
+//! Every file is pinned to a sha256 checksum, and the prover refuses files
+//! that don't match, since a tampered proving key or witness generator
+//! undermines the privacy of every proof made with it. The pins are those
+//! of [`ARTIFACTS`] unless [`set_pins`] replaces them, for a pool deployed
+//! from another trusted setup.
+
+use solana_sdk::hash::Hasher;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// A circuit file and its expected sha256, hex encoded
+pub struct Artifact {
+    /// File name in the artifacts directory
+    pub name: &'static str,
+    /// Hex, lowercase
+    pub sha256: &'static str,
+}
+
+/// Files the prover needs, as built by the trusted setup in
+/// `artifacts/TRUSTED_SETUP.MD`
+pub const ARTIFACTS: [Artifact; 2] = [
+    Artifact {
+        name: "transaction2.wasm",
+        sha256: "a277631b7616c2c0bfd78a1648b069972ac6020e5509ae8f9bfc8772bdc70ec1",
+    },
+    Artifact {
+        name: "transaction2.zkey",
+        sha256: "4aa7aa5c1c28ed1f00fee84f49c1686f53210fd999ef7c8db6cfcd298af4e693",
+    },
+];
+
+/// The checksum a file is held to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pin {
+    /// File name in the artifacts directory
+    pub name: &'static str,
+    /// Hex, lowercase
+    pub sha256: String,
+}
+
+impl From<&Artifact> for Pin {
+    fn from(artifact: &Artifact) -> Self {
+        Self { name: artifact.name, sha256: artifact.sha256.to_string() }
+    }
+}
+
+/// Pins in effect, set once at startup by [`set_pins`]
+static PINS: OnceLock<Vec<Pin>> = OnceLock::new();
+
+/// Hold files to `pins` for the rest of the process
+pub fn set_pins(pins: Vec<Pin>) {
+    let _ = PINS.set(pins);
+}
+
+/// The pins in effect: those set by [`set_pins`], or [`ARTIFACTS`]
+pub fn pins() -> &'static [Pin] {
+    PINS.get_or_init(|| ARTIFACTS.iter().map(Pin::from).collect())
+}
+
+/// The sha256 a file at `path` is pinned to, by its file name
+pub fn pinned(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?;
+    pins()
+        .iter()
+        .find(|pin| pin.name == name)
+        .map(|pin| pin.sha256.as_str())
+}
+
+/// Files of [`ARTIFACTS`] that `dir` lacks
+pub fn missing(dir: &Path) -> Vec<&'static str> {
+    ARTIFACTS
+        .iter()
+        .map(|artifact| artifact.name)
+        .filter(|name| !dir.join(name).exists())
+        .collect()
+}
+
+/// Hex sha256 of `data`
+pub fn sha256_bytes(data: &[u8]) -> String {
+    let mut hasher = Hasher::default();
+    hasher.hash(data);
+    hex::encode(hasher.result().to_bytes())
+}
@@ -0,0 +1,50 @@
+//! Cancelling a proof from another thread
+//!
+//! The prover checks a [`CancelToken`] between its phases and stops with
+//! [`Cancelled`] once it is set, so a caller running it on a blocking
+//! thread can give up without waiting for the proof.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// A cancellable step was stopped before it finished
+#[derive(Debug, thiserror::Error)]
+#[error("cancelled")]
+pub struct Cancelled;
+
+/// Cancellation flag shared between a step and whoever may stop it
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<(AtomicBool, Notify)>);
+
+impl CancelToken {
+    /// Set the flag and wake everything awaiting [`CancelToken::cancelled`]
+    pub fn cancel(&self) {
+        self.0 .0.store(true, Ordering::SeqCst);
+        self.0 .1.notify_waiters();
+    }
+
+    /// Whether [`CancelToken::cancel`] has been called
+    pub fn is_cancelled(&self) -> bool {
+        self.0 .0.load(Ordering::SeqCst)
+    }
+
+    /// `Err(Cancelled)` once cancelled; checked between phases of a step
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            return Err(Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Resolves once cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.0 .1.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
@@ -0,0 +1,401 @@
+//! The transaction circuit's witness inputs and proof layout
+//!
+//! Everything between a note and a [`TransactProofData`] that doesn't touch
+//! the file system or the witness runtime: the inputs a withdrawal feeds the
+//! circuit, the Groth16 proof over a computed witness, and the byte layout
+//! the program reads the proof in. [`crate::prover`] uses it natively and
+//! the `privacy-zig-wasm` crate uses it in the browser, so both prove
+//! the same statement the same way.
+
+use ark_bn254::{Bn254, Fr, G1Affine, G2Affine};
+use ark_circom::CircomReduction;
+use ark_ff::{BigInteger, PrimeField};
+use ark_groth16::{Groth16, Proof, ProvingKey};
+use ark_relations::r1cs::{ConstraintMatrices, SynthesisError};
+use ark_std::rand::{CryptoRng, RngCore};
+use ark_std::UniformRand;
+use num_bigint::{BigInt, BigUint, Sign};
+use num_traits::ToPrimitive;
+use std::collections::HashMap;
+
+use crate::crypto::{
+    fr_to_be_bytes, poseidon, random_fr_from, str_to_fr, verify_path, CryptoError, ExtData, MerkleError,
+    MerklePath, MerkleTree, Utxo, FIELD_SIZE, MERKLE_TREE_HEIGHT,
+};
+use crate::TransactProofData;
+
+/// BN254 base field modulus (for G1 point negation)
+const BN254_FIELD_MODULUS: &str =
+    "21888242871839275222246405745257275088696311157297823662689037894645226208583";
+
+/// Witness inputs by signal name, as the witness calculator takes them
+pub type Inputs = HashMap<String, Vec<BigInt>>;
+
+/// Overwrite every input with zero, in place. The inputs carry the spent
+/// note's private key and blinding; the witness calculator takes its own
+/// copy by value, which is out of our reach, so callers hand it a clone and
+/// wipe theirs as soon as the witness is computed.
+pub fn wipe_inputs(inputs: &mut Inputs) {
+    for value in inputs.values_mut().flatten() {
+        // Rewrites the existing digit buffer rather than freeing it
+        let zeros = vec![0u32; value.iter_u32_digits().len()];
+        value.assign_from_slice(Sign::Plus, &zeros);
+    }
+}
+
+/// A proof's public signals don't make a valid transaction
+#[derive(Debug, thiserror::Error)]
+pub enum FormatError {
+    #[error("The proof's extDataHash {actual} isn't {expected}, computed from its recipient, relayer and fee")]
+    ExtDataMismatch { expected: String, actual: String },
+    #[error("Public amount {0} is out of range")]
+    AmountOutOfRange(BigInt),
+}
+
+/// The path of `utxo`'s commitment at `leaf_index` of `tree`, checked to
+/// lead to the root before a proof is spent on it
+pub fn note_path(tree: &MerkleTree, utxo: &Utxo, leaf_index: usize) -> Result<MerklePath, CryptoError> {
+    let path = tree.get_path(leaf_index)?;
+    if !verify_path(&tree.try_root()?, &str_to_fr(&utxo.commitment)?, &path) {
+        return Err(MerkleError::PathMismatch { index: leaf_index }.into());
+    }
+    Ok(path)
+}
+
+/// Nullifier of the zero-amount second input of a one-note spend: a note
+/// of the spender's `pubkey` with `blinding`, at leaf 0. The circuit only
+/// checks the Merkle path of inputs with an amount, so it needn't exist.
+pub fn dummy_nullifier(privkey: &Fr, pubkey: &Fr, mint: &Fr, blinding: &Fr) -> Result<Fr, CryptoError> {
+    let commitment = poseidon(&[Fr::from(0u64), *pubkey, *blinding, *mint])?;
+    let signature = poseidon(&[*privkey, commitment, Fr::from(0u64)])?;
+    poseidon(&[commitment, Fr::from(0u64), signature])
+}
+
+/// Inputs withdrawing all of `utxo`, at `leaf_index` of `tree`, with
+/// `ext_data`, and the `extDataHash` they commit to
+pub fn withdraw_inputs(
+    utxo: &Utxo,
+    leaf_index: usize,
+    tree: &MerkleTree,
+    ext_data: &ExtData,
+) -> Result<(Inputs, Fr), CryptoError> {
+    withdraw_inputs_with_rng(utxo, leaf_index, tree, ext_data, &mut rand::thread_rng())
+}
+
+/// [`withdraw_inputs`], with the dummy input's and the change's blindings
+/// drawn from `rng`
+pub fn withdraw_inputs_with_rng(
+    utxo: &Utxo,
+    leaf_index: usize,
+    tree: &MerkleTree,
+    ext_data: &ExtData,
+    rng: &mut impl RngCore,
+) -> Result<(Inputs, Fr), CryptoError> {
+    let amount = utxo.amount;
+    let root = tree.root();
+    let path = note_path(tree, utxo, leaf_index)?;
+
+    // Get utxo owner's keys
+    let privkey = str_to_fr(&utxo.privkey)?;
+    let pubkey = str_to_fr(&utxo.pubkey)?;
+    let mint = str_to_fr(&utxo.mint)?;
+
+    // Compute nullifier1 for real input
+    let nullifier1 = utxo.compute_nullifier(leaf_index)?;
+
+    // Dummy second input - uses SAME privkey/pubkey but different blinding
+    let dummy_blinding = random_fr_from(rng);
+    let nullifier2 = dummy_nullifier(&privkey, &pubkey, &mint, &dummy_blinding)?;
+
+    // Output commitments (both zero amount, same pubkey)
+    let out_blinding1 = random_fr_from(rng);
+    let out_blinding2 = random_fr_from(rng);
+    let out_commitment1 = poseidon(&[Fr::from(0u64), pubkey, out_blinding1, mint])?;
+    let out_commitment2 = poseidon(&[Fr::from(0u64), pubkey, out_blinding2, mint])?;
+
+    // Public amount (negative for withdrawal, represented in field)
+    let field_size = BigUint::parse_bytes(FIELD_SIZE.as_bytes(), 10).unwrap();
+    let neg_amount = field_size - BigUint::from(amount);
+    let public_amount_bigint = BigInt::from_biguint(num_bigint::Sign::Plus, neg_amount);
+
+    // ExtData hash
+    let ext_data_hash = ext_data.hash(amount)?;
+
+    // Build witness inputs
+    let path_bigint: Vec<BigInt> = path.elements.iter().map(fr_to_bigint).collect();
+    let zero_path: Vec<BigInt> = (0..MERKLE_TREE_HEIGHT).map(|_| BigInt::from(0)).collect();
+
+    let mut inputs: Inputs = HashMap::new();
+    inputs.insert("root".to_string(), vec![fr_to_bigint(&root)]);
+    inputs.insert("publicAmount".to_string(), vec![public_amount_bigint]);
+    inputs.insert(
+        "extDataHash".to_string(),
+        vec![fr_to_bigint(&ext_data_hash)],
+    );
+    inputs.insert("mintAddress".to_string(), vec![fr_to_bigint(&mint)]);
+    inputs.insert(
+        "inputNullifier".to_string(),
+        vec![fr_to_bigint(&nullifier1), fr_to_bigint(&nullifier2)],
+    );
+    inputs.insert(
+        "inAmount".to_string(),
+        vec![BigInt::from(amount), BigInt::from(0)],
+    );
+    // NOTE: Both inputs use the same private key (utxo owner)
+    inputs.insert(
+        "inPrivateKey".to_string(),
+        vec![str_to_bigint(&utxo.privkey)?, str_to_bigint(&utxo.privkey)?],
+    );
+    inputs.insert(
+        "inBlinding".to_string(),
+        vec![
+            str_to_bigint(&utxo.blinding)?,
+            fr_to_bigint(&dummy_blinding),
+        ],
+    );
+    inputs.insert(
+        "inPathIndices".to_string(),
+        vec![BigInt::from(leaf_index as u64), BigInt::from(0)],
+    );
+    inputs.insert(
+        "inPathElements".to_string(),
+        [path_bigint, zero_path].concat(),
+    );
+    inputs.insert(
+        "outputCommitment".to_string(),
+        vec![
+            fr_to_bigint(&out_commitment1),
+            fr_to_bigint(&out_commitment2),
+        ],
+    );
+    inputs.insert(
+        "outAmount".to_string(),
+        vec![BigInt::from(0), BigInt::from(0)],
+    );
+    // NOTE: Both outputs use the same pubkey (utxo owner)
+    inputs.insert(
+        "outPubkey".to_string(),
+        vec![str_to_bigint(&utxo.pubkey)?, str_to_bigint(&utxo.pubkey)?],
+    );
+    inputs.insert(
+        "outBlinding".to_string(),
+        vec![fr_to_bigint(&out_blinding1), fr_to_bigint(&out_blinding2)],
+    );
+
+    Ok((inputs, ext_data_hash))
+}
+
+/// Variables of the circuit of `matrices`, the constant 1 included: the
+/// length of its witness. ark-circom's `read_zkey` counts the constant
+/// twice, as an instance variable and again among the witness variables.
+pub fn variable_count(matrices: &ConstraintMatrices<Fr>) -> usize {
+    matrices.num_instance_variables + matrices.num_witness_variables - 1
+}
+
+/// Groth16 proof of the circuit of `params` and `matrices` over a computed
+/// `witness`, and its public signals
+pub fn groth16_prove<R: RngCore + CryptoRng>(
+    params: &ProvingKey<Bn254>,
+    matrices: &ConstraintMatrices<Fr>,
+    witness: &[Fr],
+    rng: &mut R,
+) -> Result<(Proof<Bn254>, Vec<Fr>), SynthesisError> {
+    let r = Fr::rand(rng);
+    let s = Fr::rand(rng);
+
+    let num_inputs = matrices.num_instance_variables;
+    let num_constraints = matrices.num_constraints;
+
+    let proof = Groth16::<Bn254, CircomReduction>::create_proof_with_reduction_and_matrices(
+        params,
+        r,
+        s,
+        matrices,
+        num_inputs,
+        num_constraints,
+        witness,
+    )?;
+
+    let public_signals: Vec<Fr> = witness[1..num_inputs].to_vec();
+
+    Ok((proof, public_signals))
+}
+
+/// Format proof for on-chain submission, checking that its `extDataHash`
+/// is `ext_data_hash`: the program doesn't recompute it, so a proof
+/// committing to anything else would only be caught by the relayer, or not
+/// at all
+pub fn transact_proof_data(
+    proof: &Proof<Bn254>,
+    public_signals: &[Fr],
+    ext_data_hash: &Fr,
+) -> Result<TransactProofData, FormatError> {
+    if public_signals[2] != *ext_data_hash {
+        return Err(FormatError::ExtDataMismatch {
+            expected: hex::encode(fr_to_be_bytes(ext_data_hash)),
+            actual: hex::encode(fr_to_be_bytes(&public_signals[2])),
+        });
+    }
+    let modulus = BigUint::parse_bytes(BN254_FIELD_MODULUS.as_bytes(), 10).unwrap();
+    let public_amount = public_signal_to_i64(&public_signals[1])?;
+
+    // Negate proof_a for pairing check
+    let a_x = g1_x_to_biguint(&proof.a);
+    let a_y = g1_y_to_biguint(&proof.a);
+    let neg_y = (&modulus - &a_y) % &modulus;
+
+    let proof_a = [biguint_to_be_32(&a_x), biguint_to_be_32(&neg_y)].concat();
+
+    // G2 point: x1_be || x0_be || y1_be || y0_be
+    let (b_x0, b_x1) = g2_x_to_biguint(&proof.b);
+    let (b_y0, b_y1) = g2_y_to_biguint(&proof.b);
+    let proof_b = [
+        biguint_to_be_32(&b_x1),
+        biguint_to_be_32(&b_x0),
+        biguint_to_be_32(&b_y1),
+        biguint_to_be_32(&b_y0),
+    ]
+    .concat();
+
+    let c_x = g1_x_to_biguint(&proof.c);
+    let c_y = g1_y_to_biguint(&proof.c);
+    let proof_c = [biguint_to_be_32(&c_x), biguint_to_be_32(&c_y)].concat();
+
+    // Public signals: root, publicAmount, extDataHash, null1, null2, commit1, commit2
+    let root = fr_to_be_bytes(&public_signals[0]).to_vec();
+    let nullifier1 = fr_to_be_bytes(&public_signals[3]).to_vec();
+    let nullifier2 = fr_to_be_bytes(&public_signals[4]).to_vec();
+    let commitment1 = fr_to_be_bytes(&public_signals[5]).to_vec();
+    let commitment2 = fr_to_be_bytes(&public_signals[6]).to_vec();
+    let ext_data_hash = fr_to_be_bytes(&public_signals[2]).to_vec();
+
+    Ok(TransactProofData {
+        proof_a,
+        proof_b,
+        proof_c,
+        root,
+        nullifier1,
+        nullifier2,
+        commitment1,
+        commitment2,
+        public_amount,
+        ext_data_hash,
+    })
+}
+
+fn public_signal_to_i64(signal: &Fr) -> Result<i64, FormatError> {
+    let field =
+        BigInt::parse_bytes(FIELD_SIZE.as_bytes(), 10).expect("FIELD_SIZE is a decimal integer");
+    let value = fr_to_bigint(signal);
+    let half_field = &field >> 1;
+    let signed = if value > half_field {
+        value - &field
+    } else {
+        value
+    };
+    signed.to_i64().ok_or(FormatError::AmountOutOfRange(signed))
+}
+
+/// `f` as ark-circom takes witness inputs
+pub fn fr_to_bigint(f: &Fr) -> BigInt {
+    let bytes = f.into_bigint().to_bytes_le();
+    BigInt::from_bytes_le(num_bigint::Sign::Plus, &bytes)
+}
+
+/// A decimal string, as notes store field elements, as a witness input
+pub fn str_to_bigint(s: &str) -> Result<BigInt, CryptoError> {
+    BigInt::parse_bytes(s.as_bytes(), 10).ok_or(CryptoError::InvalidFieldElement {
+        name: "bigint",
+        reason: "not a decimal integer",
+    })
+}
+
+fn g1_x_to_biguint(p: &G1Affine) -> BigUint {
+    BigUint::from_bytes_le(&p.x.into_bigint().to_bytes_le())
+}
+
+fn g1_y_to_biguint(p: &G1Affine) -> BigUint {
+    BigUint::from_bytes_le(&p.y.into_bigint().to_bytes_le())
+}
+
+fn g2_x_to_biguint(p: &G2Affine) -> (BigUint, BigUint) {
+    (
+        BigUint::from_bytes_le(&p.x.c0.into_bigint().to_bytes_le()),
+        BigUint::from_bytes_le(&p.x.c1.into_bigint().to_bytes_le()),
+    )
+}
+
+fn g2_y_to_biguint(p: &G2Affine) -> (BigUint, BigUint) {
+    (
+        BigUint::from_bytes_le(&p.y.c0.into_bigint().to_bytes_le()),
+        BigUint::from_bytes_le(&p.y.c1.into_bigint().to_bytes_le()),
+    )
+}
+
+fn biguint_to_be_32(n: &BigUint) -> Vec<u8> {
+    let bytes = n.to_bytes_be();
+    let mut result = vec![0u8; 32];
+    let start = 32 - bytes.len().min(32);
+    result[start..].copy_from_slice(&bytes[..bytes.len().min(32)]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    #[test]
+    fn test_withdraw_inputs_cover_the_circuit() {
+        let utxo = Utxo::new(100_000_000, crate::crypto::sol_mint()).unwrap();
+        let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+        tree.insert_many(&[Fr::from(1u64), str_to_fr(&utxo.commitment).unwrap()])
+            .unwrap();
+        let recipient = Pubkey::new_from_array([7; 32]);
+        let ext_data = ExtData::direct(recipient, recipient);
+
+        let (inputs, ext_data_hash) = withdraw_inputs(&utxo, 1, &tree, &ext_data).unwrap();
+        assert_eq!(ext_data_hash, ext_data.hash(utxo.amount).unwrap());
+        assert_eq!(inputs["root"], vec![fr_to_bigint(&tree.root())]);
+        assert_eq!(inputs["inPathIndices"][0], BigInt::from(1));
+        assert_eq!(inputs["inPathElements"].len(), 2 * MERKLE_TREE_HEIGHT);
+        assert_eq!(
+            inputs["inputNullifier"][0],
+            fr_to_bigint(&utxo.compute_nullifier(1).unwrap())
+        );
+        // -amount in the field
+        let field = BigInt::parse_bytes(FIELD_SIZE.as_bytes(), 10).unwrap();
+        assert_eq!(inputs["publicAmount"][0], field - BigInt::from(utxo.amount));
+
+        // Caught before proving: a leaf that isn't the note's, or isn't there
+        let err = withdraw_inputs(&utxo, 0, &tree, &ext_data).unwrap_err();
+        assert!(matches!(err, CryptoError::Merkle(MerkleError::PathMismatch { index: 0 })), "{}", err);
+        let err = withdraw_inputs(&utxo, 2, &tree, &ext_data).unwrap_err();
+        assert!(matches!(err, CryptoError::Merkle(MerkleError::LeafOutOfRange { index: 2, leaves: 2 })));
+    }
+
+    #[test]
+    fn test_wipe_inputs() {
+        let utxo = Utxo::new(100_000_000, crate::crypto::sol_mint()).unwrap();
+        let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+        tree.insert(str_to_fr(&utxo.commitment).unwrap()).unwrap();
+        let recipient = Pubkey::new_from_array([7; 32]);
+        let (mut inputs, _) = withdraw_inputs(&utxo, 0, &tree, &ExtData::direct(recipient, recipient)).unwrap();
+        assert_ne!(inputs["inPrivateKey"][0], BigInt::from(0));
+
+        wipe_inputs(&mut inputs);
+        assert!(inputs.values().flatten().all(|value| *value == BigInt::from(0)));
+    }
+
+    #[test]
+    fn test_public_amount_is_signed() {
+        let minus_one = -Fr::from(1u64);
+        assert_eq!(public_signal_to_i64(&minus_one).unwrap(), -1);
+        assert_eq!(public_signal_to_i64(&Fr::from(5u64)).unwrap(), 5);
+        let too_large = Fr::from(u64::MAX) * Fr::from(4u64);
+        assert!(matches!(
+            public_signal_to_i64(&too_large),
+            Err(FormatError::AmountOutOfRange(_))
+        ));
+    }
+}
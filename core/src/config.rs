@@ -0,0 +1,437 @@
+//! Addresses of the deployed pool
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use thiserror::Error;
+
+use crate::spl::SplPool;
+
+/// Program ID for privacy-zig on testnet (with separate recipient account)
+pub const PROGRAM_ID: &str = "9A6fck3xNW2C6vwwqM4i1f4GeYpieuB7XKpF1YFduT6h";
+pub const TREE_ACCOUNT: &str = "4EGnTF2XfKDTBAszzoqQLe4zbmiURkWtkYQGnj99GiJf";
+pub const GLOBAL_CONFIG: &str = "7RUeHfhA6L7BUrmt9ZK7SJ9rmTMkD8qjjJgHRrUEGMq9";
+pub const POOL_VAULT: &str = "7nAKNHQwTeaybrnX6y3c3fLDL3qzQ3A6FGwMwH1LPc8q";
+/// Use relayer address as fee_recipient to avoid exposing user address
+pub const FEE_RECIPIENT: &str = "FcuLoWBhZ8bNQRsSgGhH5NCJJbqK5uhHMZR6V21kyTgS";
+
+/// `PRIVACY_POOL_*` variables overriding each address, in field order
+const ENV_VARS: [&str; 5] = [
+    "PRIVACY_POOL_PROGRAM_ID",
+    "PRIVACY_POOL_TREE_ACCOUNT",
+    "PRIVACY_POOL_GLOBAL_CONFIG",
+    "PRIVACY_POOL_POOL_VAULT",
+    "PRIVACY_POOL_FEE_RECIPIENT",
+];
+
+/// Config file keys of each address, in field order
+const FIELDS: [&str; 5] = [
+    "program_id",
+    "tree_account",
+    "global_config",
+    "pool_vault",
+    "fee_recipient",
+];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ConfigError {
+    #[error("Unknown network {0:?} (expected devnet, testnet or mainnet)")]
+    UnknownNetwork(String),
+    #[error("No pool is deployed on {network}; set {}", missing.join(", "))]
+    NotDeployed {
+        network: &'static str,
+        missing: Vec<&'static str>,
+    },
+    #[error("Invalid {field}: {value:?} is not a base58 pubkey")]
+    InvalidAddress { field: &'static str, value: String },
+    #[error(
+        "No pool is configured for the token {0}; add it as an [[spl]] entry of the config file"
+    )]
+    NoSplPool(Pubkey),
+}
+
+/// A Solana cluster with a bundled RPC URL and, where the pool is deployed,
+/// its addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Devnet,
+    Testnet,
+    Mainnet,
+}
+
+impl Network {
+    /// Cluster name, as recorded in notes
+    pub fn name(self) -> &'static str {
+        match self {
+            Network::Devnet => "devnet",
+            Network::Testnet => "testnet",
+            Network::Mainnet => "mainnet-beta",
+        }
+    }
+
+    pub fn rpc_url(self) -> &'static str {
+        match self {
+            Network::Devnet => "https://api.devnet.solana.com",
+            Network::Testnet => "https://api.testnet.solana.com",
+            Network::Mainnet => "https://api.mainnet-beta.solana.com",
+        }
+    }
+
+    /// Solana Explorer link for a transaction on this cluster
+    pub fn explorer_tx_url(self, signature: &str) -> String {
+        match self {
+            Network::Mainnet => format!("https://explorer.solana.com/tx/{}", signature),
+            _ => format!(
+                "https://explorer.solana.com/tx/{}?cluster={}",
+                signature,
+                self.name()
+            ),
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "devnet" => Ok(Network::Devnet),
+            "testnet" => Ok(Network::Testnet),
+            "mainnet" | "mainnet-beta" => Ok(Network::Mainnet),
+            _ => Err(ConfigError::UnknownNetwork(s.to_string())),
+        }
+    }
+}
+
+/// Pool configuration with all relevant addresses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolConfig {
+    pub program_id: Pubkey,
+    pub tree_account: Pubkey,
+    pub global_config: Pubkey,
+    pub pool_vault: Pubkey,
+    pub fee_recipient: Pubkey,
+    /// Pools of SPL tokens, one per mint
+    pub spl_pools: Vec<SplPool>,
+}
+
+/// Pool addresses as written in a config file; unset ones fall back to the
+/// network's preset
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolAddresses {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub program_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tree_account: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub global_config: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_vault: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee_recipient: Option<String>,
+    /// SPL token pools, as `[[spl]]` tables; no network has a preset
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spl: Vec<SplPoolAddresses>,
+}
+
+/// Addresses of an SPL token pool, as created by `initialize_spl`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SplPoolAddresses {
+    pub mint: String,
+    pub tree_account: String,
+    pub token_pool: String,
+    pub vault: String,
+}
+
+impl SplPoolAddresses {
+    fn parse(&self) -> Result<SplPool, ConfigError> {
+        Ok(SplPool {
+            mint: parse_field("spl.mint", &self.mint)?,
+            tree_account: parse_field("spl.tree_account", &self.tree_account)?,
+            token_pool: parse_field("spl.token_pool", &self.token_pool)?,
+            vault: parse_field("spl.vault", &self.vault)?,
+        })
+    }
+}
+
+impl PoolAddresses {
+    fn fields(&self) -> [Option<&str>; 5] {
+        [
+            self.program_id.as_deref(),
+            self.tree_account.as_deref(),
+            self.global_config.as_deref(),
+            self.pool_vault.as_deref(),
+            self.fee_recipient.as_deref(),
+        ]
+    }
+}
+
+impl PoolConfig {
+    /// The testnet deployment
+    pub fn testnet() -> Self {
+        Self {
+            program_id: parse(PROGRAM_ID),
+            tree_account: parse(TREE_ACCOUNT),
+            global_config: parse(GLOBAL_CONFIG),
+            pool_vault: parse(POOL_VAULT),
+            fee_recipient: parse(FEE_RECIPIENT),
+            spl_pools: Vec::new(),
+        }
+    }
+
+    /// The bundled deployment on `network`, if the pool is deployed there
+    pub fn preset(network: Network) -> Option<Self> {
+        match network {
+            Network::Testnet => Some(Self::testnet()),
+            Network::Devnet | Network::Mainnet => None,
+        }
+    }
+
+    /// The testnet deployment, with each address overridable through
+    /// `PRIVACY_POOL_*` environment variables
+    pub fn from_env() -> Self {
+        Self::for_network(Network::Testnet).expect("testnet has a preset")
+    }
+
+    /// `network`'s preset with each address overridable through
+    /// `PRIVACY_POOL_*` environment variables. Without a preset, all of them
+    /// must be set.
+    pub fn for_network(network: Network) -> Result<Self, ConfigError> {
+        Self::resolve(network, &PoolAddresses::default())
+    }
+
+    /// Like [`for_network`](Self::for_network), with `file` between the
+    /// environment and the preset: env > file > preset
+    pub fn resolve(network: Network, file: &PoolAddresses) -> Result<Self, ConfigError> {
+        Self::with_overrides(network, file, |key| std::env::var(key).ok())
+    }
+
+    /// Like [`resolve`](Self::resolve), reading the `PRIVACY_POOL_*`
+    /// variables through `lookup`
+    pub fn with_overrides(
+        network: Network,
+        file: &PoolAddresses,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let preset = Self::preset(network).map(|p| {
+            [
+                p.program_id,
+                p.tree_account,
+                p.global_config,
+                p.pool_vault,
+                p.fee_recipient,
+            ]
+        });
+        let mut addresses = [Pubkey::default(); 5];
+        let mut missing = Vec::new();
+        for (i, (key, from_file)) in ENV_VARS.iter().zip(file.fields()).enumerate() {
+            let value = match (lookup(key), from_file) {
+                (Some(value), _) => Some(parse_field(key, &value)?),
+                (None, Some(value)) => Some(parse_field(FIELDS[i], value)?),
+                (None, None) => preset.map(|p| p[i]),
+            };
+            match value {
+                Some(address) => addresses[i] = address,
+                None => missing.push(*key),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(ConfigError::NotDeployed {
+                network: network.name(),
+                missing,
+            });
+        }
+        let [program_id, tree_account, global_config, pool_vault, fee_recipient] = addresses;
+        Ok(Self {
+            program_id,
+            tree_account,
+            global_config,
+            pool_vault,
+            fee_recipient,
+            spl_pools: file
+                .spl
+                .iter()
+                .map(SplPoolAddresses::parse)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// The pool of `mint`'s tokens
+    pub fn spl_pool(&self, mint: &Pubkey) -> Result<&SplPool, ConfigError> {
+        self.spl_pools
+            .iter()
+            .find(|pool| pool.mint == *mint)
+            .ok_or(ConfigError::NoSplPool(*mint))
+    }
+
+    /// These addresses with `pool`'s tree and vault in place of the SOL
+    /// pool's, for reading its tree and pricing its transactions
+    pub fn for_spl(&self, pool: &SplPool) -> Self {
+        Self {
+            tree_account: pool.tree_account,
+            pool_vault: pool.vault,
+            ..self.clone()
+        }
+    }
+
+    /// PDA that records a spent nullifier
+    pub fn nullifier_pda(&self, nullifier: &[u8]) -> Pubkey {
+        Pubkey::find_program_address(&[b"nullifier", nullifier], &self.program_id).0
+    }
+
+    /// Writable accounts every transact locks, whose recent prioritization
+    /// fees set the price of an `auto` priority fee
+    pub fn contended_accounts(&self) -> [Pubkey; 2] {
+        [self.tree_account, self.pool_vault]
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+fn parse(address: &str) -> Pubkey {
+    Pubkey::from_str(address).expect("Invalid built-in pubkey")
+}
+
+fn parse_field(field: &'static str, value: &str) -> Result<Pubkey, ConfigError> {
+    Pubkey::from_str(value.trim()).map_err(|_| ConfigError::InvalidAddress {
+        field,
+        value: value.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preset_with_overrides() {
+        let vault = Pubkey::new_unique();
+        let config =
+            PoolConfig::with_overrides(Network::Testnet, &PoolAddresses::default(), |key| {
+                (key == "PRIVACY_POOL_POOL_VAULT").then(|| vault.to_string())
+            })
+            .unwrap();
+        assert_eq!(config.pool_vault, vault);
+        assert_eq!(config.tree_account, PoolConfig::testnet().tree_account);
+    }
+
+    #[test]
+    fn undeployed_network_needs_every_address() {
+        let err = PoolConfig::with_overrides(Network::Mainnet, &PoolAddresses::default(), |key| {
+            (key == "PRIVACY_POOL_PROGRAM_ID").then(|| Pubkey::new_unique().to_string())
+        })
+        .unwrap_err();
+        let ConfigError::NotDeployed { network, missing } = err else {
+            panic!("expected NotDeployed");
+        };
+        assert_eq!(network, "mainnet-beta");
+        assert_eq!(missing, &ENV_VARS[1..]);
+
+        let config = PoolConfig::with_overrides(Network::Devnet, &PoolAddresses::default(), |_| {
+            Some(Pubkey::new_unique().to_string())
+        });
+        assert!(config.is_ok());
+    }
+
+    #[test]
+    fn env_overrides_file_overrides_preset() {
+        let (from_env, from_file) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let file = PoolAddresses {
+            tree_account: Some(from_file.to_string()),
+            pool_vault: Some(from_file.to_string()),
+            ..Default::default()
+        };
+        let config = PoolConfig::with_overrides(Network::Testnet, &file, |key| {
+            (key == "PRIVACY_POOL_POOL_VAULT").then(|| from_env.to_string())
+        })
+        .unwrap();
+        assert_eq!(config.pool_vault, from_env);
+        assert_eq!(config.tree_account, from_file);
+        assert_eq!(config.program_id, PoolConfig::testnet().program_id);
+    }
+
+    #[test]
+    fn invalid_address_names_field() {
+        let file = PoolAddresses {
+            global_config: Some("not-a-pubkey".to_string()),
+            ..Default::default()
+        };
+        let err = PoolConfig::with_overrides(Network::Testnet, &file, |_| None).unwrap_err();
+        assert_eq!(
+            err,
+            ConfigError::InvalidAddress {
+                field: "global_config",
+                value: "not-a-pubkey".to_string()
+            }
+        );
+        assert!(err.to_string().contains("global_config"));
+
+        let err = PoolConfig::with_overrides(Network::Testnet, &PoolAddresses::default(), |key| {
+            (key == "PRIVACY_POOL_FEE_RECIPIENT").then(|| "0xdead".to_string())
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::InvalidAddress {
+                field: "PRIVACY_POOL_FEE_RECIPIENT",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn spl_pools_from_file() {
+        let pool = SplPool {
+            mint: Pubkey::new_unique(),
+            tree_account: Pubkey::new_unique(),
+            token_pool: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+        };
+        let mut file = PoolAddresses {
+            spl: vec![SplPoolAddresses {
+                mint: pool.mint.to_string(),
+                tree_account: pool.tree_account.to_string(),
+                token_pool: pool.token_pool.to_string(),
+                vault: pool.vault.to_string(),
+            }],
+            ..Default::default()
+        };
+        let config = PoolConfig::with_overrides(Network::Testnet, &file, |_| None).unwrap();
+        assert_eq!(config.spl_pool(&pool.mint), Ok(&pool));
+        let other = Pubkey::new_unique();
+        assert_eq!(config.spl_pool(&other), Err(ConfigError::NoSplPool(other)));
+
+        let spl = config.for_spl(&pool);
+        assert_eq!(spl.contended_accounts(), [pool.tree_account, pool.vault]);
+        assert_eq!(spl.program_id, config.program_id);
+
+        file.spl[0].vault = "vault".to_string();
+        let err = PoolConfig::with_overrides(Network::Testnet, &file, |_| None).unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::InvalidAddress {
+                field: "spl.vault",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn network_names_and_links() {
+        assert_eq!("mainnet".parse::<Network>(), Ok(Network::Mainnet));
+        assert_eq!("Devnet".parse::<Network>(), Ok(Network::Devnet));
+        assert!("localnet".parse::<Network>().is_err());
+        assert_eq!(
+            Network::Testnet.explorer_tx_url("abc"),
+            "https://explorer.solana.com/tx/abc?cluster=testnet"
+        );
+        assert_eq!(
+            Network::Mainnet.explorer_tx_url("abc"),
+            "https://explorer.solana.com/tx/abc"
+        );
+    }
+}
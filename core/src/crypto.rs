@@ -0,0 +1,1247 @@
+//! Notes and the hashing the circuit checks them with
+//!
+//! Poseidon over BN254 as circom computes it, the pool's Merkle tree of note
+//! commitments, [`Utxo`] notes with their commitments and nullifiers, the
+//! [`ExtData`] a transaction's `extDataHash` commits to, and parsing field
+//! elements from the decimal strings notes store them as. All of it is pure
+//! Rust, so the CLI, the relayer and the WASM bindings hash alike.
+
+use light_poseidon::{Poseidon, PoseidonError, PoseidonHasher};
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use zeroize::Zeroize;
+
+pub use crate::{FIELD_SIZE, MERKLE_TREE_HEIGHT};
+
+/// BN254 base field modulus (for G1 point negation)
+pub const BN254_FIELD_MODULUS: &str = "21888242871839275222246405745257275088696311157297823662689037894645226208583";
+
+/// Why hashing or reading a note failed
+#[derive(Debug, thiserror::Error)]
+pub enum CryptoError {
+    /// A key, blinding or commitment that can't be read as a field element;
+    /// the value itself is left out, as it may be a private key
+    #[error("Invalid {name}: {reason}")]
+    InvalidFieldElement {
+        name: &'static str,
+        reason: &'static str,
+    },
+    #[error("Poseidon hash failed: {0}")]
+    Poseidon(#[from] PoseidonError),
+    #[error(transparent)]
+    Merkle(#[from] MerkleError),
+}
+
+/// A leaf or path the Merkle tree can't give
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum MerkleError {
+    #[error("Leaf {index} is not in the Merkle tree, which has {leaves} leaves")]
+    LeafOutOfRange { index: usize, leaves: usize },
+    /// The layers don't match the height, as in a tree whose rebuild failed
+    #[error("The Merkle tree's layers are incomplete; rebuild it")]
+    NotBuilt,
+    #[error("The Merkle path of leaf {index} doesn't lead from the note's commitment to the root")]
+    PathMismatch { index: usize },
+}
+
+/// Poseidon hasher wrapper
+pub struct PoseidonHash {
+    hasher: Poseidon<Fr>,
+}
+
+impl PoseidonHash {
+    /// A hasher of two inputs, circom's parameters
+    pub fn new() -> Result<Self, CryptoError> {
+        Ok(Self {
+            hasher: Poseidon::<Fr>::new_circom(2)?,
+        })
+    }
+
+    /// Hash two field elements
+    pub fn hash2(&mut self, a: &Fr, b: &Fr) -> Result<Fr, CryptoError> {
+        Ok(self.hasher.hash(&[*a, *b])?)
+    }
+
+    /// Hash a single field element (with padding)
+    pub fn hash1(&mut self, a: &Fr) -> Result<Fr, CryptoError> {
+        poseidon(&[*a])
+    }
+
+    /// Hash three field elements, as a note's signature and nullifier do
+    pub fn hash3(&mut self, a: &Fr, b: &Fr, c: &Fr) -> Result<Fr, CryptoError> {
+        poseidon(&[*a, *b, *c])
+    }
+
+    /// Hash four field elements, as a note's commitment does
+    pub fn hash4(&mut self, a: &Fr, b: &Fr, c: &Fr, d: &Fr) -> Result<Fr, CryptoError> {
+        poseidon(&[*a, *b, *c, *d])
+    }
+
+    /// Hash multiple field elements
+    pub fn hash_many(&mut self, inputs: &[Fr]) -> Result<Fr, CryptoError> {
+        poseidon(inputs)
+    }
+}
+
+/// Most inputs any hash in the protocol takes (a commitment's four)
+const MAX_CACHED_INPUTS: usize = 4;
+
+thread_local! {
+    /// One hasher per input count, built on first use: deriving the round
+    /// constants costs far more than a hash
+    static HASHERS: RefCell<[Option<Poseidon<Fr>>; MAX_CACHED_INPUTS]> =
+        const { RefCell::new([None, None, None, None]) };
+}
+
+/// Poseidon of `inputs`, with the circom parameters for their number
+pub fn poseidon(inputs: &[Fr]) -> Result<Fr, CryptoError> {
+    let n = inputs.len();
+    if !(1..=MAX_CACHED_INPUTS).contains(&n) {
+        return Ok(Poseidon::<Fr>::new_circom(n)?.hash(inputs)?);
+    }
+    HASHERS.with(|hashers| {
+        let slot = &mut hashers.borrow_mut()[n - 1];
+        let hasher = match slot {
+            Some(hasher) => hasher,
+            None => slot.insert(Poseidon::<Fr>::new_circom(n)?),
+        };
+        Ok(hasher.hash(inputs)?)
+    })
+}
+
+fn invalid(name: &'static str, reason: &'static str) -> CryptoError {
+    CryptoError::InvalidFieldElement { name, reason }
+}
+
+/// Convert bigint string to Fr, reducing values outside the field. Only
+/// for strings the CLI wrote itself with [`fr_to_str`], which are already
+/// in the field; use [`str_to_fr_strict`] for anything read from outside
+pub fn str_to_fr(s: &str) -> Result<Fr, CryptoError> {
+    let big = BigUint::from_str(s).map_err(|_| invalid("bigint", "not a decimal integer"))?;
+    let bytes = big.to_bytes_le();
+    let mut arr = [0u8; 32];
+    let len = bytes.len().min(32);
+    arr[..len].copy_from_slice(&bytes[..len]);
+    Ok(Fr::from_le_bytes_mod_order(&arr))
+}
+
+/// Convert bigint string to Fr, rejecting values that are not below the
+/// field modulus instead of silently reducing them
+pub fn str_to_fr_strict(s: &str) -> Result<Fr, CryptoError> {
+    parse_field("field element", s)
+}
+
+/// [`str_to_fr_strict`], naming the value as `name` in errors
+pub fn parse_field(name: &'static str, s: &str) -> Result<Fr, CryptoError> {
+    let big = BigUint::from_str(s).map_err(|_| invalid(name, "not a decimal integer"))?;
+    if big.bits() > 256 {
+        return Err(invalid(name, "does not fit in 32 bytes"));
+    }
+    let modulus = BigUint::from_str(FIELD_SIZE).expect("FIELD_SIZE is a decimal integer");
+    if big >= modulus {
+        return Err(invalid(name, "not below the field modulus"));
+    }
+    Ok(Fr::from(big))
+}
+
+/// Convert 32 big-endian bytes, the layout of field elements in
+/// instruction data and account state, to Fr, rejecting any other length
+/// and values that are not below the field modulus
+pub fn be_bytes_to_fr_strict(bytes: &[u8]) -> Result<Fr, CryptoError> {
+    let name = "field element";
+    if bytes.len() != 32 {
+        return Err(invalid(name, "not 32 bytes"));
+    }
+    let value = Fr::from_be_bytes_mod_order(bytes);
+    if fr_to_be_bytes(&value) != bytes {
+        return Err(invalid(name, "not below the field modulus"));
+    }
+    Ok(value)
+}
+
+/// Convert Fr to bigint string
+pub fn fr_to_str(f: &Fr) -> String {
+    let bytes = f.into_bigint().to_bytes_le();
+    let big = BigUint::from_bytes_le(&bytes);
+    big.to_string()
+}
+
+/// A private key or blinding as a decimal string. It is wiped from memory
+/// when dropped, and `Debug` never prints it, so structs holding one can
+/// derive `Debug` without leaking it. Serialized as the plain string.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SecretString(String);
+
+impl SecretString {
+    /// The secret itself; don't log it
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for SecretString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl Zeroize for SecretString {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+/// Convert Fr to big-endian bytes (32 bytes)
+///
+/// Right-aligned whatever length the bigint serializes to: a shorter
+/// encoding is zero-padded, and only the low 32 bytes of a longer one are
+/// kept, which are the whole value for anything below the modulus.
+pub fn fr_to_be_bytes(f: &Fr) -> [u8; 32] {
+    let bytes = f.into_bigint().to_bytes_be();
+    let len = bytes.len().min(32);
+    let mut arr = [0u8; 32];
+    arr[32 - len..].copy_from_slice(&bytes[bytes.len() - len..]);
+    arr
+}
+
+/// Generate random field element (for blinding/keys)
+pub fn random_fr() -> Fr {
+    random_fr_from(&mut rand::thread_rng())
+}
+
+/// A random field element drawn from `rng`
+///
+/// Uniform over the whole field: 254-bit candidates are drawn until one is
+/// below the modulus, which takes 1.3 tries on average. Reducing wider values
+/// mod p would make small elements more likely.
+pub fn random_fr_from(rng: &mut impl rand::RngCore) -> Fr {
+    loop {
+        let mut bytes = [0u8; 32];
+        rng.fill_bytes(&mut bytes);
+        // Little-endian: clear the top two bits of the last byte
+        bytes[31] &= 0x3f;
+        let limbs =
+            std::array::from_fn(|i| u64::from_le_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap()));
+        if let Some(fr) = Fr::from_bigint(ark_ff::BigInt(limbs)) {
+            return fr;
+        }
+    }
+}
+
+/// Domain separation tags for deterministic note keys
+const NOTE_SEED_TAG: &[u8] = b"privacy-zig/note-seed";
+const NOTE_PRIVKEY_TAG: &[u8] = b"privacy-zig/privkey";
+const NOTE_BLINDING_TAG: &[u8] = b"privacy-zig/blinding";
+
+/// Root of deterministic note keys, derived from the wallet secret key
+///
+/// The keys of note `index` are `Poseidon(seed, index, tag)`, so a lost note
+/// store can be rebuilt from the wallet alone (`privacy scan --from-seed`).
+/// Changing anything here orphans every existing deterministic note.
+#[derive(Clone)]
+pub struct NoteSeed(Fr);
+
+impl NoteSeed {
+    /// Derive the seed from a 32-byte ed25519 secret key
+    pub fn from_secret_key(secret: &[u8; 32]) -> Result<Self, CryptoError> {
+        let hi = Fr::from_be_bytes_mod_order(&secret[..16]);
+        let lo = Fr::from_be_bytes_mod_order(&secret[16..]);
+        let tag = Fr::from_be_bytes_mod_order(NOTE_SEED_TAG);
+        Ok(Self(poseidon(&[hi, lo, tag])?))
+    }
+
+    /// Private key of note `index`
+    pub fn privkey(&self, index: u64) -> Result<Fr, CryptoError> {
+        self.derive(index, NOTE_PRIVKEY_TAG)
+    }
+
+    /// Blinding of note `index`
+    pub fn blinding(&self, index: u64) -> Result<Fr, CryptoError> {
+        self.derive(index, NOTE_BLINDING_TAG)
+    }
+
+    fn derive(&self, index: u64, tag: &[u8]) -> Result<Fr, CryptoError> {
+        let tag = Fr::from_be_bytes_mod_order(tag);
+        poseidon(&[self.0, Fr::from(index), tag])
+    }
+}
+
+/// Zero hashes of a tree of [`MERKLE_TREE_HEIGHT`], the pool's, computed
+/// by the first such tree and shared by every later one
+static STANDARD_ZEROS: OnceLock<Vec<Fr>> = OnceLock::new();
+
+/// Merkle tree for privacy pool
+pub struct MerkleTree {
+    height: usize,
+    zeros: Vec<Fr>,
+    leaves: Vec<Fr>,
+    /// Leaf indices of each leaf value, by its big-endian encoding
+    positions: HashMap<[u8; 32], Vec<usize>>,
+    layers: Vec<Vec<Fr>>,
+    hasher: PoseidonHash,
+}
+
+impl MerkleTree {
+    /// An empty tree of `height` levels
+    pub fn new(height: usize) -> Result<Self, CryptoError> {
+        let mut hasher = PoseidonHash::new()?;
+        let zeros = Self::zero_hashes(height, &mut hasher)?;
+        Ok(Self {
+            height,
+            zeros,
+            leaves: Vec::new(),
+            positions: HashMap::new(),
+            layers: Vec::new(),
+            hasher,
+        })
+    }
+
+    fn zero_hashes(height: usize, hasher: &mut PoseidonHash) -> Result<Vec<Fr>, CryptoError> {
+        if height != MERKLE_TREE_HEIGHT {
+            return Self::compute_zero_hashes(height, hasher);
+        }
+        if let Some(zeros) = STANDARD_ZEROS.get() {
+            return Ok(zeros.clone());
+        }
+        let zeros = Self::compute_zero_hashes(height, hasher)?;
+        Ok(STANDARD_ZEROS.get_or_init(|| zeros).clone())
+    }
+
+    fn compute_zero_hashes(
+        height: usize,
+        hasher: &mut PoseidonHash,
+    ) -> Result<Vec<Fr>, CryptoError> {
+        let mut zeros = vec![Fr::from(0u64)];
+        for i in 1..=height {
+            let prev = zeros[i - 1];
+            zeros.push(hasher.hash2(&prev, &prev)?);
+        }
+        Ok(zeros)
+    }
+
+    /// Append `leaf` and rehash
+    pub fn insert(&mut self, leaf: Fr) -> Result<(), CryptoError> {
+        self.insert_many(&[leaf])
+    }
+
+    /// Append `leaves` in order, rehashing once
+    pub fn insert_many(&mut self, leaves: &[Fr]) -> Result<(), CryptoError> {
+        for leaf in leaves {
+            self.positions.entry(fr_to_be_bytes(leaf)).or_default().push(self.leaves.len());
+            self.leaves.push(*leaf);
+        }
+        self.rebuild()
+    }
+
+    /// The leaves, by index
+    pub fn leaves(&self) -> &[Fr] {
+        &self.leaves
+    }
+
+    /// The root of an empty subtree at each level, from the empty leaf
+    /// (`0`) up to the empty tree's root
+    pub fn zeros(&self) -> &[Fr] {
+        &self.zeros
+    }
+
+    /// Index of the first leaf equal to `commitment`, without scanning
+    pub fn index_of(&self, commitment: &Fr) -> Option<usize> {
+        self.indices_of(commitment).first().copied()
+    }
+
+    /// Indices of every leaf equal to `commitment`, in order. Only a
+    /// commitment deposited more than once has several.
+    pub fn indices_of(&self, commitment: &Fr) -> &[usize] {
+        self.positions.get(&fr_to_be_bytes(commitment)).map_or(&[], Vec::as_slice)
+    }
+
+    fn rebuild(&mut self) -> Result<(), CryptoError> {
+        self.layers = vec![self.leaves.clone()];
+
+        for level in 0..self.height {
+            let mut next = hash_layer(&self.layers[level], &self.zeros[level], &mut self.hasher)?;
+            if next.is_empty() {
+                next.push(self.zeros[level + 1]);
+            }
+
+            self.layers.push(next);
+        }
+        Ok(())
+    }
+
+    /// The root, or that of an empty tree before any insert
+    pub fn root(&self) -> Fr {
+        self.try_root().unwrap_or(self.zeros[self.height])
+    }
+
+    /// The root, or [`MerkleError::NotBuilt`] if the layers are incomplete
+    pub fn try_root(&self) -> Result<Fr, MerkleError> {
+        if self.layers.is_empty() {
+            return Ok(self.zeros[self.height]);
+        }
+        self.layers
+            .get(self.height)
+            .and_then(|top| top.first())
+            .copied()
+            .ok_or(MerkleError::NotBuilt)
+    }
+
+    /// The path from leaf `leaf_index` to the root, which must be one of
+    /// the inserted leaves
+    pub fn get_path(&self, leaf_index: usize) -> Result<MerklePath, MerkleError> {
+        if leaf_index >= self.leaves.len() {
+            return Err(MerkleError::LeafOutOfRange { index: leaf_index, leaves: self.leaves.len() });
+        }
+        if self.layers.len() <= self.height {
+            return Err(MerkleError::NotBuilt);
+        }
+
+        let mut path = MerklePath { elements: Vec::new(), indices: Vec::new() };
+        let mut current_index = leaf_index;
+        for level in 0..self.height {
+            let is_right = current_index % 2 == 1;
+            let sibling_index = if is_right {
+                current_index - 1
+            } else {
+                current_index + 1
+            };
+
+            path.indices.push(if is_right { 1u8 } else { 0u8 });
+            path.elements.push(self.layers[level].get(sibling_index).copied().unwrap_or(self.zeros[level]));
+
+            current_index /= 2;
+        }
+
+        Ok(path)
+    }
+
+    /// The index the next leaf gets
+    pub fn leaf_count(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// The root the tree had when it held its first `leaves` leaves, as the
+    /// program recorded it in its root history then
+    pub fn root_after(&self, leaves: usize) -> Result<Fr, CryptoError> {
+        if leaves > self.leaves.len() {
+            return Err(MerkleError::LeafOutOfRange { index: leaves, leaves: self.leaves.len() }.into());
+        }
+        if leaves == 0 {
+            return Ok(self.zeros[self.height]);
+        }
+        if self.layers.len() <= self.height {
+            return Err(MerkleError::NotBuilt.into());
+        }
+
+        // Up from the last of those leaves: the subtrees to its left were
+        // complete by then, and those to its right still empty
+        let mut hasher = PoseidonHash::new()?;
+        let mut index = leaves - 1;
+        let mut node = self.leaves[index];
+        for level in 0..self.height {
+            node = if index % 2 == 1 {
+                hasher.hash2(&self.layers[level][index - 1], &node)?
+            } else {
+                hasher.hash2(&node, &self.zeros[level])?
+            };
+            index /= 2;
+        }
+        Ok(node)
+    }
+
+    /// The roots after each of the tree's last `n` leaf counts, newest
+    /// first: element `i` is the root with `i` fewer leaves, down to the
+    /// empty tree's root at the most
+    pub fn recent_roots(&self, n: usize) -> Result<Vec<Fr>, CryptoError> {
+        let newest = self.leaves.len();
+        (0..n.min(newest + 1)).map(|behind| self.root_after(newest - behind)).collect()
+    }
+}
+
+/// The parents of each pair of nodes in `layer`, a last unpaired node
+/// hashed with `zero`
+#[cfg(not(feature = "parallel"))]
+fn hash_layer(layer: &[Fr], zero: &Fr, hasher: &mut PoseidonHash) -> Result<Vec<Fr>, CryptoError> {
+    layer.chunks(2).map(|pair| hasher.hash2(&pair[0], pair.get(1).unwrap_or(zero))).collect()
+}
+
+/// [`hash_layer`] on every core, each thread with its own hasher
+#[cfg(feature = "parallel")]
+fn hash_layer(layer: &[Fr], zero: &Fr, _hasher: &mut PoseidonHash) -> Result<Vec<Fr>, CryptoError> {
+    use rayon::prelude::*;
+
+    layer
+        .par_chunks(2)
+        .map_init(PoseidonHash::new, |hasher, pair| {
+            let (left, right) = (&pair[0], pair.get(1).unwrap_or(zero));
+            match hasher {
+                Ok(hasher) => hasher.hash2(left, right),
+                // Fails the same way, returning the error
+                Err(_) => poseidon(&[*left, *right]),
+            }
+        })
+        .collect()
+}
+
+/// A leaf's siblings from the bottom of the tree up, and at each level
+/// whether the node on the path is a left (0) or right (1) child
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerklePath {
+    /// Sibling at each level
+    pub elements: Vec<Fr>,
+    /// 0 or 1 at each level
+    pub indices: Vec<u8>,
+}
+
+/// Whether `path` leads from `leaf` to `root`; checked before spending a
+/// note, as a bad path would only show after proving
+pub fn verify_path(root: &Fr, leaf: &Fr, path: &MerklePath) -> bool {
+    if path.elements.len() != path.indices.len() {
+        return false;
+    }
+    let Ok(mut hasher) = PoseidonHash::new() else {
+        return false;
+    };
+    let mut current = *leaf;
+    for (sibling, index) in path.elements.iter().zip(&path.indices) {
+        let parent = match index {
+            0 => hasher.hash2(&current, sibling),
+            1 => hasher.hash2(sibling, &current),
+            _ => return false,
+        };
+        let Ok(parent) = parent else {
+            return false;
+        };
+        current = parent;
+    }
+    current == *root
+}
+
+/// Commitments already present in the on-chain tree, keyed by their
+/// big-endian encoding, for cheap duplicate checks before a deposit
+pub struct CommitmentSet {
+    leaves: HashMap<[u8; 32], usize>,
+}
+
+impl CommitmentSet {
+    /// The set of the tree's `leaves`, by index
+    pub fn from_leaves(leaves: &[Fr]) -> Self {
+        let mut map = HashMap::with_capacity(leaves.len());
+        for (index, leaf) in leaves.iter().enumerate() {
+            map.entry(fr_to_be_bytes(leaf)).or_insert(index);
+        }
+        Self { leaves: map }
+    }
+
+    /// Leaf index of the first occurrence of `commitment`, if any
+    pub fn leaf_index(&self, commitment: &Fr) -> Option<usize> {
+        self.leaves.get(&fr_to_be_bytes(commitment)).copied()
+    }
+}
+
+/// The `mint` of native SOL commitments
+pub fn sol_mint() -> Fr {
+    Fr::from(1u64)
+}
+
+/// The `mint` of commitments to SPL tokens of `mint`: the first 31 bytes of
+/// its address, big-endian, which always fit in the field
+pub fn spl_mint(mint: &Pubkey) -> Fr {
+    Fr::from_be_bytes_mod_order(&mint.to_bytes()[..31])
+}
+
+fn sol_mint_str() -> String {
+    fr_to_str(&sol_mint())
+}
+
+/// Commitment = Poseidon(amount, pubkey, blinding, mint)
+fn compute_commitment(
+    amount: u64,
+    pubkey: &Fr,
+    blinding: &Fr,
+    mint: &Fr,
+) -> Result<Fr, CryptoError> {
+    poseidon(&[Fr::from(amount), *pubkey, *blinding, *mint])
+}
+
+/// What a transaction's `extDataHash` commits to besides its amount: who
+/// gets the withdrawal, and who submits it for what fee. The program takes
+/// only the hash, as a public input of the proof; the relayer recomputes it
+/// and refuses withdrawals that don't name it and the fee it is paid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtData {
+    /// Who the withdrawal pays
+    pub recipient: Pubkey,
+    /// Signer of the transaction: the relayer, or the wallet itself
+    pub relayer: Pubkey,
+    /// Lamports (or token base units) paid to the relayer's fee address
+    pub fee: u64,
+}
+
+impl ExtData {
+    /// A transaction the wallet submits itself, paying no relayer fee
+    pub fn direct(recipient: Pubkey, wallet: Pubkey) -> Self {
+        Self { recipient, relayer: wallet, fee: 0 }
+    }
+
+    /// `extDataHash = Poseidon(recipient, amount, relayer, fee)`, with each
+    /// address given as two inputs; see [`address_limbs`]
+    pub fn hash(&self, amount: u64) -> Result<Fr, CryptoError> {
+        let [recipient_hi, recipient_lo] = address_limbs(&self.recipient);
+        let [relayer_hi, relayer_lo] = address_limbs(&self.relayer);
+        poseidon(&[
+            recipient_hi,
+            recipient_lo,
+            Fr::from(amount),
+            relayer_hi,
+            relayer_lo,
+            Fr::from(self.fee),
+        ])
+    }
+}
+
+/// An address as the big-endian integers of its high and low 16 bytes.
+/// The program only passes `extDataHash` on to the verifier, so this
+/// encoding is for the CLI and relayer to agree on. Reading the 32 bytes as
+/// one integer reduced into the field would map up to six addresses to the
+/// same element, letting a relayer swap the recipient for another; two
+/// 128-bit halves always fit, so distinct addresses never hash alike.
+pub fn address_limbs(pubkey: &Pubkey) -> [Fr; 2] {
+    let bytes = pubkey.to_bytes();
+    let half = |range: std::ops::Range<usize>| {
+        Fr::from(u128::from_be_bytes(bytes[range].try_into().unwrap()))
+    };
+    [half(0..16), half(16..32)]
+}
+
+/// UTXO (Unspent Transaction Output) for privacy pool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Utxo {
+    /// Amount in lamports
+    pub amount: u64,
+    /// Owner's public key (Poseidon hash of private key)
+    pub pubkey: String,
+    /// Owner's private key
+    pub privkey: SecretString,
+    /// Random blinding factor
+    pub blinding: SecretString,
+    /// Token the amount is in, [`sol_mint`] or [`spl_mint`]
+    #[serde(default = "sol_mint_str")]
+    pub mint: String,
+    /// Commitment = Poseidon(amount, pubkey, blinding, mint)
+    pub commitment: String,
+}
+
+impl Utxo {
+    /// Generate a new UTXO of `mint` with random keys
+    pub fn new(amount: u64, mint: Fr) -> Result<Self, CryptoError> {
+        Self::from_keys(amount, mint, random_fr(), random_fr())
+    }
+
+    /// Generate the UTXO for note `index` of a deterministic seed
+    pub fn derive(
+        amount: u64,
+        mint: Fr,
+        seed: &NoteSeed,
+        index: u64,
+    ) -> Result<Self, CryptoError> {
+        Self::from_keys(amount, mint, seed.privkey(index)?, seed.blinding(index)?)
+    }
+
+    fn from_keys(amount: u64, mint: Fr, privkey: Fr, blinding: Fr) -> Result<Self, CryptoError> {
+        let pubkey = poseidon(&[privkey])?;
+        let commitment = compute_commitment(amount, &pubkey, &blinding, &mint)?;
+
+        Ok(Self {
+            amount,
+            pubkey: fr_to_str(&pubkey),
+            privkey: fr_to_str(&privkey).into(),
+            blinding: fr_to_str(&blinding).into(),
+            mint: fr_to_str(&mint),
+            commitment: fr_to_str(&commitment),
+        })
+    }
+
+    /// Create UTXO from existing values
+    pub fn from_values(
+        amount: u64,
+        privkey: &str,
+        pubkey: &str,
+        blinding: &str,
+        mint: Fr,
+    ) -> Result<Self, CryptoError> {
+        parse_field("privkey", privkey)?;
+        let pubkey_fr = parse_field("pubkey", pubkey)?;
+        let blinding_fr = parse_field("blinding", blinding)?;
+        let commitment = compute_commitment(amount, &pubkey_fr, &blinding_fr, &mint)?;
+
+        Ok(Self {
+            amount,
+            pubkey: pubkey.to_string(),
+            privkey: privkey.into(),
+            blinding: blinding.into(),
+            mint: fr_to_str(&mint),
+            commitment: fr_to_str(&commitment),
+        })
+    }
+
+    /// Whether `pubkey` is the hash of `privkey` and `commitment` matches
+    /// the amount, pubkey and blinding; a note edited by hand or corrupted
+    /// on disk fails this, as does one whose values aren't field elements
+    pub fn verify_commitment(&self) -> bool {
+        let parse = |value: &str| str_to_fr_strict(value).ok();
+        let (Some(privkey), Some(pubkey), Some(blinding), Some(mint), Some(commitment)) = (
+            parse(&self.privkey),
+            parse(&self.pubkey),
+            parse(&self.blinding),
+            parse(&self.mint),
+            parse(&self.commitment),
+        ) else {
+            return false;
+        };
+        poseidon(&[privkey]).is_ok_and(|hash| hash == pubkey)
+            && compute_commitment(self.amount, &pubkey, &blinding, &mint)
+                .is_ok_and(|hash| hash == commitment)
+    }
+
+    /// Compute nullifier for this UTXO at given leaf index
+    pub fn compute_nullifier(&self, leaf_index: usize) -> Result<Fr, CryptoError> {
+        let privkey = str_to_fr(&self.privkey)?;
+        let commitment = str_to_fr(&self.commitment)?;
+        let index_fr = Fr::from(leaf_index as u64);
+
+        // signature = Poseidon(privkey, commitment, index)
+        let signature = poseidon(&[privkey, commitment, index_fr])?;
+
+        // nullifier = Poseidon(commitment, index, signature)
+        poseidon(&[commitment, index_fr, signature])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_str_to_fr_strict() {
+        let modulus = BigUint::from_str(FIELD_SIZE).unwrap();
+        let max = (&modulus - 1u32).to_string();
+        assert_eq!(str_to_fr_strict(&max).unwrap(), -Fr::from(1u64));
+        assert_eq!(str_to_fr_strict("0").unwrap(), Fr::from(0u64));
+
+        let err = str_to_fr_strict(FIELD_SIZE).unwrap_err();
+        assert!(err.to_string().contains("not below the field modulus"));
+        assert!(str_to_fr_strict(&(&modulus + 1u32).to_string()).is_err());
+        let wide = (BigUint::from(1u32) << 256u32).to_string();
+        assert!(str_to_fr_strict(&wide).unwrap_err().to_string().contains("32 bytes"));
+        assert!(str_to_fr_strict("12abc").is_err());
+
+        // The lenient version reduces instead
+        assert_eq!(str_to_fr(FIELD_SIZE).unwrap(), Fr::from(0u64));
+
+        let err = Utxo::from_values(1, "1", "2", FIELD_SIZE, sol_mint()).unwrap_err();
+        assert!(err.to_string().contains("Invalid blinding"));
+        assert!(matches!(
+            err,
+            CryptoError::InvalidFieldElement {
+                name: "blinding",
+                reason: "not below the field modulus",
+            }
+        ));
+    }
+
+    #[test]
+    fn test_be_bytes_to_fr_strict() {
+        let modulus = BigUint::from_str(FIELD_SIZE).unwrap();
+        let be_32 = |value: &BigUint| {
+            let bytes = value.to_bytes_be();
+            let mut padded = vec![0u8; 32 - bytes.len()];
+            padded.extend(bytes);
+            padded
+        };
+
+        let max = be_32(&(&modulus - 1u32));
+        assert_eq!(be_bytes_to_fr_strict(&max).unwrap(), -Fr::from(1u64));
+        assert_eq!(be_bytes_to_fr_strict(&[0; 32]).unwrap(), Fr::from(0u64));
+
+        let err = be_bytes_to_fr_strict(&be_32(&modulus)).unwrap_err();
+        assert!(err.to_string().contains("not below the field modulus"));
+        assert!(be_bytes_to_fr_strict(&be_32(&(&modulus + 1u32))).is_err());
+        assert!(be_bytes_to_fr_strict(&[0xff; 32]).is_err());
+
+        // A valid value padded to 33 bytes, and one cut to 31
+        let mut wide = vec![0u8];
+        wide.extend(&max);
+        assert!(be_bytes_to_fr_strict(&wide).unwrap_err().to_string().contains("not 32 bytes"));
+        assert!(be_bytes_to_fr_strict(&max[1..]).is_err());
+    }
+
+    #[test]
+    fn test_fr_to_be_bytes() {
+        // Public inputs go on chain in this layout: 32 bytes, most
+        // significant first, small values zero-padded on the left
+        assert_eq!(fr_to_be_bytes(&Fr::from(0u64)), [0; 32]);
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        assert_eq!(fr_to_be_bytes(&Fr::from(1u64)), one);
+        let mut two_bytes = [0u8; 32];
+        two_bytes[30..].copy_from_slice(&[0x01, 0x02]);
+        assert_eq!(fr_to_be_bytes(&Fr::from(0x0102u64)), two_bytes);
+
+        // The largest element uses all 32 bytes
+        let max = -Fr::from(1u64);
+        assert_eq!(
+            hex::encode(fr_to_be_bytes(&max)),
+            "30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000000"
+        );
+        assert_eq!(be_bytes_to_fr_strict(&fr_to_be_bytes(&max)).unwrap(), max);
+    }
+
+    #[test]
+    fn test_verify_commitment() {
+        let utxo = Utxo::new(100_000_000, sol_mint()).unwrap();
+        assert!(utxo.verify_commitment());
+        let rebuilt = Utxo::from_values(
+            utxo.amount,
+            &utxo.privkey,
+            &utxo.pubkey,
+            &utxo.blinding,
+            sol_mint(),
+        )
+        .unwrap();
+        assert_eq!(rebuilt.commitment, utxo.commitment);
+
+        let mut tampered = utxo.clone();
+        tampered.blinding = fr_to_str(&(str_to_fr(&utxo.blinding).unwrap() + Fr::from(1u64))).into();
+        assert!(!tampered.verify_commitment());
+
+        let mut tampered = utxo.clone();
+        tampered.amount += 1;
+        assert!(!tampered.verify_commitment());
+
+        // A consistent commitment for keys that don't belong together
+        let other = Utxo::new(100_000_000, sol_mint()).unwrap();
+        let mut tampered = other.clone();
+        tampered.privkey = utxo.privkey.clone();
+        assert!(!tampered.verify_commitment());
+
+        let mut tampered = utxo.clone();
+        tampered.mint = fr_to_str(&spl_mint(&Pubkey::new_unique()));
+        assert!(!tampered.verify_commitment());
+
+        let mut tampered = utxo;
+        tampered.commitment = FIELD_SIZE.to_string();
+        assert!(!tampered.verify_commitment());
+    }
+
+    #[test]
+    fn test_utxo_debug_hides_secrets() {
+        let utxo = Utxo::new(100_000_000, sol_mint()).unwrap();
+        let debug = format!("{:?}", utxo);
+        assert!(!debug.contains(utxo.privkey.as_str()), "{}", debug);
+        assert!(!debug.contains(utxo.blinding.as_str()), "{}", debug);
+        assert!(debug.contains(&utxo.commitment) && debug.contains("[redacted]"), "{}", debug);
+
+        // Serialized as before, as plain strings
+        let json = serde_json::to_value(&utxo).unwrap();
+        assert_eq!(json["privkey"], utxo.privkey.as_str());
+        let parsed: Utxo = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.blinding, utxo.blinding);
+    }
+
+    #[test]
+    fn test_mint_is_part_of_the_commitment() {
+        let usdc: Pubkey = "EPjFWdd5AufqSSqeM2qA1EjKx2vqAfH7K4wbHxm2jfMh".parse().unwrap();
+        let native_mint: Pubkey = "So11111111111111111111111111111111111111112".parse().unwrap();
+        assert_ne!(spl_mint(&usdc), spl_mint(&native_mint));
+        assert_ne!(spl_mint(&native_mint), sol_mint());
+
+        let (privkey, blinding) = (Fr::from(123u64), Fr::from(456u64));
+        let pubkey = fr_to_str(&PoseidonHash::new().unwrap().hash1(&privkey).unwrap());
+        let utxo = |mint| Utxo::from_values(1_000_000, "123", &pubkey, "456", mint).unwrap();
+        let (sol, token) = (utxo(sol_mint()), utxo(spl_mint(&usdc)));
+        assert_ne!(sol.commitment, token.commitment);
+        assert!(sol.verify_commitment() && token.verify_commitment());
+        assert_eq!(token.mint, fr_to_str(&spl_mint(&usdc)));
+
+        let derived = Utxo::from_keys(1_000_000, spl_mint(&usdc), privkey, blinding).unwrap();
+        assert_eq!(derived.commitment, token.commitment);
+        // Notes written before mints existed are SOL
+        let mut json = serde_json::to_value(&sol).unwrap();
+        json.as_object_mut().unwrap().remove("mint");
+        let old: Utxo = serde_json::from_value(json).unwrap();
+        assert_eq!(old.mint, "1");
+        assert!(old.verify_commitment());
+    }
+
+    #[test]
+    fn test_poseidon_hash() {
+        let mut hasher = PoseidonHash::new().unwrap();
+        let a = Fr::from(1u64);
+        let b = Fr::from(2u64);
+        let hash = hasher.hash2(&a, &b).unwrap();
+        assert_ne!(hash, Fr::from(0u64));
+    }
+
+    #[test]
+    fn test_poseidon_matches_circomlib_vectors() {
+        // circomlibjs poseidon([1]), ([1, 2]), ... for every cached arity
+        let vectors = [
+            "18586133768512220936620570745912940619677854269274689475585506675881198879027",
+            "7853200120776062878684798364095072458815029376092732009249414926327459813530",
+            "6542985608222806190361240322586112750744169038454362455181422643027100751666",
+            "18821383157269793795438455681495246036402687001665670618754263018637548127333",
+        ];
+        let mut hasher = PoseidonHash::new().unwrap();
+        // Twice over, interleaving arities, so the second round hits cached hashers
+        for _ in 0..2 {
+            for (n, expected) in vectors.iter().enumerate() {
+                let inputs: Vec<Fr> = (1..=n as u64 + 1).map(Fr::from).collect();
+                assert_eq!(fr_to_str(&poseidon(&inputs).unwrap()), *expected, "{} inputs", n + 1);
+                assert_eq!(fr_to_str(&hasher.hash_many(&inputs).unwrap()), *expected);
+            }
+        }
+        let one = Fr::from(1u64);
+        let [two, three, four] = [2u64, 3, 4].map(Fr::from);
+        assert_eq!(fr_to_str(&hasher.hash1(&one).unwrap()), vectors[0]);
+        assert_eq!(fr_to_str(&hasher.hash2(&one, &two).unwrap()), vectors[1]);
+        assert_eq!(fr_to_str(&hasher.hash3(&one, &two, &three).unwrap()), vectors[2]);
+        assert_eq!(fr_to_str(&hasher.hash4(&one, &two, &three, &four).unwrap()), vectors[3]);
+    }
+
+    #[test]
+    fn test_merkle_tree() {
+        let mut tree = MerkleTree::new(4).unwrap();
+        assert_eq!(tree.leaf_count(), 0);
+
+        let leaf1 = Fr::from(1u64);
+        let leaf2 = Fr::from(2u64);
+
+        tree.insert(leaf1).unwrap();
+        tree.insert(leaf2).unwrap();
+
+        assert_eq!(tree.leaf_count(), 2);
+
+        let path = tree.get_path(0).unwrap();
+        assert_eq!(path.elements.len(), 4);
+        assert_eq!(path.indices.len(), 4);
+
+        for (index, leaf) in [leaf1, leaf2].iter().enumerate() {
+            assert!(verify_path(&tree.root(), leaf, &tree.get_path(index).unwrap()));
+        }
+        assert!(!verify_path(&tree.root(), &leaf2, &path));
+        let short = MerklePath { elements: path.elements[1..].to_vec(), ..path.clone() };
+        assert!(!verify_path(&tree.root(), &leaf1, &short));
+        assert_eq!(tree.get_path(2), Err(MerkleError::LeafOutOfRange { index: 2, leaves: 2 }));
+    }
+
+    #[test]
+    fn test_rebuild_matches_pairwise_hashing() {
+        use rand::{Rng, SeedableRng};
+
+        // The root hashed pair by pair, as the circuit does
+        fn root(leaves: &[Fr], height: usize) -> Fr {
+            let mut hasher = PoseidonHash::new().unwrap();
+            let mut zero = Fr::from(0u64);
+            let mut layer = leaves.to_vec();
+            for _ in 0..height {
+                if layer.is_empty() {
+                    layer.push(zero);
+                }
+                if layer.len() % 2 == 1 {
+                    layer.push(zero);
+                }
+                layer = layer.chunks(2).map(|pair| hasher.hash2(&pair[0], &pair[1]).unwrap()).collect();
+                zero = hasher.hash2(&zero, &zero).unwrap();
+            }
+            layer[0]
+        }
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+        for count in [0, 1, 2, 3, 64, 1000, 1001] {
+            let leaves: Vec<Fr> = (0..count).map(|_| random_fr_from(&mut rng)).collect();
+            let height = rng.gen_range(10..=12);
+            let mut tree = MerkleTree::new(height).unwrap();
+            tree.insert_many(&leaves).unwrap();
+            assert_eq!(tree.root(), root(&leaves, height), "{} leaves", count);
+        }
+    }
+
+    #[test]
+    fn test_root_after_replays_earlier_roots() {
+        let leaves: Vec<Fr> = (1..=9u64).map(Fr::from).collect();
+        let mut tree = MerkleTree::new(5).unwrap();
+        tree.insert_many(&leaves).unwrap();
+
+        let mut earlier = MerkleTree::new(5).unwrap();
+        assert_eq!(tree.root_after(0).unwrap(), earlier.root());
+        for (count, leaf) in leaves.iter().enumerate() {
+            earlier.insert(*leaf).unwrap();
+            assert_eq!(tree.root_after(count + 1).unwrap(), earlier.root(), "{} leaves", count + 1);
+        }
+        assert!(matches!(
+            tree.root_after(10),
+            Err(CryptoError::Merkle(MerkleError::LeafOutOfRange { index: 10, leaves: 9 }))
+        ));
+    }
+
+    #[test]
+    fn test_recent_roots_rotate_as_leaves_arrive() {
+        let mut tree = MerkleTree::new(5).unwrap();
+        assert_eq!(tree.recent_roots(3).unwrap(), vec![tree.root()]);
+
+        let mut roots = vec![tree.root()];
+        for leaf in 1..=6u64 {
+            tree.insert(Fr::from(leaf)).unwrap();
+            roots.insert(0, tree.root());
+            // The newest root comes first and the oldest drops off the end
+            assert_eq!(tree.recent_roots(3).unwrap(), roots[..roots.len().min(3)]);
+        }
+        assert_eq!(tree.recent_roots(10).unwrap(), roots);
+        assert!(tree.recent_roots(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cached_zero_hashes_match_computed() {
+        let mut hasher = PoseidonHash::new().unwrap();
+        let computed = MerkleTree::compute_zero_hashes(MERKLE_TREE_HEIGHT, &mut hasher).unwrap();
+        assert_eq!(computed.len(), MERKLE_TREE_HEIGHT + 1);
+        for _ in 0..2 {
+            assert_eq!(MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap().zeros, computed);
+        }
+        assert_eq!(STANDARD_ZEROS.get(), Some(&computed));
+
+        // Other heights aren't cached
+        assert_eq!(MerkleTree::new(4).unwrap().zeros, computed[..5]);
+    }
+
+    #[test]
+    fn test_index_of_finds_every_copy() {
+        let mut tree = MerkleTree::new(4).unwrap();
+        tree.insert_many(&[Fr::from(7u64), Fr::from(8u64)]).unwrap();
+        tree.insert(Fr::from(7u64)).unwrap();
+
+        assert_eq!(tree.index_of(&Fr::from(8u64)), Some(1));
+        assert_eq!(tree.index_of(&Fr::from(7u64)), Some(0));
+        assert_eq!(tree.indices_of(&Fr::from(7u64)), [0, 2]);
+        assert_eq!(tree.index_of(&Fr::from(9u64)), None);
+        assert!(tree.indices_of(&Fr::from(9u64)).is_empty());
+        for (index, leaf) in tree.leaves().iter().enumerate() {
+            assert!(tree.indices_of(leaf).contains(&index));
+        }
+    }
+
+    #[test]
+    fn test_random_trees_have_verifiable_paths() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+        for _ in 0..20 {
+            let height = rng.gen_range(1..=6);
+            let count = rng.gen_range(0..=1usize << height);
+            let leaves: Vec<Fr> = (0..count).map(|_| random_fr_from(&mut rng)).collect();
+            let mut tree = MerkleTree::new(height).unwrap();
+            tree.insert_many(&leaves).unwrap();
+            let root = tree.try_root().unwrap();
+            assert_eq!(root, tree.root());
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                let path = tree.get_path(index).unwrap();
+                assert_eq!(path.elements.len(), height);
+                assert!(verify_path(&root, leaf, &path));
+            }
+            for index in [count, count + 1, usize::MAX] {
+                assert_eq!(
+                    tree.get_path(index),
+                    Err(MerkleError::LeafOutOfRange { index, leaves: count })
+                );
+            }
+        }
+
+        // Nothing inserted: the empty tree's root, and no paths
+        let empty = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+        assert_eq!(empty.try_root().unwrap(), empty.root());
+        assert!(empty.get_path(0).is_err());
+    }
+
+    #[test]
+    fn test_commitment_set_detects_replayed_deposit() {
+        let mut hasher = PoseidonHash::new().unwrap();
+        let privkey = Fr::from(123u64);
+        let pubkey = fr_to_str(&hasher.hash1(&privkey).unwrap());
+        let replayed = Utxo::from_values(1_000_000_000, "123", &pubkey, "456", sol_mint()).unwrap();
+
+        let leaves = vec![
+            Fr::from(11u64),
+            Fr::from(22u64),
+            str_to_fr(&replayed.commitment).unwrap(),
+            Fr::from(33u64),
+        ];
+        let set = CommitmentSet::from_leaves(&leaves);
+
+        let commitment = str_to_fr(&replayed.commitment).unwrap();
+        assert_eq!(set.leaf_index(&commitment), Some(2));
+
+        let fresh = Utxo::new(1_000_000_000, sol_mint()).unwrap();
+        assert_eq!(set.leaf_index(&str_to_fr(&fresh.commitment).unwrap()), None);
+    }
+
+    /// Fixed vectors: these must never change, or deterministic notes
+    /// can no longer be recovered from the wallet
+    #[test]
+    fn test_note_seed_vectors() {
+        let mut secret = [0u8; 32];
+        for (i, b) in secret.iter_mut().enumerate() {
+            *b = i as u8 + 1;
+        }
+        let seed = NoteSeed::from_secret_key(&secret).unwrap();
+
+        let vectors = [
+            (
+                0,
+                "3254614654904862830386178026350465342551321591429624818455201074278861971491",
+                "4910418878771116957843728010207206365143163996926101039558401435745566848381",
+                "5951146635983709486100564762110472064279593357089876151767598961922891483138",
+            ),
+            (
+                1,
+                "5079993572148160348096247221464297102834846660954779191462472930363885006990",
+                "18856188545769167260283120322288116107163992644596782299661992968773816019808",
+                "123231911352249618178211964278214259577455753578276314315956735629821565287",
+            ),
+        ];
+        for (index, privkey, blinding, commitment) in vectors {
+            let utxo = Utxo::derive(100_000_000, sol_mint(), &seed, index).unwrap();
+            assert_eq!(utxo.privkey.as_str(), privkey);
+            assert_eq!(utxo.blinding.as_str(), blinding);
+            assert_eq!(utxo.commitment, commitment);
+        }
+    }
+
+    /// Pinned: the CLI proves and the relayer checks with this one hash,
+    /// so a change to what a proof commits to fails here
+    #[test]
+    fn test_ext_data_hash_vector() {
+        let fixture: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/ext_data.json")).unwrap();
+        let pubkey = |key: &str| Pubkey::from_str(fixture[key].as_str().unwrap()).unwrap();
+        let ext_data = ExtData {
+            recipient: pubkey("recipient"),
+            relayer: pubkey("relayer"),
+            fee: fixture["fee"].as_u64().unwrap(),
+        };
+        let hash = ext_data.hash(fixture["amount"].as_u64().unwrap()).unwrap();
+        assert_eq!(hex::encode(fr_to_be_bytes(&hash)), fixture["hash"].as_str().unwrap());
+
+        // Every field is bound
+        let amount = fixture["amount"].as_u64().unwrap();
+        let other = Pubkey::new_from_array([7; 32]);
+        assert_ne!(ExtData { recipient: other, ..ext_data }.hash(amount).unwrap(), hash);
+        assert_ne!(ExtData { relayer: other, ..ext_data }.hash(amount).unwrap(), hash);
+        assert_ne!(ExtData { fee: ext_data.fee + 1, ..ext_data }.hash(amount).unwrap(), hash);
+        assert_ne!(ext_data.hash(amount + 1).unwrap(), hash);
+
+        // Down to the last byte of each address
+        let mut bytes = ext_data.recipient.to_bytes();
+        bytes[31] ^= 1;
+        let recipient = Pubkey::new_from_array(bytes);
+        assert_ne!(ExtData { recipient, ..ext_data }.hash(amount).unwrap(), hash);
+        let limbs = address_limbs(&Pubkey::new_from_array([0xff; 32]));
+        assert_eq!(limbs, [Fr::from(u128::MAX); 2]);
+    }
+
+    /// Pinned: the WASM bindings check the same vector, so a browser
+    /// derives the commitments and nullifiers the CLI does
+    #[test]
+    fn test_note_vectors() {
+        let fixture: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/note_vectors.json")).unwrap();
+        let field = |value: &serde_json::Value| str_to_fr_strict(value.as_str().unwrap()).unwrap();
+        let text = |key: &str| fixture[key].as_str().unwrap();
+
+        let amount = text("amount").parse().unwrap();
+        let utxo = Utxo::from_values(amount, text("privkey"), text("pubkey"), text("blinding"), sol_mint())
+            .unwrap();
+        assert_eq!(utxo.mint, text("mint"));
+        assert_eq!(utxo.commitment, text("commitment"));
+        assert!(utxo.verify_commitment());
+        let leaf_index = fixture["leaf_index"].as_u64().unwrap() as usize;
+        assert_eq!(fr_to_str(&utxo.compute_nullifier(leaf_index).unwrap()), text("nullifier"));
+
+        let leaves: Vec<Fr> = fixture["leaves"].as_array().unwrap().iter().map(field).collect();
+        let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+        tree.insert_many(&leaves).unwrap();
+        assert_eq!(fr_to_str(&tree.root()), text("root"));
+        let path = tree.get_path(leaf_index).unwrap();
+        let pinned: Vec<Fr> = fixture["path_elements"].as_array().unwrap().iter().map(field).collect();
+        assert_eq!(path.elements, pinned);
+        assert_eq!(serde_json::json!(path.indices), fixture["path_indices"]);
+        assert!(verify_path(&tree.root(), &leaves[leaf_index], &path));
+    }
+
+    #[test]
+    fn test_random_fr_covers_the_field_uniformly() {
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let samples = 10_000;
+        let (mut top_bit, mut above_248) = (0, 0);
+        for _ in 0..samples {
+            let bigint = random_fr_from(&mut rng).into_bigint();
+            top_bit += bigint.get_bit(253) as usize;
+            above_248 += (bigint.num_bits() > 248) as usize;
+        }
+        // For a uniform element, P(bit 253 set) = (p - 2^253) / p ≈ 0.339
+        // and P(x >= 2^248) = 1 - 2^248 / p ≈ 0.979
+        let top_bit = top_bit as f64 / samples as f64;
+        let above_248 = above_248 as f64 / samples as f64;
+        assert!((0.32..0.36).contains(&top_bit), "P(bit 253) = {}", top_bit);
+        assert!((0.97..0.99).contains(&above_248), "P(x >= 2^248) = {}", above_248);
+    }
+
+    #[test]
+    fn test_utxo() {
+        let utxo = Utxo::new(1_000_000_000, sol_mint()).unwrap();
+        assert_eq!(utxo.amount, 1_000_000_000);
+        assert!(!utxo.commitment.is_empty());
+
+        let nullifier = utxo.compute_nullifier(0).unwrap();
+        assert_ne!(nullifier, Fr::from(0u64));
+    }
+}
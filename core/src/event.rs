@@ -0,0 +1,80 @@
+//! Events the pool program logs
+//!
+//! `transact` and `transact_spl` emit a `CommitmentData` event for each
+//! output commitment they insert, the Anchor way: `sol_log_data` of the
+//! event's discriminator followed by its Borsh encoding, which RPCs return
+//! as a `Program data: <base64>` log line.
+//!
+//! ```text
+//! offset  size  field
+//!      0     8  discriminator
+//!      8     8  index (u64 LE), the commitment's leaf
+//!     16    32  commitment
+//! ```
+//!
+//! Unlike the instruction data, the event names the leaf, and it is
+//! logged however the program was invoked, CPI included.
+
+/// Anchor event discriminator, `sha256("event:CommitmentData")[..8]`
+pub const COMMITMENT_DATA_DISCRIMINATOR: [u8; 8] = [13, 110, 215, 127, 244, 62, 234, 34];
+
+/// Bytes of a logged `CommitmentData` event, discriminator included
+pub const COMMITMENT_DATA_LEN: usize = 48;
+
+/// A commitment inserted into a tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommitmentData {
+    /// Leaf of the commitment
+    pub index: u64,
+    /// Big-endian field element
+    pub commitment: [u8; 32],
+}
+
+impl CommitmentData {
+    /// The event logged as `data`, `None` if `data` is another event
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() != COMMITMENT_DATA_LEN || data[..8] != COMMITMENT_DATA_DISCRIMINATOR {
+            return None;
+        }
+        Some(Self {
+            index: u64::from_le_bytes(data[8..16].try_into().unwrap()),
+            commitment: data[16..48].try_into().unwrap(),
+        })
+    }
+
+    /// The event as the program logs it
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(COMMITMENT_DATA_LEN);
+        data.extend_from_slice(&COMMITMENT_DATA_DISCRIMINATOR);
+        data.extend_from_slice(&self.index.to_le_bytes());
+        data.extend_from_slice(&self.commitment);
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discriminator_is_the_anchor_event_hash() {
+        let hash = solana_sdk::hash::hash(b"event:CommitmentData");
+        assert_eq!(hash.to_bytes()[..8], COMMITMENT_DATA_DISCRIMINATOR);
+    }
+
+    #[test]
+    fn parses_logged_events() {
+        let event = CommitmentData { index: 1_000_003, commitment: [7; 32] };
+        let data = event.to_bytes();
+        assert_eq!(data.len(), COMMITMENT_DATA_LEN);
+        assert_eq!(data[8..11], [0x43, 0x42, 0x0f]);
+        assert_eq!(CommitmentData::parse(&data), Some(event));
+
+        // Another event, or a truncated or padded one
+        let mut other = data.clone();
+        other[0] ^= 1;
+        assert_eq!(CommitmentData::parse(&other), None);
+        assert_eq!(CommitmentData::parse(&data[..47]), None);
+        assert_eq!(CommitmentData::parse(&[data, vec![0]].concat()), None);
+    }
+}
@@ -0,0 +1,140 @@
+//! Layout of the on-chain global config account
+//!
+//! ```text
+//! offset  size  field
+//!      0     8  discriminator
+//!      8    32  authority
+//!     40    32  fee_recipient
+//!     72     2  deposit_fee_rate (u16 LE)
+//!     74     2  withdrawal_fee_rate (u16 LE)
+//!     76     2  fee_error_margin (u16 LE)
+//!     78     1  bump
+//! ```
+//!
+//! Rates are in basis points of the amount deposited or withdrawn. The
+//! program has no minimum deposit and no pause switch; the maximum deposit
+//! is kept in the tree account (see [`crate::tree::max_deposit_amount`]).
+
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+
+/// Anchor account discriminator, `sha256("account:GlobalConfig")[..8]`
+pub const GLOBAL_CONFIG_DISCRIMINATOR: [u8; 8] = [149, 8, 156, 202, 160, 252, 176, 217];
+
+/// Bytes up to and including `bump`
+pub const GLOBAL_CONFIG_LEN: usize = 79;
+
+/// Fee rates are out of this many basis points
+pub const FEE_DENOMINATOR: u64 = 10_000;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum GlobalConfigError {
+    #[error("Global config account is too short ({len} bytes, need {GLOBAL_CONFIG_LEN})")]
+    TooShort { len: usize },
+    #[error("Account is not a global config (discriminator {0:?})")]
+    WrongDiscriminator([u8; 8]),
+}
+
+/// Fee settings of the pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlobalConfig {
+    pub authority: Pubkey,
+    pub fee_recipient: Pubkey,
+    /// Basis points of each deposit paid to `fee_recipient`
+    pub deposit_fee_rate: u16,
+    /// Basis points of each withdrawal paid to `fee_recipient`
+    pub withdrawal_fee_rate: u16,
+    pub fee_error_margin: u16,
+}
+
+impl GlobalConfig {
+    pub fn parse(data: &[u8]) -> Result<Self, GlobalConfigError> {
+        if data.len() < GLOBAL_CONFIG_LEN {
+            return Err(GlobalConfigError::TooShort { len: data.len() });
+        }
+        let discriminator: [u8; 8] = data[..8].try_into().unwrap();
+        if discriminator != GLOBAL_CONFIG_DISCRIMINATOR {
+            return Err(GlobalConfigError::WrongDiscriminator(discriminator));
+        }
+        let pubkey = |offset: usize| Pubkey::try_from(&data[offset..offset + 32]).unwrap();
+        let u16_at = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        Ok(Self {
+            authority: pubkey(8),
+            fee_recipient: pubkey(40),
+            deposit_fee_rate: u16_at(72),
+            withdrawal_fee_rate: u16_at(74),
+            fee_error_margin: u16_at(76),
+        })
+    }
+
+    /// Lamports of a `lamports` deposit that go to the fee recipient
+    /// rather than the pool
+    pub fn deposit_fee(&self, lamports: u64) -> u64 {
+        fee_for(lamports, self.deposit_fee_rate)
+    }
+
+    /// Lamports of a `lamports` withdrawal that go to the fee recipient
+    /// rather than the recipient
+    pub fn withdrawal_fee(&self, lamports: u64) -> u64 {
+        fee_for(lamports, self.withdrawal_fee_rate)
+    }
+}
+
+/// `rate` basis points of `lamports`, rounded down as the program does
+pub fn fee_for(lamports: u64, rate: u16) -> u64 {
+    (lamports as u128 * rate as u128 / FEE_DENOMINATOR as u128) as u64
+}
+
+/// `rate` basis points as a percentage, e.g. `0.25%`
+pub fn format_rate(rate: u16) -> String {
+    format!("{}%", rate as f64 / 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(deposit_fee_rate: u16, withdrawal_fee_rate: u16) -> Vec<u8> {
+        let mut data = GLOBAL_CONFIG_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&[1; 32]);
+        data.extend_from_slice(&[2; 32]);
+        data.extend_from_slice(&deposit_fee_rate.to_le_bytes());
+        data.extend_from_slice(&withdrawal_fee_rate.to_le_bytes());
+        data.extend_from_slice(&500u16.to_le_bytes());
+        data.extend_from_slice(&[254, 0]);
+        data
+    }
+
+    #[test]
+    fn parses_fee_settings() {
+        let config = GlobalConfig::parse(&account(0, 25)).unwrap();
+        assert_eq!(config.authority, Pubkey::from([1; 32]));
+        assert_eq!(config.fee_recipient, Pubkey::from([2; 32]));
+        assert_eq!(config.deposit_fee_rate, 0);
+        assert_eq!(config.withdrawal_fee_rate, 25);
+        assert_eq!(config.fee_error_margin, 500);
+
+        assert_eq!(config.deposit_fee(1_000_000_000), 0);
+        assert_eq!(config.withdrawal_fee(1_000_000_000), 2_500_000);
+        assert_eq!(config.withdrawal_fee(399), 0);
+        assert_eq!(format_rate(config.withdrawal_fee_rate), "0.25%");
+        assert_eq!(format_rate(100), "1%");
+    }
+
+    #[test]
+    fn rejects_short_and_foreign_accounts() {
+        let data = account(0, 25);
+        assert_eq!(
+            GlobalConfig::parse(&data[..GLOBAL_CONFIG_LEN - 1]),
+            Err(GlobalConfigError::TooShort { len: 78 })
+        );
+        assert!(GlobalConfig::parse(&[]).is_err());
+
+        let mut tree = data;
+        tree[..8].copy_from_slice(&[214, 38, 107, 35, 76, 133, 73, 49]);
+        assert!(matches!(
+            GlobalConfig::parse(&tree),
+            Err(GlobalConfigError::WrongDiscriminator(_))
+        ));
+    }
+}
@@ -0,0 +1,707 @@
+//! The transact instruction
+//!
+//! ```text
+//! offset  size  field
+//!      0     8  discriminator
+//!      8   256  proof (a: 64, b: 128, c: 64)
+//!    264    32  root
+//!    296    32  input_nullifier1
+//!    328    32  input_nullifier2
+//!    360    32  output_commitment1
+//!    392    32  output_commitment2
+//!    424     8  public_amount (i64 LE, negative for withdrawals)
+//!    432    32  ext_data_hash
+//! ```
+//!
+//! `transact_spl` takes the same data behind its own discriminator. A
+//! shielded transfer also carries a memo instruction, the recipient's note
+//! sealed to their shielded address; the program never sees it.
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    instruction::{AccountMeta, Instruction},
+    pubkey,
+    pubkey::Pubkey,
+};
+// Moved to `solana-sdk-ids` in 2.2, which the binaries don't depend on yet
+#[allow(deprecated)]
+use solana_sdk::system_program;
+
+use crate::config::PoolConfig;
+use crate::spl::{SplPool, TOKEN_PROGRAM_ID};
+use crate::verify::fr_from_be;
+
+pub const TRANSACT_DISCRIMINATOR: [u8; 8] = [217, 149, 130, 143, 221, 52, 252, 119];
+/// `sha256("global:transact_spl")[..8]`
+pub const TRANSACT_SPL_DISCRIMINATOR: [u8; 8] = [154, 66, 244, 204, 78, 225, 163, 151];
+pub const TRANSACT_DATA_LEN: usize = 464;
+pub const PROOF_OFFSET: usize = 8;
+pub const ROOT_OFFSET: usize = 264;
+pub const NULLIFIER1_OFFSET: usize = 296;
+pub const NULLIFIER2_OFFSET: usize = 328;
+pub const COMMITMENT1_OFFSET: usize = 360;
+pub const COMMITMENT2_OFFSET: usize = 392;
+pub const PUBLIC_AMOUNT_OFFSET: usize = 424;
+pub const EXT_DATA_HASH_OFFSET: usize = 432;
+
+/// Compute units requested for a transact transaction that wasn't
+/// simulated, the most a transaction can use
+pub const COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Compute units requested on top of what a simulation used, in percent
+pub const COMPUTE_UNIT_MARGIN_PERCENT: u64 = 10;
+
+/// The SPL memo program
+pub const MEMO_PROGRAM_ID: Pubkey = pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+/// Longest memo a transact transaction can carry: with it, the largest one
+/// (`transact_spl` behind both compute budget instructions) still fits in a
+/// packet
+pub const MAX_MEMO_LEN: usize = 144;
+
+/// Positions of the signer, recipient and fee recipient in the transact
+/// account list
+pub const SIGNER_ACCOUNT: usize = 5;
+pub const RECIPIENT_ACCOUNT: usize = 6;
+pub const FEE_RECIPIENT_ACCOUNT: usize = 7;
+
+/// Positions of the token account paid out to (or deposited from), the
+/// signer and the fee recipient's token account in the `transact_spl`
+/// account list
+pub const SPL_USER_TOKEN_ACCOUNT: usize = 5;
+pub const SPL_SIGNER_ACCOUNT: usize = 7;
+pub const SPL_FEE_RECIPIENT_ACCOUNT: usize = 9;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransactDataError {
+    #[error("transact instruction data must be {TRANSACT_DATA_LEN} bytes, got {0}")]
+    Length(usize),
+    #[error("instruction data is not a transact instruction")]
+    Discriminator,
+    /// A public input is not below the BN254 scalar field modulus
+    #[error("{0} is not a canonical field element")]
+    FieldElement(&'static str),
+}
+
+/// Proof data formatted for on-chain transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactProofData {
+    pub proof_a: Vec<u8>,
+    pub proof_b: Vec<u8>,
+    pub proof_c: Vec<u8>,
+    pub root: Vec<u8>,
+    pub nullifier1: Vec<u8>,
+    pub nullifier2: Vec<u8>,
+    pub commitment1: Vec<u8>,
+    pub commitment2: Vec<u8>,
+    pub public_amount: i64,
+    pub ext_data_hash: Vec<u8>,
+}
+
+impl TransactProofData {
+    /// Build instruction data for transact
+    pub fn to_instruction_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(TRANSACT_DATA_LEN);
+
+        // Discriminator
+        data.extend_from_slice(&TRANSACT_DISCRIMINATOR);
+
+        // Proof (256 bytes)
+        data.extend_from_slice(&self.proof_a);
+        data.extend_from_slice(&self.proof_b);
+        data.extend_from_slice(&self.proof_c);
+
+        // Public inputs
+        data.extend_from_slice(&self.root);
+        data.extend_from_slice(&self.nullifier1);
+        data.extend_from_slice(&self.nullifier2);
+        data.extend_from_slice(&self.commitment1);
+        data.extend_from_slice(&self.commitment2);
+
+        // Public amount as i64 little-endian
+        data.extend_from_slice(&self.public_amount.to_le_bytes());
+
+        // Ext data hash
+        data.extend_from_slice(&self.ext_data_hash);
+
+        data
+    }
+
+    /// Split transact instruction data back into its fields
+    ///
+    /// Checks the length, the discriminator and that every 32-byte public
+    /// input is a canonical field element, as the program's verifier
+    /// requires. The proof's points are left to [`Self::verify`].
+    pub fn from_instruction_data(data: &[u8]) -> Result<Self, TransactDataError> {
+        if data.len() != TRANSACT_DATA_LEN {
+            return Err(TransactDataError::Length(data.len()));
+        }
+        if data[0..8] != TRANSACT_DISCRIMINATOR {
+            return Err(TransactDataError::Discriminator);
+        }
+        let field = |name: &'static str, offset: usize| {
+            let bytes = &data[offset..offset + 32];
+            fr_from_be(bytes)
+                .map(|_| bytes.to_vec())
+                .ok_or(TransactDataError::FieldElement(name))
+        };
+        Ok(Self {
+            proof_a: data[PROOF_OFFSET..PROOF_OFFSET + 64].to_vec(),
+            proof_b: data[PROOF_OFFSET + 64..PROOF_OFFSET + 192].to_vec(),
+            proof_c: data[PROOF_OFFSET + 192..ROOT_OFFSET].to_vec(),
+            root: field("root", ROOT_OFFSET)?,
+            nullifier1: field("input_nullifier1", NULLIFIER1_OFFSET)?,
+            nullifier2: field("input_nullifier2", NULLIFIER2_OFFSET)?,
+            commitment1: field("output_commitment1", COMMITMENT1_OFFSET)?,
+            commitment2: field("output_commitment2", COMMITMENT2_OFFSET)?,
+            public_amount: i64::from_le_bytes(
+                data[PUBLIC_AMOUNT_OFFSET..PUBLIC_AMOUNT_OFFSET + 8]
+                    .try_into()
+                    .unwrap(),
+            ),
+            ext_data_hash: field("ext_data_hash", EXT_DATA_HASH_OFFSET)?,
+        })
+    }
+}
+
+/// `public_amount` of a transact instruction, if `data` is one
+pub fn public_amount(data: &[u8]) -> Option<i64> {
+    if data.len() < TRANSACT_DATA_LEN || data[0..8] != TRANSACT_DISCRIMINATOR {
+        return None;
+    }
+    let bytes: [u8; 8] = data[PUBLIC_AMOUNT_OFFSET..PUBLIC_AMOUNT_OFFSET + 8]
+        .try_into()
+        .ok()?;
+    Some(i64::from_le_bytes(bytes))
+}
+
+/// Build the transact instruction for a generated proof
+pub fn build_transact_instruction(
+    config: &PoolConfig,
+    proof_data: &TransactProofData,
+    signer: &Pubkey,
+    recipient: &Pubkey,
+    fee_recipient: &Pubkey,
+) -> Result<Instruction, TransactDataError> {
+    transact_instruction_from_data(
+        config,
+        proof_data.to_instruction_data(),
+        signer,
+        recipient,
+        fee_recipient,
+    )
+}
+
+/// Build the transact instruction from serialized instruction data
+///
+/// The nullifier PDAs are derived from the nullifiers inside `data`.
+pub fn transact_instruction_from_data(
+    config: &PoolConfig,
+    data: Vec<u8>,
+    signer: &Pubkey,
+    recipient: &Pubkey,
+    fee_recipient: &Pubkey,
+) -> Result<Instruction, TransactDataError> {
+    if data.len() != TRANSACT_DATA_LEN {
+        return Err(TransactDataError::Length(data.len()));
+    }
+    if data[0..8] != TRANSACT_DISCRIMINATOR {
+        return Err(TransactDataError::Discriminator);
+    }
+
+    let nullifier1_pda = config.nullifier_pda(&data[NULLIFIER1_OFFSET..NULLIFIER1_OFFSET + 32]);
+    let nullifier2_pda = config.nullifier_pda(&data[NULLIFIER2_OFFSET..NULLIFIER2_OFFSET + 32]);
+
+    // Account order: tree, null1, null2, config, vault, signer, recipient, fee_recipient, system
+    Ok(Instruction {
+        program_id: config.program_id,
+        accounts: vec![
+            AccountMeta::new(config.tree_account, false),
+            AccountMeta::new(nullifier1_pda, false),
+            AccountMeta::new(nullifier2_pda, false),
+            AccountMeta::new_readonly(config.global_config, false),
+            AccountMeta::new(config.pool_vault, false),
+            AccountMeta::new(*signer, true),
+            AccountMeta::new(*recipient, false),
+            AccountMeta::new(*fee_recipient, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data,
+    })
+}
+
+/// Build the `transact_spl` instruction for a generated proof
+///
+/// `user_token_account` is the depositor's token account for a deposit and
+/// the recipient's for a withdrawal; `signer` pays the nullifier rent either
+/// way.
+pub fn build_transact_spl_instruction(
+    config: &PoolConfig,
+    pool: &SplPool,
+    proof_data: &TransactProofData,
+    signer: &Pubkey,
+    user_token_account: &Pubkey,
+    fee_recipient_token_account: &Pubkey,
+) -> Result<Instruction, TransactDataError> {
+    transact_spl_instruction_from_data(
+        config,
+        pool,
+        proof_data.to_instruction_data(),
+        signer,
+        user_token_account,
+        fee_recipient_token_account,
+    )
+}
+
+/// Build the `transact_spl` instruction from serialized `transact`
+/// instruction data, swapping in its discriminator
+pub fn transact_spl_instruction_from_data(
+    config: &PoolConfig,
+    pool: &SplPool,
+    mut data: Vec<u8>,
+    signer: &Pubkey,
+    user_token_account: &Pubkey,
+    fee_recipient_token_account: &Pubkey,
+) -> Result<Instruction, TransactDataError> {
+    if data.len() != TRANSACT_DATA_LEN {
+        return Err(TransactDataError::Length(data.len()));
+    }
+    if data[0..8] != TRANSACT_DISCRIMINATOR {
+        return Err(TransactDataError::Discriminator);
+    }
+    data[0..8].copy_from_slice(&TRANSACT_SPL_DISCRIMINATOR);
+
+    let nullifier1_pda = config.nullifier_pda(&data[NULLIFIER1_OFFSET..NULLIFIER1_OFFSET + 32]);
+    let nullifier2_pda = config.nullifier_pda(&data[NULLIFIER2_OFFSET..NULLIFIER2_OFFSET + 32]);
+
+    // Account order: tree, token_pool, null1, null2, config, user_token, vault,
+    // signer, vault_authority, fee_recipient_token, system; then the token
+    // program, which the program invokes to move the tokens
+    Ok(Instruction {
+        program_id: config.program_id,
+        accounts: vec![
+            AccountMeta::new(pool.tree_account, false),
+            AccountMeta::new_readonly(pool.token_pool, false),
+            AccountMeta::new(nullifier1_pda, false),
+            AccountMeta::new(nullifier2_pda, false),
+            AccountMeta::new_readonly(config.global_config, false),
+            AccountMeta::new(*user_token_account, false),
+            AccountMeta::new(pool.vault, false),
+            AccountMeta::new(*signer, true),
+            AccountMeta::new_readonly(pool.vault_authority(&config.program_id), false),
+            AccountMeta::new(*fee_recipient_token_account, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        ],
+        data,
+    })
+}
+
+/// The compute budget instructions followed by `transact`
+///
+/// `unit_limit` is normally [`COMPUTE_UNIT_LIMIT`]. `unit_price` is the
+/// priority fee in micro-lamports per compute unit; at that limit, each
+/// micro-lamport adds 1.4 lamports to the fee. No price instruction is added
+/// for `0`.
+pub fn with_compute_budget(
+    transact: Instruction,
+    unit_limit: u32,
+    unit_price: u64,
+) -> Vec<Instruction> {
+    let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(unit_limit)];
+    if unit_price > 0 {
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_price(unit_price));
+    }
+    instructions.push(transact);
+    instructions
+}
+
+/// A memo instruction carrying `memo`
+///
+/// The memo program checks that it is UTF-8 and nothing else; without
+/// accounts, no signature is asked of anyone.
+pub fn memo_instruction(memo: &str) -> Instruction {
+    Instruction {
+        program_id: MEMO_PROGRAM_ID,
+        accounts: vec![],
+        data: memo.as_bytes().to_vec(),
+    }
+}
+
+/// The compute unit limit for a transaction whose simulation used
+/// `units_consumed`, plus [`COMPUTE_UNIT_MARGIN_PERCENT`]; requesting only
+/// that makes a priority fee cheaper. [`COMPUTE_UNIT_LIMIT`] if there was no
+/// simulation or it reported no units.
+pub fn sized_compute_unit_limit(units_consumed: Option<u64>) -> u32 {
+    match units_consumed {
+        Some(units) if units > 0 => units
+            .saturating_mul(100 + COMPUTE_UNIT_MARGIN_PERCENT)
+            .div_ceil(100)
+            .min(COMPUTE_UNIT_LIMIT as u64) as u32,
+        _ => COMPUTE_UNIT_LIMIT,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_ff::{BigInteger, PrimeField};
+
+    fn proof_data(public_amount: i64) -> TransactProofData {
+        TransactProofData {
+            proof_a: vec![1; 64],
+            proof_b: vec![2; 128],
+            proof_c: vec![3; 64],
+            root: vec![4; 32],
+            nullifier1: vec![5; 32],
+            nullifier2: vec![6; 32],
+            commitment1: vec![7; 32],
+            commitment2: vec![8; 32],
+            public_amount,
+            ext_data_hash: vec![9; 32],
+        }
+    }
+
+    /// (pubkey, is_signer, is_writable) of each account
+    fn metas(ix: &Instruction) -> Vec<(Pubkey, bool, bool)> {
+        ix.accounts
+            .iter()
+            .map(|m| (m.pubkey, m.is_signer, m.is_writable))
+            .collect()
+    }
+
+    #[test]
+    fn deposit_account_order() {
+        let config = PoolConfig::testnet();
+        let depositor = Pubkey::new_unique();
+        let ix = build_transact_instruction(
+            &config,
+            &proof_data(100_000_000),
+            &depositor,
+            &depositor,
+            &config.fee_recipient,
+        )
+        .unwrap();
+
+        assert_eq!(ix.program_id, config.program_id);
+        assert_eq!(
+            metas(&ix),
+            vec![
+                (config.tree_account, false, true),
+                (config.nullifier_pda(&[5; 32]), false, true),
+                (config.nullifier_pda(&[6; 32]), false, true),
+                (config.global_config, false, false),
+                (config.pool_vault, false, true),
+                (depositor, true, true),
+                (depositor, false, true),
+                (config.fee_recipient, false, true),
+                (system_program::id(), false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn withdraw_account_order() {
+        let config = PoolConfig::testnet();
+        let relayer = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let ix = build_transact_instruction(
+            &config,
+            &proof_data(-100_000_000),
+            &relayer,
+            &recipient,
+            &config.fee_recipient,
+        )
+        .unwrap();
+
+        let metas = metas(&ix);
+        assert_eq!(metas.len(), 9);
+        assert_eq!(metas[SIGNER_ACCOUNT], (relayer, true, true));
+        assert_eq!(metas[RECIPIENT_ACCOUNT], (recipient, false, true));
+        assert_eq!(metas[FEE_RECIPIENT_ACCOUNT], (config.fee_recipient, false, true));
+        assert_eq!(metas.iter().filter(|m| m.1).count(), 1);
+    }
+
+    #[test]
+    fn spl_account_order() {
+        let config = PoolConfig::testnet();
+        let pool = SplPool {
+            mint: Pubkey::new_unique(),
+            tree_account: Pubkey::new_unique(),
+            token_pool: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+        };
+        let relayer = Pubkey::new_unique();
+        let recipient_ata = Pubkey::new_unique();
+        let fee_ata = Pubkey::new_unique();
+        let ix = build_transact_spl_instruction(
+            &config,
+            &pool,
+            &proof_data(-1_000_000),
+            &relayer,
+            &recipient_ata,
+            &fee_ata,
+        )
+        .unwrap();
+
+        assert_eq!(ix.program_id, config.program_id);
+        assert_eq!(ix.data[0..8], TRANSACT_SPL_DISCRIMINATOR);
+        assert_eq!(ix.data[8..], proof_data(-1_000_000).to_instruction_data()[8..]);
+        // Not mistaken for a SOL transact
+        assert_eq!(public_amount(&ix.data), None);
+        assert_eq!(
+            metas(&ix),
+            vec![
+                (pool.tree_account, false, true),
+                (pool.token_pool, false, false),
+                (config.nullifier_pda(&[5; 32]), false, true),
+                (config.nullifier_pda(&[6; 32]), false, true),
+                (config.global_config, false, false),
+                (recipient_ata, false, true),
+                (pool.vault, false, true),
+                (relayer, true, true),
+                (pool.vault_authority(&config.program_id), false, false),
+                (fee_ata, false, true),
+                (system_program::id(), false, false),
+                (TOKEN_PROGRAM_ID, false, false),
+            ]
+        );
+        assert_eq!(ix.accounts[SPL_USER_TOKEN_ACCOUNT].pubkey, recipient_ata);
+        assert_eq!(ix.accounts[SPL_SIGNER_ACCOUNT].pubkey, relayer);
+        assert_eq!(ix.accounts[SPL_FEE_RECIPIENT_ACCOUNT].pubkey, fee_ata);
+    }
+
+    #[test]
+    fn instruction_data_layout() {
+        let data = proof_data(-42).to_instruction_data();
+        assert_eq!(data.len(), TRANSACT_DATA_LEN);
+        assert_eq!(data[0..8], TRANSACT_DISCRIMINATOR);
+        assert_eq!(data[NULLIFIER1_OFFSET], 5);
+        assert_eq!(data[NULLIFIER2_OFFSET], 6);
+        assert_eq!(public_amount(&data), Some(-42));
+        assert_eq!(
+            TransactProofData::from_instruction_data(&data).unwrap(),
+            proof_data(-42)
+        );
+        assert!(matches!(
+            TransactProofData::from_instruction_data(&data[1..]),
+            Err(TransactDataError::Length(463))
+        ));
+    }
+
+    /// The layout the program reads, field by field
+    const LAYOUT: &str = include_str!("../tests/fixtures/instruction/layout.json");
+
+    #[test]
+    fn instruction_data_snapshot() {
+        #[derive(Deserialize)]
+        struct Layout {
+            fields: Vec<(String, usize, usize)>,
+            data: String,
+        }
+        let layout: Layout = serde_json::from_str(LAYOUT).unwrap();
+
+        let fields = [
+            ("discriminator", 0, 8),
+            ("proof_a", PROOF_OFFSET, 64),
+            ("proof_b", PROOF_OFFSET + 64, 128),
+            ("proof_c", PROOF_OFFSET + 192, 64),
+            ("root", ROOT_OFFSET, 32),
+            ("input_nullifier1", NULLIFIER1_OFFSET, 32),
+            ("input_nullifier2", NULLIFIER2_OFFSET, 32),
+            ("output_commitment1", COMMITMENT1_OFFSET, 32),
+            ("output_commitment2", COMMITMENT2_OFFSET, 32),
+            ("public_amount", PUBLIC_AMOUNT_OFFSET, 8),
+            ("ext_data_hash", EXT_DATA_HASH_OFFSET, 32),
+        ]
+        .map(|(name, offset, size)| (name.to_string(), offset, size));
+        assert_eq!(layout.fields, fields);
+
+        // Contiguous, with nothing after the last field
+        let mut end = 0;
+        for (name, offset, size) in &layout.fields {
+            assert_eq!(*offset, end, "{}", name);
+            end += size;
+        }
+        assert_eq!(end, TRANSACT_DATA_LEN);
+
+        let data = proof_data(-42).to_instruction_data();
+        let hex: String = data.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, layout.data);
+    }
+
+    /// splitmix64, so the property test is reproducible without a `rand`
+    /// dependency
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        }
+
+        fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+
+        /// 32 random bytes below the field modulus, which starts 0x30
+        fn field(&mut self) -> Vec<u8> {
+            let mut bytes = self.bytes(32);
+            bytes[0] %= 0x30;
+            bytes
+        }
+    }
+
+    #[test]
+    fn instruction_data_round_trip() {
+        let mut rng = Rng(0x5eed);
+        for _ in 0..1000 {
+            let proof = TransactProofData {
+                proof_a: rng.bytes(64),
+                proof_b: rng.bytes(128),
+                proof_c: rng.bytes(64),
+                root: rng.field(),
+                nullifier1: rng.field(),
+                nullifier2: rng.field(),
+                commitment1: rng.field(),
+                commitment2: rng.field(),
+                public_amount: rng.next() as i64,
+                ext_data_hash: rng.field(),
+            };
+            let data = proof.to_instruction_data();
+            assert_eq!(data.len(), TRANSACT_DATA_LEN);
+            assert_eq!(
+                data[PUBLIC_AMOUNT_OFFSET..PUBLIC_AMOUNT_OFFSET + 8],
+                proof.public_amount.to_le_bytes()
+            );
+            assert_eq!(TransactProofData::from_instruction_data(&data).unwrap(), proof);
+        }
+    }
+
+    #[test]
+    fn rejects_public_inputs_outside_the_field() {
+        let modulus = ark_bn254::Fr::MODULUS.to_bytes_be();
+        let mut below = modulus.clone();
+        below[31] -= 1;
+
+        let data = proof_data(0).to_instruction_data();
+        let inputs = [
+            ("root", ROOT_OFFSET),
+            ("input_nullifier1", NULLIFIER1_OFFSET),
+            ("input_nullifier2", NULLIFIER2_OFFSET),
+            ("output_commitment1", COMMITMENT1_OFFSET),
+            ("output_commitment2", COMMITMENT2_OFFSET),
+            ("ext_data_hash", EXT_DATA_HASH_OFFSET),
+        ];
+        for (name, offset) in inputs {
+            let mut data = data.clone();
+            data[offset..offset + 32].copy_from_slice(&below);
+            assert!(TransactProofData::from_instruction_data(&data).is_ok(), "{}", name);
+
+            data[offset..offset + 32].copy_from_slice(&modulus);
+            assert!(matches!(
+                TransactProofData::from_instruction_data(&data),
+                Err(TransactDataError::FieldElement(field)) if field == name
+            ));
+        }
+
+        // The proof's coordinates are base field elements, checked by `verify`
+        let mut data = data;
+        data[PROOF_OFFSET..PROOF_OFFSET + 32].fill(0xff);
+        assert!(TransactProofData::from_instruction_data(&data).is_ok());
+
+        // Only transact data is parsed: the relayer rebuilds transact_spl
+        // data from it
+        data[0..8].copy_from_slice(&TRANSACT_SPL_DISCRIMINATOR);
+        assert!(matches!(
+            TransactProofData::from_instruction_data(&data),
+            Err(TransactDataError::Discriminator)
+        ));
+    }
+
+    #[test]
+    fn compute_budget_comes_first() {
+        let config = PoolConfig::testnet();
+        let signer = Pubkey::new_unique();
+        let transact =
+            build_transact_instruction(&config, &proof_data(0), &signer, &signer, &signer).unwrap();
+
+        let instructions = with_compute_budget(transact.clone(), COMPUTE_UNIT_LIMIT, 5_000);
+        assert_eq!(
+            instructions,
+            [
+                ComputeBudgetInstruction::set_compute_unit_limit(COMPUTE_UNIT_LIMIT),
+                ComputeBudgetInstruction::set_compute_unit_price(5_000),
+                transact.clone(),
+            ]
+        );
+        assert_eq!(with_compute_budget(transact, COMPUTE_UNIT_LIMIT, 0).len(), 2);
+    }
+
+    #[test]
+    fn longest_memo_fits_in_a_packet() {
+        use solana_sdk::{packet::PACKET_DATA_SIZE, transaction::Transaction};
+
+        let config = PoolConfig::testnet();
+        let pool = SplPool {
+            mint: Pubkey::new_unique(),
+            tree_account: Pubkey::new_unique(),
+            token_pool: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+        };
+        let signer = Pubkey::new_unique();
+        let transact = build_transact_spl_instruction(
+            &config,
+            &pool,
+            &proof_data(0),
+            &signer,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        )
+        .unwrap();
+        let mut instructions = with_compute_budget(transact, COMPUTE_UNIT_LIMIT, u64::MAX);
+        instructions.push(memo_instruction(&"m".repeat(MAX_MEMO_LEN)));
+
+        let tx = Transaction::new_with_payer(&instructions, Some(&signer));
+        // Signature count (a one-byte short vec), signature, message
+        let size = 1 + 64 + tx.message.serialize().len();
+        assert!(size <= PACKET_DATA_SIZE, "{} bytes", size);
+        assert_eq!(instructions[3].program_id, MEMO_PROGRAM_ID);
+        assert!(instructions[3].accounts.is_empty());
+    }
+
+    #[test]
+    fn compute_unit_limit_from_simulation() {
+        assert_eq!(sized_compute_unit_limit(Some(200_000)), 220_000);
+        // Rounded up, so the margin never falls short
+        assert_eq!(sized_compute_unit_limit(Some(190_001)), 209_002);
+        assert_eq!(sized_compute_unit_limit(Some(1)), 2);
+        // Never above what a transaction may request
+        assert_eq!(sized_compute_unit_limit(Some(1_300_000)), COMPUTE_UNIT_LIMIT);
+        assert_eq!(sized_compute_unit_limit(Some(u64::MAX)), COMPUTE_UNIT_LIMIT);
+
+        // Without a simulation, fall back to the maximum
+        assert_eq!(sized_compute_unit_limit(None), COMPUTE_UNIT_LIMIT);
+        assert_eq!(sized_compute_unit_limit(Some(0)), COMPUTE_UNIT_LIMIT);
+    }
+
+    #[test]
+    fn rejects_malformed_data() {
+        let config = PoolConfig::testnet();
+        let signer = Pubkey::new_unique();
+
+        let mut short = proof_data(0);
+        short.proof_a.pop();
+        let err = build_transact_instruction(&config, &short, &signer, &signer, &signer).unwrap_err();
+        assert!(matches!(err, TransactDataError::Length(463)));
+
+        let mut data = proof_data(0).to_instruction_data();
+        data[0] ^= 1;
+        let err = transact_instruction_from_data(&config, data, &signer, &signer, &signer).unwrap_err();
+        assert!(matches!(err, TransactDataError::Discriminator));
+    }
+}
@@ -0,0 +1,210 @@
+//! Loading wallet keypairs
+//!
+//! A keypair can be given as a Solana keypair file (a JSON array of 64
+//! bytes), a base58-encoded secret key as browser wallets export it, or a
+//! BIP39 seed phrase, read as `solana-keygen recover` reads one: no
+//! derivation path and an empty passphrase. [`load_keypair`] takes either a
+//! path to a file holding one of these or the key itself, so the CLI's
+//! `PRIVACY_ZIG_KEYPAIR` and the relayer's `RELAYER_KEYPAIR` can hold the key
+//! directly. The format is told from the input; errors name the format that
+//! was assumed and never include the key.
+
+use solana_sdk::bs58;
+use solana_sdk::signature::{keypair_from_seed_phrase_and_passphrase, Keypair};
+use std::fmt;
+use std::path::Path;
+use thiserror::Error;
+
+/// Word counts of a BIP39 seed phrase
+const SEED_PHRASE_WORDS: [usize; 5] = [12, 15, 18, 21, 24];
+
+/// Shortest base58 encoding of a 32-byte secret key
+const MIN_BASE58_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeypairFormat {
+    Json,
+    Base58,
+    SeedPhrase,
+}
+
+impl fmt::Display for KeypairFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Json => "JSON keypair",
+            Self::Base58 => "base58 secret key",
+            Self::SeedPhrase => "seed phrase",
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KeypairError {
+    #[error("Failed to read keypair file {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    /// The file was read, but what it holds isn't a keypair
+    #[error("{path} does not hold a keypair: {source}")]
+    File {
+        path: String,
+        source: Box<KeypairError>,
+    },
+    #[error("Invalid JSON keypair: {0}; expected an array of 64 bytes")]
+    Json(String),
+    #[error("Invalid base58 secret key: {0}")]
+    Base58(String),
+    #[error("The {format} is {len} bytes; expected a 64-byte keypair or a 32-byte secret key")]
+    Length { format: KeypairFormat, len: usize },
+    #[error("The public half of the {0} doesn't match its secret key")]
+    Mismatch(KeypairFormat),
+    #[error(
+        "The seed phrase has {0} words; expected 12, 15, 18, 21 or 24 lowercase words \
+         separated by spaces"
+    )]
+    SeedPhraseLength(usize),
+    #[error("Invalid seed phrase: {0}")]
+    SeedPhrase(String),
+}
+
+/// The format `input` is in, if it is a key rather than a path
+pub fn inline_format(input: &str) -> Option<KeypairFormat> {
+    let input = input.trim();
+    let words: Vec<&str> = input.split_whitespace().collect();
+    if input.starts_with('[') {
+        Some(KeypairFormat::Json)
+    } else if words.len() > 1
+        && words
+            .iter()
+            .all(|word| word.bytes().all(|b| b.is_ascii_lowercase()))
+    {
+        Some(KeypairFormat::SeedPhrase)
+    } else if input.len() >= MIN_BASE58_LEN && bs58::decode(input).into_vec().is_ok() {
+        Some(KeypairFormat::Base58)
+    } else {
+        None
+    }
+}
+
+/// Parse a keypair in any of the supported formats
+pub fn parse_keypair(input: &str) -> Result<Keypair, KeypairError> {
+    let input = input.trim();
+    match inline_format(input) {
+        Some(KeypairFormat::Json) => {
+            let bytes: Vec<u8> =
+                serde_json::from_str(input).map_err(|e| KeypairError::Json(e.to_string()))?;
+            from_bytes(KeypairFormat::Json, &bytes)
+        }
+        Some(KeypairFormat::SeedPhrase) => {
+            let words: Vec<&str> = input.split_whitespace().collect();
+            if !SEED_PHRASE_WORDS.contains(&words.len()) {
+                return Err(KeypairError::SeedPhraseLength(words.len()));
+            }
+            keypair_from_seed_phrase_and_passphrase(&words.join(" "), "")
+                .map_err(|e| KeypairError::SeedPhrase(e.to_string()))
+        }
+        // Anything else is taken for base58, so the error says why it isn't
+        Some(KeypairFormat::Base58) | None => {
+            let bytes = bs58::decode(input)
+                .into_vec()
+                .map_err(|e| KeypairError::Base58(e.to_string()))?;
+            from_bytes(KeypairFormat::Base58, &bytes)
+        }
+    }
+}
+
+fn from_bytes(format: KeypairFormat, bytes: &[u8]) -> Result<Keypair, KeypairError> {
+    match bytes.len() {
+        64 => Keypair::try_from(bytes).map_err(|_| KeypairError::Mismatch(format)),
+        32 => Ok(Keypair::new_from_array(bytes.try_into().unwrap())),
+        len => Err(KeypairError::Length { format, len }),
+    }
+}
+
+/// Read a keypair file in any of the supported formats
+pub fn read_keypair(path: &Path) -> Result<Keypair, KeypairError> {
+    let display = path.display().to_string();
+    let data = std::fs::read_to_string(path).map_err(|source| KeypairError::Read {
+        path: display.clone(),
+        source,
+    })?;
+    parse_keypair(&data).map_err(|e| KeypairError::File {
+        path: display,
+        source: Box::new(e),
+    })
+}
+
+/// The keypair `value` holds, or the one in the file it names
+pub fn load_keypair(value: &str) -> Result<Keypair, KeypairError> {
+    match inline_format(value) {
+        Some(_) => parse_keypair(value),
+        None => read_keypair(Path::new(value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Signer;
+
+    const PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon \
+                          abandon abandon abandon about";
+
+    #[test]
+    fn loads_each_format() {
+        let keypair = Keypair::new();
+        let json = serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap();
+        let base58 = keypair.to_base58_string();
+        let secret = bs58::encode(keypair.secret_bytes()).into_string();
+        for input in [&json, &base58, &secret] {
+            assert_eq!(load_keypair(input).unwrap().pubkey(), keypair.pubkey());
+        }
+        assert_eq!(inline_format(&json), Some(KeypairFormat::Json));
+        assert_eq!(inline_format(&base58), Some(KeypairFormat::Base58));
+
+        // As `solana-keygen recover` derives it, with or without stray spacing
+        let from_phrase = load_keypair(PHRASE).unwrap().pubkey();
+        assert_eq!(
+            from_phrase.to_string(),
+            "EHqmfkN89RJ7Y33CXM6uCzhVeuywHoJXZZLszBHHZy7o"
+        );
+        let spaced = format!("  {}\n", PHRASE.replace(' ', "   "));
+        assert_eq!(load_keypair(&spaced).unwrap().pubkey(), from_phrase);
+
+        // Paths are read, whatever the file holds
+        let path = std::env::temp_dir().join(format!("keypair-{}.txt", std::process::id()));
+        std::fs::write(&path, format!("{}\n", base58)).unwrap();
+        assert_eq!(inline_format(path.to_str().unwrap()), None);
+        let loaded = load_keypair(path.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.pubkey(), keypair.pubkey());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn explains_each_failure() {
+        let keypair = Keypair::new();
+        let error = |input: &str| parse_keypair(input).unwrap_err().to_string();
+
+        assert!(error("[1, 2, 300]").starts_with("Invalid JSON keypair"));
+        assert!(error("[1, 2, 3]").contains("JSON keypair is 3 bytes"));
+        let mut bytes = keypair.to_bytes();
+        bytes[63] ^= 1;
+        let json = serde_json::to_string(&bytes.to_vec()).unwrap();
+        assert!(error(&json).contains("doesn't match its secret key"));
+
+        assert!(error("abandon abandon abandon").contains("has 3 words"));
+        assert!(error("not-a-key!").starts_with("Invalid base58 secret key"));
+        let short = bs58::encode([1u8; 40]).into_string();
+        assert!(error(&short).contains("base58 secret key is 40 bytes"));
+
+        // Errors name the file, never the key
+        let missing = load_keypair("/nonexistent/id.json")
+            .unwrap_err()
+            .to_string();
+        assert!(missing.contains("/nonexistent/id.json"), "{}", missing);
+        let secret = keypair.to_base58_string();
+        let err = parse_keypair(&secret[..60]).unwrap_err().to_string();
+        assert!(!err.contains(&secret[..20]), "{}", err);
+    }
+}
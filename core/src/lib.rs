@@ -0,0 +1,60 @@
+//! Shared building blocks of the privacy-zig CLI, relayer and browser
+//! prover: deployment addresses, protocol constants, notes and the Poseidon
+//! hashing of the circuit, the transact instructions and the events they
+//! log, SPL token pools, proof verification, RPC failover, keypair loading
+//! and the pool vault's withdrawal check.
+//!
+//! Features:
+//! - `circuit`: the circuit's witness inputs ([`circuit`])
+//! - `prover`: Groth16 proving with the circuit artifacts ([`prover`],
+//!   [`artifacts`], [`cancel`])
+//! - `parallel`: hash Merkle tree levels on several threads
+
+#[cfg(feature = "prover")]
+pub mod artifacts;
+#[cfg(feature = "prover")]
+pub mod cancel;
+#[cfg(feature = "circuit")]
+pub mod circuit;
+pub mod config;
+pub mod crypto;
+pub mod event;
+pub mod global_config;
+pub mod instruction;
+pub mod keypair;
+pub mod priority_fee;
+#[cfg(feature = "prover")]
+pub mod prover;
+pub mod rpc_pool;
+pub mod spl;
+pub mod tree;
+pub mod vault;
+pub mod verify;
+
+pub use config::{
+    ConfigError, Network, PoolAddresses, PoolConfig, SplPoolAddresses, FEE_RECIPIENT,
+    GLOBAL_CONFIG, POOL_VAULT, PROGRAM_ID, TREE_ACCOUNT,
+};
+pub use crypto::{CryptoError, ExtData, MerkleTree, Utxo};
+pub use event::CommitmentData;
+pub use global_config::{GlobalConfig, GlobalConfigError};
+pub use instruction::{
+    build_transact_instruction, build_transact_spl_instruction, memo_instruction,
+    sized_compute_unit_limit, transact_instruction_from_data, transact_spl_instruction_from_data,
+    with_compute_budget, TransactDataError, TransactProofData, COMPUTE_UNIT_LIMIT,
+    COMPUTE_UNIT_MARGIN_PERCENT, MAX_MEMO_LEN, MEMO_PROGRAM_ID, TRANSACT_DISCRIMINATOR,
+    TRANSACT_SPL_DISCRIMINATOR,
+};
+pub use keypair::{load_keypair, KeypairError};
+pub use priority_fee::{priority_fee_lamports, PriorityFee, PriorityFeeError};
+pub use rpc_pool::{Endpoint, RpcPool, DEFAULT_REPROBE_INTERVAL};
+pub use spl::{SplError, SplPool};
+pub use vault::{check_withdrawal, pool_vault_address, withdrawal_lamports, VaultError};
+pub use verify::{verifying_key_from_snarkjs, ProofError, VerifyingKeyError};
+
+/// Merkle tree height
+pub const MERKLE_TREE_HEIGHT: usize = 26;
+
+/// BN254 scalar field size
+pub const FIELD_SIZE: &str =
+    "21888242871839275222246405745257275088548364400416034343698204186575808495617";
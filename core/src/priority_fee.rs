@@ -0,0 +1,200 @@
+//! Priority fees: the compute unit price of a transaction
+//!
+//! A price is either fixed, in micro-lamports per compute unit, or `auto`:
+//! a percentile of the prioritization fees recently paid by transactions
+//! that locked the same accounts, as reported by the RPC
+//! `getRecentPrioritizationFees` method for the last 150 slots.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Percentile of recent fees an `auto` priority fee pays
+pub const AUTO_FEE_PERCENTILE: u8 = 75;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriorityFee {
+    /// Micro-lamports per compute unit; `0` for none
+    Fixed(u64),
+    /// [`AUTO_FEE_PERCENTILE`] of recent fees for the accounts involved
+    Auto,
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid priority fee {0:?}: expected micro-lamports per compute unit or \"auto\"")]
+pub struct PriorityFeeError(String);
+
+impl PriorityFee {
+    /// The compute unit price to use; `recent_fees` is only called for
+    /// `auto`, and returns the fees of recent slots for the accounts the
+    /// transaction locks
+    pub fn resolve<E>(self, recent_fees: impl FnOnce() -> Result<Vec<u64>, E>) -> Result<u64, E> {
+        match self {
+            Self::Fixed(price) => Ok(price),
+            Self::Auto => Ok(percentile(recent_fees()?, AUTO_FEE_PERCENTILE)),
+        }
+    }
+}
+
+impl Default for PriorityFee {
+    fn default() -> Self {
+        Self::Fixed(0)
+    }
+}
+
+impl FromStr for PriorityFee {
+    type Err = PriorityFeeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        s.parse()
+            .map(Self::Fixed)
+            .map_err(|_| PriorityFeeError(s.to_string()))
+    }
+}
+
+impl fmt::Display for PriorityFee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fixed(price) => write!(f, "{}", price),
+            Self::Auto => f.write_str("auto"),
+        }
+    }
+}
+
+/// A number or `"auto"`
+impl<'de> Deserialize<'de> for PriorityFee {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Fixed(u64),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Fixed(price) => Ok(Self::Fixed(price)),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl Serialize for PriorityFee {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Fixed(price) => serializer.serialize_u64(*price),
+            Self::Auto => serializer.serialize_str("auto"),
+        }
+    }
+}
+
+/// Lamports a transaction requesting `unit_limit` compute units pays at
+/// `unit_price` micro-lamports each, on top of the signature fee
+pub fn priority_fee_lamports(unit_limit: u32, unit_price: u64) -> u64 {
+    (unit_limit as u128 * unit_price as u128).div_ceil(1_000_000) as u64
+}
+
+/// The nearest-rank `pct`th percentile of `fees`, `0` if there are none
+fn percentile(mut fees: Vec<u64>, pct: u8) -> u64 {
+    if fees.is_empty() {
+        return 0;
+    }
+    fees.sort_unstable();
+    let rank = (fees.len() * pct.min(100) as usize).div_ceil(100).max(1);
+    fees[rank - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::with_compute_budget;
+    use solana_sdk::{compute_budget::ComputeBudgetInstruction, instruction::Instruction};
+
+    fn transact() -> Instruction {
+        Instruction::new_with_bytes(crate::PROGRAM_ID.parse().unwrap(), &[1, 2, 3], vec![])
+    }
+
+    #[test]
+    fn parses_fixed_and_auto() {
+        assert_eq!("0".parse::<PriorityFee>().unwrap(), PriorityFee::Fixed(0));
+        assert_eq!(
+            " 25000 ".parse::<PriorityFee>().unwrap(),
+            PriorityFee::Fixed(25_000)
+        );
+        assert_eq!("AUTO".parse::<PriorityFee>().unwrap(), PriorityFee::Auto);
+        let err = "fast".parse::<PriorityFee>().unwrap_err().to_string();
+        assert!(err.contains("\"fast\""), "{}", err);
+        assert!("-1".parse::<PriorityFee>().is_err());
+        assert_eq!(
+            PriorityFee::Auto
+                .to_string()
+                .parse::<PriorityFee>()
+                .unwrap(),
+            PriorityFee::Auto
+        );
+
+        let parsed: Vec<PriorityFee> = serde_json::from_str(r#"[1000, "auto", "5"]"#).unwrap();
+        assert_eq!(
+            parsed,
+            [
+                PriorityFee::Fixed(1000),
+                PriorityFee::Auto,
+                PriorityFee::Fixed(5)
+            ]
+        );
+        assert!(serde_json::from_str::<PriorityFee>(r#""fast""#).is_err());
+    }
+
+    #[test]
+    fn fixed_price_is_added_without_asking_the_rpc() {
+        let price = PriorityFee::Fixed(25_000)
+            .resolve(|| -> Result<Vec<u64>, ()> { panic!("fees fetched for a fixed price") })
+            .unwrap();
+        let instructions = with_compute_budget(transact(), 1_400_000, price);
+        assert_eq!(
+            instructions[1],
+            ComputeBudgetInstruction::set_compute_unit_price(25_000)
+        );
+    }
+
+    #[test]
+    fn auto_price_is_a_percentile_of_recent_fees() {
+        // 150 slots, most of them empty
+        let mut fees = vec![0; 100];
+        fees.extend((1..=50).map(|i| i * 1000));
+        let price = PriorityFee::Auto.resolve(|| Ok::<_, ()>(fees)).unwrap();
+        assert_eq!(price, 13_000);
+        let instructions = with_compute_budget(transact(), 1_400_000, price);
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(
+            instructions[1],
+            ComputeBudgetInstruction::set_compute_unit_price(13_000)
+        );
+
+        // Nothing paid recently: no price instruction
+        let price = PriorityFee::Auto.resolve(|| Ok::<_, ()>(vec![])).unwrap();
+        assert_eq!(with_compute_budget(transact(), 1_400_000, price).len(), 2);
+        assert_eq!(
+            PriorityFee::Auto.resolve(|| Err("rate limited")),
+            Err("rate limited")
+        );
+    }
+
+    #[test]
+    fn percentiles() {
+        assert_eq!(percentile(vec![5], 75), 5);
+        assert_eq!(percentile(vec![4, 1, 3, 2], 75), 3);
+        assert_eq!(percentile(vec![4, 1, 3, 2], 100), 4);
+        assert_eq!(percentile(vec![4, 1, 3, 2], 0), 1);
+    }
+
+    #[test]
+    fn fee_in_lamports() {
+        assert_eq!(priority_fee_lamports(1_400_000, 0), 0);
+        assert_eq!(priority_fee_lamports(1_400_000, 1), 2);
+        assert_eq!(priority_fee_lamports(1_400_000, 10_000), 14_000);
+    }
+}
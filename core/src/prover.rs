@@ -0,0 +1,1134 @@
+//! ZK proof generation for privacy pool transactions
+//!
+//! Uses ark-circom to:
+//! 1. Load circuit WASM for witness calculation
+//! 2. Load zkey for proving key
+//! 3. Generate Groth16 proofs using arkworks
+//!
+//! The artifacts are held to the checksums of [`crate::artifacts`], and
+//! every proof is checked against the zkey's verifying key before it is
+//! returned. Proving blocks for tens of seconds; [`Progress`] stops it
+//! between phases and reports them.
+
+use ark_bn254::{Bn254, Fr};
+use ark_circom::{read_zkey, WitnessCalculator};
+use ark_groth16::{prepare_verifying_key, PreparedVerifyingKey, Proof, ProvingKey, VerifyingKey};
+use ark_relations::r1cs::ConstraintMatrices;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::rand::{thread_rng, CryptoRng, RngCore};
+use memmap2::Mmap;
+use num_bigint::BigInt;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use wasmer::{Store, Value};
+use zeroize::Zeroizing;
+
+use crate::cancel::{CancelToken, Cancelled};
+use crate::circuit::{self, fr_to_bigint, str_to_bigint, FormatError};
+use crate::crypto::{
+    poseidon, str_to_fr, CryptoError, ExtData, MerkleTree, Utxo, MERKLE_TREE_HEIGHT,
+};
+use crate::verify::NR_PUBLIC_INPUTS;
+use crate::{ProofError, TransactProofData};
+
+/// Provers loaded so far, by artifacts directory
+static LOADED: Mutex<Vec<(String, Arc<PrivacyProver>)>> = Mutex::new(Vec::new());
+
+/// Times a zkey has been parsed in this process
+static ZKEY_LOADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Where proofs that fail local verification are written, if anywhere
+static DEBUG_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Write the public inputs of proofs that fail local verification to new
+/// files in `dir`, for the rest of the process
+pub fn set_debug_dir(dir: PathBuf) {
+    let _ = DEBUG_DIR.set(dir);
+}
+
+/// Why the prover couldn't load or prove
+#[derive(Debug, thiserror::Error)]
+pub enum ProverError {
+    #[error(
+        "Missing {} in {dir}; run 'privacy artifacts download' or pass --artifacts <DIR> \
+         ('privacy artifacts path' lists where they are looked for)",
+        missing.join(", ")
+    )]
+    ArtifactNotFound {
+        dir: String,
+        missing: Vec<&'static str>,
+    },
+    #[error("Failed to read {path}")]
+    Io {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The file isn't the one pinned for its name; see [`crate::artifacts`]
+    #[error(
+        "{path} has sha256 {actual}, not the pinned {expected}, and may have been tampered \
+         with; run 'privacy artifacts download --force'"
+    )]
+    ChecksumMismatch {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("Invalid zkey {path}: {reason}")]
+    ZkeyParse { path: String, reason: String },
+    /// The wasm and zkey aren't of the same circuit, or not of the one this
+    /// build proves for
+    #[error(
+        "{0}; transaction2.wasm and transaction2.zkey must come from the same build of the \
+         transaction circuit. Check 'privacy artifacts verify'"
+    )]
+    CircuitMismatch(String),
+    #[error("Failed to load witness calculator {path}: {reason}")]
+    WasmLoad { path: String, reason: String },
+    #[error("Witness calculation failed: {0}")]
+    WitnessCalculation(String),
+    /// The circuit rejected the inputs, e.g. a note that isn't in the tree
+    /// its path was taken from
+    #[error("The inputs don't satisfy the circuit's constraints: {0}")]
+    ConstraintUnsatisfied(String),
+    #[error("Proof generation failed: {0}")]
+    ProofGeneration(String),
+    #[error(
+        "The generated proof failed local verification ({error}); nothing was sent. \
+         Check 'privacy artifacts verify'; {saved}"
+    )]
+    VerificationFailed { error: ProofError, saved: String },
+    /// The witness generator put another `extDataHash` in the proof than
+    /// the one computed for it
+    #[error(
+        "The proof's extDataHash {actual} isn't {expected}, computed from its recipient, relayer \
+         and fee; check 'privacy artifacts verify'"
+    )]
+    ExtDataMismatch { expected: String, actual: String },
+    #[error("Public amount {0} is out of range")]
+    AmountOutOfRange(BigInt),
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
+}
+
+impl From<FormatError> for ProverError {
+    fn from(e: FormatError) -> Self {
+        match e {
+            FormatError::ExtDataMismatch { expected, actual } => {
+                Self::ExtDataMismatch { expected, actual }
+            }
+            FormatError::AmountOutOfRange(amount) => Self::AmountOutOfRange(amount),
+        }
+    }
+}
+
+/// A note created by [`PrivacyProver::prove_transfer`]: `amount`
+/// committed to `pubkey`, which needn't be ours
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferOutput {
+    /// Lamports, or token base units
+    pub amount: u64,
+    /// The owner's note public key
+    pub pubkey: Fr,
+    /// Random, so equal amounts to one owner commit differently
+    pub blinding: Fr,
+}
+
+impl TransferOutput {
+    /// `amount` for the owner of `pubkey`, with a fresh blinding
+    pub fn to(amount: u64, pubkey: Fr) -> Self {
+        Self { amount, pubkey, blinding: crate::crypto::random_fr() }
+    }
+
+    /// The output creating `utxo`
+    pub fn of(utxo: &Utxo) -> Result<Self, CryptoError> {
+        Ok(Self {
+            amount: utxo.amount,
+            pubkey: str_to_fr(&utxo.pubkey)?,
+            blinding: str_to_fr(&utxo.blinding)?,
+        })
+    }
+
+    /// The commitment of the note this creates, of `mint`
+    pub fn commitment(&self, mint: &Fr) -> Result<Fr, CryptoError> {
+        poseidon(&[Fr::from(self.amount), self.pubkey, self.blinding, *mint])
+    }
+}
+
+/// A phase of generating a proof, reported to the `_with_progress`
+/// variants of the `prove_*` methods
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProverStage {
+    /// The circuit's WASM starts computing the witness from the inputs
+    WitnessStarted,
+    /// The witness is complete
+    WitnessDone,
+    /// Groth16 proving starts, most of a proof's time
+    ProofStarted,
+    /// The proof is generated, before it is checked against the zkey
+    ProofDone,
+}
+
+/// How a proof in progress is stopped and followed: it stops before either
+/// phase once `cancel` is set, and reports each phase to `on_stage`
+#[derive(Clone, Copy)]
+pub struct Progress<'a> {
+    /// Checked before each phase
+    pub cancel: &'a CancelToken,
+    /// Called as each phase starts and ends
+    pub on_stage: &'a dyn Fn(ProverStage),
+}
+
+impl<'a> Progress<'a> {
+    /// Stopped by `cancel`, reporting nothing
+    pub fn cancel(cancel: &'a CancelToken) -> Self {
+        Self { cancel, on_stage: &|_| {} }
+    }
+}
+
+/// Prover for privacy pool transactions
+pub struct PrivacyProver {
+    params: ProvingKey<Bn254>,
+    /// `params.vk`, prepared once to check every proof before it is used
+    vk: PreparedVerifyingKey<Bn254>,
+    matrices: ConstraintMatrices<Fr>,
+    /// Compiled witness generator, kept between proofs
+    witness: Mutex<(Store, WitnessCalculator)>,
+    /// The runtime whose handle the witness generator's WASI environment
+    /// holds, if the prover wasn't loaded inside one
+    runtime: Option<tokio::runtime::Runtime>,
+}
+
+impl Drop for PrivacyProver {
+    fn drop(&mut self) {
+        // Dropping a runtime blocks, which panics inside another one
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl PrivacyProver {
+    /// The prover for `dir`, loaded on first use and shared for the rest of
+    /// the process, so a transfer parses the zkey once for both of its proofs.
+    /// `progress` is only called if this call loads it; see [`load`](Self::load).
+    pub fn shared(dir: &str, progress: impl FnMut(u64, u64)) -> Result<Arc<Self>, ProverError> {
+        let mut loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, prover)) = loaded.iter().find(|(loaded_dir, _)| loaded_dir == dir) {
+            return Ok(prover.clone());
+        }
+        let prover = Arc::new(Self::from_dir(dir, progress)?);
+        loaded.push((dir.to_string(), prover.clone()));
+        Ok(prover)
+    }
+
+    /// Load prover from the artifacts in `dir`, naming what is missing and
+    /// how to fetch it rather than failing on the first open
+    pub fn from_dir(dir: &str, progress: impl FnMut(u64, u64)) -> Result<Self, ProverError> {
+        let missing = crate::artifacts::missing(std::path::Path::new(dir));
+        if !missing.is_empty() {
+            return Err(ProverError::ArtifactNotFound {
+                dir: dir.to_string(),
+                missing,
+            });
+        }
+        Self::load(
+            &format!("{}/transaction2.wasm", dir),
+            &format!("{}/transaction2.zkey", dir),
+            progress,
+        )
+    }
+
+    /// Load prover from circuit artifacts
+    pub fn new(wasm_path: &str, zkey_path: &str) -> Result<Self, ProverError> {
+        Self::load(wasm_path, zkey_path, |_, _| {})
+    }
+
+    /// Load prover from circuit artifacts, calling `progress` with the bytes
+    /// of the zkey parsed so far and its size, about once per percent
+    pub fn load(
+        wasm_path: &str,
+        zkey_path: &str,
+        progress: impl FnMut(u64, u64),
+    ) -> Result<Self, ProverError> {
+        let zkey = map_zkey(zkey_path)?;
+        let (params, matrices) = parse_zkey(zkey_path, &zkey, progress)?;
+
+        if crate::artifacts::pinned(Path::new(wasm_path)).is_some() {
+            let wasm = std::fs::read(wasm_path).map_err(|source| ProverError::Io {
+                path: wasm_path.to_string(),
+                source,
+            })?;
+            check_pin(wasm_path, &wasm)?;
+        }
+
+        // ark-circom's WASI environment takes the current Tokio runtime's
+        // handle; a caller outside one, such as a plain `main`, gets its own
+        let runtime = match tokio::runtime::Handle::try_current() {
+            Ok(_) => None,
+            Err(_) => Some(
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .map_err(|source| ProverError::Io { path: wasm_path.to_string(), source })?,
+            ),
+        };
+        let entered = runtime.as_ref().map(|runtime| runtime.enter());
+
+        let mut store = Store::default();
+        let wtns = WitnessCalculator::new(&mut store, wasm_path).map_err(|e| {
+            ProverError::WasmLoad {
+                path: wasm_path.to_string(),
+                reason: format!("{:?}", e),
+            }
+        })?;
+        drop(entered);
+        check_witness_len(witness_size(&mut store, &wtns, wasm_path)?, &matrices)?;
+
+        Ok(Self {
+            vk: prepare_verifying_key(&params.vk),
+            params,
+            matrices,
+            witness: Mutex::new((store, wtns)),
+            runtime,
+        })
+    }
+
+    /// Generate proof for a deposit transaction
+    /// root should be the current on-chain Merkle tree root
+    pub fn prove_deposit(
+        &self,
+        amount: u64,
+        utxo: &Utxo,
+        ext_data: &ExtData,
+        root: Fr,
+        cancel: &CancelToken,
+    ) -> Result<TransactProofData, ProverError> {
+        self.prove_deposit_with_progress(amount, utxo, ext_data, root, Progress::cancel(cancel))
+    }
+
+    /// [`prove_deposit`](Self::prove_deposit), reporting to `progress` as
+    /// the proof progresses
+    pub fn prove_deposit_with_progress(
+        &self,
+        amount: u64,
+        utxo: &Utxo,
+        ext_data: &ExtData,
+        root: Fr,
+        progress: Progress,
+    ) -> Result<TransactProofData, ProverError> {
+
+        // Every input and output of a transaction is in the same token
+        let mint = str_to_fr(&utxo.mint)?;
+
+        // Compute dummy nullifiers for fresh deposit
+        let dummy_utxo1 = Utxo::new(0, mint)?;
+        let dummy_utxo2 = Utxo::new(0, mint)?;
+        let nullifier1 = dummy_utxo1.compute_nullifier(0)?;
+        let nullifier2 = dummy_utxo2.compute_nullifier(0)?;
+
+        // Output UTXO
+        let out_utxo1 = Utxo::from_values(amount, &utxo.privkey, &utxo.pubkey, &utxo.blinding, mint)?;
+        let out_utxo2 = Utxo::new(0, mint)?;
+
+        // ExtData hash
+        let ext_data_hash = ext_data.hash(amount)?;
+
+        // Build witness inputs
+        let zero_path: Vec<BigInt> = (0..MERKLE_TREE_HEIGHT).map(|_| BigInt::from(0)).collect();
+        
+        let mut inputs: HashMap<String, Vec<BigInt>> = HashMap::new();
+        inputs.insert("root".to_string(), vec![fr_to_bigint(&root)]);
+        inputs.insert("publicAmount".to_string(), vec![BigInt::from(amount)]);
+        inputs.insert("extDataHash".to_string(), vec![fr_to_bigint(&ext_data_hash)]);
+        inputs.insert("mintAddress".to_string(), vec![fr_to_bigint(&mint)]);
+        inputs.insert("inputNullifier".to_string(), vec![
+            fr_to_bigint(&nullifier1),
+            fr_to_bigint(&nullifier2),
+        ]);
+        inputs.insert("inAmount".to_string(), vec![BigInt::from(0), BigInt::from(0)]);
+        inputs.insert("inPrivateKey".to_string(), vec![
+            str_to_bigint(&dummy_utxo1.privkey)?,
+            str_to_bigint(&dummy_utxo2.privkey)?,
+        ]);
+        inputs.insert("inBlinding".to_string(), vec![
+            str_to_bigint(&dummy_utxo1.blinding)?,
+            str_to_bigint(&dummy_utxo2.blinding)?,
+        ]);
+        inputs.insert("inPathIndices".to_string(), vec![BigInt::from(0), BigInt::from(0)]);
+        inputs.insert("inPathElements".to_string(), [zero_path.clone(), zero_path].concat());
+        inputs.insert("outputCommitment".to_string(), vec![
+            str_to_bigint(&out_utxo1.commitment)?,
+            str_to_bigint(&out_utxo2.commitment)?,
+        ]);
+        inputs.insert("outAmount".to_string(), vec![BigInt::from(amount), BigInt::from(0)]);
+        inputs.insert("outPubkey".to_string(), vec![
+            str_to_bigint(&utxo.pubkey)?,
+            str_to_bigint(&out_utxo2.pubkey)?,
+        ]);
+        inputs.insert("outBlinding".to_string(), vec![
+            str_to_bigint(&utxo.blinding)?,
+            str_to_bigint(&out_utxo2.blinding)?,
+        ]);
+
+        // Generate proof
+        let (proof, public_signals) = self.generate_proof(inputs, progress)?;
+        self.verified(self.format_proof(&proof, &public_signals, &ext_data_hash)?)
+    }
+
+    /// Generate proof for a withdrawal transaction
+    pub fn prove_withdraw(
+        &self,
+        utxo: &Utxo,
+        leaf_index: usize,
+        tree: &MerkleTree,
+        ext_data: &ExtData,
+        cancel: &CancelToken,
+    ) -> Result<TransactProofData, ProverError> {
+        self.prove_withdraw_with_progress(utxo, leaf_index, tree, ext_data, Progress::cancel(cancel))
+    }
+
+    /// [`prove_withdraw`](Self::prove_withdraw), reporting to `progress` as
+    /// the proof progresses
+    pub fn prove_withdraw_with_progress(
+        &self,
+        utxo: &Utxo,
+        leaf_index: usize,
+        tree: &MerkleTree,
+        ext_data: &ExtData,
+        progress: Progress,
+    ) -> Result<TransactProofData, ProverError> {
+        let (inputs, ext_data_hash) = circuit::withdraw_inputs(utxo, leaf_index, tree, ext_data)?;
+        let (proof, public_signals) = self.generate_proof(inputs, progress)?;
+        self.verified(self.format_proof(&proof, &public_signals, &ext_data_hash)?)
+    }
+
+    /// Generate proof for a transfer inside the pool: `utxo`, at
+    /// `leaf_index` of `tree`, is spent into `outputs`, which must add up
+    /// to its amount, and nothing enters or leaves the pool
+    pub fn prove_transfer(
+        &self,
+        utxo: &Utxo,
+        leaf_index: usize,
+        tree: &MerkleTree,
+        outputs: &[TransferOutput; 2],
+        ext_data: &ExtData,
+        cancel: &CancelToken,
+    ) -> Result<TransactProofData, ProverError> {
+        self.prove_transfer_with_progress(
+            utxo,
+            leaf_index,
+            tree,
+            outputs,
+            ext_data,
+            Progress::cancel(cancel),
+        )
+    }
+
+    /// [`prove_transfer`](Self::prove_transfer), reporting to `progress` as
+    /// the proof progresses
+    pub fn prove_transfer_with_progress(
+        &self,
+        utxo: &Utxo,
+        leaf_index: usize,
+        tree: &MerkleTree,
+        outputs: &[TransferOutput; 2],
+        ext_data: &ExtData,
+        progress: Progress,
+    ) -> Result<TransactProofData, ProverError> {
+        use crate::crypto::random_fr;
+
+        let total = outputs.iter().map(|output| output.amount as u128).sum::<u128>();
+        if total != utxo.amount as u128 {
+            return Err(ProverError::ConstraintUnsatisfied(format!(
+                "the outputs add up to {}, not the spent note's {}",
+                total, utxo.amount
+            )));
+        }
+
+        let root = tree.root();
+        let path = circuit::note_path(tree, utxo, leaf_index)?;
+        let privkey = str_to_fr(&utxo.privkey)?;
+        let pubkey = str_to_fr(&utxo.pubkey)?;
+        let mint = str_to_fr(&utxo.mint)?;
+
+        let nullifier1 = utxo.compute_nullifier(leaf_index)?;
+
+        // Dummy second input, as for a withdrawal
+        let dummy_blinding = random_fr();
+        let nullifier2 = circuit::dummy_nullifier(&privkey, &pubkey, &mint, &dummy_blinding)?;
+
+        // Nothing leaves the pool
+        let ext_data_hash = ext_data.hash(0)?;
+
+        let path_bigint: Vec<BigInt> = path.elements.iter().map(fr_to_bigint).collect();
+        let zero_path: Vec<BigInt> = (0..MERKLE_TREE_HEIGHT).map(|_| BigInt::from(0)).collect();
+        let output_commitments = [outputs[0].commitment(&mint)?, outputs[1].commitment(&mint)?];
+
+        let mut inputs: HashMap<String, Vec<BigInt>> = HashMap::new();
+        inputs.insert("root".to_string(), vec![fr_to_bigint(&root)]);
+        inputs.insert("publicAmount".to_string(), vec![BigInt::from(0)]);
+        inputs.insert("extDataHash".to_string(), vec![fr_to_bigint(&ext_data_hash)]);
+        inputs.insert("mintAddress".to_string(), vec![fr_to_bigint(&mint)]);
+        inputs.insert("inputNullifier".to_string(), vec![
+            fr_to_bigint(&nullifier1),
+            fr_to_bigint(&nullifier2),
+        ]);
+        inputs.insert("inAmount".to_string(), vec![BigInt::from(utxo.amount), BigInt::from(0)]);
+        inputs.insert("inPrivateKey".to_string(), vec![
+            str_to_bigint(&utxo.privkey)?,
+            str_to_bigint(&utxo.privkey)?,
+        ]);
+        inputs.insert("inBlinding".to_string(), vec![
+            str_to_bigint(&utxo.blinding)?,
+            fr_to_bigint(&dummy_blinding),
+        ]);
+        inputs.insert("inPathIndices".to_string(), vec![
+            BigInt::from(leaf_index as u64),
+            BigInt::from(0),
+        ]);
+        inputs.insert("inPathElements".to_string(), [path_bigint, zero_path].concat());
+        inputs.insert("outputCommitment".to_string(), output_commitments.iter().map(fr_to_bigint).collect());
+        inputs.insert("outAmount".to_string(), outputs.iter().map(|o| BigInt::from(o.amount)).collect());
+        inputs.insert("outPubkey".to_string(), outputs.iter().map(|o| fr_to_bigint(&o.pubkey)).collect());
+        inputs.insert("outBlinding".to_string(), outputs.iter().map(|o| fr_to_bigint(&o.blinding)).collect());
+
+        let (proof, public_signals) = self.generate_proof(inputs, progress)?;
+        self.verified(self.format_proof(&proof, &public_signals, &ext_data_hash)?)
+    }
+
+    /// Generate proof using witness calculator and arkworks, stopping
+    /// before either phase if `progress` is cancelled and reporting each as
+    /// it starts and ends
+    fn generate_proof(
+        &self,
+        inputs: HashMap<String, Vec<BigInt>>,
+        progress: Progress,
+    ) -> Result<(Proof<Bn254>, Vec<Fr>), ProverError> {
+        self.generate_proof_with_rng(inputs, progress, &mut thread_rng())
+    }
+
+    /// [`generate_proof`](Self::generate_proof) drawing the proof's blinding
+    /// factors `r` and `s` from `rng`. Only tests pass anything but
+    /// `thread_rng`: with a seeded `rng`, the same inputs always give the
+    /// same proof, which a proof that hides its witness must never do.
+    fn generate_proof_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        mut inputs: HashMap<String, Vec<BigInt>>,
+        progress: Progress,
+        rng: &mut R,
+    ) -> Result<(Proof<Bn254>, Vec<Fr>), ProverError> {
+        let Progress { cancel, on_stage } = progress;
+        // Calculate witness
+        let computed = cancel.check().map_err(ProverError::from).and_then(|()| {
+            on_stage(ProverStage::WitnessStarted);
+            let mut witness = self.witness.lock().unwrap_or_else(|e| e.into_inner());
+            let (store, wtns) = &mut *witness;
+            wtns.calculate_witness_element::<Fr, _>(store, inputs.clone(), false)
+                .map_err(|e| witness_error(format!("{:?}", e)))
+        });
+        circuit::wipe_inputs(&mut inputs);
+        let full_assignment = Zeroizing::new(computed?);
+        check_witness_len(full_assignment.len(), &self.matrices)?;
+        on_stage(ProverStage::WitnessDone);
+
+        // Generate proof
+        cancel.check()?;
+        on_stage(ProverStage::ProofStarted);
+        let proof = circuit::groth16_prove(&self.params, &self.matrices, &full_assignment, rng)
+            .map_err(|e| ProverError::ProofGeneration(format!("{:?}", e)))?;
+        on_stage(ProverStage::ProofDone);
+        Ok(proof)
+    }
+
+    /// `proof` if it verifies against the circuit's key. A proof that
+    /// doesn't, e.g. from a wasm and zkey of different circuits, would only
+    /// fail on chain after paying the fee, so it stops here and its public
+    /// inputs are written to the [`set_debug_dir`] directory for triage.
+    fn verified(&self, proof: TransactProofData) -> Result<TransactProofData, ProverError> {
+        self.verified_or_dumped(proof, DEBUG_DIR.get().map(PathBuf::as_path))
+    }
+
+    fn verified_or_dumped(
+        &self,
+        proof: TransactProofData,
+        debug_dir: Option<&Path>,
+    ) -> Result<TransactProofData, ProverError> {
+        let Err(error) = proof.verify(&self.vk) else {
+            return Ok(proof);
+        };
+        let saved = match debug_dir.map(|dir| dump_public_inputs(&proof, &error, dir)) {
+            Some(Ok(path)) => format!("public inputs saved to {}", path.display()),
+            Some(Err(dump_error)) => format!("saving its public inputs failed: {}", dump_error),
+            None => "its public inputs weren't saved".to_string(),
+        };
+        Err(ProverError::VerificationFailed { error, saved })
+    }
+
+    /// Format proof for on-chain submission; see
+    /// [`circuit::transact_proof_data`]
+    fn format_proof(
+        &self,
+        proof: &Proof<Bn254>,
+        public_signals: &[Fr],
+        ext_data_hash: &Fr,
+    ) -> Result<TransactProofData, ProverError> {
+        Ok(circuit::transact_proof_data(proof, public_signals, ext_data_hash)?)
+    }
+}
+
+/// Write the public inputs of a proof that failed verification to a new
+/// file in `dir`, returning its path
+fn dump_public_inputs(
+    proof: &TransactProofData,
+    error: &ProofError,
+    dir: &Path,
+) -> io::Result<PathBuf> {
+    let inputs: Vec<String> = match proof.public_inputs() {
+        Ok(inputs) => inputs.iter().map(|input| input.to_string()).collect(),
+        Err(_) => Vec::new(),
+    };
+    let dump = serde_json::json!({
+        "error": error.to_string(),
+        "public_inputs": inputs,
+        "root": hex::encode(&proof.root),
+        "public_amount": proof.public_amount,
+        "ext_data_hash": hex::encode(&proof.ext_data_hash),
+        "nullifiers": [hex::encode(&proof.nullifier1), hex::encode(&proof.nullifier2)],
+        "commitments": [hex::encode(&proof.commitment1), hex::encode(&proof.commitment2)],
+        "instruction_data": hex::encode(proof.to_instruction_data()),
+    });
+    std::fs::create_dir_all(dir)
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to create {}: {}", dir.display(), e)))?;
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let path = dir.join(format!("proof-{}.json", millis));
+    std::fs::write(&path, serde_json::to_string_pretty(&dump)?)
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to write {}: {}", path.display(), e)))?;
+    Ok(path)
+}
+
+/// Circom's runtime reports a failed `===` or `assert` as "Assert Failed"
+fn witness_error(message: String) -> ProverError {
+    if message.contains("Assert Failed") {
+        ProverError::ConstraintUnsatisfied(message)
+    } else {
+        ProverError::WitnessCalculation(message)
+    }
+}
+
+/// Check that the zkey is for a circuit with the transaction's public
+/// inputs, which the instruction data and [`TransactProofData`] are laid out
+/// for. Its first instance variable is the constant 1.
+/// The zkey at `zkey_path`, mapped rather than read into a buffer so the
+/// file isn't held in memory next to the key parsed from it, and checked
+/// to look like a zkey and match its pin
+fn map_zkey(zkey_path: &str) -> Result<Mmap, ProverError> {
+    let io_error = |source| ProverError::Io {
+        path: zkey_path.to_string(),
+        source,
+    };
+    let zkey_file = File::open(zkey_path).map_err(io_error)?;
+    // SAFETY: the mapping is only read while the file is open, and
+    // `artifacts download` replaces files by renaming rather than
+    // rewriting them in place.
+    let zkey = unsafe { Mmap::map(&zkey_file) }.map_err(io_error)?;
+    check_zkey(&zkey).map_err(|reason| ProverError::ZkeyParse {
+        path: zkey_path.to_string(),
+        reason,
+    })?;
+    check_pin(zkey_path, &zkey)?;
+    Ok(zkey)
+}
+
+/// The proving key and constraint matrices in `zkey`, the file at
+/// `zkey_path`, checked to be of a circuit with this build's public inputs
+fn parse_zkey(
+    zkey_path: &str,
+    zkey: &[u8],
+    progress: impl FnMut(u64, u64),
+) -> Result<(ProvingKey<Bn254>, ConstraintMatrices<Fr>), ProverError> {
+    let mut reader = ProgressReader::new(zkey, progress);
+    let (params, matrices) = read_zkey(&mut reader).map_err(|e| ProverError::ZkeyParse {
+        path: zkey_path.to_string(),
+        reason: format!("{:?}", e),
+    })?;
+    ZKEY_LOADS.fetch_add(1, Ordering::Relaxed);
+    check_public_inputs(zkey_path, &matrices)?;
+    Ok((params, matrices))
+}
+
+/// The transaction circuit's verifying key, from the zkey at `zkey_path`,
+/// for checking proofs without loading a prover. Parsing a zkey takes
+/// seconds, so the key is cached in `cache_dir` under the zkey's sha256 and
+/// read from there for as long as the zkey is unchanged.
+pub fn verifying_key(zkey_path: &str, cache_dir: &Path) -> Result<PreparedVerifyingKey<Bn254>, ProverError> {
+    let zkey = map_zkey(zkey_path)?;
+    let cached = cache_dir.join(format!("vk-{}.bin", crate::artifacts::sha256_bytes(&zkey)));
+    let from_cache = std::fs::read(&cached)
+        .ok()
+        .and_then(|bytes| VerifyingKey::<Bn254>::deserialize_compressed(bytes.as_slice()).ok())
+        .filter(|vk| vk.gamma_abc_g1.len() == NR_PUBLIC_INPUTS + 1);
+    if let Some(vk) = from_cache {
+        return Ok(prepare_verifying_key(&vk));
+    }
+
+    let (params, _) = parse_zkey(zkey_path, &zkey, |_, _| {})?;
+    // The cache only saves time; a key that can't be written is parsed again next time
+    let mut bytes = Vec::new();
+    if params.vk.serialize_compressed(&mut bytes).is_ok() {
+        let _ = std::fs::create_dir_all(cache_dir).and_then(|()| std::fs::write(&cached, bytes));
+    }
+    Ok(prepare_verifying_key(&params.vk))
+}
+
+fn check_public_inputs(zkey_path: &str, matrices: &ConstraintMatrices<Fr>) -> Result<(), ProverError> {
+    let public_inputs = matrices.num_instance_variables.saturating_sub(1);
+    if public_inputs != NR_PUBLIC_INPUTS {
+        return Err(ProverError::CircuitMismatch(format!(
+            "{} is for a circuit with {} public inputs, not the transaction circuit's {}",
+            zkey_path, public_inputs, NR_PUBLIC_INPUTS
+        )));
+    }
+    Ok(())
+}
+
+/// Signals in a witness computed by `wtns`, as its wasm's `getWitnessSize`
+/// reports. ark-circom only calls it while computing a witness, so it is
+/// called through the instance's exports.
+fn witness_size(store: &mut Store, wtns: &WitnessCalculator, wasm_path: &str) -> Result<usize, ProverError> {
+    let load_error = |reason: String| ProverError::WasmLoad { path: wasm_path.to_string(), reason };
+    let size = wtns
+        .instance
+        .exports
+        .get_function("getWitnessSize")
+        .map_err(|e| load_error(e.to_string()))?
+        .call(store, &[])
+        .map_err(|e| load_error(e.to_string()))?;
+    match size.first() {
+        Some(Value::I32(size)) => Ok(*size as u32 as usize),
+        _ => Err(load_error(format!("getWitnessSize returned {:?}", size))),
+    }
+}
+
+/// Check that the witness generator has a signal for every variable of the
+/// zkey's circuit: on load, from the wasm's witness size, so a wasm of
+/// another circuit is refused before anything is proved, and again for
+/// each witness computed.
+fn check_witness_len(len: usize, matrices: &ConstraintMatrices<Fr>) -> Result<(), ProverError> {
+    let expected = circuit::variable_count(matrices);
+    if len != expected {
+        return Err(ProverError::CircuitMismatch(format!(
+            "The witness generator has {} signals, but the zkey's circuit has {} \
+             variables ({} constraints)",
+            len, expected, matrices.num_constraints
+        )));
+    }
+    Ok(())
+}
+
+/// Refuse an artifact whose sha256 isn't the one pinned for its file name
+fn check_pin(path: &str, data: &[u8]) -> Result<(), ProverError> {
+    let Some(expected) = crate::artifacts::pinned(Path::new(path)) else {
+        return Ok(());
+    };
+    let actual = crate::artifacts::sha256_bytes(data);
+    if actual != expected {
+        return Err(ProverError::ChecksumMismatch {
+            path: path.to_string(),
+            expected: expected.to_string(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Check the header and section table of a zkey before parsing it, so a bad
+/// download fails with a clear message instead of a parse error halfway in
+fn check_zkey(data: &[u8]) -> Result<(), String> {
+    const HINT: &str = "download it again with 'privacy artifacts download --force'";
+    if data.trim_ascii_start().starts_with(b"<") {
+        return Err(format!("this is an HTML page, not a zkey; {}", HINT));
+    }
+    if !data.starts_with(b"zkey") {
+        return Err(format!("not a zkey file; {}", HINT));
+    }
+    if data.len() < 12 {
+        return Err(format!("truncated zkey: only {} bytes; {}", data.len(), HINT));
+    }
+    // "zkey", version, section count, then per section: type (u32), size
+    // (u64), contents
+    let sections = u32::from_le_bytes(data[8..12].try_into().unwrap());
+    let len = data.len() as u64;
+    let mut offset = 12u64;
+    for section in 1..=sections {
+        let end = data
+            .get(offset as usize + 4..offset as usize + 12)
+            .map(|size| u64::from_le_bytes(size.try_into().unwrap()))
+            .and_then(|size| (offset + 12).checked_add(size));
+        match end {
+            Some(end) if end <= len => offset = end,
+            _ => {
+                return Err(format!(
+                    "truncated zkey: section {} of {} runs past the end of the file ({} bytes); {}",
+                    section,
+                    sections,
+                    len,
+                    HINT
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads a zkey from memory, reporting how much of it has been read
+struct ProgressReader<'a, F> {
+    cursor: Cursor<&'a [u8]>,
+    read: u64,
+    reported_percent: u64,
+    progress: F,
+}
+
+impl<'a, F: FnMut(u64, u64)> ProgressReader<'a, F> {
+    fn new(data: &'a [u8], progress: F) -> Self {
+        Self {
+            cursor: Cursor::new(data),
+            read: 0,
+            reported_percent: 0,
+            progress,
+        }
+    }
+}
+
+impl<F: FnMut(u64, u64)> Read for ProgressReader<'_, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.cursor.read(buf)?;
+        // Bytes read rather than the position: the section table is walked
+        // by seeking to the end and back
+        self.read += n as u64;
+        let total = self.cursor.get_ref().len() as u64;
+        let percent = self.read * 100 / total.max(1);
+        if percent > self.reported_percent {
+            self.reported_percent = percent;
+            (self.progress)(self.read, total);
+        }
+        Ok(n)
+    }
+}
+
+impl<F> Seek for ProgressReader<'_, F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.cursor.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::fr_to_be_bytes;
+
+    const ARTIFACTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../artifacts");
+    /// `to_instruction_data()` of the proof of seed 1 in
+    /// `test_seeded_proofs_are_reproducible`
+    const SEEDED_PROOF_HEX: &str = include_str!("../tests/fixtures/seeded_proof.hex");
+
+    fn zkey() -> Vec<u8> {
+        std::fs::read(format!("{}/transaction2.zkey", ARTIFACTS)).unwrap()
+    }
+
+    #[test]
+    fn test_prover_is_loaded_once_per_dir() {
+        let first = PrivacyProver::shared(ARTIFACTS, |_, _| {}).unwrap();
+        let loads = ZKEY_LOADS.load(Ordering::Relaxed);
+
+        // As in a transfer: the withdrawal reuses the deposit's prover
+        let second = PrivacyProver::shared(ARTIFACTS, |_, _| panic!("loaded again")).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(ZKEY_LOADS.load(Ordering::Relaxed), loads);
+
+        // A failed load isn't cached
+        assert!(matches!(
+            PrivacyProver::shared("/nonexistent", |_, _| {}),
+            Err(ProverError::ArtifactNotFound { .. })
+        ));
+        assert!(PrivacyProver::shared("/nonexistent", |_, _| {}).is_err());
+        assert_eq!(ZKEY_LOADS.load(Ordering::Relaxed), loads);
+    }
+
+    #[test]
+    fn test_corrupted_proof_is_caught_before_sending() {
+        let prover = PrivacyProver::shared(ARTIFACTS, |_, _| {}).unwrap();
+        let utxo = Utxo::new(100_000_000, crate::crypto::sol_mint()).unwrap();
+        let root = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap().root();
+        let payer = solana_sdk::pubkey::Pubkey::new_from_array([7; 32]);
+        let ext_data = ExtData::direct(payer, payer);
+        let proof = prover
+            .prove_deposit(100_000_000, &utxo, &ext_data, root, &CancelToken::default())
+            .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("proof-debug-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(prover.verified_or_dumped(proof.clone(), Some(&dir)).is_ok());
+        assert!(!dir.exists());
+
+        // One flipped bit of proof_c, and a wrong root
+        let mut corrupted = proof.clone();
+        corrupted.proof_c[31] ^= 1;
+        let mut wrong_root = proof;
+        wrong_root.root = fr_to_be_bytes(&Fr::from(1u64)).to_vec();
+        for bad in [corrupted, wrong_root] {
+            let err = prover.verified_or_dumped(bad, Some(&dir)).unwrap_err();
+            assert!(matches!(err, ProverError::VerificationFailed { .. }), "{}", err);
+            let err = err.to_string();
+            assert!(err.contains("failed local verification"), "{}", err);
+            assert!(err.contains("nothing was sent"), "{}", err);
+        }
+        let dumps: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect();
+        assert!(!dumps.is_empty());
+        let dump: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&dumps[0]).unwrap()).unwrap();
+        assert_eq!(dump["public_inputs"].as_array().unwrap().len(), 7);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_proof_stages_are_reported_in_order() {
+        use std::cell::RefCell;
+
+        let prover = PrivacyProver::shared(ARTIFACTS, |_, _| {}).unwrap();
+        let utxo = Utxo::new(100_000_000, crate::crypto::sol_mint()).unwrap();
+        let root = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap().root();
+        let payer = solana_sdk::pubkey::Pubkey::new_from_array([7; 32]);
+        let ext_data = ExtData::direct(payer, payer);
+        let stages = RefCell::new(Vec::new());
+        let record = |stage: ProverStage| stages.borrow_mut().push(stage);
+
+        let cancel = CancelToken::default();
+        let progress = Progress { cancel: &cancel, on_stage: &record };
+        prover
+            .prove_deposit_with_progress(100_000_000, &utxo, &ext_data, root, progress)
+            .unwrap();
+        assert_eq!(
+            stages.take(),
+            [
+                ProverStage::WitnessStarted,
+                ProverStage::WitnessDone,
+                ProverStage::ProofStarted,
+                ProverStage::ProofDone,
+            ]
+        );
+
+        // A proof cancelled before it starts reports nothing
+        cancel.cancel();
+        assert!(prover
+            .prove_deposit_with_progress(100_000_000, &utxo, &ext_data, root, progress)
+            .is_err());
+        assert!(stages.take().is_empty());
+    }
+
+    #[test]
+    fn test_transfer_pays_another_pubkey() {
+        let prover = PrivacyProver::shared(ARTIFACTS, |_, _| {}).unwrap();
+        let mint = crate::crypto::sol_mint();
+        let utxo = Utxo::new(100_000_000, mint).unwrap();
+        let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+        tree.insert_many(&[Fr::from(1u64), str_to_fr(&utxo.commitment).unwrap()]).unwrap();
+
+        let recipient = TransferOutput::to(60_000_000, Fr::from(42u64));
+        let change = TransferOutput::of(&Utxo::new(40_000_000, mint).unwrap()).unwrap();
+        let payer = solana_sdk::pubkey::Pubkey::new_from_array([7; 32]);
+        let ext_data = ExtData::direct(payer, payer);
+        let cancel = CancelToken::default();
+        let proof = prover
+            .prove_transfer(&utxo, 1, &tree, &[recipient, change], &ext_data, &cancel)
+            .unwrap();
+        assert_eq!(proof.public_amount, 0);
+        assert_eq!(proof.commitment1, fr_to_be_bytes(&recipient.commitment(&mint).unwrap()));
+        assert_eq!(proof.root, fr_to_be_bytes(&tree.root()));
+
+        // Amounts can't be created
+        let greedy = TransferOutput { amount: 60_000_001, ..recipient };
+        let err = prover
+            .prove_transfer(&utxo, 1, &tree, &[greedy, change], &ext_data, &cancel)
+            .unwrap_err();
+        assert!(matches!(err, ProverError::ConstraintUnsatisfied(_)), "{}", err);
+    }
+
+    #[test]
+    fn test_seeded_proofs_are_reproducible() {
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha20Rng;
+
+        let prover = PrivacyProver::shared(ARTIFACTS, |_, _| {}).unwrap();
+        // The keys of tests/vectors.rs, whose pubkey is Poseidon(privkey)
+        let utxo = Utxo::from_values(
+            100_000_000,
+            "4932897425348234597827354123098745612309874561230987456123",
+            "18279723950900672485979820291930596425376290165896281340336186363028079899503",
+            "1234567890123456789012345678901234567890123456789012345678",
+            crate::crypto::sol_mint(),
+        )
+        .unwrap();
+        let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+        tree.insert_many(&[Fr::from(1u64), str_to_fr(&utxo.commitment).unwrap()]).unwrap();
+        let payer = solana_sdk::pubkey::Pubkey::new_from_array([7; 32]);
+        let ext_data = ExtData::direct(payer, payer);
+
+        // The dummy input's and the change's blindings come from a seeded
+        // RNG too, so the whole proof is pinned
+        let (inputs, ext_data_hash) = circuit::withdraw_inputs_with_rng(
+            &utxo,
+            1,
+            &tree,
+            &ext_data,
+            &mut ChaCha20Rng::seed_from_u64(0),
+        )
+        .unwrap();
+        let cancel = CancelToken::default();
+        let prove = |seed: u64| {
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            let (proof, signals) =
+                prover.generate_proof_with_rng(inputs.clone(), Progress::cancel(&cancel), &mut rng).unwrap();
+            prover.format_proof(&proof, &signals, &ext_data_hash).unwrap()
+        };
+
+        // Pinned, so a change to the inputs, the witness generator or the
+        // prover's use of its randomness shows up here
+        let proof = prove(1);
+        assert_eq!(hex::encode(proof.to_instruction_data()), SEEDED_PROOF_HEX.trim());
+        assert_eq!(prove(1), proof);
+        assert_eq!(proof.verify(&prover.vk), Ok(()));
+        assert_eq!(proof.root, fr_to_be_bytes(&tree.root()));
+        assert_eq!(proof.public_amount, -100_000_000);
+
+        // Another seed proves the same statement with other points
+        let other = prove(2);
+        assert_ne!(other.proof_a, proof.proof_a);
+        assert_eq!(other.public_inputs().unwrap(), proof.public_inputs().unwrap());
+        assert_eq!(other.verify(&prover.vk), Ok(()));
+    }
+
+    #[test]
+    fn test_bad_zkey_downloads_are_named() {
+        let data = zkey();
+        assert!(check_zkey(&data).is_ok());
+
+        let err = check_zkey(&data[..data.len() / 2]).unwrap_err().to_string();
+        assert!(err.starts_with("truncated zkey: section"), "{}", err);
+        assert!(check_zkey(&data[..10]).unwrap_err().to_string().contains("only 10 bytes"));
+
+        let page = b"\n<!DOCTYPE html><html><body>404: Not Found</body></html>";
+        assert!(check_zkey(page).unwrap_err().to_string().contains("HTML page"));
+        assert!(check_zkey(b"{\"error\": 1}").unwrap_err().to_string().contains("not a zkey"));
+
+        let path = std::env::temp_dir().join(format!("truncated-{}.zkey", std::process::id()));
+        std::fs::write(&path, &data[..data.len() / 2]).unwrap();
+        let wasm = format!("{}/transaction2.wasm", ARTIFACTS);
+        let err = PrivacyProver::new(&wasm, path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ProverError::ZkeyParse { .. }), "{}", err);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tampered_artifacts_are_refused() {
+        let dir = std::env::temp_dir().join(format!("tampered-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy(format!("{}/transaction2.wasm", ARTIFACTS), dir.join("transaction2.wasm"))
+            .unwrap();
+        // Well formed, so only the checksum catches it
+        let mut data = zkey();
+        *data.last_mut().unwrap() ^= 1;
+        assert!(check_zkey(&data).is_ok());
+        std::fs::write(dir.join("transaction2.zkey"), &data).unwrap();
+
+        let err = PrivacyProver::from_dir(dir.to_str().unwrap(), |_, _| {}).err().unwrap();
+        assert!(matches!(err, ProverError::ChecksumMismatch { .. }), "{}", err);
+        assert!(err.to_string().contains(crate::artifacts::ARTIFACTS[1].sha256), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_proof_must_commit_to_its_ext_data() {
+        let prover = PrivacyProver::shared(ARTIFACTS, |_, _| {}).unwrap();
+        let signals = vec![Fr::from(1u64); 7];
+        let err = prover.format_proof(&Proof::default(), &signals, &Fr::from(2u64)).err().unwrap();
+        assert!(matches!(err, ProverError::ExtDataMismatch { .. }), "{}", err);
+        assert!(prover.format_proof(&Proof::default(), &signals, &Fr::from(1u64)).is_ok());
+    }
+
+    #[test]
+    fn test_mismatched_circuits_are_named() {
+        let prover = PrivacyProver::shared(ARTIFACTS, |_, _| {}).unwrap();
+        let matrices = &prover.matrices;
+        assert_eq!(matrices.num_instance_variables, NR_PUBLIC_INPUTS + 1);
+        assert!(check_public_inputs("transaction2.zkey", matrices).is_ok());
+        let variables = circuit::variable_count(matrices);
+        assert_eq!(variables, 33_862);
+        assert!(check_witness_len(variables, matrices).is_ok());
+
+        // The wasm reports its witness size without computing a witness
+        let mut witness = prover.witness.lock().unwrap();
+        let (store, wtns) = &mut *witness;
+        assert_eq!(witness_size(store, wtns, "transaction2.wasm").unwrap(), variables);
+
+        let err = check_witness_len(variables - 1, matrices).unwrap_err();
+        assert!(matches!(err, ProverError::CircuitMismatch(_)), "{}", err);
+        let other = ConstraintMatrices {
+            num_instance_variables: 4,
+            ..matrices.clone()
+        };
+        let err = check_public_inputs("other.zkey", &other).unwrap_err().to_string();
+        assert!(err.contains("other.zkey is for a circuit with 3 public inputs"), "{}", err);
+    }
+
+    #[test]
+    fn test_failed_asserts_are_unsatisfied_constraints() {
+        let err = witness_error("RuntimeError: Error: Assert Failed. at template 12".to_string());
+        assert!(matches!(err, ProverError::ConstraintUnsatisfied(_)));
+        let err = witness_error("Input signal root not found".to_string());
+        assert!(matches!(err, ProverError::WitnessCalculation(_)));
+    }
+
+    #[test]
+    fn test_load_reports_progress() {
+        let mut reports = Vec::new();
+        let dir = format!("{}/transaction2", ARTIFACTS);
+        PrivacyProver::load(&format!("{}.wasm", dir), &format!("{}.zkey", dir), |done, total| {
+            reports.push((done, total))
+        })
+        .unwrap();
+        let total = zkey().len() as u64;
+        assert!(reports.len() <= 100, "{} reports", reports.len());
+        assert!(reports.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(reports.iter().all(|&(done, t)| t == total && done <= total));
+        assert!(reports.last().unwrap().0 * 100 / total >= 90, "{:?}", reports.last());
+    }
+
+    /// Cold load of the zkey read through the file versus mapped; run with
+    /// `cargo test --release -- --ignored --nocapture test_zkey_load_time`
+    /// after dropping the page cache
+    #[test]
+    #[ignore = "timing only"]
+    fn test_zkey_load_time() {
+        let path = format!("{}/transaction2.zkey", ARTIFACTS);
+        let started = std::time::Instant::now();
+        read_zkey(&mut File::open(&path).unwrap()).unwrap();
+        let read = started.elapsed();
+
+        let started = std::time::Instant::now();
+        let file = File::open(&path).unwrap();
+        let map = unsafe { Mmap::map(&file) }.unwrap();
+        read_zkey(&mut ProgressReader::new(&map, |_, _| {})).unwrap();
+        let mapped = started.elapsed();
+        println!("read_zkey(File): {:?}, mapped: {:?}", read, mapped);
+    }
+}
@@ -0,0 +1,295 @@
+//! Failover between RPC endpoints
+//!
+//! Public RPC endpoints rate-limit and go down, often in the middle of a
+//! withdrawal. [`RpcPool`] holds a client per endpoint and sends each call
+//! to the current one; when an endpoint is rate limited, fails with a server
+//! error or times out, it is marked down and the call moves on to the next.
+//! Down endpoints are probed with `getHealth` again once `reprobe_interval`
+//! has passed, and calls return to the first healthy endpoint, so the first
+//! URL acts as the primary.
+//!
+//! The pool is generic over the client so this crate doesn't depend on
+//! `solana-client`; the CLI and relayer implement [`Endpoint`] for a wrapper
+//! around `RpcClient`.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a down endpoint is left alone before it is probed again
+pub const DEFAULT_REPROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// An RPC client the pool can route calls to
+pub trait Endpoint {
+    type Error;
+
+    fn url(&self) -> String;
+
+    /// Whether `err` is the endpoint's fault (rate limited, 5xx, timed out,
+    /// unreachable) rather than the request's, so another endpoint may
+    /// succeed
+    fn is_unavailable(err: &Self::Error) -> bool;
+
+    /// `getHealth`
+    fn is_healthy(&self) -> bool;
+}
+
+pub struct RpcPool<C> {
+    endpoints: Vec<C>,
+    reprobe_interval: Duration,
+    state: Mutex<State>,
+}
+
+struct State {
+    current: usize,
+    /// When each endpoint was last found down, `None` while it is healthy
+    down_since: Vec<Option<Instant>>,
+}
+
+impl<C: Endpoint> RpcPool<C> {
+    /// Panics if `endpoints` is empty
+    pub fn new(endpoints: Vec<C>, reprobe_interval: Duration) -> Self {
+        assert!(!endpoints.is_empty(), "RpcPool needs at least one endpoint");
+        let down_since = vec![None; endpoints.len()];
+        Self {
+            endpoints,
+            reprobe_interval,
+            state: Mutex::new(State {
+                current: 0,
+                down_since,
+            }),
+        }
+    }
+
+    /// The endpoint calls currently go to
+    pub fn current(&self) -> &C {
+        &self.endpoints[self.state.lock().unwrap().current]
+    }
+
+    pub fn endpoints(&self) -> &[C] {
+        &self.endpoints
+    }
+
+    /// Run `f` against the current endpoint, moving on to the next one each
+    /// time an endpoint turns out to be unavailable
+    ///
+    /// Other errors are returned straight away. If every endpoint is
+    /// unavailable, the last error is returned.
+    pub fn call<T>(&self, f: impl FnMut(&C) -> Result<T, C::Error>) -> Result<T, C::Error> {
+        self.call_at(Instant::now(), f)
+    }
+
+    fn call_at<T>(
+        &self,
+        now: Instant,
+        mut f: impl FnMut(&C) -> Result<T, C::Error>,
+    ) -> Result<T, C::Error> {
+        self.reprobe(now);
+        let mut last_err = None;
+        for i in self.order() {
+            match f(&self.endpoints[i]) {
+                Ok(value) => {
+                    self.mark_up(i);
+                    return Ok(value);
+                }
+                Err(e) if C::is_unavailable(&e) => {
+                    self.mark_down(i, now);
+                    last_err = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_err.expect("the pool has at least one endpoint"))
+    }
+
+    /// Probe endpoints that have been down for `reprobe_interval`, and go
+    /// back to the first healthy one
+    fn reprobe(&self, now: Instant) {
+        let due: Vec<usize> = {
+            let state = self.state.lock().unwrap();
+            (0..self.endpoints.len())
+                .filter(|&i| {
+                    state.down_since[i]
+                        .is_some_and(|t| now.duration_since(t) >= self.reprobe_interval)
+                })
+                .collect()
+        };
+        // Probe without holding the lock; health checks are network calls
+        let healthy: Vec<(usize, bool)> = due
+            .into_iter()
+            .map(|i| (i, self.endpoints[i].is_healthy()))
+            .collect();
+
+        let mut state = self.state.lock().unwrap();
+        for (i, healthy) in healthy {
+            state.down_since[i] = if healthy { None } else { Some(now) };
+        }
+        if let Some(first) = state.down_since.iter().position(Option::is_none) {
+            state.current = state.current.min(first);
+        }
+    }
+
+    /// Healthy endpoints from the current one on, then the down ones in
+    /// case they recovered
+    fn order(&self) -> Vec<usize> {
+        let state = self.state.lock().unwrap();
+        let n = self.endpoints.len();
+        let rotation = (0..n).map(|k| (state.current + k) % n);
+        let (up, down): (Vec<usize>, Vec<usize>) =
+            rotation.partition(|&i| state.down_since[i].is_none());
+        up.into_iter().chain(down).collect()
+    }
+
+    fn mark_up(&self, i: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.down_since[i] = None;
+        state.current = i;
+    }
+
+    fn mark_down(&self, i: usize, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        state.down_since[i] = Some(now);
+        if state.current == i {
+            state.current = (i + 1) % self.endpoints.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::collections::VecDeque;
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum FakeError {
+        RateLimited,
+        BadRequest,
+    }
+
+    /// Answers calls from a script; an empty script means success
+    struct FakeEndpoint {
+        name: &'static str,
+        script: RefCell<VecDeque<FakeError>>,
+        healthy: Cell<bool>,
+        calls: Cell<usize>,
+    }
+
+    impl FakeEndpoint {
+        fn new(name: &'static str, script: Vec<FakeError>) -> Self {
+            Self {
+                name,
+                script: RefCell::new(script.into()),
+                healthy: Cell::new(true),
+                calls: Cell::new(0),
+            }
+        }
+
+        fn get(&self) -> Result<&'static str, FakeError> {
+            self.calls.set(self.calls.get() + 1);
+            match self.script.borrow_mut().pop_front() {
+                Some(e) => Err(e),
+                None => Ok(self.name),
+            }
+        }
+    }
+
+    impl Endpoint for FakeEndpoint {
+        type Error = FakeError;
+
+        fn url(&self) -> String {
+            self.name.to_string()
+        }
+
+        fn is_unavailable(err: &FakeError) -> bool {
+            *err == FakeError::RateLimited
+        }
+
+        fn is_healthy(&self) -> bool {
+            self.healthy.get()
+        }
+    }
+
+    const INTERVAL: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn rotates_past_unavailable_endpoint() {
+        let pool = RpcPool::new(
+            vec![
+                FakeEndpoint::new("a", vec![FakeError::RateLimited]),
+                FakeEndpoint::new("b", vec![]),
+            ],
+            INTERVAL,
+        );
+        assert_eq!(pool.call(FakeEndpoint::get), Ok("b"));
+        assert_eq!(pool.current().url(), "b");
+
+        // `a` stays out of rotation until it is probed again
+        assert_eq!(pool.call(FakeEndpoint::get), Ok("b"));
+        assert_eq!(pool.endpoints()[0].calls.get(), 1);
+    }
+
+    #[test]
+    fn request_errors_do_not_fail_over() {
+        let pool = RpcPool::new(
+            vec![
+                FakeEndpoint::new("a", vec![FakeError::BadRequest]),
+                FakeEndpoint::new("b", vec![]),
+            ],
+            INTERVAL,
+        );
+        assert_eq!(pool.call(FakeEndpoint::get), Err(FakeError::BadRequest));
+        assert_eq!(pool.endpoints()[1].calls.get(), 0);
+        assert_eq!(pool.current().url(), "a");
+    }
+
+    #[test]
+    fn all_down_returns_last_error() {
+        let pool = RpcPool::new(
+            vec![
+                FakeEndpoint::new("a", vec![FakeError::RateLimited]),
+                FakeEndpoint::new("b", vec![FakeError::RateLimited]),
+            ],
+            INTERVAL,
+        );
+        assert_eq!(pool.call(FakeEndpoint::get), Err(FakeError::RateLimited));
+        assert!(pool.endpoints().iter().all(|e| e.calls.get() == 1));
+
+        // Down endpoints are still tried when nothing else is left
+        assert_eq!(pool.call(FakeEndpoint::get), Ok("a"));
+    }
+
+    #[test]
+    fn returns_to_primary_once_healthy() {
+        let pool = RpcPool::new(
+            vec![
+                FakeEndpoint::new("a", vec![FakeError::RateLimited]),
+                FakeEndpoint::new("b", vec![]),
+                FakeEndpoint::new("c", vec![]),
+            ],
+            INTERVAL,
+        );
+        let start = Instant::now();
+        assert_eq!(pool.call_at(start, FakeEndpoint::get), Ok("b"));
+
+        // Not probed before the interval is up
+        pool.endpoints()[0].healthy.set(false);
+        assert_eq!(
+            pool.call_at(start + INTERVAL / 2, FakeEndpoint::get),
+            Ok("b")
+        );
+
+        // Probed but still unhealthy: the clock restarts
+        assert_eq!(pool.call_at(start + INTERVAL, FakeEndpoint::get), Ok("b"));
+        pool.endpoints()[0].healthy.set(true);
+        assert_eq!(
+            pool.call_at(start + INTERVAL * 3 / 2, FakeEndpoint::get),
+            Ok("b")
+        );
+
+        assert_eq!(
+            pool.call_at(start + INTERVAL * 2, FakeEndpoint::get),
+            Ok("a")
+        );
+        assert_eq!(pool.current().url(), "a");
+    }
+}
@@ -0,0 +1,214 @@
+//! SPL token pools
+//!
+//! Each mint has a pool of its own, set up by `initialize_spl`: a tree
+//! account, a `TokenPoolAccount` recording the mint and the vault token
+//! account, and a vault authority PDA (`["vault_authority", tree]`) that
+//! signs transfers out of the vault. Deposits and withdrawals go through
+//! `transact_spl`, which takes the same instruction data as `transact` and
+//! moves tokens with a CPI into the token program; see
+//! [`crate::instruction::build_transact_spl_instruction`].
+//!
+//! Token amounts are in the mint's base units. [`format_amount`] and
+//! [`parse_amount`] convert them with the mint's decimals.
+
+use solana_sdk::{pubkey, pubkey::Pubkey};
+use thiserror::Error;
+
+/// The SPL token program, which `transact_spl` invokes to move tokens
+pub const TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+
+/// The associated token account program, whose PDAs are each wallet's token
+/// account for a mint
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+    pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// Size of a mint account; `decimals` is at offset 44
+pub const MINT_LEN: usize = 82;
+const DECIMALS_OFFSET: usize = 44;
+
+/// Size of a token account: mint (32), owner (32), amount (u64 LE), ...
+pub const TOKEN_ACCOUNT_LEN: usize = 165;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SplError {
+    #[error("Account is not a token mint ({len} bytes, expected {MINT_LEN})")]
+    NotAMint { len: usize },
+    #[error("Account is not a token account ({len} bytes, expected {TOKEN_ACCOUNT_LEN})")]
+    NotATokenAccount { len: usize },
+    #[error("Invalid amount {0:?}")]
+    InvalidAmount(String),
+    #[error("{amount} has more than the {decimals} decimal places the mint allows")]
+    TooPrecise { amount: String, decimals: u8 },
+}
+
+/// Addresses of the pool of one mint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplPool {
+    pub mint: Pubkey,
+    /// Tree account of the pool, separate from the SOL pool's
+    pub tree_account: Pubkey,
+    /// `TokenPoolAccount` recording the mint and vault
+    pub token_pool: Pubkey,
+    /// Token account holding the pool's tokens
+    pub vault: Pubkey,
+}
+
+impl SplPool {
+    /// PDA that owns the vault and signs withdrawals out of it
+    pub fn vault_authority(&self, program_id: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[b"vault_authority", self.tree_account.as_ref()],
+            program_id,
+        )
+        .0
+    }
+}
+
+/// `owner`'s associated token account for `mint`
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), TOKEN_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Decimals of a mint, from its account data
+pub fn mint_decimals(data: &[u8]) -> Result<u8, SplError> {
+    if data.len() < MINT_LEN {
+        return Err(SplError::NotAMint { len: data.len() });
+    }
+    Ok(data[DECIMALS_OFFSET])
+}
+
+/// The fields of a token account the CLI and relayer look at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenAccount {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+impl TokenAccount {
+    pub fn parse(data: &[u8]) -> Result<Self, SplError> {
+        if data.len() < TOKEN_ACCOUNT_LEN {
+            return Err(SplError::NotATokenAccount { len: data.len() });
+        }
+        let pubkey = |offset: usize| Pubkey::try_from(&data[offset..offset + 32]).unwrap();
+        Ok(Self {
+            mint: pubkey(0),
+            owner: pubkey(32),
+            amount: u64::from_le_bytes(data[64..72].try_into().unwrap()),
+        })
+    }
+}
+
+/// `amount` base units as a decimal with `decimals` places, trailing zeros
+/// dropped, e.g. `1.5` for 1_500_000 at 6 decimals
+pub fn format_amount(amount: u64, decimals: u8) -> String {
+    let scale = 10u128.pow(decimals as u32);
+    let (whole, fraction) = (amount as u128 / scale, amount as u128 % scale);
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    let fraction = format!("{:0width$}", fraction, width = decimals as usize);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+/// Parse a decimal amount into base units of a mint with `decimals`,
+/// rejecting more precision than the mint has rather than rounding
+pub fn parse_amount(s: &str, decimals: u8) -> Result<u64, SplError> {
+    let invalid = || SplError::InvalidAmount(s.to_string());
+    let (whole, fraction) = s.trim().split_once('.').unwrap_or((s.trim(), ""));
+    let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !digits(whole) || !digits(fraction) {
+        return Err(invalid());
+    }
+    if fraction.len() > decimals as usize {
+        return Err(SplError::TooPrecise {
+            amount: s.to_string(),
+            decimals,
+        });
+    }
+    let padded = format!("{}{:0<width$}", whole, fraction, width = decimals as usize);
+    padded.parse::<u64>().map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts_round_trip_through_decimals() {
+        assert_eq!(format_amount(1_500_000, 6), "1.5");
+        assert_eq!(format_amount(1, 6), "0.000001");
+        assert_eq!(format_amount(42_000_000, 6), "42");
+        assert_eq!(format_amount(7, 0), "7");
+        assert_eq!(format_amount(u64::MAX, 9), "18446744073.709551615");
+
+        assert_eq!(parse_amount("1.5", 6), Ok(1_500_000));
+        assert_eq!(parse_amount("0.000001", 6), Ok(1));
+        assert_eq!(parse_amount("42", 6), Ok(42_000_000));
+        assert_eq!(parse_amount(".5", 1), Ok(5));
+        assert_eq!(parse_amount("7", 0), Ok(7));
+        assert!(matches!(
+            parse_amount("0.0000001", 6),
+            Err(SplError::TooPrecise { decimals: 6, .. })
+        ));
+        for bad in ["", ".", "-1", "1e6", "1.2.3", "99999999999999999999"] {
+            assert!(
+                matches!(parse_amount(bad, 6), Err(SplError::InvalidAmount(_))),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_mint_and_token_accounts() {
+        let mut mint = vec![0; MINT_LEN];
+        mint[DECIMALS_OFFSET] = 6;
+        assert_eq!(mint_decimals(&mint), Ok(6));
+        assert_eq!(
+            mint_decimals(&mint[..40]),
+            Err(SplError::NotAMint { len: 40 })
+        );
+
+        let mut account = vec![0; TOKEN_ACCOUNT_LEN];
+        account[..32].fill(1);
+        account[32..64].fill(2);
+        account[64..72].copy_from_slice(&1_500_000u64.to_le_bytes());
+        assert_eq!(
+            TokenAccount::parse(&account),
+            Ok(TokenAccount {
+                mint: Pubkey::from([1; 32]),
+                owner: Pubkey::from([2; 32]),
+                amount: 1_500_000,
+            })
+        );
+        assert!(TokenAccount::parse(&mint).is_err());
+    }
+
+    #[test]
+    fn derives_token_addresses() {
+        // A PDA of the associated token program, distinct per owner and mint
+        let owner = pubkey!("7nAKNHQwTeaybrnX6y3c3fLDL3qzQ3A6FGwMwH1LPc8q");
+        let usdc = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+        let ata = associated_token_address(&owner, &usdc);
+        assert_ne!(ata, associated_token_address(&usdc, &owner));
+        assert!(!ata.is_on_curve());
+
+        let pool = SplPool {
+            mint: usdc,
+            tree_account: Pubkey::new_unique(),
+            token_pool: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+        };
+        let program_id = Pubkey::new_unique();
+        let expected = Pubkey::find_program_address(
+            &[b"vault_authority", pool.tree_account.as_ref()],
+            &program_id,
+        )
+        .0;
+        assert_eq!(pool.vault_authority(&program_id), expected);
+    }
+}
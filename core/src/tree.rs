@@ -0,0 +1,90 @@
+//! Layout of the on-chain Merkle tree account
+//!
+//! ```text
+//! offset  size  field
+//!      0     8  discriminator
+//!      8    32  authority
+//!     40     8  next_index (u64 LE)
+//!     48     8  root_index (u64 LE)
+//!     56     1  bump
+//!     64     8  max_deposit_amount
+//!     72     1  height
+//!     73     1  root_history_size
+//!     79  3200  root_history ([32]u8 x ROOT_HISTORY_SIZE)
+//! ```
+//!
+//! `root_history` is a ring buffer of big-endian field elements. Each insert
+//! advances `root_index` and writes the new root there, so the current root
+//! is at `ROOT_HISTORY_OFFSET + root_index * 32`; once the buffer has
+//! wrapped, the slot after it holds the oldest root still accepted.
+
+/// Roots the program keeps, any of which a proof may be generated against
+pub const ROOT_HISTORY_SIZE: usize = 100;
+
+pub const NEXT_INDEX_OFFSET: usize = 8 + 32;
+pub const ROOT_INDEX_OFFSET: usize = NEXT_INDEX_OFFSET + 8;
+pub const MAX_DEPOSIT_AMOUNT_OFFSET: usize = 8 + 56;
+pub const ROOT_HISTORY_OFFSET: usize = 8 + 71;
+
+/// Number of leaves in the tree, two per transact of any kind; the account
+/// keeps no separate count of deposits
+pub fn next_index(data: &[u8]) -> Option<u64> {
+    read_u64(data, NEXT_INDEX_OFFSET)
+}
+
+/// Slot of the current root in `root_history`
+pub fn root_index(data: &[u8]) -> Option<u64> {
+    read_u64(data, ROOT_INDEX_OFFSET)
+}
+
+/// Largest deposit the program accepts, in lamports
+pub fn max_deposit_amount(data: &[u8]) -> Option<u64> {
+    read_u64(data, MAX_DEPOSIT_AMOUNT_OFFSET)
+}
+
+/// The root after the most recent insert
+pub fn current_root(data: &[u8]) -> Option<[u8; 32]> {
+    let index = root_index(data)? as usize % ROOT_HISTORY_SIZE;
+    let offset = ROOT_HISTORY_OFFSET + index * 32;
+    data.get(offset..offset + 32).map(|root| root.try_into().unwrap())
+}
+
+/// Non-zero roots in the tree's root history, in storage order
+pub fn root_history(data: &[u8]) -> Option<Vec<[u8; 32]>> {
+    let history = data.get(ROOT_HISTORY_OFFSET..ROOT_HISTORY_OFFSET + ROOT_HISTORY_SIZE * 32)?;
+    Some(
+        history
+            .chunks_exact(32)
+            .map(|root| <[u8; 32]>::try_from(root).unwrap())
+            .filter(|root| root.iter().any(|b| *b != 0))
+            .collect(),
+    )
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    data.get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_roots_and_next_index() {
+        let mut data = vec![0u8; ROOT_HISTORY_OFFSET + ROOT_HISTORY_SIZE * 32 + 26 * 32];
+        data[NEXT_INDEX_OFFSET..NEXT_INDEX_OFFSET + 8].copy_from_slice(&26u64.to_le_bytes());
+        data[ROOT_HISTORY_OFFSET..ROOT_HISTORY_OFFSET + 32].fill(1);
+        data[ROOT_HISTORY_OFFSET + 32 * 5..ROOT_HISTORY_OFFSET + 32 * 6].fill(2);
+
+        assert_eq!(next_index(&data), Some(26));
+        assert_eq!(max_deposit_amount(&data), Some(0));
+        assert_eq!(root_history(&data).unwrap(), vec![[1u8; 32], [2u8; 32]]);
+        assert_eq!(root_history(&data[..ROOT_HISTORY_OFFSET + 64]), None);
+
+        data[ROOT_INDEX_OFFSET..ROOT_INDEX_OFFSET + 8].copy_from_slice(&5u64.to_le_bytes());
+        assert_eq!(root_index(&data), Some(5));
+        assert_eq!(current_root(&data), Some([2u8; 32]));
+        assert_eq!(current_root(&data[..ROOT_HISTORY_OFFSET + 64]), None);
+    }
+}
@@ -0,0 +1,146 @@
+//! Whether the pool vault can pay a SOL withdrawal
+//!
+//! The vault is a system-owned PDA of the program, seeds `["pool_vault"]`,
+//! holding every SOL note's lamports. A withdrawal takes the note's amount
+//! out of it, recipient's share and fee alike, and the rent of the two
+//! nullifier accounts the transaction creates; what's left must keep the
+//! vault rent-exempt. Checking this before proving saves a proof the chain
+//! would reject with an opaque insufficient-funds error, and the owner and
+//! address checks catch a mistyped `PRIVACY_POOL_POOL_VAULT`.
+
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+#[allow(deprecated)]
+use solana_sdk::system_program;
+use thiserror::Error;
+
+use crate::config::PoolConfig;
+
+/// Seed of the vault PDA
+pub const POOL_VAULT_SEED: &[u8] = b"pool_vault";
+
+/// Bytes of a nullifier account: discriminator and `is_used`
+pub const NULLIFIER_ACCOUNT_LEN: usize = 16;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum VaultError {
+    #[error("Pool vault {vault} is not the program's vault PDA {expected}; check PRIVACY_POOL_POOL_VAULT")]
+    Address { vault: Pubkey, expected: Pubkey },
+    #[error("Pool vault {0} does not exist; check PRIVACY_POOL_POOL_VAULT")]
+    Missing(Pubkey),
+    #[error("Pool vault {vault} is owned by {owner}, not the system program; check PRIVACY_POOL_POOL_VAULT")]
+    Owner { vault: Pubkey, owner: Pubkey },
+    #[error(
+        "The pool vault holds {} SOL but this withdrawal needs {} SOL (the note plus rent); nothing was sent",
+        sol(*balance),
+        sol(*needed)
+    )]
+    Underfunded { balance: u64, needed: u64 },
+}
+
+/// The vault of `program_id`
+pub fn pool_vault_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[POOL_VAULT_SEED], program_id).0
+}
+
+/// Lamports the vault must hold to pay a withdrawal of `amount`: the
+/// amount, the two nullifier accounts' rent and its own rent-exempt minimum
+pub fn withdrawal_lamports(amount: u64) -> u64 {
+    let rent = Rent::default();
+    amount
+        .saturating_add(2 * rent.minimum_balance(NULLIFIER_ACCOUNT_LEN))
+        .saturating_add(rent.minimum_balance(0))
+}
+
+/// Check the vault `account`, its owner and lamports or `None` if it
+/// doesn't exist, can pay a withdrawal of `amount` from `config`'s pool
+pub fn check_withdrawal(
+    config: &PoolConfig,
+    account: Option<(Pubkey, u64)>,
+    amount: u64,
+) -> Result<(), VaultError> {
+    let vault = config.pool_vault;
+    let expected = pool_vault_address(&config.program_id);
+    if vault != expected {
+        return Err(VaultError::Address { vault, expected });
+    }
+    let (owner, balance) = account.ok_or(VaultError::Missing(vault))?;
+    if owner != system_program::id() {
+        return Err(VaultError::Owner { vault, owner });
+    }
+    let needed = withdrawal_lamports(amount);
+    if balance < needed {
+        return Err(VaultError::Underfunded { balance, needed });
+    }
+    Ok(())
+}
+
+fn sol(lamports: u64) -> String {
+    format!("{:.9}", lamports as f64 / LAMPORTS_PER_SOL as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOL: u64 = LAMPORTS_PER_SOL;
+
+    fn system() -> Pubkey {
+        system_program::id()
+    }
+
+    #[test]
+    fn testnet_vault_is_the_pda() {
+        let config = PoolConfig::testnet();
+        assert_eq!(pool_vault_address(&config.program_id), config.pool_vault);
+    }
+
+    #[test]
+    fn withdrawal_needs_the_amount_plus_rent() {
+        // Two 16-byte nullifier accounts and an empty account's minimum
+        assert_eq!(withdrawal_lamports(0), 2 * 1_002_240 + 890_880);
+        assert_eq!(withdrawal_lamports(SOL), SOL + 2_895_360);
+        assert_eq!(withdrawal_lamports(u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn checks_the_balance() {
+        let config = PoolConfig::testnet();
+        let needed = withdrawal_lamports(SOL);
+        assert_eq!(check_withdrawal(&config, Some((system(), needed)), SOL), Ok(()));
+        assert_eq!(
+            check_withdrawal(&config, Some((system(), needed - 1)), SOL),
+            Err(VaultError::Underfunded { balance: needed - 1, needed })
+        );
+
+        // Exactly the amount isn't enough: the rent is still owed
+        let err = check_withdrawal(&config, Some((system(), SOL)), SOL).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The pool vault holds 1.000000000 SOL but this withdrawal needs 1.002895360 SOL \
+             (the note plus rent); nothing was sent"
+        );
+    }
+
+    #[test]
+    fn rejects_a_mistyped_vault() {
+        let mut config = PoolConfig::testnet();
+        let vault = config.pool_vault;
+
+        // A program-owned account, such as the tree, in the vault's place
+        let owner = config.program_id;
+        assert_eq!(
+            check_withdrawal(&config, Some((owner, 100 * SOL)), SOL),
+            Err(VaultError::Owner { vault, owner })
+        );
+        assert_eq!(check_withdrawal(&config, None, SOL), Err(VaultError::Missing(vault)));
+
+        // A funded wallet that isn't the vault
+        config.pool_vault = Pubkey::new_unique();
+        assert_eq!(
+            check_withdrawal(&config, Some((system(), 100 * SOL)), SOL),
+            Err(VaultError::Address { vault: config.pool_vault, expected: vault })
+        );
+    }
+}
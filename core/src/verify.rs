@@ -0,0 +1,271 @@
+//! Groth16 verification of transact proofs
+//!
+//! The program rejects a bad proof only once the transaction has been paid
+//! for. The CLI checks each proof it generates, and the relayer each proof
+//! it is asked to submit, so a malformed one costs nothing.
+//!
+//! Proofs are read in the program's format: big-endian coordinates,
+//! `proof_a` negated, and G2 coordinates as `x.c1 || x.c0 || y.c1 || y.c0`.
+
+use ark_bn254::{Bn254, Fq, Fq2, Fr, G1Affine, G2Affine};
+use ark_ff::{BigInteger, PrimeField};
+use ark_groth16::{Groth16, PreparedVerifyingKey, Proof, VerifyingKey};
+use serde::Deserialize;
+use std::str::FromStr;
+use thiserror::Error;
+
+use crate::instruction::TransactProofData;
+
+/// Number of public inputs of the transaction circuit
+pub const NR_PUBLIC_INPUTS: usize = 7;
+
+#[derive(Debug, Clone, Copy, Error, PartialEq, Eq)]
+pub enum ProofError {
+    /// The proof or a public input isn't a valid encoding
+    #[error("Malformed proof: {0}")]
+    Malformed(&'static str),
+    #[error("The proof does not verify for these public inputs")]
+    Invalid,
+}
+
+#[derive(Debug, Error)]
+pub enum VerifyingKeyError {
+    #[error("Invalid verifying key JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("expected a key for {NR_PUBLIC_INPUTS} public inputs, got {0}")]
+    PublicInputs(usize),
+    #[error("{0}")]
+    Point(&'static str),
+    #[error("invalid field element {0}")]
+    FieldElement(String),
+}
+
+/// snarkjs `verification_key.json`
+#[derive(Deserialize)]
+struct VkJson {
+    #[serde(rename = "nPublic")]
+    n_public: usize,
+    vk_alpha_1: Vec<String>,
+    vk_beta_2: Vec<Vec<String>>,
+    vk_gamma_2: Vec<Vec<String>>,
+    vk_delta_2: Vec<Vec<String>>,
+    #[serde(rename = "IC")]
+    ic: Vec<Vec<String>>,
+}
+
+/// Parse a snarkjs verifying key exported from the circuit's zkey
+pub fn verifying_key_from_snarkjs(
+    data: &str,
+) -> Result<PreparedVerifyingKey<Bn254>, VerifyingKeyError> {
+    let json: VkJson = serde_json::from_str(data)?;
+    if json.n_public != NR_PUBLIC_INPUTS || json.ic.len() != NR_PUBLIC_INPUTS + 1 {
+        return Err(VerifyingKeyError::PublicInputs(json.n_public));
+    }
+    let vk = VerifyingKey::<Bn254> {
+        alpha_g1: g1_from_strings(&json.vk_alpha_1)?,
+        beta_g2: g2_from_strings(&json.vk_beta_2)?,
+        gamma_g2: g2_from_strings(&json.vk_gamma_2)?,
+        delta_g2: g2_from_strings(&json.vk_delta_2)?,
+        gamma_abc_g1: json
+            .ic
+            .iter()
+            .map(|p| g1_from_strings(p))
+            .collect::<Result<_, _>>()?,
+    };
+    Ok(ark_groth16::prepare_verifying_key(&vk))
+}
+
+impl TransactProofData {
+    /// Check the proof against its public inputs with `vk`, the prepared
+    /// verifying key of the transaction circuit
+    pub fn verify(&self, vk: &PreparedVerifyingKey<Bn254>) -> Result<(), ProofError> {
+        let proof = self.proof()?;
+        let inputs = self.public_inputs()?;
+        match Groth16::<Bn254>::verify_proof(vk, &proof, &inputs) {
+            Ok(true) => Ok(()),
+            _ => Err(ProofError::Invalid),
+        }
+    }
+
+    /// The proof, undoing the negation of `proof_a`
+    pub fn proof(&self) -> Result<Proof<Bn254>, ProofError> {
+        let bytes = [&self.proof_a[..], &self.proof_b, &self.proof_c].concat();
+        parse_proof(&bytes)
+    }
+
+    /// Public inputs in circuit order: root, publicAmount, extDataHash,
+    /// inputNullifier[2], outputCommitment[2]
+    pub fn public_inputs(&self) -> Result<[Fr; NR_PUBLIC_INPUTS], ProofError> {
+        let scalar = |bytes: &[u8]| {
+            fr_from_be(bytes).ok_or(ProofError::Malformed("public input is not a field element"))
+        };
+        let public_amount = if self.public_amount < 0 {
+            -Fr::from(self.public_amount.unsigned_abs())
+        } else {
+            Fr::from(self.public_amount as u64)
+        };
+        Ok([
+            scalar(&self.root)?,
+            public_amount,
+            scalar(&self.ext_data_hash)?,
+            scalar(&self.nullifier1)?,
+            scalar(&self.nullifier2)?,
+            scalar(&self.commitment1)?,
+            scalar(&self.commitment2)?,
+        ])
+    }
+}
+
+/// Parse a 256-byte proof, undoing the client's negation of `proof_a`
+pub fn parse_proof(bytes: &[u8]) -> Result<Proof<Bn254>, ProofError> {
+    if bytes.len() != 256 {
+        return Err(ProofError::Malformed("proof must be 256 bytes"));
+    }
+    let neg_a =
+        g1_from_be(&bytes[0..64]).ok_or(ProofError::Malformed("proof_a is not a valid point"))?;
+    let b =
+        g2_from_be(&bytes[64..192]).ok_or(ProofError::Malformed("proof_b is not a valid point"))?;
+    let c = g1_from_be(&bytes[192..256])
+        .ok_or(ProofError::Malformed("proof_c is not a valid point"))?;
+    Ok(Proof { a: -neg_a, b, c })
+}
+
+/// Canonical big-endian encoding of a field element, or `None`
+fn canonical<F: PrimeField>(bytes: &[u8]) -> Option<F> {
+    let value = F::from_be_bytes_mod_order(bytes);
+    (bytes.len() == 32 && value.into_bigint().to_bytes_be() == bytes).then_some(value)
+}
+
+pub(crate) fn fr_from_be(bytes: &[u8]) -> Option<Fr> {
+    canonical(bytes)
+}
+
+fn g1_from_be(bytes: &[u8]) -> Option<G1Affine> {
+    valid_g1(G1Affine::new_unchecked(
+        canonical(&bytes[0..32])?,
+        canonical(&bytes[32..64])?,
+    ))
+}
+
+fn g2_from_be(bytes: &[u8]) -> Option<G2Affine> {
+    let x = Fq2::new(canonical(&bytes[32..64])?, canonical(&bytes[0..32])?);
+    let y = Fq2::new(canonical(&bytes[96..128])?, canonical(&bytes[64..96])?);
+    valid_g2(G2Affine::new_unchecked(x, y))
+}
+
+fn valid_g1(point: G1Affine) -> Option<G1Affine> {
+    (point.is_on_curve() && point.is_in_correct_subgroup_assuming_on_curve()).then_some(point)
+}
+
+fn valid_g2(point: G2Affine) -> Option<G2Affine> {
+    (point.is_on_curve() && point.is_in_correct_subgroup_assuming_on_curve()).then_some(point)
+}
+
+fn fq(s: &str) -> Result<Fq, VerifyingKeyError> {
+    Fq::from_str(s).map_err(|_| VerifyingKeyError::FieldElement(s.to_string()))
+}
+
+fn g1_from_strings(p: &[String]) -> Result<G1Affine, VerifyingKeyError> {
+    let [x, y, ..] = p else {
+        return Err(VerifyingKeyError::Point("G1 point needs two coordinates"));
+    };
+    valid_g1(G1Affine::new_unchecked(fq(x)?, fq(y)?))
+        .ok_or(VerifyingKeyError::Point("G1 point is not on the curve"))
+}
+
+fn g2_from_strings(p: &[Vec<String>]) -> Result<G2Affine, VerifyingKeyError> {
+    let [x, y, ..] = p else {
+        return Err(VerifyingKeyError::Point("G2 point needs two coordinates"));
+    };
+    let coord = |c: &[String]| {
+        let [c0, c1, ..] = c else {
+            return Err(VerifyingKeyError::Point("Fq2 element needs two limbs"));
+        };
+        Ok(Fq2::new(fq(c0)?, fq(c1)?))
+    };
+    valid_g2(G2Affine::new_unchecked(coord(x)?, coord(y)?))
+        .ok_or(VerifyingKeyError::Point("G2 point is not on the curve"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The relayer's fixture: a test key with a known trapdoor, so the proof
+    // is simulated rather than generated from the circuit
+    const VK: &str = include_str!("../tests/fixtures/verify/vk.json");
+    const PROOF_HEX: &str = include_str!("../tests/fixtures/verify/proof.hex");
+
+    fn fixture() -> (PreparedVerifyingKey<Bn254>, TransactProofData) {
+        let data: Vec<u8> = (0..PROOF_HEX.trim().len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&PROOF_HEX.trim()[i..i + 2], 16).unwrap())
+            .collect();
+        let proof = TransactProofData::from_instruction_data(&data).unwrap();
+        (verifying_key_from_snarkjs(VK).unwrap(), proof)
+    }
+
+    #[test]
+    fn accepts_known_good_proof() {
+        let (vk, proof) = fixture();
+        assert_eq!(proof.verify(&vk), Ok(()));
+    }
+
+    #[test]
+    fn rejects_corrupted_proof_byte() {
+        let (vk, proof) = fixture();
+
+        // One flipped bit takes proof_a off the curve
+        let mut corrupted = proof.clone();
+        corrupted.proof_a[63] ^= 1;
+        assert!(matches!(
+            corrupted.verify(&vk),
+            Err(ProofError::Malformed(_))
+        ));
+
+        // A valid point, but the wrong one: proof_a not negated
+        let mut unnegated = proof.clone();
+        let a = unnegated.proof().unwrap().a;
+        unnegated.proof_a[32..].copy_from_slice(&a.y.into_bigint().to_bytes_be());
+        assert_eq!(unnegated.verify(&vk), Err(ProofError::Invalid));
+
+        let mut unreduced = proof;
+        unreduced.proof_c[..32].fill(0xff);
+        assert!(matches!(
+            unreduced.verify(&vk),
+            Err(ProofError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_wrong_public_inputs() {
+        let (vk, proof) = fixture();
+
+        // Wrong root
+        let mut wrong_root = proof.clone();
+        wrong_root.root[31] ^= 1;
+        assert_eq!(wrong_root.verify(&vk), Err(ProofError::Invalid));
+
+        // Nullifiers in the wrong order
+        let mut swapped = proof.clone();
+        std::mem::swap(&mut swapped.nullifier1, &mut swapped.nullifier2);
+        assert_eq!(swapped.verify(&vk), Err(ProofError::Invalid));
+
+        let mut amount = proof;
+        amount.public_amount = -amount.public_amount;
+        assert_eq!(amount.verify(&vk), Err(ProofError::Invalid));
+    }
+
+    #[test]
+    fn rejects_bad_verifying_keys() {
+        assert!(matches!(
+            verifying_key_from_snarkjs("{}"),
+            Err(VerifyingKeyError::Json(_))
+        ));
+        let six_inputs = VK.replace("\"nPublic\": 7", "\"nPublic\": 6");
+        assert!(matches!(
+            verifying_key_from_snarkjs(&six_inputs),
+            Err(VerifyingKeyError::PublicInputs(6))
+        ));
+    }
+}
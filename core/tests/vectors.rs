@@ -0,0 +1,176 @@
+//! Golden vectors pinning the note formulas to `circuits/transaction.circom`
+//!
+//! A proof only verifies if the commitments, nullifiers and `extDataHash`
+//! computed here are the ones the circuit computes from the same inputs. A
+//! change to any of them, or to how amounts, keys or addresses are encoded
+//! into field elements, fails these tests instead of producing proofs that
+//! fail constraint satisfaction.
+//!
+//! The public key, commitment, nullifier and dummy-nullifier values are
+//! the circuit's own signals, read from `artifacts/transaction2_js`'s
+//! witness calculator spending these notes:
+//!
+//! ```text
+//! cd scripts && npx tsx circuit-vectors.ts
+//! ```
+//!
+//! The circuit takes `extDataHash` as an input and only hashes the path it
+//! is given, so `extDataHash` and the tree roots are computed from its
+//! formulas instead:
+//!
+//! - `extDataHash = Poseidon(recipientHi, recipientLo, amount, relayerHi, relayerLo, fee)`
+//! - `zeros[i + 1] = Poseidon(zeros[i], zeros[i])`, as `merkleProof.circom` hashes a level
+//!
+//! using a Poseidon whose outputs match circomlib's, as `crypto`'s own
+//! tests check. Don't update an expected value to make a test pass: a
+//! mismatch means the circuit and this crate no longer agree.
+
+use ark_bn254::Fr;
+use privacy_zig_core::circuit::dummy_nullifier;
+use privacy_zig_core::crypto::{fr_to_str, poseidon, sol_mint, spl_mint, str_to_fr, MERKLE_TREE_HEIGHT};
+use privacy_zig_core::{ExtData, MerkleTree, Utxo};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+const PRIVKEY: &str = "4932897425348234597827354123098745612309874561230987456123";
+const PUBKEY: &str = "18279723950900672485979820291930596425376290165896281340336186363028079899503";
+const BLINDING: &str = "1234567890123456789012345678901234567890123456789012345678";
+
+const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+const RECIPIENT: &str = "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM";
+const RELAYER: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+
+/// Roots of empty subtrees of height 0 to 26: `zeros[i + 1] = Poseidon(zeros[i], zeros[i])`
+const ZEROS: [&str; 27] = [
+    "0",
+    "14744269619966411208579211824598458697587494354926760081771325075741142829156",
+    "7423237065226347324353380772367382631490014989348495481811164164159255474657",
+    "11286972368698509976183087595462810875513684078608517520839298933882497716792",
+    "3607627140608796879659380071776844901612302623152076817094415224584923813162",
+    "19712377064642672829441595136074946683621277828620209496774504837737984048981",
+    "20775607673010627194014556968476266066927294572720319469184847051418138353016",
+    "3396914609616007258851405644437304192397291162432396347162513310381425243293",
+    "21551820661461729022865262380882070649935529853313286572328683688269863701601",
+    "6573136701248752079028194407151022595060682063033565181951145966236778420039",
+    "12413880268183407374852357075976609371175688755676981206018884971008854919922",
+    "14271763308400718165336499097156975241954733520325982997864342600795471836726",
+    "20066985985293572387227381049700832219069292839614107140851619262827735677018",
+    "9394776414966240069580838672673694685292165040808226440647796406499139370960",
+    "11331146992410411304059858900317123658895005918277453009197229807340014528524",
+    "15819538789928229930262697811477882737253464456578333862691129291651619515538",
+    "19217088683336594659449020493828377907203207941212636669271704950158751593251",
+    "21035245323335827719745544373081896983162834604456827698288649288827293579666",
+    "6939770416153240137322503476966641397417391950902474480970945462551409848591",
+    "10941962436777715901943463195175331263348098796018438960955633645115732864202",
+    "15019797232609675441998260052101280400536945603062888308240081994073687793470",
+    "11702828337982203149177882813338547876343922920234831094975924378932809409969",
+    "11217067736778784455593535811108456786943573747466706329920902520905755780395",
+    "16072238744996205792852194127671441602062027943016727953216607508365787157389",
+    "17681057402012993898104192736393849603097507831571622013521167331642182653248",
+    "21694045479371014653083846597424257852691458318143380497809004364947786214945",
+    "8163447297445169709687354538480474434591144168767135863541048304198280615192",
+];
+
+fn address(address: &str) -> Pubkey {
+    Pubkey::from_str(address).unwrap()
+}
+
+#[test]
+fn test_pubkey() {
+    let pubkey = poseidon(&[str_to_fr(PRIVKEY).unwrap()]).unwrap();
+    assert_eq!(fr_to_str(&pubkey), PUBKEY);
+}
+
+#[test]
+fn test_sol_note() {
+    let utxo = Utxo::from_values(1_500_000_000, PRIVKEY, PUBKEY, BLINDING, sol_mint()).unwrap();
+    assert_eq!(utxo.mint, "1");
+    assert_eq!(
+        utxo.commitment,
+        "13784777523031912557374644463712261182797061211867069841082060352168045013173"
+    );
+    assert_eq!(
+        fr_to_str(&utxo.compute_nullifier(0).unwrap()),
+        "3584837395260670923893259764751204208141052996106211589703637588272293543081"
+    );
+    assert!(utxo.verify_commitment());
+}
+
+#[test]
+fn test_spl_note() {
+    let mint = spl_mint(&address(USDC));
+    assert_eq!(
+        fr_to_str(&mint),
+        "351564470195712479312889784364378094793935933814098925423924767071335165789"
+    );
+    let utxo = Utxo::from_values(25_000_000, PRIVKEY, PUBKEY, BLINDING, mint).unwrap();
+    assert_eq!(
+        utxo.commitment,
+        "6142361577993096687161246446713768549635526630595664169476972963518461074204"
+    );
+    // A leaf index spanning three bytes
+    assert_eq!(
+        fr_to_str(&utxo.compute_nullifier(1_000_003).unwrap()),
+        "4728716205711840185430681708216274997167589762297978539685928592140315458307"
+    );
+}
+
+#[test]
+fn test_dummy_input_nullifier() {
+    let [privkey, pubkey, blinding] = [PRIVKEY, PUBKEY, "42"].map(|value| str_to_fr(value).unwrap());
+    let nullifier = dummy_nullifier(&privkey, &pubkey, &sol_mint(), &blinding).unwrap();
+    assert_eq!(
+        fr_to_str(&nullifier),
+        "21018460605325899358582148336005609742205722146109413855188723412317858698045"
+    );
+
+    // The same as a zero-amount note's at leaf 0
+    let dummy = Utxo::from_values(0, PRIVKEY, PUBKEY, "42", sol_mint()).unwrap();
+    assert_eq!(dummy.compute_nullifier(0).unwrap(), nullifier);
+}
+
+#[test]
+fn test_ext_data_hash() {
+    let relayed = ExtData { recipient: address(RECIPIENT), relayer: address(RELAYER), fee: 5_000_000 };
+    assert_eq!(
+        fr_to_str(&relayed.hash(1_500_000_000).unwrap()),
+        "3048218211822977952531071913929492422733240184870767113047889390319874273528"
+    );
+
+    let direct = ExtData::direct(address(RECIPIENT), address(RECIPIENT));
+    assert_eq!(
+        fr_to_str(&direct.hash(1_500_000_000).unwrap()),
+        "7489323594536191411865822416229227909451429675160867057449071728196554623202"
+    );
+}
+
+#[test]
+fn test_zero_hashes() {
+    let tree = MerkleTree::new(26).unwrap();
+    let zeros: Vec<String> = tree.zeros().iter().map(fr_to_str).collect();
+    assert_eq!(zeros, ZEROS);
+
+    // The pool's height, whose zeros are cached separately
+    let pool = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+    assert_eq!(pool.zeros().len(), MERKLE_TREE_HEIGHT + 1);
+    assert_eq!(fr_to_str(&pool.root()), ZEROS[MERKLE_TREE_HEIGHT]);
+}
+
+#[test]
+fn test_four_leaf_tree() {
+    let leaves = [1u64, 2, 3, 4].map(Fr::from);
+
+    let mut full = MerkleTree::new(2).unwrap();
+    full.insert_many(&leaves).unwrap();
+    assert_eq!(
+        fr_to_str(&full.root()),
+        "3330844108758711782672220159612173083623710937399719017074673646455206473965"
+    );
+
+    let mut pool = MerkleTree::new(MERKLE_TREE_HEIGHT).unwrap();
+    pool.insert_many(&leaves).unwrap();
+    assert_eq!(
+        fr_to_str(&pool.root()),
+        "9228267802024003504122325259099864541375341725346623052336544141648544733185"
+    );
+}
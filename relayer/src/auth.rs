@@ -0,0 +1,424 @@
+//! API keys and per-key quotas
+//!
+//! With keys configured, every route but `/health`, `/metrics` and `/info`
+//! requires `Authorization: Bearer <key>`, and requests without a known key
+//! get `401`. `/pool/research` keeps its own admin token. Each key may have a
+//! daily quota of accepted withdrawals (UTC days); once it is used up,
+//! `/relay` returns `429` until the next day. Only withdrawals the relayer
+//! accepts count against the quota. Usage is kept in the job database so
+//! quotas hold across restarts.
+//!
+//! A key can also get a discount on the relayer fee, in basis points of the
+//! fee (`10000` relays for free).
+//!
+//! ```toml
+//! [[auth.keys]]
+//! name = "alice"
+//! key = "3f7c0d..."
+//! daily_quota = 50
+//! fee_discount_bps = 5000
+//! ```
+//!
+//! Without any keys the relayer is open to everyone, as before.
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use rusqlite::{params, Connection};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use crate::dedup::unix_now;
+use crate::fee::FEE_DENOMINATOR;
+use crate::metrics;
+
+/// Days of usage kept in the database
+const USAGE_RETENTION_DAYS: u64 = 30;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ApiKey {
+    /// Shown in logs and metrics instead of the key itself
+    pub name: String,
+    pub key: String,
+    /// Accepted withdrawals per UTC day; unlimited when unset
+    #[serde(default)]
+    pub daily_quota: Option<u64>,
+    /// Discount on the relayer fee, in basis points of the fee
+    #[serde(default)]
+    pub fee_discount_bps: u16,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub keys: Vec<ApiKey>,
+}
+
+impl AuthConfig {
+    /// Reject empty, duplicate and overgenerous keys
+    pub fn validate(&self) -> Result<()> {
+        let mut names = HashSet::new();
+        let mut keys = HashSet::new();
+        for key in &self.keys {
+            if key.key.is_empty() {
+                return Err(anyhow!("API key {:?} is empty", key.name));
+            }
+            if !names.insert(&key.name) {
+                return Err(anyhow!("API key name {:?} is used twice", key.name));
+            }
+            if !keys.insert(&key.key) {
+                return Err(anyhow!(
+                    "API key {:?} has the same key as another",
+                    key.name
+                ));
+            }
+            if key.fee_discount_bps as u64 > FEE_DENOMINATOR {
+                return Err(anyhow!(
+                    "Fee discount of {} bps for {:?} exceeds 100%",
+                    key.fee_discount_bps,
+                    key.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether requests need a key
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    fn find(&self, key: &str) -> Option<&ApiKey> {
+        self.keys.iter().find(|k| k.key == key)
+    }
+}
+
+/// Accepted withdrawals per key and day
+pub struct Usage {
+    conn: Mutex<Connection>,
+}
+
+impl Usage {
+    /// Open the usage table in the database at `path`
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS api_key_usage (
+                name TEXT NOT NULL,
+                day INTEGER NOT NULL,
+                count INTEGER NOT NULL,
+                PRIMARY KEY (name, day)
+            );",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Count one use of `name` today, unless it has reached `quota`
+    fn take(&self, name: &str, quota: Option<u64>) -> Result<bool> {
+        if quota == Some(0) {
+            return Ok(false);
+        }
+        let day = today();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM api_key_usage WHERE day < ?1",
+            [day.saturating_sub(USAGE_RETENTION_DAYS)],
+        )?;
+        let changed = conn.execute(
+            "INSERT INTO api_key_usage (name, day, count) VALUES (?1, ?2, 1)
+             ON CONFLICT (name, day) DO UPDATE SET count = count + 1
+             WHERE ?3 IS NULL OR count < ?3",
+            params![name, day, quota],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Give back a use whose request was turned away
+    fn release(&self, name: &str) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE api_key_usage SET count = max(count - 1, 0) WHERE name = ?1 AND day = ?2",
+            params![name, today()],
+        )?;
+        Ok(())
+    }
+
+    /// Uses of `name` today
+    pub fn used_today(&self, name: &str) -> Result<u64> {
+        let conn = self.conn.lock().unwrap();
+        let count = conn.query_row(
+            "SELECT coalesce(sum(count), 0) FROM api_key_usage WHERE name = ?1 AND day = ?2",
+            params![name, today()],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+}
+
+fn today() -> u64 {
+    unix_now() / 86_400
+}
+
+pub struct Auth {
+    config: AuthConfig,
+    usage: Usage,
+}
+
+impl Auth {
+    pub fn new(config: AuthConfig, usage: Usage) -> Self {
+        Self { config, usage }
+    }
+
+    /// The key a request was sent with, or why it was turned away
+    fn authenticate(&self, request: &Request) -> Result<ApiKey, &'static str> {
+        let provided = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        let Some(provided) = provided else {
+            metrics::record_auth_failure("missing_key");
+            return Err("This relayer requires an API key (Authorization: Bearer <key>)");
+        };
+        match self.config.find(provided) {
+            Some(key) => Ok(key.clone()),
+            None => {
+                metrics::record_auth_failure("invalid_key");
+                Err("Invalid API key")
+            }
+        }
+    }
+}
+
+fn reject(status: StatusCode, error: &str) -> Response {
+    (
+        status,
+        Json(serde_json::json!({ "success": false, "error": error })),
+    )
+        .into_response()
+}
+
+/// Check the key and pass it on to the handler as an extension
+async fn require_key(State(auth): State<Arc<Auth>>, mut request: Request, next: Next) -> Response {
+    let key = match auth.authenticate(&request) {
+        Ok(key) => key,
+        Err(error) => return reject(StatusCode::UNAUTHORIZED, error),
+    };
+    metrics::record_api_request(&key.name);
+    request.extensions_mut().insert(key);
+    next.run(request).await
+}
+
+/// Like [`require_key`], and count successful requests against the quota
+async fn require_quota(
+    State(auth): State<Arc<Auth>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let key = match auth.authenticate(&request) {
+        Ok(key) => key,
+        Err(error) => return reject(StatusCode::UNAUTHORIZED, error),
+    };
+    metrics::record_api_request(&key.name);
+
+    match auth.usage.take(&key.name, key.daily_quota) {
+        Ok(true) => {}
+        Ok(false) => {
+            metrics::record_quota_exceeded(&key.name);
+            return reject(
+                StatusCode::TOO_MANY_REQUESTS,
+                &format!(
+                    "Daily quota of {} withdrawals reached; it resets at 00:00 UTC",
+                    key.daily_quota.unwrap_or(0)
+                ),
+            );
+        }
+        Err(e) => {
+            return reject(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Failed to record API key usage: {}", e),
+            )
+        }
+    }
+
+    let name = key.name.clone();
+    request.extensions_mut().insert(key);
+    let response = next.run(request).await;
+    if response.status().is_success() {
+        metrics::record_api_usage(&name);
+    } else if let Err(e) = auth.usage.release(&name) {
+        tracing::error!("Failed to release API key usage: {}", e);
+    }
+    response
+}
+
+/// Merge `open` routes with `protected` ones that need a key and `metered`
+/// ones that also count against its quota; with auth disabled all three are
+/// open
+pub fn apply<S>(
+    open: Router<S>,
+    protected: Router<S>,
+    metered: Router<S>,
+    auth: Arc<Auth>,
+) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    if !auth.config.is_enabled() {
+        return open.merge(protected).merge(metered);
+    }
+    open.merge(protected.route_layer(middleware::from_fn_with_state(auth.clone(), require_key)))
+        .merge(metered.route_layer(middleware::from_fn_with_state(auth, require_quota)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::Path, routing::get, routing::post, Extension};
+    use tower::ServiceExt;
+
+    const CONFIG: &str = r#"
+        [[keys]]
+        name = "alice"
+        key = "alice-key"
+        daily_quota = 2
+        fee_discount_bps = 5000
+
+        [[keys]]
+        name = "bob"
+        key = "bob-key"
+    "#;
+
+    fn temp_db(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("relayer-auth-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    fn app(config: AuthConfig, db: &str) -> Router {
+        let auth = Arc::new(Auth::new(config, Usage::open(db).unwrap()));
+        let open = Router::new().route("/info", get(|| async { "info" }));
+        let protected = Router::new().route(
+            "/status/:id",
+            get(|key: Option<Extension<ApiKey>>| async move {
+                key.map_or("anonymous".to_string(), |Extension(key)| key.name)
+            }),
+        );
+        // `/relay/ok` is accepted, `/relay/bad` turned away
+        let metered = Router::new().route(
+            "/relay/:outcome",
+            post(|Path(outcome): Path<String>| async move {
+                match outcome.as_str() {
+                    "ok" => StatusCode::ACCEPTED,
+                    _ => StatusCode::BAD_REQUEST,
+                }
+            }),
+        );
+        apply(open, protected, metered, auth)
+    }
+
+    async fn send(app: &Router, method: &str, uri: &str, key: Option<&str>) -> StatusCode {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(key) = key {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", key));
+        }
+        let request = request.body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_and_wrong_keys() {
+        let app = app(toml::from_str::<AuthConfig>(CONFIG).unwrap(), ":memory:");
+        assert_eq!(
+            send(&app, "GET", "/status/1", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(&app, "POST", "/relay/ok", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(&app, "GET", "/status/1", Some("carol-key")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(&app, "GET", "/status/1", Some("bob-key")).await,
+            StatusCode::OK
+        );
+        // /info stays public
+        assert_eq!(send(&app, "GET", "/info", None).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn enforces_daily_quota_across_restarts() {
+        let db = temp_db("quota");
+        let config = toml::from_str::<AuthConfig>(CONFIG).unwrap();
+        let app1 = app(config.clone(), &db);
+        assert_eq!(
+            send(&app1, "POST", "/relay/ok", Some("alice-key")).await,
+            StatusCode::ACCEPTED
+        );
+        // Rejected withdrawals don't use up the quota
+        assert_eq!(
+            send(&app1, "POST", "/relay/bad", Some("alice-key")).await,
+            StatusCode::BAD_REQUEST
+        );
+        drop(app1);
+
+        let app2 = app(config, &db);
+        assert_eq!(
+            send(&app2, "POST", "/relay/ok", Some("alice-key")).await,
+            StatusCode::ACCEPTED
+        );
+        assert_eq!(
+            send(&app2, "POST", "/relay/ok", Some("alice-key")).await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        // Other keys and unmetered routes are unaffected; bob has no quota
+        assert_eq!(
+            send(&app2, "GET", "/status/1", Some("alice-key")).await,
+            StatusCode::OK
+        );
+        for _ in 0..5 {
+            assert_eq!(
+                send(&app2, "POST", "/relay/ok", Some("bob-key")).await,
+                StatusCode::ACCEPTED
+            );
+        }
+        assert_eq!(Usage::open(&db).unwrap().used_today("alice").unwrap(), 2);
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    #[tokio::test]
+    async fn passes_everything_through_when_disabled() {
+        let app = app(AuthConfig::default(), ":memory:");
+        assert_eq!(send(&app, "GET", "/status/1", None).await, StatusCode::OK);
+        assert_eq!(
+            send(&app, "POST", "/relay/ok", None).await,
+            StatusCode::ACCEPTED
+        );
+        assert_eq!(
+            send(&app, "POST", "/relay/ok", Some("anything")).await,
+            StatusCode::ACCEPTED
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_key_config() {
+        let validate = |data: &str| toml::from_str::<AuthConfig>(data).unwrap().validate();
+        assert!(validate("[[keys]]\nname = \"a\"\nkey = \"\"\n").is_err());
+        let duplicate = "[[keys]]\nname = \"a\"\nkey = \"k\"\n[[keys]]\nname = \"b\"\nkey = \"k\"\n";
+        assert!(validate(duplicate).is_err());
+        let discount = "[[keys]]\nname = \"a\"\nkey = \"k\"\nfee_discount_bps = 10001\n";
+        assert!(validate(discount).is_err());
+        assert!(validate("").is_ok());
+    }
+}
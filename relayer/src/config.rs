@@ -0,0 +1,631 @@
+//! `relayer.toml` and its environment overrides
+//!
+//! Each setting resolves as environment variable > config file > built-in
+//! default, so a container can override single fields without shipping a
+//! file. The file is named by `--config` or `RELAYER_CONFIG` (formerly
+//! `RELAYER_FEE_CONFIG`); without either, `relayer.toml` in the working
+//! directory is read if it exists. Pool addresses in the file sit between
+//! the `PRIVACY_POOL_*` variables and the network's preset, as in the CLI.
+//!
+//! ```toml
+//! network = "testnet"
+//! rpc_url = "https://api.testnet.solana.com,https://rpc.example.com"
+//! keypair = "~/.config/solana/id.json"   # or RELAYER_KEYPAIR holding the key
+//! listen = "0.0.0.0:3001"
+//! min_balance_sol = 0.05
+//! drain_timeout_secs = 60
+//! tree_account = "4EGnTF2XfKDTBAszzoqQLe4zbmiURkWtkYQGnj99GiJf"
+//!
+//! [compute]
+//! unit_limit = 1400000           # unset: sized by simulation
+//! unit_price = 0                 # or "auto"
+//! ```
+//!
+//! The `[cors]`, `[tls]`, `[fee]`, `[limits]` and `[auth]` tables are
+//! described in their own modules. Everything is checked when the relayer starts, so a typo fails
+//! fast with the setting and variable to fix instead of on the first relay.
+
+use anyhow::{anyhow, Context, Result};
+use privacy_zig_core::keypair::{self, load_keypair, KeypairError};
+use privacy_zig_core::{Network, PoolAddresses, PoolConfig, PriorityFee, COMPUTE_UNIT_LIMIT};
+use serde::Deserialize;
+use solana_sdk::{
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    signature::Keypair,
+};
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::auth::AuthConfig;
+use crate::cors::CorsConfig;
+use crate::fee::{FeePolicy, FEE_DENOMINATOR};
+use crate::limits::LimitsConfig;
+use crate::server::TlsConfig;
+
+const DEFAULT_CONFIG_FILE: &str = "relayer.toml";
+const DEFAULT_KEYPAIR: &str = "~/.config/solana/id.json";
+const DEFAULT_LISTEN: &str = "0.0.0.0:3001";
+
+/// Enough for a few withdrawals: each pays the transaction fee and rent for
+/// two nullifier accounts
+const DEFAULT_MIN_BALANCE_SOL: f64 = 0.05;
+
+/// Long enough for a withdrawal to confirm after a few blockhash retries
+const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ComputeConfig {
+    /// Compute units requested per withdrawal (`RELAYER_COMPUTE_UNIT_LIMIT`);
+    /// unset, each requests what its simulation used plus 10%
+    pub unit_limit: Option<u32>,
+    /// Priority fee in micro-lamports per compute unit, or `auto` for a
+    /// percentile of recent fees on the pool (`RELAYER_PRIORITY_FEE`)
+    pub unit_price: PriorityFee,
+}
+
+/// `relayer.toml` as written; unset fields fall back to the environment
+/// or the defaults
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    network: Option<String>,
+    rpc_url: Option<String>,
+    keypair: Option<String>,
+    listen: Option<String>,
+    min_balance_sol: Option<f64>,
+    drain_timeout_secs: Option<u64>,
+    #[serde(flatten)]
+    pool: PoolAddresses,
+    #[serde(default)]
+    cors: CorsConfig,
+    #[serde(default)]
+    tls: TlsFile,
+    #[serde(default)]
+    compute: ComputeConfig,
+    #[serde(default)]
+    fee: FeePolicy,
+    #[serde(default)]
+    limits: LimitsConfig,
+    #[serde(default)]
+    auth: AuthConfig,
+}
+
+/// `[tls]` as written; both or neither must end up set
+#[derive(Debug, Default, Deserialize)]
+struct TlsFile {
+    cert: Option<String>,
+    key: Option<String>,
+}
+
+/// Resolved and checked relayer settings
+#[derive(Debug, Clone)]
+pub struct RelayerConfig {
+    pub network: Network,
+    /// `RPC_URLS`, in order of preference
+    pub rpc_urls: Vec<String>,
+    pub pool: PoolConfig,
+    /// `RELAYER_KEYPAIR`: a keypair file, or the key itself
+    pub keypair: PathBuf,
+    /// `RELAYER_LISTEN`, or `PORT` on all interfaces
+    pub listen: SocketAddr,
+    /// The relayer refuses to start below this balance
+    /// (`RELAYER_MIN_BALANCE_SOL`)
+    pub min_balance_lamports: u64,
+    pub cors: CorsConfig,
+    /// Serve HTTPS instead of HTTP
+    pub tls: Option<TlsConfig>,
+    /// How long shutdown waits for in-flight withdrawals
+    /// (`RELAYER_DRAIN_TIMEOUT_SECS`)
+    pub drain_timeout: Duration,
+    pub compute: ComputeConfig,
+    pub fee: FeePolicy,
+    pub limits: LimitsConfig,
+    pub auth: AuthConfig,
+}
+
+impl RelayerConfig {
+    /// Read `--config` or `RELAYER_CONFIG`, which must exist, or else
+    /// `relayer.toml` if there is one, and apply the environment
+    pub fn load(explicit: Option<&str>) -> Result<Self> {
+        let named = explicit.map(str::to_string).or_else(|| {
+            ["RELAYER_CONFIG", "RELAYER_FEE_CONFIG"]
+                .iter()
+                .find_map(|key| std::env::var(key).ok().filter(|path| !path.is_empty()))
+        });
+        let path = match named {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|path| path.exists()),
+        };
+        let data = path
+            .as_ref()
+            .map(|path| {
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config {}", path.display()))
+            })
+            .transpose()?;
+        let config = Self::resolve(data.as_deref(), |key| std::env::var(key).ok());
+        match &path {
+            Some(path) => config.with_context(|| format!("Invalid config {}", path.display())),
+            None => config.context("Invalid relayer config"),
+        }
+    }
+
+    /// Apply the variables `env` returns over the file `data`, then check
+    /// the result
+    pub fn resolve(data: Option<&str>, env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let file: ConfigFile = match data {
+            Some(data) => toml::from_str(data)?,
+            None => ConfigFile::default(),
+        };
+        let env = |key: &str| env(key).filter(|value| !value.is_empty());
+
+        let network = match env("RELAYER_NETWORK").or(file.network) {
+            Some(network) => Network::from_str(&network)?,
+            None => Network::Testnet,
+        };
+
+        let rpc_url = env("RPC_URLS").or(file.rpc_url).unwrap_or_default();
+        let mut rpc_urls = split_urls(&rpc_url);
+        if rpc_urls.is_empty() {
+            rpc_urls.push(network.rpc_url().to_string());
+        }
+        if let Some(url) = rpc_urls
+            .iter()
+            .find(|url| !url.starts_with("http://") && !url.starts_with("https://"))
+        {
+            return Err(anyhow!(
+                "Invalid RPC URL {:?} in rpc_url / RPC_URLS: expected http:// or https://",
+                url
+            ));
+        }
+
+        let pool = PoolConfig::with_overrides(network, &file.pool, env)?;
+
+        let keypair = env("RELAYER_KEYPAIR")
+            .or(file.keypair)
+            .unwrap_or_else(|| DEFAULT_KEYPAIR.to_string());
+        let home = env("HOME");
+        let keypair = match keypair::inline_format(&keypair) {
+            Some(_) => PathBuf::from(keypair),
+            None => expand_home(&keypair, home.as_deref()),
+        };
+
+        let listen = match (env("RELAYER_LISTEN"), env("PORT")) {
+            (Some(listen), _) => listen,
+            (None, Some(port)) => format!("0.0.0.0:{}", port),
+            (None, None) => file.listen.unwrap_or_else(|| DEFAULT_LISTEN.to_string()),
+        };
+        let listen = listen.parse().map_err(|_| {
+            anyhow!(
+                "Invalid listen address {:?} in listen / RELAYER_LISTEN / PORT: expected host:port",
+                listen
+            )
+        })?;
+
+        let min_balance_sol = parse_env(env, "RELAYER_MIN_BALANCE_SOL")?
+            .or(file.min_balance_sol)
+            .unwrap_or(DEFAULT_MIN_BALANCE_SOL);
+        if !min_balance_sol.is_finite() || min_balance_sol < 0.0 {
+            return Err(anyhow!(
+                "Invalid min_balance_sol / RELAYER_MIN_BALANCE_SOL {}: expected SOL >= 0",
+                min_balance_sol
+            ));
+        }
+
+        let mut cors = file.cors;
+        if let Some(origins) = env("RELAYER_CORS_ORIGINS") {
+            cors.origins = split_urls(&origins);
+        }
+        cors.validate()?;
+
+        let tls = match (
+            env("RELAYER_TLS_CERT").or(file.tls.cert),
+            env("RELAYER_TLS_KEY").or(file.tls.key),
+        ) {
+            (Some(cert), Some(key)) => Some(TlsConfig {
+                cert: expand_home(&cert, home.as_deref()),
+                key: expand_home(&key, home.as_deref()),
+            }),
+            (None, None) => None,
+            _ => {
+                return Err(anyhow!(
+                    "TLS needs both tls.cert / RELAYER_TLS_CERT and tls.key / RELAYER_TLS_KEY"
+                ))
+            }
+        };
+
+        let drain_timeout_secs = parse_env(env, "RELAYER_DRAIN_TIMEOUT_SECS")?
+            .or(file.drain_timeout_secs)
+            .unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS);
+
+        let mut compute = file.compute;
+        if let Some(limit) = parse_env(env, "RELAYER_COMPUTE_UNIT_LIMIT")? {
+            compute.unit_limit = Some(limit);
+        }
+        if let Some(price) = parse_env(env, "RELAYER_PRIORITY_FEE")? {
+            compute.unit_price = price;
+        }
+        if let Some(limit) = compute.unit_limit {
+            if limit == 0 || limit > COMPUTE_UNIT_LIMIT {
+                return Err(anyhow!(
+                    "Invalid compute.unit_limit / RELAYER_COMPUTE_UNIT_LIMIT {}: expected 1 to {}",
+                    limit,
+                    COMPUTE_UNIT_LIMIT
+                ));
+            }
+        }
+
+        let mut fee = file.fee;
+        if let Some(flat) = parse_env(env, "RELAYER_FEE_LAMPORTS")? {
+            fee.flat_lamports = flat;
+        }
+        if let Some(bps) = parse_env(env, "RELAYER_FEE_BPS")? {
+            fee.bps = bps;
+        }
+        if fee.bps as u64 > FEE_DENOMINATOR {
+            return Err(anyhow!(
+                "Fee of {} bps exceeds 100% (fee.bps / RELAYER_FEE_BPS)",
+                fee.bps
+            ));
+        }
+
+        let mut limits = file.limits;
+        if let Some(rate) = parse_env(env, "RELAYER_RATE_LIMIT")? {
+            limits.requests_per_minute = rate;
+        }
+        if let Some(burst) = parse_env(env, "RELAYER_RATE_BURST")? {
+            limits.burst = burst;
+        }
+
+        file.auth.validate().context("Invalid [auth] config")?;
+
+        Ok(Self {
+            network,
+            rpc_urls,
+            pool,
+            keypair,
+            listen,
+            min_balance_lamports: (min_balance_sol * LAMPORTS_PER_SOL as f64) as u64,
+            cors,
+            tls,
+            drain_timeout: Duration::from_secs(drain_timeout_secs),
+            compute,
+            fee,
+            limits,
+            auth: file.auth,
+        })
+    }
+
+    /// Read the relayer keypair, from its file or from `RELAYER_KEYPAIR`
+    /// holding the key itself
+    pub fn read_keypair(&self) -> Result<Keypair> {
+        let value = self.keypair.to_string_lossy();
+        load_keypair(&value).map_err(|e| match e {
+            KeypairError::Read { .. } => anyhow!(
+                "Failed to read the relayer keypair: {}; set keypair in relayer.toml or \
+                 RELAYER_KEYPAIR, or create one with `solana-keygen new -o {}`",
+                e,
+                value
+            ),
+            e => anyhow!("Invalid relayer keypair: {}", e),
+        })
+    }
+
+    /// Refuse to start with too little SOL to pay for withdrawals
+    pub fn check_balance(&self, address: &Pubkey, lamports: u64) -> Result<()> {
+        if lamports >= self.min_balance_lamports {
+            return Ok(());
+        }
+        let sol = |lamports: u64| lamports as f64 / LAMPORTS_PER_SOL as f64;
+        Err(anyhow!(
+            "Relayer wallet {} has {} SOL, below the minimum of {} SOL it needs to pay for \
+             withdrawals; fund it (e.g. `solana transfer {} 1`) or lower min_balance_sol / \
+             RELAYER_MIN_BALANCE_SOL",
+            address,
+            sol(lamports),
+            sol(self.min_balance_lamports),
+            address
+        ))
+    }
+}
+
+/// Parse the variable `key`, naming it in the error
+fn parse_env<T>(env: impl Fn(&str) -> Option<String>, key: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    env(key)
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|e| anyhow!("Invalid {} {:?}: {}", key, value, e))
+        })
+        .transpose()
+}
+
+/// Split a comma-separated list
+pub fn split_urls(urls: &str) -> Vec<String> {
+    urls.split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn expand_home(path: &str, home: Option<&str>) -> PathBuf {
+    match (path.strip_prefix("~/"), home) {
+        (Some(rest), Some(home)) => Path::new(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signer::Signer;
+    use std::collections::HashMap;
+
+    type Env<'a> = &'a [(&'a str, &'a str)];
+
+    const FILE: &str = r#"
+        rpc_url = "http://file:8899, http://backup:8899"
+        keypair = "/etc/relayer/id.json"
+        listen = "127.0.0.1:4000"
+        tree_account = "4EGnTF2XfKDTBAszzoqQLe4zbmiURkWtkYQGnj99GiJf"
+        pool_vault = "FcuLoWBhZ8bNQRsSgGhH5NCJJbqK5uhHMZR6V21kyTgS"
+
+        [cors]
+        origins = ["https://app.example.com"]
+
+        [tls]
+        cert = "/etc/relayer/cert.pem"
+        key = "/etc/relayer/key.pem"
+
+        [compute]
+        unit_price = "auto"
+
+        [fee]
+        flat_lamports = 5000
+        bps = 10
+
+        [limits]
+        requests_per_minute = 6
+        trust_forwarded_for = true
+    "#;
+
+    fn resolve(data: Option<&str>, env: Env) -> Result<RelayerConfig> {
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        RelayerConfig::resolve(data, |key| env.get(key).cloned())
+    }
+
+    fn error(data: Option<&str>, env: Env) -> String {
+        format!("{:#}", resolve(data, env).unwrap_err())
+    }
+
+    #[test]
+    fn defaults_without_file_or_env() {
+        let config = resolve(None, &[("HOME", "/home/relayer")]).unwrap();
+        assert_eq!(config.rpc_urls, [Network::Testnet.rpc_url()]);
+        assert_eq!(config.pool, PoolConfig::testnet());
+        assert_eq!(
+            config.keypair,
+            Path::new("/home/relayer/.config/solana/id.json")
+        );
+        assert_eq!(config.listen, "0.0.0.0:3001".parse().unwrap());
+        assert_eq!(config.min_balance_lamports, 50_000_000);
+        assert_eq!(config.cors, CorsConfig::default());
+        assert!(config.cors.origins.is_empty());
+        assert_eq!(config.tls, None);
+        assert_eq!(config.drain_timeout, Duration::from_secs(60));
+        assert_eq!(config.compute, ComputeConfig::default());
+        assert_eq!(config.fee, FeePolicy::default());
+        assert_eq!(config.limits, LimitsConfig::default());
+        assert!(!config.auth.is_enabled());
+    }
+
+    #[test]
+    fn file_overrides_defaults() {
+        let config = resolve(Some(FILE), &[]).unwrap();
+        assert_eq!(config.rpc_urls, ["http://file:8899", "http://backup:8899"]);
+        assert_eq!(config.keypair, Path::new("/etc/relayer/id.json"));
+        assert_eq!(config.listen, "127.0.0.1:4000".parse().unwrap());
+        assert_eq!(config.cors.origins, ["https://app.example.com"]);
+        assert_eq!(config.cors.methods, CorsConfig::default().methods);
+        let tls = config.tls.unwrap();
+        assert_eq!(tls.cert, Path::new("/etc/relayer/cert.pem"));
+        assert_eq!(tls.key, Path::new("/etc/relayer/key.pem"));
+        assert_eq!(
+            config.pool.pool_vault.to_string(),
+            "FcuLoWBhZ8bNQRsSgGhH5NCJJbqK5uhHMZR6V21kyTgS"
+        );
+        assert_eq!(config.pool.program_id, PoolConfig::testnet().program_id);
+        assert_eq!(config.compute.unit_price, PriorityFee::Auto);
+        assert_eq!(config.compute.unit_limit, None);
+        assert_eq!(
+            config.fee,
+            FeePolicy {
+                flat_lamports: 5_000,
+                bps: 10
+            }
+        );
+        assert_eq!(config.limits.requests_per_minute, 6);
+        assert!(config.limits.trust_forwarded_for);
+        assert_eq!(config.limits.burst, LimitsConfig::default().burst);
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        let vault = Pubkey::new_unique().to_string();
+        let config = resolve(
+            Some(FILE),
+            &[
+                ("RPC_URLS", "http://env:8899"),
+                ("RELAYER_KEYPAIR", "/run/secrets/id.json"),
+                ("PORT", "5000"),
+                ("RELAYER_CORS_ORIGINS", "*"),
+                ("RELAYER_TLS_KEY", "/run/secrets/key.pem"),
+                ("RELAYER_DRAIN_TIMEOUT_SECS", "5"),
+                ("PRIVACY_POOL_POOL_VAULT", vault.as_str()),
+                ("RELAYER_PRIORITY_FEE", "2500"),
+                ("RELAYER_COMPUTE_UNIT_LIMIT", "300000"),
+                ("RELAYER_FEE_BPS", "20"),
+                ("RELAYER_RATE_BURST", "2"),
+                // Empty variables are ignored
+                ("RELAYER_FEE_LAMPORTS", ""),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.rpc_urls, ["http://env:8899"]);
+        assert_eq!(config.keypair, Path::new("/run/secrets/id.json"));
+        assert_eq!(config.listen, "0.0.0.0:5000".parse().unwrap());
+        assert!(config.cors.allows_any_origin());
+        assert_eq!(config.tls.unwrap().key, Path::new("/run/secrets/key.pem"));
+        assert_eq!(config.drain_timeout, Duration::from_secs(5));
+        assert_eq!(config.pool.pool_vault.to_string(), vault);
+        assert_eq!(
+            config.pool.tree_account.to_string(),
+            "4EGnTF2XfKDTBAszzoqQLe4zbmiURkWtkYQGnj99GiJf"
+        );
+        assert_eq!(config.compute.unit_price, PriorityFee::Fixed(2500));
+        assert_eq!(config.compute.unit_limit, Some(300_000));
+        assert_eq!(
+            config.fee,
+            FeePolicy {
+                flat_lamports: 5_000,
+                bps: 20
+            }
+        );
+        assert_eq!(
+            (config.limits.requests_per_minute, config.limits.burst),
+            (6, 2)
+        );
+
+        // RELAYER_LISTEN wins over PORT
+        let config = resolve(
+            None,
+            &[("RELAYER_LISTEN", "127.0.0.1:6000"), ("PORT", "5000")],
+        )
+        .unwrap();
+        assert_eq!(config.listen, "127.0.0.1:6000".parse().unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let cases: &[(Option<&str>, Env, &str)] = &[
+            (Some("tree_account = \"not-a-key\""), &[], "tree_account"),
+            (
+                None,
+                &[("PRIVACY_POOL_FEE_RECIPIENT", "nope")],
+                "PRIVACY_POOL_FEE_RECIPIENT",
+            ),
+            (
+                None,
+                &[("RELAYER_NETWORK", "mainnet")],
+                "PRIVACY_POOL_PROGRAM_ID",
+            ),
+            (Some("rpc_url = \"localhost:8899\""), &[], "RPC_URLS"),
+            (Some("listen = \"3001\""), &[], "RELAYER_LISTEN"),
+            (None, &[("PORT", "http")], "PORT"),
+            (
+                None,
+                &[("RELAYER_MIN_BALANCE_SOL", "-1")],
+                "RELAYER_MIN_BALANCE_SOL",
+            ),
+            (
+                Some("[cors]\norigins = [\"*\", \"https://a.example\"]"),
+                &[],
+                "cors.origins",
+            ),
+            (
+                None,
+                &[("RELAYER_CORS_ORIGINS", "app.example.com")],
+                "RELAYER_CORS_ORIGINS",
+            ),
+            (Some("[tls]\ncert = \"cert.pem\""), &[], "RELAYER_TLS_KEY"),
+            (Some("[compute]\nunit_limit = 2000000"), &[], "unit_limit"),
+            (Some("[compute]\nunit_price = \"fast\""), &[], "unit_price"),
+            (
+                None,
+                &[("RELAYER_PRIORITY_FEE", "-5")],
+                "RELAYER_PRIORITY_FEE",
+            ),
+            (None, &[("RELAYER_FEE_BPS", "10001")], "RELAYER_FEE_BPS"),
+            (
+                None,
+                &[("RELAYER_RATE_LIMIT", "fast")],
+                "RELAYER_RATE_LIMIT",
+            ),
+            (Some("[fee]\nbps = -1"), &[], "bps"),
+            (
+                Some("[[auth.keys]]\nname = \"a\"\nkey = \"\""),
+                &[],
+                "[auth]",
+            ),
+        ];
+        for (data, env, expected) in cases {
+            let err = error(*data, env);
+            assert!(err.contains(expected), "{:?}: {}", expected, err);
+        }
+    }
+
+    #[test]
+    fn checks_keypair_and_balance() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("relayer-keypair-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let env = [("RELAYER_KEYPAIR", path.to_str().unwrap())];
+        let config = resolve(None, &env).unwrap();
+
+        let err = format!("{:#}", config.read_keypair().unwrap_err());
+        assert!(err.contains("RELAYER_KEYPAIR"), "{}", err);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(config
+            .read_keypair()
+            .unwrap_err()
+            .to_string()
+            .contains("does not hold a keypair"));
+
+        let keypair = Keypair::new();
+        std::fs::write(
+            &path,
+            serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(config.read_keypair().unwrap().pubkey(), keypair.pubkey());
+        std::fs::remove_file(&path).unwrap();
+
+        // RELAYER_KEYPAIR may hold the key itself, which errors never echo
+        let base58 = keypair.to_base58_string();
+        let inline = resolve(None, &[("RELAYER_KEYPAIR", &base58)]).unwrap();
+        assert_eq!(inline.read_keypair().unwrap().pubkey(), keypair.pubkey());
+        let truncated = resolve(None, &[("RELAYER_KEYPAIR", &base58[..60])]).unwrap();
+        let err = truncated.read_keypair().unwrap_err().to_string();
+        assert!(!err.contains(&base58[..20]), "{}", err);
+
+        assert!(config.check_balance(&keypair.pubkey(), 50_000_000).is_ok());
+        let err = config
+            .check_balance(&keypair.pubkey(), 1_000)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("below the minimum of 0.05 SOL"), "{}", err);
+    }
+
+    #[test]
+    fn splits_comma_separated_urls() {
+        assert_eq!(
+            split_urls("http://a:8899, http://b:8899,"),
+            ["http://a:8899", "http://b:8899"]
+        );
+        assert!(split_urls("").is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+//! Which browser origins may call the API
+//!
+//! Browsers send an `Origin` header with cross-site requests; the CLI and
+//! other non-browser clients don't, and are never affected. A request whose
+//! origin isn't listed in `[cors]` is rejected with `403`, logged and
+//! counted in `relayer_cors_rejections_total`. No origin is allowed unless
+//! configured, so a relayer doesn't pay for withdrawals from arbitrary
+//! websites by accident.
+//!
+//! ```toml
+//! [cors]
+//! origins = ["https://app.example.com"]   # or ["*"] for any
+//! methods = ["GET", "POST"]
+//! headers = ["content-type", "authorization"]
+//! ```
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{Request, State},
+    http::{header::ORIGIN, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+use tracing::warn;
+
+use crate::metrics;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins such as `https://app.example.com`, or `*` for any
+    /// (`RELAYER_CORS_ORIGINS`)
+    pub origins: Vec<String>,
+    /// Methods browsers may use, or `*` for any
+    pub methods: Vec<String>,
+    /// Request headers browsers may send, or `*` for any
+    pub headers: Vec<String>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            origins: Vec::new(),
+            methods: vec!["GET".to_string(), "POST".to_string()],
+            headers: vec!["content-type".to_string(), "authorization".to_string()],
+        }
+    }
+}
+
+impl CorsConfig {
+    pub fn validate(&self) -> Result<()> {
+        for (name, values) in [
+            ("origins", &self.origins),
+            ("methods", &self.methods),
+            ("headers", &self.headers),
+        ] {
+            if values.iter().any(|v| v == "*") && values.len() > 1 {
+                return Err(anyhow!(
+                    "cors.{} lists \"*\" with other values; use one or the other",
+                    name
+                ));
+            }
+        }
+        for origin in self.origins.iter().filter(|o| *o != "*") {
+            let host = origin
+                .strip_prefix("https://")
+                .or_else(|| origin.strip_prefix("http://"));
+            if !matches!(host, Some(host) if !host.is_empty() && !host.contains('/'))
+                || HeaderValue::from_str(origin).is_err()
+            {
+                return Err(anyhow!(
+                    "Invalid origin {:?} in cors.origins / RELAYER_CORS_ORIGINS: expected \
+                     scheme://host[:port] with no path, e.g. https://app.example.com",
+                    origin
+                ));
+            }
+        }
+        for method in self.methods.iter().filter(|m| *m != "*") {
+            parse_method(method)
+                .map_err(|_| anyhow!("Invalid method {:?} in cors.methods", method))?;
+        }
+        for header in self.headers.iter().filter(|h| *h != "*") {
+            HeaderName::from_bytes(header.as_bytes())
+                .map_err(|_| anyhow!("Invalid header {:?} in cors.headers", header))?;
+        }
+        Ok(())
+    }
+
+    pub fn allows_any_origin(&self) -> bool {
+        self.origins.iter().any(|origin| origin == "*")
+    }
+
+    fn allows(&self, origin: &HeaderValue) -> bool {
+        self.allows_any_origin()
+            || self
+                .origins
+                .iter()
+                .any(|allowed| allowed.as_bytes() == origin.as_bytes())
+    }
+
+    /// Answers preflight requests and adds the `Access-Control-*` headers;
+    /// the config must have passed [`Self::validate`]
+    fn layer(&self) -> CorsLayer {
+        let any = |values: &[String]| values.iter().any(|v| v == "*");
+        let origins = if any(&self.origins) {
+            AllowOrigin::any()
+        } else {
+            AllowOrigin::list(self.origins.iter().map(|o| o.parse().unwrap()))
+        };
+        let methods = if any(&self.methods) {
+            AllowMethods::any()
+        } else {
+            AllowMethods::list(self.methods.iter().map(|m| parse_method(m).unwrap()))
+        };
+        let headers = if any(&self.headers) {
+            AllowHeaders::any()
+        } else {
+            AllowHeaders::list(
+                self.headers
+                    .iter()
+                    .map(|h| HeaderName::from_bytes(h.as_bytes()).unwrap()),
+            )
+        };
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers)
+    }
+}
+
+fn parse_method(method: &str) -> Result<Method, axum::http::method::InvalidMethod> {
+    Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+}
+
+async fn check_origin(
+    State(config): State<Arc<CorsConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match request.headers().get(ORIGIN) {
+        Some(origin) if !config.allows(origin) => {
+            let origin = String::from_utf8_lossy(origin.as_bytes()).into_owned();
+            warn!(
+                "Rejected {} {} from origin {}",
+                request.method(),
+                request.uri().path(),
+                origin
+            );
+            metrics::record_cors_rejection();
+            (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({
+                    "success": false,
+                    "error": format!("Origin {} is not allowed to use this relayer", origin),
+                })),
+            )
+                .into_response()
+        }
+        _ => next.run(request).await,
+    }
+}
+
+/// Reject requests from origins not in `config` and answer CORS preflight
+/// requests for the rest, before any other layer sees them
+pub fn apply<S>(router: Router<S>, config: &CorsConfig) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router
+        .layer(config.layer())
+        .layer(middleware::from_fn_with_state(
+            Arc::new(config.clone()),
+            check_origin,
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::header::{
+            ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+            ACCESS_CONTROL_REQUEST_METHOD,
+        },
+        routing::post,
+    };
+    use tower::ServiceExt;
+
+    const APP_ORIGIN: &str = "https://app.example.com";
+
+    fn app(origins: &[&str]) -> Router {
+        let config = CorsConfig {
+            origins: origins.iter().map(|o| o.to_string()).collect(),
+            ..CorsConfig::default()
+        };
+        config.validate().unwrap();
+        apply(
+            Router::new().route("/relay", post(|| async { "queued" })),
+            &config,
+        )
+    }
+
+    async fn send(app: &Router, method: Method, origin: Option<&str>) -> Response {
+        let mut request = Request::builder().method(method.clone()).uri("/relay");
+        if let Some(origin) = origin {
+            request = request.header(ORIGIN, origin);
+        }
+        if method == Method::OPTIONS {
+            request = request.header(ACCESS_CONTROL_REQUEST_METHOD, "POST");
+        }
+        app.clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn allows_preflight_from_listed_origin() {
+        let app = app(&[APP_ORIGIN]);
+        let response = send(&app, Method::OPTIONS, Some(APP_ORIGIN)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], APP_ORIGIN);
+        let methods = response.headers()[ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .unwrap();
+        assert!(methods.contains("POST"), "{}", methods);
+
+        let response = send(&app, Method::POST, Some(APP_ORIGIN)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], APP_ORIGIN);
+    }
+
+    #[tokio::test]
+    async fn rejects_other_origins() {
+        let app = app(&[APP_ORIGIN]);
+        for method in [Method::OPTIONS, Method::POST] {
+            let response = send(&app, method, Some("https://evil.example")).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            assert!(response
+                .headers()
+                .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+                .is_none());
+        }
+
+        // Clients that aren't browsers send no origin
+        let response = send(&app, Method::POST, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn denies_every_origin_unless_configured() {
+        let app = app(&[]);
+        let response = send(&app, Method::OPTIONS, Some(APP_ORIGIN)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let app = self::app(&["*"]);
+        let response = send(&app, Method::OPTIONS, Some("https://any.example")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        let invalid = |config: CorsConfig| config.validate().unwrap_err().to_string();
+        let origins = |origins: &[&str]| CorsConfig {
+            origins: origins.iter().map(|o| o.to_string()).collect(),
+            ..CorsConfig::default()
+        };
+        assert!(invalid(origins(&["*", APP_ORIGIN])).contains("cors.origins"));
+        assert!(invalid(origins(&["app.example.com"])).contains("app.example.com"));
+        assert!(invalid(origins(&["https://app.example.com/"])).contains("no path"));
+        assert!(invalid(CorsConfig {
+            methods: vec!["GET POST".to_string()],
+            ..CorsConfig::default()
+        })
+        .contains("cors.methods"));
+        assert!(invalid(CorsConfig {
+            headers: vec!["x header".to_string()],
+            ..CorsConfig::default()
+        })
+        .contains("cors.headers"));
+        assert!(CorsConfig::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,177 @@
+//! Rejecting duplicate withdrawals before they cost a fee
+//!
+//! A withdrawal's nullifiers are claimed when its request is accepted and
+//! kept for `RELAYER_DEDUP_TTL_SECS` once it has been broadcast, so a second
+//! request with either nullifier is turned away with `409` instead of being
+//! sent again. A claim is released if the request fails before broadcast, so
+//! the client can fix it and retry.
+//!
+//! The set is rebuilt from the job database on startup (see
+//! [`restore`](RecentNullifiers::restore)), so a restart doesn't reopen the
+//! window.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub type Nullifier = [u8; 32];
+
+pub struct RecentNullifiers {
+    ttl: Duration,
+    /// Nullifier -> expiry (unix seconds)
+    entries: Mutex<HashMap<Nullifier, u64>>,
+}
+
+/// Nullifiers held by an in-flight request; released on drop unless kept
+pub struct Claim {
+    set: Arc<RecentNullifiers>,
+    nullifiers: Vec<Nullifier>,
+    kept: bool,
+}
+
+impl Claim {
+    /// Hold the nullifiers for the full TTL; call once the transaction may
+    /// have been broadcast
+    pub fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if !self.kept {
+            self.set.forget(&self.nullifiers);
+        }
+    }
+}
+
+impl RecentNullifiers {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Hold nullifiers that were relayed at the given unix times, e.g.
+    /// before a restart; ones older than the TTL are skipped
+    pub fn restore(&self, relayed: impl IntoIterator<Item = (Nullifier, u64)>) {
+        let now = unix_now();
+        let mut entries = self.entries.lock().unwrap();
+        for (nullifier, relayed_at) in relayed {
+            let expires_at = relayed_at + self.ttl.as_secs();
+            if expires_at > now {
+                entries.insert(nullifier, expires_at);
+            }
+        }
+    }
+
+    /// Claim `nullifiers` for a new request, or `None` if any of them was
+    /// relayed recently or is in flight. Check-and-insert is atomic, so of
+    /// two identical concurrent requests exactly one gets the claim.
+    pub fn claim(self: &Arc<Self>, nullifiers: &[Nullifier]) -> Option<Claim> {
+        self.claim_at(nullifiers, unix_now())
+    }
+
+    fn claim_at(self: &Arc<Self>, nullifiers: &[Nullifier], now: u64) -> Option<Claim> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, expires_at| *expires_at > now);
+        if nullifiers.iter().any(|n| entries.contains_key(n)) {
+            return None;
+        }
+        let expires_at = now + self.ttl.as_secs();
+        for nullifier in nullifiers {
+            entries.insert(*nullifier, expires_at);
+        }
+        Some(Claim {
+            set: self.clone(),
+            nullifiers: nullifiers.to_vec(),
+            kept: false,
+        })
+    }
+
+    /// Stop holding `nullifiers`, e.g. when a job turned out not to land
+    pub fn forget(&self, nullifiers: &[Nullifier]) {
+        let mut entries = self.entries.lock().unwrap();
+        for nullifier in nullifiers {
+            entries.remove(nullifier);
+        }
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+
+    const TTL: Duration = Duration::from_secs(600);
+
+    #[test]
+    fn concurrent_duplicates_relay_once() {
+        let set = Arc::new(RecentNullifiers::new(TTL));
+        let barrier = Arc::new(Barrier::new(8));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let (set, barrier) = (set.clone(), barrier.clone());
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    match set.claim(&[[1; 32], [2; 32]]) {
+                        Some(claim) => {
+                            claim.keep();
+                            true
+                        }
+                        None => false,
+                    }
+                })
+            })
+            .collect();
+        let relayed = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|claimed| *claimed)
+            .count();
+        assert_eq!(relayed, 1);
+    }
+
+    #[test]
+    fn rejects_overlapping_nullifier() {
+        let set = Arc::new(RecentNullifiers::new(TTL));
+        set.claim(&[[1; 32], [2; 32]]).unwrap().keep();
+        assert!(set.claim(&[[3; 32], [2; 32]]).is_none());
+        assert!(set.claim(&[[3; 32], [4; 32]]).is_some());
+    }
+
+    #[test]
+    fn released_on_drop_unless_kept() {
+        let set = Arc::new(RecentNullifiers::new(TTL));
+        let claim = set.claim(&[[1; 32], [2; 32]]).unwrap();
+        assert!(set.claim(&[[1; 32], [2; 32]]).is_none());
+        drop(claim);
+        assert!(set.claim(&[[1; 32], [2; 32]]).is_some());
+    }
+
+    #[test]
+    fn expires_after_ttl() {
+        let set = Arc::new(RecentNullifiers::new(TTL));
+        set.claim_at(&[[1; 32], [2; 32]], 1_000).unwrap().keep();
+        assert!(set.claim_at(&[[1; 32], [2; 32]], 1_599).is_none());
+        assert!(set.claim_at(&[[1; 32], [2; 32]], 1_600).is_some());
+    }
+
+    #[test]
+    fn restored_after_restart() {
+        let set = Arc::new(RecentNullifiers::new(TTL));
+        let now = unix_now();
+        set.restore([([1; 32], now - 10), ([3; 32], now - 601)]);
+        assert!(set.claim(&[[2; 32], [1; 32]]).is_none());
+        // Relayed longer than the TTL ago
+        assert!(set.claim(&[[3; 32], [4; 32]]).is_some());
+    }
+}
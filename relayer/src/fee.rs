@@ -0,0 +1,230 @@
+//! Relayer fee quoting and enforcement
+//!
+//! The program pays `withdrawal_fee_rate` basis points (from the
+//! `global_config` account) of every withdrawal to the `fee_recipient`
+//! account of the transact instruction, which the relayer fills in with its
+//! own fee address. The fee is therefore a function of `public_amount`, a
+//! public input of the proof, and can't be changed without invalidating it.
+//!
+//! The relayer's [`FeePolicy`] says how much of that fee it requires: a flat
+//! amount plus basis points of the withdrawal. SPL token withdrawals pay the
+//! fee in tokens, to the fee address's token account, so only the basis
+//! points apply to them.
+
+use anyhow::{anyhow, Result};
+pub use privacy_zig_core::global_config::{fee_for, FEE_DENOMINATOR};
+use privacy_zig_core::global_config::GlobalConfig;
+use privacy_zig_core::instruction::{
+    FEE_RECIPIENT_ACCOUNT, SPL_FEE_RECIPIENT_ACCOUNT, TRANSACT_SPL_DISCRIMINATOR,
+};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+
+/// What the relayer charges per withdrawal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeePolicy {
+    /// Charged on every withdrawal, in lamports
+    #[serde(default)]
+    pub flat_lamports: u64,
+    /// Charged on top, in basis points of the withdrawal amount
+    #[serde(default)]
+    pub bps: u16,
+}
+
+impl FeePolicy {
+    /// The policy with both parts reduced by `discount_bps` of themselves
+    pub fn discounted(&self, discount_bps: u16) -> Self {
+        let keep = FEE_DENOMINATOR.saturating_sub(discount_bps as u64);
+        Self {
+            flat_lamports: (self.flat_lamports as u128 * keep as u128 / FEE_DENOMINATOR as u128) as u64,
+            bps: (self.bps as u64 * keep / FEE_DENOMINATOR) as u16,
+        }
+    }
+
+    /// The policy for SPL token withdrawals, which leaves out the flat fee:
+    /// it is in lamports, and the withdrawal pays in tokens
+    pub fn for_token(&self) -> Self {
+        Self { flat_lamports: 0, ..*self }
+    }
+
+    /// Fee the relayer requires for a withdrawal of `amount` lamports
+    pub fn required_fee(&self, amount: u64) -> u64 {
+        self.flat_lamports.saturating_add(fee_for(amount, self.bps))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeeQuote {
+    /// Flat part of the relayer fee, in lamports
+    pub flat_fee_lamports: u64,
+    /// Proportional part of the relayer fee, in basis points
+    pub fee_bps: u16,
+    /// On-chain withdrawal fee rate in basis points
+    pub withdrawal_fee_rate_bps: u16,
+    /// Account the program pays the fee to
+    pub fee_recipient: String,
+    /// Smallest withdrawal whose on-chain fee covers the relayer fee, if any
+    pub min_withdrawal_lamports: Option<u64>,
+}
+
+/// Read `withdrawal_fee_rate` from the global config account data
+pub fn withdrawal_fee_rate(config_data: &[u8]) -> Result<u16> {
+    let config = GlobalConfig::parse(config_data).map_err(|e| anyhow!("{}", e))?;
+    Ok(config.withdrawal_fee_rate)
+}
+
+/// Smallest withdrawal whose on-chain fee covers the policy
+pub fn min_withdrawal(policy: &FeePolicy, rate_bps: u16) -> Option<u64> {
+    if policy.flat_lamports == 0 && policy.bps <= rate_bps {
+        return Some(0);
+    }
+    if rate_bps <= policy.bps {
+        return None;
+    }
+    // amount * (rate - bps) / 10_000 >= flat, give or take the rounding of
+    // both fees, which the loops below correct
+    let covers = |amount| fee_for(amount, rate_bps) >= policy.required_fee(amount);
+    let margin = (rate_bps - policy.bps) as u128;
+    let estimate = (policy.flat_lamports as u128 * FEE_DENOMINATOR as u128).div_ceil(margin);
+    let mut amount = u64::try_from(estimate).ok()?;
+    while !covers(amount) {
+        amount = amount.checked_add(1)?;
+    }
+    while amount > 0 && covers(amount - 1) {
+        amount -= 1;
+    }
+    Some(amount)
+}
+
+/// Check that a withdrawal pays at least the policy's fee, returning the fee
+pub fn check_withdrawal_fee(public_amount: i64, rate_bps: u16, policy: &FeePolicy) -> Result<u64, String> {
+    if public_amount >= 0 {
+        return Err("The relayer only submits withdrawals (negative public_amount)".to_string());
+    }
+    let amount = public_amount.unsigned_abs();
+    let fee = fee_for(amount, rate_bps);
+    let required = policy.required_fee(amount);
+    if fee < required {
+        return Err(format!(
+            "Withdrawal pays a {} lamport fee, below the relayer fee of {} lamports \
+             ({} lamports + {} bps)",
+            fee, required, policy.flat_lamports, policy.bps
+        ));
+    }
+    Ok(fee)
+}
+
+/// Check that the instruction pays the fee to `expected`, the fee address
+/// or, for `transact_spl`, its token account
+pub fn check_fee_recipient(ix: &Instruction, expected: &Pubkey) -> Result<(), String> {
+    let index = if ix.data.starts_with(&TRANSACT_SPL_DISCRIMINATOR) {
+        SPL_FEE_RECIPIENT_ACCOUNT
+    } else {
+        FEE_RECIPIENT_ACCOUNT
+    };
+    match ix.accounts.get(index) {
+        Some(meta) if meta.pubkey == *expected && meta.is_writable => Ok(()),
+        Some(meta) => Err(format!(
+            "Instruction pays the fee to {}, not the relayer fee address {}",
+            meta.pubkey, expected
+        )),
+        None => Err("Instruction has no fee recipient account".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use privacy_zig_core::spl::associated_token_address;
+    use privacy_zig_core::{
+        transact_instruction_from_data, transact_spl_instruction_from_data, PoolConfig, SplPool,
+        TRANSACT_DISCRIMINATOR,
+    };
+
+    const RATE_BPS: u16 = 25;
+
+    fn policy() -> FeePolicy {
+        FeePolicy { flat_lamports: 5_000, bps: 10 }
+    }
+
+    #[test]
+    fn exact_fee_is_accepted() {
+        // 0.25% of 1 SOL pays 2_500_000, exactly 1_500_000 + 0.10%
+        let amount: i64 = 1_000_000_000;
+        let exact = FeePolicy { flat_lamports: 2_500_000 - 1_000_000, bps: 10 };
+        assert_eq!(check_withdrawal_fee(-amount, RATE_BPS, &exact), Ok(2_500_000));
+    }
+
+    #[test]
+    fn overpaying_withdrawal_is_accepted() {
+        assert_eq!(check_withdrawal_fee(-1_000_000_000, RATE_BPS, &policy()), Ok(2_500_000));
+    }
+
+    #[test]
+    fn underpaying_withdrawal_is_rejected() {
+        // 0.25% of 1_000_000 is 2_500, short of 5_000 + 1_000
+        let err = check_withdrawal_fee(-1_000_000, RATE_BPS, &policy()).unwrap_err();
+        assert!(err.contains("below the relayer fee of 6000 lamports"));
+        // Deposits are never relayed
+        assert!(check_withdrawal_fee(1_000_000, RATE_BPS, &policy()).is_err());
+    }
+
+    #[test]
+    fn min_withdrawal_covers_policy() {
+        let amount = min_withdrawal(&policy(), RATE_BPS).unwrap();
+        assert!(check_withdrawal_fee(-(amount as i64), RATE_BPS, &policy()).is_ok());
+        assert!(check_withdrawal_fee(-(amount as i64) + 1, RATE_BPS, &policy()).is_err());
+        // A policy above the on-chain rate can never be met
+        assert_eq!(min_withdrawal(&FeePolicy { flat_lamports: 0, bps: 30 }, RATE_BPS), None);
+        assert_eq!(min_withdrawal(&FeePolicy::default(), 0), Some(0));
+    }
+
+    #[test]
+    fn discount_scales_both_parts() {
+        assert_eq!(policy().discounted(5_000), FeePolicy { flat_lamports: 2_500, bps: 5 });
+        assert_eq!(policy().discounted(10_000), FeePolicy::default());
+        assert_eq!(policy().discounted(0), policy());
+        assert_eq!(policy().for_token(), FeePolicy { flat_lamports: 0, bps: 10 });
+    }
+
+    #[test]
+    fn fee_must_go_to_relayer() {
+        let config = PoolConfig::testnet();
+        let mut data = vec![0u8; 464];
+        data[0..8].copy_from_slice(&TRANSACT_DISCRIMINATOR);
+        let signer = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+
+        let ix = transact_instruction_from_data(&config, data.clone(), &signer, &recipient, &config.fee_recipient)
+            .unwrap();
+        assert!(check_fee_recipient(&ix, &config.fee_recipient).is_ok());
+
+        let ix = transact_instruction_from_data(&config, data, &signer, &recipient, &recipient).unwrap();
+        assert!(check_fee_recipient(&ix, &config.fee_recipient).is_err());
+    }
+
+    #[test]
+    fn token_fee_must_go_to_relayer_token_account() {
+        let config = PoolConfig::testnet();
+        let pool = SplPool {
+            mint: Pubkey::new_unique(),
+            tree_account: Pubkey::new_unique(),
+            token_pool: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+        };
+        let mut data = vec![0u8; 464];
+        data[0..8].copy_from_slice(&TRANSACT_DISCRIMINATOR);
+        let signer = Pubkey::new_unique();
+        let recipient = associated_token_address(&Pubkey::new_unique(), &pool.mint);
+        let fee_account = associated_token_address(&config.fee_recipient, &pool.mint);
+
+        let ix = transact_spl_instruction_from_data(&config, &pool, data.clone(), &signer, &recipient, &fee_account)
+            .unwrap();
+        assert!(check_fee_recipient(&ix, &fee_account).is_ok());
+        // The fee address itself holds no tokens
+        assert!(check_fee_recipient(&ix, &config.fee_recipient).is_err());
+
+        let ix = transact_spl_instruction_from_data(&config, &pool, data, &signer, &recipient, &recipient).unwrap();
+        assert!(check_fee_recipient(&ix, &fee_account).is_err());
+    }
+}
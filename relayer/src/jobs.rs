@@ -0,0 +1,498 @@
+//! Relay jobs and their status
+//!
+//! Confirming a withdrawal can take longer than most reverse proxies keep a
+//! request open, so `/relay` only validates the request and queues it. A
+//! single worker sends queued jobs in order, and clients poll
+//! `GET /status/:job_id` until the job is `confirmed` or `failed`. Finished
+//! jobs are kept for `RELAYER_JOB_RETENTION_SECS` so a client that lost its
+//! connection can still look up the outcome.
+//!
+//! Jobs live in a SQLite database (`RELAYER_DB`). Every attempt's signature
+//! is recorded before it is sent, so after a restart jobs that were sent but
+//! not confirmed can be finalized from their signatures (see [`resolve`])
+//! instead of being lost or sent again.
+
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::error;
+
+use crate::dedup::{unix_now, Claim, Nullifier};
+use crate::shutdown::InFlight;
+
+/// Schema changes, applied in order; `PRAGMA user_version` counts the ones
+/// already applied
+const MIGRATIONS: &[&str] = &["CREATE TABLE jobs (
+        id TEXT PRIMARY KEY,
+        request_hash TEXT NOT NULL,
+        nullifier1 TEXT NOT NULL,
+        nullifier2 TEXT NOT NULL,
+        recipient TEXT NOT NULL,
+        status TEXT NOT NULL,
+        -- Space-separated signatures of every attempt, oldest first
+        signatures TEXT NOT NULL DEFAULT '',
+        error TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE INDEX jobs_request_hash ON jobs (request_hash);
+    CREATE INDEX jobs_status ON jobs (status, updated_at);"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    /// Sent, waiting for confirmation; `signature` is the latest attempt
+    Submitted,
+    Confirmed,
+    Failed,
+}
+
+impl JobState {
+    fn parse(s: &str) -> Result<Self> {
+        match s {
+            "queued" => Ok(JobState::Queued),
+            "submitted" => Ok(JobState::Submitted),
+            "confirmed" => Ok(JobState::Confirmed),
+            "failed" => Ok(JobState::Failed),
+            _ => Err(anyhow!("unknown job status {:?}", s)),
+        }
+    }
+}
+
+/// `GET /status/:job_id`
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub job_id: String,
+    pub status: JobState,
+    pub signature: Option<String>,
+    pub error: Option<String>,
+}
+
+/// What a job is for, recorded when it is created
+pub struct JobRequest {
+    /// Hash of the instruction data, to find the job of a repeated request
+    pub request_hash: String,
+    pub nullifiers: [Nullifier; 2],
+    pub recipient: String,
+}
+
+/// A job that was queued or sent but never finished
+#[derive(Debug)]
+pub struct UnfinishedJob {
+    pub id: String,
+    pub status: JobState,
+    pub nullifiers: [Nullifier; 2],
+    pub signatures: Vec<String>,
+    pub updated_at: u64,
+}
+
+/// A validated withdrawal waiting for the worker
+pub struct RelayJob {
+    pub id: String,
+    pub instructions: Vec<Instruction>,
+    pub nullifier_pdas: [Pubkey; 2],
+    /// Released if the job fails before it is sent
+    pub claim: Claim,
+    /// Holds shutdown until the worker is done with the job
+    pub _in_flight: InFlight,
+}
+
+pub struct Jobs {
+    retention: Duration,
+    conn: Mutex<Connection>,
+}
+
+impl Jobs {
+    /// Open (or create) the job database at `path` and migrate it
+    pub fn open(path: &str, retention: Duration) -> Result<Self> {
+        let mut conn = Connection::open(path)?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            retention,
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Register a new queued job and return its id
+    pub fn create(&self, request: &JobRequest) -> Result<String> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let now = unix_now();
+        let conn = self.conn.lock().unwrap();
+        self.evict(&conn, now)?;
+        conn.execute(
+            "INSERT INTO jobs (id, request_hash, nullifier1, nullifier2, recipient, status, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, 'queued', ?6, ?6)",
+            params![
+                id,
+                request.request_hash,
+                hex::encode(request.nullifiers[0]),
+                hex::encode(request.nullifiers[1]),
+                request.recipient,
+                now,
+            ],
+        )?;
+        Ok(id)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<JobStatus>> {
+        let conn = self.conn.lock().unwrap();
+        self.evict(&conn, unix_now())?;
+        conn.query_row(
+            "SELECT status, signatures, error FROM jobs WHERE id = ?1",
+            [id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            },
+        )
+        .optional()?
+        .map(|(status, signatures, error)| {
+            Ok(JobStatus {
+                job_id: id.to_string(),
+                status: JobState::parse(&status)?,
+                signature: signatures.split_whitespace().next_back().map(str::to_string),
+                error,
+            })
+        })
+        .transpose()
+    }
+
+    /// Most recent job for the same instruction data, if any
+    pub fn find_by_request(&self, request_hash: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn
+            .query_row(
+                "SELECT id FROM jobs WHERE request_hash = ?1 ORDER BY created_at DESC LIMIT 1",
+                [request_hash],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Record an attempt's signature; called before it is sent
+    pub fn submitted(&self, id: &str, signature: String) {
+        self.log_failure(self.conn.lock().unwrap().execute(
+            "UPDATE jobs SET status = 'submitted', signatures = trim(signatures || ' ' || ?2), updated_at = ?3
+             WHERE id = ?1",
+            params![id, signature, unix_now()],
+        ));
+    }
+
+    pub fn confirmed(&self, id: &str, signature: String) {
+        self.log_failure(self.conn.lock().unwrap().execute(
+            "UPDATE jobs SET status = 'confirmed',
+                 signatures = CASE WHEN instr(signatures, ?2) THEN signatures ELSE trim(signatures || ' ' || ?2) END,
+                 error = NULL, updated_at = ?3
+             WHERE id = ?1",
+            params![id, signature, unix_now()],
+        ));
+    }
+
+    pub fn failed(&self, id: &str, error: String) {
+        self.log_failure(self.conn.lock().unwrap().execute(
+            "UPDATE jobs SET status = 'failed', error = ?2, updated_at = ?3 WHERE id = ?1",
+            params![id, error, unix_now()],
+        ));
+    }
+
+    /// Drop a job that was never queued
+    pub fn remove(&self, id: &str) {
+        self.log_failure(
+            self.conn
+                .lock()
+                .unwrap()
+                .execute("DELETE FROM jobs WHERE id = ?1", [id]),
+        );
+    }
+
+    /// Write pages SQLite still holds in memory to disk, before exiting
+    pub fn flush(&self) -> Result<()> {
+        Ok(self.conn.lock().unwrap().cache_flush()?)
+    }
+
+    /// Jobs left queued or submitted, e.g. by a crash
+    pub fn unfinished(&self) -> Result<Vec<UnfinishedJob>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, status, nullifier1, nullifier2, signatures, updated_at FROM jobs
+             WHERE status IN ('queued', 'submitted') ORDER BY created_at, rowid",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, u64>(5)?,
+            ))
+        })?;
+        rows.map(|row| {
+            let (id, status, nullifier1, nullifier2, signatures, updated_at) = row?;
+            Ok(UnfinishedJob {
+                id,
+                status: JobState::parse(&status)?,
+                nullifiers: [decode_nullifier(&nullifier1)?, decode_nullifier(&nullifier2)?],
+                signatures: signatures.split_whitespace().map(str::to_string).collect(),
+                updated_at,
+            })
+        })
+        .collect()
+    }
+
+    /// Nullifiers of jobs that weren't failed and changed since `since`,
+    /// with when they last changed, to seed the duplicate check on startup
+    pub fn nullifiers_since(&self, since: u64) -> Result<Vec<(Nullifier, u64)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT nullifier1, nullifier2, updated_at FROM jobs
+             WHERE status != 'failed' AND updated_at >= ?1",
+        )?;
+        let rows = stmt.query_map([since], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, u64>(2)?,
+            ))
+        })?;
+        let mut nullifiers = Vec::new();
+        for row in rows {
+            let (nullifier1, nullifier2, updated_at) = row?;
+            nullifiers.push((decode_nullifier(&nullifier1)?, updated_at));
+            nullifiers.push((decode_nullifier(&nullifier2)?, updated_at));
+        }
+        Ok(nullifiers)
+    }
+
+    /// Forget finished jobs older than the retention window; unfinished
+    /// ones are kept however long they take
+    fn evict(&self, conn: &Connection, now: u64) -> Result<()> {
+        conn.execute(
+            "DELETE FROM jobs WHERE status IN ('confirmed', 'failed') AND updated_at < ?1",
+            [now.saturating_sub(self.retention.as_secs())],
+        )?;
+        Ok(())
+    }
+
+    /// Status updates happen in the worker, which keeps going without them
+    fn log_failure(&self, result: rusqlite::Result<usize>) {
+        if let Err(e) = result {
+            error!("Failed to update job database: {}", e);
+        }
+    }
+}
+
+/// What became of an attempt, from `get_signature_statuses`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttemptStatus {
+    Succeeded,
+    Failed,
+    /// Not found: never landed, or not yet
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Resolution {
+    Confirmed(String),
+    /// The job failed; `resubmit` if nothing landed and the client can
+    /// safely send the withdrawal again
+    Failed { error: String, resubmit: bool },
+    /// Still in flight; check again once its blockhash has expired
+    Pending,
+}
+
+/// Decide the outcome of a job that was sent before a restart
+///
+/// `statuses` line up with the job's signatures. `nullifier_spent` is whether
+/// either nullifier account exists, and `expired` whether the last attempt's
+/// blockhash is too old for it to land.
+pub fn resolve(
+    signatures: &[String],
+    statuses: &[AttemptStatus],
+    nullifier_spent: bool,
+    expired: bool,
+) -> Resolution {
+    if let Some((signature, _)) = signatures
+        .iter()
+        .zip(statuses)
+        .find(|(_, status)| **status == AttemptStatus::Succeeded)
+    {
+        return Resolution::Confirmed(signature.clone());
+    }
+    if nullifier_spent {
+        return Resolution::Failed {
+            error: "This note was spent by another transaction".to_string(),
+            resubmit: false,
+        };
+    }
+    if !statuses.is_empty() && statuses.iter().all(|s| *s == AttemptStatus::Failed) {
+        return Resolution::Failed {
+            error: "Transaction failed".to_string(),
+            resubmit: false,
+        };
+    }
+    if expired {
+        return Resolution::Failed {
+            error: "The transaction expired without landing; submit the withdrawal again"
+                .to_string(),
+            resubmit: true,
+        };
+    }
+    Resolution::Pending
+}
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let applied: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", version + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn decode_nullifier(s: &str) -> Result<Nullifier> {
+    let mut nullifier = [0u8; 32];
+    hex::decode_to_slice(s, &mut nullifier)?;
+    Ok(nullifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(n: u8) -> JobRequest {
+        JobRequest {
+            request_hash: format!("hash{}", n),
+            nullifiers: [[n; 32], [n + 1; 32]],
+            recipient: "FM7WTd5Hr7ppp6vu3M4uAspF4DoRjrYPPFvAmqB7H95D".to_string(),
+        }
+    }
+
+    fn temp_db(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("relayer-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn tracks_job_lifecycle() {
+        let jobs = Jobs::open(":memory:", Duration::from_secs(3600)).unwrap();
+        let id = jobs.create(&request(1)).unwrap();
+        assert_eq!(jobs.get(&id).unwrap().unwrap().status, JobState::Queued);
+        assert_eq!(jobs.find_by_request("hash1").unwrap().as_deref(), Some(id.as_str()));
+
+        jobs.submitted(&id, "sig1".to_string());
+        jobs.submitted(&id, "sig2".to_string());
+        let status = jobs.get(&id).unwrap().unwrap();
+        assert_eq!((status.status, status.signature.as_deref()), (JobState::Submitted, Some("sig2")));
+
+        jobs.failed(&id, "Transaction failed".to_string());
+        let status = jobs.get(&id).unwrap().unwrap();
+        assert_eq!(status.status, JobState::Failed);
+        assert_eq!(status.signature.as_deref(), Some("sig2"));
+        assert_eq!(status.error.as_deref(), Some("Transaction failed"));
+
+        assert!(jobs.get("unknown").unwrap().is_none());
+    }
+
+    #[test]
+    fn evicts_only_finished_jobs_after_retention() {
+        let jobs = Jobs::open(":memory:", Duration::from_secs(60)).unwrap();
+        let finished = jobs.create(&request(1)).unwrap();
+        let pending = jobs.create(&request(3)).unwrap();
+        jobs.confirmed(&finished, "sig".to_string());
+        jobs.submitted(&pending, "sig2".to_string());
+
+        let conn = jobs.conn.lock().unwrap();
+        let count = |conn: &Connection| -> usize {
+            conn.query_row("SELECT count(*) FROM jobs", [], |row| row.get(0)).unwrap()
+        };
+        jobs.evict(&conn, unix_now() + 59).unwrap();
+        assert_eq!(count(&conn), 2);
+        jobs.evict(&conn, unix_now() + 61).unwrap();
+        assert_eq!(count(&conn), 1);
+        drop(conn);
+        assert!(jobs.get(&pending).unwrap().is_some());
+    }
+
+    #[test]
+    fn migrations_are_applied_once() {
+        let path = temp_db("migrate");
+        let id = Jobs::open(&path, Duration::from_secs(60)).unwrap().create(&request(1)).unwrap();
+        // Reopening must not re-run the migration (the table already exists)
+        let jobs = Jobs::open(&path, Duration::from_secs(60)).unwrap();
+        assert!(jobs.get(&id).unwrap().is_some());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn crash_between_submit_and_confirm() {
+        let path = temp_db("crash");
+        let jobs = Jobs::open(&path, Duration::from_secs(3600)).unwrap();
+        let landed = jobs.create(&request(1)).unwrap();
+        let expired = jobs.create(&request(3)).unwrap();
+        let queued = jobs.create(&request(5)).unwrap();
+        jobs.submitted(&landed, "a1".to_string());
+        jobs.submitted(&landed, "a2".to_string());
+        jobs.submitted(&expired, "b1".to_string());
+        // The process dies before any confirmation is recorded
+        drop(jobs);
+
+        let jobs = Jobs::open(&path, Duration::from_secs(3600)).unwrap();
+        let unfinished = jobs.unfinished().unwrap();
+        assert_eq!(
+            unfinished.iter().map(|j| (j.id.as_str(), j.status)).collect::<Vec<_>>(),
+            [
+                (landed.as_str(), JobState::Submitted),
+                (expired.as_str(), JobState::Submitted),
+                (queued.as_str(), JobState::Queued),
+            ]
+        );
+        assert_eq!(unfinished[0].signatures, ["a1", "a2"]);
+        assert_eq!(unfinished[1].nullifiers, [[3; 32], [4; 32]]);
+
+        // The first attempt expired but the retry landed
+        use AttemptStatus::*;
+        let resolution = resolve(&unfinished[0].signatures, &[Unknown, Succeeded], true, true);
+        assert_eq!(resolution, Resolution::Confirmed("a2".to_string()));
+        jobs.confirmed(&landed, "a2".to_string());
+        let status = jobs.get(&landed).unwrap().unwrap();
+        assert_eq!((status.status, status.signature.as_deref()), (JobState::Confirmed, Some("a2")));
+
+        // Still unknown: wait for the blockhash to expire before giving up
+        assert_eq!(resolve(&unfinished[1].signatures, &[Unknown], false, false), Resolution::Pending);
+        assert!(matches!(
+            resolve(&unfinished[1].signatures, &[Unknown], false, true),
+            Resolution::Failed { resubmit: true, .. }
+        ));
+
+        // The duplicate check survives the restart
+        let nullifiers: Vec<_> = jobs.nullifiers_since(0).unwrap().into_iter().map(|(n, _)| n).collect();
+        assert!(nullifiers.contains(&[1; 32]) && nullifiers.contains(&[4; 32]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn resolve_spent_and_failed() {
+        use AttemptStatus::*;
+        let signatures = ["a".to_string(), "b".to_string()];
+        assert!(matches!(
+            resolve(&signatures, &[Unknown, Unknown], true, false),
+            Resolution::Failed { resubmit: false, .. }
+        ));
+        assert!(matches!(
+            resolve(&signatures, &[Failed, Failed], false, false),
+            Resolution::Failed { resubmit: false, .. }
+        ));
+        assert_eq!(resolve(&signatures, &[Failed, Unknown], false, false), Resolution::Pending);
+    }
+}
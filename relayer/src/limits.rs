@@ -0,0 +1,290 @@
+//! Rate, size and time limits on the HTTP API
+//!
+//! Every route except `/health` and `/metrics` is rate limited per client IP
+//! with a token bucket: a client may send `burst` requests at once and one
+//! more every `60 / requests_per_minute` seconds. Requests over the limit
+//! get `429` with a `Retry-After` header. Bodies larger than
+//! `max_body_bytes` are rejected with `413`, and requests that take longer
+//! than `request_timeout_secs` with `408`.
+//!
+//! The client IP is the peer address, or the first `X-Forwarded-For` entry
+//! when `trust_forwarded_for` is set. Only set it behind a proxy that
+//! overwrites the header, or clients can pick their own key.
+//!
+//! ```toml
+//! [limits]
+//! requests_per_minute = 30
+//! burst = 10
+//! max_body_bytes = 16384
+//! request_timeout_secs = 30
+//! trust_forwarded_for = false
+//! ```
+
+use anyhow::Result;
+use axum::{
+    extract::{ConnectInfo, DefaultBodyLimit, Request, State},
+    http::{header::RETRY_AFTER, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tower_http::timeout::TimeoutLayer;
+
+/// Buckets are pruned once this many clients are tracked
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    /// Sustained requests per client IP (`RELAYER_RATE_LIMIT`)
+    pub requests_per_minute: u32,
+    /// Requests a client can send at once (`RELAYER_RATE_BURST`)
+    pub burst: u32,
+    /// Largest accepted request body; a relay request is well under 1 KiB
+    pub max_body_bytes: usize,
+    pub request_timeout_secs: u64,
+    /// Key clients on `X-Forwarded-For` instead of the peer address
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: 30,
+            burst: 10,
+            max_body_bytes: 16 * 1024,
+            request_timeout_secs: 30,
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct RateLimiter {
+    /// Tokens added per second
+    rate: f64,
+    burst: f64,
+    trust_forwarded_for: bool,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &LimitsConfig) -> Self {
+        Self {
+            rate: config.requests_per_minute as f64 / 60.0,
+            burst: config.burst.max(1) as f64,
+            trust_forwarded_for: config.trust_forwarded_for,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token for `ip`, or return how long until one is available
+    fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            // Full buckets hold no state worth keeping
+            buckets.retain(|_, b| self.refilled(b, now) < self.burst);
+        }
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if self.rate > 0.0 {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        } else {
+            Err(Duration::MAX)
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.rate).min(self.burst)
+    }
+
+    fn client_ip(&self, request: &Request) -> IpAddr {
+        let forwarded = self
+            .trust_forwarded_for
+            .then(|| request.headers().get("x-forwarded-for"))
+            .flatten()
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+        let peer = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        forwarded
+            .or(peer)
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    }
+}
+
+async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = limiter.client_ip(&request);
+    match limiter.check(ip, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let secs = wait.as_secs_f64().ceil().min(u32::MAX as f64) as u64;
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, secs.to_string())],
+                Json(serde_json::json!({
+                    "success": false,
+                    "error": format!("Too many requests; retry in {} seconds", secs),
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Merge `public` routes with rate-limited `limited` ones, applying the
+/// body size limit and timeout to both
+pub fn apply<S>(public: Router<S>, limited: Router<S>, config: &LimitsConfig) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let limiter = Arc::new(RateLimiter::new(config));
+    public
+        .merge(limited.route_layer(middleware::from_fn_with_state(limiter, rate_limit)))
+        .layer(DefaultBodyLimit::max(config.max_body_bytes))
+        .layer(TimeoutLayer::new(Duration::from_secs(
+            config.request_timeout_secs,
+        )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, body::Bytes, routing::get, routing::post};
+    use tower::ServiceExt;
+
+    const CONFIG: LimitsConfig = LimitsConfig {
+        requests_per_minute: 1,
+        burst: 3,
+        max_body_bytes: 1024,
+        request_timeout_secs: 5,
+        trust_forwarded_for: false,
+    };
+
+    fn app(config: &LimitsConfig) -> Router {
+        let public = Router::new().route("/health", get(|| async { "OK" }));
+        let limited = Router::new()
+            .route(
+                "/relay",
+                post(|body: Bytes| async move { body.len().to_string() }),
+            )
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    "done"
+                }),
+            );
+        apply(public, limited, config)
+    }
+
+    async fn send(app: &Router, method: &str, uri: &str, ip: [u8; 4], body: Vec<u8>) -> Response {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .extension(ConnectInfo(SocketAddr::from((ip, 4000))))
+            .body(Body::from(body))
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn limits_each_client_after_burst() {
+        let app = app(&CONFIG);
+        for _ in 0..CONFIG.burst {
+            let response = send(&app, "POST", "/relay", [10, 0, 0, 1], vec![]).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = send(&app, "POST", "/relay", [10, 0, 0, 1], vec![]).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "60");
+
+        // Other clients and the health check are unaffected
+        let response = send(&app, "POST", "/relay", [10, 0, 0, 2], vec![]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        for _ in 0..20 {
+            let response = send(&app, "GET", "/health", [10, 0, 0, 1], vec![]).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_bodies() {
+        let app = app(&CONFIG);
+        let response = send(&app, "POST", "/relay", [10, 0, 0, 1], vec![0; 1024]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, "POST", "/relay", [10, 0, 0, 1], vec![0; 1025]).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn times_out_slow_requests() {
+        let app = app(&CONFIG);
+        let response = send(&app, "GET", "/slow", [10, 0, 0, 1], vec![]).await;
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let limiter = RateLimiter::new(&LimitsConfig {
+            requests_per_minute: 60,
+            ..CONFIG
+        });
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let start = Instant::now();
+        for _ in 0..CONFIG.burst {
+            assert!(limiter.check(ip, start).is_ok());
+        }
+        assert_eq!(limiter.check(ip, start), Err(Duration::from_secs(1)));
+        assert!(limiter.check(ip, start + Duration::from_secs(1)).is_ok());
+        assert!(limiter.check(ip, start + Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn forwarded_for_is_only_trusted_when_enabled() {
+        let request = || {
+            Request::builder()
+                .header("x-forwarded-for", "203.0.113.7, 10.0.0.1")
+                .extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let limiter = RateLimiter::new(&CONFIG);
+        assert_eq!(limiter.client_ip(&request()), IpAddr::from([10, 0, 0, 1]));
+
+        let limiter = RateLimiter::new(&LimitsConfig {
+            trust_forwarded_for: true,
+            ..CONFIG
+        });
+        assert_eq!(
+            limiter.client_ip(&request()),
+            IpAddr::from([203, 0, 113, 7])
+        );
+    }
+}
@@ -1,45 +1,125 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use axum::{
-    extract::State,
-    http::StatusCode,
+    extract::{Extension, Path, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
     routing::{get, post},
     Json, Router,
 };
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use serde::{Deserialize, Serialize};
-use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
-    commitment_config::CommitmentConfig,
     compute_budget::ComputeBudgetInstruction,
-    instruction::{AccountMeta, Instruction},
+    instruction::Instruction,
     pubkey::Pubkey,
-    signature::Keypair,
+    signature::{Keypair, Signature},
     signer::Signer,
-    system_program,
     transaction::Transaction,
 };
-use std::{str::FromStr, sync::Arc};
-use tower_http::cors::{Any, CorsLayer};
-use tracing::{info, error};
-
-// Pool configuration
-const PROGRAM_ID: &str = "9A6fck3xNW2C6vwwqM4i1f4GeYpieuB7XKpF1YFduT6h";
-const TREE_ACCOUNT: &str = "4EGnTF2XfKDTBAszzoqQLe4zbmiURkWtkYQGnj99GiJf";
-const GLOBAL_CONFIG: &str = "7RUeHfhA6L7BUrmt9ZK7SJ9rmTMkD8qjjJgHRrUEGMq9";
-const POOL_VAULT: &str = "7nAKNHQwTeaybrnX6y3c3fLDL3qzQ3A6FGwMwH1LPc8q";
-// Use relayer address as fee_recipient to avoid exposing user address
-const FEE_RECIPIENT: &str = "FcuLoWBhZ8bNQRsSgGhH5NCJJbqK5uhHMZR6V21kyTgS";
+use metrics_exporter_prometheus::PrometheusHandle;
+use privacy_zig_core::{
+    sized_compute_unit_limit, spl, transact_instruction_from_data,
+    transact_spl_instruction_from_data, with_compute_budget, Endpoint, PoolConfig, SplPool,
+    TransactProofData, VaultError, COMPUTE_UNIT_LIMIT,
+};
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+use tracing::{info, error, warn};
+
+mod auth;
+mod config;
+mod cors;
+mod dedup;
+mod fee;
+mod jobs;
+mod limits;
+mod metrics;
+mod research;
+mod rpc;
+mod server;
+mod shutdown;
+mod simulate;
+mod submit;
+mod verify;
+
+use crate::auth::{ApiKey, Auth, Usage};
+use crate::config::{ComputeConfig, RelayerConfig};
+use crate::dedup::{unix_now, RecentNullifiers};
+use crate::fee::{FeePolicy, FeeQuote};
+use crate::jobs::{
+    AttemptStatus, JobRequest, JobState, JobStatus, Jobs, RelayJob, Resolution, UnfinishedJob,
+};
+use crate::metrics::FailureKind;
+use crate::research::ResearchReport;
+use crate::rpc::ClientPool;
+use crate::shutdown::Shutdown;
+use crate::simulate::{SimulateResponse, Simulation, SimulationFailure};
+use crate::submit::{RetryPolicy, SubmitError};
+use crate::verify::{VerifyError, Verifier};
 
 struct AppState {
-    client: RpcClient,
+    /// Every endpoint in `RPC_URLS`
+    client: ClientPool,
     relayer_keypair: Keypair,
-    program_id: Pubkey,
-    tree_account: Pubkey,
-    global_config: Pubkey,
-    pool_vault: Pubkey,
-    fee_recipient: Pubkey,
+    /// Pool addresses (`PRIVACY_POOL_*` or `relayer.toml`)
+    pool: PoolConfig,
+    metrics: PrometheusHandle,
+    /// Bearer token for operator-only endpoints; they are disabled when unset
+    admin_token: Option<String>,
+    /// Fee the relayer requires, paid to `fee_recipient`
+    fee_policy: FeePolicy,
+    /// Simulate each transaction before sending it (`RELAYER_SIMULATE`)
+    simulate: bool,
+    /// Compute budget requested by each withdrawal
+    compute: ComputeConfig,
+    /// Resubmission on blockhash expiry
+    retry: RetryPolicy,
+    /// Checks proofs before we pay to send them; `None` if disabled with
+    /// `RELAYER_VERIFY=false`
+    verifier: Option<Verifier>,
+    /// Nullifiers relayed recently or in flight
+    recent_nullifiers: Arc<RecentNullifiers>,
+    jobs: Jobs,
+    /// Validated withdrawals for the send worker
+    queue: mpsc::Sender<RelayJob>,
+    /// Relay requests and jobs to finish before exiting
+    shutdown: Arc<Shutdown>,
 }
 
+/// snarkjs verifying key of the transaction circuit
+const DEFAULT_VERIFYING_KEY: &str = "../artifacts/verifyingkey2.json";
+
+/// How long a relayed withdrawal's nullifiers are remembered
+const DEFAULT_DEDUP_TTL_SECS: &str = "600";
+
+/// How long finished jobs can still be polled
+const DEFAULT_JOB_RETENTION_SECS: &str = "3600";
+
+/// Job database, relative to the working directory
+const DEFAULT_DB_PATH: &str = "relayer.db";
+
+/// How long after its last attempt a job can still land; its blockhash has
+/// expired by then
+const BLOCKHASH_EXPIRY: Duration = Duration::from_secs(120);
+
+/// Withdrawals that can wait for the send worker before `/relay` returns `503`
+const DEFAULT_QUEUE_SIZE: &str = "100";
+
+/// How often the relayer balance gauge is refreshed
+const BALANCE_SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long open connections get to finish once draining is over
+const CONNECTION_GRACE: Duration = Duration::from_secs(5);
+
+/// Delay before the first resubmission after a blockhash expiry
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// `InvalidProof` (6000) as reported in a failed transaction's error
+const INVALID_PROOF_ERROR: &str = "custom program error: 0x1770";
+
 fn env_or_default(key: &str, fallback: &str) -> String {
     std::env::var(key).unwrap_or_else(|_| fallback.to_string())
 }
@@ -54,13 +134,36 @@ struct RelayRequest {
     nullifier2: String,
     /// Recipient address (base58)
     recipient: String,
+    /// Mint of an SPL token withdrawal (base58); the tokens go to the
+    /// recipient's associated token account. Absent for SOL.
+    #[serde(default)]
+    mint: Option<String>,
 }
 
 #[derive(Serialize)]
 struct RelayResponse {
     success: bool,
+    /// Poll `/status/:job_id` for the outcome of an accepted withdrawal
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job_id: Option<String>,
     signature: Option<String>,
     error: Option<String>,
+    /// Machine-readable reason for rejected proofs (see `verify::VerifyError`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+}
+
+fn relay_error(status: StatusCode, error: String) -> (StatusCode, Json<RelayResponse>) {
+    (
+        status,
+        Json(RelayResponse {
+            success: false,
+            job_id: None,
+            signature: None,
+            error: Some(error),
+            code: None,
+        }),
+    )
 }
 
 #[derive(Serialize)]
@@ -75,219 +178,949 @@ async fn health() -> &'static str {
     "OK"
 }
 
+async fn metrics_handler(State(state): State<Arc<AppState>>) -> String {
+    state.metrics.render()
+}
+
 async fn info(State(state): State<Arc<AppState>>) -> Json<InfoResponse> {
-    let balance = state
+    let lamports = state
         .client
-        .get_balance(&state.relayer_keypair.pubkey())
-        .unwrap_or(0) as f64
-        / 1_000_000_000.0;
+        .call(|c| c.get_balance(&state.relayer_keypair.pubkey()))
+        .unwrap_or(0);
+    metrics::set_balance(lamports);
+    let balance = lamports as f64 / 1_000_000_000.0;
 
     Json(InfoResponse {
         relayer_address: state.relayer_keypair.pubkey().to_string(),
-        program_id: state.program_id.to_string(),
-        pool_vault: state.pool_vault.to_string(),
+        program_id: state.pool.program_id.to_string(),
+        pool_vault: state.pool.pool_vault.to_string(),
         balance,
     })
 }
 
-async fn relay_withdraw(
+/// `400` response carrying a verification failure's message and code
+fn verification_error(e: VerifyError) -> (StatusCode, Json<RelayResponse>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(RelayResponse {
+            success: false,
+            job_id: None,
+            signature: None,
+            error: Some(e.message()),
+            code: Some(e.code()),
+        }),
+    )
+}
+
+/// Verify the proof against the current root history of `tree_account`
+fn verify_proof(
+    state: &AppState,
+    verifier: &Verifier,
+    tree_account: &Pubkey,
+    data: &[u8],
+) -> Result<Result<(), VerifyError>> {
+    let tree = state.client.call(|c| c.get_account_data(tree_account))?;
+    let roots = privacy_zig_core::tree::root_history(&tree)
+        .ok_or_else(|| anyhow::anyhow!("Tree account is too short ({} bytes)", tree.len()))?;
+    Ok(verifier.verify(data, &roots))
+}
+
+/// Read the on-chain withdrawal fee rate from the global config account
+fn withdrawal_fee_rate(state: &AppState) -> Result<u16> {
+    let data = state.client.call(|c| c.get_account_data(&state.pool.global_config))?;
+    fee::withdrawal_fee_rate(&data)
+}
+
+/// The fee a request has to pay, after its API key's discount
+fn fee_policy(state: &AppState, key: Option<&ApiKey>) -> FeePolicy {
+    key.map_or(state.fee_policy, |key| state.fee_policy.discounted(key.fee_discount_bps))
+}
+
+/// Current fee quote, so clients know the smallest withdrawal we accept
+async fn fee_quote(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<RelayRequest>,
-) -> (StatusCode, Json<RelayResponse>) {
-    info!("Received relay request for recipient: {}", req.recipient);
+    key: Option<Extension<ApiKey>>,
+) -> Result<Json<FeeQuote>, (StatusCode, String)> {
+    let rate = withdrawal_fee_rate(&state)
+        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Failed to read fee rate: {}", e)))?;
+    let policy = fee_policy(&state, key.as_deref());
 
-    // Parse inputs
-    let instruction_data = match BASE64.decode(&req.instruction_data) {
-        Ok(data) => data,
-        Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(RelayResponse {
-                    success: false,
-                    signature: None,
-                    error: Some(format!("Invalid instruction data: {}", e)),
-                }),
-            );
+    Ok(Json(FeeQuote {
+        flat_fee_lamports: policy.flat_lamports,
+        fee_bps: policy.bps,
+        withdrawal_fee_rate_bps: rate,
+        fee_recipient: state.pool.fee_recipient.to_string(),
+        min_withdrawal_lamports: fee::min_withdrawal(&policy, rate),
+    }))
+}
+
+/// Aggregate anonymity statistics (operator only)
+async fn pool_research(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<ResearchReport>, (StatusCode, String)> {
+    let Some(expected) = state.admin_token.as_deref() else {
+        return Err((StatusCode::NOT_FOUND, "Research endpoint is disabled".to_string()));
+    };
+    let provided = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if provided != Some(expected) {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid admin token".to_string()));
+    }
+
+    let scan_state = state.clone();
+    let events = tokio::task::spawn_blocking(move || {
+        research::fetch_events(&scan_state.client, &scan_state.pool.tree_account)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Failed to read pool history: {}", e)))?;
+
+    Ok(Json(research::aggregate(&events)))
+}
+
+/// A response turning a request away
+type Rejection = (StatusCode, Json<RelayResponse>);
+
+/// A relay request with its inputs decoded and cross-checked
+struct ParsedRequest {
+    instruction_data: Vec<u8>,
+    nullifiers: [dedup::Nullifier; 2],
+    recipient: Pubkey,
+    /// Lamports or token units withdrawn, fee included
+    amount: u64,
+    /// Pool of the token withdrawn, `None` for SOL
+    spl: Option<SplPool>,
+    request_hash: String,
+}
+
+/// Decode a request and check the fee it pays
+fn parse_request(
+    state: &AppState,
+    req: &RelayRequest,
+    key: Option<&ApiKey>,
+) -> Result<ParsedRequest, Rejection> {
+    let invalid = |error: String| {
+        metrics::record_failure(FailureKind::InvalidInput);
+        relay_error(StatusCode::BAD_REQUEST, error)
+    };
+
+    let instruction_data = BASE64
+        .decode(&req.instruction_data)
+        .map_err(|e| invalid(format!("Invalid instruction data: {}", e)))?;
+
+    // Parsed up front so that malformed data is turned away before any RPC
+    // call, rather than forwarded to the program
+    let proof = TransactProofData::from_instruction_data(&instruction_data)
+        .map_err(|e| invalid(format!("Invalid instruction data: {}", e)))?;
+    // Only withdrawals: for a deposit the program would move the relayer's
+    // own SOL into the pool
+    let public_amount = proof.public_amount;
+
+    let spl = match &req.mint {
+        Some(mint) => {
+            let mint =
+                Pubkey::from_str(mint).map_err(|e| invalid(format!("Invalid mint: {}", e)))?;
+            Some(*state.pool.spl_pool(&mint).map_err(|e| invalid(e.to_string()))?)
         }
+        None => None,
     };
 
-    let nullifier1 = match hex::decode(&req.nullifier1) {
-        Ok(data) => data,
+    let policy = match spl {
+        Some(_) => fee_policy(state, key).for_token(),
+        None => fee_policy(state, key),
+    };
+    // Read even when we charge nothing: the proof commits to the fee
+    let rate = withdrawal_fee_rate(state).map_err(|e| {
+        metrics::record_failure(FailureKind::ChainError);
+        relay_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to read fee rate: {}", e),
+        )
+    })?;
+
+    let fee = match fee::check_withdrawal_fee(public_amount, rate, &policy) {
+        Ok(fee) => fee,
+        Err(e) if public_amount >= 0 => return Err(invalid(e)),
         Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(RelayResponse {
-                    success: false,
-                    signature: None,
-                    error: Some(format!("Invalid nullifier1: {}", e)),
-                }),
-            );
+            metrics::record_failure(FailureKind::InsufficientFee);
+            return Err(relay_error(StatusCode::PAYMENT_REQUIRED, e));
         }
     };
 
-    let nullifier2 = match hex::decode(&req.nullifier2) {
-        Ok(data) => data,
+    let nullifier1 =
+        hex::decode(&req.nullifier1).map_err(|e| invalid(format!("Invalid nullifier1: {}", e)))?;
+    let nullifier2 =
+        hex::decode(&req.nullifier2).map_err(|e| invalid(format!("Invalid nullifier2: {}", e)))?;
+    let recipient =
+        Pubkey::from_str(&req.recipient).map_err(|e| invalid(format!("Invalid recipient: {}", e)))?;
+
+    // The transaction creates the PDAs of the nullifiers inside the
+    // instruction data, so the hex copies must agree with it
+    if (&proof.nullifier1, &proof.nullifier2) != (&nullifier1, &nullifier2) {
+        return Err(invalid("Nullifiers do not match the instruction data".to_string()));
+    }
+    verify::check_ext_data(
+        &instruction_data,
+        &recipient,
+        public_amount.unsigned_abs(),
+        &state.relayer_keypair.pubkey(),
+        fee,
+    )
+    .map_err(invalid)?;
+    let nullifiers = [
+        proof.nullifier1[..].try_into().unwrap(),
+        proof.nullifier2[..].try_into().unwrap(),
+    ];
+    let request_hash = solana_sdk::hash::hash(&instruction_data).to_string();
+
+    Ok(ParsedRequest {
+        instruction_data,
+        nullifiers,
+        recipient,
+        amount: public_amount.unsigned_abs(),
+        spl,
+        request_hash,
+    })
+}
+
+/// Check the withdrawal against the chain and build its instructions
+fn check_withdrawal(state: &AppState, req: &ParsedRequest) -> Result<Vec<Instruction>, Rejection> {
+    // A token's pool has its own tree and vault
+    let pool = match &req.spl {
+        Some(spl) => state.pool.for_spl(spl),
+        None => state.pool.clone(),
+    };
+    let pdas = req.nullifiers.map(|n| state.pool.nullifier_pda(&n));
+
+    // The program creates a nullifier PDA when its note is spent
+    match state.client.call(|c| c.get_multiple_accounts(&pdas)) {
+        Ok(accounts) if accounts.iter().any(Option::is_some) => {
+            metrics::record_failure(FailureKind::Duplicate);
+            return Err(relay_error(
+                StatusCode::CONFLICT,
+                "This note has already been spent".to_string(),
+            ));
+        }
+        Ok(_) => {}
         Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(RelayResponse {
-                    success: false,
-                    signature: None,
-                    error: Some(format!("Invalid nullifier2: {}", e)),
-                }),
+            metrics::record_failure(FailureKind::ChainError);
+            return Err(relay_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to read nullifier accounts: {}", e),
+            ));
+        }
+    }
+
+    // Before spending a verification or simulation on it
+    if req.spl.is_none() {
+        check_vault(state, req.amount)?;
+    }
+
+    if let Some(verifier) = &state.verifier {
+        match verify_proof(state, verifier, &pool.tree_account, &req.instruction_data) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                info!("Rejected relay: {}", e.message());
+                metrics::record_failure(FailureKind::VerificationFailed);
+                return Err(verification_error(e));
+            }
+            Err(e) => {
+                metrics::record_failure(FailureKind::ChainError);
+                return Err(relay_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to read root history: {}", e),
+                ));
+            }
+        }
+    }
+
+    // Build transaction with relayer as signer; the recipient gets the SOL,
+    // or the tokens in its token account
+    let signer = state.relayer_keypair.pubkey();
+    let (transact_ix, fee_account) = match &req.spl {
+        Some(spl) => {
+            let token_accounts = check_token_accounts(state, spl, &req.recipient)?;
+            let ix = transact_spl_instruction_from_data(
+                &state.pool,
+                spl,
+                req.instruction_data.clone(),
+                &signer,
+                &token_accounts[0],
+                &token_accounts[1],
+            );
+            (ix, token_accounts[1])
+        }
+        None => {
+            let ix = transact_instruction_from_data(
+                &state.pool,
+                req.instruction_data.clone(),
+                &signer,
+                &req.recipient,
+                &state.pool.fee_recipient,
             );
+            (ix, state.pool.fee_recipient)
         }
     };
+    let transact_ix = transact_ix.map_err(|e| {
+        metrics::record_failure(FailureKind::InvalidInput);
+        relay_error(StatusCode::BAD_REQUEST, e.to_string())
+    })?;
+    // The fee checked above is only ours if it's paid to our fee address
+    if let Err(e) = fee::check_fee_recipient(&transact_ix, &fee_account) {
+        metrics::record_failure(FailureKind::InsufficientFee);
+        return Err(relay_error(StatusCode::PAYMENT_REQUIRED, e));
+    }
 
-    let recipient = match Pubkey::from_str(&req.recipient) {
-        Ok(pk) => pk,
-        Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
+    let unit_price = state
+        .compute
+        .unit_price
+        .resolve(|| {
+            let accounts = pool.contended_accounts();
+            state
+                .client
+                .call(|c| c.get_recent_prioritization_fees(&accounts))
+                .map(|fees| fees.iter().map(|f| f.prioritization_fee).collect())
+        })
+        .map_err(|e| {
+            metrics::record_failure(FailureKind::ChainError);
+            relay_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to read recent priority fees: {}", e),
+            )
+        })?;
+
+    // Lowered to what the simulation used by `size_compute_budget`
+    let unit_limit = state.compute.unit_limit.unwrap_or(COMPUTE_UNIT_LIMIT);
+    Ok(with_compute_budget(transact_ix, unit_limit, unit_price))
+}
+
+/// Reject a SOL withdrawal the pool vault can't pay
+fn check_vault(state: &AppState, amount: u64) -> Result<(), Rejection> {
+    let vault = [state.pool.pool_vault];
+    let accounts = state.client.call(|c| c.get_multiple_accounts(&vault)).map_err(|e| {
+        metrics::record_failure(FailureKind::ChainError);
+        relay_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to read the pool vault: {}", e),
+        )
+    })?;
+    let account = accounts.into_iter().next().flatten().map(|a| (a.owner, a.lamports));
+    match privacy_zig_core::check_withdrawal(&state.pool, account, amount) {
+        Ok(()) => Ok(()),
+        Err(e @ VaultError::Underfunded { .. }) => {
+            warn!("Rejected relay: {}", e);
+            metrics::record_failure(FailureKind::ChainError);
+            Err((
+                StatusCode::SERVICE_UNAVAILABLE,
                 Json(RelayResponse {
                     success: false,
+                    job_id: None,
                     signature: None,
-                    error: Some(format!("Invalid recipient: {}", e)),
+                    error: Some(e.to_string()),
+                    code: Some(SimulationFailure::PoolUnderfunded.code()),
                 }),
-            );
+            ))
         }
-    };
+        // A wrong vault is the relayer's configuration, not the request
+        Err(e) => {
+            error!("{}", e);
+            metrics::record_failure(FailureKind::ChainError);
+            Err(relay_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "The relayer's pool vault is misconfigured".to_string(),
+            ))
+        }
+    }
+}
 
-    // Derive nullifier PDAs
-    let (nullifier1_pda, _) = Pubkey::find_program_address(
-        &[b"nullifier", &nullifier1],
-        &state.program_id,
+/// The recipient's and the fee address's token accounts for `spl`'s mint,
+/// which have to exist for the program to pay into them
+fn check_token_accounts(
+    state: &AppState,
+    spl: &SplPool,
+    recipient: &Pubkey,
+) -> Result<[Pubkey; 2], Rejection> {
+    let accounts = [
+        spl::associated_token_address(recipient, &spl.mint),
+        spl::associated_token_address(&state.pool.fee_recipient, &spl.mint),
+    ];
+    let existing = state.client.call(|c| c.get_multiple_accounts(&accounts)).map_err(|e| {
+        metrics::record_failure(FailureKind::ChainError);
+        relay_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to read token accounts: {}", e),
+        )
+    })?;
+    if existing[0].is_none() {
+        metrics::record_failure(FailureKind::InvalidInput);
+        return Err(relay_error(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Recipient {} has no token account for mint {}; create it with \
+                 `spl-token create-account {} --owner {}`",
+                recipient, spl.mint, spl.mint, recipient
+            ),
+        ));
+    }
+    if existing[1].is_none() {
+        error!(
+            "Fee address {} has no token account for mint {}",
+            state.pool.fee_recipient, spl.mint
+        );
+        metrics::record_failure(FailureKind::ChainError);
+        return Err(relay_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("The relayer can't take fees in mint {} yet", spl.mint),
+        ));
+    }
+    Ok(accounts)
+}
+
+/// Request the compute units a simulation used plus a margin, unless
+/// `compute.unit_limit` is set; the maximum if there was no simulation
+fn size_compute_budget(
+    state: &AppState,
+    instructions: &mut [Instruction],
+    units_consumed: Option<u64>,
+) {
+    let unit_limit = state
+        .compute
+        .unit_limit
+        .unwrap_or_else(|| sized_compute_unit_limit(units_consumed));
+    // `with_compute_budget` puts the limit first
+    instructions[0] = ComputeBudgetInstruction::set_compute_unit_limit(unit_limit);
+    info!(
+        "Requesting {} compute units (simulation used {:?})",
+        unit_limit, units_consumed
+    );
+}
+
+/// Sign `instructions` with a fresh blockhash and simulate them
+fn simulate_withdrawal(state: &AppState, instructions: &[Instruction]) -> Result<Simulation> {
+    let recent_blockhash = state
+        .client
+        .call(|c| c.get_latest_blockhash())
+        .context("Failed to get blockhash")?;
+    let tx = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&state.relayer_keypair.pubkey()),
+        &[&state.relayer_keypair],
+        recent_blockhash,
     );
-    let (nullifier2_pda, _) = Pubkey::find_program_address(
-        &[b"nullifier", &nullifier2],
-        &state.program_id,
+    let result = state
+        .client
+        .call(|c| c.simulate_transaction(&tx))
+        .context("Failed to simulate transaction")?
+        .value;
+    let simulation = Simulation::new(
+        result.err,
+        result.logs.unwrap_or_default(),
+        result.units_consumed,
     );
+    metrics::record_simulation(simulation.failure);
+    Ok(simulation)
+}
 
-    // Build transaction with relayer as signer
-    // Account order: tree, null1, null2, config, vault, signer, recipient, fee_recipient, system
-    let transact_ix = Instruction {
-        program_id: state.program_id,
-        accounts: vec![
-            AccountMeta::new(state.tree_account, false),
-            AccountMeta::new(nullifier1_pda, false),
-            AccountMeta::new(nullifier2_pda, false),
-            AccountMeta::new_readonly(state.global_config, false),
-            AccountMeta::new(state.pool_vault, false),
-            AccountMeta::new(state.relayer_keypair.pubkey(), true), // relayer signs!
-            AccountMeta::new(recipient, false),                     // recipient gets SOL
-            AccountMeta::new(state.fee_recipient, false),
-            AccountMeta::new_readonly(system_program::id(), false),
-        ],
-        data: instruction_data,
+fn simulation_failure_kind(simulation: &Simulation) -> FailureKind {
+    if simulation.failure == Some(SimulationFailure::InvalidProof) {
+        FailureKind::VerificationFailed
+    } else {
+        FailureKind::ChainError
+    }
+}
+
+/// Reject a withdrawal that failed simulation
+fn simulation_error(simulation: &Simulation) -> Rejection {
+    let failure = simulation.failure.unwrap_or(SimulationFailure::Other);
+    metrics::record_failure(simulation_failure_kind(simulation));
+    (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        Json(RelayResponse {
+            success: false,
+            job_id: None,
+            signature: None,
+            error: simulation.describe(),
+            code: Some(failure.code()),
+        }),
+    )
+}
+
+async fn relay_withdraw(
+    State(state): State<Arc<AppState>>,
+    key: Option<Extension<ApiKey>>,
+    Json(req): Json<RelayRequest>,
+) -> Rejection {
+    info!("Received relay request for recipient: {}", req.recipient);
+    metrics::record_request();
+
+    let parsed = match parse_request(&state, &req, key.as_deref()) {
+        Ok(parsed) => parsed,
+        Err(rejection) => return rejection,
     };
 
-    let compute_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_400_000);
+    // Held until the transaction is broadcast, then for the TTL, so a
+    // duplicate request can't be sent a second time
+    let Some(claim) = state.recent_nullifiers.claim(&parsed.nullifiers) else {
+        metrics::record_failure(FailureKind::Duplicate);
+        // A client repeating its request can poll the original job
+        let job_id = state.jobs.find_by_request(&parsed.request_hash).ok().flatten();
+        return (
+            StatusCode::CONFLICT,
+            Json(RelayResponse {
+                success: false,
+                job_id,
+                signature: None,
+                error: Some("This withdrawal is already being relayed".to_string()),
+                code: None,
+            }),
+        );
+    };
 
-    let recent_blockhash = match state.client.get_latest_blockhash() {
-        Ok(bh) => bh,
+    let mut instructions = match check_withdrawal(&state, &parsed) {
+        Ok(instructions) => instructions,
+        Err(rejection) => return rejection,
+    };
+
+    let mut units_consumed = None;
+    if state.simulate {
+        match simulate_withdrawal(&state, &instructions) {
+            Ok(simulation) if simulation.failure.is_some() => {
+                info!("Simulation rejected relay: {:?}", simulation.err);
+                return simulation_error(&simulation);
+            }
+            Ok(simulation) => units_consumed = simulation.units_consumed,
+            Err(e) => {
+                metrics::record_failure(FailureKind::ChainError);
+                return relay_error(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e));
+            }
+        }
+    }
+    size_compute_budget(&state, &mut instructions, units_consumed);
+
+    let request = JobRequest {
+        request_hash: parsed.request_hash,
+        nullifiers: parsed.nullifiers,
+        recipient: req.recipient.clone(),
+    };
+    let job_id = match state.jobs.create(&request) {
+        Ok(id) => id,
         Err(e) => {
-            return (
+            error!("Failed to record job: {}", e);
+            return relay_error(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(RelayResponse {
-                    success: false,
-                    signature: None,
-                    error: Some(format!("Failed to get blockhash: {}", e)),
-                }),
+                format!("Failed to record job: {}", e),
             );
         }
     };
+    let job = RelayJob {
+        id: job_id.clone(),
+        instructions,
+        nullifier_pdas: parsed.nullifiers.map(|n| state.pool.nullifier_pda(&n)),
+        claim,
+        _in_flight: state.shutdown.track(),
+    };
+    if let Err(e) = state.queue.try_send(job) {
+        state.jobs.remove(&job_id);
+        metrics::record_failure(FailureKind::ChainError);
+        let message = match e {
+            mpsc::error::TrySendError::Full(_) => "The relayer is busy; try again shortly",
+            mpsc::error::TrySendError::Closed(_) => "The relayer is shutting down",
+        };
+        return relay_error(StatusCode::SERVICE_UNAVAILABLE, message.to_string());
+    }
+    info!("Queued relay job {}", job_id);
 
-    let tx = Transaction::new_signed_with_payer(
-        &[compute_ix, transact_ix],
-        Some(&state.relayer_keypair.pubkey()),
-        &[&state.relayer_keypair],
+    (
+        StatusCode::ACCEPTED,
+        Json(RelayResponse {
+            success: true,
+            job_id: Some(job_id),
+            signature: None,
+            error: None,
+            code: None,
+        }),
+    )
+}
+
+/// Run a withdrawal through the same checks as `/relay` and simulate it,
+/// without queueing it or claiming its nullifiers
+async fn simulate_relay(
+    State(state): State<Arc<AppState>>,
+    key: Option<Extension<ApiKey>>,
+    Json(req): Json<RelayRequest>,
+) -> Result<Json<SimulateResponse>, Rejection> {
+    let parsed = parse_request(&state, &req, key.as_deref())?;
+    let instructions = check_withdrawal(&state, &parsed)?;
+    let simulation = simulate_withdrawal(&state, &instructions).map_err(|e| {
+        metrics::record_failure(FailureKind::ChainError);
+        relay_error(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e))
+    })?;
+    Ok(Json(simulation.into_response()))
+}
+
+/// Outcome of a queued withdrawal
+async fn job_status(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+) -> Result<Json<JobStatus>, (StatusCode, String)> {
+    match state.jobs.get(&job_id) {
+        Ok(Some(status)) => Ok(Json(status)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, format!("Unknown job {}", job_id))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+/// Finalize jobs a previous run left queued or submitted
+async fn resume_jobs(state: Arc<AppState>) {
+    let unfinished = match state.jobs.unfinished() {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            error!("Failed to read unfinished jobs: {}", e);
+            return;
+        }
+    };
+
+    for job in unfinished {
+        if job.status == JobState::Queued {
+            state.recent_nullifiers.forget(&job.nullifiers);
+            state.jobs.failed(
+                &job.id,
+                "The relayer restarted before sending this withdrawal; submit it again".to_string(),
+            );
+            continue;
+        }
+
+        // Resolve what we can now; wait out the blockhash for the rest
+        let age = Duration::from_secs(unix_now().saturating_sub(job.updated_at));
+        let mut resolution = check_unfinished(&state, &job, age >= BLOCKHASH_EXPIRY);
+        if let Ok(Resolution::Pending) = resolution {
+            tokio::time::sleep(BLOCKHASH_EXPIRY.saturating_sub(age)).await;
+            resolution = check_unfinished(&state, &job, true);
+        }
+
+        match resolution {
+            Ok(Resolution::Confirmed(signature)) => {
+                info!("Job {} landed before the restart: {}", job.id, signature);
+                state.jobs.confirmed(&job.id, signature);
+            }
+            Ok(Resolution::Failed { error, resubmit }) => {
+                info!("Job {} did not land: {}", job.id, error);
+                if resubmit {
+                    state.recent_nullifiers.forget(&job.nullifiers);
+                }
+                state.jobs.failed(&job.id, error);
+            }
+            Ok(Resolution::Pending) => {}
+            Err(e) => error!("Failed to check job {}: {}", job.id, e),
+        }
+    }
+}
+
+fn check_unfinished(state: &AppState, job: &UnfinishedJob, expired: bool) -> Result<Resolution> {
+    let signatures = job
+        .signatures
+        .iter()
+        .map(|s| Signature::from_str(s))
+        .collect::<Result<Vec<_>, _>>()?;
+    let statuses: Vec<AttemptStatus> = state
+        .client
+        .call(|c| c.get_signature_statuses_with_history(&signatures))?
+        .value
+        .into_iter()
+        .map(|status| match status {
+            Some(status) if status.err.is_none() => AttemptStatus::Succeeded,
+            Some(_) => AttemptStatus::Failed,
+            None => AttemptStatus::Unknown,
+        })
+        .collect();
+    let pdas = job.nullifiers.map(|n| state.pool.nullifier_pda(&n));
+    let spent = state
+        .client
+        .call(|c| c.get_multiple_accounts(&pdas))?
+        .iter()
+        .any(Option::is_some);
+    Ok(jobs::resolve(&job.signatures, &statuses, spent, expired))
+}
+
+/// Send queued withdrawals one at a time
+async fn run_worker(state: Arc<AppState>, mut queue: mpsc::Receiver<RelayJob>) {
+    while let Some(job) = queue.recv().await {
+        // Simulating and sending block on RPC calls for tens of seconds,
+        // which would stall the handlers sharing this thread
+        let state = state.clone();
+        if let Err(e) = tokio::task::spawn_blocking(move || send_job(&state, job)).await {
+            error!("Relay job panicked: {}", e);
+        }
+    }
+}
+
+/// Simulate and send one queued withdrawal, recording how it ended
+fn send_job(state: &AppState, job: RelayJob) {
+    // The root history or vault may have moved since the job was queued;
+    // a failure here costs nothing, and dropping the claim lets the
+    // client retry
+    if state.simulate {
+        match simulate_withdrawal(state, &job.instructions) {
+            Ok(simulation) if simulation.failure.is_some() => {
+                info!("Job {} failed simulation: {:?}", job.id, simulation.err);
+                metrics::record_failure(simulation_failure_kind(&simulation));
+                let error = simulation.describe().unwrap_or_default();
+                state.jobs.failed(&job.id, format!("Simulation failed: {}", error));
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                metrics::record_failure(FailureKind::ChainError);
+                state.jobs.failed(&job.id, format!("{:#}", e));
+                return;
+            }
+        }
+    }
+
+    let recent_blockhash = match state.client.call(|c| c.get_latest_blockhash()) {
+        Ok(bh) => bh,
+        Err(e) => {
+            // Nothing was sent; dropping the claim lets the client retry
+            metrics::record_failure(FailureKind::ChainError);
+            state.jobs.failed(&job.id, format!("Failed to get blockhash: {}", e));
+            return;
+        }
+    };
+
+    // From here on it may land, even if we report an error
+    job.claim.keep();
+    let started = Instant::now();
+    let result = submit::send_with_retry(
+        &state.client,
+        &job.instructions,
+        &state.relayer_keypair,
+        &job.nullifier_pdas,
         recent_blockhash,
+        state.retry,
+        |signature| state.jobs.submitted(&job.id, signature.to_string()),
     );
+    metrics::record_submission(started.elapsed());
 
-    // Send transaction
-    match state.client.send_and_confirm_transaction(&tx) {
+    match result {
         Ok(sig) => {
             info!("Transaction successful: {}", sig);
-            (
-                StatusCode::OK,
-                Json(RelayResponse {
-                    success: true,
-                    signature: Some(sig.to_string()),
-                    error: None,
-                }),
-            )
+            metrics::record_success();
+            state.jobs.confirmed(&job.id, sig.to_string());
         }
-        Err(e) => {
+        Err(SubmitError::NullifierSpent) => {
+            metrics::record_failure(FailureKind::ChainError);
+            state.jobs.failed(
+                &job.id,
+                "This note was spent by another transaction while retrying".to_string(),
+            );
+        }
+        Err(SubmitError::Client(e)) => {
             error!("Transaction failed: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(RelayResponse {
-                    success: false,
-                    signature: None,
-                    error: Some(format!("Transaction failed: {}", e)),
-                }),
-            )
+            if e.to_string().contains(INVALID_PROOF_ERROR) {
+                metrics::record_failure(FailureKind::VerificationFailed);
+            } else {
+                metrics::record_failure(FailureKind::ChainError);
+            }
+            state.jobs.failed(&job.id, format!("Transaction failed: {}", e));
         }
     }
 }
 
+/// `privacy-relayer report --research [--output <file.csv>]`
+fn run_report(config: &RelayerConfig, args: &[String]) -> Result<()> {
+    if !args.iter().any(|a| a == "--research") {
+        anyhow::bail!("usage: privacy-relayer report --research [--output <file.csv>]");
+    }
+
+    let client = rpc::connect(&config.rpc_urls);
+    let events = research::fetch_events(&client, &config.pool.tree_account)?;
+    let csv = research::aggregate(&events).to_csv();
+
+    match args.iter().position(|a| a == "--output") {
+        Some(i) => {
+            let path = args
+                .get(i + 1)
+                .ok_or_else(|| anyhow::anyhow!("--output requires a path"))?;
+            std::fs::write(path, csv)?;
+            println!("Research report written to {}", path);
+        }
+        None => print!("{}", csv),
+    }
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
 
-    // Load relayer keypair from default location or env
-    let keypair_path = std::env::var("RELAYER_KEYPAIR")
-        .unwrap_or_else(|_| {
-            format!("{}/.config/solana/id.json", std::env::var("HOME").unwrap())
-        });
-    
-    let keypair_data: Vec<u8> = serde_json::from_str(
-        &std::fs::read_to_string(&keypair_path)?
-    )?;
-    let relayer_keypair = Keypair::from_bytes(&keypair_data)?;
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let config_path = match args.iter().position(|a| a == "--config") {
+        Some(i) => {
+            let path = args
+                .get(i + 1)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("--config requires a path"))?;
+            args.drain(i..=i + 1);
+            Some(path)
+        }
+        None => None,
+    };
+    let config = RelayerConfig::load(config_path.as_deref())?;
+    if args.first().map(String::as_str) == Some("report") {
+        return run_report(&config, &args[1..]);
+    }
+
+    let metrics_handle = metrics::install()?;
 
+    let relayer_keypair = config.read_keypair()?;
     info!("Relayer address: {}", relayer_keypair.pubkey());
+    info!("Network: {}", config.network.name());
 
-    // Connect to testnet
-    let client = RpcClient::new_with_commitment(
-        "https://api.testnet.solana.com".to_string(),
-        CommitmentConfig::confirmed(),
-    );
+    let client = rpc::connect(&config.rpc_urls);
+    let urls: Vec<String> = client.endpoints().iter().map(|e| e.url()).collect();
+    info!("RPC endpoints: {}", urls.join(", "));
 
-    let balance = client.get_balance(&relayer_keypair.pubkey())?;
+    let balance = client
+        .call(|c| c.get_balance(&relayer_keypair.pubkey()))
+        .context("Failed to fetch the relayer balance; check rpc_url / RPC_URLS")?;
     info!("Relayer balance: {} SOL", balance as f64 / 1_000_000_000.0);
+    metrics::set_balance(balance);
+    config.check_balance(&relayer_keypair.pubkey(), balance)?;
+
+    let verifier = if env_or_default("RELAYER_VERIFY", "true").parse()? {
+        let path = env_or_default("RELAYER_VERIFYING_KEY", DEFAULT_VERIFYING_KEY);
+        let verifier = Verifier::load(&path)?;
+        info!("Verifying proofs with {}", path);
+        Some(verifier)
+    } else {
+        None
+    };
+
+    let dedup_ttl = Duration::from_secs(
+        env_or_default("RELAYER_DEDUP_TTL_SECS", DEFAULT_DEDUP_TTL_SECS).parse()?,
+    );
+    let job_retention = Duration::from_secs(
+        env_or_default("RELAYER_JOB_RETENTION_SECS", DEFAULT_JOB_RETENTION_SECS).parse()?,
+    );
+    let db_path = env_or_default("RELAYER_DB", DEFAULT_DB_PATH);
+    let jobs = Jobs::open(&db_path, job_retention)?;
+    info!("Job database: {}", db_path);
+
+    // Nullifiers relayed before a restart are still taken
+    let recent_nullifiers = RecentNullifiers::new(dedup_ttl);
+    let since = unix_now().saturating_sub(dedup_ttl.as_secs());
+    recent_nullifiers.restore(jobs.nullifiers_since(since)?);
+    let queue_size = env_or_default("RELAYER_QUEUE_SIZE", DEFAULT_QUEUE_SIZE).parse()?;
+    let (queue, queued) = mpsc::channel(queue_size);
+
+    let usage = Usage::open(&db_path)?;
+    for key in &config.auth.keys {
+        info!("API key {}: {} withdrawals today", key.name, usage.used_today(&key.name)?);
+    }
+    let auth = Arc::new(Auth::new(config.auth.clone(), usage));
+    let shutdown = Shutdown::new();
 
     let state = Arc::new(AppState {
         client,
         relayer_keypair,
-        program_id: Pubkey::from_str(&env_or_default("PRIVACY_POOL_PROGRAM_ID", PROGRAM_ID)).unwrap(),
-        tree_account: Pubkey::from_str(&env_or_default("PRIVACY_POOL_TREE_ACCOUNT", TREE_ACCOUNT)).unwrap(),
-        global_config: Pubkey::from_str(&env_or_default("PRIVACY_POOL_GLOBAL_CONFIG", GLOBAL_CONFIG)).unwrap(),
-        pool_vault: Pubkey::from_str(&env_or_default("PRIVACY_POOL_POOL_VAULT", POOL_VAULT)).unwrap(),
-        fee_recipient: Pubkey::from_str(&env_or_default("PRIVACY_POOL_FEE_RECIPIENT", FEE_RECIPIENT)).unwrap(),
+        pool: config.pool.clone(),
+        metrics: metrics_handle,
+        admin_token: std::env::var("RELAYER_ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+        fee_policy: config.fee,
+        simulate: env_or_default("RELAYER_SIMULATE", "true").parse()?,
+        compute: config.compute,
+        retry: RetryPolicy {
+            max_retries: env_or_default("RELAYER_MAX_RETRIES", "3").parse()?,
+            base_delay: RETRY_BASE_DELAY,
+        },
+        verifier,
+        recent_nullifiers: Arc::new(recent_nullifiers),
+        jobs,
+        queue,
+        shutdown: shutdown.clone(),
     });
 
-    // Setup CORS
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+    tokio::spawn(resume_jobs(state.clone()));
+    tokio::spawn(run_worker(state.clone(), queued));
 
-    // Build router
-    let app = Router::new()
+    // Keep the balance gauge fresh between /info calls
+    let sampler_state = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(BALANCE_SAMPLE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let state = sampler_state.clone();
+            let balance = tokio::task::spawn_blocking(move || {
+                state
+                    .client
+                    .call(|c| c.get_balance(&state.relayer_keypair.pubkey()))
+            })
+            .await;
+            if let Ok(Ok(lamports)) = balance {
+                metrics::set_balance(lamports);
+            }
+        }
+    });
+
+    // Build router; monitoring stays reachable when a client is rate limited
+    let public = Router::new()
         .route("/health", get(health))
+        .route("/metrics", get(metrics_handler));
+    // `/pool/research` checks the admin token in the same header as API keys
+    let open = Router::new()
         .route("/info", get(info))
-        .route("/relay", post(relay_withdraw))
-        .layer(cors)
-        .with_state(state);
-
-    let port = std::env::var("PORT").unwrap_or_else(|_| "3001".to_string());
-    let addr = format!("0.0.0.0:{}", port);
-    
-    info!("Starting relayer on {}", addr);
-    
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+        .route("/pool/research", get(pool_research));
+    let protected = Router::new()
+        .route("/fee", get(fee_quote))
+        .route("/simulate", post(simulate_relay))
+        .route("/status/:job_id", get(job_status));
+    // Status routes stay up while draining so clients can poll their jobs
+    let metered = shutdown::apply(
+        Router::new().route("/relay", post(relay_withdraw)),
+        shutdown.clone(),
+    );
+    let limited = auth::apply(open, protected, metered, auth);
+    // Preflight requests are answered before rate limits and API keys
+    let app = limits::apply(public, limited, &config.limits);
+    let app = cors::apply(app, &config.cors).with_state(state.clone());
+
+    if config.cors.origins.is_empty() {
+        info!("No CORS origins configured; browsers can't call the API");
+    } else {
+        info!("CORS origins: {}", config.cors.origins.join(", "));
+    }
+    let scheme = if config.tls.is_some() { "https" } else { "http" };
+    info!("Starting relayer on {}://{}", scheme, config.listen);
+
+    let handle = axum_server::Handle::new();
+    let drain_timeout = config.drain_timeout;
+    tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            shutdown::signal().await;
+            info!(
+                "Shutting down; waiting up to {:?} for {} in-flight withdrawals",
+                drain_timeout,
+                shutdown.in_flight()
+            );
+            shutdown.begin();
+            if !shutdown.drain(drain_timeout).await {
+                warn!(
+                    "{} withdrawals still in flight; they will be finalized on the next start",
+                    shutdown.in_flight()
+                );
+            }
+            handle.graceful_shutdown(Some(CONNECTION_GRACE));
+        }
+    });
+    server::serve(app, config.listen, config.tls.as_ref(), handle).await?;
+
+    state.jobs.flush().context("Failed to flush the job database")?;
+    info!("Relayer stopped");
 
     Ok(())
 }
@@ -0,0 +1,137 @@
+//! Prometheus metrics for the relayer
+//!
+//! Counters and histograms are recorded through the `metrics` facade and
+//! rendered in Prometheus text format by the `/metrics` route.
+
+use anyhow::Result;
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::time::Duration;
+
+use crate::simulate::SimulationFailure;
+
+const RELAYS_TOTAL: &str = "relayer_relays_total";
+const RELAY_SUCCESS_TOTAL: &str = "relayer_relay_success_total";
+const RELAY_FAILURES_TOTAL: &str = "relayer_relay_failures_total";
+const SUBMISSION_SECONDS: &str = "relayer_submission_duration_seconds";
+const BALANCE_SOL: &str = "relayer_balance_sol";
+const SIMULATIONS_TOTAL: &str = "relayer_simulations_total";
+const API_REQUESTS_TOTAL: &str = "relayer_api_key_requests_total";
+const API_RELAYS_TOTAL: &str = "relayer_api_key_relays_total";
+const API_QUOTA_EXCEEDED_TOTAL: &str = "relayer_api_key_quota_exceeded_total";
+const AUTH_FAILURES_TOTAL: &str = "relayer_auth_failures_total";
+const CORS_REJECTIONS_TOTAL: &str = "relayer_cors_rejections_total";
+
+/// Submission latency buckets in seconds (confirmation usually takes 1-30s)
+const SUBMISSION_BUCKETS: &[f64] = &[0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0];
+
+/// Failure category, exported as the `reason` label
+#[derive(Debug, Clone, Copy)]
+pub enum FailureKind {
+    /// Malformed request (bad base64/hex/pubkey)
+    InvalidInput,
+    /// The program rejected the proof
+    VerificationFailed,
+    /// RPC or transaction failure
+    ChainError,
+    /// Withdrawal fee below the relayer minimum
+    InsufficientFee,
+    /// Nullifiers already spent or being relayed
+    Duplicate,
+}
+
+impl FailureKind {
+    const ALL: [FailureKind; 5] = [
+        FailureKind::InvalidInput,
+        FailureKind::VerificationFailed,
+        FailureKind::ChainError,
+        FailureKind::InsufficientFee,
+        FailureKind::Duplicate,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            FailureKind::InvalidInput => "invalid_input",
+            FailureKind::VerificationFailed => "verification_failed",
+            FailureKind::ChainError => "chain_error",
+            FailureKind::InsufficientFee => "insufficient_fee",
+            FailureKind::Duplicate => "duplicate",
+        }
+    }
+}
+
+/// Install the global Prometheus recorder
+pub fn install() -> Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(SUBMISSION_SECONDS.to_string()),
+            SUBMISSION_BUCKETS,
+        )?
+        .install_recorder()?;
+
+    describe_counter!(RELAYS_TOTAL, "Relay requests received");
+    describe_counter!(RELAY_SUCCESS_TOTAL, "Relay requests that landed on chain");
+    describe_counter!(RELAY_FAILURES_TOTAL, "Relay requests that failed, by reason");
+    describe_histogram!(SUBMISSION_SECONDS, "Time spent sending and confirming a transaction");
+    describe_gauge!(BALANCE_SOL, "Relayer wallet balance in SOL");
+    describe_counter!(SIMULATIONS_TOTAL, "Simulated withdrawals, by result");
+    describe_counter!(API_REQUESTS_TOTAL, "Authenticated requests, by API key name");
+    describe_counter!(API_RELAYS_TOTAL, "Withdrawals counted against a quota, by API key name");
+    describe_counter!(API_QUOTA_EXCEEDED_TOTAL, "Withdrawals turned away by a quota, by API key name");
+    describe_counter!(AUTH_FAILURES_TOTAL, "Requests without a valid API key, by reason");
+    describe_counter!(CORS_REJECTIONS_TOTAL, "Browser requests from an origin not allowed by [cors]");
+
+    // Export every failure reason at zero so dashboards don't see gaps
+    for kind in FailureKind::ALL {
+        counter!(RELAY_FAILURES_TOTAL, "reason" => kind.as_str()).absolute(0);
+    }
+
+    Ok(handle)
+}
+
+pub fn record_request() {
+    counter!(RELAYS_TOTAL).increment(1);
+}
+
+pub fn record_success() {
+    counter!(RELAY_SUCCESS_TOTAL).increment(1);
+}
+
+pub fn record_failure(kind: FailureKind) {
+    counter!(RELAY_FAILURES_TOTAL, "reason" => kind.as_str()).increment(1);
+}
+
+/// `result` is `ok` or the lowercased failure code
+pub fn record_simulation(failure: Option<SimulationFailure>) {
+    let result = failure.map_or_else(|| "ok".to_string(), |f| f.code().to_ascii_lowercase());
+    counter!(SIMULATIONS_TOTAL, "result" => result).increment(1);
+}
+
+pub fn record_api_request(key: &str) {
+    counter!(API_REQUESTS_TOTAL, "key" => key.to_string()).increment(1);
+}
+
+pub fn record_api_usage(key: &str) {
+    counter!(API_RELAYS_TOTAL, "key" => key.to_string()).increment(1);
+}
+
+pub fn record_quota_exceeded(key: &str) {
+    counter!(API_QUOTA_EXCEEDED_TOTAL, "key" => key.to_string()).increment(1);
+}
+
+/// `reason` is `missing_key` or `invalid_key`
+pub fn record_auth_failure(reason: &'static str) {
+    counter!(AUTH_FAILURES_TOTAL, "reason" => reason).increment(1);
+}
+
+pub fn record_cors_rejection() {
+    counter!(CORS_REJECTIONS_TOTAL).increment(1);
+}
+
+pub fn record_submission(elapsed: Duration) {
+    histogram!(SUBMISSION_SECONDS).record(elapsed.as_secs_f64());
+}
+
+pub fn set_balance(lamports: u64) {
+    gauge!(BALANCE_SOL).set(lamports as f64 / 1_000_000_000.0);
+}
@@ -0,0 +1,351 @@
+//! Aggregate, privacy-preserving statistics about the pool's anonymity
+//!
+//! Events are reconstructed from the tree account's transaction history.
+//! Only aggregates leave this module: a `PoolEvent` keeps the direction,
+//! amount, time and whether the withdrawal was relayed, and never the
+//! signer, recipient or nullifiers it was parsed from.
+
+use anyhow::Result;
+use serde::Serialize;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use solana_transaction_status::UiTransactionEncoding;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use privacy_zig_core::instruction::{self, RECIPIENT_ACCOUNT, SIGNER_ACCOUNT};
+
+use crate::rpc::ClientPool;
+
+/// Amounts are grouped into 0.01 SOL denominations
+const DENOMINATION_LAMPORTS: u64 = 10_000_000;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Upper bounds (exclusive, in seconds) of the withdrawal gap buckets
+const GAP_BUCKETS: [(i64, &str); 6] = [
+    (60, "<1m"),
+    (600, "1m-10m"),
+    (3_600, "10m-1h"),
+    (21_600, "1h-6h"),
+    (86_400, "6h-24h"),
+    (604_800, "1d-7d"),
+];
+const GAP_OVERFLOW: &str = ">7d";
+const GAP_NO_PRIOR: &str = "no_prior_deposit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Deposit,
+    Withdrawal,
+}
+
+/// A transact instruction observed on chain, stripped of identifiers
+#[derive(Debug, Clone)]
+pub struct PoolEvent {
+    pub kind: EventKind,
+    pub amount: u64,
+    pub block_time: i64,
+    /// Withdrawal paid for by someone other than the recipient
+    pub relayed: bool,
+}
+
+impl PoolEvent {
+    /// Parse a transact instruction given its data and resolved account keys
+    pub fn parse(data: &[u8], accounts: &[Pubkey], block_time: i64) -> Option<Self> {
+        let public_amount = instruction::public_amount(data)?;
+        let kind = match public_amount {
+            a if a > 0 => EventKind::Deposit,
+            a if a < 0 => EventKind::Withdrawal,
+            _ => return None,
+        };
+
+        let relayed = kind == EventKind::Withdrawal
+            && accounts.get(SIGNER_ACCOUNT) != accounts.get(RECIPIENT_ACCOUNT);
+
+        Some(Self {
+            kind,
+            amount: public_amount.unsigned_abs(),
+            block_time,
+            relayed,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GapBucket {
+    pub bucket: &'static str,
+    pub withdrawals: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnonymitySetPoint {
+    /// UTC day (YYYY-MM-DD) the set size was observed at
+    pub day: String,
+    pub denomination_sol: f64,
+    /// Deposits of this denomination up to and including `day`
+    pub set_size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResearchReport {
+    pub deposits: usize,
+    pub withdrawals: usize,
+    pub relayed_withdrawals: usize,
+    pub direct_withdrawals: usize,
+    pub relayed_fraction: f64,
+    /// Time between each withdrawal and the nearest prior deposit of the
+    /// same denomination
+    pub withdrawal_gaps: Vec<GapBucket>,
+    pub anonymity_sets: Vec<AnonymitySetPoint>,
+}
+
+fn denomination(amount: u64) -> u64 {
+    (amount + DENOMINATION_LAMPORTS / 2) / DENOMINATION_LAMPORTS
+}
+
+fn gap_bucket(gap: i64) -> &'static str {
+    GAP_BUCKETS
+        .iter()
+        .find(|(bound, _)| gap < *bound)
+        .map(|(_, label)| *label)
+        .unwrap_or(GAP_OVERFLOW)
+}
+
+fn format_day(day: i64) -> String {
+    chrono::DateTime::from_timestamp(day * SECONDS_PER_DAY, 0)
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+/// Aggregate events into a report
+pub fn aggregate(events: &[PoolEvent]) -> ResearchReport {
+    let mut events = events.to_vec();
+    events.sort_by_key(|e| e.block_time);
+
+    let mut last_deposit: HashMap<u64, i64> = HashMap::new();
+    let mut deposits_so_far: HashMap<u64, u64> = HashMap::new();
+    let mut gaps: BTreeMap<&'static str, usize> = BTreeMap::new();
+    // (day, denomination) -> set size at the end of that day
+    let mut sets: BTreeMap<(i64, u64), u64> = BTreeMap::new();
+
+    let (mut deposits, mut withdrawals, mut relayed) = (0, 0, 0);
+
+    for event in &events {
+        let denom = denomination(event.amount);
+        match event.kind {
+            EventKind::Deposit => {
+                deposits += 1;
+                last_deposit.insert(denom, event.block_time);
+                let count = deposits_so_far.entry(denom).or_insert(0);
+                *count += 1;
+                sets.insert((event.block_time.div_euclid(SECONDS_PER_DAY), denom), *count);
+            }
+            EventKind::Withdrawal => {
+                withdrawals += 1;
+                if event.relayed {
+                    relayed += 1;
+                }
+                let bucket = match last_deposit.get(&denom) {
+                    Some(deposited_at) => gap_bucket(event.block_time - deposited_at),
+                    None => GAP_NO_PRIOR,
+                };
+                *gaps.entry(bucket).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut withdrawal_gaps: Vec<GapBucket> = GAP_BUCKETS
+        .iter()
+        .map(|(_, label)| *label)
+        .chain([GAP_OVERFLOW, GAP_NO_PRIOR])
+        .map(|bucket| GapBucket {
+            bucket,
+            withdrawals: gaps.get(bucket).copied().unwrap_or(0),
+        })
+        .collect();
+    withdrawal_gaps.retain(|g| g.withdrawals > 0 || g.bucket != GAP_NO_PRIOR);
+
+    let anonymity_sets = sets
+        .into_iter()
+        .map(|((day, denom), set_size)| AnonymitySetPoint {
+            day: format_day(day),
+            denomination_sol: (denom * DENOMINATION_LAMPORTS) as f64 / 1_000_000_000.0,
+            set_size,
+        })
+        .collect();
+
+    ResearchReport {
+        deposits,
+        withdrawals,
+        relayed_withdrawals: relayed,
+        direct_withdrawals: withdrawals - relayed,
+        relayed_fraction: if withdrawals == 0 {
+            0.0
+        } else {
+            relayed as f64 / withdrawals as f64
+        },
+        withdrawal_gaps,
+        anonymity_sets,
+    }
+}
+
+impl ResearchReport {
+    /// Render as CSV with `section,key,denomination_sol,value` columns
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("section,key,denomination_sol,value\n");
+        let summary = [
+            ("deposits", self.deposits.to_string()),
+            ("withdrawals", self.withdrawals.to_string()),
+            ("relayed_withdrawals", self.relayed_withdrawals.to_string()),
+            ("direct_withdrawals", self.direct_withdrawals.to_string()),
+            ("relayed_fraction", format!("{:.4}", self.relayed_fraction)),
+        ];
+        for (key, value) in summary {
+            let _ = writeln!(out, "summary,{},,{}", key, value);
+        }
+        for gap in &self.withdrawal_gaps {
+            let _ = writeln!(out, "withdrawal_gap,{},,{}", gap.bucket, gap.withdrawals);
+        }
+        for point in &self.anonymity_sets {
+            let _ = writeln!(
+                out,
+                "anonymity_set,{},{:.2},{}",
+                point.day, point.denomination_sol, point.set_size
+            );
+        }
+        out
+    }
+}
+
+/// Reconstruct pool events from the tree account's recent history
+pub fn fetch_events(client: &ClientPool, tree_account: &Pubkey) -> Result<Vec<PoolEvent>> {
+    let signatures = client.call(|c| c.get_signatures_for_address(tree_account))?;
+    let mut events = Vec::new();
+
+    for sig_info in signatures {
+        if sig_info.err.is_some() {
+            continue;
+        }
+        let (Ok(signature), Some(block_time)) = (sig_info.signature.parse(), sig_info.block_time)
+        else {
+            continue;
+        };
+
+        let Ok(tx) = client.call(|c| {
+            c.get_transaction_with_config(
+                &signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+        }) else {
+            continue;
+        };
+
+        let Some(decoded) = tx.transaction.transaction.decode() else {
+            continue;
+        };
+        let keys = decoded.message.static_account_keys();
+        for ix in decoded.message.instructions() {
+            let accounts: Vec<Pubkey> = ix
+                .accounts
+                .iter()
+                .filter_map(|&i| keys.get(i as usize).copied())
+                .collect();
+            if let Some(event) = PoolEvent::parse(&ix.data, &accounts, block_time) {
+                events.push(event);
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use privacy_zig_core::instruction::{PUBLIC_AMOUNT_OFFSET, TRANSACT_DATA_LEN, TRANSACT_DISCRIMINATOR};
+
+    fn transact_data(public_amount: i64, nullifier_byte: u8) -> Vec<u8> {
+        let mut data = vec![0u8; TRANSACT_DATA_LEN];
+        data[0..8].copy_from_slice(&TRANSACT_DISCRIMINATOR);
+        // nullifier1/nullifier2 follow proof(256) and root(32)
+        data[296..360].fill(nullifier_byte);
+        data[PUBLIC_AMOUNT_OFFSET..PUBLIC_AMOUNT_OFFSET + 8]
+            .copy_from_slice(&public_amount.to_le_bytes());
+        data
+    }
+
+    fn accounts(signer: Pubkey, recipient: Pubkey) -> Vec<Pubkey> {
+        let mut keys: Vec<Pubkey> = (0..9).map(|_| Pubkey::new_unique()).collect();
+        keys[SIGNER_ACCOUNT] = signer;
+        keys[RECIPIENT_ACCOUNT] = recipient;
+        keys
+    }
+
+    #[test]
+    fn test_aggregate_counts_and_gaps() {
+        let depositor = Pubkey::new_unique();
+        let relayer = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+
+        let events = vec![
+            PoolEvent::parse(&transact_data(100_000_000, 1), &accounts(depositor, depositor), 0)
+                .unwrap(),
+            PoolEvent::parse(&transact_data(-100_000_000, 2), &accounts(relayer, recipient), 30)
+                .unwrap(),
+            PoolEvent::parse(&transact_data(-500_000_000, 3), &accounts(recipient, recipient), 90)
+                .unwrap(),
+        ];
+
+        let report = aggregate(&events);
+        assert_eq!(report.deposits, 1);
+        assert_eq!(report.withdrawals, 2);
+        assert_eq!(report.relayed_withdrawals, 1);
+        assert_eq!(report.direct_withdrawals, 1);
+        assert_eq!(report.withdrawal_gaps[0].bucket, "<1m");
+        assert_eq!(report.withdrawal_gaps[0].withdrawals, 1);
+        let no_prior = report
+            .withdrawal_gaps
+            .iter()
+            .find(|g| g.bucket == GAP_NO_PRIOR)
+            .unwrap();
+        assert_eq!(no_prior.withdrawals, 1);
+        assert_eq!(report.anonymity_sets.len(), 1);
+        assert_eq!(report.anonymity_sets[0].set_size, 1);
+    }
+
+    #[test]
+    fn test_report_never_contains_identifiers() {
+        let relayer = Pubkey::new_unique();
+        let recipients: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+
+        let mut events = Vec::new();
+        for (i, recipient) in recipients.iter().enumerate() {
+            let t = i as i64 * 3_600;
+            events.push(
+                PoolEvent::parse(&transact_data(200_000_000, 0xAA), &accounts(*recipient, *recipient), t)
+                    .unwrap(),
+            );
+            events.push(
+                PoolEvent::parse(&transact_data(-200_000_000, 0xBB), &accounts(relayer, *recipient), t + 120)
+                    .unwrap(),
+            );
+        }
+
+        let report = aggregate(&events);
+        let outputs = [serde_json::to_string(&report).unwrap(), report.to_csv()];
+        let nullifier_hex = [hex::encode([0xAAu8; 32]), hex::encode([0xBBu8; 32])];
+
+        for output in &outputs {
+            for key in recipients.iter().chain([&relayer]) {
+                assert!(!output.contains(&key.to_string()));
+            }
+            for nullifier in &nullifier_hex {
+                assert!(!output.contains(nullifier.as_str()));
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! RPC endpoints with failover
+//!
+//! `rpc_url` (or `RPC_URLS`) takes a comma-separated list of endpoints, in
+//! order of preference. Every call goes through a [`ClientPool`], which
+//! moves on to the next endpoint when one is rate limited, failing or
+//! unreachable, and returns to the first once it is healthy again.
+
+use privacy_zig_core::{Endpoint, RpcPool, DEFAULT_REPROBE_INTERVAL};
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_client::RpcClient,
+};
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::ops::Deref;
+
+/// An `RpcClient` the [`ClientPool`] can fail over from
+pub struct RpcNode(RpcClient);
+
+impl Deref for RpcNode {
+    type Target = RpcClient;
+
+    fn deref(&self) -> &RpcClient {
+        &self.0
+    }
+}
+
+impl Endpoint for RpcNode {
+    type Error = ClientError;
+
+    fn url(&self) -> String {
+        self.0.url()
+    }
+
+    fn is_unavailable(err: &ClientError) -> bool {
+        is_unavailable(err)
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.0.get_health().is_ok()
+    }
+}
+
+/// Rate limits and server errors surface as HTTP errors, timeouts and
+/// refused connections as I/O errors
+pub fn is_unavailable(err: &ClientError) -> bool {
+    matches!(err.kind(), ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_))
+}
+
+pub type ClientPool = RpcPool<RpcNode>;
+
+pub fn connect(urls: &[String]) -> ClientPool {
+    let nodes = urls
+        .iter()
+        .map(|url| {
+            RpcNode(RpcClient::new_with_commitment(
+                url.clone(),
+                CommitmentConfig::confirmed(),
+            ))
+        })
+        .collect();
+    RpcPool::new(nodes, DEFAULT_REPROBE_INTERVAL)
+}
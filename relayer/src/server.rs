@@ -0,0 +1,144 @@
+//! Serving the API over HTTP, or HTTPS with rustls
+//!
+//! With a `[tls]` table (or `RELAYER_TLS_CERT` and `RELAYER_TLS_KEY`) the
+//! relayer terminates TLS itself, so a browser frontend can reach it without
+//! a proxy in front. Both are PEM files; the certificate file may hold the
+//! whole chain, leaf first.
+//!
+//! ```toml
+//! [tls]
+//! cert = "/etc/relayer/fullchain.pem"
+//! key = "/etc/relayer/privkey.pem"
+//! ```
+
+use anyhow::{Context, Result};
+use axum::Router;
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// PEM certificate chain (`RELAYER_TLS_CERT`)
+    pub cert: PathBuf,
+    /// PEM private key (`RELAYER_TLS_KEY`)
+    pub key: PathBuf,
+}
+
+/// Serve `app` on `listen` until `handle` shuts the server down
+pub async fn serve(
+    app: Router,
+    listen: SocketAddr,
+    tls: Option<&TlsConfig>,
+    handle: Handle,
+) -> Result<()> {
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    match tls {
+        Some(tls) => {
+            // axum-server brings in aws-lc-rs and the Solana client ring, and
+            // rustls won't pick between them on its own
+            let _ = rustls::crypto::ring::default_provider().install_default();
+            let rustls = RustlsConfig::from_pem_file(&tls.cert, &tls.key)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to load the TLS certificate {} and key {}",
+                        tls.cert.display(),
+                        tls.key.display()
+                    )
+                })?;
+            axum_server::bind_rustls(listen, rustls)
+                .handle(handle)
+                .serve(app)
+                .await
+        }
+        None => axum_server::bind(listen).handle(handle).serve(app).await,
+    }
+    .with_context(|| format!("Failed to serve on {}", listen))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use std::path::Path;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const LOCALHOST: &str = "127.0.0.1:0";
+
+    /// A test CA, and a certificate for localhost it signed that is valid
+    /// until 2126
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/tls")
+            .join(name)
+    }
+
+    /// Start a server with a `/health` route and wait until it listens
+    async fn start(tls: Option<TlsConfig>) -> (Handle, SocketAddr) {
+        let app = Router::new().route("/health", get(|| async { "OK" }));
+        let handle = Handle::new();
+        let server = handle.clone();
+        tokio::spawn(async move {
+            serve(app, LOCALHOST.parse().unwrap(), tls.as_ref(), server)
+                .await
+                .unwrap()
+        });
+        let addr = handle.listening().await.expect("server failed to start");
+        (handle, addr)
+    }
+
+    #[tokio::test]
+    async fn serves_plain_http() {
+        let (handle, addr) = start(None).await;
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("OK"), "{}", response);
+
+        handle.shutdown();
+    }
+
+    #[tokio::test]
+    async fn serves_https() {
+        let tls = TlsConfig {
+            cert: fixture("cert.pem"),
+            key: fixture("key.pem"),
+        };
+        let (handle, addr) = start(Some(tls)).await;
+
+        let ca = std::fs::read(fixture("ca.pem")).unwrap();
+        let client = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(&ca).unwrap())
+            .build()
+            .unwrap();
+        let url = format!("https://localhost:{}/health", addr.port());
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "OK");
+
+        // Plain HTTP is not answered on the TLS port
+        let plain = url.replacen("https", "http", 1);
+        assert!(reqwest::get(&plain).await.is_err());
+
+        handle.shutdown();
+    }
+
+    #[tokio::test]
+    async fn reports_unreadable_certificates() {
+        let tls = TlsConfig {
+            cert: fixture("missing.pem"),
+            key: fixture("key.pem"),
+        };
+        let err = serve(Router::new(), LOCALHOST.parse().unwrap(), Some(&tls), Handle::new())
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("missing.pem"), "{:#}", err);
+    }
+}
@@ -0,0 +1,235 @@
+//! Graceful shutdown
+//!
+//! Killing the relayer between sending a transaction and confirming it
+//! leaves the client without a signature. On SIGTERM or SIGINT it instead
+//! stops taking withdrawals, so `/relay` returns `503`, while the status
+//! endpoints keep answering and clients can still poll their jobs. It then
+//! waits up to `drain_timeout_secs` (`RELAYER_DRAIN_TIMEOUT_SECS`) for relay
+//! requests being handled and queued jobs to finish before exiting. Jobs
+//! still unfinished at the deadline stay in the job database and are
+//! finalized from their signatures on the next start.
+
+use axum::{
+    extract::{Request, State},
+    http::{header::RETRY_AFTER, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Tracks work that has to finish before the relayer exits
+#[derive(Default)]
+pub struct Shutdown {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+/// A relay request or queued job; shutdown waits until it is dropped
+pub struct InFlight(Arc<Shutdown>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Make shutdown wait for the caller, even once draining has begun
+    pub fn track(self: &Arc<Self>) -> InFlight {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(self.clone())
+    }
+
+    /// Like [`Self::track`], unless draining has begun
+    fn admit(self: &Arc<Self>) -> Option<InFlight> {
+        // Counted before the check, so `drain` can't miss a request that
+        // got past it
+        let in_flight = self.track();
+        (!self.is_draining()).then_some(in_flight)
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Relay requests and queued jobs not finished yet
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Stop admitting relay requests
+    pub fn begin(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    /// Wait until nothing is in flight, for at most `timeout`; returns
+    /// whether everything finished
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let idle = async {
+            loop {
+                let notified = self.idle.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                if self.in_flight() == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        tokio::time::timeout(timeout, idle).await.is_ok()
+    }
+}
+
+async fn reject_when_draining(
+    State(shutdown): State<Arc<Shutdown>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match shutdown.admit() {
+        Some(_in_flight) => next.run(request).await,
+        None => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(RETRY_AFTER, "30")],
+            Json(serde_json::json!({
+                "success": false,
+                "error": "The relayer is shutting down; try again shortly",
+            })),
+        )
+            .into_response(),
+    }
+}
+
+/// Turn `routes` away with `503` once draining begins, and make shutdown
+/// wait for requests already being handled
+pub fn apply<S>(routes: Router<S>, shutdown: Arc<Shutdown>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    routes.route_layer(middleware::from_fn_with_state(
+        shutdown,
+        reject_when_draining,
+    ))
+}
+
+/// Resolve on SIGTERM or SIGINT (Ctrl-C)
+pub async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for SIGINT");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{to_bytes, Body},
+        routing::{get, post},
+    };
+    use tower::ServiceExt;
+
+    /// How long the mocked RPC node takes to confirm a withdrawal
+    const CONFIRM_TIME: Duration = Duration::from_secs(10);
+
+    fn app(shutdown: &Arc<Shutdown>) -> Router {
+        let relay = Router::new().route(
+            "/relay",
+            post(|| async {
+                tokio::time::sleep(CONFIRM_TIME).await;
+                "confirmed"
+            }),
+        );
+        Router::new()
+            .route("/status/:job_id", get(|| async { "submitted" }))
+            .merge(apply(relay, shutdown.clone()))
+    }
+
+    async fn send(app: &Router, method: &str, uri: &str) -> Response {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn finishes_started_requests_and_rejects_new_ones() {
+        let shutdown = Shutdown::new();
+        let app = app(&shutdown);
+
+        let started = tokio::spawn({
+            let app = app.clone();
+            async move { send(&app, "POST", "/relay").await }
+        });
+        while shutdown.in_flight() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        shutdown.begin();
+        let drain = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.drain(Duration::from_secs(60)).await }
+        });
+
+        let late = send(&app, "POST", "/relay").await;
+        assert_eq!(late.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(late.headers()[RETRY_AFTER], "30");
+        // Clients can still poll while the relayer drains
+        let status = send(&app, "GET", "/status/abc").await;
+        assert_eq!(status.status(), StatusCode::OK);
+        assert!(!drain.is_finished());
+
+        let response = started.await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"confirmed");
+        assert!(drain.await.unwrap());
+        assert_eq!(shutdown.in_flight(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_queued_jobs_up_to_the_timeout() {
+        let shutdown = Shutdown::new();
+        assert!(shutdown.drain(Duration::from_secs(1)).await);
+
+        let job = shutdown.track();
+        shutdown.begin();
+        assert!(!shutdown.drain(Duration::from_secs(5)).await);
+
+        let drain = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.drain(Duration::from_secs(5)).await }
+        });
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        drop(job);
+        assert!(drain.await.unwrap());
+    }
+}
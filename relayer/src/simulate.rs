@@ -0,0 +1,221 @@
+//! Pre-flight simulation of relayed transactions
+//!
+//! Simulating before sending keeps doomed withdrawals (spent notes, stale
+//! roots, bad proofs) from costing the relayer a fee, and lets us tell the
+//! client why instead of returning a raw RPC error.
+//!
+//! `/relay` simulates before queueing and the worker again right before
+//! sending, since the root history or the vault may have changed in between.
+//! `POST /simulate` runs the same checks for clients that want to know
+//! whether a withdrawal would go through without submitting it.
+
+use serde::Serialize;
+use solana_sdk::{instruction::InstructionError, transaction::TransactionError};
+
+/// Custom error codes of the privacy-pool program
+const INVALID_PROOF: u32 = 6000;
+const INVALID_ROOT: u32 = 6001;
+const NULLIFIER_ALREADY_USED: u32 = 6002;
+const TREE_FULL: u32 = 6003;
+const INSUFFICIENT_FUNDS: u32 = 6005;
+
+/// Names of the program's custom errors, from `errors` in its IDL
+const PROGRAM_ERRORS: [(u32, &str); 7] = [
+    (INVALID_PROOF, "InvalidProof"),
+    (INVALID_ROOT, "InvalidRoot"),
+    (NULLIFIER_ALREADY_USED, "NullifierAlreadyUsed"),
+    (TREE_FULL, "TreeFull"),
+    (6004, "DepositLimitExceeded"),
+    (INSUFFICIENT_FUNDS, "InsufficientFunds"),
+    (6006, "Unauthorized"),
+];
+
+/// Name of a privacy-pool error code
+pub fn program_error_name(code: u32) -> Option<&'static str> {
+    PROGRAM_ERRORS
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| *name)
+}
+
+/// Why a simulated transaction failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulationFailure {
+    InvalidProof,
+    UnknownRoot,
+    NullifierUsed,
+    PoolUnderfunded,
+    TreeFull,
+    Other,
+}
+
+impl SimulationFailure {
+    pub fn classify(err: &TransactionError, logs: &[String]) -> Self {
+        if let TransactionError::InstructionError(_, InstructionError::Custom(code)) = err {
+            match *code {
+                INVALID_PROOF => return Self::InvalidProof,
+                INVALID_ROOT => return Self::UnknownRoot,
+                NULLIFIER_ALREADY_USED => return Self::NullifierUsed,
+                INSUFFICIENT_FUNDS => return Self::PoolUnderfunded,
+                TREE_FULL => return Self::TreeFull,
+                _ => {}
+            }
+        }
+        // A spent nullifier usually surfaces as the system program refusing
+        // to create its PDA a second time
+        if logs.iter().any(|l| l.contains("already in use")) {
+            return Self::NullifierUsed;
+        }
+        Self::Other
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            Self::InvalidProof => "The proof is invalid for these public inputs",
+            Self::UnknownRoot => {
+                "The Merkle root is not in the pool's recent root history; regenerate the proof"
+            }
+            Self::NullifierUsed => "This note has already been spent",
+            Self::PoolUnderfunded => "The pool vault can't cover this withdrawal",
+            Self::TreeFull => "The pool's Merkle tree is full",
+            Self::Other => "The transaction failed in simulation",
+        }
+    }
+
+    /// Machine-readable reason, returned as `code`
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::InvalidProof => "INVALID_PROOF",
+            Self::UnknownRoot => "UNKNOWN_ROOT",
+            Self::NullifierUsed => "NULLIFIER_USED",
+            Self::PoolUnderfunded => "POOL_UNDERFUNDED",
+            Self::TreeFull => "TREE_FULL",
+            Self::Other => "SIMULATION_FAILED",
+        }
+    }
+}
+
+/// Client-facing error: a friendly summary followed by the simulation logs
+pub fn describe(err: &TransactionError, logs: &[String]) -> (SimulationFailure, String) {
+    let failure = SimulationFailure::classify(err, logs);
+    let mut message = match err {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+            match program_error_name(*code) {
+                Some(name) => format!("{} ({}: {})", failure.message(), name, err),
+                None => format!("{} ({})", failure.message(), err),
+            }
+        }
+        _ => format!("{} ({})", failure.message(), err),
+    };
+    if !logs.is_empty() {
+        message.push_str("\nSimulation logs:\n");
+        message.push_str(&logs.join("\n"));
+    }
+    (failure, message)
+}
+
+/// Outcome of simulating a withdrawal
+pub struct Simulation {
+    pub err: Option<TransactionError>,
+    /// `None` if the simulation succeeded
+    pub failure: Option<SimulationFailure>,
+    pub logs: Vec<String>,
+    pub units_consumed: Option<u64>,
+}
+
+/// `POST /simulate`
+#[derive(Debug, Serialize)]
+pub struct SimulateResponse {
+    pub success: bool,
+    pub error: Option<String>,
+    pub code: Option<&'static str>,
+    pub logs: Vec<String>,
+    pub units_consumed: Option<u64>,
+}
+
+impl Simulation {
+    pub fn new(
+        err: Option<TransactionError>,
+        logs: Vec<String>,
+        units_consumed: Option<u64>,
+    ) -> Self {
+        let failure = err.as_ref().map(|e| SimulationFailure::classify(e, &logs));
+        Self {
+            err,
+            failure,
+            logs,
+            units_consumed,
+        }
+    }
+
+    /// The client-facing error, `None` if the simulation succeeded
+    pub fn describe(&self) -> Option<String> {
+        self.err.as_ref().map(|e| describe(e, &self.logs).1)
+    }
+
+    pub fn into_response(self) -> SimulateResponse {
+        SimulateResponse {
+            success: self.err.is_none(),
+            error: self.err.as_ref().map(|e| describe(e, &[]).1),
+            code: self.failure.map(SimulationFailure::code),
+            logs: self.logs,
+            units_consumed: self.units_consumed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(code: u32) -> TransactionError {
+        TransactionError::InstructionError(1, InstructionError::Custom(code))
+    }
+
+    #[test]
+    fn maps_program_errors() {
+        assert_eq!(SimulationFailure::classify(&custom(6000), &[]), SimulationFailure::InvalidProof);
+        assert_eq!(SimulationFailure::classify(&custom(6001), &[]), SimulationFailure::UnknownRoot);
+        assert_eq!(SimulationFailure::classify(&custom(6002), &[]), SimulationFailure::NullifierUsed);
+        assert_eq!(SimulationFailure::classify(&custom(6003), &[]), SimulationFailure::TreeFull);
+        assert_eq!(SimulationFailure::classify(&custom(42), &[]), SimulationFailure::Other);
+    }
+
+    #[test]
+    fn names_program_errors() {
+        let (_, message) = describe(&custom(6001), &[]);
+        assert!(message.starts_with("The Merkle root is not in the pool's recent root history"));
+        assert!(message.contains("InvalidRoot"));
+        assert_eq!(program_error_name(6006), Some("Unauthorized"));
+        assert_eq!(program_error_name(1), None);
+    }
+
+    #[test]
+    fn simulate_response_keeps_logs_separate() {
+        let logs = vec!["Program log: Error: InvalidProof".to_string()];
+        let response =
+            Simulation::new(Some(custom(6000)), logs.clone(), Some(152_000)).into_response();
+        assert!(!response.success);
+        assert_eq!(response.code, Some("INVALID_PROOF"));
+        assert!(!response.error.unwrap().contains("Simulation logs"));
+        assert_eq!(response.logs, logs);
+        assert_eq!(response.units_consumed, Some(152_000));
+
+        let response = Simulation::new(None, vec![], Some(150_000)).into_response();
+        assert!(response.success);
+        assert_eq!(response.code, None);
+    }
+
+    #[test]
+    fn detects_existing_nullifier_account() {
+        let logs = vec![
+            "Program 11111111111111111111111111111111 invoke [2]".to_string(),
+            "Create Account: account Address { address: 5Xy.., base: None } already in use"
+                .to_string(),
+        ];
+        let (failure, message) = describe(&custom(0), &logs);
+        assert_eq!(failure, SimulationFailure::NullifierUsed);
+        assert!(message.starts_with("This note has already been spent"));
+        assert!(message.contains("already in use"));
+    }
+}
@@ -0,0 +1,381 @@
+//! Sending relayed transactions with retries
+//!
+//! Under load the blockhash can expire before a transaction lands, and the
+//! RPC node can drop the connection. Such failures are retried with a fresh
+//! blockhash, re-signing the same instructions, after a jittered exponential
+//! backoff. Program errors (a spent nullifier, an invalid proof) are returned
+//! immediately. Before each retry we check whether an earlier attempt landed
+//! after all, and whether the nullifiers have been consumed by someone else,
+//! so a withdrawal is never submitted twice.
+
+use solana_client::client_error::{ClientError, Result as ClientResult};
+use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    transaction::{self, Transaction, TransactionError},
+};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::rpc::{self, ClientPool};
+
+/// The RPC calls sending needs, so tests can fail them on purpose
+pub trait SubmitRpc {
+    fn latest_blockhash(&self) -> ClientResult<Hash>;
+    fn send_and_confirm(&self, tx: &Transaction) -> ClientResult<Signature>;
+    /// Outcome of each signature, `None` where it hasn't landed
+    fn signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> ClientResult<Vec<Option<transaction::Result<()>>>>;
+    fn account_exists(&self, pubkey: &Pubkey) -> ClientResult<bool>;
+}
+
+/// A send that fails over re-sends the same signed transaction, which can
+/// land at most once
+impl SubmitRpc for ClientPool {
+    fn latest_blockhash(&self) -> ClientResult<Hash> {
+        self.call(|c| c.get_latest_blockhash())
+    }
+
+    fn send_and_confirm(&self, tx: &Transaction) -> ClientResult<Signature> {
+        self.call(|c| c.send_and_confirm_transaction(tx))
+    }
+
+    fn signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> ClientResult<Vec<Option<transaction::Result<()>>>> {
+        Ok(self
+            .call(|c| c.get_signature_statuses(signatures))?
+            .value
+            .into_iter()
+            .map(|status| status.map(|s| s.status))
+            .collect())
+    }
+
+    fn account_exists(&self, pubkey: &Pubkey) -> ClientResult<bool> {
+        Ok(self
+            .call(|c| c.get_account_with_commitment(pubkey, c.commitment()))?
+            .value
+            .is_some())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt (`RELAYER_MAX_RETRIES`)
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each one after it
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.base_delay.saturating_mul(1 << retry.min(16))
+    }
+
+    /// [`backoff`](Self::backoff) scaled into its upper half by `jitter`
+    /// in `[0, 1)`, so relayers retrying together don't hit the node at
+    /// the same moment
+    pub fn jittered(&self, retry: u32, jitter: f64) -> Duration {
+        let backoff = self.backoff(retry);
+        backoff / 2 + backoff.mul_f64(jitter.clamp(0.0, 1.0) / 2.0)
+    }
+}
+
+/// What to do about a failed send
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// The blockhash went stale before the transaction landed
+    BlockhashExpired,
+    /// The RPC node couldn't be reached or timed out
+    Transient,
+    /// This exact transaction already landed
+    AlreadyProcessed,
+    /// Anything else, including every program error
+    Fatal,
+}
+
+pub enum SubmitError {
+    Client(ClientError),
+    /// The nullifiers were consumed, but not by any of our attempts
+    NullifierSpent,
+}
+
+impl From<ClientError> for SubmitError {
+    fn from(e: ClientError) -> Self {
+        SubmitError::Client(e)
+    }
+}
+
+pub fn classify(err: &ClientError) -> Failure {
+    match err.get_transaction_error() {
+        Some(TransactionError::BlockhashNotFound) => return Failure::BlockhashExpired,
+        Some(TransactionError::AlreadyProcessed) => return Failure::AlreadyProcessed,
+        // The program rejected it; sending it again can't help
+        Some(_) => return Failure::Fatal,
+        None => {}
+    }
+    if rpc::is_unavailable(err) {
+        return Failure::Transient;
+    }
+    let message = err.to_string();
+    if message.contains("Blockhash not found")
+        || message.contains("block height exceeded")
+        || message.contains("unable to confirm transaction")
+    {
+        Failure::BlockhashExpired
+    } else if message.contains("already been processed") {
+        Failure::AlreadyProcessed
+    } else {
+        Failure::Fatal
+    }
+}
+
+/// Sign and send `instructions`, retrying on blockhash expiry and transient
+/// RPC errors
+///
+/// `nullifier_pdas` are the accounts the transaction creates; if one of them
+/// appears without any attempt having landed, the note was spent elsewhere.
+/// `on_send` is called with each attempt's signature before it is sent.
+/// Blocks on the RPC calls and the backoff, so call it from a blocking
+/// thread.
+pub fn send_with_retry(
+    client: &impl SubmitRpc,
+    instructions: &[Instruction],
+    payer: &Keypair,
+    nullifier_pdas: &[Pubkey],
+    first_blockhash: Hash,
+    policy: RetryPolicy,
+    mut on_send: impl FnMut(&Signature),
+) -> Result<Signature, SubmitError> {
+    let mut attempts: Vec<Signature> = Vec::new();
+    let mut blockhash = first_blockhash;
+
+    for retry in 0..=policy.max_retries {
+        if retry > 0 {
+            blockhash = client.latest_blockhash()?;
+        }
+        let tx = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&payer.pubkey()),
+            &[payer],
+            blockhash,
+        );
+        attempts.push(tx.signatures[0]);
+        on_send(&tx.signatures[0]);
+
+        let err = match client.send_and_confirm(&tx) {
+            Ok(signature) => return Ok(signature),
+            Err(e) => e,
+        };
+        let failure = classify(&err);
+        match failure {
+            Failure::AlreadyProcessed => return Ok(tx.signatures[0]),
+            Failure::BlockhashExpired | Failure::Transient if retry < policy.max_retries => {}
+            _ => return Err(err.into()),
+        }
+
+        // "Expired" attempts, and ones whose confirmation was lost with the
+        // connection, sometimes land anyway
+        if let Some(signature) = landed(client, &attempts)? {
+            return Ok(signature);
+        }
+        for pda in nullifier_pdas {
+            if client.account_exists(pda)? {
+                return Err(SubmitError::NullifierSpent);
+            }
+        }
+
+        let delay = policy.jittered(retry, rand::random());
+        warn!(
+            "Attempt {}/{} failed ({:?}: {}); retrying in {:?}",
+            retry + 1,
+            policy.max_retries + 1,
+            failure,
+            err,
+            delay
+        );
+        std::thread::sleep(delay);
+    }
+
+    unreachable!("the last attempt always returns")
+}
+
+/// First of our earlier attempts that was confirmed successfully
+fn landed(client: &impl SubmitRpc, attempts: &[Signature]) -> ClientResult<Option<Signature>> {
+    let statuses = client.signature_statuses(attempts)?;
+    Ok(attempts
+        .iter()
+        .zip(statuses)
+        .find(|(_, status)| matches!(status, Some(Ok(()))))
+        .map(|(signature, _)| *signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::instruction::InstructionError;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        max_retries: 3,
+        base_delay: Duration::from_millis(1),
+    };
+
+    /// Answers sends from a script and records what was sent
+    #[derive(Default)]
+    struct MockRpc {
+        sends: RefCell<VecDeque<ClientResult<()>>>,
+        sent: RefCell<Vec<Transaction>>,
+        blockhashes: RefCell<u8>,
+        nullifier_spent: bool,
+    }
+
+    impl MockRpc {
+        fn failing_with(errors: Vec<ClientResult<()>>) -> Self {
+            Self {
+                sends: RefCell::new(errors.into()),
+                ..Default::default()
+            }
+        }
+    }
+
+    impl SubmitRpc for MockRpc {
+        fn latest_blockhash(&self) -> ClientResult<Hash> {
+            *self.blockhashes.borrow_mut() += 1;
+            Ok(Hash::new_from_array([*self.blockhashes.borrow(); 32]))
+        }
+
+        fn send_and_confirm(&self, tx: &Transaction) -> ClientResult<Signature> {
+            self.sent.borrow_mut().push(tx.clone());
+            self.sends.borrow_mut().pop_front().unwrap_or(Ok(()))?;
+            Ok(tx.signatures[0])
+        }
+
+        fn signature_statuses(
+            &self,
+            signatures: &[Signature],
+        ) -> ClientResult<Vec<Option<transaction::Result<()>>>> {
+            Ok(vec![None; signatures.len()])
+        }
+
+        fn account_exists(&self, _pubkey: &Pubkey) -> ClientResult<bool> {
+            Ok(self.nullifier_spent)
+        }
+    }
+
+    fn send(rpc: &MockRpc) -> Result<Signature, SubmitError> {
+        let payer = Keypair::new();
+        let ix = Instruction::new_with_bytes(Pubkey::new_unique(), &[1, 2, 3], vec![]);
+        let mut recorded = Vec::new();
+        let result = send_with_retry(
+            rpc,
+            &[ix],
+            &payer,
+            &[Pubkey::new_unique()],
+            Hash::default(),
+            POLICY,
+            |signature| recorded.push(*signature),
+        );
+        assert_eq!(recorded, rpc.sent.borrow().iter().map(|tx| tx.signatures[0]).collect::<Vec<_>>());
+        result
+    }
+
+    fn io_error() -> ClientError {
+        std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset").into()
+    }
+
+    #[test]
+    fn retries_expired_blockhash_with_fresh_signature() {
+        let rpc = MockRpc::failing_with(vec![Err(TransactionError::BlockhashNotFound.into())]);
+        let signature = send(&rpc).ok().unwrap();
+        let sent = rpc.sent.borrow();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(signature, sent[1].signatures[0]);
+        // Same instruction, re-signed over a new blockhash
+        assert_ne!(sent[0].message.recent_blockhash, sent[1].message.recent_blockhash);
+        assert_eq!(sent[0].message.instructions, sent[1].message.instructions);
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let rpc = MockRpc::failing_with(vec![Err(io_error()), Err(io_error())]);
+        assert!(send(&rpc).is_ok());
+        assert_eq!(rpc.sent.borrow().len(), 3);
+    }
+
+    #[test]
+    fn already_processed_is_success() {
+        let rpc = MockRpc::failing_with(vec![Err(TransactionError::AlreadyProcessed.into())]);
+        let signature = send(&rpc).ok().unwrap();
+        let sent = rpc.sent.borrow();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(signature, sent[0].signatures[0]);
+    }
+
+    #[test]
+    fn program_errors_are_not_retried() {
+        let used = TransactionError::InstructionError(1, InstructionError::Custom(6002));
+        let rpc = MockRpc::failing_with(vec![Err(used.into())]);
+        assert!(matches!(send(&rpc), Err(SubmitError::Client(_))));
+        assert_eq!(rpc.sent.borrow().len(), 1);
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let rpc = MockRpc::failing_with((0..=POLICY.max_retries).map(|_| Err(io_error())).collect());
+        assert!(matches!(send(&rpc), Err(SubmitError::Client(_))));
+        assert_eq!(rpc.sent.borrow().len(), POLICY.max_retries as usize + 1);
+    }
+
+    #[test]
+    fn stops_when_nullifier_spent_elsewhere() {
+        let rpc = MockRpc {
+            nullifier_spent: true,
+            ..MockRpc::failing_with(vec![Err(TransactionError::BlockhashNotFound.into())])
+        };
+        assert!(matches!(send(&rpc), Err(SubmitError::NullifierSpent)));
+        assert_eq!(rpc.sent.borrow().len(), 1);
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn jitter_stays_within_backoff() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+        };
+        assert_eq!(policy.jittered(1, 0.0), Duration::from_millis(500));
+        assert_eq!(policy.jittered(1, 1.0), Duration::from_secs(1));
+        assert!(policy.jittered(1, 0.5) > Duration::from_millis(500));
+    }
+
+    #[test]
+    fn only_expiry_and_transient_errors_are_retried() {
+        assert_eq!(classify(&TransactionError::BlockhashNotFound.into()), Failure::BlockhashExpired);
+        assert_eq!(classify(&io_error()), Failure::Transient);
+        assert_eq!(classify(&TransactionError::AlreadyProcessed.into()), Failure::AlreadyProcessed);
+
+        // A spent nullifier or an invalid proof must never be resubmitted
+        let used = TransactionError::InstructionError(1, InstructionError::Custom(6002));
+        assert_eq!(classify(&used.into()), Failure::Fatal);
+        let invalid = TransactionError::InstructionError(1, InstructionError::Custom(6000));
+        assert_eq!(classify(&invalid.into()), Failure::Fatal);
+    }
+}
@@ -0,0 +1,211 @@
+//! Server-side Groth16 verification of relayed proofs
+//!
+//! The program rejects bad proofs and unknown roots too, but only after the
+//! relayer has paid for the transaction. Checking both here first turns a
+//! garbage request into a `400` instead of a lost fee.
+//!
+//! The checks themselves live in `privacy_zig_core::verify`, shared with
+//! the CLI, which verifies each proof it generates before sending it.
+//!
+//! The program takes `extDataHash` only as a public input of the proof;
+//! nothing on chain ties it to the transaction's accounts.
+//! [`check_ext_data`] recomputes it with core's `ExtData::hash`, as the CLI
+//! does, so a proof generated for another recipient, another relayer or
+//! another fee is turned away with `400` too.
+
+use anyhow::{anyhow, Context, Result};
+use ark_bn254::Bn254;
+use ark_groth16::PreparedVerifyingKey;
+use privacy_zig_core::crypto::fr_to_be_bytes;
+use privacy_zig_core::instruction::EXT_DATA_HASH_OFFSET;
+use privacy_zig_core::{verifying_key_from_snarkjs, ExtData, ProofError, TransactProofData};
+use solana_sdk::pubkey::Pubkey;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The instruction data can't be parsed into a proof and public inputs
+    Malformed(&'static str),
+    InvalidProof,
+    /// The proof's root is not in the tree's recent root history
+    UnknownRoot,
+}
+
+impl VerifyError {
+    /// Stable code clients can match on
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Malformed(_) => "MALFORMED_PROOF",
+            Self::InvalidProof => "INVALID_PROOF",
+            Self::UnknownRoot => "UNKNOWN_ROOT",
+        }
+    }
+
+    pub fn message(self) -> String {
+        match self {
+            Self::Malformed(what) => format!("Malformed proof: {}", what),
+            Self::InvalidProof => "The proof does not verify for these public inputs".to_string(),
+            Self::UnknownRoot => {
+                "The Merkle root is not in the pool's recent root history; regenerate the proof"
+                    .to_string()
+            }
+        }
+    }
+}
+
+impl From<ProofError> for VerifyError {
+    fn from(e: ProofError) -> Self {
+        match e {
+            ProofError::Malformed(what) => Self::Malformed(what),
+            ProofError::Invalid => Self::InvalidProof,
+        }
+    }
+}
+
+pub struct Verifier {
+    pvk: PreparedVerifyingKey<Bn254>,
+}
+
+impl Verifier {
+    /// Load a snarkjs verifying key exported from the circuit's zkey
+    pub fn load(path: &str) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read verifying key {}", path))?;
+        Self::from_vk_json(&data).with_context(|| format!("Invalid verifying key {}", path))
+    }
+
+    pub fn from_vk_json(data: &str) -> Result<Self> {
+        Ok(Self {
+            pvk: verifying_key_from_snarkjs(data).map_err(|e| anyhow!("{}", e))?,
+        })
+    }
+
+    /// Verify the proof in transact instruction data against its public
+    /// inputs and the tree's recent roots
+    pub fn verify(&self, data: &[u8], recent_roots: &[[u8; 32]]) -> Result<(), VerifyError> {
+        let proof = TransactProofData::from_instruction_data(data)
+            .map_err(|_| VerifyError::Malformed("not a transact instruction"))?;
+        // A malformed proof is reported as such whatever its root
+        let verified = proof.verify(&self.pvk);
+        if let Err(ProofError::Malformed(what)) = verified {
+            return Err(VerifyError::Malformed(what));
+        }
+        if !recent_roots.iter().any(|root| root[..] == proof.root[..]) {
+            return Err(VerifyError::UnknownRoot);
+        }
+        Ok(verified?)
+    }
+}
+
+/// Check that the `extDataHash` in `instruction_data` commits to a
+/// withdrawal of `amount` to `recipient`, sent by `relayer` for `fee`
+pub fn check_ext_data(
+    instruction_data: &[u8],
+    recipient: &Pubkey,
+    amount: u64,
+    relayer: &Pubkey,
+    fee: u64,
+) -> Result<(), String> {
+    let ext_data = ExtData { recipient: *recipient, relayer: *relayer, fee };
+    let expected = fr_to_be_bytes(&ext_data.hash(amount).map_err(|e| e.to_string())?);
+    let actual = instruction_data
+        .get(EXT_DATA_HASH_OFFSET..EXT_DATA_HASH_OFFSET + 32)
+        .ok_or("Instruction data has no extDataHash")?;
+    if actual != expected {
+        return Err(format!(
+            "The proof's extDataHash doesn't commit to recipient {}, relayer {} and a fee of {}; \
+             generate the proof for this relayer",
+            recipient, relayer, fee
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_ff::{BigInteger, PrimeField};
+    use privacy_zig_core::instruction::{
+        COMMITMENT2_OFFSET, EXT_DATA_HASH_OFFSET, NULLIFIER1_OFFSET, PROOF_OFFSET,
+        PUBLIC_AMOUNT_OFFSET, ROOT_OFFSET,
+    };
+    use privacy_zig_core::verify::parse_proof;
+
+    // A test key with a known trapdoor, so the fixture proof is simulated
+    // rather than generated from the circuit
+    const VK: &str = include_str!("../tests/fixtures/verify/vk.json");
+    const PROOF_HEX: &str = include_str!("../tests/fixtures/verify/proof.hex");
+
+    fn fixture() -> (Verifier, Vec<u8>, [u8; 32]) {
+        let data = hex::decode(PROOF_HEX.trim()).unwrap();
+        let root: [u8; 32] = data[ROOT_OFFSET..ROOT_OFFSET + 32].try_into().unwrap();
+        (Verifier::from_vk_json(VK).unwrap(), data, root)
+    }
+
+    #[test]
+    fn accepts_known_good_proof() {
+        let (verifier, data, root) = fixture();
+        assert_eq!(verifier.verify(&data, &[[7; 32], root]), Ok(()));
+    }
+
+    #[test]
+    fn rejects_unknown_root() {
+        let (verifier, data, _) = fixture();
+        assert_eq!(verifier.verify(&data, &[[7; 32]]), Err(VerifyError::UnknownRoot));
+    }
+
+    #[test]
+    fn rejects_tampered_public_inputs() {
+        let (verifier, data, root) = fixture();
+        for offset in [NULLIFIER1_OFFSET + 31, COMMITMENT2_OFFSET + 31, PUBLIC_AMOUNT_OFFSET, EXT_DATA_HASH_OFFSET + 31] {
+            let mut tampered = data.clone();
+            tampered[offset] ^= 1;
+            assert_eq!(verifier.verify(&tampered, &[root]), Err(VerifyError::InvalidProof), "offset {}", offset);
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_proof() {
+        let (verifier, data, root) = fixture();
+
+        // Un-negated proof_a: still a valid point, but the wrong one
+        let mut flipped = data.clone();
+        let a = parse_proof(&data[8..264]).unwrap().a;
+        flipped[40..72].copy_from_slice(&a.y.into_bigint().to_bytes_be());
+        assert_eq!(verifier.verify(&flipped, &[root]), Err(VerifyError::InvalidProof));
+
+        // Off the curve
+        let mut off_curve = data.clone();
+        off_curve[PROOF_OFFSET + 63] ^= 1;
+        assert_eq!(verifier.verify(&off_curve, &[root]).unwrap_err().code(), "MALFORMED_PROOF");
+
+        // Coordinate not reduced mod p
+        let mut unreduced = data.clone();
+        unreduced[PROOF_OFFSET + 192..PROOF_OFFSET + 224].fill(0xff);
+        assert_eq!(verifier.verify(&unreduced, &[root]).unwrap_err().code(), "MALFORMED_PROOF");
+
+        // Truncated
+        assert_eq!(verifier.verify(&data[..400], &[root]).unwrap_err().code(), "MALFORMED_PROOF");
+    }
+
+    #[test]
+    fn rejects_proof_for_another_relayer_or_fee() {
+        let recipient = Pubkey::new_from_array([1; 32]);
+        let relayer = Pubkey::new_from_array([2; 32]);
+        let (amount, fee) = (1_000_000_000, 2_500_000);
+        let hash = ExtData { recipient, relayer, fee }.hash(amount).unwrap();
+        let mut data = vec![0u8; 464];
+        data[EXT_DATA_HASH_OFFSET..EXT_DATA_HASH_OFFSET + 32].copy_from_slice(&fr_to_be_bytes(&hash));
+
+        assert_eq!(check_ext_data(&data, &recipient, amount, &relayer, fee), Ok(()));
+        let other = Pubkey::new_from_array([7; 32]);
+        assert!(check_ext_data(&data, &recipient, amount, &other, fee).is_err());
+        assert!(check_ext_data(&data, &other, amount, &relayer, fee).is_err());
+        assert!(check_ext_data(&data, &recipient, amount, &relayer, 0).is_err());
+        assert!(check_ext_data(&data[..400], &recipient, amount, &relayer, fee).is_err());
+
+        // Every byte of the recipient counts, not just a prefix
+        let mut bytes = recipient.to_bytes();
+        bytes[31] ^= 1;
+        assert!(check_ext_data(&data, &Pubkey::new_from_array(bytes), amount, &relayer, fee).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+/**
+ * Golden vectors for core/tests/vectors.rs, read from the circuit itself
+ *
+ * Runs artifacts/transaction2_js's witness calculator on notes spent as
+ * transaction inputs and reads the circuit's own signals: the spending
+ * key's `publicKey`, each input's commitment and nullifier hasher outputs.
+ * The witness calculator asserts every `===` as it goes, so the public
+ * inputs it checks (nullifiers, output commitments, the root of a
+ * non-zero input) are filled in from the signals computed before the
+ * failed assert, and the run repeated until the witness is complete: the
+ * printed values are the ones a proof has to use.
+ *
+ * Usage: npx tsx circuit-vectors.ts
+ */
+
+import * as fs from 'fs';
+import * as path from 'path';
+
+const ARTIFACTS = path.join(__dirname, '..', 'artifacts');
+const HEIGHT = 26;
+const FIELD_SIZE = BigInt('21888242871839275222246405745257275088548364400416034343698204186575808495617');
+
+// circom's field elements in the WASM's memory: a 32-bit short value, a
+// 32-bit type and a 256-bit long value, the long in Montgomery form when
+// the type says so
+const ELEMENT_SIZE = 40;
+const LONG = 0x80000000;
+const MONTGOMERY = 0x40000000;
+// 2^-256 mod p, to take a long value out of Montgomery form
+const R_INV = modPow(modPow(2n, 256n, FIELD_SIZE), FIELD_SIZE - 2n, FIELD_SIZE);
+
+const PRIVKEY = 4932897425348234597827354123098745612309874561230987456123n;
+const BLINDING = 1234567890123456789012345678901234567890123456789012345678n;
+const SOL_MINT = 1n;
+// spl_mint(EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v), USDC
+const USDC_MINT = 351564470195712479312889784364378094793935933814098925423924767071335165789n;
+
+function modPow(base: bigint, exp: bigint, m: bigint): bigint {
+  let result = 1n;
+  base %= m;
+  while (exp > 0n) {
+    if (exp & 1n) result = (result * base) % m;
+    base = (base * base) % m;
+    exp >>= 1n;
+  }
+  return result;
+}
+
+/** Signal indices of the main component, by name */
+function readSymbols(): Map<string, number> {
+  const symbols = new Map<string, number>();
+  for (const line of fs.readFileSync(path.join(ARTIFACTS, 'transaction2.sym'), 'utf8').split('\n')) {
+    const [signal, , , name] = line.split(',');
+    if (name) symbols.set(name, Number(signal));
+  }
+  return symbols;
+}
+
+type Input = Record<string, any>;
+
+/** Offset of signal 0 in the WASM's memory, found on the first run */
+let signalsBase: number | undefined;
+
+/**
+ * Run the witness calculator on `input`, returning its signals whether or
+ * not an assert failed and the failed assert's message
+ */
+async function run(wc: any, symbols: Map<string, number>, input: Input) {
+  let error: string | null = null;
+  try {
+    await wc.calculateWitness(input, 0);
+  } catch (e) {
+    error = String(e);
+  }
+
+  // Signal 1 is `root`, the first input: find it to find the rest. The
+  // signals stay put between runs, and later roots may be anywhere else
+  const memory = new DataView(wc.instance.exports.memory.buffer);
+  signalsBase ??= findSignals(memory, input.root);
+  const base = signalsBase;
+  const signal = (name: string): bigint => {
+    const index = symbols.get(name);
+    if (index === undefined) throw new Error(`No signal ${name}`);
+    const offset = base + index * ELEMENT_SIZE;
+    const type = memory.getUint32(offset + 4, true);
+    if (!(type & LONG)) return BigInt(memory.getInt32(offset, true));
+    let value = 0n;
+    for (let i = 7; i >= 0; i--) value = (value << 32n) | BigInt(memory.getUint32(offset + 8 + 4 * i, true));
+    return type & MONTGOMERY ? (value * R_INV) % FIELD_SIZE : value;
+  };
+  return { error, signal };
+}
+
+/** Offset of signal 0, found from signal 1 holding `root` */
+function findSignals(memory: DataView, root: bigint): number {
+  const words: number[] = [];
+  for (let i = 0, value = root; i < 8; i++, value >>= 32n) words.push(Number(value & 0xffffffffn));
+  for (let offset = 0; offset + 40 <= memory.byteLength; offset += 4) {
+    if (words.every((word, i) => memory.getUint32(offset + 8 + 4 * i, true) === word)) {
+      return offset - ELEMENT_SIZE;
+    }
+  }
+  throw new Error('root not found in the witness memory');
+}
+
+/**
+ * Spend `notes` as the two inputs of a transaction in `mint` into one
+ * output of their total, fixing up the asserted public inputs until the
+ * witness calculator accepts it
+ */
+async function spend(wc: any, symbols: Map<string, number>, mint: bigint, notes: Note[]) {
+  const total = notes.reduce((sum, note) => sum + note.amount, 0n);
+  const input: Input = {
+    // Distinctive, so findSignals can't mistake it, until the real root is known
+    root: 0x5eed5eed5eed5eed5eed5eed5eed5eed5eed5eed5eed5eed5eedn,
+    publicAmount: 0n,
+    extDataHash: 0n,
+    mintAddress: mint,
+    inputNullifier: [0n, 0n],
+    inAmount: notes.map((note) => note.amount),
+    inPrivateKey: notes.map((note) => note.privkey),
+    inBlinding: notes.map((note) => note.blinding),
+    inPathIndices: notes.map((note) => note.leaf),
+    inPathElements: notes.map(() => Array(HEIGHT).fill(0n)),
+    outputCommitment: [0n, 0n],
+    outAmount: [total, 0n],
+    outPubkey: [0n, 0n],
+    outBlinding: [0n, 0n],
+  };
+
+  for (let attempt = 0; attempt < 8; attempt++) {
+    const { error, signal } = await run(wc, symbols, input);
+    if (!error) return signal;
+    // Every assert a run can fail, and the signals that satisfy it
+    input.inputNullifier = [0, 1].map((i) => signal(`main.inNullifierHasher[${i}].out`));
+    input.outputCommitment = [0, 1].map((i) => signal(`main.outCommitmentHasher[${i}].out`));
+    const enabled = notes.findIndex((note) => note.amount > 0n);
+    if (enabled >= 0) input.root = signal(`main.inTree[${enabled}].root`);
+  }
+  throw new Error('the witness calculator kept failing');
+}
+
+interface Note {
+  amount: bigint;
+  privkey: bigint;
+  blinding: bigint;
+  leaf: bigint;
+}
+
+async function main() {
+  const builder = require(path.join(ARTIFACTS, 'transaction2_js', 'witness_calculator.js'));
+  const wc = await builder(fs.readFileSync(path.join(ARTIFACTS, 'transaction2_js', 'transaction2.wasm')));
+  const symbols = readSymbols();
+
+  // A SOL note at leaf 0 and a dummy input, blinding 42, also at leaf 0.
+  // Signals are read before the next run overwrites them.
+  const dummy = { amount: 0n, privkey: PRIVKEY, blinding: 42n, leaf: 0n };
+  const sol = await spend(wc, symbols, SOL_MINT, [
+    { amount: 1_500_000_000n, privkey: PRIVKEY, blinding: BLINDING, leaf: 0n },
+    dummy,
+  ]);
+  const vectors: Record<string, bigint> = {
+    pubkey: sol('main.inKeypair[0].publicKey'),
+    solCommitment: sol('main.inCommitmentHasher[0].out'),
+    solNullifierAtLeaf0: sol('main.inNullifierHasher[0].out'),
+    dummyNullifier: sol('main.inNullifierHasher[1].out'),
+  };
+
+  // A USDC note at a leaf index spanning three bytes
+  const spl = await spend(wc, symbols, USDC_MINT, [
+    { amount: 25_000_000n, privkey: PRIVKEY, blinding: BLINDING, leaf: 1_000_003n },
+    dummy,
+  ]);
+  vectors.splCommitment = spl('main.inCommitmentHasher[0].out');
+  vectors.splNullifierAtLeaf1000003 = spl('main.inNullifierHasher[0].out');
+
+  for (const [name, value] of Object.entries(vectors)) {
+    console.log(`${name}: ${value}`);
+  }
+}
+
+main().catch((err) => {
+  console.error(err);
+  process.exit(1);
+});
@@ -0,0 +1,500 @@
+//! WASM bindings for notes, commitments, nullifiers and withdrawal proofs
+//!
+//! Lets a web frontend derive what the CLI derives without a round trip to
+//! a server: new notes and their commitments, nullifiers, Merkle path checks,
+//! withdrawal proofs and `transact` instruction data, so a note's keys never
+//! leave the page. The hashing and the circuit inputs are the [`crypto`] and
+//! [`circuit`] modules of `privacy-zig-core` the CLI uses, compiled for
+//! wasm32, so both produce the same bytes; `tests/web.rs` pins this against
+//! the vectors core's tests check, and `tests/prove.test.mjs` proves a
+//! withdrawal end to end.
+//!
+//! Amounts cross into JavaScript as decimal strings, since a `number` loses
+//! lamports above 2^53, and field elements as decimal strings too, as they
+//! are stored in notes. Failures throw an `Error` whose message says which
+//! value was wrong but, as in the CLI, never includes a key.
+//!
+//! The witness is computed as in the CLI, by ark-circom running
+//! `transaction2.wasm` through wasmer; built for wasm32, wasmer's js backend
+//! compiles it with the JS engine's `WebAssembly`, so no other witness
+//! runtime is needed. Browsers only compile modules that large
+//! synchronously off the main thread, and a proof takes a minute or more
+//! single-threaded, so create the [`Prover`] in a Web Worker.
+//!
+//! ```text
+//! wasm-pack build wasm --target web
+//! wasm-pack test wasm --node
+//! ```
+
+use ark_bn254::{Bn254, Fr};
+use ark_circom::{read_zkey, WitnessCalculator};
+use ark_groth16::{prepare_verifying_key, PreparedVerifyingKey, ProvingKey};
+use ark_relations::r1cs::ConstraintMatrices;
+use privacy_zig_core::instruction::TRANSACT_DATA_LEN;
+use privacy_zig_core::verify::NR_PUBLIC_INPUTS;
+use privacy_zig_core::TransactProofData;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::io::Cursor;
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+use wasmer::{Module, Store};
+use zeroize::Zeroizing;
+
+pub use privacy_zig_core::{circuit, crypto};
+
+use crypto::{
+    be_bytes_to_fr_strict, fr_to_be_bytes, fr_to_str, parse_field, str_to_fr_strict, ExtData,
+    MerklePath, MerkleTree, MERKLE_TREE_HEIGHT,
+};
+
+fn js_error(e: impl std::fmt::Display) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+fn parse_amount(amount: &str) -> Result<u64, JsError> {
+    u64::from_str(amount.trim()).map_err(|_| {
+        JsError::new(&format!(
+            "Invalid amount {:?}: expected whole base units",
+            amount
+        ))
+    })
+}
+
+/// A note: its amount, keys, blinding, mint and commitment
+#[wasm_bindgen]
+pub struct Utxo(crypto::Utxo);
+
+#[wasm_bindgen]
+impl Utxo {
+    /// A new note of `amount` base units of `mint` with random keys
+    #[wasm_bindgen(constructor)]
+    pub fn new(amount: &str, mint: &str) -> Result<Utxo, JsError> {
+        let mint = parse_field("mint", mint).map_err(js_error)?;
+        crypto::Utxo::new(parse_amount(amount)?, mint)
+            .map(Utxo)
+            .map_err(js_error)
+    }
+
+    /// The note with these values, e.g. one read back from storage; the
+    /// commitment is recomputed from them
+    #[wasm_bindgen(js_name = fromValues)]
+    pub fn from_values(
+        amount: &str,
+        privkey: &str,
+        pubkey: &str,
+        blinding: &str,
+        mint: &str,
+    ) -> Result<Utxo, JsError> {
+        let mint = parse_field("mint", mint).map_err(js_error)?;
+        crypto::Utxo::from_values(parse_amount(amount)?, privkey, pubkey, blinding, mint)
+            .map(Utxo)
+            .map_err(js_error)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn amount(&self) -> String {
+        self.0.amount.to_string()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn privkey(&self) -> String {
+        self.0.privkey.to_string()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn pubkey(&self) -> String {
+        self.0.pubkey.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn blinding(&self) -> String {
+        self.0.blinding.to_string()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn mint(&self) -> String {
+        self.0.mint.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn commitment(&self) -> String {
+        self.0.commitment.clone()
+    }
+
+    /// Whether the pubkey and commitment match the other values
+    #[wasm_bindgen(js_name = verifyCommitment)]
+    pub fn verify_commitment(&self) -> bool {
+        self.0.verify_commitment()
+    }
+
+    /// The nullifier spending this note at `leaf_index` reveals
+    #[wasm_bindgen(js_name = computeNullifier)]
+    pub fn compute_nullifier(&self, leaf_index: u32) -> Result<String, JsError> {
+        self.0
+            .compute_nullifier(leaf_index as usize)
+            .map(|nullifier| fr_to_str(&nullifier))
+            .map_err(js_error)
+    }
+}
+
+/// A note as JSON: what [`new_utxo`] returns and
+/// [`Prover::prove_withdraw`] spends
+#[derive(Serialize, Deserialize)]
+struct NoteJson {
+    amount: String,
+    privkey: String,
+    pubkey: String,
+    blinding: String,
+    mint: String,
+    /// Recomputed from the other fields, and checked against them if given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    commitment: Option<String>,
+}
+
+impl NoteJson {
+    fn to_utxo(&self) -> Result<crypto::Utxo, JsError> {
+        let mint = parse_field("mint", &self.mint).map_err(js_error)?;
+        let utxo = crypto::Utxo::from_values(
+            parse_amount(&self.amount)?,
+            &self.privkey,
+            &self.pubkey,
+            &self.blinding,
+            mint,
+        )
+        .map_err(js_error)?;
+        if let Some(commitment) = &self.commitment {
+            if parse_field("commitment", commitment).map_err(js_error)?
+                != str_to_fr_strict(&utxo.commitment).map_err(js_error)?
+            {
+                return Err(JsError::new(
+                    "The note's commitment doesn't match its amount, keys and blinding",
+                ));
+            }
+        }
+        Ok(utxo)
+    }
+}
+
+impl From<&crypto::Utxo> for NoteJson {
+    fn from(utxo: &crypto::Utxo) -> Self {
+        Self {
+            amount: utxo.amount.to_string(),
+            privkey: utxo.privkey.to_string(),
+            pubkey: utxo.pubkey.clone(),
+            blinding: utxo.blinding.to_string(),
+            mint: utxo.mint.clone(),
+            commitment: Some(utxo.commitment.clone()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct NewUtxoRequest {
+    amount: String,
+    mint: String,
+}
+
+/// A new note with random keys, from `{"amount", "mint"}`, as JSON
+/// `{"amount", "privkey", "pubkey", "blinding", "mint", "commitment"}`
+#[wasm_bindgen(js_name = newUtxo)]
+pub fn new_utxo(request_json: &str) -> Result<String, JsError> {
+    let request: NewUtxoRequest = serde_json::from_str(request_json)
+        .map_err(|e| js_error(format!("Invalid note request: {}", e)))?;
+    let utxo = Utxo::new(&request.amount, &request.mint)?;
+    serde_json::to_string(&NoteJson::from(&utxo.0)).map_err(js_error)
+}
+
+fn parse_commitments(commitments: &[String]) -> Result<Vec<Fr>, JsError> {
+    commitments
+        .iter()
+        .map(|commitment| parse_field("commitment", commitment))
+        .collect::<Result<Vec<Fr>, _>>()
+        .map_err(js_error)
+}
+
+fn merkle_tree(commitments: &[Fr]) -> Result<MerkleTree, JsError> {
+    let mut tree = MerkleTree::new(MERKLE_TREE_HEIGHT).map_err(js_error)?;
+    tree.insert_many(commitments).map_err(js_error)?;
+    Ok(tree)
+}
+
+/// The pool's tree rebuilt from every commitment in it, a JSON array of
+/// decimal strings in leaf order, as JSON `{"root", "leafCount"}`. Compare
+/// the root with the tree account's before proving against it.
+#[wasm_bindgen(js_name = buildMerkleTreeFromCommitments)]
+pub fn build_merkle_tree_from_commitments(commitments_json: &str) -> Result<String, JsError> {
+    let commitments: Vec<String> = serde_json::from_str(commitments_json)
+        .map_err(|e| js_error(format!("Invalid commitments: {}", e)))?;
+    let tree = merkle_tree(&parse_commitments(&commitments)?)?;
+    Ok(serde_json::json!({
+        "root": fr_to_str(&tree.root()),
+        "leafCount": commitments.len(),
+    })
+    .to_string())
+}
+
+/// What [`Prover::prove_withdraw`] takes: the note, the tree it is in and
+/// who the withdrawal pays, as for the CLI's `withdraw`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WithdrawRequest {
+    utxo: NoteJson,
+    /// Every commitment in the pool's tree, in leaf order
+    commitments: Vec<String>,
+    /// The tree account's current root, if the caller has it; a proof
+    /// against any other root would be rejected on chain
+    #[serde(default)]
+    root: Option<String>,
+    /// Base58 address the note's amount is paid to
+    recipient: String,
+    /// Base58 address of the signer: a relayer, or the wallet itself
+    relayer: String,
+    /// Relayer fee in base units
+    #[serde(default)]
+    fee: Option<String>,
+}
+
+fn parse_pubkey(name: &str, value: &str) -> Result<Pubkey, JsError> {
+    Pubkey::from_str(value).map_err(|e| js_error(format!("Invalid {} {}: {}", name, value, e)))
+}
+
+/// Groth16 prover for the transaction circuit, loaded from the same
+/// `transaction2.wasm` and `transaction2.zkey` the CLI uses
+#[wasm_bindgen]
+pub struct Prover {
+    params: ProvingKey<Bn254>,
+    /// `params.vk`, prepared once to check every proof before it is returned
+    vk: PreparedVerifyingKey<Bn254>,
+    matrices: ConstraintMatrices<Fr>,
+    store: Store,
+    witness: WitnessCalculator,
+}
+
+#[wasm_bindgen]
+impl Prover {
+    /// Load the circuit's witness generator and proving key from their bytes
+    #[wasm_bindgen(constructor)]
+    pub fn new(circuit_wasm: &[u8], zkey: &[u8]) -> Result<Prover, JsError> {
+        let (params, matrices) = read_zkey(&mut Cursor::new(zkey))
+            .map_err(|e| js_error(format!("Invalid zkey: {:?}", e)))?;
+        let public_inputs = matrices.num_instance_variables.saturating_sub(1);
+        if public_inputs != NR_PUBLIC_INPUTS {
+            return Err(JsError::new(&format!(
+                "The zkey is for a circuit with {} public inputs, not the transaction circuit's {}",
+                public_inputs, NR_PUBLIC_INPUTS
+            )));
+        }
+
+        let mut store = Store::default();
+        let module = Module::new(&store, circuit_wasm)
+            .map_err(|e| js_error(format!("Invalid circuit wasm: {}", e)))?;
+        let witness = WitnessCalculator::from_module(&mut store, module)
+            .map_err(|e| js_error(format!("Failed to load the witness generator: {:?}", e)))?;
+
+        Ok(Prover {
+            vk: prepare_verifying_key(&params.vk),
+            params,
+            matrices,
+            store,
+            witness,
+        })
+    }
+
+    /// Prove the withdrawal of a whole note, from the JSON of
+    /// `{"utxo", "commitments", "root"?, "recipient", "relayer", "fee"?}`,
+    /// returning the proof as JSON that [`transact_instruction_data`] takes.
+    /// The proof is verified before it is returned.
+    #[wasm_bindgen(js_name = proveWithdraw)]
+    pub fn prove_withdraw(&mut self, request_json: &str) -> Result<String, JsError> {
+        let request: WithdrawRequest = serde_json::from_str(request_json)
+            .map_err(|e| js_error(format!("Invalid withdrawal request: {}", e)))?;
+        let utxo = request.utxo.to_utxo()?;
+        let ext_data = ExtData {
+            recipient: parse_pubkey("recipient", &request.recipient)?,
+            relayer: parse_pubkey("relayer", &request.relayer)?,
+            fee: request.fee.as_deref().map_or(Ok(0), parse_amount)?,
+        };
+
+        let commitments = parse_commitments(&request.commitments)?;
+        let tree = merkle_tree(&commitments)?;
+        if let Some(root) = &request.root {
+            if parse_field("root", root).map_err(js_error)? != tree.root() {
+                return Err(JsError::new(&format!(
+                    "The commitments make root {}, not {}; fetch them again",
+                    fr_to_str(&tree.root()),
+                    root
+                )));
+            }
+        }
+        let commitment = str_to_fr_strict(&utxo.commitment).map_err(js_error)?;
+        let leaf_index = tree
+            .index_of(&commitment)
+            .ok_or_else(|| JsError::new("The note's commitment is not in the tree"))?;
+
+        let (mut inputs, ext_data_hash) =
+            circuit::withdraw_inputs(&utxo, leaf_index, &tree, &ext_data).map_err(js_error)?;
+        let computed = self
+            .witness
+            .calculate_witness_element::<Fr, _>(&mut self.store, inputs.clone(), false);
+        circuit::wipe_inputs(&mut inputs);
+        let witness = Zeroizing::new(
+            computed.map_err(|e| js_error(format!("Witness calculation failed: {:?}", e)))?,
+        );
+        let variables = circuit::variable_count(&self.matrices);
+        if witness.len() != variables {
+            return Err(JsError::new(&format!(
+                "The circuit wasm computed {} signals, but the zkey's circuit has {} variables; \
+                 they must come from the same build",
+                witness.len(),
+                variables
+            )));
+        }
+
+        let (proof, public_signals) = circuit::groth16_prove(
+            &self.params,
+            &self.matrices,
+            &witness,
+            &mut rand::thread_rng(),
+        )
+        .map_err(|e| js_error(format!("Proof generation failed: {:?}", e)))?;
+        let proof = circuit::transact_proof_data(&proof, &public_signals, &ext_data_hash)
+            .map_err(js_error)?;
+        proof
+            .verify(&self.vk)
+            .map_err(|e| js_error(format!("The generated proof failed verification ({})", e)))?;
+        serde_json::to_string(&ProofData::from(&proof)).map_err(js_error)
+    }
+}
+
+/// The mint field of SOL notes
+#[wasm_bindgen(js_name = solMint)]
+pub fn sol_mint() -> String {
+    fr_to_str(&crypto::sol_mint())
+}
+
+/// The mint field of notes of the token `mint`, a base58 address
+#[wasm_bindgen(js_name = splMint)]
+pub fn spl_mint(mint: &str) -> Result<String, JsError> {
+    let mint =
+        Pubkey::from_str(mint).map_err(|e| js_error(format!("Invalid mint {}: {}", mint, e)))?;
+    Ok(fr_to_str(&crypto::spl_mint(&mint)))
+}
+
+/// The `extDataHash` of a withdrawal of `amount` to `recipient`, sent by
+/// `relayer` for `fee`, as a decimal string: the hash the CLI proves and
+/// the relayer checks, with each address as its two [`address_limbs`]
+///
+/// [`address_limbs`]: crypto::address_limbs
+#[wasm_bindgen(js_name = extDataHash)]
+pub fn ext_data_hash(
+    recipient: &str,
+    amount: &str,
+    relayer: &str,
+    fee: &str,
+) -> Result<String, JsError> {
+    let ext_data = ExtData {
+        recipient: parse_pubkey("recipient", recipient)?,
+        relayer: parse_pubkey("relayer", relayer)?,
+        fee: parse_amount(fee)?,
+    };
+    Ok(fr_to_str(&ext_data.hash(parse_amount(amount)?).map_err(js_error)?))
+}
+
+/// The 32 big-endian bytes of a field element given in decimal, as they
+/// appear in instruction data and account state
+#[wasm_bindgen(js_name = frToBytes)]
+pub fn fr_to_bytes(value: &str) -> Result<Vec<u8>, JsError> {
+    Ok(fr_to_be_bytes(&str_to_fr_strict(value).map_err(js_error)?).to_vec())
+}
+
+/// The decimal field element of 32 big-endian bytes, rejecting values that
+/// are not below the field modulus
+#[wasm_bindgen(js_name = bytesToFr)]
+pub fn bytes_to_fr(bytes: &[u8]) -> Result<String, JsError> {
+    Ok(fr_to_str(&be_bytes_to_fr_strict(bytes).map_err(js_error)?))
+}
+
+/// Whether `path_elements` and `path_indices` lead from `leaf` to `root`
+#[wasm_bindgen(js_name = verifyMerklePath)]
+pub fn verify_merkle_path(
+    leaf: &str,
+    path_elements: Vec<String>,
+    path_indices: &[u8],
+    root: &str,
+) -> Result<bool, JsError> {
+    let path_elements = path_elements
+        .iter()
+        .map(|element| parse_field("path element", element))
+        .collect::<Result<Vec<Fr>, _>>()
+        .map_err(js_error)?;
+    let leaf = parse_field("leaf", leaf).map_err(js_error)?;
+    let root = parse_field("root", root).map_err(js_error)?;
+    let path = MerklePath { elements: path_elements, indices: path_indices.to_vec() };
+    Ok(crypto::verify_path(&root, &leaf, &path))
+}
+
+/// [`TransactProofData`] with the public amount as a string
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProofData {
+    proof_a: Vec<u8>,
+    proof_b: Vec<u8>,
+    proof_c: Vec<u8>,
+    root: Vec<u8>,
+    nullifier1: Vec<u8>,
+    nullifier2: Vec<u8>,
+    commitment1: Vec<u8>,
+    commitment2: Vec<u8>,
+    public_amount: String,
+    ext_data_hash: Vec<u8>,
+}
+
+impl From<&TransactProofData> for ProofData {
+    fn from(proof: &TransactProofData) -> Self {
+        Self {
+            proof_a: proof.proof_a.clone(),
+            proof_b: proof.proof_b.clone(),
+            proof_c: proof.proof_c.clone(),
+            root: proof.root.clone(),
+            nullifier1: proof.nullifier1.clone(),
+            nullifier2: proof.nullifier2.clone(),
+            commitment1: proof.commitment1.clone(),
+            commitment2: proof.commitment2.clone(),
+            public_amount: proof.public_amount.to_string(),
+            ext_data_hash: proof.ext_data_hash.clone(),
+        }
+    }
+}
+
+/// The data of a `transact` instruction, from the JSON of a proof:
+/// `proofA`, `proofB`, `proofC` and the public inputs as byte arrays, and
+/// `publicAmount` as a decimal string, negative for a withdrawal
+#[wasm_bindgen(js_name = transactInstructionData)]
+pub fn transact_instruction_data(proof_json: &str) -> Result<Vec<u8>, JsError> {
+    let proof: ProofData =
+        serde_json::from_str(proof_json).map_err(|e| js_error(format!("Invalid proof: {}", e)))?;
+    let public_amount = i64::from_str(proof.public_amount.trim())
+        .map_err(|_| JsError::new(&format!("Invalid public amount {:?}", proof.public_amount)))?;
+    let data = TransactProofData {
+        proof_a: proof.proof_a,
+        proof_b: proof.proof_b,
+        proof_c: proof.proof_c,
+        root: proof.root,
+        nullifier1: proof.nullifier1,
+        nullifier2: proof.nullifier2,
+        commitment1: proof.commitment1,
+        commitment2: proof.commitment2,
+        public_amount,
+        ext_data_hash: proof.ext_data_hash,
+    }
+    .to_instruction_data();
+    if data.len() != TRANSACT_DATA_LEN {
+        return Err(JsError::new(&format!(
+            "Proof fields have the wrong lengths: instruction data would be {} bytes, expected {}",
+            data.len(),
+            TRANSACT_DATA_LEN
+        )));
+    }
+    Ok(data)
+}
@@ -0,0 +1,67 @@
+// End-to-end withdrawal proof through the WASM bindings, with the real
+// circuit artifacts. Build the Node.js package first, then run:
+//
+//   wasm-pack build wasm --target nodejs --out-dir pkg-node
+//   node --test wasm/tests/prove.test.mjs
+//
+// Proving takes tens of seconds; a browser should do it in a Web Worker.
+
+import assert from "node:assert/strict";
+import { readFileSync } from "node:fs";
+import { test } from "node:test";
+import { fileURLToPath } from "node:url";
+
+import * as privacy from "../pkg-node/privacy_zig_wasm.js";
+
+const artifact = (name) =>
+  readFileSync(fileURLToPath(new URL(`../../artifacts/${name}`, import.meta.url)));
+
+test("proves a withdrawal the program accepts the layout of", () => {
+  const prover = new privacy.Prover(
+    artifact("transaction2.wasm"),
+    artifact("transaction2.zkey"),
+  );
+
+  const note = JSON.parse(
+    privacy.newUtxo(JSON.stringify({ amount: "100000000", mint: privacy.solMint() })),
+  );
+  const other = JSON.parse(
+    privacy.newUtxo(JSON.stringify({ amount: "1", mint: privacy.solMint() })),
+  );
+  const commitments = [other.commitment, note.commitment];
+  const { root, leafCount } = JSON.parse(
+    privacy.buildMerkleTreeFromCommitments(JSON.stringify(commitments)),
+  );
+  assert.equal(leafCount, 2);
+
+  const proof = JSON.parse(
+    prover.proveWithdraw(
+      JSON.stringify({
+        utxo: note,
+        commitments,
+        root,
+        recipient: "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin",
+        relayer: "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin",
+        fee: "0",
+      }),
+    ),
+  );
+  assert.equal(proof.publicAmount, "-100000000");
+  assert.equal(privacy.bytesToFr(Uint8Array.from(proof.root)), root);
+
+  const data = privacy.transactInstructionData(JSON.stringify(proof));
+  assert.equal(data.length, 464);
+
+  // A root the commitments don't make is refused before proving
+  assert.throws(() =>
+    prover.proveWithdraw(
+      JSON.stringify({
+        utxo: note,
+        commitments: [note.commitment],
+        root,
+        recipient: "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin",
+        relayer: "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin",
+      }),
+    ),
+  );
+});
@@ -0,0 +1,163 @@
+//! Parity with the CLI: core's crypto tests pin the same vectors
+//!
+//! Run with `wasm-pack test --node` (or `--headless --firefox`).
+
+use privacy_zig_core::TRANSACT_DISCRIMINATOR;
+use privacy_zig_wasm::*;
+use wasm_bindgen_test::*;
+
+const FIXTURE: &str = include_str!("../../core/tests/fixtures/note_vectors.json");
+
+fn fixture() -> serde_json::Value {
+    serde_json::from_str(FIXTURE).unwrap()
+}
+
+fn strings(value: &serde_json::Value) -> Vec<String> {
+    value
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v.as_str().unwrap().to_string())
+        .collect()
+}
+
+#[wasm_bindgen_test]
+fn matches_native_note_vectors() {
+    let json = fixture();
+    let text = |key: &str| json[key].as_str().unwrap();
+
+    assert_eq!(sol_mint(), text("mint"));
+    let utxo = Utxo::from_values(
+        text("amount"),
+        text("privkey"),
+        text("pubkey"),
+        text("blinding"),
+        &sol_mint(),
+    )
+    .unwrap();
+    assert_eq!(utxo.amount(), text("amount"));
+    assert_eq!(utxo.commitment(), text("commitment"));
+    assert!(utxo.verify_commitment());
+    let leaf_index = json["leaf_index"].as_u64().unwrap() as u32;
+    assert_eq!(
+        utxo.compute_nullifier(leaf_index).unwrap(),
+        text("nullifier")
+    );
+
+    let path_indices: Vec<u8> = serde_json::from_value(json["path_indices"].clone()).unwrap();
+    let path = strings(&json["path_elements"]);
+    let leaf = &strings(&json["leaves"])[leaf_index as usize];
+    assert!(verify_merkle_path(leaf, path.clone(), &path_indices, text("root")).unwrap());
+    assert!(!verify_merkle_path(text("pubkey"), path, &path_indices, text("root")).unwrap());
+}
+
+#[wasm_bindgen_test]
+fn new_notes_are_consistent() {
+    // Above 2^53, where a JavaScript number would round
+    let amount = "9007199254740993";
+    let utxo = Utxo::new(amount, &sol_mint()).unwrap();
+    assert_eq!(utxo.amount(), amount);
+    assert!(utxo.verify_commitment());
+    assert_ne!(
+        utxo.commitment(),
+        Utxo::new(amount, &sol_mint()).unwrap().commitment()
+    );
+
+    let usdc = spl_mint("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+    assert_ne!(usdc, sol_mint());
+    assert!(Utxo::new("1.5", &sol_mint()).is_err());
+    assert!(Utxo::new("-1", &sol_mint()).is_err());
+}
+
+#[wasm_bindgen_test]
+fn field_elements_round_trip_through_bytes() {
+    let commitment = fixture()["commitment"].as_str().unwrap().to_string();
+    let bytes = fr_to_bytes(&commitment).unwrap();
+    assert_eq!(bytes.len(), 32);
+    assert_eq!(bytes_to_fr(&bytes).unwrap(), commitment);
+    assert_eq!(fr_to_bytes("1").unwrap()[31], 1);
+
+    assert!(bytes_to_fr(&[0xff; 32]).is_err());
+    assert!(bytes_to_fr(&[1; 31]).is_err());
+    assert!(fr_to_bytes("not a number").is_err());
+}
+
+#[wasm_bindgen_test]
+fn builds_transact_instruction_data() {
+    let proof = |public_amount: &str, root_len: usize| {
+        serde_json::json!({
+            "proofA": vec![1u8; 64],
+            "proofB": vec![2u8; 128],
+            "proofC": vec![3u8; 64],
+            "root": vec![4u8; root_len],
+            "nullifier1": vec![5u8; 32],
+            "nullifier2": vec![6u8; 32],
+            "commitment1": vec![7u8; 32],
+            "commitment2": vec![8u8; 32],
+            "publicAmount": public_amount,
+            "extDataHash": vec![9u8; 32],
+        })
+        .to_string()
+    };
+
+    // A withdrawal too large for a JavaScript number
+    let data = transact_instruction_data(&proof("-9007199254740993", 32)).unwrap();
+    assert_eq!(data.len(), 464);
+    assert_eq!(data[..8], TRANSACT_DISCRIMINATOR);
+    assert_eq!(data[424..432], (-9_007_199_254_740_993i64).to_le_bytes());
+
+    assert!(transact_instruction_data(&proof("-1", 31)).is_err());
+    assert!(transact_instruction_data(&proof("1.5", 32)).is_err());
+}
+
+#[wasm_bindgen_test]
+fn new_utxo_returns_a_spendable_note() {
+    let request = serde_json::json!({ "amount": "9007199254740993", "mint": sol_mint() });
+    let note: serde_json::Value =
+        serde_json::from_str(&new_utxo(&request.to_string()).unwrap()).unwrap();
+    let text = |key: &str| note[key].as_str().unwrap().to_string();
+    assert_eq!(text("amount"), "9007199254740993");
+    let utxo = Utxo::from_values(
+        &text("amount"),
+        &text("privkey"),
+        &text("pubkey"),
+        &text("blinding"),
+        &text("mint"),
+    )
+    .unwrap();
+    assert_eq!(utxo.commitment(), text("commitment"));
+
+    // Amounts are strings, as everywhere else
+    let request = serde_json::json!({ "amount": 1, "mint": sol_mint() });
+    assert!(new_utxo(&request.to_string()).is_err());
+}
+
+#[wasm_bindgen_test]
+fn builds_merkle_tree_from_commitments() {
+    let json = fixture();
+    let tree: serde_json::Value = serde_json::from_str(
+        &build_merkle_tree_from_commitments(&json["leaves"].to_string()).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(tree["root"], json["root"]);
+    assert_eq!(tree["leafCount"], 3);
+
+    assert!(build_merkle_tree_from_commitments(r#"["not a number"]"#).is_err());
+}
+
+#[wasm_bindgen_test]
+fn hashes_ext_data_as_the_relayer_checks_it() {
+    let json: serde_json::Value =
+        serde_json::from_str(include_str!("../../core/tests/fixtures/ext_data.json")).unwrap();
+    let text = |key: &str| json[key].as_str().unwrap().to_string();
+    let (amount, fee) = (json["amount"].to_string(), json["fee"].to_string());
+
+    let hash = ext_data_hash(&text("recipient"), &amount, &text("relayer"), &fee).unwrap();
+    let bytes: String = fr_to_bytes(&hash).unwrap().iter().map(|b| format!("{:02x}", b)).collect();
+    assert_eq!(bytes, text("hash"));
+
+    // The relayer's address and fee are bound, not just the recipient's
+    assert_ne!(ext_data_hash(&text("recipient"), &amount, &text("recipient"), &fee).unwrap(), hash);
+    assert_ne!(ext_data_hash(&text("recipient"), &amount, &text("relayer"), "0").unwrap(), hash);
+    assert!(ext_data_hash("not an address", &amount, &text("relayer"), &fee).is_err());
+}